@@ -5,17 +5,19 @@ use actix_web::{
     body::EitherBody,
 };
 use actix_cors::Cors;
-use mongodb::{Client, Collection, bson::{doc, oid::ObjectId}, options::FindOptions};
+use mongodb::{Client, Collection, IndexModel, bson::{doc, oid::ObjectId, Document}, options::{FindOptions, IndexOptions, ReplaceOptions, UpdateOptions}};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use chrono::{DateTime, Utc};
+use jsonwebtoken::{decode, encode, get_current_timestamp, DecodingKey, EncodingKey, Header, Validation, Algorithm};
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc};
 use std::fmt;
 use std::env;
 use std::future::{ready, Ready, Future};
 use std::pin::Pin;
 use std::rc::Rc;
+use std::sync::{Arc, RwLock};
 use anyhow::Context;
-use log::info;
+use campus_auth::{load_jwt_keys, reload_jwt_keys, SharedKeySet, JWT_KEY_REFRESH_SECS};
+use log::{info, warn};
 
 // ── Custom API Error Type ─────────────────────────────────────────────────────
 
@@ -30,6 +32,7 @@ enum AppError {
     Forbidden(String),
     BadRequest(String),
     NotFound(String),
+    Conflict(String),
     Internal(anyhow::Error),
 }
 
@@ -40,6 +43,7 @@ impl fmt::Display for AppError {
             AppError::Forbidden(m) => write!(f, "{}", m),
             AppError::BadRequest(m) => write!(f, "{}", m),
             AppError::NotFound(m) => write!(f, "{}", m),
+            AppError::Conflict(m) => write!(f, "{}", m),
             AppError::Internal(e) => write!(f, "Internal server error: {}", e),
         }
     }
@@ -53,6 +57,7 @@ impl ResponseError for AppError {
             AppError::Forbidden(_) => HttpResponse::Forbidden().json(body),
             AppError::BadRequest(_) => HttpResponse::BadRequest().json(body),
             AppError::NotFound(_) => HttpResponse::NotFound().json(body),
+            AppError::Conflict(_) => HttpResponse::Conflict().json(body),
             AppError::Internal(_) => HttpResponse::InternalServerError().json(body),
         }
     }
@@ -72,6 +77,7 @@ struct Claims {
     role: String,
     campus_id: String,
     exp: usize,
+    iat: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -82,6 +88,12 @@ struct Course {
     course_name: String,
     credits: i32,
     department: String,
+    /// Course codes that must be passed (or taken earlier) before enrolling
+    #[serde(default)]
+    prerequisites: Vec<String>,
+    /// Seats per semester offering; unlimited when absent
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_seats: Option<i32>,
     campus_id: String,
     created_at: DateTime<Utc>,
 }
@@ -93,6 +105,8 @@ struct CourseRequest {
     course_name: Option<String>,
     credits: Option<i32>,
     department: Option<String>,
+    prerequisites: Option<Vec<String>>,
+    max_seats: Option<i32>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -102,15 +116,67 @@ struct Enrollment {
     student_id: String,
     course_code: String,
     semester: String,
+    /// Section name, for offerings split into sections
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section: Option<String>,
     campus_id: String,
     enrolled_at: DateTime<Utc>,
 }
 
+/// A student waiting for a seat in a full course offering.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct WaitlistEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    course_code: String,
+    semester: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section: Option<String>,
+    campus_id: String,
+    requested_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Deserialize)]
 struct EnrollmentRequest {
     student_id: Option<String>,
     course_code: Option<String>,
     semester: Option<String>,
+    section: Option<String>,
+}
+
+/// A section of a course offering, in the `sections` collection. Timetable
+/// slots, enrollments and attendance refer to it by `section_name`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Section {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    course_code: String,
+    semester: String,
+    section_name: String,
+    capacity: i32,
+    /// Students currently enrolled; updated atomically against `capacity`
+    enrolled: i32,
+    faculty_id: String,
+    /// Timetable slots scheduled for this section
+    #[serde(default)]
+    slot_ids: Vec<ObjectId>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct SectionRequest {
+    course_code: Option<String>,
+    semester: Option<String>,
+    section_name: Option<String>,
+    capacity: Option<i32>,
+    faculty_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EnrollmentSectionRequest {
+    section: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -119,16 +185,62 @@ struct Attendance {
     id: Option<ObjectId>,
     student_id: String,
     course_code: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section: Option<String>,
     date: String,
     status: String,
+    /// Set when the record is for a makeup or remedial class
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extra_class_id: Option<ObjectId>,
     campus_id: String,
     created_at: DateTime<Utc>,
 }
 
+/// A live QR attendance session opened by faculty for a timetable slot.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AttendanceSession {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    slot_id: ObjectId,
+    course_code: String,
+    section: String,
+    semester: String,
+    /// "YYYY-MM-DD" the attendance is recorded against
+    date: String,
+    opened_by: String,
+    campus_id: String,
+    opened_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    closed: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttendanceSessionRequest {
+    slot_id: Option<String>,
+    duration_mins: Option<i64>,
+}
+
+/// Payload of the short-lived token encoded in the QR code. It is signed with
+/// a key derived from JWT_SECRET (see `derive_qr_key`), so it can never pass
+/// as a login token (and vice versa).
+#[derive(Debug, Serialize, Deserialize)]
+struct AttendanceQrClaims {
+    sid: String,
+    campus_id: String,
+    iat: usize,
+    exp: usize,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttendanceCheckinRequest {
+    token: Option<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct AttendanceRequest {
     student_id: Option<String>,
     course_code: Option<String>,
+    section: Option<String>,
     date: Option<String>,
     status: Option<String>,
 }
@@ -196,574 +308,7346 @@ struct ExamResultRequest {
     semester: Option<String>,
 }
 
+/// One assessment score in the `grades` collection. A course grade is derived
+/// from all assessments of a student in a course and semester.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct Note {
+struct Grade {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     id: Option<ObjectId>,
-    title: String,
-    description: String,
+    student_id: String,
     course_code: String,
-    file_url: String,
-    file_type: String,
-    uploaded_by: String,
-    uploader_role: String,
+    semester: String,
+    /// Assessment name, e.g. "midterm", "lab-1", "final"
+    assessment: String,
+    marks_obtained: f64,
+    max_marks: f64,
+    graded_by: String,
     campus_id: String,
     created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Deserialize)]
-struct NoteRequest {
-    title: Option<String>,
-    description: Option<String>,
+struct GradeRequest {
+    student_id: Option<String>,
     course_code: Option<String>,
-    file_url: Option<String>,
-    file_type: Option<String>,
+    semester: Option<String>,
+    assessment: Option<String>,
+    marks_obtained: Option<f64>,
+    max_marks: Option<f64>,
 }
 
+/// How a course offering's internal score is built from its assessments,
+/// e.g. quiz 10%, midterm 30%, endsem 60%.
 #[derive(Debug, Serialize, Deserialize, Clone)]
-struct StudentNoteSubmission {
+struct AssessmentScheme {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     id: Option<ObjectId>,
+    course_code: String,
+    semester: String,
+    components: Vec<AssessmentComponent>,
+    campus_id: String,
+    updated_by: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AssessmentComponent {
+    /// Matches `Grade::assessment`
+    name: String,
+    /// Percentage of the final score; all components sum to 100
+    weight: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssessmentSchemeRequest {
+    semester: Option<String>,
+    components: Option<Vec<AssessmentComponent>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ComponentMarksRequest {
+    semester: Option<String>,
+    max_marks: Option<f64>,
+    marks: Option<Vec<StudentMarks>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StudentMarks {
     student_id: String,
-    title: String,
-    description: String,
+    marks_obtained: f64,
+}
+
+/// Where a course offering's results are in the publication workflow, in
+/// `result_publications`. Offerings without a record are drafts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ResultPublication {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
     course_code: String,
-    file_url: String,
-    file_type: String,
+    semester: String,
+    /// "draft", "verified" or "published"
     status: String,
-    review_comment: Option<String>,
-    reviewed_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verified_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    verified_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    published_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    published_at: Option<DateTime<Utc>>,
+    campus_id: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ResultPublicationRequest {
+    semester: Option<String>,
+}
+
+/// A student's request to re-evaluate a published grade, in `grade_appeals`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct GradeAppeal {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    grade_id: ObjectId,
+    student_id: String,
+    course_code: String,
+    semester: String,
+    assessment: String,
+    reason: String,
+    /// "pending", "accepted" or "rejected"
+    status: String,
+    original_marks: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    revised_marks: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolution_note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolved_by: Option<String>,
     campus_id: String,
     created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolved_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Deserialize)]
-struct StudentNoteSubmissionRequest {
-    title: Option<String>,
-    description: Option<String>,
-    course_code: Option<String>,
-    file_url: Option<String>,
-    file_type: Option<String>,
+struct GradeAppealRequest {
+    reason: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
-struct ReviewNoteRequest {
+struct ResolveAppealRequest {
+    /// "accepted" or "rejected"
     status: Option<String>,
-    review_comment: Option<String>,
+    /// New marks, required when accepting
+    marks_obtained: Option<f64>,
+    note: Option<String>,
 }
 
-struct AppState {
-    db: mongodb::Database,
-    jwt_secret: String,
+/// Computed result of one course on a grade card.
+#[derive(Debug, Serialize, Clone)]
+struct CourseGrade {
+    course_code: String,
+    course_name: String,
+    credits: i32,
+    marks_obtained: f64,
+    max_marks: f64,
+    percentage: f64,
+    grade: String,
+    grade_points: f64,
 }
 
-// ── Logging Middleware ────────────────────────────────────────────────────────
-
-pub struct RequestLogger;
+#[derive(Debug, Serialize, Clone)]
+struct SemesterGrades {
+    semester: String,
+    courses: Vec<CourseGrade>,
+    /// Credits attempted this semester
+    credits: i32,
+    /// Credits of courses passed this semester
+    credits_earned: i32,
+    sgpa: f64,
+}
 
-impl<S, B> Transform<S, ServiceRequest> for RequestLogger
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<B>;
-    type Error = actix_web::Error;
-    type Transform = RequestLoggerMiddleware<S>;
-    type InitError = ();
-    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+#[derive(Debug, Serialize, Clone)]
+struct GradeCard {
+    student_id: String,
+    semesters: Vec<SemesterGrades>,
+    total_credits: i32,
+    total_credits_earned: i32,
+    cgpa: f64,
+}
 
-    fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(RequestLoggerMiddleware { service: Rc::new(service) }))
-    }
+/// A one-off makeup or remedial class outside the weekly timetable, in
+/// `extra_classes`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExtraClass {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    course_code: String,
+    semester: String,
+    /// Limits the class to one section; None means the whole offering
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    section: Option<String>,
+    /// "makeup" or "remedial"
+    kind: String,
+    topic: String,
+    date: String,
+    start_time: String,
+    end_time: String,
+    room: String,
+    faculty_id: String,
+    /// "scheduled" or "cancelled"
+    status: String,
+    campus_id: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
 }
 
-pub struct RequestLoggerMiddleware<S> {
-    service: Rc<S>,
+#[derive(Debug, Deserialize)]
+struct ExtraClassRequest {
+    course_code: Option<String>,
+    semester: Option<String>,
+    section: Option<String>,
+    kind: Option<String>,
+    topic: Option<String>,
+    date: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    room: Option<String>,
+    faculty_id: Option<String>,
 }
 
-impl<S, B> Service<ServiceRequest> for RequestLoggerMiddleware<S>
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<B>;
-    type Error = actix_web::Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+#[derive(Debug, Deserialize)]
+struct ExtraClassAttendanceRequest {
+    records: Option<Vec<StudentAttendanceRecord>>,
+}
 
-    forward_ready!(service);
+/// A weekly class slot in the `timetable` collection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TimetableSlot {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    course_code: String,
+    /// Student group the slot is for, e.g. "CSE-A"; a section name, or a batch
+    /// name for offerings without sections
+    section: String,
+    faculty_id: String,
+    room: String,
+    /// monday … sunday
+    day: String,
+    /// "HH:MM", 24-hour
+    start_time: String,
+    end_time: String,
+    semester: String,
+    campus_id: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
+}
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        let method = req.method().to_string();
-        let path = req.path().to_string();
-        let svc = self.service.clone();
+#[derive(Debug, Deserialize)]
+struct TimetableSlotRequest {
+    course_code: Option<String>,
+    section: Option<String>,
+    faculty_id: Option<String>,
+    room: Option<String>,
+    day: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+    semester: Option<String>,
+}
 
-        Box::pin(async move {
-            let res = svc.call(req).await?;
-            info!("{} {} -> {}", method, path, res.status().as_u16());
-            Ok(res)
-        })
-    }
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExamHall {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    hall_name: String,
+    capacity: i32,
+    campus_id: String,
+    created_at: DateTime<Utc>,
 }
 
-// ── JWT Auth Middleware ───────────────────────────────────────────────────────
-// Blocks requests to protected routes that lack a valid Bearer token.
-// Only /health is public; all API routes require a valid JWT.
+#[derive(Debug, Deserialize)]
+struct ExamHallRequest {
+    hall_name: Option<String>,
+    capacity: Option<i32>,
+}
 
-pub struct JwtAuth {
-    pub jwt_secret: String,
+/// A scheduled exam sitting for one course offering.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Exam {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    course_code: String,
+    semester: String,
+    exam_type: String,
+    /// "YYYY-MM-DD"
+    date: String,
+    /// "HH:MM", 24-hour
+    start_time: String,
+    end_time: String,
+    campus_id: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
 }
 
-impl<S, B> Transform<S, ServiceRequest> for JwtAuth
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<EitherBody<B>>;
-    type Error = actix_web::Error;
-    type Transform = JwtAuthMiddleware<S>;
-    type InitError = ();
-    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+#[derive(Debug, Deserialize)]
+struct ExamRequest {
+    course_code: Option<String>,
+    semester: Option<String>,
+    exam_type: Option<String>,
+    date: Option<String>,
+    start_time: Option<String>,
+    end_time: Option<String>,
+}
 
-    fn new_transform(&self, service: S) -> Self::Future {
-        ready(Ok(JwtAuthMiddleware {
-            service: Rc::new(service),
-            jwt_secret: self.jwt_secret.clone(),
-        }))
-    }
+/// One student's seat for one exam, in the `exam_seats` collection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ExamSeat {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    exam_id: ObjectId,
+    student_id: String,
+    hall_name: String,
+    seat_number: i32,
+    campus_id: String,
 }
 
-pub struct JwtAuthMiddleware<S> {
-    service: Rc<S>,
-    jwt_secret: String,
+#[derive(Debug, Deserialize)]
+struct SeatingRequest {
+    /// Halls to use, in order; all campus halls when omitted
+    hall_names: Option<Vec<String>>,
 }
 
-fn is_public_route(path: &str, method: &str) -> bool {
-    matches!((method, path), ("GET", "/health"))
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Assignment {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    course_code: String,
+    semester: String,
+    title: String,
+    description: String,
+    due_at: DateTime<Utc>,
+    max_marks: f64,
+    /// When false, submissions after `due_at` are rejected instead of flagged
+    allow_late: bool,
+    created_by: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
 }
 
-impl<S, B> Service<ServiceRequest> for JwtAuthMiddleware<S>
-where
-    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
-    B: 'static,
-{
-    type Response = ServiceResponse<EitherBody<B>>;
-    type Error = actix_web::Error;
-    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+#[derive(Debug, Deserialize)]
+struct AssignmentRequest {
+    course_code: Option<String>,
+    semester: Option<String>,
+    title: Option<String>,
+    description: Option<String>,
+    /// RFC 3339 timestamp, e.g. "2024-10-01T23:59:00Z"
+    due_at: Option<String>,
+    max_marks: Option<f64>,
+    allow_late: Option<bool>,
+}
 
-    forward_ready!(service);
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Submission {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    assignment_id: ObjectId,
+    student_id: String,
+    file_url: Option<String>,
+    text: Option<String>,
+    submitted_at: DateTime<Utc>,
+    is_late: bool,
+    marks: Option<f64>,
+    feedback: Option<String>,
+    graded_by: Option<String>,
+    graded_at: Option<DateTime<Utc>>,
+    campus_id: String,
+}
 
-    fn call(&self, req: ServiceRequest) -> Self::Future {
-        let path = req.path().to_string();
-        let method = req.method().to_string();
-        let svc = self.service.clone();
-        let secret = self.jwt_secret.clone();
+#[derive(Debug, Deserialize)]
+struct SubmissionRequest {
+    file_url: Option<String>,
+    text: Option<String>,
+}
 
-        Box::pin(async move {
-            if is_public_route(&path, &method) {
-                return svc.call(req).await.map(|r| r.map_into_left_body());
-            }
+#[derive(Debug, Deserialize)]
+struct GradeSubmissionRequest {
+    marks: Option<f64>,
+    feedback: Option<String>,
+}
 
-            let auth_result = req
-                .headers()
-                .get(header::AUTHORIZATION)
-                .and_then(|v| v.to_str().ok())
-                .and_then(|s| s.strip_prefix("Bearer "))
-                .ok_or("missing")
-                .and_then(|token| {
-                    decode::<Claims>(
-                        token,
-                        &DecodingKey::from_secret(secret.as_bytes()),
-                        &Validation::new(Algorithm::HS256),
-                    )
-                    .map_err(|_| "invalid")
-                });
+/// An issued transcript, kept so its verification code can be checked later.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TranscriptRecord {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    verification_code: String,
+    student_id: String,
+    campus_id: String,
+    cgpa: f64,
+    total_credits_earned: i32,
+    issued_by: String,
+    issued_at: DateTime<Utc>,
+}
 
-            match auth_result {
-                Ok(_) => svc.call(req).await.map(|r| r.map_into_left_body()),
-                Err(reason) => {
-                    let msg = if reason == "missing" {
-                        "No token provided"
-                    } else {
-                        "Invalid or expired token"
-                    };
-                    let response = HttpResponse::Unauthorized()
-                        .json(ErrorBody { error: msg.to_string() });
-                    let (http_req, _) = req.into_parts();
-                    Ok(ServiceResponse::new(http_req, response).map_into_right_body())
-                }
-            }
-        })
-    }
+/// One row on a transcript; `grade` is "IP" for courses not yet graded.
+#[derive(Debug, Clone)]
+struct TranscriptLine {
+    course_code: String,
+    course_name: String,
+    credits: i32,
+    marks: Option<(f64, f64)>,
+    grade: String,
+    grade_points: Option<f64>,
 }
 
+#[derive(Debug)]
+struct TranscriptSemester {
+    semester: String,
+    lines: Vec<TranscriptLine>,
+    sgpa: Option<f64>,
+}
 
+/// A degree program's curriculum, e.g. "BTECH-CSE".
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Program {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    program_code: String,
+    program_name: String,
+    department: String,
+    /// Credits needed to graduate, across all passed courses
+    total_credits: i32,
+    required_courses: Vec<String>,
+    elective_baskets: Vec<ElectiveBasket>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+/// A pool of courses from which at least `min_credits` must be passed.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ElectiveBasket {
+    name: String,
+    courses: Vec<String>,
+    min_credits: i32,
+}
 
-/// Shared pagination params: ?page=1&limit=20
 #[derive(Debug, Deserialize)]
-struct PaginationParams {
-    page: Option<u64>,
-    limit: Option<u64>,
+struct ProgramRequest {
+    program_code: Option<String>,
+    program_name: Option<String>,
+    department: Option<String>,
+    total_credits: Option<i32>,
+    required_courses: Option<Vec<String>>,
+    elective_baskets: Option<Vec<ElectiveBasket>>,
 }
 
-impl PaginationParams {
-    fn page(&self) -> u64 {
-        self.page.unwrap_or(1).max(1)
-    }
-    fn limit(&self) -> u64 {
-        self.limit.unwrap_or(20).clamp(1, 100)
-    }
-    fn skip(&self) -> u64 {
-        (self.page() - 1) * self.limit()
-    }
+/// Which program a student is pursuing, in the `student_programs` collection.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StudentProgram {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    program_code: String,
+    campus_id: String,
+    assigned_at: DateTime<Utc>,
 }
 
-/// Filter params for GET /api/attendance
 #[derive(Debug, Deserialize)]
-struct AttendanceFilter {
-    page: Option<u64>,
-    limit: Option<u64>,
-    /// Filter by attendance status: present | absent | late
-    status: Option<String>,
-    /// Filter by course code
+struct StudentProgramRequest {
+    program_code: Option<String>,
+}
+
+/// A notification for a student and their advisors, in the `alerts` collection.
+/// At most one unresolved attendance shortage alert exists per student and course.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Alert {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    kind: String,
+    student_id: String,
+    course_code: String,
+    message: String,
+    /// Attendance figures, set on attendance shortage alerts
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    attendance_percentage: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    threshold: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    classes_attended: Option<i32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    total_classes: Option<i32>,
+    /// Set on extra class notices
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    extra_class_id: Option<ObjectId>,
+    resolved: bool,
+    /// Users (the student or advisors) who have seen the alert
+    #[serde(default)]
+    acknowledged_by: Vec<String>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShortageScanRequest {
+    threshold: Option<f64>,
     course_code: Option<String>,
-    /// Filter by student id
-    student_id: Option<String>,
 }
 
-/// Filter params for GET /api/enrollments
+/// Thresholds for academic standing, per campus in `standing_rules`. A student
+/// falls into a band when their CGPA is below its cutoff or their failed
+/// credits reach its limit.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StandingRules {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    warning_cgpa: f64,
+    warning_failed_credits: i32,
+    probation_cgpa: f64,
+    probation_failed_credits: i32,
+    campus_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_by: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
 #[derive(Debug, Deserialize)]
-struct EnrollmentFilter {
-    page: Option<u64>,
-    limit: Option<u64>,
-    /// Filter by semester, e.g. "Fall 2024"
+struct StandingRulesRequest {
+    warning_cgpa: Option<f64>,
+    warning_failed_credits: Option<i32>,
+    probation_cgpa: Option<f64>,
+    probation_failed_credits: Option<i32>,
+}
+
+/// A student's latest evaluated standing, in `academic_standings`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AcademicStanding {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    /// "good", "warning" or "probation"
+    standing: String,
+    cgpa: f64,
+    total_credits: i32,
+    /// Credits of failed courses the student has not since passed
+    failed_credits: i32,
+    /// Rules the student tripped, empty when in good standing
+    reasons: Vec<String>,
+    campus_id: String,
+    evaluated_at: DateTime<Utc>,
+}
+
+/// An elective-bidding window: students rank the round's courses between
+/// `opens_at` and `closes_at`, then an admin runs the allocation.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ElectiveRound {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    semester: String,
+    courses: Vec<String>,
+    /// Electives each student receives
+    picks: i32,
+    opens_at: DateTime<Utc>,
+    closes_at: DateTime<Utc>,
+    /// "lottery" (random order) or "cgpa" (highest CGPA first, ties by lottery)
+    strategy: String,
+    /// "open" until allocation runs, then "allocated"
+    status: String,
+    campus_id: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    allocated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElectiveRoundRequest {
+    name: Option<String>,
     semester: Option<String>,
-    /// Filter by course code
+    courses: Option<Vec<String>>,
+    picks: Option<i32>,
+    opens_at: Option<String>,
+    closes_at: Option<String>,
+    strategy: Option<String>,
+}
+
+/// A student's ranked choices for one round, in `elective_preferences`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ElectivePreference {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    round_id: ObjectId,
+    student_id: String,
+    /// Course codes, most wanted first
+    preferences: Vec<String>,
+    /// Courses the allocation enrolled the student in
+    #[serde(default)]
+    allocated: Vec<String>,
+    campus_id: String,
+    submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElectivePreferenceRequest {
+    preferences: Option<Vec<String>>,
+}
+
+/// Metadata for a syllabus or lecture file; the bytes live in the
+/// `materials` GridFS bucket under `file_id`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CourseMaterial {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    course_code: String,
+    /// "syllabus" or "lecture"
+    kind: String,
+    title: String,
+    description: String,
+    file_id: ObjectId,
+    filename: String,
+    content_type: String,
+    size: i64,
+    /// "enrolled" (enrolled students and staff) or "staff" (teachers and admins only)
+    visibility: String,
+    uploaded_by: String,
+    campus_id: String,
+    uploaded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Note {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    title: String,
+    description: String,
+    course_code: String,
+    file_url: String,
+    file_type: String,
+    uploaded_by: String,
+    uploader_role: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NoteRequest {
+    title: Option<String>,
+    description: Option<String>,
     course_code: Option<String>,
+    file_url: Option<String>,
+    file_type: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StudentNoteSubmission {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    title: String,
+    description: String,
+    course_code: String,
+    file_url: String,
+    file_type: String,
+    status: String,
+    review_comment: Option<String>,
+    reviewed_by: Option<String>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
 }
 
-/// Filter params for GET /api/courses
 #[derive(Debug, Deserialize)]
-struct CourseFilter {
-    page: Option<u64>,
-    limit: Option<u64>,
-    /// Filter by department name
-    department: Option<String>,
+struct StudentNoteSubmissionRequest {
+    title: Option<String>,
+    description: Option<String>,
+    course_code: Option<String>,
+    file_url: Option<String>,
+    file_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReviewNoteRequest {
+    status: Option<String>,
+    review_comment: Option<String>,
+}
+
+struct AppState {
+    db: mongodb::Database,
+    /// Signs attendance QR codes
+    qr_key: Vec<u8>,
+    /// Verification keys for session tokens, by `kid`
+    jwt_keys: SharedKeySet,
+    /// Printed in the header of generated documents such as transcripts
+    institution_name: String,
+    /// Default attendance percentage below which students are flagged
+    attendance_threshold: f64,
+}
+
+// ── Logging Middleware ────────────────────────────────────────────────────────
+
+pub struct RequestLogger;
+
+impl<S, B> Transform<S, ServiceRequest> for RequestLogger
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Transform = RequestLoggerMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(RequestLoggerMiddleware { service: Rc::new(service) }))
+    }
+}
+
+pub struct RequestLoggerMiddleware<S> {
+    service: Rc<S>,
+}
+
+impl<S, B> Service<ServiceRequest> for RequestLoggerMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<B>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let method = req.method().to_string();
+        let path = req.path().to_string();
+        let svc = self.service.clone();
+
+        Box::pin(async move {
+            let res = svc.call(req).await?;
+            info!("{} {} -> {}", method, path, res.status().as_u16());
+            Ok(res)
+        })
+    }
+}
+
+// ── JWT Auth Middleware ───────────────────────────────────────────────────────
+// Blocks requests to protected routes that lack a valid Bearer token.
+// Only /health is public; all API routes require a valid JWT.
+
+pub struct JwtAuth {
+    pub jwt_keys: SharedKeySet,
+}
+
+impl<S, B> Transform<S, ServiceRequest> for JwtAuth
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Transform = JwtAuthMiddleware<S>;
+    type InitError = ();
+    type Future = Ready<Result<Self::Transform, Self::InitError>>;
+
+    fn new_transform(&self, service: S) -> Self::Future {
+        ready(Ok(JwtAuthMiddleware {
+            service: Rc::new(service),
+            jwt_keys: self.jwt_keys.clone(),
+        }))
+    }
+}
+
+pub struct JwtAuthMiddleware<S> {
+    service: Rc<S>,
+    jwt_keys: SharedKeySet,
+}
+
+fn is_public_route(path: &str, method: &str) -> bool {
+    matches!((method, path), ("GET", "/health"))
+        || (method == "GET" && path.starts_with("/api/transcripts/verify/"))
+}
+
+impl<S, B> Service<ServiceRequest> for JwtAuthMiddleware<S>
+where
+    S: Service<ServiceRequest, Response = ServiceResponse<B>, Error = actix_web::Error> + 'static,
+    B: 'static,
+{
+    type Response = ServiceResponse<EitherBody<B>>;
+    type Error = actix_web::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>>>>;
+
+    forward_ready!(service);
+
+    fn call(&self, req: ServiceRequest) -> Self::Future {
+        let path = req.path().to_string();
+        let method = req.method().to_string();
+        let svc = self.service.clone();
+        let keys = self.jwt_keys.clone();
+
+        Box::pin(async move {
+            if is_public_route(&path, &method) {
+                return svc.call(req).await.map(|r| r.map_into_left_body());
+            }
+
+            let auth_result = req
+                .headers()
+                .get(header::AUTHORIZATION)
+                .and_then(|v| v.to_str().ok())
+                .and_then(|s| s.strip_prefix("Bearer "))
+                .ok_or("missing")
+                .and_then(|token| {
+                    let keys = keys.read().expect("JWT key set lock poisoned");
+                    keys.decode::<Claims>(token).ok_or("invalid")
+                });
+
+            match auth_result {
+                Ok(_) => svc.call(req).await.map(|r| r.map_into_left_body()),
+                Err(reason) => {
+                    let msg = if reason == "missing" {
+                        "No token provided"
+                    } else {
+                        "Invalid or expired token"
+                    };
+                    let response = HttpResponse::Unauthorized()
+                        .json(ErrorBody { error: msg.to_string() });
+                    let (http_req, _) = req.into_parts();
+                    Ok(ServiceResponse::new(http_req, response).map_into_right_body())
+                }
+            }
+        })
+    }
+}
+
+
+
+/// Shared pagination params: ?page=1&limit=20
+#[derive(Debug, Deserialize)]
+struct PaginationParams {
+    page: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl PaginationParams {
+    fn page(&self) -> u64 {
+        self.page.unwrap_or(1).max(1)
+    }
+    fn limit(&self) -> u64 {
+        self.limit.unwrap_or(20).clamp(1, 100)
+    }
+    fn skip(&self) -> u64 {
+        (self.page() - 1) * self.limit()
+    }
+}
+
+/// Filter params for GET /api/attendance
+#[derive(Debug, Deserialize)]
+struct AttendanceFilter {
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Filter by attendance status: present | absent | late
+    status: Option<String>,
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by section name
+    section: Option<String>,
+    /// Filter by student id
+    student_id: Option<String>,
+}
+
+/// Query params for GET /api/attendance/export
+#[derive(Debug, Deserialize)]
+struct AttendanceExportQuery {
+    course_code: Option<String>,
+    /// First date included, YYYY-MM-DD
+    from: Option<String>,
+    /// Last date included, YYYY-MM-DD
+    to: Option<String>,
+    /// Limit to one section
+    section: Option<String>,
+    /// "csv" (default) or "xlsx"
+    format: Option<String>,
+}
+
+/// Filter params for GET /api/enrollments
+#[derive(Debug, Deserialize)]
+struct EnrollmentFilter {
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by section name
+    section: Option<String>,
+}
+
+/// Filter params for GET /api/sections
+#[derive(Debug, Deserialize)]
+struct SectionFilter {
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+}
+
+/// Filter params for GET /api/waitlist
+#[derive(Debug, Deserialize)]
+struct WaitlistFilter {
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+}
+
+/// Filter params for GET /api/alerts
+#[derive(Debug, Deserialize)]
+struct AlertFilter {
+    /// Filter by alert kind, e.g. "attendance_shortage"
+    kind: Option<String>,
+    /// Filter by student (ignored for students, who only see their own)
+    student_id: Option<String>,
+    /// Include resolved alerts (default false)
+    include_resolved: Option<bool>,
+}
+
+/// Filter params for GET /api/courses
+#[derive(Debug, Deserialize)]
+struct CourseFilter {
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Filter by department name
+    department: Option<String>,
+    /// Filter by exact credit count
+    credits: Option<i32>,
+    /// Case-insensitive search on course_code and course_name
+    q: Option<String>,
+    /// Sort field, prefixed with '-' for descending (default "-created_at")
+    sort: Option<String>,
+}
+
+/// Fields GET /api/courses can sort by.
+const COURSE_SORT_FIELDS: [&str; 4] = ["course_code", "course_name", "credits", "created_at"];
+
+/// Query params for DELETE /api/courses/{id}
+#[derive(Debug, Deserialize)]
+struct CourseDeleteQuery {
+    /// Also delete the course's enrollments, attendance, timetable slots, waitlist and sections
+    force: Option<bool>,
+}
+
+/// Query params for assessment scheme, internal score and grade stats endpoints
+#[derive(Debug, Deserialize)]
+struct SemesterQuery {
+    /// Semester, e.g. "Fall 2024"
+    semester: Option<String>,
+}
+
+/// Filter params for GET /api/grade-appeals
+#[derive(Debug, Deserialize)]
+struct GradeAppealFilter {
+    /// "pending", "accepted" or "rejected"
+    status: Option<String>,
+    /// Filter by course code
+    course_code: Option<String>,
+}
+
+/// Filter params for GET /api/grades
+#[derive(Debug, Deserialize)]
+struct GradeFilter {
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+    /// Filter by student id
+    student_id: Option<String>,
+}
+
+/// Query params for GET /api/grades/card/{student_id}
+#[derive(Debug, Deserialize)]
+struct GradeCardQuery {
+    /// Restrict the card to one semester; CGPA still covers all semesters
+    semester: Option<String>,
+}
+
+/// Filter params for GET /api/timetable
+#[derive(Debug, Deserialize)]
+struct TimetableFilter {
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by section name
+    section: Option<String>,
+    /// Filter by room
+    room: Option<String>,
+}
+
+/// Query params for the weekly timetable views
+#[derive(Debug, Deserialize)]
+struct WeeklyTimetableQuery {
+    /// Restrict to one semester
+    semester: Option<String>,
+}
+
+/// Filter params for GET /api/extra-classes
+#[derive(Debug, Deserialize)]
+struct ExtraClassFilter {
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+    /// First date included, YYYY-MM-DD
+    from: Option<String>,
+    /// Last date included, YYYY-MM-DD
+    to: Option<String>,
+    /// Include cancelled classes
+    include_cancelled: Option<bool>,
+}
+
+/// Filter params for GET /api/exams
+#[derive(Debug, Deserialize)]
+struct ExamFilter {
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+    /// Filter by course code
+    course_code: Option<String>,
+}
+
+/// Filter params for GET /api/assignments
+#[derive(Debug, Deserialize)]
+struct AssignmentFilter {
+    /// Filter by course code
+    course_code: Option<String>,
+    /// Filter by semester, e.g. "Fall 2024"
+    semester: Option<String>,
+}
+
+/// Filter params for GET /api/academic-standing
+#[derive(Debug, Deserialize)]
+struct AcademicStandingFilter {
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// "good", "warning" or "probation"
+    standing: Option<String>,
+}
+
+/// Filter params for GET /api/electives/rounds
+#[derive(Debug, Deserialize)]
+struct ElectiveRoundFilter {
+    /// Semester, e.g. "Fall 2024"
+    semester: Option<String>,
+    /// "open" or "allocated"
+    status: Option<String>,
+}
+
+// ── Input Validation Helpers ──────────────────────────────────────────────────
+
+fn regex_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Parses "field" / "-field" into a sort document, limited to `allowed` fields.
+fn parse_sort(sort: Option<&str>, allowed: &[&str], default: Document) -> Result<Document, AppError> {
+    let Some(sort) = sort.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Ok(default);
+    };
+    let (field, direction) = match sort.strip_prefix('-') {
+        Some(field) => (field, -1),
+        None => (sort, 1),
+    };
+    if !allowed.contains(&field) {
+        return Err(AppError::BadRequest(format!(
+            "Invalid sort field '{}'. Must be one of: {}",
+            field,
+            allowed.join(", ")
+        )));
+    }
+    Ok(doc! { field: direction })
+}
+
+fn require_field<'a>(value: &'a Option<String>, field: &str) -> Result<&'a str, AppError> {
+    match value {
+        Some(v) if !v.trim().is_empty() => Ok(v.as_str()),
+        Some(_) => Err(AppError::BadRequest(format!("'{}' must not be blank", field))),
+        None => Err(AppError::BadRequest(format!("'{}' is required", field))),
+    }
+}
+
+fn require_f64(value: Option<f64>, field: &str) -> Result<f64, AppError> {
+    value.ok_or_else(|| AppError::BadRequest(format!("'{}' is required", field)))
+}
+
+fn require_i32(value: Option<i32>, field: &str) -> Result<i32, AppError> {
+    value.ok_or_else(|| AppError::BadRequest(format!("'{}' is required", field)))
+}
+
+/// Validates attendance status values.
+fn validate_attendance_status(status: &str) -> Result<(), AppError> {
+    match status {
+        "present" | "absent" | "late" => Ok(()),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid status '{}'. Must be: present, absent, or late",
+            status
+        ))),
+    }
+}
+
+fn validate_material_kind(kind: &str) -> Result<(), AppError> {
+    match kind {
+        "syllabus" | "lecture" => Ok(()),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid kind '{}'. Must be: syllabus or lecture",
+            kind
+        ))),
+    }
+}
+
+fn validate_material_visibility(visibility: &str) -> Result<(), AppError> {
+    match visibility {
+        "enrolled" | "staff" => Ok(()),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid visibility '{}'. Must be: enrolled or staff",
+            visibility
+        ))),
+    }
+}
+
+/// Validates exam type values.
+fn validate_exam_type(exam_type: &str) -> Result<(), AppError> {
+    match exam_type {
+        "midterm" | "final" | "quiz" | "assignment" => Ok(()),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid exam_type '{}'. Must be: midterm, final, quiz, or assignment",
+            exam_type
+        ))),
+    }
+}
+
+/// Validates review status values.
+fn validate_review_status(status: &str) -> Result<(), AppError> {
+    match status {
+        "reviewed" | "verified" | "rejected" => Ok(()),
+        _ => Err(AppError::BadRequest(format!(
+            "Invalid status '{}'. Must be: reviewed, verified, or rejected",
+            status
+        ))),
+    }
+}
+
+const WEEKDAYS: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+/// Validates and lower-cases a weekday name.
+fn validate_day(day: &str) -> Result<String, AppError> {
+    let day = day.trim().to_lowercase();
+    if WEEKDAYS.contains(&day.as_str()) {
+        Ok(day)
+    } else {
+        Err(AppError::BadRequest(format!(
+            "Invalid day '{}'. Must be one of: {}",
+            day,
+            WEEKDAYS.join(", ")
+        )))
+    }
+}
+
+/// Parses "H:MM"/"HH:MM" and returns it zero-padded so stored times compare
+/// correctly as strings.
+fn normalize_time(value: &str, field: &str) -> Result<String, AppError> {
+    NaiveTime::parse_from_str(value.trim(), "%H:%M")
+        .map(|t| t.format("%H:%M").to_string())
+        .map_err(|_| AppError::BadRequest(format!("'{}' must be a time in HH:MM format", field)))
+}
+
+/// Parses "YYYY-MM-DD" and returns it in canonical form.
+fn normalize_date(value: &str, field: &str) -> Result<String, AppError> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map(|d| d.format("%Y-%m-%d").to_string())
+        .map_err(|_| AppError::BadRequest(format!("'{}' must be a date in YYYY-MM-DD format", field)))
+}
+
+// ── JWT Extraction (uses anyhow internally) ───────────────────────────────────
+
+/// Extracts and validates JWT claims from the Authorization header.
+/// Returns Result<Claims, AppError> — no panics, no unwrap.
+fn extract_claims(req: &HttpRequest, jwt_keys: &SharedKeySet) -> Result<Claims, AppError> {
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .ok_or_else(|| AppError::Unauthorized("No token provided".to_string()))?;
+
+    let auth_str = auth_header
+        .to_str()
+        .map_err(|_| AppError::Unauthorized("Malformed Authorization header".to_string()))?;
+
+    let token = auth_str.strip_prefix("Bearer ").ok_or_else(|| {
+        AppError::Unauthorized("Authorization header must use Bearer scheme".to_string())
+    })?;
+
+    let keys = jwt_keys.read().expect("JWT key set lock poisoned");
+    keys.decode(token)
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))
+}
+
+
+// ── Service Helpers (anyhow for DB operations) ────────────────────────────────
+
+/// Fetches a batch by ObjectId. Returns Option<Batch> — None means not found.
+/// Uses anyhow context to add meaningful error messages for DB failures.
+async fn find_batch_by_id(
+    collection: &Collection<Batch>,
+    batch_id: &str,
+    campus_id: &str,
+) -> anyhow::Result<Option<Batch>> {
+    let oid = ObjectId::parse_str(batch_id)
+        .context("Invalid batch ID format")?;
+    collection
+        .find_one(doc! { "_id": oid, "campus_id": campus_id }, None)
+        .await
+        .context("Database error while fetching batch")
+}
+
+fn calculate_grade(marks: f64, total: f64) -> String {
+    if total == 0.0 {
+        return "N/A".to_string();
+    }
+    let percentage = (marks / total) * 100.0;
+    match percentage as i32 {
+        90..=100 => "A+".to_string(),
+        80..=89 => "A".to_string(),
+        70..=79 => "B+".to_string(),
+        60..=69 => "B".to_string(),
+        50..=59 => "C".to_string(),
+        40..=49 => "D".to_string(),
+        _ => "F".to_string(),
+    }
+}
+
+/// Grade points on a 10-point scale for the letter grades of calculate_grade.
+fn grade_points(grade: &str) -> f64 {
+    match grade {
+        "A+" => 10.0,
+        "A" => 9.0,
+        "B+" => 8.0,
+        "B" => 7.0,
+        "C" => 6.0,
+        "D" => 5.0,
+        _ => 0.0,
+    }
+}
+
+/// Role that verifies a department's draft results.
+const HOD_ROLE: &str = "hod";
+/// Role that publishes verified results to students.
+const CONTROLLER_ROLE: &str = "exam_controller";
+
+/// Letter grades from best to worst, as produced by calculate_grade.
+const LETTER_GRADES: [&str; 7] = ["A+", "A", "B+", "B", "C", "D", "F"];
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+/// Credit-weighted grade point average over the given course grades.
+fn grade_point_average<'a>(courses: impl IntoIterator<Item = &'a CourseGrade>) -> (i32, f64) {
+    let (credits, weighted) = courses
+        .into_iter()
+        .fold((0, 0.0), |(c, w), g| (c + g.credits, w + g.credits as f64 * g.grade_points));
+    let gpa = if credits > 0 { weighted / credits as f64 } else { 0.0 };
+    (credits, round2(gpa))
+}
+
+async fn find_assessment_scheme(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+) -> anyhow::Result<Option<AssessmentScheme>> {
+    db.collection::<AssessmentScheme>("assessment_schemes")
+        .find_one(doc! { "course_code": course_code, "semester": semester, "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch assessment scheme")
+}
+
+/// (obtained, max) for one student's assessments in a course. With a scheme
+/// this is the weighted score out of 100, missing components counting as
+/// zero; without one it is the plain sum of marks.
+fn course_score(grades: &[Grade], scheme: Option<&AssessmentScheme>) -> (f64, f64) {
+    match scheme {
+        Some(scheme) => {
+            let score = scheme
+                .components
+                .iter()
+                .filter_map(|c| {
+                    grades
+                        .iter()
+                        .find(|g| g.assessment == c.name && g.max_marks > 0.0)
+                        .map(|g| c.weight * g.marks_obtained / g.max_marks)
+                })
+                .sum::<f64>();
+            (round2(score), 100.0)
+        }
+        None => grades
+            .iter()
+            .fold((0.0, 0.0), |(o, m), g| (o + g.marks_obtained, m + g.max_marks)),
+    }
+}
+
+/// Upserts one assessment mark, returning whether it replaced an earlier one.
+#[allow(clippy::too_many_arguments)]
+async fn save_grade(
+    db: &mongodb::Database,
+    claims: &Claims,
+    student_id: &str,
+    course_code: &str,
+    semester: &str,
+    assessment: &str,
+    marks_obtained: f64,
+    max_marks: f64,
+) -> anyhow::Result<bool> {
+    let collection: Collection<Grade> = db.collection("grades");
+    let key = doc! {
+        "student_id": student_id,
+        "course_code": course_code,
+        "semester": semester,
+        "assessment": assessment,
+        "campus_id": &claims.campus_id
+    };
+
+    let now = Utc::now();
+    let existing = collection
+        .find_one(key.clone(), None)
+        .await
+        .context("Failed to check existing grade")?;
+
+    let grade = Grade {
+        id: None,
+        student_id: student_id.to_string(),
+        course_code: course_code.to_string(),
+        semester: semester.to_string(),
+        assessment: assessment.to_string(),
+        marks_obtained,
+        max_marks,
+        graded_by: claims.sub.clone(),
+        campus_id: claims.campus_id.clone(),
+        created_at: existing.as_ref().map(|g| g.created_at).unwrap_or(now),
+        updated_at: now,
+    };
+
+    collection
+        .replace_one(key, &grade, ReplaceOptions::builder().upsert(true).build())
+        .await
+        .context("Failed to save grade")?;
+
+    Ok(existing.is_some())
+}
+
+/// Rejects assessments that are not part of the offering's scheme, if it has one.
+fn check_scheme_component(scheme: Option<&AssessmentScheme>, assessment: &str) -> Result<(), AppError> {
+    match scheme {
+        Some(scheme) if !scheme.components.iter().any(|c| c.name == assessment) => {
+            let names: Vec<&str> = scheme.components.iter().map(|c| c.name.as_str()).collect();
+            Err(AppError::BadRequest(format!(
+                "Unknown assessment '{}'; this course's scheme has: {}",
+                assessment,
+                names.join(", ")
+            )))
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn result_status(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+) -> anyhow::Result<String> {
+    let publication = db
+        .collection::<ResultPublication>("result_publications")
+        .find_one(doc! { "course_code": course_code, "semester": semester, "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch result publication")?;
+    Ok(publication.map(|p| p.status).unwrap_or_else(|| "draft".to_string()))
+}
+
+/// Marks and schemes can only change while an offering's results are drafts.
+async fn ensure_results_editable(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+) -> Result<(), AppError> {
+    let status = result_status(db, campus_id, course_code, semester).await?;
+    if status != "draft" {
+        return Err(AppError::Conflict(format!(
+            "Results for {} in {} are {} and locked",
+            course_code, semester, status
+        )));
+    }
+    Ok(())
+}
+
+/// Builds a student's grade card from the `grades` collection, using course
+/// credits from `courses`. Semesters are ordered by their first recorded grade.
+/// With `published_only`, offerings whose results are not published are left out.
+async fn compute_grade_card(
+    db: &mongodb::Database,
+    campus_id: &str,
+    student_id: &str,
+    published_only: bool,
+) -> anyhow::Result<GradeCard> {
+    let grades: Collection<Grade> = db.collection("grades");
+    let courses: Collection<Course> = db.collection("courses");
+
+    let options = FindOptions::builder().sort(doc! { "created_at": 1 }).build();
+    let mut cursor = grades
+        .find(doc! { "student_id": student_id, "campus_id": campus_id }, options)
+        .await
+        .context("Failed to query grades")?;
+
+    // (semester, course_code) -> assessments, in first-seen order
+    let mut semester_order: Vec<String> = Vec::new();
+    let mut recorded: Vec<((String, String), Vec<Grade>)> = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let grade = result.context("Failed to read grade from cursor")?;
+        if !semester_order.contains(&grade.semester) {
+            semester_order.push(grade.semester.clone());
+        }
+        let key = (grade.semester.clone(), grade.course_code.clone());
+        match recorded.iter_mut().find(|(k, _)| *k == key) {
+            Some((_, grades)) => grades.push(grade),
+            None => recorded.push((key, vec![grade])),
+        }
+    }
+
+    let mut totals: Vec<((String, String), (f64, f64))> = Vec::new();
+    for ((semester, course_code), grades) in recorded {
+        if published_only && result_status(db, campus_id, &course_code, &semester).await? != "published" {
+            continue;
+        }
+        let scheme = find_assessment_scheme(db, campus_id, &course_code, &semester).await?;
+        totals.push(((semester, course_code), course_score(&grades, scheme.as_ref())));
+    }
+
+    let mut course_info: std::collections::HashMap<String, Option<Course>> = std::collections::HashMap::new();
+    let mut semesters = Vec::new();
+    for semester in semester_order {
+        let mut course_grades = Vec::new();
+        for ((sem, course_code), (obtained, max)) in &totals {
+            if *sem != semester {
+                continue;
+            }
+            if !course_info.contains_key(course_code) {
+                let course = courses
+                    .find_one(doc! { "course_code": course_code, "campus_id": campus_id }, None)
+                    .await
+                    .context("Failed to fetch course details")?;
+                course_info.insert(course_code.clone(), course);
+            }
+            // Courses deleted after grading carry no credits and do not count towards GPA
+            let course = course_info.get(course_code).cloned().flatten();
+
+            let grade = calculate_grade(*obtained, *max);
+            course_grades.push(CourseGrade {
+                course_code: course_code.clone(),
+                course_name: course.as_ref().map(|c| c.course_name.clone()).unwrap_or_default(),
+                credits: course.map(|c| c.credits).unwrap_or(0),
+                marks_obtained: *obtained,
+                max_marks: *max,
+                percentage: if *max > 0.0 { round2(obtained / max * 100.0) } else { 0.0 },
+                grade_points: grade_points(&grade),
+                grade,
+            });
+        }
+
+        if course_grades.is_empty() {
+            continue;
+        }
+        let (credits, sgpa) = grade_point_average(&course_grades);
+        let credits_earned = course_grades
+            .iter()
+            .filter(|g| g.grade_points > 0.0)
+            .map(|g| g.credits)
+            .sum();
+        semesters.push(SemesterGrades {
+            semester,
+            courses: course_grades,
+            credits,
+            credits_earned,
+            sgpa,
+        });
+    }
+
+    let (total_credits, cgpa) = grade_point_average(semesters.iter().flat_map(|s| &s.courses));
+    let total_credits_earned = semesters.iter().map(|s| s.credits_earned).sum();
+
+    Ok(GradeCard {
+        student_id: student_id.to_string(),
+        semesters,
+        total_credits,
+        total_credits_earned,
+        cgpa,
+    })
+}
+
+/// Normalises a prerequisite list and checks every code is an existing course
+/// other than the course itself.
+async fn validate_prerequisites(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    prerequisites: Vec<String>,
+) -> Result<Vec<String>, AppError> {
+    let mut codes: Vec<String> = Vec::new();
+    for code in prerequisites.into_iter().map(|c| c.trim().to_string()) {
+        if code.is_empty() {
+            return Err(AppError::BadRequest("Prerequisite course codes must not be blank".to_string()));
+        }
+        if code == course_code {
+            return Err(AppError::BadRequest("A course cannot be its own prerequisite".to_string()));
+        }
+        if !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+
+    let courses: Collection<Course> = db.collection("courses");
+    for code in &codes {
+        courses
+            .find_one(doc! { "course_code": code, "campus_id": campus_id }, None)
+            .await
+            .context("Failed to fetch prerequisite course")?
+            .ok_or_else(|| AppError::BadRequest(format!("Prerequisite course '{}' not found", code)))?;
+    }
+    Ok(codes)
+}
+
+/// Prerequisites the student has not satisfied. A prerequisite counts as met
+/// when the student passed it, or was enrolled in it in another semester and
+/// has no grades recorded for it yet.
+async fn missing_prerequisites(
+    db: &mongodb::Database,
+    campus_id: &str,
+    student_id: &str,
+    semester: &str,
+    prerequisites: &[String],
+) -> anyhow::Result<Vec<String>> {
+    if prerequisites.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let card = compute_grade_card(db, campus_id, student_id, false).await?;
+    let graded: Vec<&CourseGrade> = card.semesters.iter().flat_map(|s| &s.courses).collect();
+    let enrollments: Collection<Enrollment> = db.collection("enrollments");
+
+    let mut missing = Vec::new();
+    for code in prerequisites {
+        let attempts: Vec<&&CourseGrade> = graded.iter().filter(|g| g.course_code == *code).collect();
+        let satisfied = if attempts.is_empty() {
+            enrollments
+                .find_one(doc! {
+                    "student_id": student_id,
+                    "course_code": code,
+                    "semester": { "$ne": semester },
+                    "campus_id": campus_id
+                }, None)
+                .await
+                .context("Failed to check prerequisite enrollment")?
+                .is_some()
+        } else {
+            attempts.iter().any(|g| g.grade_points > 0.0)
+        };
+        if !satisfied {
+            missing.push(code.clone());
+        }
+    }
+    Ok(missing)
+}
+
+/// Sections of a course offering, sorted by name.
+async fn offering_sections(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+) -> anyhow::Result<Vec<Section>> {
+    let sections: Collection<Section> = db.collection("sections");
+    let options = FindOptions::builder().sort(doc! { "section_name": 1 }).build();
+    let mut cursor = sections
+        .find(doc! { "course_code": course_code, "semester": semester, "campus_id": campus_id }, options)
+        .await
+        .context("Failed to query sections")?;
+
+    let mut result = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(section) = cursor.next().await {
+        result.push(section.context("Failed to read section")?);
+    }
+    Ok(result)
+}
+
+/// Checks `requested` against the offering's sections. Offerings without
+/// sections take no section; offerings with sections require one of them.
+async fn resolve_section(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+    requested: Option<&str>,
+) -> Result<Option<Section>, AppError> {
+    let sections = offering_sections(db, campus_id, course_code, semester).await?;
+    let requested = requested.map(str::trim).filter(|s| !s.is_empty());
+    let names = || sections.iter().map(|s| s.section_name.as_str()).collect::<Vec<_>>().join(", ");
+
+    match requested {
+        None if sections.is_empty() => Ok(None),
+        None => Err(AppError::BadRequest(format!(
+            "'section' is required for {} in {}; choose one of: {}",
+            course_code,
+            semester,
+            names()
+        ))),
+        Some(name) => match sections.iter().find(|s| s.section_name == name) {
+            Some(section) => Ok(Some(section.clone())),
+            None if sections.is_empty() => Err(AppError::BadRequest(format!(
+                "{} has no sections in {}",
+                course_code, semester
+            ))),
+            None => Err(AppError::BadRequest(format!(
+                "Unknown section '{}'; choose one of: {}",
+                name,
+                names()
+            ))),
+        },
+    }
+}
+
+/// Atomically takes a seat in a section, returning false when it is full.
+async fn take_section_seat(db: &mongodb::Database, section_id: Option<ObjectId>) -> anyhow::Result<bool> {
+    let result = db
+        .collection::<Section>("sections")
+        .update_one(
+            doc! { "_id": section_id, "$expr": { "$lt": ["$enrolled", "$capacity"] } },
+            doc! { "$inc": { "enrolled": 1 } },
+            None,
+        )
+        .await
+        .context("Failed to reserve section seat")?;
+    Ok(result.modified_count == 1)
+}
+
+async fn release_section_seat(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+    section_name: &str,
+) -> anyhow::Result<()> {
+    db.collection::<Section>("sections")
+        .update_one(
+            doc! {
+                "course_code": course_code,
+                "semester": semester,
+                "section_name": section_name,
+                "campus_id": campus_id,
+                "enrolled": { "$gt": 0 }
+            },
+            doc! { "$inc": { "enrolled": -1 } },
+            None,
+        )
+        .await
+        .context("Failed to release section seat")?;
+    Ok(())
+}
+
+/// Takes one seat in a course offering, returning false when it is full.
+/// Seat counts live in `course_seats` so the cap check and the increment are a
+/// single atomic update.
+async fn reserve_seat(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course: &Course,
+    semester: &str,
+) -> anyhow::Result<bool> {
+    let seats: Collection<Document> = db.collection("course_seats");
+    let key = doc! { "course_code": &course.course_code, "semester": semester, "campus_id": campus_id };
+
+    let existing = seats
+        .find_one(key.clone(), None)
+        .await
+        .context("Failed to fetch seat count")?;
+    if existing.is_none() {
+        // Seed from enrollments made before seat tracking existed
+        let enrolled = db
+            .collection::<Enrollment>("enrollments")
+            .count_documents(key.clone(), None)
+            .await
+            .context("Failed to count enrollments")?;
+        seats
+            .update_one(
+                key.clone(),
+                doc! { "$setOnInsert": { "taken": enrolled as i64 } },
+                UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .context("Failed to initialise seat count")?;
+    }
+
+    let result = seats
+        .update_one(seat_filter(key, course.max_seats), doc! { "$inc": { "taken": 1_i64 } }, None)
+        .await
+        .context("Failed to reserve seat")?;
+    Ok(result.modified_count == 1)
+}
+
+/// Matches the `course_seats` row under `key` only while a seat is free.
+/// Courses without `max_seats` never fill up.
+fn seat_filter(key: Document, max_seats: Option<i32>) -> Document {
+    let mut filter = key;
+    if let Some(max_seats) = max_seats {
+        filter.insert("taken", doc! { "$lt": max_seats as i64 });
+    }
+    filter
+}
+
+async fn release_seat(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+) -> anyhow::Result<()> {
+    db.collection::<Document>("course_seats")
+        .update_one(
+            doc! {
+                "course_code": course_code,
+                "semester": semester,
+                "campus_id": campus_id,
+                "taken": { "$gt": 0_i64 }
+            },
+            doc! { "$inc": { "taken": -1_i64 } },
+            None,
+        )
+        .await
+        .context("Failed to release seat")?;
+    Ok(())
+}
+
+/// The campus's standing rules, falling back to warning below CGPA 6.0 or at
+/// 6 failed credits and probation below 5.0 or at 12.
+async fn standing_rules(db: &mongodb::Database, campus_id: &str) -> anyhow::Result<StandingRules> {
+    let rules = db
+        .collection::<StandingRules>("standing_rules")
+        .find_one(doc! { "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch standing rules")?;
+    Ok(rules.unwrap_or_else(|| StandingRules {
+        id: None,
+        warning_cgpa: 6.0,
+        warning_failed_credits: 6,
+        probation_cgpa: 5.0,
+        probation_failed_credits: 12,
+        campus_id: campus_id.to_string(),
+        updated_by: None,
+        updated_at: Utc::now(),
+    }))
+}
+
+/// Applies the rules to a grade card, returning (standing, failed credits, reasons).
+fn evaluate_standing(card: &GradeCard, rules: &StandingRules) -> (String, i32, Vec<String>) {
+    let courses: Vec<&CourseGrade> = card.semesters.iter().flat_map(|s| &s.courses).collect();
+    let mut failed: Vec<(&str, i32)> = Vec::new();
+    for course in &courses {
+        let passed_later = courses
+            .iter()
+            .any(|c| c.course_code == course.course_code && c.grade_points > 0.0);
+        if course.grade_points == 0.0 && !passed_later && !failed.iter().any(|(code, _)| *code == course.course_code) {
+            failed.push((&course.course_code, course.credits));
+        }
+    }
+    let failed_credits: i32 = failed.iter().map(|(_, credits)| credits).sum();
+
+    let check = |cgpa_cutoff: f64, credit_limit: i32| {
+        let mut reasons = Vec::new();
+        if card.total_credits > 0 && card.cgpa < cgpa_cutoff {
+            reasons.push(format!("CGPA {:.2} is below {:.2}", card.cgpa, cgpa_cutoff));
+        }
+        if failed_credits >= credit_limit {
+            reasons.push(format!("{} failed credits (limit {})", failed_credits, credit_limit));
+        }
+        reasons
+    };
+
+    let probation = check(rules.probation_cgpa, rules.probation_failed_credits);
+    if !probation.is_empty() {
+        return ("probation".to_string(), failed_credits, probation);
+    }
+    let warning = check(rules.warning_cgpa, rules.warning_failed_credits);
+    if !warning.is_empty() {
+        return ("warning".to_string(), failed_credits, warning);
+    }
+    ("good".to_string(), failed_credits, Vec::new())
+}
+
+/// Re-evaluates one student from their published results and stores the standing.
+async fn record_academic_standing(
+    db: &mongodb::Database,
+    campus_id: &str,
+    student_id: &str,
+    rules: &StandingRules,
+) -> anyhow::Result<AcademicStanding> {
+    let card = compute_grade_card(db, campus_id, student_id, true).await?;
+    let (standing, failed_credits, reasons) = evaluate_standing(&card, rules);
+    let record = AcademicStanding {
+        id: None,
+        student_id: student_id.to_string(),
+        standing,
+        cgpa: card.cgpa,
+        total_credits: card.total_credits,
+        failed_credits,
+        reasons,
+        campus_id: campus_id.to_string(),
+        evaluated_at: Utc::now(),
+    };
+    db.collection::<AcademicStanding>("academic_standings")
+        .replace_one(
+            doc! { "student_id": student_id, "campus_id": campus_id },
+            &record,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .context("Failed to save academic standing")?;
+    Ok(record)
+}
+
+/// Enrolls a student through the usual seat caps, picking the emptiest
+/// section when the offering has sections. Returns false when it is full.
+async fn enroll_with_seat(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course: &Course,
+    semester: &str,
+    student_id: &str,
+) -> anyhow::Result<bool> {
+    if !reserve_seat(db, campus_id, course, semester).await? {
+        return Ok(false);
+    }
+
+    let mut sections = offering_sections(db, campus_id, &course.course_code, semester).await?;
+    sections.sort_by_key(|s| s.enrolled);
+    let mut section_name = None;
+    for section in &sections {
+        if take_section_seat(db, section.id).await? {
+            section_name = Some(section.section_name.clone());
+            break;
+        }
+    }
+    if !sections.is_empty() && section_name.is_none() {
+        release_seat(db, campus_id, &course.course_code, semester).await?;
+        return Ok(false);
+    }
+
+    db.collection::<Enrollment>("enrollments")
+        .insert_one(Enrollment {
+            id: None,
+            student_id: student_id.to_string(),
+            course_code: course.course_code.clone(),
+            semester: semester.to_string(),
+            section: section_name,
+            campus_id: campus_id.to_string(),
+            enrolled_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to insert enrollment")?;
+    Ok(true)
+}
+
+/// Enrolls the longest-waiting student if a seat is free, returning their id.
+/// Students waiting for a full section are skipped in favour of later ones.
+async fn promote_from_waitlist(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course: &Course,
+    semester: &str,
+) -> anyhow::Result<Option<String>> {
+    if !reserve_seat(db, campus_id, course, semester).await? {
+        return Ok(None);
+    }
+
+    let waitlist: Collection<WaitlistEntry> = db.collection("waitlist");
+    let sections = offering_sections(db, campus_id, &course.course_code, semester).await?;
+    let options = FindOptions::builder().sort(doc! { "requested_at": 1 }).build();
+    let mut cursor = waitlist
+        .find(doc! { "course_code": &course.course_code, "semester": semester, "campus_id": campus_id }, options)
+        .await
+        .context("Failed to query waitlist")?;
+
+    let mut entries = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        entries.push(result.context("Failed to read waitlist entry")?);
+    }
+
+    for (entry, section) in promotion_order(entries, &sections) {
+        if let Some(section) = section {
+            if !take_section_seat(db, section.id).await? {
+                continue;
+            }
+        }
+
+        // Another request may have promoted this entry in the meantime
+        let claimed = waitlist
+            .find_one_and_delete(doc! { "_id": entry.id }, None)
+            .await
+            .context("Failed to pop waitlist")?;
+        if claimed.is_none() {
+            if let Some(section) = section {
+                release_section_seat(db, campus_id, &course.course_code, semester, &section.section_name).await?;
+            }
+            continue;
+        }
+
+        db.collection::<Enrollment>("enrollments")
+            .insert_one(Enrollment {
+                id: None,
+                student_id: entry.student_id.clone(),
+                course_code: entry.course_code,
+                semester: entry.semester,
+                section: section.map(|s| s.section_name.clone()),
+                campus_id: entry.campus_id,
+                enrolled_at: Utc::now(),
+            }, None)
+            .await
+            .context("Failed to enroll waitlisted student")?;
+
+        return Ok(Some(entry.student_id));
+    }
+
+    release_seat(db, campus_id, &course.course_code, semester).await?;
+    Ok(None)
+}
+
+/// Waitlist entries in the order they are offered a seat, earliest request
+/// first, each with the section it waits for. Entries for a section that is
+/// already full are left out; an unknown section name counts as none.
+fn promotion_order(mut entries: Vec<WaitlistEntry>, sections: &[Section]) -> Vec<(WaitlistEntry, Option<&Section>)> {
+    entries.sort_by_key(|e| e.requested_at);
+    entries
+        .into_iter()
+        .map(|entry| {
+            let section = entry
+                .section
+                .as_ref()
+                .and_then(|name| sections.iter().find(|s| s.section_name == *name));
+            (entry, section)
+        })
+        .filter(|(_, section)| section.is_none_or(|s| s.enrolled < s.capacity))
+        .collect()
+}
+
+/// Creates the indexes the service relies on for correctness and for the
+/// course catalog queries.
+async fn create_indexes(db: &mongodb::Database) -> anyhow::Result<()> {
+    let catalog = [
+        doc! { "campus_id": 1, "course_code": 1 },
+        doc! { "campus_id": 1, "department": 1, "course_code": 1 },
+        doc! { "campus_id": 1, "credits": 1 },
+        doc! { "campus_id": 1, "course_name": 1 },
+        doc! { "campus_id": 1, "created_at": -1 },
+    ];
+    db.collection::<Course>("courses")
+        .create_indexes(catalog.into_iter().map(|keys| IndexModel::builder().keys(keys).build()), None)
+        .await
+        .context("Failed to create course indexes")?;
+
+    db.collection::<Document>("course_seats")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "course_code": 1, "semester": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await
+        .context("Failed to create course_seats index")?;
+    db.collection::<Document>("attendance_checkins")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "session_id": 1, "student_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await
+        .context("Failed to create attendance_checkins index")?;
+    db.collection::<ResultPublication>("result_publications")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "course_code": 1, "semester": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await
+        .context("Failed to create result_publications index")?;
+
+    let removed = remove_duplicate_waitlist_entries(db).await?;
+    if removed > 0 {
+        warn!("Removed {} duplicate waitlist entries before indexing", removed);
+    }
+    db.collection::<WaitlistEntry>("waitlist")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "course_code": 1, "semester": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await
+        .context("Failed to create waitlist index")?;
+    Ok(())
+}
+
+/// Keeps only the earliest waitlist entry per student and course offering,
+/// so the unique waitlist index can be built over older data. Returns how
+/// many entries were removed.
+async fn remove_duplicate_waitlist_entries(db: &mongodb::Database) -> anyhow::Result<u64> {
+    use futures::stream::StreamExt;
+
+    let waitlist: Collection<WaitlistEntry> = db.collection("waitlist");
+    let pipeline = [
+        doc! { "$sort": { "requested_at": 1 } },
+        doc! { "$group": {
+            "_id": { "student_id": "$student_id", "course_code": "$course_code", "semester": "$semester", "campus_id": "$campus_id" },
+            "ids": { "$push": "$_id" },
+            "count": { "$sum": 1 }
+        } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+    let mut cursor = waitlist
+        .aggregate(pipeline, None)
+        .await
+        .context("Failed to look for duplicate waitlist entries")?;
+
+    let mut later_entries = Vec::new();
+    while let Some(group) = cursor.next().await {
+        let group = group.context("Failed to read duplicate waitlist entries")?;
+        if let Ok(ids) = group.get_array("ids") {
+            later_entries.extend(ids.iter().skip(1).cloned());
+        }
+    }
+    if later_entries.is_empty() {
+        return Ok(0);
+    }
+
+    let result = waitlist
+        .delete_many(doc! { "_id": { "$in": later_entries } }, None)
+        .await
+        .context("Failed to remove duplicate waitlist entries")?;
+    Ok(result.deleted_count)
+}
+
+/// True for a write rejected by a unique index.
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref we)) if we.code == 11000
+    )
+}
+
+/// One student's row in an attendance export.
+struct AttendanceMatrixRow {
+    student_id: String,
+    /// Per date: "P", "A" or "L", joined with "/" when a day had several classes
+    cells: Vec<String>,
+    attended: i32,
+    total: i32,
+}
+
+/// Pivots attendance records into students × dates. Late counts as attended.
+fn attendance_matrix(records: &[Attendance]) -> (Vec<String>, Vec<AttendanceMatrixRow>) {
+    let dates: Vec<String> = records
+        .iter()
+        .map(|r| r.date.clone())
+        .collect::<std::collections::BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let students: std::collections::BTreeSet<&str> = records.iter().map(|r| r.student_id.as_str()).collect();
+
+    let rows = students
+        .into_iter()
+        .map(|student_id| {
+            let mine: Vec<&Attendance> = records.iter().filter(|r| r.student_id == student_id).collect();
+            let cells = dates
+                .iter()
+                .map(|date| {
+                    mine.iter()
+                        .filter(|r| r.date == *date)
+                        .map(|r| match r.status.as_str() {
+                            "present" => "P",
+                            "late" => "L",
+                            _ => "A",
+                        })
+                        .collect::<Vec<_>>()
+                        .join("/")
+                })
+                .collect();
+            AttendanceMatrixRow {
+                student_id: student_id.to_string(),
+                cells,
+                attended: mine.iter().filter(|r| r.status == "present" || r.status == "late").count() as i32,
+                total: mine.len() as i32,
+            }
+        })
+        .collect();
+    (dates, rows)
+}
+
+fn attendance_percentage(row: &AttendanceMatrixRow) -> f64 {
+    if row.total == 0 {
+        0.0
+    } else {
+        round2(row.attended as f64 * 100.0 / row.total as f64)
+    }
+}
+
+fn attendance_matrix_csv(dates: &[String], rows: &[AttendanceMatrixRow]) -> anyhow::Result<Vec<u8>> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let mut header = vec!["student_id".to_string()];
+    header.extend(dates.iter().cloned());
+    header.extend(["attended".to_string(), "total".to_string(), "percentage".to_string()]);
+    writer.write_record(&header).context("Failed to write CSV header")?;
+
+    for row in rows {
+        let mut record = vec![row.student_id.clone()];
+        record.extend(row.cells.iter().cloned());
+        record.extend([
+            row.attended.to_string(),
+            row.total.to_string(),
+            attendance_percentage(row).to_string(),
+        ]);
+        writer.write_record(&record).context("Failed to write CSV row")?;
+    }
+    writer.into_inner().context("Failed to finish CSV")
+}
+
+fn attendance_matrix_xlsx(
+    course_code: &str,
+    dates: &[String],
+    rows: &[AttendanceMatrixRow],
+) -> anyhow::Result<Vec<u8>> {
+    use rust_xlsxwriter::{Format, Workbook};
+
+    let mut workbook = Workbook::new();
+    let sheet = workbook.add_worksheet();
+    // Sheet names are capped at 31 characters and cannot contain []:*?/\
+    let sheet_name: String = course_code
+        .chars()
+        .filter(|c| !"[]:*?/\\".contains(*c))
+        .take(31)
+        .collect();
+    if !sheet_name.is_empty() {
+        sheet.set_name(sheet_name).context("Failed to name worksheet")?;
+    }
+
+    let bold = Format::new().set_bold();
+    let summary_col = dates.len() as u16 + 1;
+    sheet.write_string_with_format(0, 0, "Student ID", &bold)?;
+    for (i, date) in dates.iter().enumerate() {
+        sheet.write_string_with_format(0, i as u16 + 1, date, &bold)?;
+    }
+    sheet.write_string_with_format(0, summary_col, "Attended", &bold)?;
+    sheet.write_string_with_format(0, summary_col + 1, "Total", &bold)?;
+    sheet.write_string_with_format(0, summary_col + 2, "Percentage", &bold)?;
+
+    for (r, row) in rows.iter().enumerate() {
+        let r = r as u32 + 1;
+        sheet.write_string(r, 0, &row.student_id)?;
+        for (c, cell) in row.cells.iter().enumerate() {
+            sheet.write_string(r, c as u16 + 1, cell)?;
+        }
+        sheet.write_number(r, summary_col, row.attended)?;
+        sheet.write_number(r, summary_col + 1, row.total)?;
+        sheet.write_number(r, summary_col + 2, attendance_percentage(row))?;
+    }
+    sheet.set_freeze_panes(1, 1)?;
+
+    workbook.save_to_buffer().context("Failed to build XLSX")
+}
+
+/// Random code printed on a transcript, e.g. "7KQ2-M9XD-4TRA".
+fn generate_verification_code() -> String {
+    use rand::Rng;
+    const ALPHABET: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ23456789";
+    let mut rng = rand::thread_rng();
+    let chars: Vec<char> = (0..12)
+        .map(|_| ALPHABET[rng.gen_range(0..ALPHABET.len())] as char)
+        .collect();
+    chars
+        .chunks(4)
+        .map(|c| c.iter().collect::<String>())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Graded courses from the grade card, followed by enrolled courses that have
+/// no grades yet, grouped by semester.
+async fn collect_transcript(
+    db: &mongodb::Database,
+    campus_id: &str,
+    card: &GradeCard,
+) -> anyhow::Result<Vec<TranscriptSemester>> {
+    let mut semesters: Vec<TranscriptSemester> = card
+        .semesters
+        .iter()
+        .map(|s| TranscriptSemester {
+            semester: s.semester.clone(),
+            lines: s
+                .courses
+                .iter()
+                .map(|c| TranscriptLine {
+                    course_code: c.course_code.clone(),
+                    course_name: c.course_name.clone(),
+                    credits: c.credits,
+                    marks: Some((c.marks_obtained, c.max_marks)),
+                    grade: c.grade.clone(),
+                    grade_points: Some(c.grade_points),
+                })
+                .collect(),
+            sgpa: Some(s.sgpa),
+        })
+        .collect();
+
+    let enrollments: Collection<Enrollment> = db.collection("enrollments");
+    let courses: Collection<Course> = db.collection("courses");
+    let options = FindOptions::builder().sort(doc! { "enrolled_at": 1 }).build();
+    let mut cursor = enrollments
+        .find(doc! { "student_id": &card.student_id, "campus_id": campus_id }, options)
+        .await
+        .context("Failed to query enrollments")?;
+
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let enrollment = result.context("Failed to read enrollment")?;
+        let graded = semesters.iter().any(|s| {
+            s.semester == enrollment.semester
+                && s.lines.iter().any(|l| l.course_code == enrollment.course_code)
+        });
+        if graded {
+            continue;
+        }
+
+        let course = courses
+            .find_one(doc! { "course_code": &enrollment.course_code, "campus_id": campus_id }, None)
+            .await
+            .context("Failed to fetch course details")?;
+        let line = TranscriptLine {
+            course_code: enrollment.course_code,
+            course_name: course.as_ref().map(|c| c.course_name.clone()).unwrap_or_default(),
+            credits: course.map(|c| c.credits).unwrap_or(0),
+            marks: None,
+            grade: "IP".to_string(),
+            grade_points: None,
+        };
+        match semesters.iter_mut().find(|s| s.semester == enrollment.semester) {
+            Some(semester) => semester.lines.push(line),
+            None => semesters.push(TranscriptSemester {
+                semester: enrollment.semester,
+                lines: vec![line],
+                sgpa: None,
+            }),
+        }
+    }
+
+    Ok(semesters)
+}
+
+/// Renders an A4 transcript with the institution header, one table per
+/// semester and the cumulative summary.
+fn render_transcript_pdf(
+    institution_name: &str,
+    campus_id: &str,
+    card: &GradeCard,
+    semesters: &[TranscriptSemester],
+    record: &TranscriptRecord,
+) -> anyhow::Result<Vec<u8>> {
+    use printpdf::{BuiltinFont, Line, Mm, PdfDocument, PdfLayerReference, Point};
+
+    const TOP: f32 = 277.0;
+    const BOTTOM: f32 = 25.0;
+    const LEFT: f32 = 20.0;
+    const RIGHT: f32 = 190.0;
+    const ROW: f32 = 6.0;
+
+    let title = format!("Transcript - {}", card.student_id);
+    let (doc, page, layer) = PdfDocument::new(title, Mm(210.0), Mm(297.0), "Transcript");
+    let font = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| anyhow::anyhow!("Failed to load font: {}", e))?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| anyhow::anyhow!("Failed to load font: {}", e))?;
+
+    let rule = |layer: &PdfLayerReference, y: f32| {
+        layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(LEFT), Mm(y)), false),
+                (Point::new(Mm(RIGHT), Mm(y)), false),
+            ],
+            is_closed: false,
+        });
+    };
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    let mut y = TOP;
+
+    layer.use_text(institution_name, 18.0, Mm(LEFT), Mm(y), &bold);
+    y -= 8.0;
+    layer.use_text("Official Academic Transcript", 12.0, Mm(LEFT), Mm(y), &font);
+    y -= 6.0;
+    layer.use_text(format!("Campus: {}", campus_id), 10.0, Mm(LEFT), Mm(y), &font);
+    y -= 4.0;
+    rule(&layer, y);
+    y -= 8.0;
+
+    layer.use_text(format!("Student ID: {}", card.student_id), 10.0, Mm(LEFT), Mm(y), &font);
+    layer.use_text(
+        format!("Issued: {}", record.issued_at.format("%Y-%m-%d")),
+        10.0,
+        Mm(130.0),
+        Mm(y),
+        &font,
+    );
+    y -= 10.0;
+
+    let columns: [(&str, f32); 6] = [
+        ("Code", LEFT),
+        ("Course", 42.0),
+        ("Credits", 120.0),
+        ("Marks", 138.0),
+        ("Grade", 162.0),
+        ("Points", 177.0),
+    ];
+
+    for semester in semesters {
+        // Keep the semester heading with at least its first rows
+        if y - 3.0 * ROW < BOTTOM {
+            let (page, page_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Transcript");
+            layer = doc.get_page(page).get_layer(page_layer);
+            y = TOP;
+        }
+
+        layer.use_text(&semester.semester, 11.0, Mm(LEFT), Mm(y), &bold);
+        if let Some(sgpa) = semester.sgpa {
+            layer.use_text(format!("SGPA: {:.2}", sgpa), 10.0, Mm(162.0), Mm(y), &bold);
+        }
+        y -= ROW;
+        for (label, x) in columns {
+            layer.use_text(label, 9.0, Mm(x), Mm(y), &bold);
+        }
+        y -= 2.0;
+        rule(&layer, y);
+        y -= 4.0;
+
+        for line in &semester.lines {
+            if y < BOTTOM {
+                let (page, page_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Transcript");
+                layer = doc.get_page(page).get_layer(page_layer);
+                y = TOP;
+            }
+            let name: String = line.course_name.chars().take(40).collect();
+            let marks = line
+                .marks
+                .map(|(obtained, max)| format!("{}/{}", obtained, max))
+                .unwrap_or_else(|| "-".to_string());
+            let points = line
+                .grade_points
+                .map(|p| format!("{:.1}", p))
+                .unwrap_or_else(|| "-".to_string());
+            let cells = [
+                line.course_code.clone(),
+                name,
+                line.credits.to_string(),
+                marks,
+                line.grade.clone(),
+                points,
+            ];
+            for (cell, (_, x)) in cells.iter().zip(columns) {
+                layer.use_text(cell, 9.0, Mm(x), Mm(y), &font);
+            }
+            y -= ROW - 1.0;
+        }
+        y -= ROW;
+    }
+
+    if y - 4.0 * ROW < BOTTOM {
+        let (page, page_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Transcript");
+        layer = doc.get_page(page).get_layer(page_layer);
+        y = TOP;
+    }
+    rule(&layer, y);
+    y -= ROW;
+    layer.use_text(
+        format!(
+            "CGPA: {:.2}    Credits attempted: {}    Credits earned: {}",
+            card.cgpa, card.total_credits, card.total_credits_earned
+        ),
+        10.0,
+        Mm(LEFT),
+        Mm(y),
+        &bold,
+    );
+    y -= ROW;
+    layer.use_text("IP = in progress. Grade points on a 10-point scale.", 8.0, Mm(LEFT), Mm(y), &font);
+    y -= ROW + 2.0;
+    layer.use_text(
+        format!("Verification code: {}", record.verification_code),
+        10.0,
+        Mm(LEFT),
+        Mm(y),
+        &bold,
+    );
+    y -= 5.0;
+    layer.use_text(
+        "Verify at GET /api/transcripts/verify/{code} on the academics service.",
+        8.0,
+        Mm(LEFT),
+        Mm(y),
+        &font,
+    );
+
+    doc.save_to_bytes()
+        .map_err(|e| anyhow::anyhow!("Failed to render transcript PDF: {}", e))
+}
+
+/// Lifetime of a QR token; faculty screens fetch a fresh one before it lapses.
+const ATTENDANCE_QR_TTL_SECS: u64 = 30;
+const ATTENDANCE_SESSION_DEFAULT_MINS: i64 = 10;
+const ATTENDANCE_SESSION_MAX_MINS: i64 = 60;
+
+/// Attendance QR tokens are signed with HMAC-SHA256(JWT_SECRET, this label)
+/// rather than JWT_SECRET itself.
+const ATTENDANCE_QR_KEY_LABEL: &[u8] = b"qr-attendance";
+
+/// Derives the QR signing key, keeping it distinct from every session key.
+fn derive_qr_key(jwt_secret: &str) -> Vec<u8> {
+    use hmac::Mac;
+    let mut mac = hmac::Hmac::<sha2::Sha256>::new_from_slice(jwt_secret.as_bytes())
+        .expect("HMAC accepts keys of any length");
+    mac.update(ATTENDANCE_QR_KEY_LABEL);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn issue_attendance_qr(session: &AttendanceSession, qr_key: &[u8]) -> anyhow::Result<serde_json::Value> {
+    let session_id = session.id.context("Attendance session has no id")?.to_hex();
+    let now = get_current_timestamp();
+    // Never outlive the session itself
+    let exp = (now + ATTENDANCE_QR_TTL_SECS).min(session.expires_at.timestamp().max(0) as u64);
+    let claims = AttendanceQrClaims {
+        sid: session_id.clone(),
+        campus_id: session.campus_id.clone(),
+        iat: now as usize,
+        exp: exp as usize,
+    };
+    let token = encode(
+        &Header::new(Algorithm::HS256),
+        &claims,
+        &EncodingKey::from_secret(qr_key),
+    )
+    .context("Failed to sign attendance token")?;
+
+    Ok(serde_json::json!({
+        "session_id": session_id,
+        "token": token,
+        "expires_in": exp.saturating_sub(now),
+        "session_expires_at": session.expires_at,
+        "qr_payload": format!("campusconnect://attendance/checkin?token={}", token)
+    }))
+}
+
+fn decode_attendance_qr(token: &str, qr_key: &[u8]) -> Option<AttendanceQrClaims> {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_required_spec_claims(&["exp", "iat"]);
+    // QR tokens are valid for seconds; a minute of leeway would defeat that
+    validation.leeway = 2;
+
+    decode::<AttendanceQrClaims>(
+        token,
+        &DecodingKey::from_secret(qr_key),
+        &validation,
+    )
+    .ok()
+    .map(|data| data.claims)
+}
+
+const ATTENDANCE_SHORTAGE_ALERT: &str = "attendance_shortage";
+const EXTRA_CLASS_ALERT: &str = "extra_class";
+
+/// Sends the same notice to each student through the `alerts` collection.
+async fn notify_students(
+    db: &mongodb::Database,
+    campus_id: &str,
+    student_ids: &[String],
+    course_code: &str,
+    message: &str,
+    extra_class_id: Option<ObjectId>,
+) -> anyhow::Result<()> {
+    if student_ids.is_empty() {
+        return Ok(());
+    }
+    let now = Utc::now();
+    let alerts: Vec<Alert> = student_ids
+        .iter()
+        .map(|student_id| Alert {
+            id: None,
+            kind: EXTRA_CLASS_ALERT.to_string(),
+            student_id: student_id.clone(),
+            course_code: course_code.to_string(),
+            message: message.to_string(),
+            attendance_percentage: None,
+            threshold: None,
+            classes_attended: None,
+            total_classes: None,
+            extra_class_id,
+            resolved: false,
+            acknowledged_by: Vec::new(),
+            campus_id: campus_id.to_string(),
+            created_at: now,
+            updated_at: now,
+        })
+        .collect();
+    db.collection::<Alert>("alerts")
+        .insert_many(alerts, None)
+        .await
+        .context("Failed to notify students")?;
+    Ok(())
+}
+
+/// Flags (student, course) pairs whose attendance is below `threshold` by
+/// opening or refreshing an alert, and resolves alerts for students who have
+/// recovered. Scans every campus when `campus_id` is None.
+/// Returns (flagged, resolved) counts.
+async fn scan_attendance_shortages(
+    db: &mongodb::Database,
+    campus_id: Option<&str>,
+    course_code: Option<&str>,
+    threshold: f64,
+) -> anyhow::Result<(u64, u64)> {
+    let mut scope = doc! {};
+    if let Some(campus_id) = campus_id {
+        scope.insert("campus_id", campus_id);
+    }
+    if let Some(course_code) = course_code {
+        scope.insert("course_code", course_code);
+    }
+
+    let pipeline = vec![
+        doc! { "$match": scope },
+        doc! { "$group": {
+            "_id": { "campus_id": "$campus_id", "student_id": "$student_id", "course_code": "$course_code" },
+            "total": { "$sum": 1 },
+            "attended": { "$sum": { "$cond": [{ "$in": ["$status", ["present", "late"]] }, 1, 0] } }
+        }},
+    ];
+
+    let attendance: Collection<Attendance> = db.collection("attendance");
+    let alerts: Collection<Alert> = db.collection("alerts");
+    let mut cursor = attendance
+        .aggregate(pipeline, None)
+        .await
+        .context("Failed to aggregate attendance")?;
+
+    let (mut flagged, mut resolved) = (0, 0);
+    let now = Utc::now();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let row = result.context("Failed to read attendance summary")?;
+        let key = row.get_document("_id").context("Attendance summary has no key")?;
+        let (campus_id, student_id, course_code) = (
+            key.get_str("campus_id").unwrap_or_default(),
+            key.get_str("student_id").unwrap_or_default(),
+            key.get_str("course_code").unwrap_or_default(),
+        );
+        let total = row.get_i32("total").unwrap_or(0);
+        let attended = row.get_i32("attended").unwrap_or(0);
+        if total == 0 {
+            continue;
+        }
+        let percentage = round2(attended as f64 / total as f64 * 100.0);
+
+        let open_alert = doc! {
+            "kind": ATTENDANCE_SHORTAGE_ALERT,
+            "student_id": student_id,
+            "course_code": course_code,
+            "campus_id": campus_id,
+            "resolved": false
+        };
+
+        if percentage >= threshold {
+            let result = alerts
+                .update_many(open_alert, doc! { "$set": {
+                    "resolved": true,
+                    "attendance_percentage": percentage,
+                    "updated_at": now.to_rfc3339()
+                }}, None)
+                .await
+                .context("Failed to resolve attendance alert")?;
+            resolved += result.modified_count;
+            continue;
+        }
+
+        let message = format!(
+            "Attendance in {} is {:.1}% ({} of {} classes), below the required {:.0}%",
+            course_code, percentage, attended, total, threshold
+        );
+        let existing = alerts
+            .find_one(open_alert.clone(), None)
+            .await
+            .context("Failed to fetch attendance alert")?;
+        match existing {
+            Some(alert) => {
+                alerts
+                    .update_one(doc! { "_id": alert.id }, doc! { "$set": {
+                        "message": &message,
+                        "attendance_percentage": percentage,
+                        "threshold": threshold,
+                        "classes_attended": attended,
+                        "total_classes": total,
+                        "updated_at": now.to_rfc3339()
+                    }}, None)
+                    .await
+                    .context("Failed to update attendance alert")?;
+            }
+            None => {
+                alerts
+                    .insert_one(Alert {
+                        id: None,
+                        kind: ATTENDANCE_SHORTAGE_ALERT.to_string(),
+                        student_id: student_id.to_string(),
+                        course_code: course_code.to_string(),
+                        message,
+                        attendance_percentage: Some(percentage),
+                        threshold: Some(threshold),
+                        classes_attended: Some(attended),
+                        total_classes: Some(total),
+                        extra_class_id: None,
+                        resolved: false,
+                        acknowledged_by: Vec::new(),
+                        campus_id: campus_id.to_string(),
+                        created_at: now,
+                        updated_at: now,
+                    }, None)
+                    .await
+                    .context("Failed to insert attendance alert")?;
+            }
+        }
+        flagged += 1;
+    }
+
+    Ok((flagged, resolved))
+}
+
+/// Groups slots into a monday…sunday map, each day sorted by start time.
+fn weekly_view(mut slots: Vec<TimetableSlot>) -> serde_json::Value {
+    slots.sort_by(|a, b| a.start_time.cmp(&b.start_time));
+    let mut week = serde_json::Map::new();
+    for day in WEEKDAYS {
+        let day_slots: Vec<&TimetableSlot> = slots.iter().filter(|s| s.day == day).collect();
+        week.insert(day.to_string(), serde_json::json!(day_slots));
+    }
+    serde_json::Value::Object(week)
+}
+
+async fn collect_slots(
+    collection: &Collection<TimetableSlot>,
+    filter: mongodb::bson::Document,
+) -> anyhow::Result<Vec<TimetableSlot>> {
+    let mut cursor = collection
+        .find(filter, None)
+        .await
+        .context("Failed to query timetable")?;
+
+    let mut slots = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        slots.push(result.context("Failed to read timetable slot from cursor")?);
+    }
+    Ok(slots)
+}
+
+/// Student ids enrolled in a course offering.
+async fn enrolled_student_ids(
+    db: &mongodb::Database,
+    campus_id: &str,
+    course_code: &str,
+    semester: &str,
+    section: Option<&str>,
+) -> anyhow::Result<Vec<String>> {
+    let collection: Collection<Enrollment> = db.collection("enrollments");
+    let options = FindOptions::builder().sort(doc! { "student_id": 1 }).build();
+    let mut filter = doc! {
+        "course_code": course_code,
+        "semester": semester,
+        "campus_id": campus_id
+    };
+    if let Some(section) = section {
+        filter.insert("section", section);
+    }
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query enrollments")?;
+
+    let mut student_ids = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        student_ids.push(result.context("Failed to read enrollment")?.student_id);
+    }
+    Ok(student_ids)
+}
+
+/// Exams on `date` whose time range overlaps [start_time, end_time).
+async fn overlapping_exams(
+    db: &mongodb::Database,
+    campus_id: &str,
+    date: &str,
+    start_time: &str,
+    end_time: &str,
+) -> anyhow::Result<Vec<Exam>> {
+    let collection: Collection<Exam> = db.collection("exams");
+    let mut cursor = collection
+        .find(doc! {
+            "campus_id": campus_id,
+            "date": date,
+            "start_time": { "$lt": end_time },
+            "end_time": { "$gt": start_time }
+        }, None)
+        .await
+        .context("Failed to query exams")?;
+
+    let mut exams = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        exams.push(result.context("Failed to read exam")?);
+    }
+    Ok(exams)
+}
+
+// ── Handlers ──────────────────────────────────────────────────────────────────
+
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "service": "academics-service"
+    }))
+}
+
+// ── Course Management ─────────────────────────────────────────────────────────
+
+async fn create_course(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let course_data: CourseRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = require_field(&course_data.course_code, "course_code")?;
+    let course_name = require_field(&course_data.course_name, "course_name")?;
+    let department = require_field(&course_data.department, "department")?;
+    let credits = require_i32(course_data.credits, "credits")?;
+
+    if !(1..=6).contains(&credits) {
+        return Err(AppError::BadRequest("Credits must be between 1 and 6".to_string()));
+    }
+
+    if course_data.max_seats.is_some_and(|seats| seats < 1) {
+        return Err(AppError::BadRequest("max_seats must be at least 1".to_string()));
+    }
+
+    let prerequisites = validate_prerequisites(
+        &data.db,
+        &claims.campus_id,
+        course_code,
+        course_data.prerequisites.clone().unwrap_or_default(),
+    )
+    .await?;
+
+    let collection: Collection<Course> = data.db.collection("courses");
+
+    let new_course = Course {
+        id: None,
+        course_code: course_code.to_string(),
+        course_name: course_name.to_string(),
+        credits,
+        department: department.to_string(),
+        prerequisites,
+        max_seats: course_data.max_seats,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    collection
+        .insert_one(new_course, None)
+        .await
+        .context("Failed to insert course")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Course created successfully" })))
+}
+
+async fn get_courses(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<CourseFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<Course> = data.db.collection("courses");
+
+    // Build filter — always scope to campus_id, optionally filter by department/credits/text
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(dept) = &query.department {
+        if !dept.trim().is_empty() {
+            filter.insert("department", dept.as_str());
+        }
+    }
+    if let Some(credits) = query.credits {
+        filter.insert("credits", credits);
+    }
+    if let Some(q) = &query.q {
+        if !q.trim().is_empty() {
+            let pattern = regex_escape(q.trim());
+            filter.insert("$or", vec![
+                doc! { "course_code": { "$regex": &pattern, "$options": "i" } },
+                doc! { "course_name": { "$regex": &pattern, "$options": "i" } },
+            ]);
+        }
+    }
+    let sort = parse_sort(query.sort.as_deref(), &COURSE_SORT_FIELDS, doc! { "created_at": -1 })?;
+
+    let pagination = PaginationParams { page: query.page, limit: query.limit };
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .context("Failed to count courses")?;
+
+    let options = FindOptions::builder()
+        .skip(pagination.skip())
+        .limit(pagination.limit() as i64)
+        .sort(sort)
+        .build();
+
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query courses")?;
+
+    let mut courses = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let course = result.context("Failed to read course from cursor")?;
+        courses.push(course);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "data": courses,
+        "pagination": {
+            "page": pagination.page(),
+            "limit": pagination.limit(),
+            "total": total,
+            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
+        }
+    })))
+}
+
+/// PUT /api/courses/{id}
+/// Partial update; the course code is fixed once created because other
+/// collections reference courses by code.
+async fn update_course(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can update courses".to_string()));
+    }
+
+    let course_data: CourseRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid course ID format".to_string()))?;
+
+    let collection: Collection<Course> = data.db.collection("courses");
+    let course = collection
+        .find_one(doc! { "_id": course_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound("Course not found".to_string()))?;
+
+    if course_data.course_code.as_ref().is_some_and(|code| *code != course.course_code) {
+        return Err(AppError::BadRequest("course_code cannot be changed".to_string()));
+    }
+
+    let mut update = doc! {};
+    if course_data.course_name.is_some() {
+        update.insert("course_name", require_field(&course_data.course_name, "course_name")?);
+    }
+    if course_data.department.is_some() {
+        update.insert("department", require_field(&course_data.department, "department")?);
+    }
+    if let Some(credits) = course_data.credits {
+        if !(1..=6).contains(&credits) {
+            return Err(AppError::BadRequest("Credits must be between 1 and 6".to_string()));
+        }
+        update.insert("credits", credits);
+    }
+    if let Some(prerequisites) = course_data.prerequisites.clone() {
+        let prerequisites =
+            validate_prerequisites(&data.db, &claims.campus_id, &course.course_code, prerequisites).await?;
+        update.insert("prerequisites", prerequisites);
+    }
+    if let Some(max_seats) = course_data.max_seats {
+        if max_seats < 1 {
+            return Err(AppError::BadRequest("max_seats must be at least 1".to_string()));
+        }
+        update.insert("max_seats", max_seats);
+    }
+
+    if update.is_empty() {
+        return Err(AppError::BadRequest("No fields to update".to_string()));
+    }
+
+    collection
+        .update_one(doc! { "_id": course_id }, doc! { "$set": update }, None)
+        .await
+        .context("Failed to update course")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Course updated successfully" })))
+}
+
+/// DELETE /api/courses/{id}
+/// Refused while students are enrolled unless `?force=true`, which also
+/// removes the course's enrollments, attendance, timetable slots, waitlist and sections.
+async fn delete_course(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<CourseDeleteQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can delete courses".to_string()));
+    }
+
+    let course_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid course ID format".to_string()))?;
+
+    let collection: Collection<Course> = data.db.collection("courses");
+    let course = collection
+        .find_one(doc! { "_id": course_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound("Course not found".to_string()))?;
+
+    let scope = doc! { "course_code": &course.course_code, "campus_id": &claims.campus_id };
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    let enrolled = enrollments
+        .count_documents(scope.clone(), None)
+        .await
+        .context("Failed to count enrollments")?;
+
+    let force = query.force.unwrap_or(false);
+    if enrolled > 0 && !force {
+        return Err(AppError::Conflict(format!(
+            "Course has {} active enrollment(s); pass force=true to delete them as well",
+            enrolled
+        )));
+    }
+
+    let mut removed = serde_json::Map::new();
+    if force {
+        for name in ["enrollments", "attendance", "timetable", "waitlist", "course_seats", "sections"] {
+            let result = data
+                .db
+                .collection::<mongodb::bson::Document>(name)
+                .delete_many(scope.clone(), None)
+                .await
+                .with_context(|| format!("Failed to delete course {}", name))?;
+            removed.insert(name.to_string(), result.deleted_count.into());
+        }
+    }
+
+    collection
+        .delete_one(doc! { "_id": course_id }, None)
+        .await
+        .context("Failed to delete course")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Course deleted successfully",
+        "removed": removed
+    })))
+}
+
+// ── Enrollment Management ─────────────────────────────────────────────────────
+
+async fn create_enrollment(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let enrollment_data: EnrollmentRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let student_id = require_field(&enrollment_data.student_id, "student_id")?;
+    let course_code = require_field(&enrollment_data.course_code, "course_code")?;
+    let semester = require_field(&enrollment_data.semester, "semester")?;
+
+    let collection: Collection<Enrollment> = data.db.collection("enrollments");
+
+    let existing = collection
+        .find_one(doc! {
+            "student_id": student_id,
+            "course_code": course_code,
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .context("Failed to check existing enrollment")?;
+
+    // Option::is_some() — if already enrolled, reject with 400
+    if existing.is_some() {
+        return Err(AppError::BadRequest(
+            "Student already enrolled in this course".to_string(),
+        ));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    let course = courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    let missing = missing_prerequisites(&data.db, &claims.campus_id, student_id, semester, &course.prerequisites).await?;
+    if !missing.is_empty() {
+        return Err(AppError::BadRequest(format!(
+            "Missing prerequisites: {}",
+            missing.join(", ")
+        )));
+    }
+
+    let section = resolve_section(&data.db, &claims.campus_id, course_code, semester, enrollment_data.section.as_deref()).await?;
+    let section_name = section.as_ref().map(|s| s.section_name.clone());
+
+    if !reserve_seat(&data.db, &claims.campus_id, &course, semester).await? {
+        let waitlist: Collection<WaitlistEntry> = data.db.collection("waitlist");
+        let offering = doc! { "course_code": course_code, "semester": semester, "campus_id": &claims.campus_id };
+
+        // The unique (student_id, course_code, semester, campus_id) index
+        // rejects a second entry, including one racing this request
+        let inserted = waitlist
+            .insert_one(WaitlistEntry {
+                id: None,
+                student_id: student_id.to_string(),
+                course_code: course_code.to_string(),
+                semester: semester.to_string(),
+                section: section_name,
+                campus_id: claims.campus_id.clone(),
+                requested_at: Utc::now(),
+            }, None)
+            .await;
+        if let Err(e) = inserted {
+            if is_duplicate_key(&e) {
+                return Err(AppError::BadRequest("Student is already on the waitlist for this course".to_string()));
+            }
+            return Err(anyhow::Error::new(e).context("Failed to add to waitlist").into());
+        }
+
+        let position = waitlist
+            .count_documents(offering, None)
+            .await
+            .context("Failed to count waitlist")?;
+
+        return Ok(HttpResponse::Accepted().json(serde_json::json!({
+            "message": "Course is full; student added to the waitlist",
+            "waitlist_position": position
+        })));
+    }
+
+    if let Some(section) = &section {
+        if !take_section_seat(&data.db, section.id).await? {
+            release_seat(&data.db, &claims.campus_id, course_code, semester).await?;
+            return Err(AppError::Conflict(format!(
+                "Section '{}' is full",
+                section.section_name
+            )));
+        }
+    }
+
+    let new_enrollment = Enrollment {
+        id: None,
+        student_id: student_id.to_string(),
+        course_code: course_code.to_string(),
+        semester: semester.to_string(),
+        section: section_name,
+        campus_id: claims.campus_id.clone(),
+        enrolled_at: Utc::now(),
+    };
+
+    if let Err(e) = collection.insert_one(new_enrollment, None).await {
+        // Hand back the seats taken above, or they would stay counted forever
+        release_seat(&data.db, &claims.campus_id, course_code, semester).await?;
+        if let Some(section) = &section {
+            release_section_seat(&data.db, &claims.campus_id, course_code, semester, &section.section_name).await?;
+        }
+        return Err(anyhow::Error::new(e).context("Failed to insert enrollment").into());
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Enrollment created successfully" })))
+}
+
+/// Paginated enrollments for a campus, optionally for one student.
+async fn list_enrollments(
+    db: &mongodb::Database,
+    campus_id: &str,
+    query: &EnrollmentFilter,
+    student_id: Option<&str>,
+) -> Result<serde_json::Value, AppError> {
+    let collection: Collection<Enrollment> = db.collection("enrollments");
+
+    // Build filter — always scope to campus_id, optionally filter by student/semester/course
+    let mut filter = doc! { "campus_id": campus_id };
+    if let Some(student_id) = student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            filter.insert("semester", semester.as_str());
+        }
+    }
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+    if let Some(section) = &query.section {
+        if !section.trim().is_empty() {
+            filter.insert("section", section.as_str());
+        }
+    }
+
+    let pagination = PaginationParams { page: query.page, limit: query.limit };
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .context("Failed to count enrollments")?;
+
+    let options = FindOptions::builder()
+        .skip(pagination.skip())
+        .limit(pagination.limit() as i64)
+        .sort(doc! { "enrolled_at": -1 })
+        .build();
+
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query enrollments")?;
+
+    let mut enrollments = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let enrollment = result.context("Failed to read enrollment from cursor")?;
+        enrollments.push(enrollment);
+    }
+
+    Ok(serde_json::json!({
+        "data": enrollments,
+        "pagination": {
+            "page": pagination.page(),
+            "limit": pagination.limit(),
+            "total": total,
+            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
+        }
+    }))
+}
+
+/// GET /api/enrollments
+/// Campus-wide listing for teachers and admins; students use /api/my/enrollments.
+async fn get_enrollments(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<EnrollmentFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden(
+            "Only teachers can list all enrollments; use /api/my/enrollments".to_string(),
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(list_enrollments(&data.db, &claims.campus_id, &query, None).await?))
+}
+
+/// DELETE /api/enrollments/{id}
+/// Drops an enrollment and hands the freed seat to the first waitlisted student.
+async fn drop_enrollment(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let enrollment_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid enrollment ID format".to_string()))?;
+
+    let collection: Collection<Enrollment> = data.db.collection("enrollments");
+    let enrollment = collection
+        .find_one(doc! { "_id": enrollment_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch enrollment")?
+        .ok_or_else(|| AppError::NotFound("Enrollment not found".to_string()))?;
+
+    let allowed = match claims.role.as_str() {
+        "teacher" | "admin" => true,
+        "student" => claims.sub == enrollment.student_id,
+        _ => false,
+    };
+    if !allowed {
+        return Err(AppError::Forbidden("Access denied: You cannot drop this enrollment".to_string()));
+    }
+
+    // Only the request that actually removes the enrollment gives its seat
+    // back; a concurrent drop of the same enrollment finds nothing here
+    let enrollment = collection
+        .find_one_and_delete(doc! { "_id": enrollment_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to delete enrollment")?
+        .ok_or_else(|| AppError::NotFound("Enrollment not found".to_string()))?;
+
+    release_seat(&data.db, &claims.campus_id, &enrollment.course_code, &enrollment.semester).await?;
+    if let Some(section) = &enrollment.section {
+        release_section_seat(&data.db, &claims.campus_id, &enrollment.course_code, &enrollment.semester, section).await?;
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    let course = courses
+        .find_one(doc! { "course_code": &enrollment.course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?;
+
+    let promoted = match course {
+        Some(course) => promote_from_waitlist(&data.db, &claims.campus_id, &course, &enrollment.semester).await?,
+        None => None,
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Enrollment dropped successfully",
+        "promoted_student_id": promoted
+    })))
+}
+
+/// GET /api/waitlist
+/// Teachers see whole queues; students see their own entries.
+async fn get_waitlist(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<WaitlistFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<WaitlistEntry> = data.db.collection("waitlist");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            filter.insert("semester", semester.as_str());
+        }
+    }
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    }
+
+    let options = FindOptions::builder().sort(doc! { "requested_at": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query waitlist")?;
+
+    let mut entries = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let entry = result.context("Failed to read waitlist entry")?;
+        let ahead = collection
+            .count_documents(doc! {
+                "course_code": &entry.course_code,
+                "semester": &entry.semester,
+                "campus_id": &claims.campus_id,
+                "requested_at": { "$lt": entry.requested_at.to_rfc3339() }
+            }, None)
+            .await
+            .context("Failed to compute waitlist position")?;
+        let mut value = serde_json::to_value(&entry).context("Failed to serialize waitlist entry")?;
+        value["position"] = serde_json::json!(ahead + 1);
+        entries.push(value);
+    }
+
+    Ok(HttpResponse::Ok().json(entries))
+}
+
+// ── Sections ──────────────────────────────────────────────────────────────────
+
+/// POST /api/sections
+async fn create_section(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can manage sections".to_string()));
+    }
+
+    let section_data: SectionRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = require_field(&section_data.course_code, "course_code")?;
+    let semester = require_field(&section_data.semester, "semester")?;
+    let section_name = require_field(&section_data.section_name, "section_name")?.trim();
+    let faculty_id = require_field(&section_data.faculty_id, "faculty_id")?;
+    let capacity = require_i32(section_data.capacity, "capacity")?;
+
+    if capacity < 1 {
+        return Err(AppError::BadRequest("capacity must be at least 1".to_string()));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    let existing = offering_sections(&data.db, &claims.campus_id, course_code, semester).await?;
+    if existing.iter().any(|s| s.section_name == section_name) {
+        return Err(AppError::Conflict(format!(
+            "Section '{}' already exists for {} in {}",
+            section_name, course_code, semester
+        )));
+    }
+
+    // Enrollments made before the offering had sections stay unsectioned
+    // until moved with PUT /api/enrollments/{id}/section.
+    let collection: Collection<Section> = data.db.collection("sections");
+    let result = collection
+        .insert_one(Section {
+            id: None,
+            course_code: course_code.to_string(),
+            semester: semester.to_string(),
+            section_name: section_name.to_string(),
+            capacity,
+            enrolled: 0,
+            faculty_id: faculty_id.to_string(),
+            slot_ids: Vec::new(),
+            campus_id: claims.campus_id,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to insert section")?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Section created successfully",
+        "id": result.inserted_id
+    })))
+}
+
+/// GET /api/sections
+async fn get_sections(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<SectionFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<Section> = data.db.collection("sections");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            filter.insert("semester", semester.as_str());
+        }
+    }
+
+    let options = FindOptions::builder().sort(doc! { "course_code": 1, "section_name": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query sections")?;
+
+    let mut sections = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        sections.push(result.context("Failed to read section")?);
+    }
+
+    Ok(HttpResponse::Ok().json(sections))
+}
+
+async fn find_section_by_id(
+    db: &mongodb::Database,
+    campus_id: &str,
+    section_id: &str,
+) -> Result<Section, AppError> {
+    let section_id = ObjectId::parse_str(section_id)
+        .map_err(|_| AppError::BadRequest("Invalid section ID format".to_string()))?;
+    let collection: Collection<Section> = db.collection("sections");
+    let section = collection
+        .find_one(doc! { "_id": section_id, "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch section")?
+        .ok_or_else(|| AppError::NotFound("Section not found".to_string()))?;
+    Ok(section)
+}
+
+/// GET /api/sections/{id}
+/// The section with its weekly schedule.
+async fn get_section(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let section = find_section_by_id(&data.db, &claims.campus_id, &path.into_inner()).await?;
+
+    let timetable: Collection<TimetableSlot> = data.db.collection("timetable");
+    let slots = collect_slots(&timetable, doc! { "_id": { "$in": &section.slot_ids } }).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "section": section,
+        "schedule": weekly_view(slots)
+    })))
+}
+
+/// PUT /api/sections/{id}
+async fn update_section(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can manage sections".to_string()));
+    }
+
+    let section_data: SectionRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let section = find_section_by_id(&data.db, &claims.campus_id, &path.into_inner()).await?;
+
+    let mut update = doc! {};
+    if let Some(capacity) = section_data.capacity {
+        if capacity < section.enrolled.max(1) {
+            return Err(AppError::BadRequest(format!(
+                "capacity cannot be below the {} students already enrolled",
+                section.enrolled
+            )));
+        }
+        update.insert("capacity", capacity);
+    }
+    if section_data.faculty_id.is_some() {
+        update.insert("faculty_id", require_field(&section_data.faculty_id, "faculty_id")?);
+    }
+    if update.is_empty() {
+        return Err(AppError::BadRequest("Only capacity and faculty_id can be updated".to_string()));
+    }
+
+    data.db
+        .collection::<Section>("sections")
+        .update_one(doc! { "_id": section.id }, doc! { "$set": update }, None)
+        .await
+        .context("Failed to update section")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Section updated successfully" })))
+}
+
+/// GET /api/sections/{id}/students
+async fn get_section_students(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role == "student" {
+        return Err(AppError::Forbidden("Access denied".to_string()));
+    }
+
+    let section = find_section_by_id(&data.db, &claims.campus_id, &path.into_inner()).await?;
+    let students = enrolled_student_ids(
+        &data.db,
+        &claims.campus_id,
+        &section.course_code,
+        &section.semester,
+        Some(&section.section_name),
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "section_name": section.section_name,
+        "course_code": section.course_code,
+        "semester": section.semester,
+        "students": students
+    })))
+}
+
+/// PUT /api/enrollments/{id}/section
+/// Places an enrollment in a section, or moves it to another one.
+async fn set_enrollment_section(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can change sections".to_string()));
+    }
+
+    let request: EnrollmentSectionRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let requested = require_field(&request.section, "section")?;
+
+    let enrollment_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid enrollment ID format".to_string()))?;
+    let collection: Collection<Enrollment> = data.db.collection("enrollments");
+    let enrollment = collection
+        .find_one(doc! { "_id": enrollment_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch enrollment")?
+        .ok_or_else(|| AppError::NotFound("Enrollment not found".to_string()))?;
+
+    let section = resolve_section(&data.db, &claims.campus_id, &enrollment.course_code, &enrollment.semester, Some(requested))
+        .await?
+        .ok_or_else(|| AppError::BadRequest("'section' is required".to_string()))?;
+
+    if enrollment.section.as_deref() == Some(section.section_name.as_str()) {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Enrollment is already in this section" })));
+    }
+
+    if !take_section_seat(&data.db, section.id).await? {
+        return Err(AppError::Conflict(format!("Section '{}' is full", section.section_name)));
+    }
+    if let Some(previous) = &enrollment.section {
+        release_section_seat(&data.db, &claims.campus_id, &enrollment.course_code, &enrollment.semester, previous).await?;
+    }
+
+    collection
+        .update_one(
+            doc! { "_id": enrollment_id },
+            doc! { "$set": { "section": &section.section_name } },
+            None,
+        )
+        .await
+        .context("Failed to update enrollment section")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Enrollment moved to section '{}'", section.section_name)
+    })))
+}
+
+// ── Attendance Management ─────────────────────────────────────────────────────
+
+async fn mark_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let attendance_data: AttendanceRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let student_id = require_field(&attendance_data.student_id, "student_id")?;
+    let course_code = require_field(&attendance_data.course_code, "course_code")?;
+    let date = require_field(&attendance_data.date, "date")?;
+    let status = require_field(&attendance_data.status, "status")?;
+
+    validate_attendance_status(status)?;
+
+    let section = attendance_data.section.as_deref().map(str::trim).filter(|s| !s.is_empty());
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+
+    let new_attendance = Attendance {
+        id: None,
+        student_id: student_id.to_string(),
+        course_code: course_code.to_string(),
+        section: section.map(str::to_string),
+        date: date.to_string(),
+        status: status.to_string(),
+        extra_class_id: None,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    collection
+        .insert_one(new_attendance, None)
+        .await
+        .context("Failed to insert attendance record")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Attendance marked successfully" })))
+}
+
+/// Paginated attendance records for a campus; `student_id` overrides the
+/// filter's student.
+async fn list_attendance(
+    db: &mongodb::Database,
+    campus_id: &str,
+    query: &AttendanceFilter,
+    student_id: Option<&str>,
+) -> Result<serde_json::Value, AppError> {
+    let collection: Collection<Attendance> = db.collection("attendance");
+
+    // Build filter — always scope to campus_id, optionally filter by status/course/student
+    let mut filter = doc! { "campus_id": campus_id };
+    if let Some(status) = &query.status {
+        if !status.trim().is_empty() {
+            validate_attendance_status(status)?;
+            filter.insert("status", status.as_str());
+        }
+    }
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+    if let Some(section) = &query.section {
+        if !section.trim().is_empty() {
+            filter.insert("section", section.as_str());
+        }
+    }
+    if let Some(student_id) = student_id.or(query.student_id.as_deref()) {
+        if !student_id.trim().is_empty() {
+            filter.insert("student_id", student_id);
+        }
+    }
+
+    let pagination = PaginationParams { page: query.page, limit: query.limit };
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .context("Failed to count attendance records")?;
+
+    let options = FindOptions::builder()
+        .skip(pagination.skip())
+        .limit(pagination.limit() as i64)
+        .sort(doc! { "created_at": -1 })
+        .build();
+
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query attendance")?;
+
+    let mut records = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let record = result.context("Failed to read attendance record from cursor")?;
+        records.push(record);
+    }
+
+    Ok(serde_json::json!({
+        "data": records,
+        "pagination": {
+            "page": pagination.page(),
+            "limit": pagination.limit(),
+            "total": total,
+            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
+        }
+    }))
+}
+
+/// GET /api/attendance
+/// Campus-wide listing for teachers and admins; students use /api/my/attendance.
+async fn get_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AttendanceFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden(
+            "Only teachers can list all attendance; use /api/my/attendance".to_string(),
+        ));
+    }
+
+    Ok(HttpResponse::Ok().json(list_attendance(&data.db, &claims.campus_id, &query, None).await?))
+}
+
+/// GET /api/attendance/export?course_code=&from=&to=
+/// Students × dates attendance matrix as CSV or XLSX, for registrar submissions.
+async fn export_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AttendanceExportQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can export attendance".to_string()));
+    }
+
+    let course_code = require_field(&query.course_code, "course_code")?;
+    let format = query.format.as_deref().unwrap_or("csv").trim().to_lowercase();
+    if format != "csv" && format != "xlsx" {
+        return Err(AppError::BadRequest("format must be 'csv' or 'xlsx'".to_string()));
+    }
+
+    let mut filter = doc! { "course_code": course_code, "campus_id": &claims.campus_id };
+    let mut date_range = Document::new();
+    if let Some(from) = query.from.as_deref().filter(|s| !s.trim().is_empty()) {
+        date_range.insert("$gte", normalize_date(from, "from")?);
+    }
+    if let Some(to) = query.to.as_deref().filter(|s| !s.trim().is_empty()) {
+        date_range.insert("$lte", normalize_date(to, "to")?);
+    }
+    if !date_range.is_empty() {
+        filter.insert("date", date_range);
+    }
+    if let Some(section) = query.section.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("section", section);
+    }
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "date": 1 }).build())
+        .await
+        .context("Failed to query attendance")?;
+
+    let mut records = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        records.push(result.context("Failed to read attendance record from cursor")?);
+    }
+
+    let (dates, rows) = attendance_matrix(&records);
+    let range = match (&query.from, &query.to) {
+        (Some(from), Some(to)) => format!("-{}-to-{}", from.trim(), to.trim()),
+        (Some(from), None) => format!("-from-{}", from.trim()),
+        (None, Some(to)) => format!("-to-{}", to.trim()),
+        (None, None) => String::new(),
+    };
+    let filename = format!("attendance-{}{}.{}", course_code, range, format).replace('"', "");
+
+    let (content_type, body) = if format == "xlsx" {
+        (
+            "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+            attendance_matrix_xlsx(course_code, &dates, &rows)?,
+        )
+    } else {
+        ("text/csv; charset=utf-8", attendance_matrix_csv(&dates, &rows)?)
+    };
+
+    Ok(HttpResponse::Ok()
+        .content_type(content_type)
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(body))
+}
+
+// ── QR Attendance Sessions ────────────────────────────────────────────────────
+
+async fn find_open_session(
+    db: &mongodb::Database,
+    campus_id: &str,
+    session_id: &str,
+) -> Result<AttendanceSession, AppError> {
+    let session_id = ObjectId::parse_str(session_id)
+        .map_err(|_| AppError::BadRequest("Invalid session ID format".to_string()))?;
+    let collection: Collection<AttendanceSession> = db.collection("attendance_sessions");
+    let session = collection
+        .find_one(doc! { "_id": session_id, "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch attendance session")?
+        .ok_or_else(|| AppError::NotFound("Attendance session not found".to_string()))?;
+
+    if session.closed || Utc::now() >= session.expires_at {
+        return Err(AppError::BadRequest("Attendance session has ended".to_string()));
+    }
+    Ok(session)
+}
+
+/// POST /api/attendance/sessions
+/// Opens a session for a timetable slot and returns the first QR token.
+async fn open_attendance_session(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can open attendance sessions".to_string()));
+    }
+
+    let session_data: AttendanceSessionRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let slot_id = ObjectId::parse_str(require_field(&session_data.slot_id, "slot_id")?)
+        .map_err(|_| AppError::BadRequest("Invalid slot ID format".to_string()))?;
+
+    let duration = session_data.duration_mins.unwrap_or(ATTENDANCE_SESSION_DEFAULT_MINS);
+    if !(1..=ATTENDANCE_SESSION_MAX_MINS).contains(&duration) {
+        return Err(AppError::BadRequest(format!(
+            "duration_mins must be between 1 and {}",
+            ATTENDANCE_SESSION_MAX_MINS
+        )));
+    }
+
+    let slots: Collection<TimetableSlot> = data.db.collection("timetable");
+    let slot = slots
+        .find_one(doc! { "_id": slot_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch timetable slot")?
+        .ok_or_else(|| AppError::NotFound("Timetable slot not found".to_string()))?;
+
+    let now = Utc::now();
+    let mut session = AttendanceSession {
+        id: None,
+        slot_id,
+        course_code: slot.course_code,
+        section: slot.section,
+        semester: slot.semester,
+        date: now.format("%Y-%m-%d").to_string(),
+        opened_by: claims.sub,
+        campus_id: claims.campus_id,
+        opened_at: now,
+        expires_at: now + chrono::Duration::minutes(duration),
+        closed: false,
+    };
+
+    let collection: Collection<AttendanceSession> = data.db.collection("attendance_sessions");
+    let result = collection
+        .insert_one(&session, None)
+        .await
+        .context("Failed to open attendance session")?;
+    session.id = result.inserted_id.as_object_id();
+
+    Ok(HttpResponse::Created().json(issue_attendance_qr(&session, &data.qr_key)?))
+}
+
+/// GET /api/attendance/sessions/{id}/qr
+/// A fresh QR token; the faculty screen polls this to rotate the code.
+async fn get_attendance_qr(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can display attendance codes".to_string()));
+    }
+
+    let session = find_open_session(&data.db, &claims.campus_id, &path.into_inner()).await?;
+    if claims.role != "admin" && session.opened_by != claims.sub {
+        return Err(AppError::Forbidden("Only the faculty who opened the session can display its code".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(issue_attendance_qr(&session, &data.qr_key)?))
+}
+
+/// POST /api/attendance/checkin
+/// Students submit the scanned token. Each student is recorded once per session.
+async fn attendance_checkin(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "student" {
+        return Err(AppError::Forbidden("Only students can check in".to_string()));
+    }
+
+    let checkin: AttendanceCheckinRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let token = require_field(&checkin.token, "token")?;
+
+    let qr = decode_attendance_qr(token, &data.qr_key)
+        .ok_or_else(|| AppError::BadRequest("Invalid or expired attendance code".to_string()))?;
+    if qr.campus_id != claims.campus_id {
+        return Err(AppError::BadRequest("Invalid or expired attendance code".to_string()));
+    }
+
+    let session = find_open_session(&data.db, &claims.campus_id, &qr.sid).await?;
+    let session_id = session.id.context("Attendance session has no id")?;
+
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    let enrollment = enrollments
+        .find_one(doc! {
+            "student_id": &claims.sub,
+            "course_code": &session.course_code,
+            "semester": &session.semester,
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .context("Failed to check enrollment")?
+        .ok_or_else(|| AppError::Forbidden("You are not enrolled in this course".to_string()))?;
+
+    if enrollment.section.as_ref().is_some_and(|section| *section != session.section) {
+        return Err(AppError::Forbidden(format!(
+            "This session is for section '{}'",
+            session.section
+        )));
+    }
+
+    // The unique (session_id, student_id) index turns a replayed check-in into a duplicate key error
+    let checkins: Collection<Document> = data.db.collection("attendance_checkins");
+    let inserted = checkins
+        .insert_one(doc! {
+            "session_id": session_id,
+            "student_id": &claims.sub,
+            "checked_in_at": Utc::now().to_rfc3339()
+        }, None)
+        .await;
+    if let Err(e) = inserted {
+        if is_duplicate_key(&e) {
+            return Err(AppError::Conflict("Attendance already recorded for this session".to_string()));
+        }
+        return Err(anyhow::Error::new(e).context("Failed to record check-in").into());
+    }
+
+    let attendance: Collection<Attendance> = data.db.collection("attendance");
+    attendance
+        .insert_one(Attendance {
+            id: None,
+            student_id: claims.sub,
+            course_code: session.course_code.clone(),
+            section: Some(session.section.clone()),
+            date: session.date.clone(),
+            status: "present".to_string(),
+            extra_class_id: None,
+            campus_id: claims.campus_id,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to insert attendance record")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Attendance marked successfully",
+        "course_code": session.course_code,
+        "date": session.date
+    })))
+}
+
+/// POST /api/attendance/sessions/{id}/close
+/// Ends the session and marks enrolled students who did not check in as absent.
+async fn close_attendance_session(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can close attendance sessions".to_string()));
+    }
+
+    let session_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid session ID format".to_string()))?;
+
+    let collection: Collection<AttendanceSession> = data.db.collection("attendance_sessions");
+    let session = collection
+        .find_one(doc! { "_id": session_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch attendance session")?
+        .ok_or_else(|| AppError::NotFound("Attendance session not found".to_string()))?;
+
+    if claims.role != "admin" && session.opened_by != claims.sub {
+        return Err(AppError::Forbidden("Only the faculty who opened the session can close it".to_string()));
+    }
+
+    // Only the first close records absences
+    let result = collection
+        .update_one(doc! { "_id": session_id, "closed": false }, doc! { "$set": { "closed": true } }, None)
+        .await
+        .context("Failed to close attendance session")?;
+    if result.modified_count == 0 {
+        return Err(AppError::BadRequest("Attendance session is already closed".to_string()));
+    }
+
+    let checkins: Collection<Document> = data.db.collection("attendance_checkins");
+    let mut cursor = checkins
+        .find(doc! { "session_id": session_id }, None)
+        .await
+        .context("Failed to query check-ins")?;
+
+    let mut present = std::collections::HashSet::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let checkin = result.context("Failed to read check-in")?;
+        if let Ok(student_id) = checkin.get_str("student_id") {
+            present.insert(student_id.to_string());
+        }
+    }
+
+    // Offerings with sections only expect the session's section to attend
+    let sectioned = !offering_sections(&data.db, &claims.campus_id, &session.course_code, &session.semester)
+        .await?
+        .is_empty();
+    let expected_section = sectioned.then_some(session.section.as_str());
+
+    let absent: Vec<Attendance> = enrolled_student_ids(&data.db, &claims.campus_id, &session.course_code, &session.semester, expected_section)
+        .await?
+        .into_iter()
+        .filter(|student_id| !present.contains(student_id))
+        .map(|student_id| Attendance {
+            id: None,
+            student_id,
+            course_code: session.course_code.clone(),
+            section: Some(session.section.clone()),
+            date: session.date.clone(),
+            status: "absent".to_string(),
+            extra_class_id: None,
+            campus_id: claims.campus_id.clone(),
+            created_at: Utc::now(),
+        })
+        .collect();
+
+    if !absent.is_empty() {
+        data.db
+            .collection::<Attendance>("attendance")
+            .insert_many(&absent, None)
+            .await
+            .context("Failed to record absences")?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Attendance session closed",
+        "present": present.len(),
+        "absent": absent.len()
+    })))
+}
+
+// ── Attendance Alerts ─────────────────────────────────────────────────────────
+
+/// POST /api/attendance/shortages/scan
+/// Runs the shortage scan for the caller's campus on demand.
+async fn scan_attendance_shortage_alerts(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: Option<web::Json<serde_json::Value>>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can run attendance scans".to_string()));
+    }
+
+    let scan: ShortageScanRequest = match body {
+        Some(body) => serde_json::from_value(body.into_inner())
+            .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?,
+        None => ShortageScanRequest { threshold: None, course_code: None },
+    };
+
+    let threshold = scan.threshold.unwrap_or(data.attendance_threshold);
+    if !(0.0..=100.0).contains(&threshold) {
+        return Err(AppError::BadRequest("threshold must be between 0 and 100".to_string()));
+    }
+
+    let (flagged, resolved) = scan_attendance_shortages(
+        &data.db,
+        Some(&claims.campus_id),
+        scan.course_code.as_deref().filter(|c| !c.trim().is_empty()),
+        threshold,
+    )
+    .await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "threshold": threshold,
+        "flagged": flagged,
+        "resolved": resolved
+    })))
+}
+
+/// GET /api/alerts
+/// Students see their own alerts; teachers and admins see the campus.
+async fn get_alerts(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AlertFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<Alert> = data.db.collection("alerts");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = &query.student_id {
+        if !student_id.trim().is_empty() {
+            filter.insert("student_id", student_id.as_str());
+        }
+    }
+    if let Some(kind) = &query.kind {
+        if !kind.trim().is_empty() {
+            filter.insert("kind", kind.as_str());
+        }
+    }
+    if !query.include_resolved.unwrap_or(false) {
+        filter.insert("resolved", false);
+    }
+
+    let options = FindOptions::builder().sort(doc! { "updated_at": -1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query alerts")?;
+
+    let mut alerts = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        alerts.push(result.context("Failed to read alert")?);
+    }
+
+    Ok(HttpResponse::Ok().json(alerts))
+}
+
+/// PUT /api/alerts/{id}/acknowledge
+async fn acknowledge_alert(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let alert_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid alert ID format".to_string()))?;
+
+    let mut filter = doc! { "_id": alert_id, "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    }
+
+    let collection: Collection<Alert> = data.db.collection("alerts");
+    let result = collection
+        .update_one(filter, doc! { "$addToSet": { "acknowledged_by": &claims.sub } }, None)
+        .await
+        .context("Failed to acknowledge alert")?;
+
+    if result.matched_count == 0 {
+        return Err(AppError::NotFound("Alert not found".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Alert acknowledged" })))
+}
+
+// ── My Academics ──────────────────────────────────────────────────────────────
+
+fn require_student(claims: &Claims) -> Result<(), AppError> {
+    if claims.role != "student" {
+        return Err(AppError::Forbidden("Only students have personal academic records".to_string()));
+    }
+    Ok(())
+}
+
+/// GET /api/my/enrollments
+async fn get_my_enrollments(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<EnrollmentFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    require_student(&claims)?;
+
+    Ok(HttpResponse::Ok().json(list_enrollments(&data.db, &claims.campus_id, &query, Some(&claims.sub)).await?))
+}
+
+/// GET /api/my/attendance
+async fn get_my_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AttendanceFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    require_student(&claims)?;
+
+    Ok(HttpResponse::Ok().json(list_attendance(&data.db, &claims.campus_id, &query, Some(&claims.sub)).await?))
+}
+
+/// GET /api/my/grades
+async fn get_my_grades(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<GradeFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    require_student(&claims)?;
+
+    Ok(HttpResponse::Ok().json(list_grades(&data.db, &claims.campus_id, &query, Some(&claims.sub)).await?))
+}
+
+// ── Student Dashboard Endpoints ───────────────────────────────────────────────
+
+async fn get_student_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own attendance".to_string(),
+        ));
+    }
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+
+    let mut cursor = collection
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to query student attendance")?;
+
+    let mut records = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let record = result.context("Failed to read attendance record")?;
+        records.push(record);
+    }
+
+    let mut subject_stats: std::collections::HashMap<String, (i32, i32)> = std::collections::HashMap::new();
+    for record in &records {
+        let entry = subject_stats.entry(record.course_code.clone()).or_insert((0, 0));
+        entry.1 += 1;
+        if record.status == "present" || record.status == "late" {
+            entry.0 += 1;
+        }
+    }
+
+    let subject_attendance: Vec<serde_json::Value> = subject_stats.iter().map(|(subject, (attended, total))| {
+        let percentage = if *total > 0 { (*attended as f64 / *total as f64) * 100.0 } else { 0.0 };
+        serde_json::json!({
+            "course_code": subject,
+            "classes_attended": attended,
+            "total_classes": total,
+            "attendance_percentage": format!("{:.1}", percentage)
+        })
+    }).collect();
+
+    let total_attended: i32 = subject_stats.values().map(|(a, _)| a).sum();
+    let total_classes: i32 = subject_stats.values().map(|(_, t)| t).sum();
+    let overall_percentage = if total_classes > 0 {
+        (total_attended as f64 / total_classes as f64) * 100.0
+    } else {
+        0.0
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "overall_attendance_percentage": format!("{:.1}", overall_percentage),
+        "total_classes_attended": total_attended,
+        "total_classes": total_classes,
+        "subject_wise_attendance": subject_attendance,
+        "records": records
+    })))
+}
+
+async fn get_student_enrollments(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own enrollments".to_string(),
+        ));
+    }
+
+    let enrollment_collection: Collection<Enrollment> = data.db.collection("enrollments");
+    let course_collection: Collection<Course> = data.db.collection("courses");
+    let attendance_collection: Collection<Attendance> = data.db.collection("attendance");
+
+    let mut cursor = enrollment_collection
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to query enrollments")?;
+
+    let mut enrollments = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let enrollment = result.context("Failed to read enrollment")?;
+        enrollments.push(enrollment);
+    }
+
+    let mut classes_info = Vec::new();
+    for enrollment in &enrollments {
+        // Returns Option<Course> — None is handled gracefully with unwrap_or_default
+        let course = course_collection
+            .find_one(doc! { "course_code": &enrollment.course_code, "campus_id": &claims.campus_id }, None)
+            .await
+            .context("Failed to fetch course details")?;
+
+        let attended = attendance_collection
+            .count_documents(doc! {
+                "student_id": &student_id,
+                "course_code": &enrollment.course_code,
+                "status": { "$in": ["present", "late"] },
+                "campus_id": &claims.campus_id
+            }, None)
+            .await
+            .context("Failed to count attended classes")?;
+
+        let total = attendance_collection
+            .count_documents(doc! {
+                "student_id": &student_id,
+                "course_code": &enrollment.course_code,
+                "campus_id": &claims.campus_id
+            }, None)
+            .await
+            .context("Failed to count total classes")?;
+
+        classes_info.push(serde_json::json!({
+            "course_code": enrollment.course_code,
+            // Option<Course> — use map to extract name, fall back to empty string
+            "course_name": course.map(|c| c.course_name).unwrap_or_default(),
+            "semester": enrollment.semester,
+            "enrolled_at": enrollment.enrolled_at,
+            "classes_attended": attended,
+            "classes_missed": total - attended,
+            "total_classes": total
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "enrollments": classes_info
+    })))
+}
+
+// ── Exam Results ──────────────────────────────────────────────────────────────
+
+async fn create_result(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can add exam results".to_string()));
+    }
+
+    let result_data: ExamResultRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let student_id = require_field(&result_data.student_id, "student_id")?;
+    let course_code = require_field(&result_data.course_code, "course_code")?;
+    let exam_type = require_field(&result_data.exam_type, "exam_type")?;
+    let semester = require_field(&result_data.semester, "semester")?;
+    let marks_obtained = require_f64(result_data.marks_obtained, "marks_obtained")?;
+    let total_marks = require_f64(result_data.total_marks, "total_marks")?;
+
+    validate_exam_type(exam_type)?;
+
+    if total_marks <= 0.0 {
+        return Err(AppError::BadRequest("total_marks must be greater than 0".to_string()));
+    }
+    if marks_obtained < 0.0 || marks_obtained > total_marks {
+        return Err(AppError::BadRequest(
+            "marks_obtained must be between 0 and total_marks".to_string(),
+        ));
+    }
+
+    let collection: Collection<ExamResult> = data.db.collection("exam_results");
+
+    let new_result = ExamResult {
+        id: None,
+        student_id: student_id.to_string(),
+        course_code: course_code.to_string(),
+        exam_type: exam_type.to_string(),
+        marks_obtained,
+        total_marks,
+        grade: calculate_grade(marks_obtained, total_marks),
+        semester: semester.to_string(),
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    collection
+        .insert_one(new_result, None)
+        .await
+        .context("Failed to insert exam result")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Exam result added successfully" })))
+}
+
+async fn get_student_results(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own results".to_string(),
+        ));
+    }
+
+    let collection: Collection<ExamResult> = data.db.collection("exam_results");
+
+    let mut cursor = collection
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to query exam results")?;
+
+    let mut results = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let r = result.context("Failed to read exam result")?;
+        results.push(r);
+    }
+
+    let total_marks: f64 = results.iter().map(|r| r.marks_obtained).sum();
+    let total_possible: f64 = results.iter().map(|r| r.total_marks).sum();
+    let overall_percentage = if total_possible > 0.0 {
+        (total_marks / total_possible) * 100.0
+    } else {
+        0.0
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "results": results,
+        "overall_marks_obtained": total_marks,
+        "overall_total_marks": total_possible,
+        "overall_percentage": format!("{:.1}", overall_percentage),
+        "overall_grade": calculate_grade(total_marks, total_possible)
+    })))
+}
+
+// ── Grades & GPA ──────────────────────────────────────────────────────────────
+
+/// POST /api/grades
+/// Records (or corrects) one assessment score. Re-posting the same
+/// student/course/semester/assessment replaces the previous marks.
+async fn post_grade(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can post grades".to_string()));
+    }
+
+    let grade_data: GradeRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let student_id = require_field(&grade_data.student_id, "student_id")?;
+    let course_code = require_field(&grade_data.course_code, "course_code")?;
+    let semester = require_field(&grade_data.semester, "semester")?;
+    let assessment = require_field(&grade_data.assessment, "assessment")?.trim();
+    let marks_obtained = require_f64(grade_data.marks_obtained, "marks_obtained")?;
+    let max_marks = require_f64(grade_data.max_marks, "max_marks")?;
+
+    if max_marks <= 0.0 {
+        return Err(AppError::BadRequest("max_marks must be greater than 0".to_string()));
+    }
+    if !(0.0..=max_marks).contains(&marks_obtained) {
+        return Err(AppError::BadRequest(
+            "marks_obtained must be between 0 and max_marks".to_string(),
+        ));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    enrollments
+        .find_one(doc! {
+            "student_id": student_id,
+            "course_code": course_code,
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .context("Failed to check enrollment")?
+        .ok_or_else(|| AppError::BadRequest("Student is not enrolled in this course".to_string()))?;
+
+    ensure_results_editable(&data.db, &claims.campus_id, course_code, semester).await?;
+    let scheme = find_assessment_scheme(&data.db, &claims.campus_id, course_code, semester).await?;
+    check_scheme_component(scheme.as_ref(), assessment)?;
+
+    let existed = save_grade(&data.db, &claims, student_id, course_code, semester, assessment, marks_obtained, max_marks).await?;
+
+    let message = if existed { "Grade updated successfully" } else { "Grade recorded successfully" };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": message,
+        "grade": calculate_grade(marks_obtained, max_marks)
+    })))
+}
+
+/// GET /api/grades
+/// Paginated assessment scores for teachers and admins.
+/// Paginated grades for a campus; `student_id` overrides the filter's student
+/// and limits the listing to published offerings, as students see it.
+async fn list_grades(
+    db: &mongodb::Database,
+    campus_id: &str,
+    query: &GradeFilter,
+    student_id: Option<&str>,
+) -> Result<serde_json::Value, AppError> {
+    let collection: Collection<Grade> = db.collection("grades");
+
+    let mut filter = doc! { "campus_id": campus_id };
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            filter.insert("semester", semester.as_str());
+        }
+    }
+    if let Some(student_id) = student_id.or(query.student_id.as_deref()) {
+        if !student_id.trim().is_empty() {
+            filter.insert("student_id", student_id);
+        }
+    }
+    if student_id.is_some() {
+        let publications: Collection<ResultPublication> = db.collection("result_publications");
+        let mut cursor = publications
+            .find(doc! { "campus_id": campus_id, "status": "published" }, None)
+            .await
+            .context("Failed to query result publications")?;
+        let mut published = Vec::new();
+        use futures::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            let publication = result.context("Failed to read result publication")?;
+            published.push(doc! { "course_code": publication.course_code, "semester": publication.semester });
+        }
+        if published.is_empty() {
+            // Nothing is published yet, so match nothing
+            filter.insert("_id", doc! { "$in": [] });
+        } else {
+            filter.insert("$or", published);
+        }
+    }
+
+    let pagination = PaginationParams { page: query.page, limit: query.limit };
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .context("Failed to count grades")?;
+
+    let options = FindOptions::builder()
+        .skip(pagination.skip())
+        .limit(pagination.limit() as i64)
+        .sort(doc! { "updated_at": -1 })
+        .build();
+
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query grades")?;
+
+    let mut grades = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let grade = result.context("Failed to read grade from cursor")?;
+        grades.push(grade);
+    }
+
+    Ok(serde_json::json!({
+        "data": grades,
+        "pagination": {
+            "page": pagination.page(),
+            "limit": pagination.limit(),
+            "total": total,
+            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
+        }
+    }))
+}
+
+async fn get_grades(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<GradeFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can list grades; use /api/my/grades".to_string()));
+    }
+
+    Ok(HttpResponse::Ok().json(list_grades(&data.db, &claims.campus_id, &query, None).await?))
+}
+
+/// GET /api/grades/card/{student_id}
+/// Letter grades per course, SGPA per semester and overall CGPA.
+async fn get_grade_card(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<GradeCardQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own grade card".to_string(),
+        ));
+    }
+
+    let mut card = compute_grade_card(&data.db, &claims.campus_id, &student_id, claims.role == "student").await?;
+
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            card.semesters.retain(|s| &s.semester == semester);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(card))
+}
+
+/// GET /api/courses/{code}/grade-stats?semester=
+/// Grade histogram, mean/median percentage and pass rate per offering.
+async fn get_grade_stats(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<SemesterQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can view grade statistics".to_string()));
+    }
+
+    let course_code = path.into_inner();
+    let mut filter = doc! { "course_code": &course_code, "campus_id": &claims.campus_id };
+    if let Some(semester) = query.semester.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("semester", semester);
+    }
+
+    // One row per (semester, student) holding all of that student's assessments
+    let pipeline = vec![
+        doc! { "$match": filter },
+        doc! { "$group": {
+            "_id": { "semester": "$semester", "student_id": "$student_id" },
+            "grades": { "$push": "$$ROOT" }
+        } },
+        doc! { "$sort": { "_id.semester": 1, "_id.student_id": 1 } },
+    ];
+    let grades: Collection<Grade> = data.db.collection("grades");
+    let mut cursor = grades
+        .aggregate(pipeline, None)
+        .await
+        .context("Failed to aggregate grades")?;
+
+    // semester -> student percentages, in first-seen order
+    let mut offerings: Vec<(String, Vec<f64>)> = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let row = result.context("Failed to read grade group")?;
+        let semester = row
+            .get_document("_id")
+            .and_then(|key| key.get_str("semester"))
+            .context("Grade group has no semester")?
+            .to_string();
+        let student_grades: Vec<Grade> = row
+            .get_array("grades")
+            .context("Grade group has no grades")?
+            .iter()
+            .filter_map(|g| g.as_document())
+            .map(|g| mongodb::bson::from_document(g.clone()))
+            .collect::<Result<_, _>>()
+            .context("Failed to decode grade")?;
+
+        if !offerings.iter().any(|(s, _)| *s == semester) {
+            offerings.push((semester.clone(), Vec::new()));
+        }
+        let scheme = find_assessment_scheme(&data.db, &claims.campus_id, &course_code, &semester).await?;
+        let (obtained, max) = course_score(&student_grades, scheme.as_ref());
+        if max > 0.0 {
+            if let Some((_, percentages)) = offerings.iter_mut().find(|(s, _)| *s == semester) {
+                percentages.push(obtained / max * 100.0);
+            }
+        }
+    }
+
+    let stats: Vec<serde_json::Value> = offerings
+        .into_iter()
+        .map(|(semester, mut percentages)| {
+            percentages.sort_by(f64::total_cmp);
+            let students = percentages.len();
+            let letters: Vec<String> = percentages.iter().map(|p| calculate_grade(*p, 100.0)).collect();
+            let histogram: Vec<serde_json::Value> = LETTER_GRADES
+                .iter()
+                .map(|grade| serde_json::json!({
+                    "grade": grade,
+                    "count": letters.iter().filter(|l| l == grade).count()
+                }))
+                .collect();
+            let mean = if students == 0 { 0.0 } else { percentages.iter().sum::<f64>() / students as f64 };
+            let median = match students {
+                0 => 0.0,
+                n if n % 2 == 1 => percentages[n / 2],
+                n => (percentages[n / 2 - 1] + percentages[n / 2]) / 2.0,
+            };
+            let passed = letters.iter().filter(|l| grade_points(l) > 0.0).count();
+            let pass_rate = if students == 0 { 0.0 } else { passed as f64 * 100.0 / students as f64 };
+
+            serde_json::json!({
+                "semester": semester,
+                "students": students,
+                "histogram": histogram,
+                "mean_percentage": round2(mean),
+                "median_percentage": round2(median),
+                "pass_rate": round2(pass_rate)
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "course_code": course_code,
+        "offerings": stats
+    })))
+}
+
+// ── Assessment Schemes ────────────────────────────────────────────────────────
+
+/// PUT /api/courses/{code}/assessment-scheme
+/// Sets the weighted components for a course offering, replacing any earlier scheme.
+async fn put_assessment_scheme(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can configure assessments".to_string()));
+    }
+
+    let scheme_data: AssessmentSchemeRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = path.into_inner();
+    let semester = require_field(&scheme_data.semester, "semester")?;
+    let components = scheme_data
+        .components
+        .ok_or_else(|| AppError::BadRequest("'components' is required".to_string()))?;
+
+    if components.is_empty() {
+        return Err(AppError::BadRequest("'components' must not be empty".to_string()));
+    }
+    let mut components: Vec<AssessmentComponent> = components
+        .into_iter()
+        .map(|c| AssessmentComponent { name: c.name.trim().to_string(), weight: c.weight })
+        .collect();
+    for (i, component) in components.iter().enumerate() {
+        if component.name.is_empty() {
+            return Err(AppError::BadRequest("Component names must not be blank".to_string()));
+        }
+        if component.weight <= 0.0 {
+            return Err(AppError::BadRequest(format!(
+                "Weight of '{}' must be greater than 0",
+                component.name
+            )));
+        }
+        if components[..i].iter().any(|c| c.name == component.name) {
+            return Err(AppError::BadRequest(format!("Duplicate component '{}'", component.name)));
+        }
+    }
+    let total: f64 = components.iter().map(|c| c.weight).sum();
+    if (total - 100.0).abs() > 0.01 {
+        return Err(AppError::BadRequest(format!(
+            "Component weights must add up to 100 (got {})",
+            round2(total)
+        )));
+    }
+    components.iter_mut().for_each(|c| c.weight = round2(c.weight));
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": &course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    ensure_results_editable(&data.db, &claims.campus_id, &course_code, semester).await?;
+
+    let scheme = AssessmentScheme {
+        id: None,
+        course_code: course_code.clone(),
+        semester: semester.to_string(),
+        components,
+        campus_id: claims.campus_id.clone(),
+        updated_by: claims.sub,
+        updated_at: Utc::now(),
+    };
+
+    data.db
+        .collection::<AssessmentScheme>("assessment_schemes")
+        .replace_one(
+            doc! { "course_code": &course_code, "semester": semester, "campus_id": &claims.campus_id },
+            &scheme,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .context("Failed to save assessment scheme")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Assessment scheme saved successfully",
+        "scheme": scheme
+    })))
+}
+
+/// GET /api/courses/{code}/assessment-scheme?semester=
+async fn get_assessment_scheme(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<SemesterQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let course_code = path.into_inner();
+    let semester = require_field(&query.semester, "semester")?;
+
+    let scheme = find_assessment_scheme(&data.db, &claims.campus_id, &course_code, semester)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!(
+            "No assessment scheme for {} in {}",
+            course_code, semester
+        )))?;
+
+    Ok(HttpResponse::Ok().json(scheme))
+}
+
+/// POST /api/courses/{code}/assessments/{component}/marks
+/// Records one component's marks for many students at once.
+async fn post_component_marks(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can post grades".to_string()));
+    }
+
+    let marks_data: ComponentMarksRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let (course_code, component) = path.into_inner();
+    let semester = require_field(&marks_data.semester, "semester")?;
+    let max_marks = require_f64(marks_data.max_marks, "max_marks")?;
+    let marks = marks_data
+        .marks
+        .ok_or_else(|| AppError::BadRequest("'marks' is required".to_string()))?;
+
+    if max_marks <= 0.0 {
+        return Err(AppError::BadRequest("max_marks must be greater than 0".to_string()));
+    }
+    if marks.is_empty() {
+        return Err(AppError::BadRequest("'marks' must not be empty".to_string()));
+    }
+
+    let scheme = find_assessment_scheme(&data.db, &claims.campus_id, &course_code, semester)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!(
+            "No assessment scheme for {} in {}",
+            course_code, semester
+        )))?;
+    check_scheme_component(Some(&scheme), &component)?;
+    ensure_results_editable(&data.db, &claims.campus_id, &course_code, semester).await?;
+
+    // Validate every row before writing any
+    let enrolled = enrolled_student_ids(&data.db, &claims.campus_id, &course_code, semester, None).await?;
+    for entry in &marks {
+        if !enrolled.contains(&entry.student_id) {
+            return Err(AppError::BadRequest(format!(
+                "Student '{}' is not enrolled in {} for {}",
+                entry.student_id, course_code, semester
+            )));
+        }
+        if !(0.0..=max_marks).contains(&entry.marks_obtained) {
+            return Err(AppError::BadRequest(format!(
+                "marks_obtained for '{}' must be between 0 and max_marks",
+                entry.student_id
+            )));
+        }
+    }
+
+    for entry in &marks {
+        save_grade(
+            &data.db,
+            &claims,
+            &entry.student_id,
+            &course_code,
+            semester,
+            &component,
+            entry.marks_obtained,
+            max_marks,
+        )
+        .await?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Recorded {} marks for {}", marks.len(), component)
+    })))
+}
+
+/// GET /api/courses/{code}/internal-scores?semester=
+/// Per-student component marks and the weighted internal score.
+async fn get_internal_scores(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<SemesterQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can view internal scores".to_string()));
+    }
+
+    let course_code = path.into_inner();
+    let semester = require_field(&query.semester, "semester")?;
+    let scheme = find_assessment_scheme(&data.db, &claims.campus_id, &course_code, semester)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!(
+            "No assessment scheme for {} in {}",
+            course_code, semester
+        )))?;
+
+    let grades: Collection<Grade> = data.db.collection("grades");
+    let mut cursor = grades
+        .find(doc! { "course_code": &course_code, "semester": semester, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to query grades")?;
+
+    let mut by_student: std::collections::BTreeMap<String, Vec<Grade>> = std::collections::BTreeMap::new();
+    for student_id in enrolled_student_ids(&data.db, &claims.campus_id, &course_code, semester, None).await? {
+        by_student.entry(student_id).or_default();
+    }
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let grade = result.context("Failed to read grade from cursor")?;
+        by_student.entry(grade.student_id.clone()).or_default().push(grade);
+    }
+
+    let students: Vec<serde_json::Value> = by_student
+        .into_iter()
+        .map(|(student_id, grades)| {
+            let components: Vec<serde_json::Value> = scheme
+                .components
+                .iter()
+                .map(|c| {
+                    let grade = grades.iter().find(|g| g.assessment == c.name);
+                    serde_json::json!({
+                        "name": c.name,
+                        "weight": c.weight,
+                        "marks_obtained": grade.map(|g| g.marks_obtained),
+                        "max_marks": grade.map(|g| g.max_marks)
+                    })
+                })
+                .collect();
+            let (score, max) = course_score(&grades, Some(&scheme));
+            serde_json::json!({
+                "student_id": student_id,
+                "components": components,
+                "internal_score": score,
+                "grade": calculate_grade(score, max)
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "course_code": course_code,
+        "semester": semester,
+        "scheme": scheme.components,
+        "students": students
+    })))
+}
+
+// ── Result Publication ────────────────────────────────────────────────────────
+
+/// Moves an offering's results from `from` to `to`, stamping who did it.
+async fn transition_results(
+    db: &mongodb::Database,
+    claims: &Claims,
+    course_code: &str,
+    semester: &str,
+    from: &str,
+    to: &str,
+) -> Result<ResultPublication, AppError> {
+    let publications: Collection<ResultPublication> = db.collection("result_publications");
+    let key = doc! { "course_code": course_code, "semester": semester, "campus_id": &claims.campus_id };
+    let now = Utc::now().to_rfc3339();
+
+    // Offerings start as drafts without a record
+    publications
+        .update_one(
+            key.clone(),
+            doc! { "$setOnInsert": { "status": "draft", "updated_at": &now } },
+            UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .context("Failed to initialise result publication")?;
+
+    let mut update = doc! { "status": to, "updated_at": &now };
+    match to {
+        "verified" => {
+            update.insert("verified_by", &claims.sub);
+            update.insert("verified_at", &now);
+        }
+        "published" => {
+            update.insert("published_by", &claims.sub);
+            update.insert("published_at", &now);
+        }
+        _ => {}
+    }
+
+    let mut filter = key.clone();
+    filter.insert("status", from);
+    let result = publications
+        .update_one(filter, doc! { "$set": update }, None)
+        .await
+        .context("Failed to update result publication")?;
+
+    let publication = publications
+        .find_one(key, None)
+        .await
+        .context("Failed to fetch result publication")?
+        .context("Result publication disappeared")?;
+    if result.modified_count == 0 {
+        return Err(AppError::Conflict(format!(
+            "Results for {} in {} are {}, not {}",
+            course_code, semester, publication.status, from
+        )));
+    }
+    Ok(publication)
+}
+
+fn publication_semester(body: serde_json::Value) -> Result<String, AppError> {
+    let request: ResultPublicationRequest = serde_json::from_value(body)
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    Ok(require_field(&request.semester, "semester")?.to_string())
+}
+
+/// GET /api/courses/{code}/results?semester=
+async fn get_result_publication(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<SemesterQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role == "student" {
+        return Err(AppError::Forbidden("Access denied".to_string()));
+    }
+
+    let course_code = path.into_inner();
+    let semester = require_field(&query.semester, "semester")?;
+    let publication = data
+        .db
+        .collection::<ResultPublication>("result_publications")
+        .find_one(doc! { "course_code": &course_code, "semester": semester, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch result publication")?;
+
+    Ok(HttpResponse::Ok().json(match publication {
+        Some(publication) => serde_json::json!(publication),
+        None => serde_json::json!({ "course_code": course_code, "semester": semester, "status": "draft" }),
+    }))
+}
+
+/// POST /api/courses/{code}/results/verify
+/// The HOD signs off on draft results, locking them against edits.
+async fn verify_results(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != HOD_ROLE && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only the HOD can verify results".to_string()));
+    }
+
+    let course_code = path.into_inner();
+    let semester = publication_semester(body.into_inner())?;
+
+    let graded = data
+        .db
+        .collection::<Grade>("grades")
+        .count_documents(doc! { "course_code": &course_code, "semester": &semester, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to count grades")?;
+    if graded == 0 {
+        return Err(AppError::BadRequest(format!(
+            "No grades recorded for {} in {}",
+            course_code, semester
+        )));
+    }
+
+    let publication = transition_results(&data.db, &claims, &course_code, &semester, "draft", "verified").await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Results verified successfully",
+        "publication": publication
+    })))
+}
+
+/// POST /api/courses/{code}/results/return
+/// The HOD sends verified results back to faculty for corrections.
+async fn return_results(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != HOD_ROLE && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only the HOD can return results".to_string()));
+    }
+
+    let course_code = path.into_inner();
+    let semester = publication_semester(body.into_inner())?;
+    let publication = transition_results(&data.db, &claims, &course_code, &semester, "verified", "draft").await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Results returned to draft",
+        "publication": publication
+    })))
+}
+
+/// POST /api/courses/{code}/results/publish
+/// The controller of examinations releases verified results to students.
+async fn publish_results(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != CONTROLLER_ROLE && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only the controller of examinations can publish results".to_string()));
+    }
+
+    let course_code = path.into_inner();
+    let semester = publication_semester(body.into_inner())?;
+    let publication = transition_results(&data.db, &claims, &course_code, &semester, "verified", "published").await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Results published successfully",
+        "publication": publication
+    })))
+}
+
+// ── Grade Appeals ─────────────────────────────────────────────────────────────
+
+/// POST /api/grades/{id}/appeals
+/// A student contests one of their published grades.
+async fn create_grade_appeal(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "student" {
+        return Err(AppError::Forbidden("Only students can appeal grades".to_string()));
+    }
+
+    let appeal_data: GradeAppealRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let reason = require_field(&appeal_data.reason, "reason")?;
+
+    let grade_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid grade ID format".to_string()))?;
+    let grade = data
+        .db
+        .collection::<Grade>("grades")
+        .find_one(doc! { "_id": grade_id, "student_id": &claims.sub, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch grade")?
+        .ok_or_else(|| AppError::NotFound("Grade not found".to_string()))?;
+
+    if result_status(&data.db, &claims.campus_id, &grade.course_code, &grade.semester).await? != "published" {
+        return Err(AppError::NotFound("Grade not found".to_string()));
+    }
+
+    let appeals: Collection<GradeAppeal> = data.db.collection("grade_appeals");
+    if appeals
+        .find_one(doc! { "grade_id": grade_id, "status": "pending" }, None)
+        .await
+        .context("Failed to check existing appeals")?
+        .is_some()
+    {
+        return Err(AppError::Conflict("An appeal for this grade is already pending".to_string()));
+    }
+
+    let result = appeals
+        .insert_one(GradeAppeal {
+            id: None,
+            grade_id,
+            student_id: claims.sub,
+            course_code: grade.course_code,
+            semester: grade.semester,
+            assessment: grade.assessment,
+            reason: reason.to_string(),
+            status: "pending".to_string(),
+            original_marks: grade.marks_obtained,
+            revised_marks: None,
+            resolution_note: None,
+            resolved_by: None,
+            campus_id: claims.campus_id,
+            created_at: Utc::now(),
+            resolved_at: None,
+        }, None)
+        .await
+        .context("Failed to insert grade appeal")?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Appeal submitted successfully",
+        "id": result.inserted_id
+    })))
+}
+
+/// GET /api/grade-appeals
+/// Students see their own appeals; staff see every appeal on the campus.
+async fn get_grade_appeals(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<GradeAppealFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    }
+    if let Some(status) = query.status.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("status", status);
+    }
+    if let Some(course_code) = query.course_code.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("course_code", course_code);
+    }
+
+    let collection: Collection<GradeAppeal> = data.db.collection("grade_appeals");
+    let options = FindOptions::builder().sort(doc! { "created_at": -1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query grade appeals")?;
+
+    let mut appeals = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        appeals.push(result.context("Failed to read grade appeal")?);
+    }
+
+    Ok(HttpResponse::Ok().json(appeals))
+}
+
+/// PUT /api/grade-appeals/{id}/resolve
+/// The only way to change a published grade: accepting an appeal with new marks.
+async fn resolve_grade_appeal(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != HOD_ROLE && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only the HOD can resolve grade appeals".to_string()));
+    }
+
+    let resolve_data: ResolveAppealRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let status = require_field(&resolve_data.status, "status")?;
+    if status != "accepted" && status != "rejected" {
+        return Err(AppError::BadRequest("status must be 'accepted' or 'rejected'".to_string()));
+    }
+
+    let appeal_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid appeal ID format".to_string()))?;
+    let appeals: Collection<GradeAppeal> = data.db.collection("grade_appeals");
+    let appeal = appeals
+        .find_one(doc! { "_id": appeal_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch grade appeal")?
+        .ok_or_else(|| AppError::NotFound("Appeal not found".to_string()))?;
+
+    let grades: Collection<Grade> = data.db.collection("grades");
+    let revised_marks = if status == "accepted" {
+        let marks = require_f64(resolve_data.marks_obtained, "marks_obtained")?;
+        let grade = grades
+            .find_one(doc! { "_id": appeal.grade_id }, None)
+            .await
+            .context("Failed to fetch grade")?
+            .ok_or_else(|| AppError::NotFound("The appealed grade no longer exists".to_string()))?;
+        if !(0.0..=grade.max_marks).contains(&marks) {
+            return Err(AppError::BadRequest(
+                "marks_obtained must be between 0 and max_marks".to_string(),
+            ));
+        }
+        Some(marks)
+    } else {
+        None
+    };
+
+    let now = Utc::now().to_rfc3339();
+    let mut update = doc! { "status": status, "resolved_by": &claims.sub, "resolved_at": &now };
+    if let Some(marks) = revised_marks {
+        update.insert("revised_marks", marks);
+    }
+    if let Some(note) = resolve_data.note.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        update.insert("resolution_note", note);
+    }
+    let result = appeals
+        .update_one(doc! { "_id": appeal_id, "status": "pending" }, doc! { "$set": update }, None)
+        .await
+        .context("Failed to resolve grade appeal")?;
+    if result.modified_count == 0 {
+        return Err(AppError::Conflict(format!("Appeal is already {}", appeal.status)));
+    }
+
+    if let Some(marks) = revised_marks {
+        grades
+            .update_one(
+                doc! { "_id": appeal.grade_id },
+                doc! { "$set": { "marks_obtained": marks, "graded_by": &claims.sub, "updated_at": &now } },
+                None,
+            )
+            .await
+            .context("Failed to update appealed grade")?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": format!("Appeal {}", status) })))
+}
+
+// ── Timetable ─────────────────────────────────────────────────────────────────
+
+/// POST /api/timetable
+/// Adds a weekly slot. Rejected with 409 when the faculty member, the room or
+/// the section already has an overlapping slot on that day in the semester.
+async fn create_timetable_slot(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can manage the timetable".to_string()));
+    }
+
+    let slot_data: TimetableSlotRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = require_field(&slot_data.course_code, "course_code")?;
+    let section = require_field(&slot_data.section, "section")?;
+    let faculty_id = require_field(&slot_data.faculty_id, "faculty_id")?;
+    let room = require_field(&slot_data.room, "room")?;
+    let semester = require_field(&slot_data.semester, "semester")?;
+    let day = validate_day(require_field(&slot_data.day, "day")?)?;
+    let start_time = normalize_time(require_field(&slot_data.start_time, "start_time")?, "start_time")?;
+    let end_time = normalize_time(require_field(&slot_data.end_time, "end_time")?, "end_time")?;
+
+    if start_time >= end_time {
+        return Err(AppError::BadRequest("start_time must be before end_time".to_string()));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    // Offerings without sections keep free-form section labels (batch names)
+    let sections = offering_sections(&data.db, &claims.campus_id, course_code, semester).await?;
+    let section_id = if sections.is_empty() {
+        None
+    } else {
+        let found = sections.iter().find(|s| s.section_name == section).ok_or_else(|| {
+            let names: Vec<&str> = sections.iter().map(|s| s.section_name.as_str()).collect();
+            AppError::BadRequest(format!(
+                "Unknown section '{}'; choose one of: {}",
+                section,
+                names.join(", ")
+            ))
+        })?;
+        found.id
+    };
+
+    let collection: Collection<TimetableSlot> = data.db.collection("timetable");
+
+    // Two slots overlap when each starts before the other ends
+    let clashes = collect_slots(&collection, doc! {
+        "campus_id": &claims.campus_id,
+        "semester": semester,
+        "day": &day,
+        "start_time": { "$lt": &end_time },
+        "end_time": { "$gt": &start_time },
+        "$or": [
+            { "faculty_id": faculty_id },
+            { "room": room },
+            { "section": section }
+        ]
+    })
+    .await?;
+
+    if !clashes.is_empty() {
+        let details: Vec<String> = clashes
+            .iter()
+            .map(|c| {
+                let mut reasons = Vec::new();
+                if c.faculty_id == faculty_id {
+                    reasons.push(format!("faculty {}", c.faculty_id));
+                }
+                if c.room == room {
+                    reasons.push(format!("room {}", c.room));
+                }
+                if c.section == section {
+                    reasons.push(format!("section {}", c.section));
+                }
+                format!(
+                    "{} {}-{} {} ({})",
+                    c.day, c.start_time, c.end_time, c.course_code, reasons.join(", ")
+                )
+            })
+            .collect();
+        return Err(AppError::Conflict(format!(
+            "Timetable clash: {}",
+            details.join("; ")
+        )));
+    }
+
+    let slot = TimetableSlot {
+        id: None,
+        course_code: course_code.to_string(),
+        section: section.to_string(),
+        faculty_id: faculty_id.to_string(),
+        room: room.to_string(),
+        day,
+        start_time,
+        end_time,
+        semester: semester.to_string(),
+        campus_id: claims.campus_id,
+        created_by: claims.sub,
+        created_at: Utc::now(),
+    };
+
+    let result = collection
+        .insert_one(&slot, None)
+        .await
+        .context("Failed to insert timetable slot")?;
+
+    if let Some(section_id) = section_id {
+        data.db
+            .collection::<Section>("sections")
+            .update_one(
+                doc! { "_id": section_id },
+                doc! { "$addToSet": { "slot_ids": &result.inserted_id } },
+                None,
+            )
+            .await
+            .context("Failed to link slot to section")?;
+    }
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Timetable slot created successfully",
+        "id": result.inserted_id
+    })))
+}
+
+/// GET /api/timetable
+async fn get_timetable(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<TimetableFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<TimetableSlot> = data.db.collection("timetable");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    for (field, value) in [
+        ("semester", &query.semester),
+        ("course_code", &query.course_code),
+        ("section", &query.section),
+        ("room", &query.room),
+    ] {
+        if let Some(value) = value {
+            if !value.trim().is_empty() {
+                filter.insert(field, value.as_str());
+            }
+        }
+    }
+
+    let mut slots = collect_slots(&collection, filter).await?;
+    slots.sort_by(|a, b| {
+        let day_index = |d: &str| WEEKDAYS.iter().position(|w| *w == d);
+        day_index(&a.day)
+            .cmp(&day_index(&b.day))
+            .then_with(|| a.start_time.cmp(&b.start_time))
+    });
+
+    Ok(HttpResponse::Ok().json(slots))
+}
+
+/// DELETE /api/timetable/{id}
+async fn delete_timetable_slot(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can manage the timetable".to_string()));
+    }
+
+    let oid = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid slot ID format".to_string()))?;
+
+    let collection: Collection<TimetableSlot> = data.db.collection("timetable");
+    let result = collection
+        .delete_one(doc! { "_id": oid, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to delete timetable slot")?;
+
+    if result.deleted_count == 0 {
+        return Err(AppError::NotFound("Timetable slot not found".to_string()));
+    }
+
+    data.db
+        .collection::<Section>("sections")
+        .update_many(
+            doc! { "slot_ids": oid, "campus_id": &claims.campus_id },
+            doc! { "$pull": { "slot_ids": oid } },
+            None,
+        )
+        .await
+        .context("Failed to unlink slot from section")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Timetable slot deleted successfully" })))
+}
+
+/// GET /api/timetable/student/{student_id}
+/// Slots of the student's enrolled courses. Where the student belongs to a
+/// batch for a course, only that batch's section is shown.
+async fn get_student_timetable(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<WeeklyTimetableQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own timetable".to_string(),
+        ));
+    }
+
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    let batches: Collection<Batch> = data.db.collection("batches");
+    let collection: Collection<TimetableSlot> = data.db.collection("timetable");
+
+    let mut enrollment_filter = doc! { "student_id": &student_id, "campus_id": &claims.campus_id };
+    if let Some(semester) = &query.semester {
+        enrollment_filter.insert("semester", semester.as_str());
+    }
+
+    let mut cursor = enrollments
+        .find(enrollment_filter, None)
+        .await
+        .context("Failed to query enrollments")?;
+
+    let mut enrolled = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        enrolled.push(result.context("Failed to read enrollment")?);
+    }
+
+    let mut slots = Vec::new();
+    for enrollment in &enrolled {
+        if let Some(section) = &enrollment.section {
+            slots.extend(collect_slots(&collection, doc! {
+                "course_code": &enrollment.course_code,
+                "semester": &enrollment.semester,
+                "section": section,
+                "campus_id": &claims.campus_id
+            }).await?);
+            continue;
+        }
+
+        let mut batch_cursor = batches
+            .find(doc! {
+                "course_code": &enrollment.course_code,
+                "student_ids": &student_id,
+                "campus_id": &claims.campus_id
+            }, None)
+            .await
+            .context("Failed to query batches")?;
+
+        let mut sections = Vec::new();
+        while let Some(result) = batch_cursor.next().await {
+            sections.push(result.context("Failed to read batch")?.batch_name);
+        }
+
+        let mut filter = doc! {
+            "course_code": &enrollment.course_code,
+            "semester": &enrollment.semester,
+            "campus_id": &claims.campus_id
+        };
+        if !sections.is_empty() {
+            filter.insert("section", doc! { "$in": sections });
+        }
+        slots.extend(collect_slots(&collection, filter).await?);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "timetable": weekly_view(slots)
+    })))
+}
+
+/// GET /api/timetable/faculty/{faculty_id}
+async fn get_faculty_timetable(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<WeeklyTimetableQuery>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let faculty_id = path.into_inner();
+
+    if claims.role == "student" {
+        return Err(AppError::Forbidden(
+            "Access denied: Students cannot view faculty timetables".to_string(),
+        ));
+    }
+
+    let collection: Collection<TimetableSlot> = data.db.collection("timetable");
+    let mut filter = doc! { "faculty_id": &faculty_id, "campus_id": &claims.campus_id };
+    if let Some(semester) = &query.semester {
+        filter.insert("semester", semester.as_str());
+    }
+
+    let slots = collect_slots(&collection, filter).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "faculty_id": faculty_id,
+        "timetable": weekly_view(slots)
+    })))
+}
+
+// ── Extra Classes ─────────────────────────────────────────────────────────────
+
+/// POST /api/extra-classes
+/// Schedules a makeup or remedial class and notifies the enrolled students.
+async fn create_extra_class(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can schedule extra classes".to_string()));
+    }
+
+    let class_data: ExtraClassRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = require_field(&class_data.course_code, "course_code")?;
+    let semester = require_field(&class_data.semester, "semester")?;
+    let room = require_field(&class_data.room, "room")?;
+    let date = normalize_date(require_field(&class_data.date, "date")?, "date")?;
+    let start_time = normalize_time(require_field(&class_data.start_time, "start_time")?, "start_time")?;
+    let end_time = normalize_time(require_field(&class_data.end_time, "end_time")?, "end_time")?;
+    let faculty_id = class_data
+        .faculty_id
+        .as_deref()
+        .map(str::trim)
+        .filter(|f| !f.is_empty())
+        .unwrap_or(&claims.sub)
+        .to_string();
+    let kind = class_data.kind.as_deref().unwrap_or("makeup").trim().to_lowercase();
+
+    if kind != "makeup" && kind != "remedial" {
+        return Err(AppError::BadRequest("kind must be 'makeup' or 'remedial'".to_string()));
+    }
+    if start_time >= end_time {
+        return Err(AppError::BadRequest("start_time must be before end_time".to_string()));
+    }
+    if date < Utc::now().format("%Y-%m-%d").to_string() {
+        return Err(AppError::BadRequest("Extra classes cannot be scheduled in the past".to_string()));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    let section = match class_data.section.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        Some(name) => {
+            let sections = offering_sections(&data.db, &claims.campus_id, course_code, semester).await?;
+            if !sections.is_empty() && !sections.iter().any(|s| s.section_name == name) {
+                let names: Vec<&str> = sections.iter().map(|s| s.section_name.as_str()).collect();
+                return Err(AppError::BadRequest(format!(
+                    "Unknown section '{}'; choose one of: {}",
+                    name,
+                    names.join(", ")
+                )));
+            }
+            Some(name.to_string())
+        }
+        None => None,
+    };
+
+    // Clashes with the regular weekly timetable on that weekday
+    let day = NaiveDate::parse_from_str(&date, "%Y-%m-%d")
+        .map(|d| d.format("%A").to_string().to_lowercase())
+        .map_err(|_| AppError::BadRequest("'date' must be a date in YYYY-MM-DD format".to_string()))?;
+    let mut slot_parties = vec![doc! { "faculty_id": &faculty_id }, doc! { "room": room }];
+    match &section {
+        Some(section) => slot_parties.push(doc! { "course_code": course_code, "section": section }),
+        None => slot_parties.push(doc! { "course_code": course_code }),
+    }
+    let timetable: Collection<TimetableSlot> = data.db.collection("timetable");
+    let slot_clashes = collect_slots(&timetable, doc! {
+        "campus_id": &claims.campus_id,
+        "semester": semester,
+        "day": &day,
+        "start_time": { "$lt": &end_time },
+        "end_time": { "$gt": &start_time },
+        "$or": slot_parties.clone()
+    })
+    .await?;
+
+    // ...and with other extra classes on the same date
+    let collection: Collection<ExtraClass> = data.db.collection("extra_classes");
+    let mut cursor = collection
+        .find(doc! {
+            "campus_id": &claims.campus_id,
+            "date": &date,
+            "status": "scheduled",
+            "start_time": { "$lt": &end_time },
+            "end_time": { "$gt": &start_time },
+            "$or": slot_parties
+        }, None)
+        .await
+        .context("Failed to query extra classes")?;
+    let mut details: Vec<String> = slot_clashes
+        .iter()
+        .map(|c| format!("{} {}-{} {} in {}", c.day, c.start_time, c.end_time, c.course_code, c.room))
+        .collect();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let c = result.context("Failed to read extra class")?;
+        details.push(format!("{} {}-{} {} in {} (extra)", c.date, c.start_time, c.end_time, c.course_code, c.room));
+    }
+    if !details.is_empty() {
+        return Err(AppError::Conflict(format!("Schedule clash: {}", details.join("; "))));
+    }
+
+    let extra_class = ExtraClass {
+        id: None,
+        course_code: course_code.to_string(),
+        semester: semester.to_string(),
+        section,
+        kind,
+        topic: class_data.topic.clone().unwrap_or_default(),
+        date,
+        start_time,
+        end_time,
+        room: room.to_string(),
+        faculty_id,
+        status: "scheduled".to_string(),
+        campus_id: claims.campus_id.clone(),
+        created_by: claims.sub,
+        created_at: Utc::now(),
+    };
+    let result = collection
+        .insert_one(&extra_class, None)
+        .await
+        .context("Failed to insert extra class")?;
+
+    let students = enrolled_student_ids(
+        &data.db,
+        &claims.campus_id,
+        course_code,
+        semester,
+        extra_class.section.as_deref(),
+    )
+    .await?;
+    let message = format!(
+        "{} {} class scheduled on {} {}-{} in {}",
+        course_code, extra_class.kind, extra_class.date, extra_class.start_time, extra_class.end_time, extra_class.room
+    );
+    notify_students(
+        &data.db,
+        &claims.campus_id,
+        &students,
+        course_code,
+        &message,
+        result.inserted_id.as_object_id(),
+    )
+    .await?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Extra class scheduled successfully",
+        "id": result.inserted_id,
+        "students_notified": students.len()
+    })))
+}
+
+/// GET /api/extra-classes
+/// Students only see classes for the offerings and sections they are enrolled in.
+async fn get_extra_classes(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ExtraClassFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(course_code) = query.course_code.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("course_code", course_code);
+    }
+    if let Some(semester) = query.semester.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("semester", semester);
+    }
+    let mut date_range = Document::new();
+    if let Some(from) = query.from.as_deref().filter(|s| !s.trim().is_empty()) {
+        date_range.insert("$gte", normalize_date(from, "from")?);
+    }
+    if let Some(to) = query.to.as_deref().filter(|s| !s.trim().is_empty()) {
+        date_range.insert("$lte", normalize_date(to, "to")?);
+    }
+    if !date_range.is_empty() {
+        filter.insert("date", date_range);
+    }
+    if !query.include_cancelled.unwrap_or(false) {
+        filter.insert("status", "scheduled");
+    }
+
+    use futures::stream::StreamExt;
+    if claims.role == "student" {
+        let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+        let mut cursor = enrollments
+            .find(doc! { "student_id": &claims.sub, "campus_id": &claims.campus_id }, None)
+            .await
+            .context("Failed to query enrollments")?;
+        let mut offerings = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let enrollment = result.context("Failed to read enrollment")?;
+            // Whole-offering classes, plus those for the student's own section
+            let sections = match enrollment.section {
+                Some(section) => vec![mongodb::bson::Bson::Null, section.into()],
+                None => vec![mongodb::bson::Bson::Null],
+            };
+            offerings.push(doc! {
+                "course_code": enrollment.course_code,
+                "semester": enrollment.semester,
+                "section": { "$in": sections }
+            });
+        }
+        if offerings.is_empty() {
+            return Ok(HttpResponse::Ok().json(Vec::<ExtraClass>::new()));
+        }
+        filter.insert("$or", offerings);
+    }
+
+    let collection: Collection<ExtraClass> = data.db.collection("extra_classes");
+    let options = FindOptions::builder().sort(doc! { "date": 1, "start_time": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query extra classes")?;
+
+    let mut classes = Vec::new();
+    while let Some(result) = cursor.next().await {
+        classes.push(result.context("Failed to read extra class")?);
+    }
+
+    Ok(HttpResponse::Ok().json(classes))
+}
+
+async fn find_extra_class(
+    db: &mongodb::Database,
+    campus_id: &str,
+    id: &str,
+) -> Result<ExtraClass, AppError> {
+    let object_id = ObjectId::parse_str(id)
+        .map_err(|_| AppError::BadRequest("Invalid extra class ID format".to_string()))?;
+    let extra_class = db
+        .collection::<ExtraClass>("extra_classes")
+        .find_one(doc! { "_id": object_id, "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch extra class")?
+        .ok_or_else(|| AppError::NotFound("Extra class not found".to_string()))?;
+    Ok(extra_class)
+}
+
+/// DELETE /api/extra-classes/{id}
+/// Cancels a scheduled class and tells the students it is off.
+async fn cancel_extra_class(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can cancel extra classes".to_string()));
+    }
+
+    let extra_class = find_extra_class(&data.db, &claims.campus_id, &path.into_inner()).await?;
+    if claims.role == "teacher" && extra_class.faculty_id != claims.sub && extra_class.created_by != claims.sub {
+        return Err(AppError::Forbidden("You can only cancel your own extra classes".to_string()));
+    }
+
+    let attendance: Collection<Attendance> = data.db.collection("attendance");
+    if attendance
+        .find_one(doc! { "extra_class_id": extra_class.id }, None)
+        .await
+        .context("Failed to check extra class attendance")?
+        .is_some()
+    {
+        return Err(AppError::Conflict("Attendance has already been taken for this class".to_string()));
+    }
+
+    let result = data
+        .db
+        .collection::<ExtraClass>("extra_classes")
+        .update_one(
+            doc! { "_id": extra_class.id, "status": "scheduled" },
+            doc! { "$set": { "status": "cancelled" } },
+            None,
+        )
+        .await
+        .context("Failed to cancel extra class")?;
+    if result.modified_count == 0 {
+        return Err(AppError::Conflict("Extra class is already cancelled".to_string()));
+    }
+
+    // The scheduling notice no longer applies
+    data.db
+        .collection::<Alert>("alerts")
+        .update_many(
+            doc! { "extra_class_id": extra_class.id, "resolved": false },
+            doc! { "$set": { "resolved": true, "updated_at": Utc::now().to_rfc3339() } },
+            None,
+        )
+        .await
+        .context("Failed to resolve extra class notices")?;
+
+    let students = enrolled_student_ids(
+        &data.db,
+        &claims.campus_id,
+        &extra_class.course_code,
+        &extra_class.semester,
+        extra_class.section.as_deref(),
+    )
+    .await?;
+    let message = format!(
+        "{} {} class on {} {}-{} has been cancelled",
+        extra_class.course_code, extra_class.kind, extra_class.date, extra_class.start_time, extra_class.end_time
+    );
+    notify_students(&data.db, &claims.campus_id, &students, &extra_class.course_code, &message, extra_class.id).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Extra class cancelled",
+        "students_notified": students.len()
+    })))
+}
+
+/// POST /api/extra-classes/{id}/attendance
+/// Records attendance for an extra class. The records are ordinary attendance
+/// rows, so they count towards summaries and shortage scans; marking again
+/// replaces a student's earlier record for the class.
+async fn mark_extra_class_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can mark attendance".to_string()));
+    }
+
+    let attendance_data: ExtraClassAttendanceRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let records = attendance_data
+        .records
+        .ok_or_else(|| AppError::BadRequest("'records' is required".to_string()))?;
+    if records.is_empty() {
+        return Err(AppError::BadRequest("'records' must not be empty".to_string()));
+    }
+
+    let extra_class = find_extra_class(&data.db, &claims.campus_id, &path.into_inner()).await?;
+    if extra_class.status != "scheduled" {
+        return Err(AppError::BadRequest("This extra class was cancelled".to_string()));
+    }
+    if extra_class.date > Utc::now().format("%Y-%m-%d").to_string() {
+        return Err(AppError::BadRequest("Attendance can be taken on or after the class date".to_string()));
+    }
+
+    // Validate every record before writing any
+    let enrolled = enrolled_student_ids(
+        &data.db,
+        &claims.campus_id,
+        &extra_class.course_code,
+        &extra_class.semester,
+        extra_class.section.as_deref(),
+    )
+    .await?;
+    for record in &records {
+        validate_attendance_status(&record.status)?;
+        if !enrolled.contains(&record.student_id) {
+            return Err(AppError::BadRequest(format!(
+                "Student '{}' is not enrolled for this class",
+                record.student_id
+            )));
+        }
+    }
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    for record in &records {
+        let attendance = Attendance {
+            id: None,
+            student_id: record.student_id.clone(),
+            course_code: extra_class.course_code.clone(),
+            section: extra_class.section.clone(),
+            date: extra_class.date.clone(),
+            status: record.status.clone(),
+            extra_class_id: extra_class.id,
+            campus_id: claims.campus_id.clone(),
+            created_at: Utc::now(),
+        };
+        collection
+            .replace_one(
+                doc! { "extra_class_id": extra_class.id, "student_id": &record.student_id },
+                attendance,
+                ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await
+            .context("Failed to save extra class attendance")?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Attendance marked for {} students", records.len())
+    })))
+}
+
+// ── Exams & Seating ───────────────────────────────────────────────────────────
+
+/// POST /api/exam-halls
+async fn create_exam_hall(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can manage exam halls".to_string()));
+    }
+
+    let hall_data: ExamHallRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let hall_name = require_field(&hall_data.hall_name, "hall_name")?;
+    let capacity = require_i32(hall_data.capacity, "capacity")?;
+
+    if capacity < 1 {
+        return Err(AppError::BadRequest("capacity must be at least 1".to_string()));
+    }
+
+    let collection: Collection<ExamHall> = data.db.collection("exam_halls");
+
+    let existing = collection
+        .find_one(doc! { "hall_name": hall_name, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to check existing exam hall")?;
+    if existing.is_some() {
+        return Err(AppError::BadRequest("Exam hall already exists".to_string()));
+    }
+
+    collection
+        .insert_one(ExamHall {
+            id: None,
+            hall_name: hall_name.to_string(),
+            capacity,
+            campus_id: claims.campus_id,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to insert exam hall")?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({ "message": "Exam hall created successfully" })))
+}
+
+/// GET /api/exam-halls
+async fn get_exam_halls(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<ExamHall> = data.db.collection("exam_halls");
+
+    let options = FindOptions::builder().sort(doc! { "hall_name": 1 }).build();
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, options)
+        .await
+        .context("Failed to query exam halls")?;
+
+    let mut halls = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        halls.push(result.context("Failed to read exam hall")?);
+    }
+
+    Ok(HttpResponse::Ok().json(halls))
+}
+
+/// POST /api/exams
+/// Schedules an exam. Rejected with 409 if any enrolled student already has
+/// an exam overlapping the same date and time.
+async fn create_exam(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can schedule exams".to_string()));
+    }
+
+    let exam_data: ExamRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = require_field(&exam_data.course_code, "course_code")?;
+    let semester = require_field(&exam_data.semester, "semester")?;
+    let exam_type = require_field(&exam_data.exam_type, "exam_type")?;
+    let date = normalize_date(require_field(&exam_data.date, "date")?, "date")?;
+    let start_time = normalize_time(require_field(&exam_data.start_time, "start_time")?, "start_time")?;
+    let end_time = normalize_time(require_field(&exam_data.end_time, "end_time")?, "end_time")?;
+
+    validate_exam_type(exam_type)?;
+    if start_time >= end_time {
+        return Err(AppError::BadRequest("start_time must be before end_time".to_string()));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    let students = enrolled_student_ids(&data.db, &claims.campus_id, course_code, semester, None).await?;
+
+    let mut clashes = Vec::new();
+    for other in overlapping_exams(&data.db, &claims.campus_id, &date, &start_time, &end_time).await? {
+        let other_students =
+            enrolled_student_ids(&data.db, &claims.campus_id, &other.course_code, &other.semester, None).await?;
+        let shared: Vec<&String> = students.iter().filter(|s| other_students.contains(s)).collect();
+        if !shared.is_empty() {
+            clashes.push(format!(
+                "{} {}-{} ({} student(s): {})",
+                other.course_code,
+                other.start_time,
+                other.end_time,
+                shared.len(),
+                shared.iter().map(|s| s.as_str()).collect::<Vec<_>>().join(", ")
+            ));
+        }
+    }
+
+    if !clashes.is_empty() {
+        return Err(AppError::Conflict(format!(
+            "Exam clash for enrolled students: {}",
+            clashes.join("; ")
+        )));
+    }
+
+    let collection: Collection<Exam> = data.db.collection("exams");
+    let result = collection
+        .insert_one(Exam {
+            id: None,
+            course_code: course_code.to_string(),
+            semester: semester.to_string(),
+            exam_type: exam_type.to_string(),
+            date,
+            start_time,
+            end_time,
+            campus_id: claims.campus_id,
+            created_by: claims.sub,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to insert exam")?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Exam scheduled successfully",
+        "id": result.inserted_id
+    })))
+}
+
+/// GET /api/exams
+async fn get_exams(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ExamFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<Exam> = data.db.collection("exams");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            filter.insert("semester", semester.as_str());
+        }
+    }
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+
+    let options = FindOptions::builder().sort(doc! { "date": 1, "start_time": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query exams")?;
+
+    let mut exams = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        exams.push(result.context("Failed to read exam")?);
+    }
+
+    Ok(HttpResponse::Ok().json(exams))
+}
+
+/// POST /api/exams/{id}/seating
+/// (Re)allocates seats to every enrolled student, filling halls in order.
+/// Seats already taken by overlapping exams in a shared hall are skipped.
+async fn allocate_exam_seating(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can allocate seating".to_string()));
+    }
+
+    let seating: SeatingRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let exam_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid exam ID format".to_string()))?;
+
+    let exams: Collection<Exam> = data.db.collection("exams");
+    let exam = exams
+        .find_one(doc! { "_id": exam_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch exam")?
+        .ok_or_else(|| AppError::NotFound("Exam not found".to_string()))?;
+
+    let students = enrolled_student_ids(&data.db, &claims.campus_id, &exam.course_code, &exam.semester, None).await?;
+    if students.is_empty() {
+        return Err(AppError::BadRequest("No students are enrolled for this exam".to_string()));
+    }
+
+    let halls_collection: Collection<ExamHall> = data.db.collection("exam_halls");
+    let mut hall_filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(names) = &seating.hall_names {
+        hall_filter.insert("hall_name", doc! { "$in": names });
+    }
+    let options = FindOptions::builder().sort(doc! { "hall_name": 1 }).build();
+    let mut cursor = halls_collection
+        .find(hall_filter, options)
+        .await
+        .context("Failed to query exam halls")?;
+
+    let mut halls = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        halls.push(result.context("Failed to read exam hall")?);
+    }
+    if let Some(names) = &seating.hall_names {
+        // Honour the requested fill order
+        halls.sort_by_key(|h| names.iter().position(|n| *n == h.hall_name));
+        if let Some(missing) = names.iter().find(|n| !halls.iter().any(|h| &h.hall_name == *n)) {
+            return Err(AppError::NotFound(format!("Exam hall '{}' not found", missing)));
+        }
+    }
+
+    // Seats held by other exams running at the same time
+    let seats: Collection<ExamSeat> = data.db.collection("exam_seats");
+    let concurrent: Vec<ObjectId> = overlapping_exams(&data.db, &claims.campus_id, &exam.date, &exam.start_time, &exam.end_time)
+        .await?
+        .into_iter()
+        .filter_map(|e| e.id)
+        .filter(|id| *id != exam_id)
+        .collect();
+
+    let mut taken: std::collections::HashSet<(String, i32)> = std::collections::HashSet::new();
+    let mut taken_cursor = seats
+        .find(doc! { "exam_id": { "$in": &concurrent }, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to query allocated seats")?;
+    while let Some(result) = taken_cursor.next().await {
+        let seat = result.context("Failed to read seat")?;
+        taken.insert((seat.hall_name, seat.seat_number));
+    }
+
+    let mut free_seats = halls.iter().flat_map(|hall| {
+        (1..=hall.capacity)
+            .filter(|n| !taken.contains(&(hall.hall_name.clone(), *n)))
+            .map(|n| (hall.hall_name.clone(), n))
+            .collect::<Vec<_>>()
+    });
+
+    let mut allocations = Vec::with_capacity(students.len());
+    for student_id in &students {
+        let (hall_name, seat_number) = free_seats.next().ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Not enough free seats: {} students, halls full after {}",
+                students.len(),
+                allocations.len()
+            ))
+        })?;
+        allocations.push(ExamSeat {
+            id: None,
+            exam_id,
+            student_id: student_id.clone(),
+            hall_name,
+            seat_number,
+            campus_id: claims.campus_id.clone(),
+        });
+    }
+
+    seats
+        .delete_many(doc! { "exam_id": exam_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to clear previous seating")?;
+    seats
+        .insert_many(&allocations, None)
+        .await
+        .context("Failed to insert seating")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Seats allocated for {} students", allocations.len()),
+        "seating": allocations
+    })))
+}
+
+/// GET /api/exams/{id}/seating
+async fn get_exam_seating(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role == "student" {
+        return Err(AppError::Forbidden(
+            "Access denied: Students can view their seat on the hall ticket".to_string(),
+        ));
+    }
+
+    let exam_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid exam ID format".to_string()))?;
+
+    let seats: Collection<ExamSeat> = data.db.collection("exam_seats");
+    let options = FindOptions::builder().sort(doc! { "hall_name": 1, "seat_number": 1 }).build();
+    let mut cursor = seats
+        .find(doc! { "exam_id": exam_id, "campus_id": &claims.campus_id }, options)
+        .await
+        .context("Failed to query seating")?;
+
+    let mut seating = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        seating.push(result.context("Failed to read seat")?);
+    }
+
+    Ok(HttpResponse::Ok().json(seating))
+}
+
+/// GET /api/exams/hall-ticket/{student_id}
+/// The student's scheduled exams with hall and seat (null until allocated).
+async fn get_hall_ticket(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ExamFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own hall ticket".to_string(),
+        ));
+    }
+
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    let exams: Collection<Exam> = data.db.collection("exams");
+    let seats: Collection<ExamSeat> = data.db.collection("exam_seats");
+
+    let mut enrollment_filter = doc! { "student_id": &student_id, "campus_id": &claims.campus_id };
+    if let Some(semester) = &query.semester {
+        enrollment_filter.insert("semester", semester.as_str());
+    }
+    let mut cursor = enrollments
+        .find(enrollment_filter, None)
+        .await
+        .context("Failed to query enrollments")?;
+
+    let mut offerings = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let enrollment = result.context("Failed to read enrollment")?;
+        offerings.push(doc! { "course_code": enrollment.course_code, "semester": enrollment.semester });
+    }
+
+    let mut entries = Vec::new();
+    if !offerings.is_empty() {
+        let options = FindOptions::builder().sort(doc! { "date": 1, "start_time": 1 }).build();
+        let mut exam_cursor = exams
+            .find(doc! { "campus_id": &claims.campus_id, "$or": offerings }, options)
+            .await
+            .context("Failed to query exams")?;
+
+        while let Some(result) = exam_cursor.next().await {
+            let exam = result.context("Failed to read exam")?;
+            let seat = match exam.id {
+                Some(exam_id) => seats
+                    .find_one(doc! { "exam_id": exam_id, "student_id": &student_id }, None)
+                    .await
+                    .context("Failed to fetch seat")?,
+                None => None,
+            };
+            entries.push(serde_json::json!({
+                "exam_id": exam.id,
+                "course_code": exam.course_code,
+                "semester": exam.semester,
+                "exam_type": exam.exam_type,
+                "date": exam.date,
+                "start_time": exam.start_time,
+                "end_time": exam.end_time,
+                "hall_name": seat.as_ref().map(|s| s.hall_name.clone()),
+                "seat_number": seat.map(|s| s.seat_number)
+            }));
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "exams": entries
+    })))
+}
+
+// ── Assignments & Submissions ─────────────────────────────────────────────────
+
+/// POST /api/assignments
+async fn create_assignment(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can create assignments".to_string()));
+    }
+
+    let assignment_data: AssignmentRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let course_code = require_field(&assignment_data.course_code, "course_code")?;
+    let semester = require_field(&assignment_data.semester, "semester")?;
+    let title = require_field(&assignment_data.title, "title")?;
+    let due_at = require_field(&assignment_data.due_at, "due_at")?;
+    let max_marks = require_f64(assignment_data.max_marks, "max_marks")?;
+
+    let due_at = DateTime::parse_from_rfc3339(due_at)
+        .map_err(|_| AppError::BadRequest("'due_at' must be an RFC 3339 timestamp".to_string()))?
+        .with_timezone(&Utc);
+
+    if max_marks <= 0.0 {
+        return Err(AppError::BadRequest("max_marks must be greater than 0".to_string()));
+    }
+
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
+
+    let collection: Collection<Assignment> = data.db.collection("assignments");
+    let result = collection
+        .insert_one(Assignment {
+            id: None,
+            course_code: course_code.to_string(),
+            semester: semester.to_string(),
+            title: title.to_string(),
+            description: assignment_data.description.clone().unwrap_or_default(),
+            due_at,
+            max_marks,
+            allow_late: assignment_data.allow_late.unwrap_or(true),
+            created_by: claims.sub,
+            campus_id: claims.campus_id,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to insert assignment")?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Assignment created successfully",
+        "id": result.inserted_id
+    })))
+}
+
+/// GET /api/assignments
+/// Students only see assignments for courses they are enrolled in.
+async fn get_assignments(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AssignmentFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(course_code) = &query.course_code {
+        if !course_code.trim().is_empty() {
+            filter.insert("course_code", course_code.as_str());
+        }
+    }
+    if let Some(semester) = &query.semester {
+        if !semester.trim().is_empty() {
+            filter.insert("semester", semester.as_str());
+        }
+    }
+
+    use futures::stream::StreamExt;
+    if claims.role == "student" {
+        let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+        let mut cursor = enrollments
+            .find(doc! { "student_id": &claims.sub, "campus_id": &claims.campus_id }, None)
+            .await
+            .context("Failed to query enrollments")?;
+
+        let mut offerings = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let enrollment = result.context("Failed to read enrollment")?;
+            offerings.push(doc! { "course_code": enrollment.course_code, "semester": enrollment.semester });
+        }
+        if offerings.is_empty() {
+            return Ok(HttpResponse::Ok().json(Vec::<Assignment>::new()));
+        }
+        filter.insert("$or", offerings);
+    }
+
+    let collection: Collection<Assignment> = data.db.collection("assignments");
+    let options = FindOptions::builder().sort(doc! { "due_at": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query assignments")?;
+
+    let mut assignments = Vec::new();
+    while let Some(result) = cursor.next().await {
+        assignments.push(result.context("Failed to read assignment")?);
+    }
+
+    Ok(HttpResponse::Ok().json(assignments))
+}
+
+/// POST /api/assignments/{id}/submissions
+/// A student may resubmit until their submission has been graded.
+async fn submit_assignment(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "student" {
+        return Err(AppError::Forbidden("Only students can submit assignments".to_string()));
+    }
+
+    let submission_data: SubmissionRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let file_url = submission_data.file_url.filter(|s| !s.trim().is_empty());
+    let text = submission_data.text.filter(|s| !s.trim().is_empty());
+    if file_url.is_none() && text.is_none() {
+        return Err(AppError::BadRequest("Either 'file_url' or 'text' is required".to_string()));
+    }
+
+    let assignment_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid assignment ID format".to_string()))?;
+
+    let assignments: Collection<Assignment> = data.db.collection("assignments");
+    let assignment = assignments
+        .find_one(doc! { "_id": assignment_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch assignment")?
+        .ok_or_else(|| AppError::NotFound("Assignment not found".to_string()))?;
+
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    enrollments
+        .find_one(doc! {
+            "student_id": &claims.sub,
+            "course_code": &assignment.course_code,
+            "semester": &assignment.semester,
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .context("Failed to check enrollment")?
+        .ok_or_else(|| AppError::Forbidden("You are not enrolled in this course".to_string()))?;
+
+    let now = Utc::now();
+    let is_late = now > assignment.due_at;
+    if is_late && !assignment.allow_late {
+        return Err(AppError::BadRequest("The deadline for this assignment has passed".to_string()));
+    }
+
+    let collection: Collection<Submission> = data.db.collection("submissions");
+    let key = doc! {
+        "assignment_id": assignment_id,
+        "student_id": &claims.sub,
+        "campus_id": &claims.campus_id
+    };
+
+    let existing = collection
+        .find_one(key.clone(), None)
+        .await
+        .context("Failed to check existing submission")?;
+    if existing.as_ref().is_some_and(|s| s.marks.is_some()) {
+        return Err(AppError::Conflict("This submission has already been graded".to_string()));
+    }
+
+    let submission = Submission {
+        id: existing.and_then(|s| s.id),
+        assignment_id,
+        student_id: claims.sub,
+        file_url,
+        text,
+        submitted_at: now,
+        is_late,
+        marks: None,
+        feedback: None,
+        graded_by: None,
+        graded_at: None,
+        campus_id: claims.campus_id,
+    };
+
+    collection
+        .replace_one(key, &submission, ReplaceOptions::builder().upsert(true).build())
+        .await
+        .context("Failed to save submission")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": if is_late { "Submitted after the deadline" } else { "Submitted successfully" },
+        "is_late": is_late
+    })))
 }
 
-// ── Input Validation Helpers ──────────────────────────────────────────────────
+/// GET /api/assignments/{id}/submissions
+/// Teachers see every submission; students only their own.
+async fn get_assignment_submissions(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-fn require_field<'a>(value: &'a Option<String>, field: &str) -> Result<&'a str, AppError> {
-    match value {
-        Some(v) if !v.trim().is_empty() => Ok(v.as_str()),
-        Some(_) => Err(AppError::BadRequest(format!("'{}' must not be blank", field))),
-        None => Err(AppError::BadRequest(format!("'{}' is required", field))),
-    }
-}
+    let assignment_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid assignment ID format".to_string()))?;
 
-fn require_f64(value: Option<f64>, field: &str) -> Result<f64, AppError> {
-    value.ok_or_else(|| AppError::BadRequest(format!("'{}' is required", field)))
-}
+    let mut filter = doc! { "assignment_id": assignment_id, "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Access denied".to_string()));
+    }
 
-fn require_i32(value: Option<i32>, field: &str) -> Result<i32, AppError> {
-    value.ok_or_else(|| AppError::BadRequest(format!("'{}' is required", field)))
-}
+    let collection: Collection<Submission> = data.db.collection("submissions");
+    let options = FindOptions::builder().sort(doc! { "student_id": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query submissions")?;
 
-/// Validates attendance status values.
-fn validate_attendance_status(status: &str) -> Result<(), AppError> {
-    match status {
-        "present" | "absent" | "late" => Ok(()),
-        _ => Err(AppError::BadRequest(format!(
-            "Invalid status '{}'. Must be: present, absent, or late",
-            status
-        ))),
+    let mut submissions = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        submissions.push(result.context("Failed to read submission")?);
     }
+
+    Ok(HttpResponse::Ok().json(submissions))
 }
 
-/// Validates exam type values.
-fn validate_exam_type(exam_type: &str) -> Result<(), AppError> {
-    match exam_type {
-        "midterm" | "final" | "quiz" | "assignment" => Ok(()),
-        _ => Err(AppError::BadRequest(format!(
-            "Invalid exam_type '{}'. Must be: midterm, final, quiz, or assignment",
-            exam_type
-        ))),
+/// PUT /api/submissions/{id}/grade
+async fn grade_submission(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can grade submissions".to_string()));
     }
-}
 
-/// Validates review status values.
-fn validate_review_status(status: &str) -> Result<(), AppError> {
-    match status {
-        "reviewed" | "verified" | "rejected" => Ok(()),
-        _ => Err(AppError::BadRequest(format!(
-            "Invalid status '{}'. Must be: reviewed, verified, or rejected",
-            status
-        ))),
+    let grade_data: GradeSubmissionRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let marks = require_f64(grade_data.marks, "marks")?;
+
+    let submission_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid submission ID format".to_string()))?;
+
+    let collection: Collection<Submission> = data.db.collection("submissions");
+    let submission = collection
+        .find_one(doc! { "_id": submission_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch submission")?
+        .ok_or_else(|| AppError::NotFound("Submission not found".to_string()))?;
+
+    let assignments: Collection<Assignment> = data.db.collection("assignments");
+    let assignment = assignments
+        .find_one(doc! { "_id": submission.assignment_id }, None)
+        .await
+        .context("Failed to fetch assignment")?
+        .ok_or_else(|| AppError::NotFound("Assignment not found".to_string()))?;
+
+    if marks < 0.0 || marks > assignment.max_marks {
+        return Err(AppError::BadRequest(format!(
+            "marks must be between 0 and {}",
+            assignment.max_marks
+        )));
     }
-}
 
-// ── JWT Extraction (uses anyhow internally) ───────────────────────────────────
+    collection
+        .update_one(
+            doc! { "_id": submission_id },
+            doc! { "$set": {
+                "marks": marks,
+                "feedback": grade_data.feedback.as_deref(),
+                "graded_by": &claims.sub,
+                "graded_at": Utc::now().to_rfc3339()
+            }},
+            None,
+        )
+        .await
+        .context("Failed to grade submission")?;
 
-/// Extracts and validates JWT claims from the Authorization header.
-/// Returns Result<Claims, AppError> — no panics, no unwrap.
-fn extract_claims(req: &HttpRequest, jwt_secret: &str) -> Result<Claims, AppError> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .ok_or_else(|| AppError::Unauthorized("No token provided".to_string()))?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Submission graded successfully" })))
+}
 
-    let auth_str = auth_header
-        .to_str()
-        .map_err(|_| AppError::Unauthorized("Malformed Authorization header".to_string()))?;
+// ── Transcripts ───────────────────────────────────────────────────────────────
+
+/// GET /api/students/{id}/transcript
+/// Renders the student's transcript as a PDF and records its verification code.
+async fn get_transcript(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
 
-    if !auth_str.starts_with("Bearer ") {
-        return Err(AppError::Unauthorized(
-            "Authorization header must use Bearer scheme".to_string(),
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only download your own transcript".to_string(),
         ));
     }
 
-    let token = &auth_str[7..];
+    // Transcripts are official documents, so only published results appear
+    let card = compute_grade_card(&data.db, &claims.campus_id, &student_id, true).await?;
+    let semesters = collect_transcript(&data.db, &claims.campus_id, &card).await?;
 
-    decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(jwt_secret.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    )
-    .map(|data| data.claims)
-    .map_err(|_| AppError::Unauthorized("Invalid or expired token".to_string()))
-}
+    if semesters.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No academic records found for student '{}'",
+            student_id
+        )));
+    }
 
-// ── Service Helpers (anyhow for DB operations) ────────────────────────────────
+    let record = TranscriptRecord {
+        id: None,
+        verification_code: generate_verification_code(),
+        student_id: student_id.clone(),
+        campus_id: claims.campus_id.clone(),
+        cgpa: card.cgpa,
+        total_credits_earned: card.total_credits_earned,
+        issued_by: claims.sub,
+        issued_at: Utc::now(),
+    };
 
-/// Fetches a batch by ObjectId. Returns Option<Batch> — None means not found.
-/// Uses anyhow context to add meaningful error messages for DB failures.
-async fn find_batch_by_id(
-    collection: &Collection<Batch>,
-    batch_id: &str,
-    campus_id: &str,
-) -> anyhow::Result<Option<Batch>> {
-    let oid = ObjectId::parse_str(batch_id)
-        .context("Invalid batch ID format")?;
+    let pdf = render_transcript_pdf(&data.institution_name, &claims.campus_id, &card, &semesters, &record)?;
+
+    let collection: Collection<TranscriptRecord> = data.db.collection("transcripts");
     collection
-        .find_one(doc! { "_id": oid, "campus_id": campus_id }, None)
+        .insert_one(&record, None)
         .await
-        .context("Database error while fetching batch")
+        .context("Failed to record transcript")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"transcript-{}.pdf\"", student_id),
+        ))
+        .insert_header(("X-Verification-Code", record.verification_code))
+        .body(pdf))
 }
 
-fn calculate_grade(marks: f64, total: f64) -> String {
-    if total == 0.0 {
-        return "N/A".to_string();
-    }
-    let percentage = (marks / total) * 100.0;
-    match percentage as i32 {
-        90..=100 => "A+".to_string(),
-        80..=89 => "A".to_string(),
-        70..=79 => "B+".to_string(),
-        60..=69 => "B".to_string(),
-        50..=59 => "C".to_string(),
-        40..=49 => "D".to_string(),
-        _ => "F".to_string(),
-    }
-}
+/// GET /api/transcripts/verify/{code} (public)
+async fn verify_transcript(
+    data: web::Data<AppState>,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let code = path.into_inner().trim().to_uppercase();
 
-// ── Handlers ──────────────────────────────────────────────────────────────────
+    let collection: Collection<TranscriptRecord> = data.db.collection("transcripts");
+    let record = collection
+        .find_one(doc! { "verification_code": &code }, None)
+        .await
+        .context("Failed to look up transcript")?
+        .ok_or_else(|| AppError::NotFound("No transcript was issued with this code".to_string()))?;
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "ok",
-        "service": "academics-service"
-    }))
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "valid": true,
+        "student_id": record.student_id,
+        "campus_id": record.campus_id,
+        "cgpa": record.cgpa,
+        "total_credits_earned": record.total_credits_earned,
+        "issued_at": record.issued_at
+    })))
 }
 
-// ── Course Management ─────────────────────────────────────────────────────────
+// ── Programs & Degree Audit ───────────────────────────────────────────────────
 
-async fn create_course(
+/// POST /api/programs
+async fn create_program(
     data: web::Data<AppState>,
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    let course_data: CourseRequest = serde_json::from_value(body.into_inner())
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can manage programs".to_string()));
+    }
+
+    let program_data: ProgramRequest = serde_json::from_value(body.into_inner())
         .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
 
-    let course_code = require_field(&course_data.course_code, "course_code")?;
-    let course_name = require_field(&course_data.course_name, "course_name")?;
-    let department = require_field(&course_data.department, "department")?;
-    let credits = require_i32(course_data.credits, "credits")?;
+    let program_code = require_field(&program_data.program_code, "program_code")?;
+    let program_name = require_field(&program_data.program_name, "program_name")?;
+    let department = require_field(&program_data.department, "department")?;
+    let total_credits = require_i32(program_data.total_credits, "total_credits")?;
+    let required_courses = program_data.required_courses.clone().unwrap_or_default();
+    let elective_baskets = program_data.elective_baskets.clone().unwrap_or_default();
 
-    if credits < 1 || credits > 6 {
-        return Err(AppError::BadRequest("Credits must be between 1 and 6".to_string()));
+    if total_credits < 1 {
+        return Err(AppError::BadRequest("total_credits must be at least 1".to_string()));
+    }
+    for basket in &elective_baskets {
+        if basket.name.trim().is_empty() || basket.courses.is_empty() {
+            return Err(AppError::BadRequest(
+                "Each elective basket needs a name and at least one course".to_string(),
+            ));
+        }
+        if basket.min_credits < 1 {
+            return Err(AppError::BadRequest(format!(
+                "Elective basket '{}' must require at least 1 credit",
+                basket.name
+            )));
+        }
     }
 
-    let collection: Collection<Course> = data.db.collection("courses");
+    let courses: Collection<Course> = data.db.collection("courses");
+    let referenced = required_courses
+        .iter()
+        .chain(elective_baskets.iter().flat_map(|b| &b.courses));
+    for code in referenced {
+        courses
+            .find_one(doc! { "course_code": code, "campus_id": &claims.campus_id }, None)
+            .await
+            .context("Failed to fetch course")?
+            .ok_or_else(|| AppError::BadRequest(format!("Course '{}' not found", code)))?;
+    }
 
-    let new_course = Course {
-        id: None,
-        course_code: course_code.to_string(),
-        course_name: course_name.to_string(),
-        credits,
-        department: department.to_string(),
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
-    };
+    let collection: Collection<Program> = data.db.collection("programs");
+    let existing = collection
+        .find_one(doc! { "program_code": program_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to check existing program")?;
+    if existing.is_some() {
+        return Err(AppError::Conflict(format!("Program '{}' already exists", program_code)));
+    }
 
     collection
-        .insert_one(new_course, None)
+        .insert_one(Program {
+            id: None,
+            program_code: program_code.to_string(),
+            program_name: program_name.to_string(),
+            department: department.to_string(),
+            total_credits,
+            required_courses,
+            elective_baskets,
+            campus_id: claims.campus_id,
+            created_at: Utc::now(),
+        }, None)
         .await
-        .context("Failed to insert course")?;
+        .context("Failed to insert program")?;
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Course created successfully" })))
+    Ok(HttpResponse::Created().json(serde_json::json!({ "message": "Program created successfully" })))
 }
 
-async fn get_courses(
+/// GET /api/programs
+async fn get_programs(
     data: web::Data<AppState>,
     req: HttpRequest,
-    query: web::Query<CourseFilter>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
-    let collection: Collection<Course> = data.db.collection("courses");
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let collection: Collection<Program> = data.db.collection("programs");
 
-    // Build filter — always scope to campus_id, optionally filter by department
-    let mut filter = doc! { "campus_id": &claims.campus_id };
-    if let Some(dept) = &query.department {
-        if !dept.trim().is_empty() {
-            filter.insert("department", dept.as_str());
-        }
+    let options = FindOptions::builder().sort(doc! { "program_code": 1 }).build();
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, options)
+        .await
+        .context("Failed to query programs")?;
+
+    let mut programs = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        programs.push(result.context("Failed to read program")?);
     }
 
-    let pagination = PaginationParams { page: query.page, limit: query.limit };
-    let total = collection
-        .count_documents(filter.clone(), None)
-        .await
-        .context("Failed to count courses")?;
+    Ok(HttpResponse::Ok().json(programs))
+}
 
-    let options = FindOptions::builder()
-        .skip(pagination.skip())
-        .limit(pagination.limit() as i64)
-        .sort(doc! { "created_at": -1 })
-        .build();
+/// GET /api/programs/{code}
+async fn get_program(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let program_code = path.into_inner();
 
-    let mut cursor = collection
-        .find(filter, options)
+    let collection: Collection<Program> = data.db.collection("programs");
+    let program = collection
+        .find_one(doc! { "program_code": &program_code, "campus_id": &claims.campus_id }, None)
         .await
-        .context("Failed to query courses")?;
+        .context("Failed to fetch program")?
+        .ok_or_else(|| AppError::NotFound(format!("Program '{}' not found", program_code)))?;
 
-    let mut courses = Vec::new();
-    use futures::stream::StreamExt;
-    while let Some(result) = cursor.next().await {
-        let course = result.context("Failed to read course from cursor")?;
-        courses.push(course);
+    Ok(HttpResponse::Ok().json(program))
+}
+
+/// PUT /api/students/{id}/program
+async fn assign_student_program(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can assign programs".to_string()));
     }
 
+    let assignment: StudentProgramRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let program_code = require_field(&assignment.program_code, "program_code")?;
+    let student_id = path.into_inner();
+
+    let programs: Collection<Program> = data.db.collection("programs");
+    programs
+        .find_one(doc! { "program_code": program_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch program")?
+        .ok_or_else(|| AppError::NotFound(format!("Program '{}' not found", program_code)))?;
+
+    let collection: Collection<StudentProgram> = data.db.collection("student_programs");
+    collection
+        .replace_one(
+            doc! { "student_id": &student_id, "campus_id": &claims.campus_id },
+            StudentProgram {
+                id: None,
+                student_id: student_id.clone(),
+                program_code: program_code.to_string(),
+                campus_id: claims.campus_id,
+                assigned_at: Utc::now(),
+            },
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .context("Failed to assign program")?;
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "data": courses,
-        "pagination": {
-            "page": pagination.page(),
-            "limit": pagination.limit(),
-            "total": total,
-            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
+        "message": format!("Student '{}' assigned to program '{}'", student_id, program_code)
+    })))
+}
+
+/// GET /api/students/{id}/degree-audit
+/// Compares passed and in-progress courses against the student's program.
+async fn get_degree_audit(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
+
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own degree audit".to_string(),
+        ));
+    }
+
+    let student_programs: Collection<StudentProgram> = data.db.collection("student_programs");
+    let assignment = student_programs
+        .find_one(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch student program")?
+        .ok_or_else(|| AppError::NotFound(format!("Student '{}' has no program assigned", student_id)))?;
+
+    let programs: Collection<Program> = data.db.collection("programs");
+    let program = programs
+        .find_one(doc! { "program_code": &assignment.program_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch program")?
+        .ok_or_else(|| AppError::NotFound(format!("Program '{}' not found", assignment.program_code)))?;
+
+    let card = compute_grade_card(&data.db, &claims.campus_id, &student_id, claims.role == "student").await?;
+    let semesters = collect_transcript(&data.db, &claims.campus_id, &card).await?;
+
+    // course_code -> credits, for each course passed in any semester
+    let mut passed: Vec<(String, i32)> = Vec::new();
+    for course in card.semesters.iter().flat_map(|s| &s.courses) {
+        if course.grade_points > 0.0 && !passed.iter().any(|(code, _)| *code == course.course_code) {
+            passed.push((course.course_code.clone(), course.credits));
+        }
+    }
+    let in_progress: Vec<String> = semesters
+        .iter()
+        .flat_map(|s| &s.lines)
+        .filter(|l| l.grade_points.is_none())
+        .map(|l| l.course_code.clone())
+        .filter(|code| !passed.iter().any(|(c, _)| c == code))
+        .collect();
+    let is_passed = |code: &String| passed.iter().any(|(c, _)| c == code);
+
+    let required_completed: Vec<&String> = program.required_courses.iter().filter(|c| is_passed(c)).collect();
+    let required_in_progress: Vec<&String> = program
+        .required_courses
+        .iter()
+        .filter(|c| !is_passed(c) && in_progress.contains(c))
+        .collect();
+    let required_remaining: Vec<&String> = program
+        .required_courses
+        .iter()
+        .filter(|c| !is_passed(c) && !in_progress.contains(c))
+        .collect();
+
+    // A passed course counts towards the first basket that lists it, never towards a required slot as well
+    let mut counted: Vec<&String> = program.required_courses.iter().collect();
+    let mut baskets = Vec::new();
+    let mut baskets_satisfied = true;
+    for basket in &program.elective_baskets {
+        let mut completed = Vec::new();
+        let mut earned = 0;
+        for code in &basket.courses {
+            if counted.contains(&code) {
+                continue;
+            }
+            if let Some((_, credits)) = passed.iter().find(|(c, _)| c == code) {
+                completed.push(code);
+                earned += credits;
+                counted.push(code);
+            }
         }
+        let remaining = (basket.min_credits - earned).max(0);
+        baskets_satisfied &= remaining == 0;
+        baskets.push(serde_json::json!({
+            "name": basket.name,
+            "min_credits": basket.min_credits,
+            "earned_credits": earned,
+            "remaining_credits": remaining,
+            "completed": completed,
+            "available": basket.courses.iter().filter(|c| !counted.contains(c)).collect::<Vec<_>>()
+        }));
+    }
+
+    let earned_credits: i32 = passed.iter().map(|(_, credits)| credits).sum();
+    let remaining_credits = (program.total_credits - earned_credits).max(0);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "program": {
+            "program_code": program.program_code,
+            "program_name": program.program_name
+        },
+        "required_courses": {
+            "completed": required_completed,
+            "in_progress": required_in_progress,
+            "remaining": required_remaining
+        },
+        "elective_baskets": baskets,
+        "credits": {
+            "required": program.total_credits,
+            "earned": earned_credits,
+            "remaining": remaining_credits
+        },
+        "cgpa": card.cgpa,
+        "complete": required_remaining.is_empty()
+            && required_in_progress.is_empty()
+            && baskets_satisfied
+            && remaining_credits == 0
     })))
 }
 
-// ── Enrollment Management ─────────────────────────────────────────────────────
+// ── Academic Standing ─────────────────────────────────────────────────────────
 
-async fn create_enrollment(
+/// GET /api/academic-standing/rules
+async fn get_standing_rules(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    Ok(HttpResponse::Ok().json(standing_rules(&data.db, &claims.campus_id).await?))
+}
+
+/// PUT /api/academic-standing/rules
+async fn update_standing_rules(
     data: web::Data<AppState>,
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    let enrollment_data: EnrollmentRequest = serde_json::from_value(body.into_inner())
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can change standing rules".to_string()));
+    }
+
+    let rules_data: StandingRulesRequest = serde_json::from_value(body.into_inner())
         .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
 
-    let student_id = require_field(&enrollment_data.student_id, "student_id")?;
-    let course_code = require_field(&enrollment_data.course_code, "course_code")?;
-    let semester = require_field(&enrollment_data.semester, "semester")?;
+    let mut rules = standing_rules(&data.db, &claims.campus_id).await?;
+    if let Some(value) = rules_data.warning_cgpa {
+        rules.warning_cgpa = value;
+    }
+    if let Some(value) = rules_data.warning_failed_credits {
+        rules.warning_failed_credits = value;
+    }
+    if let Some(value) = rules_data.probation_cgpa {
+        rules.probation_cgpa = value;
+    }
+    if let Some(value) = rules_data.probation_failed_credits {
+        rules.probation_failed_credits = value;
+    }
 
-    let collection: Collection<Enrollment> = data.db.collection("enrollments");
+    for cgpa in [rules.warning_cgpa, rules.probation_cgpa] {
+        if !(0.0..=10.0).contains(&cgpa) {
+            return Err(AppError::BadRequest("CGPA cutoffs must be between 0 and 10".to_string()));
+        }
+    }
+    if rules.warning_failed_credits < 1 || rules.probation_failed_credits < 1 {
+        return Err(AppError::BadRequest("Failed credit limits must be at least 1".to_string()));
+    }
+    if rules.probation_cgpa > rules.warning_cgpa || rules.probation_failed_credits < rules.warning_failed_credits {
+        return Err(AppError::BadRequest("Probation rules must be stricter than warning rules".to_string()));
+    }
 
-    let existing = collection
-        .find_one(doc! {
-            "student_id": student_id,
-            "course_code": course_code,
-            "campus_id": &claims.campus_id
-        }, None)
+    rules.id = None;
+    rules.updated_by = Some(claims.sub);
+    rules.updated_at = Utc::now();
+    data.db
+        .collection::<StandingRules>("standing_rules")
+        .replace_one(
+            doc! { "campus_id": &claims.campus_id },
+            &rules,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
         .await
-        .context("Failed to check existing enrollment")?;
+        .context("Failed to save standing rules")?;
 
-    // Option::is_some() — if already enrolled, reject with 400
-    if existing.is_some() {
-        return Err(AppError::BadRequest(
-            "Student already enrolled in this course".to_string(),
-        ));
-    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Standing rules updated successfully",
+        "rules": rules
+    })))
+}
 
-    let new_enrollment = Enrollment {
-        id: None,
-        student_id: student_id.to_string(),
-        course_code: course_code.to_string(),
-        semester: semester.to_string(),
-        campus_id: claims.campus_id,
-        enrolled_at: Utc::now(),
-    };
+/// POST /api/academic-standing/evaluate
+/// Re-evaluates every student with grades on the campus.
+async fn evaluate_academic_standing(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    collection
-        .insert_one(new_enrollment, None)
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can evaluate academic standing".to_string()));
+    }
+
+    let rules = standing_rules(&data.db, &claims.campus_id).await?;
+    let student_ids = data
+        .db
+        .collection::<Grade>("grades")
+        .distinct("student_id", doc! { "campus_id": &claims.campus_id }, None)
         .await
-        .context("Failed to insert enrollment")?;
+        .context("Failed to list graded students")?;
+
+    let (mut good, mut warning, mut probation) = (0, 0, 0);
+    for student_id in student_ids.iter().filter_map(|id| id.as_str()) {
+        let record = record_academic_standing(&data.db, &claims.campus_id, student_id, &rules).await?;
+        match record.standing.as_str() {
+            "probation" => probation += 1,
+            "warning" => warning += 1,
+            _ => good += 1,
+        }
+    }
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Enrollment created successfully" })))
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Evaluated {} students", good + warning + probation),
+        "good": good,
+        "warning": warning,
+        "probation": probation
+    })))
 }
 
-async fn get_enrollments(
+/// GET /api/academic-standing
+/// Latest recorded standings, most at-risk first, for the dean's office.
+async fn get_academic_standings(
     data: web::Data<AppState>,
     req: HttpRequest,
-    query: web::Query<EnrollmentFilter>,
+    query: web::Query<AcademicStandingFilter>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
-    let collection: Collection<Enrollment> = data.db.collection("enrollments");
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    // Build filter — always scope to campus_id, optionally filter by semester/course
-    let mut filter = doc! { "campus_id": &claims.campus_id };
-    if let Some(semester) = &query.semester {
-        if !semester.trim().is_empty() {
-            filter.insert("semester", semester.as_str());
-        }
+    if claims.role == "student" {
+        return Err(AppError::Forbidden("Access denied".to_string()));
     }
-    if let Some(course_code) = &query.course_code {
-        if !course_code.trim().is_empty() {
-            filter.insert("course_code", course_code.as_str());
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(standing) = query.standing.as_deref().filter(|s| !s.trim().is_empty()) {
+        if !["good", "warning", "probation"].contains(&standing) {
+            return Err(AppError::BadRequest("standing must be 'good', 'warning' or 'probation'".to_string()));
         }
+        filter.insert("standing", standing);
     }
 
+    let collection: Collection<AcademicStanding> = data.db.collection("academic_standings");
     let pagination = PaginationParams { page: query.page, limit: query.limit };
     let total = collection
         .count_documents(filter.clone(), None)
         .await
-        .context("Failed to count enrollments")?;
+        .context("Failed to count academic standings")?;
 
     let options = FindOptions::builder()
         .skip(pagination.skip())
         .limit(pagination.limit() as i64)
-        .sort(doc! { "enrolled_at": -1 })
+        .sort(doc! { "cgpa": 1, "failed_credits": -1, "student_id": 1 })
         .build();
-
     let mut cursor = collection
         .find(filter, options)
         .await
-        .context("Failed to query enrollments")?;
+        .context("Failed to query academic standings")?;
 
-    let mut enrollments = Vec::new();
+    let mut standings = Vec::new();
     use futures::stream::StreamExt;
     while let Some(result) = cursor.next().await {
-        let enrollment = result.context("Failed to read enrollment from cursor")?;
-        enrollments.push(enrollment);
+        standings.push(result.context("Failed to read academic standing")?);
     }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "data": enrollments,
+        "data": standings,
         "pagination": {
             "page": pagination.page(),
             "limit": pagination.limit(),
@@ -773,350 +7657,713 @@ async fn get_enrollments(
     })))
 }
 
-// ── Attendance Management ─────────────────────────────────────────────────────
+/// GET /api/students/{id}/academic-standing
+/// Evaluates the student's standing afresh and records it.
+async fn get_student_academic_standing(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let student_id = path.into_inner();
 
-async fn mark_attendance(
+    if claims.role == "student" && claims.sub != student_id {
+        return Err(AppError::Forbidden(
+            "Access denied: You can only view your own academic standing".to_string(),
+        ));
+    }
+
+    let rules = standing_rules(&data.db, &claims.campus_id).await?;
+    let record = record_academic_standing(&data.db, &claims.campus_id, &student_id, &rules).await?;
+
+    Ok(HttpResponse::Ok().json(record))
+}
+
+// ── Elective Allocation ───────────────────────────────────────────────────────
+
+/// POST /api/electives/rounds
+async fn create_elective_round(
     data: web::Data<AppState>,
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    let attendance_data: AttendanceRequest = serde_json::from_value(body.into_inner())
-        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can open elective rounds".to_string()));
+    }
 
-    let student_id = require_field(&attendance_data.student_id, "student_id")?;
-    let course_code = require_field(&attendance_data.course_code, "course_code")?;
-    let date = require_field(&attendance_data.date, "date")?;
-    let status = require_field(&attendance_data.status, "status")?;
+    let round_data: ElectiveRoundRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
 
-    validate_attendance_status(status)?;
+    let name = require_field(&round_data.name, "name")?;
+    let semester = require_field(&round_data.semester, "semester")?;
+    let opens_at = require_field(&round_data.opens_at, "opens_at")?;
+    let closes_at = require_field(&round_data.closes_at, "closes_at")?;
+    let courses = round_data
+        .courses
+        .ok_or_else(|| AppError::BadRequest("'courses' is required".to_string()))?;
+    let picks = round_data.picks.unwrap_or(1);
+    let strategy = round_data.strategy.as_deref().unwrap_or("lottery").trim().to_lowercase();
+
+    let opens_at = DateTime::parse_from_rfc3339(opens_at)
+        .map_err(|_| AppError::BadRequest("'opens_at' must be an RFC 3339 timestamp".to_string()))?
+        .with_timezone(&Utc);
+    let closes_at = DateTime::parse_from_rfc3339(closes_at)
+        .map_err(|_| AppError::BadRequest("'closes_at' must be an RFC 3339 timestamp".to_string()))?
+        .with_timezone(&Utc);
+    if closes_at <= opens_at {
+        return Err(AppError::BadRequest("closes_at must be after opens_at".to_string()));
+    }
+    if strategy != "lottery" && strategy != "cgpa" {
+        return Err(AppError::BadRequest("strategy must be 'lottery' or 'cgpa'".to_string()));
+    }
 
-    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let mut codes: Vec<String> = Vec::new();
+    for code in courses {
+        let code = code.trim().to_string();
+        if !code.is_empty() && !codes.contains(&code) {
+            codes.push(code);
+        }
+    }
+    if codes.len() < 2 {
+        return Err(AppError::BadRequest("An elective round needs at least two courses".to_string()));
+    }
+    if picks < 1 || picks as usize >= codes.len() {
+        return Err(AppError::BadRequest(
+            "picks must be at least 1 and fewer than the number of courses".to_string(),
+        ));
+    }
 
-    let new_attendance = Attendance {
-        id: None,
-        student_id: student_id.to_string(),
-        course_code: course_code.to_string(),
-        date: date.to_string(),
-        status: status.to_string(),
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
-    };
+    let courses: Collection<Course> = data.db.collection("courses");
+    for code in &codes {
+        courses
+            .find_one(doc! { "course_code": code, "campus_id": &claims.campus_id }, None)
+            .await
+            .context("Failed to fetch course")?
+            .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", code)))?;
+    }
 
-    collection
-        .insert_one(new_attendance, None)
+    let collection: Collection<ElectiveRound> = data.db.collection("elective_rounds");
+    let result = collection
+        .insert_one(ElectiveRound {
+            id: None,
+            name: name.to_string(),
+            semester: semester.to_string(),
+            courses: codes,
+            picks,
+            opens_at,
+            closes_at,
+            strategy,
+            status: "open".to_string(),
+            campus_id: claims.campus_id,
+            created_by: claims.sub,
+            created_at: Utc::now(),
+            allocated_at: None,
+        }, None)
         .await
-        .context("Failed to insert attendance record")?;
+        .context("Failed to insert elective round")?;
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Attendance marked successfully" })))
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Elective round created successfully",
+        "id": result.inserted_id
+    })))
 }
 
-async fn get_attendance(
+/// GET /api/electives/rounds
+async fn get_elective_rounds(
     data: web::Data<AppState>,
     req: HttpRequest,
-    query: web::Query<AttendanceFilter>,
+    query: web::Query<ElectiveRoundFilter>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
-    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    // Build filter — always scope to campus_id, optionally filter by status/course/student
     let mut filter = doc! { "campus_id": &claims.campus_id };
-    if let Some(status) = &query.status {
-        if !status.trim().is_empty() {
-            validate_attendance_status(status)?;
-            filter.insert("status", status.as_str());
-        }
-    }
-    if let Some(course_code) = &query.course_code {
-        if !course_code.trim().is_empty() {
-            filter.insert("course_code", course_code.as_str());
-        }
+    if let Some(semester) = query.semester.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("semester", semester);
     }
-    if let Some(student_id) = &query.student_id {
-        if !student_id.trim().is_empty() {
-            filter.insert("student_id", student_id.as_str());
-        }
+    if let Some(status) = query.status.as_deref().filter(|s| !s.trim().is_empty()) {
+        filter.insert("status", status);
     }
 
-    let pagination = PaginationParams { page: query.page, limit: query.limit };
-    let total = collection
-        .count_documents(filter.clone(), None)
-        .await
-        .context("Failed to count attendance records")?;
-
-    let options = FindOptions::builder()
-        .skip(pagination.skip())
-        .limit(pagination.limit() as i64)
-        .sort(doc! { "created_at": -1 })
-        .build();
-
+    let collection: Collection<ElectiveRound> = data.db.collection("elective_rounds");
+    let options = FindOptions::builder().sort(doc! { "opens_at": -1 }).build();
     let mut cursor = collection
         .find(filter, options)
         .await
-        .context("Failed to query attendance")?;
+        .context("Failed to query elective rounds")?;
 
-    let mut records = Vec::new();
+    let mut rounds = Vec::new();
     use futures::stream::StreamExt;
     while let Some(result) = cursor.next().await {
-        let record = result.context("Failed to read attendance record from cursor")?;
-        records.push(record);
+        rounds.push(result.context("Failed to read elective round")?);
     }
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "data": records,
-        "pagination": {
-            "page": pagination.page(),
-            "limit": pagination.limit(),
-            "total": total,
-            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
-        }
-    })))
+    Ok(HttpResponse::Ok().json(rounds))
 }
 
-// ── Student Dashboard Endpoints ───────────────────────────────────────────────
+async fn find_elective_round(
+    db: &mongodb::Database,
+    campus_id: &str,
+    id: &str,
+) -> Result<ElectiveRound, AppError> {
+    let object_id = ObjectId::parse_str(id)
+        .map_err(|_| AppError::BadRequest("Invalid round ID".to_string()))?;
+    let round = db
+        .collection::<ElectiveRound>("elective_rounds")
+        .find_one(doc! { "_id": object_id, "campus_id": campus_id }, None)
+        .await
+        .context("Failed to fetch elective round")?
+        .ok_or_else(|| AppError::NotFound("Elective round not found".to_string()))?;
+    Ok(round)
+}
 
-async fn get_student_attendance(
+/// PUT /api/electives/rounds/{id}/preferences
+/// A student submits or replaces their ranking while the round is open.
+async fn submit_elective_preferences(
     data: web::Data<AppState>,
     req: HttpRequest,
     path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
-    let student_id = path.into_inner();
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    if claims.role == "student" && claims.sub != student_id {
-        return Err(AppError::Forbidden(
-            "Access denied: You can only view your own attendance".to_string(),
-        ));
+    if claims.role != "student" {
+        return Err(AppError::Forbidden("Only students can submit elective preferences".to_string()));
     }
 
-    let collection: Collection<Attendance> = data.db.collection("attendance");
-
-    let mut cursor = collection
-        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
-        .await
-        .context("Failed to query student attendance")?;
-
-    let mut records = Vec::new();
-    use futures::stream::StreamExt;
-    while let Some(result) = cursor.next().await {
-        let record = result.context("Failed to read attendance record")?;
-        records.push(record);
+    let preference_data: ElectivePreferenceRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let preferences = preference_data
+        .preferences
+        .ok_or_else(|| AppError::BadRequest("'preferences' is required".to_string()))?;
+
+    let round = find_elective_round(&data.db, &claims.campus_id, &path.into_inner()).await?;
+    let now = Utc::now();
+    if round.status != "open" || now < round.opens_at || now > round.closes_at {
+        return Err(AppError::BadRequest(format!(
+            "Preferences are accepted from {} to {}",
+            round.opens_at.to_rfc3339(),
+            round.closes_at.to_rfc3339()
+        )));
     }
 
-    let mut subject_stats: std::collections::HashMap<String, (i32, i32)> = std::collections::HashMap::new();
-    for record in &records {
-        let entry = subject_stats.entry(record.course_code.clone()).or_insert((0, 0));
-        entry.1 += 1;
-        if record.status == "present" || record.status == "late" {
-            entry.0 += 1;
+    if preferences.is_empty() {
+        return Err(AppError::BadRequest("'preferences' must not be empty".to_string()));
+    }
+    for (i, code) in preferences.iter().enumerate() {
+        if !round.courses.contains(code) {
+            return Err(AppError::BadRequest(format!(
+                "'{}' is not offered in this round; choose from: {}",
+                code,
+                round.courses.join(", ")
+            )));
+        }
+        if preferences[..i].contains(code) {
+            return Err(AppError::BadRequest(format!("'{}' is ranked more than once", code)));
         }
     }
 
-    let subject_attendance: Vec<serde_json::Value> = subject_stats.iter().map(|(subject, (attended, total))| {
-        let percentage = if *total > 0 { (*attended as f64 / *total as f64) * 100.0 } else { 0.0 };
-        serde_json::json!({
-            "course_code": subject,
-            "classes_attended": attended,
-            "total_classes": total,
-            "attendance_percentage": format!("{:.1}", percentage)
-        })
-    }).collect();
-
-    let total_attended: i32 = subject_stats.values().map(|(a, _)| a).sum();
-    let total_classes: i32 = subject_stats.values().map(|(_, t)| t).sum();
-    let overall_percentage = if total_classes > 0 {
-        (total_attended as f64 / total_classes as f64) * 100.0
-    } else {
-        0.0
+    let round_id = round.id.context("Elective round has no id")?;
+    let preference = ElectivePreference {
+        id: None,
+        round_id,
+        student_id: claims.sub.clone(),
+        preferences,
+        allocated: Vec::new(),
+        campus_id: claims.campus_id.clone(),
+        submitted_at: now,
     };
 
+    data.db
+        .collection::<ElectivePreference>("elective_preferences")
+        .replace_one(
+            doc! { "round_id": round_id, "student_id": &claims.sub },
+            &preference,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .context("Failed to save elective preferences")?;
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "student_id": student_id,
-        "overall_attendance_percentage": format!("{:.1}", overall_percentage),
-        "total_classes_attended": total_attended,
-        "total_classes": total_classes,
-        "subject_wise_attendance": subject_attendance,
-        "records": records
+        "message": "Preferences saved successfully",
+        "preferences": preference.preferences
+    })))
+}
+
+/// GET /api/electives/rounds/{id}/preferences
+/// Staff see every submission; students see only their own.
+async fn get_elective_preferences(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let round = find_elective_round(&data.db, &claims.campus_id, &path.into_inner()).await?;
+
+    let mut filter = doc! { "round_id": round.id, "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    }
+
+    let collection: Collection<ElectivePreference> = data.db.collection("elective_preferences");
+    let options = FindOptions::builder().sort(doc! { "student_id": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query elective preferences")?;
+
+    let mut preferences = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        preferences.push(result.context("Failed to read elective preference")?);
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "round": round,
+        "preferences": preferences
     })))
 }
 
-async fn get_student_enrollments(
+/// Highest CGPA first. The sort is stable, so students with equal CGPAs keep
+/// the lottery order they arrive in.
+fn order_by_cgpa(mut ranked: Vec<(f64, ElectivePreference)>) -> Vec<ElectivePreference> {
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranked.into_iter().map(|(_, p)| p).collect()
+}
+
+/// A student's ranked courses still worth trying this pass: not yet
+/// allocated to them, not full, and not ruled out for them.
+fn open_choices(preference: &ElectivePreference, full: &[String], ruled_out: &[&str]) -> Vec<String> {
+    preference
+        .preferences
+        .iter()
+        .filter(|code| {
+            !preference.allocated.contains(code) && !full.contains(code) && !ruled_out.contains(&code.as_str())
+        })
+        .cloned()
+        .collect()
+}
+
+/// POST /api/electives/rounds/{id}/allocate
+/// Runs after the window closes. Students are ordered by lottery or by CGPA,
+/// then take turns: each pass gives every student, in order, their
+/// highest-ranked course that still has a seat, until everyone has `picks`
+/// electives or their list runs out.
+async fn allocate_electives(
     data: web::Data<AppState>,
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
-    let student_id = path.into_inner();
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
-    if claims.role == "student" && claims.sub != student_id {
-        return Err(AppError::Forbidden(
-            "Access denied: You can only view your own enrollments".to_string(),
-        ));
+    if claims.role != "admin" {
+        return Err(AppError::Forbidden("Only admins can allocate electives".to_string()));
     }
 
-    let enrollment_collection: Collection<Enrollment> = data.db.collection("enrollments");
-    let course_collection: Collection<Course> = data.db.collection("courses");
-    let attendance_collection: Collection<Attendance> = data.db.collection("attendance");
+    let round = find_elective_round(&data.db, &claims.campus_id, &path.into_inner()).await?;
+    if Utc::now() <= round.closes_at {
+        return Err(AppError::BadRequest(format!(
+            "The preference window is open until {}",
+            round.closes_at.to_rfc3339()
+        )));
+    }
 
-    let mut cursor = enrollment_collection
-        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+    // Claiming the round first stops two allocations running at once
+    let rounds: Collection<ElectiveRound> = data.db.collection("elective_rounds");
+    let claimed = rounds
+        .update_one(
+            doc! { "_id": round.id, "status": "open" },
+            doc! { "$set": { "status": "allocated", "allocated_at": Utc::now().to_rfc3339() } },
+            None,
+        )
         .await
-        .context("Failed to query enrollments")?;
+        .context("Failed to claim elective round")?;
+    if claimed.modified_count == 0 {
+        return Err(AppError::Conflict("Electives for this round have already been allocated".to_string()));
+    }
 
-    let mut enrollments = Vec::new();
+    let preference_collection: Collection<ElectivePreference> = data.db.collection("elective_preferences");
+    let mut cursor = preference_collection
+        .find(doc! { "round_id": round.id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to query elective preferences")?;
+    let mut preferences = Vec::new();
     use futures::stream::StreamExt;
     while let Some(result) = cursor.next().await {
-        let enrollment = result.context("Failed to read enrollment")?;
-        enrollments.push(enrollment);
+        preferences.push(result.context("Failed to read elective preference")?);
     }
 
-    let mut classes_info = Vec::new();
-    for enrollment in &enrollments {
-        // Returns Option<Course> — None is handled gracefully with unwrap_or_default
-        let course = course_collection
-            .find_one(doc! { "course_code": &enrollment.course_code, "campus_id": &claims.campus_id }, None)
-            .await
-            .context("Failed to fetch course details")?;
+    {
+        use rand::seq::SliceRandom;
+        preferences.shuffle(&mut rand::thread_rng());
+    }
+    if round.strategy == "cgpa" {
+        let mut ranked = Vec::with_capacity(preferences.len());
+        for preference in preferences {
+            let card = compute_grade_card(&data.db, &claims.campus_id, &preference.student_id, true).await?;
+            ranked.push((card.cgpa, preference));
+        }
+        preferences = order_by_cgpa(ranked);
+    }
 
-        let attended = attendance_collection
-            .count_documents(doc! {
-                "student_id": &student_id,
-                "course_code": &enrollment.course_code,
-                "status": { "$in": ["present", "late"] },
-                "campus_id": &claims.campus_id
-            }, None)
+    let courses: Collection<Course> = data.db.collection("courses");
+    let mut offered = Vec::new();
+    for code in &round.courses {
+        if let Some(course) = courses
+            .find_one(doc! { "course_code": code, "campus_id": &claims.campus_id }, None)
             .await
-            .context("Failed to count attended classes")?;
+            .context("Failed to fetch course")?
+        {
+            offered.push(course);
+        }
+    }
 
-        let total = attendance_collection
-            .count_documents(doc! {
-                "student_id": &student_id,
-                "course_code": &enrollment.course_code,
-                "campus_id": &claims.campus_id
-            }, None)
-            .await
-            .context("Failed to count total classes")?;
+    let enrollments: Collection<Enrollment> = data.db.collection("enrollments");
+    let mut full: Vec<String> = Vec::new();
+    // Courses a student cannot take (already enrolled or missing prerequisites)
+    let mut ineligible: Vec<(usize, String)> = Vec::new();
 
-        classes_info.push(serde_json::json!({
-            "course_code": enrollment.course_code,
-            // Option<Course> — use map to extract name, fall back to empty string
-            "course_name": course.map(|c| c.course_name).unwrap_or_default(),
-            "semester": enrollment.semester,
-            "enrolled_at": enrollment.enrolled_at,
-            "classes_attended": attended,
-            "classes_missed": total - attended,
-            "total_classes": total
-        }));
+    for _ in 0..round.picks {
+        for (i, preference) in preferences.iter_mut().enumerate() {
+            if preference.allocated.len() >= round.picks as usize {
+                continue;
+            }
+            let ruled_out: Vec<&str> = ineligible
+                .iter()
+                .filter(|(s, _)| *s == i)
+                .map(|(_, c)| c.as_str())
+                .collect();
+            for code in open_choices(preference, &full, &ruled_out) {
+                let Some(course) = offered.iter().find(|c| c.course_code == code) else {
+                    continue;
+                };
+
+                let already_enrolled = enrollments
+                    .find_one(doc! {
+                        "student_id": &preference.student_id,
+                        "course_code": &code,
+                        "campus_id": &claims.campus_id
+                    }, None)
+                    .await
+                    .context("Failed to check existing enrollment")?
+                    .is_some();
+                let missing = missing_prerequisites(
+                    &data.db,
+                    &claims.campus_id,
+                    &preference.student_id,
+                    &round.semester,
+                    &course.prerequisites,
+                )
+                .await?;
+                if already_enrolled || !missing.is_empty() {
+                    ineligible.push((i, code));
+                    continue;
+                }
+
+                if enroll_with_seat(&data.db, &claims.campus_id, course, &round.semester, &preference.student_id).await? {
+                    preference.allocated.push(code);
+                    break;
+                }
+                full.push(code);
+            }
+        }
+    }
+
+    for preference in &preferences {
+        preference_collection
+            .update_one(
+                doc! { "_id": preference.id },
+                doc! { "$set": { "allocated": &preference.allocated } },
+                None,
+            )
+            .await
+            .context("Failed to record allocation")?;
     }
 
+    let allocations: Vec<serde_json::Value> = preferences
+        .iter()
+        .map(|p| serde_json::json!({ "student_id": p.student_id, "allocated": p.allocated }))
+        .collect();
+    let unallocated: Vec<&str> = preferences
+        .iter()
+        .filter(|p| p.allocated.is_empty())
+        .map(|p| p.student_id.as_str())
+        .collect();
+
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "student_id": student_id,
-        "enrollments": classes_info
+        "message": format!("Allocated electives to {} of {} students", preferences.len() - unallocated.len(), preferences.len()),
+        "strategy": round.strategy,
+        "allocations": allocations,
+        "unallocated": unallocated
     })))
 }
 
-// ── Exam Results ──────────────────────────────────────────────────────────────
+// ── Course Materials ──────────────────────────────────────────────────────────
 
-async fn create_result(
+/// Largest file accepted by the materials upload endpoint.
+const MATERIAL_MAX_BYTES: usize = 25 * 1024 * 1024;
+/// Largest accepted value for a plain-text multipart field.
+const MATERIAL_MAX_FIELD_BYTES: usize = 4 * 1024;
+
+fn materials_bucket(db: &mongodb::Database) -> mongodb::gridfs::GridFsBucket {
+    db.gridfs_bucket(
+        mongodb::options::GridFsBucketOptions::builder()
+            .bucket_name("materials".to_string())
+            .build(),
+    )
+}
+
+/// Students may only see a course's materials while enrolled in it.
+async fn ensure_can_view_materials(
+    db: &mongodb::Database,
+    claims: &Claims,
+    course_code: &str,
+) -> Result<(), AppError> {
+    if claims.role != "student" {
+        return Ok(());
+    }
+    let enrollments: Collection<Enrollment> = db.collection("enrollments");
+    enrollments
+        .find_one(doc! {
+            "student_id": &claims.sub,
+            "course_code": course_code,
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .context("Failed to check enrollment")?
+        .ok_or_else(|| AppError::Forbidden("You are not enrolled in this course".to_string()))?;
+    Ok(())
+}
+
+/// POST /api/courses/{code}/materials
+/// multipart/form-data with a `file` part and `title`, `kind`, optional
+/// `description` and `visibility` text parts.
+async fn upload_course_material(
     data: web::Data<AppState>,
     req: HttpRequest,
-    body: web::Json<serde_json::Value>,
+    path: web::Path<String>,
+    mut payload: actix_multipart::Multipart,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
-        return Err(AppError::Forbidden("Only teachers can add exam results".to_string()));
+        return Err(AppError::Forbidden("Only teachers can upload course materials".to_string()));
     }
 
-    let result_data: ExamResultRequest = serde_json::from_value(body.into_inner())
-        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let course_code = path.into_inner();
+    let courses: Collection<Course> = data.db.collection("courses");
+    courses
+        .find_one(doc! { "course_code": &course_code, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course")?
+        .ok_or_else(|| AppError::NotFound(format!("Course '{}' not found", course_code)))?;
 
-    let student_id = require_field(&result_data.student_id, "student_id")?;
-    let course_code = require_field(&result_data.course_code, "course_code")?;
-    let exam_type = require_field(&result_data.exam_type, "exam_type")?;
-    let semester = require_field(&result_data.semester, "semester")?;
-    let marks_obtained = require_f64(result_data.marks_obtained, "marks_obtained")?;
-    let total_marks = require_f64(result_data.total_marks, "total_marks")?;
+    use futures::io::AsyncWriteExt;
+    use futures::stream::StreamExt;
 
-    validate_exam_type(exam_type)?;
+    let bucket = materials_bucket(&data.db);
+    let mut fields: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut upload: Option<(ObjectId, String, String, usize)> = None;
 
-    if total_marks <= 0.0 {
-        return Err(AppError::BadRequest("total_marks must be greater than 0".to_string()));
-    }
-    if marks_obtained < 0.0 || marks_obtained > total_marks {
-        return Err(AppError::BadRequest(
-            "marks_obtained must be between 0 and total_marks".to_string(),
-        ));
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(|e| AppError::BadRequest(format!("Invalid multipart body: {}", e)))?;
+        let name = field.name().unwrap_or_default().to_string();
+
+        if name == "file" {
+            if upload.is_some() {
+                return Err(AppError::BadRequest("Only one file can be uploaded at a time".to_string()));
+            }
+            let filename = field
+                .content_disposition()
+                .and_then(|cd| cd.get_filename())
+                .map(|f| f.rsplit(['/', '\\']).next().unwrap_or(f).to_string())
+                .filter(|f| !f.trim().is_empty())
+                .ok_or_else(|| AppError::BadRequest("The file part needs a filename".to_string()))?;
+            let content_type = field
+                .content_type()
+                .map(|m| m.to_string())
+                .unwrap_or_else(|| "application/octet-stream".to_string());
+
+            let mut stream = bucket.open_upload_stream(&filename, None);
+            let file_id = stream.id().as_object_id().context("GridFS file id is not an ObjectId")?;
+            let mut size = 0;
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(|e| AppError::BadRequest(format!("Invalid multipart body: {}", e)))?;
+                size += chunk.len();
+                if size > MATERIAL_MAX_BYTES {
+                    stream.abort().await.context("Failed to abort upload")?;
+                    return Err(AppError::BadRequest(format!(
+                        "File exceeds the {} MB limit",
+                        MATERIAL_MAX_BYTES / (1024 * 1024)
+                    )));
+                }
+                stream.write_all(&chunk).await.context("Failed to store file chunk")?;
+            }
+            stream.close().await.context("Failed to finish upload")?;
+            upload = Some((file_id, filename, content_type, size));
+        } else {
+            let mut value = Vec::new();
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(|e| AppError::BadRequest(format!("Invalid multipart body: {}", e)))?;
+                value.extend_from_slice(&chunk);
+                if value.len() > MATERIAL_MAX_FIELD_BYTES {
+                    return Err(AppError::BadRequest(format!("Field '{}' is too long", name)));
+                }
+            }
+            let value = String::from_utf8(value)
+                .map_err(|_| AppError::BadRequest(format!("Field '{}' must be UTF-8 text", name)))?;
+            fields.insert(name, value);
+        }
     }
 
-    let collection: Collection<ExamResult> = data.db.collection("exam_results");
+    let (file_id, filename, content_type, size) =
+        upload.ok_or_else(|| AppError::BadRequest("'file' is required".to_string()))?;
+
+    // Validate the text fields only now that the whole body has been read,
+    // and drop the stored file if they are rejected.
+    let validated = (|| {
+        let title = require_field(&fields.get("title").cloned(), "title")?.to_string();
+        let kind = require_field(&fields.get("kind").cloned(), "kind")?.to_string();
+        let visibility = fields.get("visibility").cloned().unwrap_or_else(|| "enrolled".to_string());
+        validate_material_kind(&kind)?;
+        validate_material_visibility(&visibility)?;
+        Ok::<_, AppError>((title, kind, visibility))
+    })();
+    let (title, kind, visibility) = match validated {
+        Ok(values) => values,
+        Err(e) => {
+            bucket.delete(file_id.into()).await.context("Failed to discard upload")?;
+            return Err(e);
+        }
+    };
 
-    let new_result = ExamResult {
+    let material = CourseMaterial {
         id: None,
-        student_id: student_id.to_string(),
-        course_code: course_code.to_string(),
-        exam_type: exam_type.to_string(),
-        marks_obtained,
-        total_marks,
-        grade: calculate_grade(marks_obtained, total_marks),
-        semester: semester.to_string(),
+        course_code,
+        kind,
+        title,
+        description: fields.get("description").cloned().unwrap_or_default(),
+        file_id,
+        filename,
+        content_type,
+        size: size as i64,
+        visibility,
+        uploaded_by: claims.sub,
         campus_id: claims.campus_id,
-        created_at: Utc::now(),
+        uploaded_at: Utc::now(),
     };
 
-    collection
-        .insert_one(new_result, None)
+    let collection: Collection<CourseMaterial> = data.db.collection("course_materials");
+    let result = collection
+        .insert_one(&material, None)
         .await
-        .context("Failed to insert exam result")?;
+        .context("Failed to insert course material")?;
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Exam result added successfully" })))
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Material uploaded successfully",
+        "id": result.inserted_id
+    })))
 }
 
-async fn get_student_results(
+/// GET /api/courses/{code}/materials
+async fn get_course_materials(
     data: web::Data<AppState>,
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
-    let student_id = path.into_inner();
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+    let course_code = path.into_inner();
 
-    if claims.role == "student" && claims.sub != student_id {
-        return Err(AppError::Forbidden(
-            "Access denied: You can only view your own results".to_string(),
-        ));
-    }
+    ensure_can_view_materials(&data.db, &claims, &course_code).await?;
 
-    let collection: Collection<ExamResult> = data.db.collection("exam_results");
+    let mut filter = doc! { "course_code": &course_code, "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("visibility", "enrolled");
+    }
 
+    let collection: Collection<CourseMaterial> = data.db.collection("course_materials");
+    let options = FindOptions::builder().sort(doc! { "kind": -1, "uploaded_at": 1 }).build();
     let mut cursor = collection
-        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .find(filter, options)
         .await
-        .context("Failed to query exam results")?;
+        .context("Failed to query course materials")?;
 
-    let mut results = Vec::new();
+    let mut materials = Vec::new();
     use futures::stream::StreamExt;
     while let Some(result) = cursor.next().await {
-        let r = result.context("Failed to read exam result")?;
-        results.push(r);
+        materials.push(result.context("Failed to read course material")?);
     }
 
-    let total_marks: f64 = results.iter().map(|r| r.marks_obtained).sum();
-    let total_possible: f64 = results.iter().map(|r| r.total_marks).sum();
-    let overall_percentage = if total_possible > 0.0 {
-        (total_marks / total_possible) * 100.0
-    } else {
-        0.0
-    };
+    Ok(HttpResponse::Ok().json(materials))
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "student_id": student_id,
-        "results": results,
-        "overall_marks_obtained": total_marks,
-        "overall_total_marks": total_possible,
-        "overall_percentage": format!("{:.1}", overall_percentage),
-        "overall_grade": calculate_grade(total_marks, total_possible)
-    })))
+/// GET /api/materials/{id}/download
+async fn download_course_material(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    let material_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid material ID format".to_string()))?;
+
+    let collection: Collection<CourseMaterial> = data.db.collection("course_materials");
+    let material = collection
+        .find_one(doc! { "_id": material_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to fetch course material")?
+        .ok_or_else(|| AppError::NotFound("Material not found".to_string()))?;
+
+    if claims.role == "student" && material.visibility != "enrolled" {
+        return Err(AppError::NotFound("Material not found".to_string()));
+    }
+    ensure_can_view_materials(&data.db, &claims, &material.course_code).await?;
+
+    let mut bytes = Vec::with_capacity(material.size.max(0) as usize);
+    materials_bucket(&data.db)
+        .download_to_futures_0_3_writer(material.file_id.into(), &mut bytes)
+        .await
+        .context("Failed to read material file")?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(material.content_type.as_str())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", material.filename.replace('"', "")),
+        ))
+        .body(bytes))
+}
+
+/// DELETE /api/materials/{id}
+async fn delete_course_material(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can delete course materials".to_string()));
+    }
+
+    let material_id = ObjectId::parse_str(path.into_inner())
+        .map_err(|_| AppError::BadRequest("Invalid material ID format".to_string()))?;
+
+    let collection: Collection<CourseMaterial> = data.db.collection("course_materials");
+    let material = collection
+        .find_one_and_delete(doc! { "_id": material_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .context("Failed to delete course material")?
+        .ok_or_else(|| AppError::NotFound("Material not found".to_string()))?;
+
+    materials_bucket(&data.db)
+        .delete(material.file_id.into())
+        .await
+        .context("Failed to delete material file")?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Material deleted successfully" })))
 }
 
 // ── Batch Management ──────────────────────────────────────────────────────────
@@ -1126,7 +8373,7 @@ async fn create_batch(
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
         return Err(AppError::Forbidden("Only teachers can manage batches".to_string()));
@@ -1165,7 +8412,7 @@ async fn get_batches(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
     let collection: Collection<Batch> = data.db.collection("batches");
 
     let filter = if claims.role == "teacher" {
@@ -1194,7 +8441,12 @@ async fn get_batch_students(
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    if claims.role != "teacher" && claims.role != "admin" {
+        return Err(AppError::Forbidden("Only teachers can view batch rosters".to_string()));
+    }
+
     let batch_id = path.into_inner();
     let collection: Collection<Batch> = data.db.collection("batches");
 
@@ -1217,7 +8469,7 @@ async fn add_students_to_batch(
     path: web::Path<String>,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
         return Err(AppError::Forbidden("Only teachers can manage batches".to_string()));
@@ -1257,7 +8509,7 @@ async fn mark_batch_attendance(
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
         return Err(AppError::Forbidden("Only teachers can mark attendance".to_string()));
@@ -1290,8 +8542,10 @@ async fn mark_batch_attendance(
             id: None,
             student_id: record.student_id.clone(),
             course_code: course_code.to_string(),
+            section: None,
             date: date.to_string(),
             status: record.status.clone(),
+            extra_class_id: None,
             campus_id: claims.campus_id.clone(),
             created_at: Utc::now(),
         };
@@ -1314,7 +8568,7 @@ async fn upload_note(
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
         return Err(AppError::Forbidden("Only teachers can upload notes".to_string()));
@@ -1356,7 +8610,7 @@ async fn get_notes(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
     let collection: Collection<Note> = data.db.collection("notes");
 
     let mut cursor = collection
@@ -1379,7 +8633,7 @@ async fn get_course_notes(
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
     let course_code = path.into_inner();
 
     if course_code.trim().is_empty() {
@@ -1412,7 +8666,7 @@ async fn submit_student_note(
     req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     let note_data: StudentNoteSubmissionRequest = serde_json::from_value(body.into_inner())
         .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
@@ -1452,7 +8706,7 @@ async fn get_student_submissions(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
         return Err(AppError::Forbidden(
@@ -1483,7 +8737,7 @@ async fn review_student_note(
     path: web::Path<String>,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let claims = extract_claims(&req, &data.jwt_secret)?;
+    let claims = extract_claims(&req, &data.jwt_keys)?;
 
     if claims.role != "teacher" && claims.role != "admin" {
         return Err(AppError::Forbidden("Only teachers can review notes".to_string()));
@@ -1533,6 +8787,17 @@ async fn main() -> std::io::Result<()> {
     let jwt_secret = env::var("JWT_SECRET")
         .unwrap_or_else(|_| "your-secret-key".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8081".to_string());
+    let institution_name = env::var("INSTITUTION_NAME")
+        .unwrap_or_else(|_| "CampusConnect".to_string());
+    let attendance_threshold = env::var("ATTENDANCE_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(75.0);
+    // Hours between automatic shortage scans; unset or 0 disables the job
+    let attendance_scan_hours = env::var("ATTENDANCE_SCAN_INTERVAL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
 
     println!("Starting Academics Service...");
     println!("Connecting to MongoDB: {}", mongodb_uri);
@@ -1543,10 +8808,58 @@ async fn main() -> std::io::Result<()> {
 
     let db = client.database(&database_name);
 
+    create_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+
+    let access_ttl = env::var("ACCESS_TOKEN_TTL_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(24 * 60);
+    let jwt_keys: SharedKeySet = Arc::new(RwLock::new(
+        load_jwt_keys(&db, &jwt_secret, chrono::Duration::minutes(access_ttl))
+            .await
+            .expect("Failed to load JWT keys"),
+    ));
+
+    // Pick up keys the auth service adds or retires.
+    {
+        let db = db.clone();
+        let jwt_secret = jwt_secret.clone();
+        let jwt_keys = jwt_keys.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(JWT_KEY_REFRESH_SECS));
+            loop {
+                interval.tick().await;
+                reload_jwt_keys(&db, &jwt_secret, &jwt_keys).await;
+            }
+        });
+    }
+
     println!("Connected to MongoDB");
     println!("Server starting on http://127.0.0.1:{}", port);
 
-    let app_state = web::Data::new(AppState { db, jwt_secret: jwt_secret.clone() });
+    if attendance_scan_hours > 0 {
+        let db = db.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(attendance_scan_hours * 3600));
+            loop {
+                interval.tick().await;
+                match scan_attendance_shortages(&db, None, None, attendance_threshold).await {
+                    Ok((flagged, resolved)) => info!("Attendance scan: {} flagged, {} resolved", flagged, resolved),
+                    Err(e) => warn!("Attendance scan failed: {:#}", e),
+                }
+            }
+        });
+    }
+
+    let app_state = web::Data::new(AppState {
+        db,
+        qr_key: derive_qr_key(&jwt_secret),
+        jwt_keys: jwt_keys.clone(),
+        institution_name,
+        attendance_threshold,
+    });
 
     HttpServer::new(move || {
         // Explicit CORS: only allow the Angular dev server origin.
@@ -1565,7 +8878,7 @@ async fn main() -> std::io::Result<()> {
             // Log every request: METHOD /path -> STATUS
             .wrap(RequestLogger)
             // JWT auth gate — all /api/* routes require a valid Bearer token
-            .wrap(JwtAuth { jwt_secret: jwt_secret.clone() })
+            .wrap(JwtAuth { jwt_keys: jwt_keys.clone() })
             .app_data(app_state.clone())
             .app_data(
                 web::JsonConfig::default()
@@ -1581,14 +8894,92 @@ async fn main() -> std::io::Result<()> {
             // Protected routes (JWT middleware enforces auth above)
             .route("/api/courses", web::post().to(create_course))
             .route("/api/courses", web::get().to(get_courses))
+            .route("/api/courses/{id}", web::put().to(update_course))
+            .route("/api/courses/{id}", web::delete().to(delete_course))
+            .route("/api/courses/{code}/assessment-scheme", web::put().to(put_assessment_scheme))
+            .route("/api/courses/{code}/assessment-scheme", web::get().to(get_assessment_scheme))
+            .route("/api/courses/{code}/assessments/{component}/marks", web::post().to(post_component_marks))
+            .route("/api/courses/{code}/internal-scores", web::get().to(get_internal_scores))
+            .route("/api/courses/{code}/grade-stats", web::get().to(get_grade_stats))
+            .route("/api/courses/{code}/results", web::get().to(get_result_publication))
+            .route("/api/courses/{code}/results/verify", web::post().to(verify_results))
+            .route("/api/courses/{code}/results/return", web::post().to(return_results))
+            .route("/api/courses/{code}/results/publish", web::post().to(publish_results))
+            .route("/api/grades/{id}/appeals", web::post().to(create_grade_appeal))
+            .route("/api/grade-appeals", web::get().to(get_grade_appeals))
+            .route("/api/grade-appeals/{id}/resolve", web::put().to(resolve_grade_appeal))
+            .route("/api/academic-standing", web::get().to(get_academic_standings))
+            .route("/api/academic-standing/rules", web::get().to(get_standing_rules))
+            .route("/api/academic-standing/rules", web::put().to(update_standing_rules))
+            .route("/api/academic-standing/evaluate", web::post().to(evaluate_academic_standing))
+            .route("/api/students/{id}/academic-standing", web::get().to(get_student_academic_standing))
+            .route("/api/electives/rounds", web::post().to(create_elective_round))
+            .route("/api/electives/rounds", web::get().to(get_elective_rounds))
+            .route("/api/electives/rounds/{id}/preferences", web::put().to(submit_elective_preferences))
+            .route("/api/electives/rounds/{id}/preferences", web::get().to(get_elective_preferences))
+            .route("/api/electives/rounds/{id}/allocate", web::post().to(allocate_electives))
+            .route("/api/courses/{code}/materials", web::post().to(upload_course_material))
+            .route("/api/courses/{code}/materials", web::get().to(get_course_materials))
+            .route("/api/materials/{id}/download", web::get().to(download_course_material))
+            .route("/api/materials/{id}", web::delete().to(delete_course_material))
             .route("/api/enrollments", web::post().to(create_enrollment))
             .route("/api/enrollments", web::get().to(get_enrollments))
+            .route("/api/enrollments/{id}", web::delete().to(drop_enrollment))
+            .route("/api/enrollments/{id}/section", web::put().to(set_enrollment_section))
+            .route("/api/sections", web::post().to(create_section))
+            .route("/api/sections", web::get().to(get_sections))
+            .route("/api/sections/{id}", web::get().to(get_section))
+            .route("/api/sections/{id}", web::put().to(update_section))
+            .route("/api/sections/{id}/students", web::get().to(get_section_students))
+            .route("/api/waitlist", web::get().to(get_waitlist))
+            .route("/api/students/{id}/transcript", web::get().to(get_transcript))
+            .route("/api/transcripts/verify/{code}", web::get().to(verify_transcript))
+            .route("/api/programs", web::post().to(create_program))
+            .route("/api/programs", web::get().to(get_programs))
+            .route("/api/programs/{code}", web::get().to(get_program))
+            .route("/api/students/{id}/program", web::put().to(assign_student_program))
+            .route("/api/students/{id}/degree-audit", web::get().to(get_degree_audit))
             .route("/api/attendance", web::post().to(mark_attendance))
             .route("/api/attendance", web::get().to(get_attendance))
+            .route("/api/attendance/export", web::get().to(export_attendance))
+            .route("/api/attendance/sessions", web::post().to(open_attendance_session))
+            .route("/api/attendance/sessions/{id}/qr", web::get().to(get_attendance_qr))
+            .route("/api/attendance/sessions/{id}/close", web::post().to(close_attendance_session))
+            .route("/api/attendance/checkin", web::post().to(attendance_checkin))
+            .route("/api/attendance/shortages/scan", web::post().to(scan_attendance_shortage_alerts))
+            .route("/api/alerts", web::get().to(get_alerts))
+            .route("/api/alerts/{id}/acknowledge", web::put().to(acknowledge_alert))
+            .route("/api/my/enrollments", web::get().to(get_my_enrollments))
+            .route("/api/my/attendance", web::get().to(get_my_attendance))
+            .route("/api/my/grades", web::get().to(get_my_grades))
             .route("/api/student/attendance/{student_id}", web::get().to(get_student_attendance))
             .route("/api/student/enrollments/{student_id}", web::get().to(get_student_enrollments))
             .route("/api/student/results/{student_id}", web::get().to(get_student_results))
             .route("/api/results", web::post().to(create_result))
+            .route("/api/grades", web::post().to(post_grade))
+            .route("/api/grades", web::get().to(get_grades))
+            .route("/api/grades/card/{student_id}", web::get().to(get_grade_card))
+            .route("/api/timetable", web::post().to(create_timetable_slot))
+            .route("/api/timetable", web::get().to(get_timetable))
+            .route("/api/timetable/{id}", web::delete().to(delete_timetable_slot))
+            .route("/api/timetable/student/{student_id}", web::get().to(get_student_timetable))
+            .route("/api/timetable/faculty/{faculty_id}", web::get().to(get_faculty_timetable))
+            .route("/api/extra-classes", web::post().to(create_extra_class))
+            .route("/api/extra-classes", web::get().to(get_extra_classes))
+            .route("/api/extra-classes/{id}", web::delete().to(cancel_extra_class))
+            .route("/api/extra-classes/{id}/attendance", web::post().to(mark_extra_class_attendance))
+            .route("/api/exam-halls", web::post().to(create_exam_hall))
+            .route("/api/exam-halls", web::get().to(get_exam_halls))
+            .route("/api/exams", web::post().to(create_exam))
+            .route("/api/exams", web::get().to(get_exams))
+            .route("/api/exams/hall-ticket/{student_id}", web::get().to(get_hall_ticket))
+            .route("/api/exams/{id}/seating", web::post().to(allocate_exam_seating))
+            .route("/api/exams/{id}/seating", web::get().to(get_exam_seating))
+            .route("/api/assignments", web::post().to(create_assignment))
+            .route("/api/assignments", web::get().to(get_assignments))
+            .route("/api/assignments/{id}/submissions", web::post().to(submit_assignment))
+            .route("/api/assignments/{id}/submissions", web::get().to(get_assignment_submissions))
+            .route("/api/submissions/{id}/grade", web::put().to(grade_submission))
             .route("/api/batches", web::post().to(create_batch))
             .route("/api/batches", web::get().to(get_batches))
             .route("/api/batches/{batch_id}/students", web::get().to(get_batch_students))
@@ -1605,3 +8996,213 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campus_auth::{JwtKey, JwtKeySet, DEFAULT_KEY_ID};
+
+    const SECRET: &str = "test-secret";
+
+    fn session_keys() -> JwtKeySet {
+        JwtKeySet {
+            keys: vec![JwtKey {
+                kid: DEFAULT_KEY_ID.to_string(),
+                secret: SECRET.to_string(),
+                active_from: DateTime::UNIX_EPOCH,
+                active_until: None,
+            }],
+            grace: chrono::Duration::minutes(60),
+        }
+    }
+
+    /// A token carrying both session and QR claims, signed with `key`.
+    fn token_with_every_claim(key: &[u8]) -> String {
+        let now = get_current_timestamp();
+        let claims = serde_json::json!({
+            "sub": "teacher1",
+            "role": "teacher",
+            "campus_id": "campus-1",
+            "sid": ObjectId::new().to_hex(),
+            "iat": now,
+            "nbf": now,
+            "exp": now + 30,
+        });
+        encode(&Header::new(Algorithm::HS256), &claims, &EncodingKey::from_secret(key)).unwrap()
+    }
+
+    #[test]
+    fn qr_key_differs_from_jwt_secret() {
+        assert_ne!(derive_qr_key(SECRET), SECRET.as_bytes());
+        assert_eq!(derive_qr_key(SECRET), derive_qr_key(SECRET));
+        assert_ne!(derive_qr_key(SECRET), derive_qr_key("other-secret"));
+    }
+
+    #[test]
+    fn qr_token_is_not_a_session_token() {
+        let qr_key = derive_qr_key(SECRET);
+        let now = Utc::now();
+        let session = AttendanceSession {
+            id: Some(ObjectId::new()),
+            slot_id: ObjectId::new(),
+            course_code: "CS101".to_string(),
+            section: "A".to_string(),
+            semester: "1".to_string(),
+            date: "2024-07-01".to_string(),
+            opened_by: "teacher1".to_string(),
+            campus_id: "campus-1".to_string(),
+            opened_at: now,
+            expires_at: now + chrono::Duration::minutes(10),
+            closed: false,
+        };
+        let issued = issue_attendance_qr(&session, &qr_key).unwrap();
+        let token = issued["token"].as_str().unwrap();
+
+        let qr = decode_attendance_qr(token, &qr_key).unwrap();
+        assert_eq!(Some(qr.sid), session.id.map(|id| id.to_hex()));
+        assert!(session_keys().decode::<Claims>(token).is_none());
+        assert!(session_keys().decode::<Claims>(&token_with_every_claim(&qr_key)).is_none());
+    }
+
+    #[test]
+    fn session_token_is_not_a_qr_token() {
+        let token = token_with_every_claim(SECRET.as_bytes());
+        assert!(session_keys().decode::<Claims>(&token).is_some());
+        assert!(decode_attendance_qr(&token, &derive_qr_key(SECRET)).is_none());
+    }
+
+    fn course_grade(course_code: &str, credits: i32, grade: &str) -> CourseGrade {
+        CourseGrade {
+            course_code: course_code.to_string(),
+            course_name: course_code.to_string(),
+            credits,
+            marks_obtained: 0.0,
+            max_marks: 100.0,
+            percentage: 0.0,
+            grade: grade.to_string(),
+            grade_points: grade_points(grade),
+        }
+    }
+
+    #[test]
+    fn letter_grades_follow_percentage_bands() {
+        assert_eq!(calculate_grade(90.0, 100.0), "A+");
+        assert_eq!(calculate_grade(89.9, 100.0), "A");
+        assert_eq!(calculate_grade(40.0, 100.0), "D");
+        assert_eq!(calculate_grade(39.5, 100.0), "F");
+        assert_eq!(calculate_grade(45.0, 50.0), "A+");
+        assert_eq!(calculate_grade(0.0, 0.0), "N/A");
+        assert_eq!(grade_points("F"), 0.0);
+        assert_eq!(grade_points("N/A"), 0.0);
+    }
+
+    #[test]
+    fn sgpa_is_weighted_by_credits() {
+        let courses = [
+            course_grade("CS101", 4, "A+"),
+            course_grade("MA101", 3, "B"),
+            course_grade("HS101", 1, "F"),
+        ];
+        // (4 * 10 + 3 * 7 + 1 * 0) / 8 = 7.625; an unweighted mean would be 5.67
+        assert_eq!(grade_point_average(&courses), (8, 7.63));
+        assert_eq!(grade_point_average(&courses[..2]), (7, 8.71));
+    }
+
+    #[test]
+    fn gpa_without_credits_is_zero() {
+        assert_eq!(grade_point_average(&[]), (0, 0.0));
+        assert_eq!(grade_point_average(&[course_grade("LAB", 0, "A")]), (0, 0.0));
+    }
+
+    fn waitlisted(student_id: &str, section: Option<&str>, minutes_ago: i64) -> WaitlistEntry {
+        WaitlistEntry {
+            id: Some(ObjectId::new()),
+            student_id: student_id.to_string(),
+            course_code: "CS101".to_string(),
+            semester: "Fall 2024".to_string(),
+            section: section.map(str::to_string),
+            campus_id: "campus-1".to_string(),
+            requested_at: Utc::now() - chrono::Duration::minutes(minutes_ago),
+        }
+    }
+
+    fn section(section_name: &str, capacity: i32, enrolled: i32) -> Section {
+        Section {
+            id: Some(ObjectId::new()),
+            course_code: "CS101".to_string(),
+            semester: "Fall 2024".to_string(),
+            section_name: section_name.to_string(),
+            capacity,
+            enrolled,
+            faculty_id: "teacher1".to_string(),
+            slot_ids: Vec::new(),
+            campus_id: "campus-1".to_string(),
+            created_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn seat_filter_caps_only_limited_courses() {
+        let key = doc! { "course_code": "CS101", "semester": "Fall 2024", "campus_id": "campus-1" };
+        assert_eq!(seat_filter(key.clone(), None), key);
+
+        let capped = seat_filter(key, Some(60));
+        assert_eq!(capped.get_document("taken").unwrap(), &doc! { "$lt": 60_i64 });
+    }
+
+    #[test]
+    fn promotion_goes_to_the_earliest_request_with_room() {
+        let sections = [section("A", 30, 30), section("B", 30, 29)];
+        let entries = vec![
+            waitlisted("late", Some("B"), 5),
+            waitlisted("full-section", Some("A"), 30),
+            waitlisted("early", None, 20),
+            waitlisted("renamed-section", Some("Z"), 10),
+        ];
+
+        let order: Vec<_> = promotion_order(entries, &sections)
+            .into_iter()
+            .map(|(entry, section)| (entry.student_id, section.map(|s| s.section_name.as_str())))
+            .collect();
+        assert_eq!(order, [
+            ("early".to_string(), None),
+            ("renamed-section".to_string(), None),
+            ("late".to_string(), Some("B")),
+        ]);
+    }
+
+    fn elective_preference(student_id: &str, preferences: &[&str], allocated: &[&str]) -> ElectivePreference {
+        ElectivePreference {
+            id: Some(ObjectId::new()),
+            round_id: ObjectId::new(),
+            student_id: student_id.to_string(),
+            preferences: preferences.iter().map(|c| c.to_string()).collect(),
+            allocated: allocated.iter().map(|c| c.to_string()).collect(),
+            campus_id: "campus-1".to_string(),
+            submitted_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn cgpa_order_keeps_the_lottery_order_on_ties() {
+        // Already shuffled by the lottery
+        let ranked = vec![
+            (7.5, elective_preference("s1", &[], &[])),
+            (9.1, elective_preference("s2", &[], &[])),
+            (7.5, elective_preference("s3", &[], &[])),
+            (8.0, elective_preference("s4", &[], &[])),
+            (7.5, elective_preference("s5", &[], &[])),
+        ];
+        let order: Vec<_> = order_by_cgpa(ranked).into_iter().map(|p| p.student_id).collect();
+        assert_eq!(order, ["s2", "s4", "s1", "s3", "s5"]);
+    }
+
+    #[test]
+    fn open_choices_skip_allocated_full_and_ineligible_courses() {
+        let preference = elective_preference("s1", &["CS401", "CS402", "CS403", "CS404", "CS405"], &["CS401"]);
+        let full = vec!["CS403".to_string()];
+
+        assert_eq!(open_choices(&preference, &full, &["CS404"]), ["CS402", "CS405"]);
+        assert_eq!(open_choices(&preference, &full, &[]), ["CS402", "CS404", "CS405"]);
+    }
+}
@@ -5,18 +5,25 @@ use actix_web::{
     body::EitherBody,
 };
 use actix_cors::Cors;
-use mongodb::{Client, Collection, bson::doc};
+use mongodb::{Client, Collection, bson::doc, options::{FindOptions, ReplaceOptions}};
+use ldap3::{dn_escape, LdapConnAsync, Scope, SearchEntry};
+use rsa::{pkcs8::DecodePublicKey, Pkcs1v15Sign, RsaPublicKey};
+use sha2::{Digest, Sha256};
+use x509_cert::{der::{DecodePem, Encode}, Certificate};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{encode, decode, Header, Validation, EncodingKey, DecodingKey, Algorithm};
+use jsonwebtoken::{encode, Header, EncodingKey};
 use bcrypt::{hash, verify, DEFAULT_COST};
-use chrono::{Utc, Duration};
+use chrono::{DateTime, Utc, Duration, SecondsFormat};
 use std::fmt;
 use std::env;
 use std::future::{ready, Ready, Future};
 use std::pin::Pin;
 use std::rc::Rc;
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::{Arc, RwLock};
 use anyhow::Context;
-use log::info;
+use campus_auth::{load_jwt_keys, reload_jwt_keys, JwtKey, SharedKeySet, DEFAULT_KEY_ID, JWT_KEY_REFRESH_SECS};
+use log::{info, warn};
 
 // ── Custom API Error Type ─────────────────────────────────────────────────────
 // Converts internal errors into structured JSON HTTP responses.
@@ -30,6 +37,7 @@ struct ErrorBody {
 #[derive(Debug)]
 enum AppError {
     Unauthorized(String),
+    Forbidden(String),
     BadRequest(String),
     NotFound(String),
     Internal(anyhow::Error),
@@ -39,6 +47,7 @@ impl fmt::Display for AppError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             AppError::Unauthorized(msg) => write!(f, "{}", msg),
+            AppError::Forbidden(msg) => write!(f, "{}", msg),
             AppError::BadRequest(msg) => write!(f, "{}", msg),
             AppError::NotFound(msg) => write!(f, "{}", msg),
             AppError::Internal(e) => write!(f, "Internal server error: {}", e),
@@ -52,6 +61,7 @@ impl ResponseError for AppError {
         let body = ErrorBody { error: self.to_string() };
         match self {
             AppError::Unauthorized(_) => HttpResponse::Unauthorized().json(body),
+            AppError::Forbidden(_) => HttpResponse::Forbidden().json(body),
             AppError::BadRequest(_) => HttpResponse::BadRequest().json(body),
             AppError::NotFound(_) => HttpResponse::NotFound().json(body),
             AppError::Internal(_) => HttpResponse::InternalServerError().json(body),
@@ -78,12 +88,17 @@ struct User {
     campus_id: String,
     email: String,
     full_name: String,
+    /// "ldap" for accounts provisioned from the directory; None for local accounts.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    auth_source: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct LoginRequest {
     username: Option<String>,
     password: Option<String>,
+    /// Extends the refresh token lifetime to REMEMBER_ME_TTL_DAYS.
+    remember_me: Option<bool>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -96,17 +111,31 @@ struct RegisterRequest {
     full_name: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+struct ChangePasswordRequest {
+    current_password: Option<String>,
+    new_password: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct Claims {
     sub: String,
     role: String,
     campus_id: String,
     exp: usize,
+    iat: usize,
+    nbf: usize,
+    /// Super-admin username when the token was issued via impersonation.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    impersonated_by: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
 struct TokenResponse {
     token: String,
+    refresh_token: String,
+    /// Access token lifetime in seconds.
+    expires_in: i64,
     user: UserInfo,
 }
 
@@ -119,6 +148,113 @@ struct UserInfo {
     full_name: String,
 }
 
+/// A registered campus (tenant). `campus_id` is the short code users and
+/// tokens carry, e.g. "CAMPUS_A".
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Campus {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<mongodb::bson::oid::ObjectId>,
+    campus_id: String,
+    name: String,
+    active: bool,
+    created_by: String,
+    created_at: DateTime<Utc>,
+}
+
+/// Creates a campus together with its first admin account.
+#[derive(Debug, Deserialize)]
+struct CampusRequest {
+    campus_id: Option<String>,
+    name: Option<String>,
+    admin_username: Option<String>,
+    admin_password: Option<String>,
+    admin_email: Option<String>,
+    admin_full_name: Option<String>,
+}
+
+/// One row in the `security_events` collection.
+/// event_type: login_success | login_failure | password_change | token_refresh | passkey_registered | email_change | impersonation
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct SecurityEvent {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<mongodb::bson::oid::ObjectId>,
+    event_type: String,
+    username: String,
+    campus_id: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    detail: Option<String>,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    occurred_at: DateTime<Utc>,
+}
+
+/// A `SecurityEvent` as returned by GET /api/auth/audit. The stored
+/// `occurred_at` is a BSON date, which would otherwise serialize to JSON as
+/// `{"$date": ...}`; here it is a plain RFC 3339 string.
+#[derive(Debug, Serialize)]
+struct SecurityEventResponse {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<mongodb::bson::oid::ObjectId>,
+    event_type: String,
+    username: String,
+    campus_id: Option<String>,
+    ip_address: Option<String>,
+    user_agent: Option<String>,
+    detail: Option<String>,
+    occurred_at: DateTime<Utc>,
+}
+
+impl From<SecurityEvent> for SecurityEventResponse {
+    fn from(event: SecurityEvent) -> Self {
+        SecurityEventResponse {
+            id: event.id,
+            event_type: event.event_type,
+            username: event.username,
+            campus_id: event.campus_id,
+            ip_address: event.ip_address,
+            user_agent: event.user_agent,
+            detail: event.detail,
+            occurred_at: event.occurred_at,
+        }
+    }
+}
+
+/// Shared pagination params: ?page=1&limit=20
+#[derive(Debug, Deserialize)]
+struct PaginationParams {
+    page: Option<u64>,
+    limit: Option<u64>,
+}
+
+impl PaginationParams {
+    fn page(&self) -> u64 {
+        self.page.unwrap_or(1).max(1)
+    }
+    fn limit(&self) -> u64 {
+        self.limit.unwrap_or(20).clamp(1, 100)
+    }
+    fn skip(&self) -> u64 {
+        (self.page() - 1) * self.limit()
+    }
+}
+
+/// Filter params for GET /api/auth/audit
+#[derive(Debug, Deserialize)]
+struct AuditFilter {
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Filter by username
+    username: Option<String>,
+    /// Filter by event type, e.g. "login_failure"
+    event_type: Option<String>,
+    /// RFC 3339 lower bound (inclusive), e.g. "2024-03-01T00:00:00Z"
+    from: Option<String>,
+    /// RFC 3339 upper bound (inclusive)
+    to: Option<String>,
+    /// super_admin only: restrict to one campus
+    campus_id: Option<String>,
+}
+
 // ── Serde Demo: Typed Request / Response Models ───────────────────────────────
 
 /// All fields are Option so we can detect and reject missing ones explicitly.
@@ -138,11 +274,509 @@ struct ProfileResponse {
     message: String,
 }
 
+/// Platform operator role. Not self-registerable; created at bootstrap.
+const SUPER_ADMIN_ROLE: &str = "super_admin";
+
+/// campus_id carried by the super admin, who is not tied to any tenant.
+const PLATFORM_CAMPUS_ID: &str = "PLATFORM";
+
+/// A role in the `roles` collection. A role satisfies checks for itself and,
+/// transitively, every role it inherits (admin ⊃ hr ⊃ staff).
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Role {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<mongodb::bson::oid::ObjectId>,
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default)]
+    inherits: Vec<String>,
+    /// Seeded roles that service code refers to by name; cannot be deleted.
+    #[serde(default)]
+    built_in: bool,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoleRequest {
+    name: Option<String>,
+    description: Option<String>,
+    inherits: Option<Vec<String>>,
+}
+
+/// Roles seeded at startup as (name, description, inherits).
+const BUILT_IN_ROLES: &[(&str, &str, &[&str])] = &[
+    ("student", "Enrolled student", &[]),
+    ("staff", "Campus staff member", &[]),
+    ("teacher", "Teaching faculty", &["staff"]),
+    ("librarian", "Library staff", &["staff"]),
+    ("hr", "Human resources", &["staff"]),
+    ("admin", "Campus administrator", &["hr", "teacher", "librarian"]),
+    (SUPER_ADMIN_ROLE, "Platform administrator", &["admin"]),
+];
+
+/// Clients of GET /api/auth/roles/hierarchy may cache it this long.
+const ROLE_HIERARCHY_MAX_AGE_SECS: u32 = 300;
+
 // ── App State ─────────────────────────────────────────────────────────────────
 
 struct AppState {
     db: mongodb::Database,
+    /// Fallback key for tokens without a `kid`; also re-read on key reloads.
     jwt_secret: String,
+    jwt_keys: SharedKeySet,
+    tokens: TokenConfig,
+    /// None when LDAP_ENABLED is not set — only local accounts are used.
+    ldap: Option<LdapConfig>,
+    /// None when SAML_ENABLED is not set — the /api/auth/saml/* routes return 404.
+    saml: Option<SamlConfig>,
+    webauthn: WebAuthnConfig,
+    /// None when SMTP_HOST is not set — outgoing mail is logged instead.
+    smtp: Option<SmtpConfig>,
+    /// Frontend page that receives ?token= from email verification links.
+    email_verification_url: String,
+    /// Reverse proxies (TRUSTED_PROXIES) whose X-Forwarded-For is believed.
+    trusted_proxies: Vec<std::net::IpAddr>,
+}
+
+// ── LDAP Configuration ────────────────────────────────────────────────────────
+
+/// Directory bind settings, loaded from the environment at startup.
+struct LdapConfig {
+    /// e.g. ldap://ldap.campus.edu:389 or ldaps://…
+    url: String,
+    /// Users are bound as "{user_attr}={username},{base_dn}".
+    base_dn: String,
+    user_attr: String,
+    /// Campus that directory users are provisioned into.
+    campus_id: String,
+    /// (group CN, role) pairs checked in order against the user's memberOf.
+    group_roles: Vec<(String, String)>,
+    /// Role given when no group matches.
+    default_role: String,
+}
+
+impl LdapConfig {
+    /// Reads LDAP_* variables. Returns None unless LDAP_ENABLED=true.
+    /// LDAP_GROUP_ROLES format: "faculty:teacher,hr-staff:hr,library:librarian".
+    fn from_env() -> Option<Self> {
+        if env::var("LDAP_ENABLED").map(|v| v != "true").unwrap_or(true) {
+            return None;
+        }
+
+        let group_roles = env::var("LDAP_GROUP_ROLES")
+            .unwrap_or_default()
+            .split(',')
+            .filter_map(|pair| pair.split_once(':'))
+            .map(|(group, role)| (group.trim().to_lowercase(), role.trim().to_string()))
+            .collect();
+
+        Some(LdapConfig {
+            url: env::var("LDAP_URL").unwrap_or_else(|_| "ldap://localhost:389".to_string()),
+            base_dn: env::var("LDAP_BASE_DN").expect("LDAP_BASE_DN is required when LDAP_ENABLED=true"),
+            user_attr: env::var("LDAP_USER_ATTR").unwrap_or_else(|_| "uid".to_string()),
+            campus_id: env::var("LDAP_CAMPUS_ID").expect("LDAP_CAMPUS_ID is required when LDAP_ENABLED=true"),
+            group_roles,
+            default_role: env::var("LDAP_DEFAULT_ROLE").unwrap_or_else(|_| "student".to_string()),
+        })
+    }
+
+    /// Maps memberOf DNs (e.g. "cn=faculty,ou=groups,dc=campus,dc=edu") to a role.
+    fn role_for_groups(&self, member_of: &[String]) -> String {
+        let group_cns: Vec<String> = member_of
+            .iter()
+            .filter_map(|dn| dn.split(',').next())
+            .filter_map(|rdn| rdn.split_once('='))
+            .filter(|(attr, _)| attr.trim().eq_ignore_ascii_case("cn"))
+            .map(|(_, cn)| cn.trim().to_lowercase())
+            .collect();
+
+        self.group_roles
+            .iter()
+            .find(|(group, _)| group_cns.contains(group))
+            .map(|(_, role)| role.clone())
+            .unwrap_or_else(|| self.default_role.clone())
+    }
+}
+
+/// Identity asserted by an external provider (LDAP bind or SAML assertion).
+struct DirectoryUser {
+    email: String,
+    full_name: String,
+    role: String,
+    campus_id: String,
+}
+
+// ── SAML 2.0 Configuration ────────────────────────────────────────────────────
+
+const NS_SAML: &str = "urn:oasis:names:tc:SAML:2.0:assertion";
+const NS_SAMLP: &str = "urn:oasis:names:tc:SAML:2.0:protocol";
+const NS_DSIG: &str = "http://www.w3.org/2000/09/xmldsig#";
+const ALG_EXC_C14N: &str = "http://www.w3.org/2001/10/xml-exc-c14n#";
+const ALG_ENVELOPED: &str = "http://www.w3.org/2000/09/xmldsig#enveloped-signature";
+const ALG_SHA256: &str = "http://www.w3.org/2001/04/xmlenc#sha256";
+const ALG_RSA_SHA256: &str = "http://www.w3.org/2001/04/xmldsig-more#rsa-sha256";
+
+/// Allowed clock drift between us and the IdP when checking assertion windows.
+const SAML_CLOCK_SKEW_SECS: i64 = 120;
+
+/// Outstanding AuthnRequests expire after this many minutes.
+const SAML_REQUEST_TTL_MINS: i64 = 10;
+
+/// Service-provider settings for SP-initiated SSO, loaded from SAML_* variables.
+struct SamlConfig {
+    sp_entity_id: String,
+    acs_url: String,
+    idp_entity_id: String,
+    idp_sso_url: String,
+    /// Public key from the IdP signing certificate (SAML_IDP_CERT_PATH, PEM).
+    idp_key: RsaPublicKey,
+    /// Assertion attribute names mapped onto Claims / User fields.
+    role_attr: String,
+    campus_attr: String,
+    email_attr: String,
+    name_attr: String,
+    /// Used when the IdP does not release a campus attribute.
+    default_campus_id: Option<String>,
+    /// Where the browser is sent after login; the token is appended as #token=…
+    post_login_redirect: Option<String>,
+}
+
+impl SamlConfig {
+    /// Reads SAML_* variables. Returns None unless SAML_ENABLED=true.
+    fn from_env() -> Option<Self> {
+        if env::var("SAML_ENABLED").map(|v| v != "true").unwrap_or(true) {
+            return None;
+        }
+
+        let required = |name: &str| {
+            env::var(name).unwrap_or_else(|_| panic!("{} is required when SAML_ENABLED=true", name))
+        };
+
+        let cert_path = required("SAML_IDP_CERT_PATH");
+        let cert_pem = std::fs::read_to_string(&cert_path)
+            .unwrap_or_else(|e| panic!("Failed to read {}: {}", cert_path, e));
+        let idp_key = load_rsa_key_from_certificate(&cert_pem)
+            .unwrap_or_else(|e| panic!("Invalid IdP certificate {}: {:#}", cert_path, e));
+
+        Some(SamlConfig {
+            sp_entity_id: required("SAML_SP_ENTITY_ID"),
+            acs_url: required("SAML_ACS_URL"),
+            idp_entity_id: required("SAML_IDP_ENTITY_ID"),
+            idp_sso_url: required("SAML_IDP_SSO_URL"),
+            idp_key,
+            role_attr: env::var("SAML_ROLE_ATTR").unwrap_or_else(|_| "role".to_string()),
+            campus_attr: env::var("SAML_CAMPUS_ATTR").unwrap_or_else(|_| "campus_id".to_string()),
+            email_attr: env::var("SAML_EMAIL_ATTR").unwrap_or_else(|_| "email".to_string()),
+            name_attr: env::var("SAML_NAME_ATTR").unwrap_or_else(|_| "displayName".to_string()),
+            default_campus_id: env::var("SAML_DEFAULT_CAMPUS_ID").ok(),
+            post_login_redirect: env::var("SAML_POST_LOGIN_REDIRECT").ok(),
+        })
+    }
+}
+
+/// Extracts the RSA public key from a PEM-encoded X.509 certificate.
+fn load_rsa_key_from_certificate(pem: &str) -> anyhow::Result<RsaPublicKey> {
+    let cert = Certificate::from_pem(pem.as_bytes()).context("Failed to parse certificate")?;
+    let spki = cert
+        .tbs_certificate
+        .subject_public_key_info
+        .to_der()
+        .context("Failed to encode public key")?;
+    RsaPublicKey::from_public_key_der(&spki).context("Certificate does not hold an RSA key")
+}
+
+/// An AuthnRequest we issued and are waiting on, keyed by its ID so the
+/// assertion's InResponseTo can be matched exactly once.
+#[derive(Debug, Serialize, Deserialize)]
+struct SamlPendingRequest {
+    request_id: String,
+    created_at: DateTime<Utc>,
+}
+
+/// HTTP-POST binding form fields sent by the IdP to the ACS endpoint.
+#[derive(Debug, Deserialize)]
+struct SamlAcsForm {
+    #[serde(rename = "SAMLResponse")]
+    saml_response: String,
+}
+
+/// Identity extracted from a verified assertion.
+#[derive(Debug)]
+struct SamlAssertion {
+    /// Assertion ID, remembered until `expires` so it cannot be replayed.
+    id: String,
+    expires: DateTime<Utc>,
+    name_id: String,
+    in_response_to: Option<String>,
+    attributes: std::collections::HashMap<String, String>,
+}
+
+/// An assertion ID already consumed at the ACS, in `saml_assertions`. It is
+/// shared by every auth instance and survives restarts; a TTL index drops it
+/// once the assertion could no longer be accepted anyway.
+#[derive(Debug, Serialize, Deserialize)]
+struct UsedSamlAssertion {
+    assertion_id: String,
+    #[serde(with = "mongodb::bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    keep_until: DateTime<Utc>,
+}
+
+impl UsedSamlAssertion {
+    fn of(assertion: &SamlAssertion) -> Self {
+        UsedSamlAssertion {
+            assertion_id: assertion.id.clone(),
+            // validate_saml_response still accepts it within the clock skew
+            keep_until: assertion.expires + Duration::seconds(SAML_CLOCK_SKEW_SECS),
+        }
+    }
+}
+
+/// Records the assertion's ID and returns true, or returns false if it was
+/// already used. The unique index on `assertion_id` settles concurrent uses.
+async fn first_use_of_assertion(db: &mongodb::Database, assertion: &SamlAssertion) -> anyhow::Result<bool> {
+    let collection: Collection<UsedSamlAssertion> = db.collection("saml_assertions");
+    match collection.insert_one(UsedSamlAssertion::of(assertion), None).await {
+        Ok(_) => Ok(true),
+        Err(e) if is_duplicate_key(&e) => Ok(false),
+        Err(e) => Err(anyhow::Error::new(e).context("Failed to record SAML assertion")),
+    }
+}
+
+// ── WebAuthn Configuration ────────────────────────────────────────────────────
+
+/// Passkey challenges must be answered within this many minutes.
+const WEBAUTHN_CHALLENGE_TTL_MINS: i64 = 5;
+
+/// COSE algorithm identifiers we accept for passkeys.
+const COSE_ALG_ES256: i64 = -7;
+const COSE_ALG_RS256: i64 = -257;
+
+/// Relying-party settings for passkeys, from WEBAUTHN_* variables.
+struct WebAuthnConfig {
+    /// Registrable domain the credentials are scoped to, e.g. "campus.edu".
+    rp_id: String,
+    rp_name: String,
+    /// Exact origin the browser reports in clientDataJSON.
+    origin: String,
+}
+
+impl WebAuthnConfig {
+    fn from_env() -> Self {
+        WebAuthnConfig {
+            rp_id: env::var("WEBAUTHN_RP_ID").unwrap_or_else(|_| "localhost".to_string()),
+            rp_name: env::var("WEBAUTHN_RP_NAME").unwrap_or_else(|_| "CampusConnect".to_string()),
+            origin: env::var("WEBAUTHN_ORIGIN").unwrap_or_else(|_| "http://localhost:4200".to_string()),
+        }
+    }
+}
+
+/// A registered passkey. Binary values are stored base64url-encoded.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PasskeyCredential {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<mongodb::bson::oid::ObjectId>,
+    username: String,
+    credential_id: String,
+    /// COSE_Key as produced by the authenticator.
+    public_key: String,
+    sign_count: i64,
+    label: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+/// An outstanding registration or authentication challenge.
+/// ceremony: registration | authentication
+#[derive(Debug, Serialize, Deserialize)]
+struct PasskeyChallenge {
+    challenge: String,
+    username: String,
+    ceremony: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PasskeyLoginStartRequest {
+    username: Option<String>,
+}
+
+/// Body of navigator.credentials.create(), with binary fields base64url-encoded.
+#[derive(Debug, Deserialize)]
+struct PasskeyRegisterFinishRequest {
+    label: Option<String>,
+    response: Option<AttestationResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttestationResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "attestationObject")]
+    attestation_object: String,
+}
+
+/// Body of navigator.credentials.get(), with binary fields base64url-encoded.
+#[derive(Debug, Deserialize)]
+struct PasskeyLoginFinishRequest {
+    username: Option<String>,
+    remember_me: Option<bool>,
+    id: Option<String>,
+    response: Option<AssertionResponse>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AssertionResponse {
+    #[serde(rename = "clientDataJSON")]
+    client_data_json: String,
+    #[serde(rename = "authenticatorData")]
+    authenticator_data: String,
+    signature: String,
+}
+
+/// The fields of clientDataJSON we check.
+#[derive(Debug, Deserialize)]
+struct ClientData {
+    #[serde(rename = "type")]
+    ceremony_type: String,
+    challenge: String,
+    origin: String,
+}
+
+/// Parsed authenticatorData (WebAuthn §6.1).
+#[derive(Debug)]
+struct AuthenticatorData {
+    rp_id_hash: Vec<u8>,
+    flags: u8,
+    sign_count: u32,
+    /// (credential id, COSE public key) — present only on registration.
+    attested_credential: Option<(Vec<u8>, Vec<u8>)>,
+}
+
+// ── Token Lifetimes ───────────────────────────────────────────────────────────
+
+/// Access and refresh token lifetimes, from *_TTL_* variables.
+#[derive(Debug, Clone, Copy)]
+struct TokenConfig {
+    access_ttl: Duration,
+    refresh_ttl: Duration,
+    /// Refresh token lifetime when the user asks to be remembered at login.
+    remember_me_ttl: Duration,
+}
+
+impl TokenConfig {
+    fn from_env() -> Self {
+        let positive = |name: &str, default: i64| {
+            env::var(name)
+                .ok()
+                .and_then(|v| v.parse::<i64>().ok())
+                .filter(|v| *v > 0)
+                .unwrap_or(default)
+        };
+
+        TokenConfig {
+            access_ttl: Duration::minutes(positive("ACCESS_TOKEN_TTL_MINS", 24 * 60)),
+            refresh_ttl: Duration::hours(positive("REFRESH_TOKEN_TTL_HOURS", 7 * 24)),
+            remember_me_ttl: Duration::days(positive("REMEMBER_ME_TTL_DAYS", 30)),
+        }
+    }
+}
+
+/// A single-use refresh token. Only the SHA-256 of the token is stored.
+#[derive(Debug, Serialize, Deserialize)]
+struct RefreshToken {
+    token_hash: String,
+    username: String,
+    remember_me: bool,
+    expires_at: DateTime<Utc>,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RefreshRequest {
+    refresh_token: Option<String>,
+}
+
+// ── JWT Signing Keys ──────────────────────────────────────────────────────────
+
+/// Impersonation tokens expire after this many minutes.
+const IMPERSONATION_TTL_MINS: i64 = 30;
+
+/// Body for POST /api/auth/keys. The secret is generated when omitted.
+#[derive(Debug, Deserialize)]
+struct CreateJwtKeyRequest {
+    kid: Option<String>,
+    secret: Option<String>,
+    active_from: Option<String>,
+    active_until: Option<String>,
+}
+
+/// Body for PUT /api/auth/keys/{kid}.
+#[derive(Debug, Deserialize)]
+struct RetireJwtKeyRequest {
+    active_until: Option<String>,
+}
+
+// ── Email Delivery ────────────────────────────────────────────────────────────
+
+/// Email verification links stay valid for this many hours.
+const EMAIL_VERIFICATION_TTL_HOURS: i64 = 24;
+
+/// Outbound SMTP settings, from SMTP_* variables.
+struct SmtpConfig {
+    host: String,
+    port: u16,
+    /// false for local catchers such as MailHog that do not speak STARTTLS.
+    starttls: bool,
+    username: Option<String>,
+    password: Option<String>,
+    from: String,
+}
+
+impl SmtpConfig {
+    /// Returns None unless SMTP_HOST is set; verification links are then
+    /// written to the log instead of being mailed.
+    fn from_env() -> Option<Self> {
+        let host = env::var("SMTP_HOST").ok().filter(|h| !h.is_empty())?;
+        Some(SmtpConfig {
+            host,
+            port: env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(587),
+            starttls: env::var("SMTP_STARTTLS").map(|v| v != "false").unwrap_or(true),
+            username: env::var("SMTP_USERNAME").ok(),
+            password: env::var("SMTP_PASSWORD").ok(),
+            from: env::var("SMTP_FROM").unwrap_or_else(|_| "CampusConnect <no-reply@campusconnect.local>".to_string()),
+        })
+    }
+}
+
+/// A pending email change. Only the SHA-256 of the token is stored.
+#[derive(Debug, Serialize, Deserialize)]
+struct EmailVerification {
+    username: String,
+    new_email: String,
+    token_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct UpdateMeRequest {
+    email: Option<String>,
+    full_name: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct VerifyEmailRequest {
+    token: Option<String>,
+}
+
+/// Response for GET/PUT /api/auth/me.
+#[derive(Debug, Serialize)]
+struct MeResponse {
+    #[serde(flatten)]
+    user: UserInfo,
+    /// New address awaiting verification, if an email change is in progress.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pending_email: Option<String>,
 }
 
 // ── Logging Middleware ────────────────────────────────────────────────────────
@@ -199,7 +833,7 @@ where
 // Public routes (/health, /api/auth/login, /api/auth/register) are skipped.
 
 pub struct JwtAuth {
-    pub jwt_secret: String,
+    keys: SharedKeySet,
 }
 
 impl<S, B> Transform<S, ServiceRequest> for JwtAuth
@@ -216,14 +850,14 @@ where
     fn new_transform(&self, service: S) -> Self::Future {
         ready(Ok(JwtAuthMiddleware {
             service: Rc::new(service),
-            jwt_secret: self.jwt_secret.clone(),
+            keys: self.keys.clone(),
         }))
     }
 }
 
 pub struct JwtAuthMiddleware<S> {
     service: Rc<S>,
-    jwt_secret: String,
+    keys: SharedKeySet,
 }
 
 /// Routes that do NOT require a JWT token.
@@ -233,6 +867,14 @@ fn is_public_route(path: &str, method: &str) -> bool {
         ("GET", "/health")
             | ("POST", "/api/auth/login")
             | ("POST", "/api/auth/register")
+            | ("GET", "/api/auth/saml/metadata")
+            | ("GET", "/api/auth/saml/login")
+            | ("POST", "/api/auth/saml/acs")
+            | ("POST", "/api/auth/webauthn/login/start")
+            | ("POST", "/api/auth/webauthn/login/finish")
+            | ("POST", "/api/auth/me/email/verify")
+            | ("GET", "/api/auth/roles/hierarchy")
+            | ("POST", "/api/auth/refresh")
     )
 }
 
@@ -251,7 +893,7 @@ where
         let path = req.path().to_string();
         let method = req.method().to_string();
         let svc = self.service.clone();
-        let secret = self.jwt_secret.clone();
+        let keys = self.keys.clone();
 
         Box::pin(async move {
             // Skip auth check for public routes
@@ -266,14 +908,7 @@ where
                 .and_then(|v| v.to_str().ok())
                 .and_then(|s| s.strip_prefix("Bearer "))
                 .ok_or("missing")
-                .and_then(|token| {
-                    decode::<Claims>(
-                        token,
-                        &DecodingKey::from_secret(secret.as_bytes()),
-                        &Validation::new(Algorithm::HS256),
-                    )
-                    .map_err(|_| "invalid")
-                });
+                .and_then(|token| keys.read().expect("JWT key set lock poisoned").decode::<Claims>(token).ok_or("invalid"));
 
             match auth_result {
                 Ok(_) => svc.call(req).await.map(|r| r.map_into_left_body()),
@@ -313,14 +948,68 @@ fn validate_email(email: &str) -> Result<(), AppError> {
     }
 }
 
-/// Validates allowed roles.
-fn validate_role(role: &str) -> Result<(), AppError> {
-    match role {
-        "student" | "teacher" | "hr" | "librarian" | "admin" => Ok(()),
-        _ => Err(AppError::BadRequest(format!(
-            "Invalid role '{}'. Must be one of: student, teacher, hr, librarian, admin",
-            role
+/// Parses an RFC 3339 timestamp query parameter.
+fn parse_timestamp(value: &str, field: &str) -> Result<DateTime<Utc>, AppError> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&Utc))
+        .map_err(|_| AppError::BadRequest(format!(
+            "'{}' must be an RFC 3339 timestamp, e.g. 2024-03-01T00:00:00Z",
+            field
+        )))
+}
+
+// ── JWT Extraction ────────────────────────────────────────────────────────────
+
+/// Extracts and validates JWT claims from the Authorization header.
+fn extract_claims(req: &HttpRequest, keys: &SharedKeySet) -> Result<Claims, AppError> {
+    let auth_header = req
+        .headers()
+        .get("Authorization")
+        .ok_or_else(|| AppError::Unauthorized("No token provided".to_string()))?;
+
+    let auth_str = auth_header
+        .to_str()
+        .map_err(|_| AppError::Unauthorized("Malformed Authorization header".to_string()))?;
+
+    let token = auth_str.strip_prefix("Bearer ").ok_or_else(|| {
+        AppError::Unauthorized("Authorization header must use Bearer scheme".to_string())
+    })?;
+
+    keys.read()
+        .expect("JWT key set lock poisoned")
+        .decode(token)
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired token".to_string()))
+}
+
+/// Campus management is reserved for the platform-level super admin.
+fn require_super_admin(claims: &Claims) -> Result<(), AppError> {
+    if claims.role == SUPER_ADMIN_ROLE {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden("Access denied: super-admin role required".to_string()))
+    }
+}
+
+/// Account-changing actions must be done by the account holder, not
+/// by a super-admin holding an impersonation token.
+fn forbid_impersonated(claims: &Claims) -> Result<(), AppError> {
+    match &claims.impersonated_by {
+        Some(by) => Err(AppError::Forbidden(format!(
+            "Not allowed while impersonating (session started by {})",
+            by
         ))),
+        None => Ok(()),
+    }
+}
+
+/// Only admins may read the security audit log: `admin` or any role that
+/// inherits it, such as the super admin.
+async fn require_admin(db: &mongodb::Database, claims: &Claims) -> Result<(), AppError> {
+    let roles = load_roles(db).await?;
+    if effective_roles(&roles, &claims.role).contains("admin") {
+        Ok(())
+    } else {
+        Err(AppError::Forbidden("Access denied: admin role required".to_string()))
     }
 }
 
@@ -338,197 +1027,2700 @@ async fn find_user_by_username(
         .context("Database error while looking up user")
 }
 
-/// Hashes a password using bcrypt. Returns anyhow::Result for clean propagation.
-fn hash_password(password: &str) -> anyhow::Result<String> {
-    hash(password, DEFAULT_COST).context("Failed to hash password")
+/// True for a write rejected by a unique index.
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref we)) if we.code == 11000
+    )
 }
 
-/// Generates a JWT token for the given user claims.
-fn generate_token(claims: &Claims, secret: &str) -> anyhow::Result<String> {
-    encode(
-        &Header::default(),
-        claims,
-        &EncodingKey::from_secret(secret.as_bytes()),
-    )
-    .context("Failed to generate JWT token")
+/// Looks up an active campus by its code. None means unknown or deactivated.
+async fn find_active_campus(
+    db: &mongodb::Database,
+    campus_id: &str,
+) -> anyhow::Result<Option<Campus>> {
+    let collection: Collection<Campus> = db.collection("campuses");
+    collection
+        .find_one(doc! { "campus_id": campus_id, "active": true }, None)
+        .await
+        .context("Database error while looking up campus")
 }
 
-// ── Handlers ──────────────────────────────────────────────────────────────────
+/// Binds to the directory as the user. Ok(None) means the directory rejected
+/// the credentials (LDAP result code 49); connection failures are errors.
+async fn ldap_authenticate(
+    config: &LdapConfig,
+    username: &str,
+    password: &str,
+) -> anyhow::Result<Option<DirectoryUser>> {
+    let (conn, mut ldap) = LdapConnAsync::new(&config.url)
+        .await
+        .context("Failed to connect to LDAP server")?;
+    ldap3::drive!(conn);
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "ok",
-        "service": "auth-service"
-    }))
-}
+    let user_dn = format!("{}={},{}", config.user_attr, dn_escape(username), config.base_dn);
 
-/// POST /api/auth/register
-/// Demonstrates: Option field validation, anyhow propagation, duplicate check.
-async fn register(
-    data: web::Data<AppState>,
-    body: web::Json<serde_json::Value>,
-) -> Result<HttpResponse, AppError> {
+    let bind = ldap
+        .simple_bind(&user_dn, password)
+        .await
+        .context("LDAP bind failed")?;
+    if bind.rc == 49 {
+        return Ok(None);
+    }
+    bind.success().context("LDAP bind failed")?;
+
+    let (entries, _) = ldap
+        .search(
+            &user_dn,
+            Scope::Base,
+            "(objectClass=*)",
+            vec!["mail", "cn", "displayName", "memberOf"],
+        )
+        .await
+        .context("LDAP search failed")?
+        .success()
+        .context("LDAP search failed")?;
+
+    let _ = ldap.unbind().await;
+
+    let entry = entries
+        .into_iter()
+        .next()
+        .map(SearchEntry::construct)
+        .context("LDAP bind succeeded but the user entry could not be read")?;
+
+    let first = |attr: &str| {
+        entry.attrs.get(attr).and_then(|values| values.first()).cloned()
+    };
+    let member_of = entry.attrs.get("memberOf").cloned().unwrap_or_default();
+
+    Ok(Some(DirectoryUser {
+        email: first("mail").unwrap_or_default(),
+        full_name: first("displayName")
+            .or_else(|| first("cn"))
+            .unwrap_or_else(|| username.to_string()),
+        role: config.role_for_groups(&member_of),
+        campus_id: config.campus_id.clone(),
+    }))
+}
+
+/// Creates or refreshes the local record for an externally authenticated user
+/// so other endpoints (and other services via claims) see current role and
+/// profile. Refuses to take over an account owned by a different source.
+async fn provision_external_user(
+    collection: &Collection<User>,
+    username: &str,
+    auth_source: &str,
+    external: DirectoryUser,
+) -> Result<User, AppError> {
+    if let Some(existing) = find_user_by_username(collection, username).await? {
+        if existing.auth_source.as_deref() != Some(auth_source) {
+            return Err(AppError::Unauthorized(
+                "Username is already registered to another account".to_string(),
+            ));
+        }
+    }
+
+    let user = User {
+        id: None,
+        username: username.to_string(),
+        // External users never authenticate locally; this is not a valid bcrypt hash.
+        password_hash: format!("!{}", auth_source),
+        role: external.role,
+        campus_id: external.campus_id,
+        email: external.email,
+        full_name: external.full_name,
+        auth_source: Some(auth_source.to_string()),
+    };
+
+    collection
+        .replace_one(
+            doc! { "username": username },
+            &user,
+            ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .context("Failed to provision external user")?;
+
+    Ok(user)
+}
+
+/// Hashes a password using bcrypt. Returns anyhow::Result for clean propagation.
+fn hash_password(password: &str) -> anyhow::Result<String> {
+    hash(password, DEFAULT_COST).context("Failed to hash password")
+}
+
+/// Generates a JWT token for the given user claims.
+/// Signs with the newest active key and stamps its `kid` in the header.
+fn generate_token(claims: &Claims, keys: &SharedKeySet) -> anyhow::Result<String> {
+    let keys = keys.read().expect("JWT key set lock poisoned");
+    let key = keys
+        .signing_key()
+        .context("No JWT signing key is currently active")?;
+
+    let header = Header {
+        kid: Some(key.kid.clone()),
+        ..Header::default()
+    };
+
+    encode(&header, claims, &EncodingKey::from_secret(key.secret.as_bytes()))
+        .context("Failed to generate JWT token")
+}
+
+impl Claims {
+    /// Claims valid from now for `ttl`.
+    fn new(sub: String, role: String, campus_id: String, ttl: Duration) -> Result<Self, AppError> {
+        let now = Utc::now();
+        let exp = now
+            .checked_add_signed(ttl)
+            .ok_or_else(|| AppError::Internal(anyhow::anyhow!("Timestamp overflow")))?;
+
+        Ok(Claims {
+            sub,
+            role,
+            campus_id,
+            exp: exp.timestamp() as usize,
+            iat: now.timestamp() as usize,
+            nbf: now.timestamp() as usize,
+            impersonated_by: None,
+        })
+    }
+}
+
+/// Issues an access token plus a single-use refresh token for `user`.
+async fn issue_session(data: &AppState, user: User, remember_me: bool) -> Result<TokenResponse, AppError> {
+    let claims = Claims::new(
+        user.username.clone(),
+        user.role.clone(),
+        user.campus_id.clone(),
+        data.tokens.access_ttl,
+    )?;
+    let token = generate_token(&claims, &data.jwt_keys)?;
+
+    let refresh_token = new_challenge();
+    let refresh_ttl = if remember_me {
+        data.tokens.remember_me_ttl
+    } else {
+        data.tokens.refresh_ttl
+    };
+
+    let collection: Collection<RefreshToken> = data.db.collection("refresh_tokens");
+    collection
+        .insert_one(RefreshToken {
+            token_hash: hash_token(&refresh_token),
+            username: user.username.clone(),
+            remember_me,
+            expires_at: Utc::now() + refresh_ttl,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to store refresh token")?;
+
+    Ok(TokenResponse {
+        token,
+        refresh_token,
+        expires_in: data.tokens.access_ttl.num_seconds(),
+        user: UserInfo {
+            username: user.username,
+            role: user.role,
+            campus_id: user.campus_id,
+            email: user.email,
+            full_name: user.full_name,
+        },
+    })
+}
+
+/// Appends an entry to the `security_events` collection.
+/// IP and user agent are taken from the incoming request. A failed write is
+/// logged rather than propagated so auditing never blocks a login.
+/// Address the request came from. X-Forwarded-For is client-controlled, so it
+/// is only consulted when the connecting peer is a trusted proxy.
+fn client_ip(req: &HttpRequest) -> Option<String> {
+    let peer = req.peer_addr()?.ip();
+    let trusted: &[std::net::IpAddr] = req
+        .app_data::<web::Data<AppState>>()
+        .map_or(&[], |data| data.trusted_proxies.as_slice());
+    let forwarded_for = req.headers().get("X-Forwarded-For").and_then(|v| v.to_str().ok());
+    Some(forwarded_client(peer, forwarded_for, trusted).to_string())
+}
+
+/// Walks X-Forwarded-For from the nearest hop back while each hop is a
+/// trusted proxy; the first untrusted address is the client. Entries further
+/// left were written by the client itself and are ignored.
+fn forwarded_client(
+    peer: std::net::IpAddr,
+    forwarded_for: Option<&str>,
+    trusted: &[std::net::IpAddr],
+) -> std::net::IpAddr {
+    let mut client = peer;
+    for hop in forwarded_for.unwrap_or_default().rsplit(',') {
+        if !trusted.contains(&client) {
+            break;
+        }
+        match hop.trim().parse() {
+            Ok(ip) => client = ip,
+            Err(_) => break,
+        }
+    }
+    client
+}
+
+async fn record_security_event(
+    db: &mongodb::Database,
+    req: &HttpRequest,
+    event_type: &str,
+    username: &str,
+    campus_id: Option<&str>,
+    detail: Option<&str>,
+) {
+    let collection: Collection<SecurityEvent> = db.collection("security_events");
+
+    let event = SecurityEvent {
+        id: None,
+        event_type: event_type.to_string(),
+        username: username.to_string(),
+        campus_id: campus_id.map(str::to_string),
+        ip_address: client_ip(req),
+        user_agent: req
+            .headers()
+            .get(header::USER_AGENT)
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string),
+        detail: detail.map(str::to_string),
+        occurred_at: Utc::now(),
+    };
+
+    if let Err(e) = collection.insert_one(event, None).await {
+        warn!("Failed to record security event '{}' for {}: {}", event_type, username, e);
+    }
+}
+
+// ── Roles ─────────────────────────────────────────────────────────────────────
+
+async fn load_roles(db: &mongodb::Database) -> anyhow::Result<Vec<Role>> {
+    let collection: Collection<Role> = db.collection("roles");
+    let options = FindOptions::builder().sort(doc! { "name": 1 }).build();
+    let mut cursor = collection
+        .find(None, options)
+        .await
+        .context("Failed to query roles")?;
+
+    let mut roles = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        roles.push(result.context("Failed to read role from cursor")?);
+    }
+    Ok(roles)
+}
+
+/// The role itself plus everything it inherits, transitively.
+fn effective_roles(roles: &[Role], name: &str) -> BTreeSet<String> {
+    let mut seen = BTreeSet::new();
+    let mut stack = vec![name.to_string()];
+    while let Some(current) = stack.pop() {
+        if !seen.insert(current.clone()) {
+            continue;
+        }
+        if let Some(role) = roles.iter().find(|r| r.name == current) {
+            stack.extend(role.inherits.iter().cloned());
+        }
+    }
+    seen
+}
+
+/// Checks that every parent exists and that `name` inheriting `inherits`
+/// would not make it (indirectly) inherit itself.
+fn validate_inherits(roles: &[Role], name: &str, inherits: &[String]) -> Result<(), AppError> {
+    for parent in inherits {
+        if !roles.iter().any(|r| &r.name == parent) {
+            return Err(AppError::BadRequest(format!("Unknown role '{}' in 'inherits'", parent)));
+        }
+        if effective_roles(roles, parent).contains(name) {
+            return Err(AppError::BadRequest(format!(
+                "Inheriting '{}' would create a cycle",
+                parent
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Only roles present in the `roles` collection may be assigned, and the
+/// platform role is never assignable through registration or SSO.
+async fn validate_role(db: &mongodb::Database, role: &str) -> Result<(), AppError> {
+    if role == SUPER_ADMIN_ROLE {
+        return Err(AppError::BadRequest(format!("Role '{}' cannot be assigned", role)));
+    }
+
+    let collection: Collection<Role> = db.collection("roles");
+    let exists = collection
+        .find_one(doc! { "name": role }, None)
+        .await
+        .context("Failed to look up role")?
+        .is_some();
+
+    if exists {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(format!("Invalid role '{}'", role)))
+    }
+}
+
+fn validate_role_name(name: &str) -> Result<(), AppError> {
+    let valid = name.len() <= 32
+        && name.starts_with(|c: char| c.is_ascii_lowercase())
+        && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if valid {
+        Ok(())
+    } else {
+        Err(AppError::BadRequest(
+            "Role name must be lowercase letters, digits or '_', starting with a letter".to_string(),
+        ))
+    }
+}
+
+/// Inserts any missing built-in roles. Existing documents are left alone
+/// so edits made through the API survive restarts.
+async fn seed_roles(db: &mongodb::Database) -> anyhow::Result<()> {
+    let collection: Collection<Role> = db.collection("roles");
+    for (name, description, inherits) in BUILT_IN_ROLES {
+        let existing = collection
+            .find_one(doc! { "name": *name }, None)
+            .await
+            .context("Failed to look up role")?;
+        if existing.is_some() {
+            continue;
+        }
+
+        collection
+            .insert_one(Role {
+                id: None,
+                name: name.to_string(),
+                description: description.to_string(),
+                inherits: inherits.iter().map(|r| r.to_string()).collect(),
+                built_in: true,
+                created_at: Utc::now(),
+            }, None)
+            .await
+            .context("Failed to seed role")?;
+        info!("Seeded role '{}'", name);
+    }
+    Ok(())
+}
+
+/// Values of `field` held by more than one document in `collection`, with
+/// how many documents hold each. Unique indexes cannot be built over these.
+async fn find_duplicate_values(
+    db: &mongodb::Database,
+    collection: &str,
+    field: &str,
+) -> anyhow::Result<Vec<(String, i32)>> {
+    use futures::stream::StreamExt;
+
+    let pipeline = [
+        doc! { "$group": { "_id": format!("${}", field), "count": { "$sum": 1 } } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+        doc! { "$sort": { "_id": 1 } },
+    ];
+    let mut cursor = db
+        .collection::<mongodb::bson::Document>(collection)
+        .aggregate(pipeline, None)
+        .await
+        .with_context(|| format!("Failed to look for duplicate {}.{}", collection, field))?;
+
+    let mut duplicates = Vec::new();
+    while let Some(group) = cursor.next().await {
+        let group = group.context("Failed to read duplicate group from cursor")?;
+        let value = group.get("_id").map(|v| v.to_string()).unwrap_or_default();
+        duplicates.push((value, group.get_i32("count").unwrap_or_default()));
+    }
+    Ok(duplicates)
+}
+
+/// Indexes the service relies on for correctness: unique campus ids and
+/// usernames, and the SAML replay store.
+async fn create_indexes(db: &mongodb::Database) -> anyhow::Result<()> {
+    use mongodb::{options::IndexOptions, IndexModel};
+
+    db.collection::<Campus>("campuses")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await
+        .context("Failed to create campuses.campus_id index")?;
+
+    db.collection::<User>("users")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "username": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await
+        .context("Failed to create users.username index")?;
+
+    // Replay protection for SAML assertions; expired IDs are removed by the TTL
+    db.collection::<UsedSamlAssertion>("saml_assertions")
+        .create_indexes(
+            [
+                IndexModel::builder()
+                    .keys(doc! { "assertion_id": 1 })
+                    .options(IndexOptions::builder().unique(true).build())
+                    .build(),
+                IndexModel::builder()
+                    .keys(doc! { "keep_until": 1 })
+                    .options(IndexOptions::builder().expire_after(std::time::Duration::ZERO).build())
+                    .build(),
+            ],
+            None,
+        )
+        .await
+        .context("Failed to create saml_assertions indexes")?;
+
+    Ok(())
+}
+
+/// Converts `security_events.occurred_at` values written as RFC 3339 strings
+/// by earlier versions into BSON dates, so range filters and sorting see them.
+async fn migrate_security_event_timestamps(db: &mongodb::Database) -> anyhow::Result<()> {
+    use futures::stream::StreamExt;
+
+    let collection: Collection<mongodb::bson::Document> = db.collection("security_events");
+    let mut cursor = collection
+        .find(doc! { "occurred_at": { "$type": "string" } }, None)
+        .await
+        .context("Failed to find security events with string timestamps")?;
+
+    let mut migrated = 0;
+    while let Some(result) = cursor.next().await {
+        let event = result.context("Failed to read security event from cursor")?;
+        let id = event.get_object_id("_id").context("Security event has no _id")?;
+        let raw = event.get_str("occurred_at").context("occurred_at is not a string")?;
+        let occurred_at = DateTime::parse_from_rfc3339(raw)
+            .with_context(|| format!("Security event {} has an invalid occurred_at '{}'", id, raw))?
+            .with_timezone(&Utc);
+        collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "occurred_at": mongodb::bson::DateTime::from_chrono(occurred_at) } },
+                None,
+            )
+            .await
+            .context("Failed to migrate security event timestamp")?;
+        migrated += 1;
+    }
+    if migrated > 0 {
+        info!("Converted occurred_at to a date on {} security event(s)", migrated);
+    }
+    Ok(())
+}
+
+// ── Email Verification ────────────────────────────────────────────────────────
+
+/// Sends a plain-text email, or logs it when SMTP is not configured.
+async fn send_email(config: Option<&SmtpConfig>, to: &str, subject: &str, body: String) -> anyhow::Result<()> {
+    use lettre::{
+        message::header::ContentType, transport::smtp::authentication::Credentials,
+        AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    };
+
+    let Some(config) = config else {
+        info!("SMTP not configured; email to {} ({}):\n{}", to, subject, body);
+        return Ok(());
+    };
+
+    let message = Message::builder()
+        .from(config.from.parse().context("Invalid SMTP_FROM address")?)
+        .to(to.parse().context("Invalid recipient address")?)
+        .subject(subject)
+        .header(ContentType::TEXT_PLAIN)
+        .body(body)
+        .context("Failed to build email")?;
+
+    let mut transport = if config.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+            .context("Failed to configure SMTP relay")?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+    }
+    .port(config.port);
+
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport.build().send(message).await.context("Failed to send email")?;
+    Ok(())
+}
+
+fn hash_token(token: &str) -> String {
+    b64url_encode(&Sha256::digest(token.as_bytes()))
+}
+
+/// Looks up any in-progress email change for a user.
+async fn find_pending_email(db: &mongodb::Database, username: &str) -> anyhow::Result<Option<String>> {
+    let collection: Collection<EmailVerification> = db.collection("email_verifications");
+    let pending = collection
+        .find_one(doc! { "username": username }, None)
+        .await
+        .context("Failed to look up pending email change")?;
+
+    Ok(pending
+        .filter(|p| p.expires_at > Utc::now())
+        .map(|p| p.new_email))
+}
+
+/// Case-insensitive check that no other account already uses `email`.
+async fn email_in_use(collection: &Collection<User>, email: &str, username: &str) -> anyhow::Result<bool> {
+    let pattern = format!("^{}$", regex_escape(email));
+    let existing = collection
+        .find_one(
+            doc! {
+                "email": { "$regex": pattern, "$options": "i" },
+                "username": { "$ne": username }
+            },
+            None,
+        )
+        .await
+        .context("Failed to check email availability")?;
+    Ok(existing.is_some())
+}
+
+fn regex_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+// ── SAML 2.0: XML Canonicalization & Signature Verification ───────────────────
+
+/// Escapes a string for use inside a double-quoted XML attribute.
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Exclusive XML Canonicalization 1.0 (without comments) of `node`'s subtree.
+/// `exclude` skips one descendant — the enveloped Signature element.
+/// `inclusive_prefixes` is the InclusiveNamespaces PrefixList ("#default" = no prefix).
+fn exc_c14n(
+    node: roxmltree::Node,
+    exclude: Option<roxmltree::NodeId>,
+    inclusive_prefixes: &[String],
+) -> String {
+    let mut out = String::new();
+    c14n_element(node, exclude, inclusive_prefixes, &[], &mut out);
+    out
+}
+
+fn c14n_element(
+    node: roxmltree::Node,
+    exclude: Option<roxmltree::NodeId>,
+    inclusive_prefixes: &[String],
+    rendered: &[(String, String)],
+    out: &mut String,
+) {
+    let qualified = |prefix: &str, name: &str| {
+        if prefix.is_empty() { name.to_string() } else { format!("{}:{}", prefix, name) }
+    };
+
+    let element_prefix = element_prefix(node).to_string();
+
+    // Namespaces visibly utilized by this element and its attributes,
+    // plus any listed in the InclusiveNamespaces PrefixList.
+    let mut needed: Vec<String> = vec![element_prefix.clone()];
+    for attr in node.attributes() {
+        let p = attribute_prefix(node, &attr);
+        if p != "xml" && !p.is_empty() {
+            needed.push(p.to_string());
+        }
+    }
+    for p in inclusive_prefixes {
+        let p = if p == "#default" { String::new() } else { p.clone() };
+        let in_scope = if p.is_empty() {
+            node.lookup_namespace_uri(None).is_some()
+        } else {
+            node.lookup_namespace_uri(Some(&p)).is_some()
+        };
+        if in_scope {
+            needed.push(p);
+        }
+    }
+    needed.sort();
+    needed.dedup();
+
+    let mut scope: Vec<(String, String)> = rendered.to_vec();
+    let mut ns_decls = Vec::new();
+    for p in needed {
+        let uri = if p.is_empty() {
+            node.lookup_namespace_uri(None).unwrap_or("")
+        } else {
+            node.lookup_namespace_uri(Some(&p)).unwrap_or("")
+        };
+        let current = scope.iter().rev().find(|(sp, _)| *sp == p).map(|(_, u)| u.as_str());
+        let already = match current {
+            Some(u) => u == uri,
+            // An empty default namespace is implied until something else is rendered
+            None => p.is_empty() && uri.is_empty(),
+        };
+        if !already {
+            let decl = if p.is_empty() { "xmlns".to_string() } else { format!("xmlns:{}", p) };
+            ns_decls.push(format!(" {}=\"{}\"", decl, c14n_escape_attr(uri)));
+            scope.push((p, uri.to_string()));
+        }
+    }
+
+    // Attributes sorted by (namespace URI, local name); unqualified first
+    let mut attrs: Vec<(String, String, String)> = node
+        .attributes()
+        .map(|a| {
+            let ns = a.namespace().unwrap_or("").to_string();
+            let name = qualified(attribute_prefix(node, &a), a.name());
+            (ns, a.name().to_string(), format!(" {}=\"{}\"", name, c14n_escape_attr(a.value())))
+        })
+        .collect();
+    attrs.sort_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+
+    let tag = qualified(&element_prefix, node.tag_name().name());
+    out.push('<');
+    out.push_str(&tag);
+    for decl in &ns_decls {
+        out.push_str(decl);
+    }
+    for (_, _, attr) in &attrs {
+        out.push_str(attr);
+    }
+    out.push('>');
+
+    for child in node.children() {
+        if Some(child.id()) == exclude {
+            continue;
+        }
+        if child.is_element() {
+            c14n_element(child, exclude, inclusive_prefixes, &scope, out);
+        } else if child.is_text() {
+            out.push_str(&c14n_escape_text(child.text().unwrap_or("")));
+        } else if let Some(pi) = child.pi() {
+            out.push_str("<?");
+            out.push_str(pi.target);
+            if let Some(value) = pi.value {
+                out.push(' ');
+                out.push_str(value);
+            }
+            out.push_str("?>");
+        }
+    }
+
+    out.push_str("</");
+    out.push_str(&tag);
+    out.push('>');
+}
+
+/// Prefix of a qualified name as written ("" when unprefixed).
+fn qname_prefix(qname: &str) -> &str {
+    qname.split_once(':').map_or("", |(prefix, _)| prefix)
+}
+
+/// The prefix an element was written with. roxmltree keeps only the resolved
+/// namespace URI, and mapping that back through lookup_prefix picks the wrong
+/// prefix when two prefixes are bound to the same namespace.
+fn element_prefix<'input>(node: roxmltree::Node<'_, 'input>) -> &'input str {
+    let start_tag = &node.document().input_text()[node.range().start + 1..];
+    let end = start_tag
+        .find(|c: char| c.is_whitespace() || c == '/' || c == '>')
+        .unwrap_or(start_tag.len());
+    qname_prefix(&start_tag[..end])
+}
+
+/// The prefix an attribute was written with (see `element_prefix`).
+fn attribute_prefix<'input>(node: roxmltree::Node<'_, 'input>, attr: &roxmltree::Attribute) -> &'input str {
+    qname_prefix(&node.document().input_text()[attr.range_qname()])
+}
+
+fn c14n_escape_text(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\r', "&#xD;")
+}
+
+fn c14n_escape_attr(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('"', "&quot;")
+        .replace('\t', "&#x9;")
+        .replace('\n', "&#xA;")
+        .replace('\r', "&#xD;")
+}
+
+/// Returns the first child element with the given namespace and local name.
+fn xml_child<'a, 'input>(
+    node: roxmltree::Node<'a, 'input>,
+    ns: &str,
+    name: &str,
+) -> Option<roxmltree::Node<'a, 'input>> {
+    node.children()
+        .find(|c| c.is_element() && c.tag_name().namespace() == Some(ns) && c.tag_name().name() == name)
+}
+
+/// Text content of an element with all whitespace removed (for base64 values).
+fn xml_compact_text(node: roxmltree::Node) -> String {
+    node.descendants()
+        .filter(|n| n.is_text())
+        .filter_map(|n| n.text())
+        .flat_map(|t| t.chars())
+        .filter(|c| !c.is_whitespace())
+        .collect()
+}
+
+/// PrefixList of an InclusiveNamespaces child, if the algorithm node has one.
+fn inclusive_prefix_list(algorithm_node: roxmltree::Node) -> Vec<String> {
+    algorithm_node
+        .children()
+        .find(|c| c.is_element() && c.tag_name().name() == "InclusiveNamespaces")
+        .and_then(|c| c.attribute("PrefixList"))
+        .map(|list| list.split_whitespace().map(str::to_string).collect())
+        .unwrap_or_default()
+}
+
+/// Verifies the enveloped XML-DSig signature that is a direct child of
+/// `element`. Only exc-c14n, SHA-256 digests and RSA-SHA256 are accepted.
+fn verify_enveloped_signature(element: roxmltree::Node, key: &RsaPublicKey) -> anyhow::Result<()> {
+    use base64::Engine;
+    let b64 = base64::engine::general_purpose::STANDARD;
+
+    let signature = xml_child(element, NS_DSIG, "Signature").context("Element is not signed")?;
+    let signed_info = xml_child(signature, NS_DSIG, "SignedInfo").context("Missing SignedInfo")?;
+
+    let c14n_method = xml_child(signed_info, NS_DSIG, "CanonicalizationMethod")
+        .context("Missing CanonicalizationMethod")?;
+    anyhow::ensure!(
+        c14n_method.attribute("Algorithm") == Some(ALG_EXC_C14N),
+        "Unsupported canonicalization algorithm"
+    );
+    let signature_method = xml_child(signed_info, NS_DSIG, "SignatureMethod")
+        .and_then(|n| n.attribute("Algorithm"));
+    anyhow::ensure!(signature_method == Some(ALG_RSA_SHA256), "Unsupported signature algorithm");
+
+    // Exactly one reference, and it must point at the element being verified
+    let references: Vec<_> = signed_info
+        .children()
+        .filter(|c| c.is_element() && c.tag_name().name() == "Reference")
+        .collect();
+    anyhow::ensure!(references.len() == 1, "Expected exactly one signature reference");
+    let reference = references[0];
+    let element_id = element.attribute("ID").context("Signed element has no ID")?;
+    anyhow::ensure!(
+        reference.attribute("URI") == Some(format!("#{}", element_id).as_str()),
+        "Signature reference does not cover the signed element"
+    );
+
+    let mut prefixes = Vec::new();
+    if let Some(transforms) = xml_child(reference, NS_DSIG, "Transforms") {
+        for transform in transforms.children().filter(|c| c.is_element()) {
+            match transform.attribute("Algorithm") {
+                Some(ALG_ENVELOPED) => {}
+                Some(ALG_EXC_C14N) => prefixes = inclusive_prefix_list(transform),
+                other => anyhow::bail!("Unsupported transform {:?}", other),
+            }
+        }
+    }
+
+    let digest_method = xml_child(reference, NS_DSIG, "DigestMethod").and_then(|n| n.attribute("Algorithm"));
+    anyhow::ensure!(digest_method == Some(ALG_SHA256), "Unsupported digest algorithm");
+    let expected_digest = b64
+        .decode(xml_compact_text(xml_child(reference, NS_DSIG, "DigestValue").context("Missing DigestValue")?))
+        .context("DigestValue is not valid base64")?;
+
+    let canonical = exc_c14n(element, Some(signature.id()), &prefixes);
+    anyhow::ensure!(
+        Sha256::digest(canonical.as_bytes()).as_slice() == expected_digest.as_slice(),
+        "Digest mismatch: signed content was modified"
+    );
+
+    let signature_value = b64
+        .decode(xml_compact_text(xml_child(signature, NS_DSIG, "SignatureValue").context("Missing SignatureValue")?))
+        .context("SignatureValue is not valid base64")?;
+    let signed_info_c14n = exc_c14n(signed_info, None, &inclusive_prefix_list(c14n_method));
+
+    key.verify(
+        Pkcs1v15Sign::new::<Sha256>(),
+        &Sha256::digest(signed_info_c14n.as_bytes()),
+        &signature_value,
+    )
+    .context("Signature verification failed")
+}
+
+/// Parses an RFC 3339 timestamp attribute of a SAML element.
+fn saml_instant(node: roxmltree::Node, attr: &str) -> anyhow::Result<Option<DateTime<Utc>>> {
+    node.attribute(attr)
+        .map(|v| {
+            DateTime::parse_from_rfc3339(v)
+                .map(|dt| dt.with_timezone(&Utc))
+                .with_context(|| format!("Invalid {} timestamp", attr))
+        })
+        .transpose()
+}
+
+/// Validates a decoded SAML Response and returns the asserted identity.
+/// Checks status, signature (on the Assertion or the enclosing Response),
+/// issuer, audience, validity windows and bearer subject confirmation.
+fn validate_saml_response(xml: &str, config: &SamlConfig) -> anyhow::Result<SamlAssertion> {
+    let doc = roxmltree::Document::parse(xml).context("SAMLResponse is not well-formed XML")?;
+    let response = doc.root_element();
+    anyhow::ensure!(
+        response.tag_name().namespace() == Some(NS_SAMLP) && response.tag_name().name() == "Response",
+        "Root element is not a SAML Response"
+    );
+
+    let status = xml_child(response, NS_SAMLP, "Status")
+        .and_then(|s| xml_child(s, NS_SAMLP, "StatusCode"))
+        .and_then(|c| c.attribute("Value"))
+        .unwrap_or("");
+    anyhow::ensure!(status.ends_with(":Success"), "IdP returned status {}", status);
+
+    // Exactly one plaintext assertion, directly under the Response
+    anyhow::ensure!(
+        !doc.descendants().any(|n| n.tag_name().name() == "EncryptedAssertion"),
+        "Encrypted assertions are not supported"
+    );
+    let assertions: Vec<_> = doc
+        .descendants()
+        .filter(|n| n.tag_name().namespace() == Some(NS_SAML) && n.tag_name().name() == "Assertion")
+        .collect();
+    anyhow::ensure!(assertions.len() == 1, "Expected exactly one assertion");
+    let assertion = assertions[0];
+    anyhow::ensure!(assertion.parent() == Some(response), "Assertion is not a child of the Response");
+
+    // Guard against signature wrapping: every ID in the document must be unique
+    let mut ids: Vec<&str> = doc.descendants().filter_map(|n| n.attribute("ID")).collect();
+    let id_count = ids.len();
+    ids.sort_unstable();
+    ids.dedup();
+    anyhow::ensure!(ids.len() == id_count, "Duplicate ID attributes in SAMLResponse");
+
+    if xml_child(assertion, NS_DSIG, "Signature").is_some() {
+        verify_enveloped_signature(assertion, &config.idp_key)?;
+    } else {
+        verify_enveloped_signature(response, &config.idp_key)?;
+    }
+
+    let issuer = xml_child(assertion, NS_SAML, "Issuer").and_then(|n| n.text()).unwrap_or("").trim();
+    anyhow::ensure!(issuer == config.idp_entity_id, "Unexpected assertion issuer '{}'", issuer);
+
+    let now = Utc::now();
+    let skew = Duration::seconds(SAML_CLOCK_SKEW_SECS);
+
+    let conditions = xml_child(assertion, NS_SAML, "Conditions").context("Assertion has no Conditions")?;
+    if let Some(not_before) = saml_instant(conditions, "NotBefore")? {
+        anyhow::ensure!(now + skew >= not_before, "Assertion is not yet valid");
+    }
+    if let Some(not_on_or_after) = saml_instant(conditions, "NotOnOrAfter")? {
+        anyhow::ensure!(now - skew < not_on_or_after, "Assertion has expired");
+    }
+    let audience_ok = conditions
+        .children()
+        .filter(|c| c.is_element() && c.tag_name().name() == "AudienceRestriction")
+        .flat_map(|r| r.children().filter(|c| c.is_element() && c.tag_name().name() == "Audience"))
+        .any(|a| a.text().map(str::trim) == Some(config.sp_entity_id.as_str()));
+    anyhow::ensure!(audience_ok, "Assertion is not addressed to this service provider");
+
+    let subject = xml_child(assertion, NS_SAML, "Subject").context("Assertion has no Subject")?;
+    let name_id = xml_child(subject, NS_SAML, "NameID")
+        .and_then(|n| n.text())
+        .map(str::trim)
+        .filter(|n| !n.is_empty())
+        .context("Assertion has no NameID")?
+        .to_string();
+
+    let confirmation_data = subject
+        .children()
+        .filter(|c| c.is_element() && c.tag_name().name() == "SubjectConfirmation")
+        .filter(|c| c.attribute("Method") == Some("urn:oasis:names:tc:SAML:2.0:cm:bearer"))
+        .find_map(|c| xml_child(c, NS_SAML, "SubjectConfirmationData"))
+        .context("Assertion has no bearer SubjectConfirmationData")?;
+    anyhow::ensure!(
+        confirmation_data.attribute("Recipient") == Some(config.acs_url.as_str()),
+        "Assertion recipient does not match the ACS URL"
+    );
+    let expires = saml_instant(confirmation_data, "NotOnOrAfter")?
+        .context("SubjectConfirmationData has no NotOnOrAfter")?;
+    anyhow::ensure!(now - skew < expires, "Subject confirmation has expired");
+
+    let mut attributes = std::collections::HashMap::new();
+    if let Some(statement) = xml_child(assertion, NS_SAML, "AttributeStatement") {
+        for attr in statement.children().filter(|c| c.is_element() && c.tag_name().name() == "Attribute") {
+            let value = xml_child(attr, NS_SAML, "AttributeValue").and_then(|v| v.text());
+            if let (Some(name), Some(value)) = (attr.attribute("Name"), value) {
+                attributes.insert(name.to_string(), value.trim().to_string());
+            }
+        }
+    }
+
+    let id = assertion.attribute("ID").context("Assertion has no ID")?.to_string();
+
+    Ok(SamlAssertion {
+        id,
+        expires,
+        name_id,
+        in_response_to: confirmation_data.attribute("InResponseTo").map(str::to_string),
+        attributes,
+    })
+}
+
+// ── WebAuthn: Ceremony Verification ───────────────────────────────────────────
+
+const AUTH_DATA_USER_PRESENT: u8 = 0x01;
+const AUTH_DATA_ATTESTED: u8 = 0x40;
+
+fn b64url_encode(bytes: &[u8]) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decodes base64url with or without padding, as browsers differ.
+fn b64url_decode(value: &str, field: &str) -> Result<Vec<u8>, AppError> {
+    use base64::Engine;
+    base64::engine::general_purpose::URL_SAFE_NO_PAD
+        .decode(value.trim_end_matches('='))
+        .map_err(|_| AppError::BadRequest(format!("'{}' must be base64url-encoded", field)))
+}
+
+/// Generates a 32-byte random challenge, base64url-encoded.
+fn new_challenge() -> String {
+    use rand::RngCore;
+    let mut bytes = [0u8; 32];
+    rand::rngs::OsRng.fill_bytes(&mut bytes);
+    b64url_encode(&bytes)
+}
+
+fn parse_authenticator_data(data: &[u8]) -> anyhow::Result<AuthenticatorData> {
+    anyhow::ensure!(data.len() >= 37, "authenticatorData is too short");
+
+    let flags = data[32];
+    let sign_count = u32::from_be_bytes([data[33], data[34], data[35], data[36]]);
+
+    let attested_credential = if flags & AUTH_DATA_ATTESTED != 0 {
+        // aaguid (16) | credentialIdLength (2) | credentialId | credentialPublicKey (CBOR)
+        let rest = &data[37..];
+        anyhow::ensure!(rest.len() >= 18, "attestedCredentialData is too short");
+        let id_len = u16::from_be_bytes([rest[16], rest[17]]) as usize;
+        anyhow::ensure!(rest.len() >= 18 + id_len, "credentialId is truncated");
+        let credential_id = rest[18..18 + id_len].to_vec();
+
+        let key_bytes = &rest[18 + id_len..];
+        let mut cursor = std::io::Cursor::new(key_bytes);
+        let _: ciborium::Value = ciborium::de::from_reader(&mut cursor)
+            .context("credentialPublicKey is not valid CBOR")?;
+        let key_len = cursor.position() as usize;
+        Some((credential_id, key_bytes[..key_len].to_vec()))
+    } else {
+        None
+    };
+
+    Ok(AuthenticatorData {
+        rp_id_hash: data[..32].to_vec(),
+        flags,
+        sign_count,
+        attested_credential,
+    })
+}
+
+/// Checks clientDataJSON against the expected ceremony, challenge and origin.
+fn verify_client_data(
+    client_data_json: &[u8],
+    ceremony_type: &str,
+    config: &WebAuthnConfig,
+) -> anyhow::Result<String> {
+    let client_data: ClientData =
+        serde_json::from_slice(client_data_json).context("clientDataJSON is not valid JSON")?;
+    anyhow::ensure!(client_data.ceremony_type == ceremony_type, "Unexpected ceremony type");
+    anyhow::ensure!(client_data.origin == config.origin, "Unexpected origin '{}'", client_data.origin);
+    Ok(client_data.challenge)
+}
+
+/// Checks the rpIdHash and user-presence flag common to both ceremonies.
+fn verify_rp_and_presence(auth_data: &AuthenticatorData, config: &WebAuthnConfig) -> anyhow::Result<()> {
+    anyhow::ensure!(
+        auth_data.rp_id_hash.as_slice() == Sha256::digest(config.rp_id.as_bytes()).as_slice(),
+        "Credential is scoped to a different relying party"
+    );
+    anyhow::ensure!(auth_data.flags & AUTH_DATA_USER_PRESENT != 0, "User presence was not asserted");
+    Ok(())
+}
+
+/// Returns the counter to store after an assertion. A counter that fails to
+/// increase suggests a cloned authenticator; authenticators that do not
+/// implement counters always report 0.
+fn check_sign_count(stored: i64, reported: u32) -> anyhow::Result<i64> {
+    let reported = reported as i64;
+    anyhow::ensure!(
+        (reported == 0 && stored == 0) || reported > stored,
+        "signature counter did not increase ({} -> {})",
+        stored,
+        reported
+    );
+    Ok(reported)
+}
+
+/// Looks up an integer-keyed entry in a COSE_Key map.
+fn cose_field(map: &[(ciborium::Value, ciborium::Value)], key: i64) -> Option<&ciborium::Value> {
+    map.iter()
+        .find(|(k, _)| k.as_integer().map(i128::from) == Some(key as i128))
+        .map(|(_, v)| v)
+}
+
+fn cose_bytes(map: &[(ciborium::Value, ciborium::Value)], key: i64) -> anyhow::Result<Vec<u8>> {
+    cose_field(map, key)
+        .and_then(|v| v.as_bytes())
+        .cloned()
+        .with_context(|| format!("COSE key is missing parameter {}", key))
+}
+
+/// Returns the COSE algorithm of a stored key, rejecting unsupported ones.
+fn cose_algorithm(cose_key: &[u8]) -> anyhow::Result<(i64, Vec<(ciborium::Value, ciborium::Value)>)> {
+    let value: ciborium::Value =
+        ciborium::de::from_reader(cose_key).context("COSE key is not valid CBOR")?;
+    let map = value.into_map().map_err(|_| anyhow::anyhow!("COSE key is not a map"))?;
+    let alg = cose_field(&map, 3)
+        .and_then(|v| v.as_integer())
+        .and_then(|i| i64::try_from(i).ok())
+        .context("COSE key has no algorithm")?;
+    anyhow::ensure!(
+        alg == COSE_ALG_ES256 || alg == COSE_ALG_RS256,
+        "Unsupported passkey algorithm {}",
+        alg
+    );
+    Ok((alg, map))
+}
+
+/// Verifies an assertion signature over authenticatorData || SHA-256(clientDataJSON).
+fn verify_passkey_signature(
+    cose_key: &[u8],
+    authenticator_data: &[u8],
+    client_data_json: &[u8],
+    signature: &[u8],
+) -> anyhow::Result<()> {
+    let (alg, map) = cose_algorithm(cose_key)?;
+
+    let mut signed = authenticator_data.to_vec();
+    signed.extend_from_slice(&Sha256::digest(client_data_json));
+
+    if alg == COSE_ALG_ES256 {
+        use p256::ecdsa::signature::Verifier;
+        // Uncompressed SEC1 point: 0x04 || x || y
+        let mut point = vec![0x04];
+        point.extend(cose_bytes(&map, -2)?);
+        point.extend(cose_bytes(&map, -3)?);
+        let key = p256::ecdsa::VerifyingKey::from_sec1_bytes(&point).context("Invalid P-256 key")?;
+        let sig = p256::ecdsa::Signature::from_der(signature).context("Invalid ES256 signature encoding")?;
+        key.verify(&signed, &sig).context("Passkey signature verification failed")
+    } else {
+        let key = RsaPublicKey::new(
+            rsa::BigUint::from_bytes_be(&cose_bytes(&map, -1)?),
+            rsa::BigUint::from_bytes_be(&cose_bytes(&map, -2)?),
+        )
+        .context("Invalid RSA key")?;
+        key.verify(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(&signed), signature)
+            .context("Passkey signature verification failed")
+    }
+}
+
+/// Consumes a pending challenge; None if unknown, already used or expired.
+async fn take_passkey_challenge(
+    db: &mongodb::Database,
+    challenge: &str,
+    username: &str,
+    ceremony: &str,
+) -> anyhow::Result<Option<PasskeyChallenge>> {
+    let collection: Collection<PasskeyChallenge> = db.collection("webauthn_challenges");
+    let pending = collection
+        .find_one_and_delete(
+            doc! { "challenge": challenge, "username": username, "ceremony": ceremony },
+            None,
+        )
+        .await
+        .context("Failed to look up passkey challenge")?;
+    Ok(pending.filter(|p| Utc::now() - p.created_at < Duration::minutes(WEBAUTHN_CHALLENGE_TTL_MINS)))
+}
+
+/// Loads all passkeys registered by a user.
+async fn find_passkeys(db: &mongodb::Database, username: &str) -> anyhow::Result<Vec<PasskeyCredential>> {
+    let collection: Collection<PasskeyCredential> = db.collection("webauthn_credentials");
+    let mut cursor = collection
+        .find(doc! { "username": username }, None)
+        .await
+        .context("Failed to query passkeys")?;
+
+    let mut credentials = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        credentials.push(result.context("Failed to read passkey from cursor")?);
+    }
+    Ok(credentials)
+}
+
+// ── Handlers ──────────────────────────────────────────────────────────────────
+
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "service": "auth-service"
+    }))
+}
+
+/// POST /api/auth/register
+/// Demonstrates: Option field validation, anyhow propagation, duplicate check.
+async fn register(
+    data: web::Data<AppState>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
     // Parse into our typed struct — missing fields become None
     let req: RegisterRequest = serde_json::from_value(body.into_inner())
         .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
 
-    // Validate all required fields using Option explicitly
-    let username = require_field(&req.username, "username")?;
-    let password = require_field(&req.password, "password")?;
-    let role = require_field(&req.role, "role")?;
-    let campus_id = require_field(&req.campus_id, "campus_id")?;
-    let email = require_field(&req.email, "email")?;
-    let full_name = require_field(&req.full_name, "full_name")?;
+    // Validate all required fields using Option explicitly
+    let username = require_field(&req.username, "username")?;
+    let password = require_field(&req.password, "password")?;
+    let role = require_field(&req.role, "role")?;
+    let campus_id = require_field(&req.campus_id, "campus_id")?;
+    let email = require_field(&req.email, "email")?;
+    let full_name = require_field(&req.full_name, "full_name")?;
+
+    validate_email(email)?;
+    validate_role(&data.db, role).await?;
+
+    if password.len() < 6 {
+        return Err(AppError::BadRequest(
+            "Password must be at least 6 characters".to_string(),
+        ));
+    }
+
+    if find_active_campus(&data.db, campus_id).await?.is_none() {
+        return Err(AppError::BadRequest(format!("Unknown campus '{}'", campus_id)));
+    }
+
+    let collection: Collection<User> = data.db.collection("users");
+
+    // Use service function — anyhow error auto-converts to AppError::Internal
+    let existing = find_user_by_username(&collection, username).await?;
+    if existing.is_some() {
+        return Err(AppError::BadRequest("Username already exists".to_string()));
+    }
+
+    let password_hash = hash_password(password)?;
+
+    let new_user = User {
+        id: None,
+        username: username.to_string(),
+        password_hash,
+        role: role.to_string(),
+        campus_id: campus_id.to_string(),
+        email: email.to_string(),
+        full_name: full_name.to_string(),
+        auth_source: None,
+    };
+
+    match collection.insert_one(new_user, None).await {
+        Ok(_) => {}
+        Err(e) if is_duplicate_key(&e) => {
+            return Err(AppError::BadRequest("Username already exists".to_string()));
+        }
+        Err(e) => return Err(anyhow::Error::new(e).context("Failed to insert user into database").into()),
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "User registered successfully"
+    })))
+}
+
+/// POST /api/auth/login
+/// Demonstrates: Option-based field validation, Result for credential check.
+async fn login(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let req: LoginRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let username = require_field(&req.username, "username")?;
+    let password = require_field(&req.password, "password")?;
+
+    let collection: Collection<User> = data.db.collection("users");
+
+    // When LDAP is enabled the directory is tried first; a rejected bind or an
+    // unreachable server falls through to local accounts (e.g. the super admin).
+    let directory_user = match &data.ldap {
+        Some(ldap) => ldap_authenticate(ldap, username, password)
+            .await
+            .unwrap_or_else(|e| {
+                warn!("LDAP authentication unavailable, using local accounts: {:#}", e);
+                None
+            }),
+        None => None,
+    };
+
+    let user = match directory_user {
+        Some(directory_user) => {
+            validate_role(&data.db, &directory_user.role).await?;
+            provision_external_user(&collection, username, "ldap", directory_user).await?
+        }
+        None => {
+            // find_user_by_username returns Option<User> — None means user doesn't exist
+            let user = match find_user_by_username(&collection, username).await? {
+                Some(user) => user,
+                None => {
+                    record_security_event(&data.db, &http_req, "login_failure", username, None, Some("unknown user")).await;
+                    return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+                }
+            };
+
+            // Directory accounts must not fall back to the placeholder local hash
+            let valid = user.auth_source.is_none()
+                && verify(password, &user.password_hash).context("Failed to verify password")?;
+
+            if !valid {
+                record_security_event(&data.db, &http_req, "login_failure", username, Some(&user.campus_id), Some("wrong password")).await;
+                return Err(AppError::Unauthorized("Invalid credentials".to_string()));
+            }
+            user
+        }
+    };
+
+    // Users of a deactivated campus can no longer sign in
+    if user.role != SUPER_ADMIN_ROLE && find_active_campus(&data.db, &user.campus_id).await?.is_none() {
+        record_security_event(&data.db, &http_req, "login_failure", username, Some(&user.campus_id), Some("campus inactive")).await;
+        return Err(AppError::Unauthorized("Campus is not active".to_string()));
+    }
+
+    record_security_event(&data.db, &http_req, "login_success", &user.username, Some(&user.campus_id), None).await;
+
+    let session = issue_session(&data, user, req.remember_me.unwrap_or(false)).await?;
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// POST /api/auth/campuses
+/// Super-admin only. Registers a campus and bootstraps its first admin user.
+async fn create_campus(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+
+    let req: CampusRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let campus_id = require_field(&req.campus_id, "campus_id")?;
+    let name = require_field(&req.name, "name")?;
+    let admin_username = require_field(&req.admin_username, "admin_username")?;
+    let admin_password = require_field(&req.admin_password, "admin_password")?;
+    let admin_email = require_field(&req.admin_email, "admin_email")?;
+    let admin_full_name = require_field(&req.admin_full_name, "admin_full_name")?;
+
+    if campus_id == PLATFORM_CAMPUS_ID {
+        return Err(AppError::BadRequest(format!("'{}' is a reserved campus_id", campus_id)));
+    }
+    validate_email(admin_email)?;
+    if admin_password.len() < 6 {
+        return Err(AppError::BadRequest(
+            "Password must be at least 6 characters".to_string(),
+        ));
+    }
+
+    let campuses: Collection<Campus> = data.db.collection("campuses");
+    let users: Collection<User> = data.db.collection("users");
+
+    let existing = campuses
+        .find_one(doc! { "campus_id": campus_id }, None)
+        .await
+        .context("Database error while looking up campus")?;
+    if existing.is_some() {
+        return Err(AppError::BadRequest("Campus already exists".to_string()));
+    }
+    if find_user_by_username(&users, admin_username).await?.is_some() {
+        return Err(AppError::BadRequest("Username already exists".to_string()));
+    }
+
+    let password_hash = hash_password(admin_password)?;
+
+    let inserted = match campuses
+        .insert_one(Campus {
+            id: None,
+            campus_id: campus_id.to_string(),
+            name: name.to_string(),
+            active: true,
+            created_by: claims.sub.clone(),
+            created_at: Utc::now(),
+        }, None)
+        .await
+    {
+        Ok(inserted) => inserted,
+        Err(e) if is_duplicate_key(&e) => {
+            return Err(AppError::BadRequest("Campus already exists".to_string()));
+        }
+        Err(e) => return Err(anyhow::Error::new(e).context("Failed to insert campus").into()),
+    };
+
+    let admin = users
+        .insert_one(User {
+            id: None,
+            username: admin_username.to_string(),
+            password_hash,
+            role: "admin".to_string(),
+            campus_id: campus_id.to_string(),
+            email: admin_email.to_string(),
+            full_name: admin_full_name.to_string(),
+            auth_source: None,
+        }, None)
+        .await;
+
+    // A campus without its admin cannot be managed or recreated, so undo it
+    if let Err(e) = admin {
+        if let Err(cleanup) = campuses.delete_one(doc! { "_id": inserted.inserted_id }, None).await {
+            warn!("Failed to remove campus '{}' after its admin could not be created: {}", campus_id, cleanup);
+        }
+        if is_duplicate_key(&e) {
+            return Err(AppError::BadRequest("Username already exists".to_string()));
+        }
+        return Err(anyhow::Error::new(e).context("Failed to insert campus admin").into());
+    }
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Campus created successfully",
+        "campus_id": campus_id,
+        "admin_username": admin_username
+    })))
+}
+
+/// GET /api/auth/campuses
+/// Super-admin only. Lists every registered campus.
+async fn get_campuses(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+
+    let collection: Collection<Campus> = data.db.collection("campuses");
+
+    let mut cursor = collection
+        .find(doc! {}, None)
+        .await
+        .context("Failed to query campuses")?;
+
+    let mut campuses = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let campus = result.context("Failed to read campus from cursor")?;
+        campuses.push(campus);
+    }
+
+    Ok(HttpResponse::Ok().json(campuses))
+}
+
+/// Returns the SAML config or a 404 when SSO is not enabled.
+fn saml_config(data: &AppState) -> Result<&SamlConfig, AppError> {
+    data.saml
+        .as_ref()
+        .ok_or_else(|| AppError::NotFound("SAML SSO is not configured".to_string()))
+}
+
+/// GET /api/auth/saml/metadata
+/// SP metadata for registering this service with the IdP.
+async fn saml_metadata(data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let config = saml_config(&data)?;
+
+    let metadata = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<md:EntityDescriptor xmlns:md="urn:oasis:names:tc:SAML:2.0:metadata" entityID="{}">"#,
+            r#"<md:SPSSODescriptor AuthnRequestsSigned="false" WantAssertionsSigned="true" "#,
+            r#"protocolSupportEnumeration="urn:oasis:names:tc:SAML:2.0:protocol">"#,
+            r#"<md:NameIDFormat>urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified</md:NameIDFormat>"#,
+            r#"<md:AssertionConsumerService Binding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST" "#,
+            r#"Location="{}" index="0" isDefault="true"/>"#,
+            r#"</md:SPSSODescriptor></md:EntityDescriptor>"#
+        ),
+        xml_escape(&config.sp_entity_id),
+        xml_escape(&config.acs_url),
+    );
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/samlmetadata+xml")
+        .body(metadata))
+}
+
+/// GET /api/auth/saml/login
+/// Starts SP-initiated SSO: redirects the browser to the IdP with a
+/// deflated AuthnRequest (HTTP-Redirect binding).
+async fn saml_login(data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    use base64::Engine;
+    use std::io::Write;
+
+    let config = saml_config(&data)?;
+
+    let request_id = format!("_{}", mongodb::bson::oid::ObjectId::new().to_hex());
+    let authn_request = format!(
+        concat!(
+            r#"<samlp:AuthnRequest xmlns:samlp="urn:oasis:names:tc:SAML:2.0:protocol" "#,
+            r#"xmlns:saml="urn:oasis:names:tc:SAML:2.0:assertion" ID="{}" Version="2.0" "#,
+            r#"IssueInstant="{}" Destination="{}" AssertionConsumerServiceURL="{}" "#,
+            r#"ProtocolBinding="urn:oasis:names:tc:SAML:2.0:bindings:HTTP-POST">"#,
+            r#"<saml:Issuer>{}</saml:Issuer>"#,
+            r#"<samlp:NameIDPolicy Format="urn:oasis:names:tc:SAML:1.1:nameid-format:unspecified" AllowCreate="true"/>"#,
+            r#"</samlp:AuthnRequest>"#
+        ),
+        request_id,
+        Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+        xml_escape(&config.idp_sso_url),
+        xml_escape(&config.acs_url),
+        xml_escape(&config.sp_entity_id),
+    );
+
+    let mut encoder = flate2::write::DeflateEncoder::new(Vec::new(), flate2::Compression::default());
+    encoder.write_all(authn_request.as_bytes()).context("Failed to deflate AuthnRequest")?;
+    let deflated = encoder.finish().context("Failed to deflate AuthnRequest")?;
+    let query = serde_urlencoded::to_string([
+        ("SAMLRequest", base64::engine::general_purpose::STANDARD.encode(deflated)),
+    ])
+    .context("Failed to encode AuthnRequest")?;
+
+    let pending: Collection<SamlPendingRequest> = data.db.collection("saml_requests");
+    pending
+        .insert_one(SamlPendingRequest { request_id, created_at: Utc::now() }, None)
+        .await
+        .context("Failed to store AuthnRequest")?;
+
+    let separator = if config.idp_sso_url.contains('?') { '&' } else { '?' };
+    Ok(HttpResponse::Found()
+        .insert_header((header::LOCATION, format!("{}{}{}", config.idp_sso_url, separator, query)))
+        .finish())
+}
+
+/// POST /api/auth/saml/acs
+/// Assertion Consumer Service: validates the IdP response, provisions the
+/// user just-in-time and issues a regular JWT.
+async fn saml_acs(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    form: web::Form<SamlAcsForm>,
+) -> Result<HttpResponse, AppError> {
+    use base64::Engine;
+
+    let config = saml_config(&data)?;
+
+    let compact: String = form.saml_response.chars().filter(|c| !c.is_whitespace()).collect();
+    let xml = base64::engine::general_purpose::STANDARD
+        .decode(compact)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or_else(|| AppError::BadRequest("SAMLResponse is not valid base64 XML".to_string()))?;
+
+    let assertion = match validate_saml_response(&xml, config) {
+        Ok(assertion) => assertion,
+        Err(e) => {
+            record_security_event(&data.db, &http_req, "login_failure", "(saml)", None, Some(&format!("saml: {:#}", e))).await;
+            return Err(AppError::Unauthorized(format!("Invalid SAML response: {}", e)));
+        }
+    };
+
+    // SP-initiated only: the assertion must answer a request we issued, once
+    let request_id = assertion
+        .in_response_to
+        .as_deref()
+        .ok_or_else(|| AppError::Unauthorized("Unsolicited SAML responses are not accepted".to_string()))?;
+    let pending: Collection<SamlPendingRequest> = data.db.collection("saml_requests");
+    let issued = pending
+        .find_one_and_delete(doc! { "request_id": request_id }, None)
+        .await
+        .context("Failed to look up AuthnRequest")?
+        .filter(|p| Utc::now() - p.created_at < Duration::minutes(SAML_REQUEST_TTL_MINS));
+    if issued.is_none() {
+        return Err(AppError::Unauthorized("SAML response does not match a pending login".to_string()));
+    }
+    if !first_use_of_assertion(&data.db, &assertion).await? {
+        record_security_event(&data.db, &http_req, "login_failure", &assertion.name_id, None, Some("saml: assertion replayed")).await;
+        return Err(AppError::Unauthorized("SAML assertion has already been used".to_string()));
+    }
+
+    let attribute = |name: &str| assertion.attributes.get(name).cloned();
+    let role = attribute(&config.role_attr).unwrap_or_else(|| "student".to_string());
+    validate_role(&data.db, &role).await?;
+    let campus_id = attribute(&config.campus_attr)
+        .or_else(|| config.default_campus_id.clone())
+        .ok_or_else(|| AppError::Unauthorized("IdP did not release a campus_id".to_string()))?;
+    if find_active_campus(&data.db, &campus_id).await?.is_none() {
+        return Err(AppError::Unauthorized("Campus is not active".to_string()));
+    }
+
+    let collection: Collection<User> = data.db.collection("users");
+    let user = provision_external_user(
+        &collection,
+        &assertion.name_id,
+        "saml",
+        DirectoryUser {
+            email: attribute(&config.email_attr).unwrap_or_default(),
+            full_name: attribute(&config.name_attr).unwrap_or_else(|| assertion.name_id.clone()),
+            role,
+            campus_id,
+        },
+    )
+    .await?;
+
+    record_security_event(&data.db, &http_req, "login_success", &user.username, Some(&user.campus_id), Some("saml")).await;
+
+    let session = issue_session(&data, user, false).await?;
+
+    if let Some(redirect) = &config.post_login_redirect {
+        return Ok(HttpResponse::Found()
+            .insert_header((
+                header::LOCATION,
+                format!("{}#token={}&refresh_token={}", redirect, session.token, session.refresh_token),
+            ))
+            .finish());
+    }
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// POST /api/auth/webauthn/register/start
+/// Returns PublicKeyCredentialCreationOptions for navigator.credentials.create().
+async fn passkey_register_start(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    forbid_impersonated(&claims)?;
+
+    let users: Collection<User> = data.db.collection("users");
+    let user = find_user_by_username(&users, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let challenge = new_challenge();
+    let challenges: Collection<PasskeyChallenge> = data.db.collection("webauthn_challenges");
+    challenges
+        .insert_one(PasskeyChallenge {
+            challenge: challenge.clone(),
+            username: user.username.clone(),
+            ceremony: "registration".to_string(),
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to store passkey challenge")?;
+
+    let exclude: Vec<serde_json::Value> = find_passkeys(&data.db, &user.username)
+        .await?
+        .into_iter()
+        .map(|c| serde_json::json!({ "type": "public-key", "id": c.credential_id }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "challenge": challenge,
+        "rp": { "id": data.webauthn.rp_id, "name": data.webauthn.rp_name },
+        "user": {
+            "id": b64url_encode(user.username.as_bytes()),
+            "name": user.username,
+            "displayName": user.full_name
+        },
+        "pubKeyCredParams": [
+            { "type": "public-key", "alg": COSE_ALG_ES256 },
+            { "type": "public-key", "alg": COSE_ALG_RS256 }
+        ],
+        "timeout": WEBAUTHN_CHALLENGE_TTL_MINS * 60 * 1000,
+        "attestation": "none",
+        "excludeCredentials": exclude,
+        "authenticatorSelection": { "residentKey": "preferred", "userVerification": "preferred" }
+    })))
+}
+
+/// POST /api/auth/webauthn/register/finish
+/// Verifies the attestation response and stores the new credential.
+async fn passkey_register_finish(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    forbid_impersonated(&claims)?;
+
+    let req: PasskeyRegisterFinishRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let response = req
+        .response
+        .ok_or_else(|| AppError::BadRequest("'response' is required".to_string()))?;
+
+    let client_data_json = b64url_decode(&response.client_data_json, "clientDataJSON")?;
+    let attestation_object = b64url_decode(&response.attestation_object, "attestationObject")?;
+
+    let challenge = verify_client_data(&client_data_json, "webauthn.create", &data.webauthn)
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    if take_passkey_challenge(&data.db, &challenge, &claims.sub, "registration").await?.is_none() {
+        return Err(AppError::BadRequest("Challenge is unknown or has expired".to_string()));
+    }
+
+    // attestationObject = { fmt, attStmt, authData }. Attestation statements are
+    // not verified ("none" conveyance), only the credential itself.
+    let attestation: ciborium::Value = ciborium::de::from_reader(attestation_object.as_slice())
+        .map_err(|_| AppError::BadRequest("attestationObject is not valid CBOR".to_string()))?;
+    let auth_data_bytes = attestation
+        .as_map()
+        .and_then(|m| m.iter().find(|(k, _)| k.as_text() == Some("authData")))
+        .and_then(|(_, v)| v.as_bytes())
+        .ok_or_else(|| AppError::BadRequest("attestationObject has no authData".to_string()))?;
+
+    let auth_data = parse_authenticator_data(auth_data_bytes)
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    verify_rp_and_presence(&auth_data, &data.webauthn)
+        .map_err(|e| AppError::BadRequest(e.to_string()))?;
+    let (credential_id, public_key) = auth_data
+        .attested_credential
+        .ok_or_else(|| AppError::BadRequest("authData has no attested credential".to_string()))?;
+    cose_algorithm(&public_key).map_err(|e| AppError::BadRequest(e.to_string()))?;
+
+    let collection: Collection<PasskeyCredential> = data.db.collection("webauthn_credentials");
+    let credential_id = b64url_encode(&credential_id);
+
+    let existing = collection
+        .find_one(doc! { "credential_id": &credential_id }, None)
+        .await
+        .context("Failed to check existing passkey")?;
+    if existing.is_some() {
+        return Err(AppError::BadRequest("Passkey is already registered".to_string()));
+    }
+
+    collection
+        .insert_one(PasskeyCredential {
+            id: None,
+            username: claims.sub.clone(),
+            credential_id: credential_id.clone(),
+            public_key: b64url_encode(&public_key),
+            sign_count: auth_data.sign_count as i64,
+            label: req.label.filter(|l| !l.trim().is_empty()).unwrap_or_else(|| "Passkey".to_string()),
+            created_at: Utc::now(),
+            last_used_at: None,
+        }, None)
+        .await
+        .context("Failed to store passkey")?;
+
+    record_security_event(&data.db, &http_req, "passkey_registered", &claims.sub, Some(&claims.campus_id), None).await;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Passkey registered successfully",
+        "credential_id": credential_id
+    })))
+}
+
+/// POST /api/auth/webauthn/login/start
+/// Returns PublicKeyCredentialRequestOptions for navigator.credentials.get().
+async fn passkey_login_start(
+    data: web::Data<AppState>,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let req: PasskeyLoginStartRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let username = require_field(&req.username, "username")?;
+
+    // A challenge is issued even for unknown users so the response does not
+    // reveal which usernames have passkeys.
+    let challenge = new_challenge();
+    let challenges: Collection<PasskeyChallenge> = data.db.collection("webauthn_challenges");
+    challenges
+        .insert_one(PasskeyChallenge {
+            challenge: challenge.clone(),
+            username: username.to_string(),
+            ceremony: "authentication".to_string(),
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .context("Failed to store passkey challenge")?;
+
+    let allow: Vec<serde_json::Value> = find_passkeys(&data.db, username)
+        .await?
+        .into_iter()
+        .map(|c| serde_json::json!({ "type": "public-key", "id": c.credential_id }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "challenge": challenge,
+        "rpId": data.webauthn.rp_id,
+        "timeout": WEBAUTHN_CHALLENGE_TTL_MINS * 60 * 1000,
+        "allowCredentials": allow,
+        "userVerification": "preferred"
+    })))
+}
+
+/// POST /api/auth/webauthn/login/finish
+/// Verifies the assertion, enforces the signature counter and issues a JWT.
+async fn passkey_login_finish(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let req: PasskeyLoginFinishRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let username = require_field(&req.username, "username")?;
+    let credential_id = require_field(&req.id, "id")?;
+    let response = req
+        .response
+        .ok_or_else(|| AppError::BadRequest("'response' is required".to_string()))?;
+
+    let client_data_json = b64url_decode(&response.client_data_json, "clientDataJSON")?;
+    let authenticator_data = b64url_decode(&response.authenticator_data, "authenticatorData")?;
+    let signature = b64url_decode(&response.signature, "signature")?;
+
+    let (db, req_ref) = (&data.db, &http_req);
+    let reject = |reason: String| async move {
+        record_security_event(db, req_ref, "login_failure", username, None, Some(&format!("passkey: {}", reason))).await;
+        AppError::Unauthorized("Passkey authentication failed".to_string())
+    };
+
+    let challenge = match verify_client_data(&client_data_json, "webauthn.get", &data.webauthn) {
+        Ok(challenge) => challenge,
+        Err(e) => return Err(reject(e.to_string()).await),
+    };
+    if take_passkey_challenge(&data.db, &challenge, username, "authentication").await?.is_none() {
+        return Err(reject("unknown or expired challenge".to_string()).await);
+    }
+
+    let credentials: Collection<PasskeyCredential> = data.db.collection("webauthn_credentials");
+    let credential = match credentials
+        .find_one(doc! { "credential_id": credential_id, "username": username }, None)
+        .await
+        .context("Failed to look up passkey")?
+    {
+        Some(credential) => credential,
+        None => return Err(reject("unknown credential".to_string()).await),
+    };
+
+    let auth_data = match parse_authenticator_data(&authenticator_data)
+        .and_then(|a| verify_rp_and_presence(&a, &data.webauthn).map(|_| a))
+    {
+        Ok(auth_data) => auth_data,
+        Err(e) => return Err(reject(e.to_string()).await),
+    };
+
+    let public_key = b64url_decode(&credential.public_key, "public_key")?;
+    if let Err(e) = verify_passkey_signature(&public_key, &authenticator_data, &client_data_json, &signature) {
+        return Err(reject(e.to_string()).await);
+    }
+
+    let new_count = match check_sign_count(credential.sign_count, auth_data.sign_count) {
+        Ok(count) => count,
+        Err(e) => return Err(reject(e.to_string()).await),
+    };
+
+    // Compare-and-set on the counter we checked against, so two concurrent
+    // logins replaying the same assertion cannot both succeed.
+    let updated = credentials
+        .update_one(
+            doc! { "credential_id": credential_id, "sign_count": credential.sign_count },
+            doc! { "$set": {
+                "sign_count": new_count,
+                "last_used_at": Utc::now().to_rfc3339_opts(SecondsFormat::AutoSi, true)
+            }},
+            None,
+        )
+        .await
+        .context("Failed to update passkey counter")?;
+    if updated.modified_count == 0 {
+        return Err(reject("signature counter changed during login".to_string()).await);
+    }
+
+    let users: Collection<User> = data.db.collection("users");
+    let user = match find_user_by_username(&users, username).await? {
+        Some(user) => user,
+        None => return Err(reject("account no longer exists".to_string()).await),
+    };
+    if user.role != SUPER_ADMIN_ROLE && find_active_campus(&data.db, &user.campus_id).await?.is_none() {
+        return Err(reject("campus inactive".to_string()).await);
+    }
+
+    record_security_event(&data.db, &http_req, "login_success", &user.username, Some(&user.campus_id), Some("passkey")).await;
+
+    let session = issue_session(&data, user, req.remember_me.unwrap_or(false)).await?;
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// GET /api/auth/me
+/// Returns the caller's profile as stored, not as embedded in the token.
+async fn get_me(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+
+    let collection: Collection<User> = data.db.collection("users");
+    let user = find_user_by_username(&collection, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let pending_email = find_pending_email(&data.db, &user.username).await?;
+
+    Ok(HttpResponse::Ok().json(MeResponse {
+        user: UserInfo {
+            username: user.username,
+            role: user.role,
+            campus_id: user.campus_id,
+            email: user.email,
+            full_name: user.full_name,
+        },
+        pending_email,
+    }))
+}
+
+/// PUT /api/auth/me
+/// Updates full_name immediately. A new email only takes effect once the
+/// link sent to that address is confirmed via POST /api/auth/me/email/verify.
+async fn update_me(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    forbid_impersonated(&claims)?;
+
+    let req: UpdateMeRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    if req.email.is_none() && req.full_name.is_none() {
+        return Err(AppError::BadRequest(
+            "Provide 'email' and/or 'full_name' to update".to_string(),
+        ));
+    }
+
+    let collection: Collection<User> = data.db.collection("users");
+    let mut user = find_user_by_username(&collection, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    if user.auth_source.is_some() {
+        return Err(AppError::BadRequest(
+            "Profile is managed by the campus directory".to_string(),
+        ));
+    }
+
+    if let Some(full_name) = &req.full_name {
+        let full_name = full_name.trim();
+        if full_name.is_empty() {
+            return Err(AppError::BadRequest("'full_name' cannot be empty".to_string()));
+        }
+
+        collection
+            .update_one(
+                doc! { "username": &user.username },
+                doc! { "$set": { "full_name": full_name } },
+                None,
+            )
+            .await
+            .context("Failed to update profile")?;
+        user.full_name = full_name.to_string();
+    }
+
+    let mut message = "Profile updated successfully".to_string();
+
+    if let Some(email) = &req.email {
+        let email = email.trim();
+        validate_email(email)?;
+
+        if !email.eq_ignore_ascii_case(&user.email) {
+            if email_in_use(&collection, email, &user.username).await? {
+                return Err(AppError::BadRequest("Email is already in use".to_string()));
+            }
+
+            let token = new_challenge();
+            let verifications: Collection<EmailVerification> = data.db.collection("email_verifications");
+
+            // Only the latest requested address can be confirmed.
+            verifications
+                .delete_many(doc! { "username": &user.username }, None)
+                .await
+                .context("Failed to clear pending email changes")?;
+            verifications
+                .insert_one(EmailVerification {
+                    username: user.username.clone(),
+                    new_email: email.to_string(),
+                    token_hash: hash_token(&token),
+                    expires_at: Utc::now() + Duration::hours(EMAIL_VERIFICATION_TTL_HOURS),
+                }, None)
+                .await
+                .context("Failed to store email verification")?;
+
+            let link = format!("{}?token={}", data.email_verification_url, token);
+            send_email(
+                data.smtp.as_ref(),
+                email,
+                "Confirm your new CampusConnect email address",
+                format!(
+                    "Hello {},\n\nConfirm this address for your CampusConnect account by opening:\n\n{}\n\nThe link expires in {} hours. If you did not request this change, ignore this email.\n",
+                    user.full_name, link, EMAIL_VERIFICATION_TTL_HOURS
+                ),
+            )
+            .await?;
+
+            message = format!("Verification link sent to {}", email);
+        }
+    }
+
+    let pending_email = find_pending_email(&data.db, &user.username).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": message,
+        "user": MeResponse {
+            user: UserInfo {
+                username: user.username,
+                role: user.role,
+                campus_id: user.campus_id,
+                email: user.email,
+                full_name: user.full_name,
+            },
+            pending_email,
+        }
+    })))
+}
+
+/// POST /api/auth/me/email/verify
+/// Public so the emailed link works without an active session; the
+/// single-use token identifies the account.
+async fn verify_email_change(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let req: VerifyEmailRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let token = require_field(&req.token, "token")?;
+
+    let verifications: Collection<EmailVerification> = data.db.collection("email_verifications");
+    let verification = verifications
+        .find_one_and_delete(doc! { "token_hash": hash_token(token) }, None)
+        .await
+        .context("Failed to look up email verification")?
+        .filter(|v| v.expires_at > Utc::now())
+        .ok_or_else(|| AppError::BadRequest("Verification link is invalid or has expired".to_string()))?;
+
+    let collection: Collection<User> = data.db.collection("users");
+    if email_in_use(&collection, &verification.new_email, &verification.username).await? {
+        return Err(AppError::BadRequest("Email is already in use".to_string()));
+    }
+
+    let result = collection
+        .update_one(
+            doc! { "username": &verification.username },
+            doc! { "$set": { "email": &verification.new_email } },
+            None,
+        )
+        .await
+        .context("Failed to update email")?;
+
+    if result.matched_count == 0 {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let user = find_user_by_username(&collection, &verification.username).await?;
+    let campus_id = user.as_ref().map(|u| u.campus_id.as_str());
+    record_security_event(&data.db, &http_req, "email_change", &verification.username, campus_id, Some(&verification.new_email)).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Email address verified",
+        "email": verification.new_email
+    })))
+}
+
+/// PUT /api/auth/password
+/// Changes the caller's password after re-checking the current one.
+async fn change_password(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    forbid_impersonated(&claims)?;
+
+    let req: ChangePasswordRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    let current_password = require_field(&req.current_password, "current_password")?;
+    let new_password = require_field(&req.new_password, "new_password")?;
+
+    if new_password.len() < 6 {
+        return Err(AppError::BadRequest(
+            "Password must be at least 6 characters".to_string(),
+        ));
+    }
+
+    let collection: Collection<User> = data.db.collection("users");
+
+    let user = find_user_by_username(&collection, &claims.sub)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    if user.auth_source.is_some() {
+        return Err(AppError::BadRequest(
+            "Password is managed by the campus directory".to_string(),
+        ));
+    }
+
+    let valid = verify(current_password, &user.password_hash)
+        .context("Failed to verify password")?;
+
+    if !valid {
+        record_security_event(&data.db, &http_req, "password_change", &claims.sub, Some(&claims.campus_id), Some("rejected: wrong current password")).await;
+        return Err(AppError::Unauthorized("Current password is incorrect".to_string()));
+    }
+
+    let password_hash = hash_password(new_password)?;
+
+    collection
+        .update_one(
+            doc! { "username": &claims.sub },
+            doc! { "$set": { "password_hash": password_hash } },
+            None,
+        )
+        .await
+        .context("Failed to update password")?;
+
+    // Sign out other sessions: their refresh tokens stop working.
+    let refresh_tokens: Collection<RefreshToken> = data.db.collection("refresh_tokens");
+    refresh_tokens
+        .delete_many(doc! { "username": &claims.sub }, None)
+        .await
+        .context("Failed to revoke refresh tokens")?;
+
+    record_security_event(&data.db, &http_req, "password_change", &claims.sub, Some(&claims.campus_id), None).await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Password changed successfully"
+    })))
+}
+
+/// POST /api/auth/refresh
+/// Public: exchanges a refresh token for a new access token. Refresh tokens
+/// are single-use; a new one is returned with a fresh lifetime.
+async fn refresh_token(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let req: RefreshRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let token = require_field(&req.refresh_token, "refresh_token")?;
+
+    let collection: Collection<RefreshToken> = data.db.collection("refresh_tokens");
+    let stored = collection
+        .find_one_and_delete(doc! { "token_hash": hash_token(token) }, None)
+        .await
+        .context("Failed to look up refresh token")?
+        .filter(|t| t.expires_at > Utc::now())
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired refresh token".to_string()))?;
+
+    let users: Collection<User> = data.db.collection("users");
+    let user = find_user_by_username(&users, &stored.username)
+        .await?
+        .ok_or_else(|| AppError::Unauthorized("Invalid or expired refresh token".to_string()))?;
+
+    if user.role != SUPER_ADMIN_ROLE && find_active_campus(&data.db, &user.campus_id).await?.is_none() {
+        return Err(AppError::Unauthorized("Campus is not active".to_string()));
+    }
+
+    record_security_event(&data.db, &http_req, "token_refresh", &user.username, Some(&user.campus_id), None).await;
+
+    let session = issue_session(&data, user, stored.remember_me).await?;
+
+    Ok(HttpResponse::Ok().json(session))
+}
+
+/// GET /api/auth/audit
+/// Admin-only, paginated view of `security_events`, newest first.
+/// Supports ?username=&event_type=&from=&to= filters.
+async fn get_audit_log(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    query: web::Query<AuditFilter>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_admin(&data.db, &claims).await?;
+
+    let collection: Collection<SecurityEvent> = data.db.collection("security_events");
+
+    // Admins only see their own campus. Events with no campus (such as
+    // failed logins for unknown usernames) span tenants, so only super_admin
+    // sees them, along with every campus unless it picks one.
+    let campus_filter = query.campus_id.as_deref().map(str::trim).filter(|c| !c.is_empty());
+    let mut filter = if claims.role == SUPER_ADMIN_ROLE {
+        campus_filter.map_or_else(|| doc! {}, |campus_id| doc! { "campus_id": campus_id })
+    } else {
+        if campus_filter.is_some_and(|campus_id| campus_id != claims.campus_id) {
+            return Err(AppError::Forbidden("Admins can only read their own campus's events".to_string()));
+        }
+        doc! { "campus_id": &claims.campus_id }
+    };
+    if let Some(username) = &query.username {
+        if !username.trim().is_empty() {
+            filter.insert("username", username.as_str());
+        }
+    }
+    if let Some(event_type) = &query.event_type {
+        if !event_type.trim().is_empty() {
+            filter.insert("event_type", event_type.as_str());
+        }
+    }
+
+    let mut range = doc! {};
+    if let Some(from) = &query.from {
+        let from = parse_timestamp(from, "from")?;
+        range.insert("$gte", mongodb::bson::DateTime::from_chrono(from));
+    }
+    if let Some(to) = &query.to {
+        let to = parse_timestamp(to, "to")?;
+        range.insert("$lte", mongodb::bson::DateTime::from_chrono(to));
+    }
+    if !range.is_empty() {
+        filter.insert("occurred_at", range);
+    }
+
+    let pagination = PaginationParams { page: query.page, limit: query.limit };
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .context("Failed to count security events")?;
+
+    let options = FindOptions::builder()
+        .skip(pagination.skip())
+        .limit(pagination.limit() as i64)
+        .sort(doc! { "occurred_at": -1 })
+        .build();
+
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .context("Failed to query security events")?;
+
+    let mut events = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let event = result.context("Failed to read security event from cursor")?;
+        events.push(SecurityEventResponse::from(event));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "data": events,
+        "pagination": {
+            "page": pagination.page(),
+            "limit": pagination.limit(),
+            "total": total,
+            "total_pages": (total as f64 / pagination.limit() as f64).ceil() as u64
+        }
+    })))
+}
+
+/// POST /api/profile
+/// Demonstrates: missing field detection via Option, structured error response.
+async fn create_profile(
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let req: CreateProfileRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    // Each field is Option — we explicitly check and return clear errors
+    let name = require_field(&req.name, "name")?;
+    let email = require_field(&req.email, "email")?;
+    let role = require_field(&req.role, "role")?;
+
+    validate_email(email)?;
+
+    Ok(HttpResponse::Created().json(ProfileResponse {
+        id: 1,
+        name: name.to_string(),
+        email: email.to_string(),
+        role: role.to_string(),
+        message: "Profile created successfully".to_string(),
+    }))
+}
+
+/// GET /api/auth/validate
+async fn validate_token(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&req, &data.jwt_keys)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "valid": true,
+        "claims": claims
+    })))
+}
+
+/// GET /api/auth/keys
+/// Super-admin view of signing keys and their windows. Secrets are never returned.
+async fn get_jwt_keys(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+
+    let keys = data.jwt_keys.read().expect("JWT key set lock poisoned");
+    let signing_kid = keys.signing_key().map(|k| k.kid.clone());
+    let now = Utc::now();
+
+    let listed: Vec<serde_json::Value> = keys
+        .keys
+        .iter()
+        .map(|k| serde_json::json!({
+            "kid": k.kid,
+            "active_from": k.active_from,
+            "active_until": k.active_until,
+            "signing": signing_kid.as_deref() == Some(k.kid.as_str()),
+            "verifying": k.verifies_at(now, keys.grace)
+        }))
+        .collect();
 
-    validate_email(email)?;
-    validate_role(role)?;
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "data": listed })))
+}
 
-    if password.len() < 6 {
-        return Err(AppError::BadRequest(
-            "Password must be at least 6 characters".to_string(),
-        ));
-    }
+/// POST /api/auth/keys
+/// Adds a signing key to the `jwt_keys` collection. Once `active_from` is
+/// reached it becomes the signing key; older keys keep verifying. Without an
+/// `active_from` the key waits one reload interval, so every service has
+/// loaded it before any token is signed with it.
+async fn create_jwt_key(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    body: web::Json<serde_json::Value>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
 
-    let collection: Collection<User> = data.db.collection("users");
+    let req: CreateJwtKeyRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
 
-    // Use service function — anyhow error auto-converts to AppError::Internal
-    let existing = find_user_by_username(&collection, username).await?;
-    if existing.is_some() {
-        return Err(AppError::BadRequest("Username already exists".to_string()));
+    let kid = require_field(&req.kid, "kid")?.trim();
+    if kid.is_empty() || kid == DEFAULT_KEY_ID {
+        return Err(AppError::BadRequest(format!(
+            "'kid' must be non-empty and not '{}'",
+            DEFAULT_KEY_ID
+        )));
     }
 
-    let password_hash = hash_password(password)?;
+    let secret = match &req.secret {
+        Some(secret) if secret.len() < 32 => {
+            return Err(AppError::BadRequest(
+                "'secret' must be at least 32 characters".to_string(),
+            ))
+        }
+        Some(secret) => secret.clone(),
+        None => new_challenge(),
+    };
 
-    let new_user = User {
-        id: None,
-        username: username.to_string(),
-        password_hash,
-        role: role.to_string(),
-        campus_id: campus_id.to_string(),
-        email: email.to_string(),
-        full_name: full_name.to_string(),
+    let active_from = match &req.active_from {
+        Some(value) => parse_timestamp(value, "active_from")?,
+        None => Utc::now() + Duration::seconds(JWT_KEY_REFRESH_SECS as i64),
     };
+    let active_until = req
+        .active_until
+        .as_deref()
+        .map(|value| parse_timestamp(value, "active_until"))
+        .transpose()?;
+    if active_until.is_some_and(|until| until <= active_from) {
+        return Err(AppError::BadRequest(
+            "'active_until' must be after 'active_from'".to_string(),
+        ));
+    }
+
+    let exists = data
+        .jwt_keys
+        .read()
+        .expect("JWT key set lock poisoned")
+        .keys
+        .iter()
+        .any(|k| k.kid == kid);
+    if exists {
+        return Err(AppError::BadRequest(format!("Key '{}' already exists", kid)));
+    }
 
+    let collection: Collection<JwtKey> = data.db.collection("jwt_keys");
     collection
-        .insert_one(new_user, None)
+        .insert_one(JwtKey {
+            kid: kid.to_string(),
+            secret,
+            active_from,
+            active_until,
+        }, None)
         .await
-        .context("Failed to insert user into database")?;
+        .context("Failed to store JWT key")?;
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "User registered successfully"
+    reload_jwt_keys(&data.db, &data.jwt_secret, &data.jwt_keys).await;
+
+    info!("JWT key '{}' added by {}", kid, claims.sub);
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Signing key created",
+        "kid": kid,
+        "active_from": active_from,
+        "active_until": active_until
     })))
 }
 
-/// POST /api/auth/login
-/// Demonstrates: Option-based field validation, Result for credential check.
-async fn login(
+/// PUT /api/auth/keys/{kid}
+/// Sets `active_until` on a key stored in `jwt_keys` to retire it.
+async fn retire_jwt_key(
     data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let req: LoginRequest = serde_json::from_value(body.into_inner())
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+
+    let kid = path.into_inner();
+    let req: RetireJwtKeyRequest = serde_json::from_value(body.into_inner())
         .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+    let active_until = parse_timestamp(require_field(&req.active_until, "active_until")?, "active_until")?;
 
-    let username = require_field(&req.username, "username")?;
-    let password = require_field(&req.password, "password")?;
+    let collection: Collection<JwtKey> = data.db.collection("jwt_keys");
+    let result = collection
+        .update_one(
+            doc! { "kid": &kid },
+            doc! { "$set": { "active_until": active_until.to_rfc3339_opts(SecondsFormat::AutoSi, true) } },
+            None,
+        )
+        .await
+        .context("Failed to retire JWT key")?;
 
-    let collection: Collection<User> = data.db.collection("users");
+    if result.matched_count == 0 {
+        return Err(AppError::NotFound(format!(
+            "Key '{}' not found in jwt_keys (keys from JWT_SECRET/JWT_KEYS are changed in config)",
+            kid
+        )));
+    }
 
-    // find_user_by_username returns Option<User> — None means user doesn't exist
-    let user = find_user_by_username(&collection, username)
-        .await?
-        .ok_or_else(|| AppError::Unauthorized("Invalid credentials".to_string()))?;
+    reload_jwt_keys(&data.db, &data.jwt_secret, &data.jwt_keys).await;
 
-    // verify returns Result<bool> — we propagate errors via anyhow context
-    let valid = verify(password, &user.password_hash)
-        .context("Failed to verify password")?;
+    info!("JWT key '{}' retired at {} by {}", kid, active_until, claims.sub);
 
-    if !valid {
-        return Err(AppError::Unauthorized("Invalid credentials".to_string()));
-    }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Signing key updated",
+        "kid": kid,
+        "active_until": active_until
+    })))
+}
 
-    let expiration = Utc::now()
-        .checked_add_signed(Duration::hours(24))
-        .ok_or_else(|| AppError::Internal(anyhow::anyhow!("Timestamp overflow")))?
-        .timestamp();
+/// POST /api/auth/impersonate/{username}
+/// Super-admin only. Issues a short-lived token for another user so support
+/// staff can reproduce issues; the token carries `impersonated_by` and cannot
+/// be refreshed.
+async fn impersonate(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+    forbid_impersonated(&claims)?;
 
-    let claims = Claims {
-        sub: user.username.clone(),
-        role: user.role.clone(),
-        campus_id: user.campus_id.clone(),
-        exp: expiration as usize,
-    };
+    let username = path.into_inner();
+    let collection: Collection<User> = data.db.collection("users");
+    let user = find_user_by_username(&collection, &username)
+        .await?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    if user.role == SUPER_ADMIN_ROLE {
+        return Err(AppError::Forbidden(
+            "Super-admin accounts cannot be impersonated".to_string(),
+        ));
+    }
 
-    let token = generate_token(&claims, &data.jwt_secret)?;
+    let mut impersonated = Claims::new(
+        user.username.clone(),
+        user.role.clone(),
+        user.campus_id.clone(),
+        Duration::minutes(IMPERSONATION_TTL_MINS),
+    )?;
+    impersonated.impersonated_by = Some(claims.sub.clone());
+    let token = generate_token(&impersonated, &data.jwt_keys)?;
 
-    Ok(HttpResponse::Ok().json(TokenResponse {
-        token,
-        user: UserInfo {
+    record_security_event(
+        &data.db,
+        &http_req,
+        "impersonation",
+        &user.username,
+        Some(&user.campus_id),
+        Some(&format!("impersonated by {}", claims.sub)),
+    )
+    .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "token": token,
+        "expires_in": IMPERSONATION_TTL_MINS * 60,
+        "impersonated_by": claims.sub,
+        "user": UserInfo {
             username: user.username,
             role: user.role,
             campus_id: user.campus_id,
             email: user.email,
             full_name: user.full_name,
-        },
-    }))
+        }
+    })))
 }
 
-/// POST /api/profile
-/// Demonstrates: missing field detection via Option, structured error response.
-async fn create_profile(
+/// GET /api/auth/roles
+/// Lists every role with the full set of roles it satisfies.
+async fn get_roles(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+) -> Result<HttpResponse, AppError> {
+    extract_claims(&http_req, &data.jwt_keys)?;
+
+    let roles = load_roles(&data.db).await?;
+    let listed: Vec<serde_json::Value> = roles
+        .iter()
+        .map(|role| serde_json::json!({
+            "name": role.name,
+            "description": role.description,
+            "inherits": role.inherits,
+            "built_in": role.built_in,
+            "effective": effective_roles(&roles, &role.name),
+            "created_at": role.created_at
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(listed))
+}
+
+/// GET /api/auth/roles/hierarchy
+/// Public, cacheable map of role -> every role it satisfies. Other services
+/// fetch this to answer "does role X satisfy Y" the same way auth does.
+async fn get_role_hierarchy(data: web::Data<AppState>) -> Result<HttpResponse, AppError> {
+    let roles = load_roles(&data.db).await?;
+    let hierarchy: BTreeMap<&str, BTreeSet<String>> = roles
+        .iter()
+        .map(|role| (role.name.as_str(), effective_roles(&roles, &role.name)))
+        .collect();
+
+    Ok(HttpResponse::Ok()
+        .insert_header((
+            header::CACHE_CONTROL,
+            format!("public, max-age={}", ROLE_HIERARCHY_MAX_AGE_SECS),
+        ))
+        .json(serde_json::json!({
+            "roles": hierarchy,
+            "max_age": ROLE_HIERARCHY_MAX_AGE_SECS
+        })))
+}
+
+/// POST /api/auth/roles
+async fn create_role(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
     body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let req: CreateProfileRequest = serde_json::from_value(body.into_inner())
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+
+    let req: RoleRequest = serde_json::from_value(body.into_inner())
         .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
 
-    // Each field is Option — we explicitly check and return clear errors
     let name = require_field(&req.name, "name")?;
-    let email = require_field(&req.email, "email")?;
-    let role = require_field(&req.role, "role")?;
+    validate_role_name(name)?;
+    let inherits = req.inherits.unwrap_or_default();
 
-    validate_email(email)?;
+    let roles = load_roles(&data.db).await?;
+    if roles.iter().any(|r| r.name == name) {
+        return Err(AppError::BadRequest("Role already exists".to_string()));
+    }
+    validate_inherits(&roles, name, &inherits)?;
 
-    Ok(HttpResponse::Created().json(ProfileResponse {
-        id: 1,
+    let role = Role {
+        id: None,
         name: name.to_string(),
-        email: email.to_string(),
-        role: role.to_string(),
-        message: "Profile created successfully".to_string(),
-    }))
+        description: req.description.unwrap_or_default(),
+        inherits,
+        built_in: false,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<Role> = data.db.collection("roles");
+    collection
+        .insert_one(&role, None)
+        .await
+        .context("Failed to insert role")?;
+
+    info!("Role '{}' created by {}", name, claims.sub);
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Role created successfully",
+        "role": role
+    })))
 }
 
-/// GET /api/auth/validate
-async fn validate_token(
+/// PUT /api/auth/roles/{name}
+/// Updates description and/or inherits. Built-in roles may be re-parented
+/// but not renamed or deleted.
+async fn update_role(
     data: web::Data<AppState>,
-    req: HttpRequest,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<serde_json::Value>,
 ) -> Result<HttpResponse, AppError> {
-    let auth_header = req
-        .headers()
-        .get("Authorization")
-        .ok_or_else(|| AppError::Unauthorized("No token provided".to_string()))?;
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
 
-    let auth_str = auth_header
-        .to_str()
-        .map_err(|_| AppError::Unauthorized("Malformed Authorization header".to_string()))?;
+    let name = path.into_inner();
+    let req: RoleRequest = serde_json::from_value(body.into_inner())
+        .map_err(|e| AppError::BadRequest(format!("Invalid JSON: {}", e)))?;
+
+    if req.name.as_deref().is_some_and(|n| n != name) {
+        return Err(AppError::BadRequest("Roles cannot be renamed".to_string()));
+    }
+
+    let roles = load_roles(&data.db).await?;
+    if !roles.iter().any(|r| r.name == name) {
+        return Err(AppError::NotFound("Role not found".to_string()));
+    }
 
-    if !auth_str.starts_with("Bearer ") {
-        return Err(AppError::Unauthorized(
-            "Authorization header must use Bearer scheme".to_string(),
+    let mut update = doc! {};
+    if let Some(description) = &req.description {
+        update.insert("description", description);
+    }
+    if let Some(inherits) = &req.inherits {
+        validate_inherits(&roles, &name, inherits)?;
+        update.insert("inherits", inherits);
+    }
+    if update.is_empty() {
+        return Err(AppError::BadRequest(
+            "Provide 'description' and/or 'inherits' to update".to_string(),
         ));
     }
 
-    let token = &auth_str[7..];
+    let collection: Collection<Role> = data.db.collection("roles");
+    collection
+        .update_one(doc! { "name": &name }, doc! { "$set": update }, None)
+        .await
+        .context("Failed to update role")?;
 
-    match decode::<Claims>(
-        token,
-        &DecodingKey::from_secret(data.jwt_secret.as_bytes()),
-        &Validation::new(Algorithm::HS256),
-    ) {
-        Ok(token_data) => Ok(HttpResponse::Ok().json(serde_json::json!({
-            "valid": true,
-            "claims": token_data.claims
-        }))),
-        Err(_) => Err(AppError::Unauthorized("Invalid or expired token".to_string())),
+    info!("Role '{}' updated by {}", name, claims.sub);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Role updated successfully",
+        "name": name
+    })))
+}
+
+/// DELETE /api/auth/roles/{name}
+/// Refused for built-in roles, roles still assigned to users and roles
+/// that other roles inherit.
+async fn delete_role(
+    data: web::Data<AppState>,
+    http_req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, AppError> {
+    let claims = extract_claims(&http_req, &data.jwt_keys)?;
+    require_super_admin(&claims)?;
+
+    let name = path.into_inner();
+    let roles = load_roles(&data.db).await?;
+    let role = roles
+        .iter()
+        .find(|r| r.name == name)
+        .ok_or_else(|| AppError::NotFound("Role not found".to_string()))?;
+
+    if role.built_in {
+        return Err(AppError::BadRequest("Built-in roles cannot be deleted".to_string()));
+    }
+    if let Some(child) = roles.iter().find(|r| r.inherits.contains(&name)) {
+        return Err(AppError::BadRequest(format!(
+            "Role is inherited by '{}'",
+            child.name
+        )));
+    }
+
+    let users: Collection<User> = data.db.collection("users");
+    let assigned = users
+        .count_documents(doc! { "role": &name }, None)
+        .await
+        .context("Failed to count users with role")?;
+    if assigned > 0 {
+        return Err(AppError::BadRequest(format!(
+            "Role is assigned to {} user(s)",
+            assigned
+        )));
+    }
+
+    let collection: Collection<Role> = data.db.collection("roles");
+    collection
+        .delete_one(doc! { "name": &name }, None)
+        .await
+        .context("Failed to delete role")?;
+
+    info!("Role '{}' deleted by {}", name, claims.sub);
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Role deleted successfully"
+    })))
+}
+
+// ── Bootstrap ─────────────────────────────────────────────────────────────────
+
+/// Creates the platform super admin from SUPER_ADMIN_USERNAME / SUPER_ADMIN_PASSWORD
+/// the first time the service starts against an empty database. Campuses and
+/// their admins are then created through POST /api/auth/campuses.
+async fn bootstrap_super_admin(db: &mongodb::Database) -> anyhow::Result<()> {
+    let users: Collection<User> = db.collection("users");
+
+    let existing = users
+        .find_one(doc! { "role": SUPER_ADMIN_ROLE }, None)
+        .await
+        .context("Database error while looking up super admin")?;
+    if existing.is_some() {
+        return Ok(());
     }
+
+    let (username, password) = match (env::var("SUPER_ADMIN_USERNAME"), env::var("SUPER_ADMIN_PASSWORD")) {
+        (Ok(u), Ok(p)) if !u.trim().is_empty() && p.len() >= 6 => (u, p),
+        _ => {
+            warn!("No super admin exists; set SUPER_ADMIN_USERNAME and SUPER_ADMIN_PASSWORD to create one");
+            return Ok(());
+        }
+    };
+
+    users
+        .insert_one(User {
+            id: None,
+            username: username.clone(),
+            password_hash: hash_password(&password)?,
+            role: SUPER_ADMIN_ROLE.to_string(),
+            campus_id: PLATFORM_CAMPUS_ID.to_string(),
+            email: env::var("SUPER_ADMIN_EMAIL").unwrap_or_default(),
+            full_name: "Platform Administrator".to_string(),
+            auth_source: None,
+        }, None)
+        .await
+        .context("Failed to insert super admin")?;
+
+    info!("Bootstrapped super admin '{}'", username);
+    Ok(())
 }
 
 // ── Main ──────────────────────────────────────────────────────────────────────
@@ -555,10 +3747,90 @@ async fn main() -> std::io::Result<()> {
 
     let db = client.database(&database_name);
 
+    // Two campuses or accounts under one name cannot be merged safely here:
+    // an operator has to decide which one is real
+    for (collection, field) in [("campuses", "campus_id"), ("users", "username")] {
+        let duplicates = find_duplicate_values(&db, collection, field)
+            .await
+            .expect("Failed to check for duplicates");
+        if !duplicates.is_empty() {
+            eprintln!("These {}.{} values are used more than once. Rename or remove the extra documents (see SETUP.md) and restart:", collection, field);
+            for (value, count) in &duplicates {
+                eprintln!("   {} ({} documents)", value, count);
+            }
+            std::process::exit(1);
+        }
+    }
+
+    create_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+
+    bootstrap_super_admin(&db)
+        .await
+        .expect("Failed to bootstrap super admin");
+
+    seed_roles(&db)
+        .await
+        .expect("Failed to seed roles");
+
+    migrate_security_event_timestamps(&db)
+        .await
+        .expect("Failed to migrate security event timestamps");
+
     println!("Connected to MongoDB");
     println!("Server starting on http://127.0.0.1:{}", port);
 
-    let app_state = web::Data::new(AppState { db, jwt_secret: jwt_secret.clone() });
+    let ldap = LdapConfig::from_env();
+    if let Some(config) = &ldap {
+        println!("LDAP authentication enabled: {}", config.url);
+    }
+
+    let saml = SamlConfig::from_env();
+    if let Some(config) = &saml {
+        println!("SAML SSO enabled for IdP: {}", config.idp_entity_id);
+    }
+
+    let tokens = TokenConfig::from_env();
+
+    let jwt_keys: SharedKeySet = Arc::new(RwLock::new(
+        load_jwt_keys(&db, &jwt_secret, tokens.access_ttl)
+            .await
+            .expect("Failed to load JWT signing keys"),
+    ));
+
+    // Pick up keys added or retired directly in the database.
+    {
+        let db = db.clone();
+        let jwt_secret = jwt_secret.clone();
+        let jwt_keys = jwt_keys.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(JWT_KEY_REFRESH_SECS));
+            loop {
+                interval.tick().await;
+                reload_jwt_keys(&db, &jwt_secret, &jwt_keys).await;
+            }
+        });
+    }
+
+    let app_state = web::Data::new(AppState {
+        db,
+        jwt_secret,
+        jwt_keys: jwt_keys.clone(),
+        tokens,
+        ldap,
+        saml,
+        webauthn: WebAuthnConfig::from_env(),
+        smtp: SmtpConfig::from_env(),
+        email_verification_url: env::var("EMAIL_VERIFICATION_URL")
+            .unwrap_or_else(|_| "http://localhost:4200/verify-email".to_string()),
+        trusted_proxies: env::var("TRUSTED_PROXIES")
+            .unwrap_or_default()
+            .split(',')
+            .filter(|ip| !ip.trim().is_empty())
+            .map(|ip| ip.trim().parse().expect("TRUSTED_PROXIES must be a comma-separated list of IP addresses"))
+            .collect(),
+    });
 
     HttpServer::new(move || {
         // Explicit CORS: only allow the Angular dev server origin.
@@ -579,7 +3851,7 @@ async fn main() -> std::io::Result<()> {
             // Log every request: METHOD /path -> STATUS
             .wrap(RequestLogger)
             // JWT auth gate — blocks protected routes without a valid token
-            .wrap(JwtAuth { jwt_secret: jwt_secret.clone() })
+            .wrap(JwtAuth { keys: jwt_keys.clone() })
             .app_data(app_state.clone())
             // Return JSON for malformed request bodies instead of plain-text 400
             .app_data(
@@ -595,11 +3867,367 @@ async fn main() -> std::io::Result<()> {
             .route("/health", web::get().to(health_check))
             .route("/api/auth/register", web::post().to(register))
             .route("/api/auth/login", web::post().to(login))
+            .route("/api/auth/saml/metadata", web::get().to(saml_metadata))
+            .route("/api/auth/saml/login", web::get().to(saml_login))
+            .route("/api/auth/saml/acs", web::post().to(saml_acs))
+            .route("/api/auth/webauthn/login/start", web::post().to(passkey_login_start))
+            .route("/api/auth/webauthn/login/finish", web::post().to(passkey_login_finish))
+            .route("/api/auth/me/email/verify", web::post().to(verify_email_change))
+            .route("/api/auth/roles/hierarchy", web::get().to(get_role_hierarchy))
+            .route("/api/auth/refresh", web::post().to(refresh_token))
             // Protected routes (JWT middleware enforces auth above)
             .route("/api/auth/validate", web::get().to(validate_token))
+            .route("/api/auth/me", web::get().to(get_me))
+            .route("/api/auth/me", web::put().to(update_me))
+            .route("/api/auth/password", web::put().to(change_password))
+            .route("/api/auth/webauthn/register/start", web::post().to(passkey_register_start))
+            .route("/api/auth/webauthn/register/finish", web::post().to(passkey_register_finish))
+            .route("/api/auth/audit", web::get().to(get_audit_log))
+            .route("/api/auth/campuses", web::post().to(create_campus))
+            .route("/api/auth/campuses", web::get().to(get_campuses))
+            .route("/api/auth/impersonate/{username}", web::post().to(impersonate))
+            .route("/api/auth/roles", web::get().to(get_roles))
+            .route("/api/auth/roles", web::post().to(create_role))
+            .route("/api/auth/roles/{name}", web::put().to(update_role))
+            .route("/api/auth/roles/{name}", web::delete().to(delete_role))
+            .route("/api/auth/keys", web::get().to(get_jwt_keys))
+            .route("/api/auth/keys", web::post().to(create_jwt_key))
+            .route("/api/auth/keys/{kid}", web::put().to(retire_jwt_key))
             .route("/api/profile", web::post().to(create_profile))
     })
     .bind(format!("127.0.0.1:{}", port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64::Engine;
+    use rsa::RsaPrivateKey;
+    use std::sync::OnceLock;
+
+    const SP: &str = "https://campus.example/sp";
+    const ACS: &str = "https://campus.example/api/auth/saml/acs";
+    const IDP: &str = "https://idp.example/metadata";
+
+    fn c14n(xml: &str, path: &[&str]) -> String {
+        let doc = roxmltree::Document::parse(xml).unwrap();
+        let mut node = doc.root_element();
+        for name in path {
+            node = node.children().find(|c| c.tag_name().name() == *name).unwrap();
+        }
+        exc_c14n(node, None, &[])
+    }
+
+    #[test]
+    fn c14n_sorts_attributes_and_drops_unused_namespaces() {
+        let xml = r#"<r:Root xmlns:r="urn:r" xmlns:unused="urn:u" b="2" a="1"><r:Child>x &amp; y</r:Child><Empty/></r:Root>"#;
+        assert_eq!(
+            c14n(xml, &[]),
+            r#"<r:Root xmlns:r="urn:r" a="1" b="2"><r:Child>x &amp; y</r:Child><Empty></Empty></r:Root>"#
+        );
+    }
+
+    #[test]
+    fn c14n_of_subtree_declares_inherited_namespaces() {
+        let xml = r#"<Root xmlns="urn:d" xmlns:x="urn:x"><Child x:b="1" a="2"/></Root>"#;
+        assert_eq!(c14n(xml, &["Child"]), r#"<Child xmlns="urn:d" xmlns:x="urn:x" a="2" x:b="1"></Child>"#);
+    }
+
+    #[test]
+    fn c14n_keeps_written_prefix_when_two_prefixes_share_a_namespace() {
+        let xml = r#"<a:Root xmlns:a="urn:same" xmlns:b="urn:same"><b:Child b:attr="v"/></a:Root>"#;
+        assert_eq!(
+            c14n(xml, &[]),
+            r#"<a:Root xmlns:a="urn:same"><b:Child xmlns:b="urn:same" b:attr="v"></b:Child></a:Root>"#
+        );
+    }
+
+    fn idp_key() -> &'static RsaPrivateKey {
+        static KEY: OnceLock<RsaPrivateKey> = OnceLock::new();
+        KEY.get_or_init(|| RsaPrivateKey::new(&mut rand::thread_rng(), 1024).unwrap())
+    }
+
+    fn config() -> SamlConfig {
+        SamlConfig {
+            sp_entity_id: SP.to_string(),
+            acs_url: ACS.to_string(),
+            idp_entity_id: IDP.to_string(),
+            idp_sso_url: "https://idp.example/sso".to_string(),
+            idp_key: idp_key().to_public_key(),
+            role_attr: "role".to_string(),
+            campus_attr: "campus_id".to_string(),
+            email_attr: "email".to_string(),
+            name_attr: "displayName".to_string(),
+            default_campus_id: None,
+            post_login_redirect: None,
+        }
+    }
+
+    /// An assertion with `{signature}` where the enveloped Signature goes.
+    fn assertion_template(id: &str, name_id: &str) -> String {
+        let now = Utc::now();
+        let not_before = (now - Duration::minutes(1)).to_rfc3339_opts(SecondsFormat::Secs, true);
+        let not_on_or_after = (now + Duration::minutes(5)).to_rfc3339_opts(SecondsFormat::Secs, true);
+        format!(
+            concat!(
+                r#"<saml:Assertion xmlns:saml="{saml}" ID="{id}" Version="2.0" IssueInstant="{nb}">"#,
+                r#"<saml:Issuer>{idp}</saml:Issuer>{{signature}}"#,
+                r#"<saml:Subject><saml:NameID>{name_id}</saml:NameID>"#,
+                r#"<saml:SubjectConfirmation Method="urn:oasis:names:tc:SAML:2.0:cm:bearer">"#,
+                r#"<saml:SubjectConfirmationData InResponseTo="_req1" Recipient="{acs}" NotOnOrAfter="{noa}"/>"#,
+                r#"</saml:SubjectConfirmation></saml:Subject>"#,
+                r#"<saml:Conditions NotBefore="{nb}" NotOnOrAfter="{noa}">"#,
+                r#"<saml:AudienceRestriction><saml:Audience>{sp}</saml:Audience></saml:AudienceRestriction>"#,
+                r#"</saml:Conditions>"#,
+                r#"<saml:AttributeStatement><saml:Attribute Name="role">"#,
+                r#"<saml:AttributeValue>teacher</saml:AttributeValue></saml:Attribute></saml:AttributeStatement>"#,
+                r#"</saml:Assertion>"#
+            ),
+            saml = NS_SAML,
+            id = id,
+            idp = IDP,
+            name_id = name_id,
+            acs = ACS,
+            sp = SP,
+            nb = not_before,
+            noa = not_on_or_after,
+        )
+    }
+
+    /// Fills in an enveloped RSA-SHA256 signature over the assertion, the
+    /// way an IdP would.
+    fn sign_assertion(template: &str, id: &str) -> String {
+        let b64 = base64::engine::general_purpose::STANDARD;
+
+        let unsigned = template.replace("{signature}", "");
+        let doc = roxmltree::Document::parse(&unsigned).unwrap();
+        let digest = b64.encode(Sha256::digest(exc_c14n(doc.root_element(), None, &[]).as_bytes()));
+
+        let signed_info = format!(
+            concat!(
+                r#"<ds:SignedInfo xmlns:ds="{ds}">"#,
+                r#"<ds:CanonicalizationMethod Algorithm="{c14n}"/>"#,
+                r#"<ds:SignatureMethod Algorithm="{rsa}"/>"#,
+                r##"<ds:Reference URI="#{id}"><ds:Transforms>"##,
+                r#"<ds:Transform Algorithm="{env}"/><ds:Transform Algorithm="{c14n}"/>"#,
+                r#"</ds:Transforms><ds:DigestMethod Algorithm="{sha}"/>"#,
+                r#"<ds:DigestValue>{digest}</ds:DigestValue></ds:Reference></ds:SignedInfo>"#
+            ),
+            ds = NS_DSIG,
+            c14n = ALG_EXC_C14N,
+            rsa = ALG_RSA_SHA256,
+            env = ALG_ENVELOPED,
+            sha = ALG_SHA256,
+            id = id,
+            digest = digest,
+        );
+        let doc = roxmltree::Document::parse(&signed_info).unwrap();
+        let canonical = exc_c14n(doc.root_element(), None, &[]);
+        let signature = idp_key()
+            .sign(Pkcs1v15Sign::new::<Sha256>(), &Sha256::digest(canonical.as_bytes()))
+            .unwrap();
+
+        template.replace(
+            "{signature}",
+            &format!(
+                r#"<ds:Signature xmlns:ds="{}">{}<ds:SignatureValue>{}</ds:SignatureValue></ds:Signature>"#,
+                NS_DSIG,
+                signed_info,
+                b64.encode(signature)
+            ),
+        )
+    }
+
+    fn response(assertions: &str) -> String {
+        format!(
+            concat!(
+                r#"<samlp:Response xmlns:samlp="{}" ID="_resp1" Version="2.0" InResponseTo="_req1">"#,
+                r#"<samlp:Status><samlp:StatusCode Value="urn:oasis:names:tc:SAML:2.0:status:Success"/></samlp:Status>"#,
+                r#"{}</samlp:Response>"#
+            ),
+            NS_SAMLP, assertions
+        )
+    }
+
+    fn signed_response() -> String {
+        response(&sign_assertion(&assertion_template("_a1", "alice"), "_a1"))
+    }
+
+    #[test]
+    fn accepts_signed_response() {
+        let assertion = validate_saml_response(&signed_response(), &config()).unwrap();
+        assert_eq!(assertion.id, "_a1");
+        assert_eq!(assertion.name_id, "alice");
+        assert_eq!(assertion.in_response_to.as_deref(), Some("_req1"));
+        assert_eq!(assertion.attributes.get("role").map(String::as_str), Some("teacher"));
+    }
+
+    #[test]
+    fn rejects_tampered_assertion() {
+        let tampered = signed_response().replace(">alice<", ">mallory<");
+        let err = validate_saml_response(&tampered, &config()).unwrap_err();
+        assert!(err.to_string().contains("Digest mismatch"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_signature_wrapping() {
+        let signed = sign_assertion(&assertion_template("_a1", "alice"), "_a1");
+        let forged = assertion_template("_evil", "mallory").replace("{signature}", "");
+
+        // A second, unsigned assertion beside the signed one
+        let xml = response(&format!("{}{}", forged, signed));
+        assert!(validate_saml_response(&xml, &config()).is_err());
+
+        // The signed assertion hidden away and a forgery reusing its ID
+        let forged_same_id = forged.replace(r#"ID="_evil""#, r#"ID="_a1""#);
+        let xml = response(&format!("<samlp:Extensions>{}</samlp:Extensions>{}", signed, forged_same_id));
+        assert!(validate_saml_response(&xml, &config()).is_err());
+
+        // Signature lifted onto the forgery: the reference no longer matches
+        let end_tag = "</ds:Signature>";
+        let signature = &signed[signed.find("<ds:Signature").unwrap()..signed.find(end_tag).unwrap() + end_tag.len()];
+        let moved = forged.replace(r#"ID="_evil""#, r#"ID="_a2""#).replace("</saml:Issuer>", &format!("</saml:Issuer>{}", signature));
+        let err = validate_saml_response(&response(&moved), &config()).unwrap_err();
+        assert!(err.to_string().contains("does not cover"), "{}", err);
+    }
+
+    #[test]
+    fn used_assertion_is_kept_while_it_could_still_validate() {
+        let assertion = validate_saml_response(&signed_response(), &config()).unwrap();
+        let used = UsedSamlAssertion::of(&assertion);
+        assert_eq!(used.assertion_id, assertion.id);
+        assert_eq!(used.keep_until, assertion.expires + Duration::seconds(SAML_CLOCK_SKEW_SECS));
+
+        // Stored as a BSON date, which the TTL index requires
+        let stored = mongodb::bson::to_document(&used).unwrap();
+        assert!(stored.get_datetime("keep_until").is_ok());
+    }
+
+    fn webauthn_config() -> WebAuthnConfig {
+        WebAuthnConfig {
+            rp_id: "campus.example".to_string(),
+            rp_name: "CampusConnect".to_string(),
+            origin: "https://campus.example".to_string(),
+        }
+    }
+
+    /// rpIdHash | flags | signCount, as sent with an assertion.
+    fn authenticator_data(rp_id: &str, flags: u8, sign_count: u32) -> Vec<u8> {
+        let mut data = Sha256::digest(rp_id.as_bytes()).to_vec();
+        data.push(flags);
+        data.extend_from_slice(&sign_count.to_be_bytes());
+        data
+    }
+
+    #[test]
+    fn parses_assertion_authenticator_data() {
+        let parsed = parse_authenticator_data(&authenticator_data("campus.example", AUTH_DATA_USER_PRESENT, 7)).unwrap();
+        assert_eq!(parsed.sign_count, 7);
+        assert!(parsed.attested_credential.is_none());
+        verify_rp_and_presence(&parsed, &webauthn_config()).unwrap();
+    }
+
+    #[test]
+    fn rejects_truncated_authenticator_data() {
+        let data = authenticator_data("campus.example", AUTH_DATA_USER_PRESENT | AUTH_DATA_ATTESTED, 0);
+        assert!(parse_authenticator_data(&[]).is_err());
+        assert!(parse_authenticator_data(&data[..36]).is_err());
+
+        // Attested flag set but no attestedCredentialData follows
+        assert!(parse_authenticator_data(&data).is_err());
+
+        // credentialIdLength claims more bytes than are present
+        let mut short_id = data.clone();
+        short_id.extend_from_slice(&[0; 16]);
+        short_id.extend_from_slice(&8u16.to_be_bytes());
+        short_id.extend_from_slice(&[1, 2, 3]);
+        assert!(parse_authenticator_data(&short_id).is_err());
+
+        // credentialPublicKey cut off mid-CBOR
+        let mut short_key = data;
+        short_key.extend_from_slice(&[0; 16]);
+        short_key.extend_from_slice(&1u16.to_be_bytes());
+        short_key.push(9);
+        short_key.extend_from_slice(&[0xa5, 0x01]);
+        assert!(parse_authenticator_data(&short_key).is_err());
+    }
+
+    #[test]
+    fn rejects_bad_rp_id_hash() {
+        let parsed = parse_authenticator_data(&authenticator_data("evil.example", AUTH_DATA_USER_PRESENT, 1)).unwrap();
+        let err = verify_rp_and_presence(&parsed, &webauthn_config()).unwrap_err();
+        assert!(err.to_string().contains("different relying party"), "{}", err);
+
+        let parsed = parse_authenticator_data(&authenticator_data("campus.example", 0, 1)).unwrap();
+        assert!(verify_rp_and_presence(&parsed, &webauthn_config()).is_err());
+    }
+
+    #[test]
+    fn rejects_counter_regression() {
+        let cases: &[(i64, u32, Option<i64>)] = &[
+            (0, 0, Some(0)),
+            (0, 1, Some(1)),
+            (5, 6, Some(6)),
+            (5, 5, None),
+            (5, 4, None),
+            (5, 0, None),
+        ];
+        for &(stored, reported, expected) in cases {
+            assert_eq!(check_sign_count(stored, reported).ok(), expected, "{} -> {}", stored, reported);
+        }
+    }
+
+    #[test]
+    fn verifies_es256_assertion_signature() {
+        use p256::ecdsa::{signature::Signer, SigningKey};
+
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        let point = signing_key.verifying_key().to_encoded_point(false);
+        let int = |i: i64| ciborium::Value::Integer(i.into());
+        let cose_key = ciborium::Value::Map(vec![
+            (int(1), int(2)),
+            (int(3), int(COSE_ALG_ES256)),
+            (int(-1), int(1)),
+            (int(-2), ciborium::Value::Bytes(point.x().unwrap().to_vec())),
+            (int(-3), ciborium::Value::Bytes(point.y().unwrap().to_vec())),
+        ]);
+        let mut cose_bytes = Vec::new();
+        ciborium::ser::into_writer(&cose_key, &mut cose_bytes).unwrap();
+
+        let auth_data = authenticator_data("campus.example", AUTH_DATA_USER_PRESENT, 3);
+        let client_data = br#"{"type":"webauthn.get","challenge":"abc","origin":"https://campus.example"}"#;
+        let mut signed = auth_data.clone();
+        signed.extend_from_slice(&Sha256::digest(client_data));
+        let signature: p256::ecdsa::Signature = signing_key.sign(&signed);
+        let der = signature.to_der();
+
+        verify_passkey_signature(&cose_bytes, &auth_data, client_data, der.as_bytes()).unwrap();
+
+        // A bumped counter is not covered by the signature
+        let replayed = authenticator_data("campus.example", AUTH_DATA_USER_PRESENT, 4);
+        assert!(verify_passkey_signature(&cose_bytes, &replayed, client_data, der.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn client_ip_ignores_forwarded_for_from_untrusted_peers() {
+        let ip = |s: &str| s.parse::<std::net::IpAddr>().unwrap();
+        let proxy = ip("10.0.0.2");
+        let cases = [
+            // (peer, X-Forwarded-For, trusted proxies, recorded address)
+            ("203.0.113.7", None, vec![], "203.0.113.7"),
+            ("203.0.113.7", Some("198.51.100.1"), vec![], "203.0.113.7"),
+            ("203.0.113.7", Some("198.51.100.1"), vec![proxy], "203.0.113.7"),
+            ("10.0.0.2", Some("198.51.100.1"), vec![proxy], "198.51.100.1"),
+            // A spoofed entry the client put in front of the real one
+            ("10.0.0.2", Some("1.2.3.4, 198.51.100.1"), vec![proxy], "198.51.100.1"),
+            ("10.0.0.2", Some("198.51.100.1, 10.0.0.3"), vec![proxy, ip("10.0.0.3")], "198.51.100.1"),
+            ("10.0.0.2", Some("not-an-ip"), vec![proxy], "10.0.0.2"),
+            ("10.0.0.2", None, vec![proxy], "10.0.0.2"),
+        ];
+        for (peer, forwarded_for, trusted, expected) in cases {
+            assert_eq!(forwarded_client(ip(peer), forwarded_for, &trusted), ip(expected), "{} {:?}", peer, forwarded_for);
+        }
+    }
+}
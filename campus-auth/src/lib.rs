@@ -0,0 +1,225 @@
+//! JWT keys shared by every CampusConnect service.
+//!
+//! The auth service signs access tokens with HMAC keys kept in JWT_SECRET,
+//! the JWT_KEYS variable and the `jwt_keys` collection. Every other service
+//! verifies them with the same key set, so rotation works the same way
+//! everywhere.
+
+use chrono::{DateTime, Duration, Utc};
+use futures::stream::StreamExt;
+use jsonwebtoken::{decode, decode_header, get_current_timestamp, Algorithm, DecodingKey, Validation};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+/// Tokens carry no `kid` before rotation was introduced; JWT_SECRET is
+/// registered under this id so they keep validating.
+pub const DEFAULT_KEY_ID: &str = "default";
+
+/// How often services reload keys from the `jwt_keys` collection.
+pub const JWT_KEY_REFRESH_SECS: u64 = 300;
+
+/// Clock skew tolerated when checking exp/nbf/iat.
+pub const JWT_LEEWAY_SECS: u64 = 60;
+
+/// An HMAC signing key with its activation window. A key signs new tokens
+/// between `active_from` and `active_until`, and keeps verifying for one
+/// access-token lifetime after that so already-issued tokens are not cut short.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct JwtKey {
+    pub kid: String,
+    pub secret: String,
+    /// Keys configured before activation windows existed have none.
+    #[serde(default = "unix_epoch")]
+    pub active_from: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active_until: Option<DateTime<Utc>>,
+}
+
+fn unix_epoch() -> DateTime<Utc> {
+    DateTime::UNIX_EPOCH
+}
+
+impl JwtKey {
+    pub fn signs_at(&self, now: DateTime<Utc>) -> bool {
+        self.active_from <= now && self.active_until.is_none_or(|until| now < until)
+    }
+
+    pub fn verifies_at(&self, now: DateTime<Utc>, grace: Duration) -> bool {
+        self.active_from <= now && self.active_until.is_none_or(|until| now < until + grace)
+    }
+}
+
+/// All known keys, merged from JWT_SECRET, JWT_KEYS and the `jwt_keys`
+/// collection (later sources win on duplicate kid).
+pub struct JwtKeySet {
+    pub keys: Vec<JwtKey>,
+    /// How long a retired key keeps verifying: the access-token lifetime
+    /// (ACCESS_TOKEN_TTL_MINS).
+    pub grace: Duration,
+}
+
+pub type SharedKeySet = Arc<RwLock<JwtKeySet>>;
+
+impl JwtKeySet {
+    /// The most recently activated key that is currently allowed to sign.
+    pub fn signing_key(&self) -> Option<&JwtKey> {
+        let now = Utc::now();
+        self.keys
+            .iter()
+            .filter(|k| k.signs_at(now))
+            .max_by_key(|k| k.active_from)
+    }
+
+    /// The key named by the token's `kid`, if it still verifies.
+    pub fn key_for(&self, token: &str) -> Option<&JwtKey> {
+        let header = decode_header(token).ok()?;
+        let kid = header.kid.as_deref().unwrap_or(DEFAULT_KEY_ID);
+        let now = Utc::now();
+        self.keys.iter().find(|k| k.kid == kid && k.verifies_at(now, self.grace))
+    }
+
+    /// Verifies the signature with the key named by `kid`, checks exp and nbf,
+    /// and rejects tokens whose `iat` lies in the future (forged or badly
+    /// mis-clocked).
+    pub fn decode<C: DeserializeOwned>(&self, token: &str) -> Option<C> {
+        let key = self.key_for(token)?;
+        let claims = decode::<serde_json::Value>(
+            token,
+            &DecodingKey::from_secret(key.secret.as_bytes()),
+            &token_validation(),
+        )
+        .ok()?
+        .claims;
+
+        let iat = claims.get("iat")?.as_u64()?;
+        if iat > get_current_timestamp() + JWT_LEEWAY_SECS {
+            return None;
+        }
+        serde_json::from_value(claims).ok()
+    }
+}
+
+/// HS256 validation that also requires `iat` and `nbf` and enforces `nbf`.
+pub fn token_validation() -> Validation {
+    let mut validation = Validation::new(Algorithm::HS256);
+    validation.set_required_spec_claims(&["exp", "iat", "nbf"]);
+    validation.validate_nbf = true;
+    validation.leeway = JWT_LEEWAY_SECS;
+    validation
+}
+
+/// Keeps the last key for each kid, in the order the kids were last seen.
+fn merge_keys(keys: Vec<JwtKey>) -> Vec<JwtKey> {
+    let mut merged: Vec<JwtKey> = Vec::with_capacity(keys.len());
+    for key in keys {
+        merged.retain(|k| k.kid != key.kid);
+        merged.push(key);
+    }
+    merged
+}
+
+/// Builds the key set from JWT_SECRET, the JWT_KEYS JSON array and the
+/// `jwt_keys` collection.
+pub async fn load_jwt_keys(db: &mongodb::Database, jwt_secret: &str, grace: Duration) -> Result<JwtKeySet, String> {
+    let mut keys = vec![JwtKey {
+        kid: DEFAULT_KEY_ID.to_string(),
+        secret: jwt_secret.to_string(),
+        active_from: DateTime::UNIX_EPOCH,
+        active_until: None,
+    }];
+
+    if let Ok(raw) = std::env::var("JWT_KEYS") {
+        let configured: Vec<JwtKey> =
+            serde_json::from_str(&raw).map_err(|e| format!("JWT_KEYS must be a JSON array of keys: {}", e))?;
+        keys.extend(configured);
+    }
+
+    let collection: mongodb::Collection<JwtKey> = db.collection("jwt_keys");
+    let mut cursor = collection
+        .find(None, None)
+        .await
+        .map_err(|e| format!("Failed to query JWT keys: {}", e))?;
+    while let Some(result) = cursor.next().await {
+        keys.push(result.map_err(|e| format!("Failed to read JWT key from cursor: {}", e))?);
+    }
+
+    Ok(JwtKeySet { keys: merge_keys(keys), grace })
+}
+
+/// Reloads `keys` in place; on failure the previous set stays in use.
+pub async fn reload_jwt_keys(db: &mongodb::Database, jwt_secret: &str, keys: &SharedKeySet) {
+    let grace = keys.read().expect("JWT key set lock poisoned").grace;
+    match load_jwt_keys(db, jwt_secret, grace).await {
+        Ok(fresh) => *keys.write().expect("JWT key set lock poisoned") = fresh,
+        Err(e) => log::warn!("Failed to reload JWT keys: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use jsonwebtoken::{encode, EncodingKey, Header};
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Claims {
+        sub: String,
+        iat: usize,
+    }
+
+    fn key(kid: &str, secret: &str) -> JwtKey {
+        JwtKey {
+            kid: kid.to_string(),
+            secret: secret.to_string(),
+            active_from: DateTime::UNIX_EPOCH,
+            active_until: None,
+        }
+    }
+
+    fn token(kid: Option<&str>, secret: &str, iat: u64) -> String {
+        let now = get_current_timestamp();
+        let claims = serde_json::json!({ "sub": "alice", "iat": iat, "nbf": now, "exp": now + 600 });
+        let header = Header { kid: kid.map(str::to_string), ..Header::default() };
+        encode(&header, &claims, &EncodingKey::from_secret(secret.as_bytes())).unwrap()
+    }
+
+    #[test]
+    fn later_sources_win_on_duplicate_kid() {
+        let merged = merge_keys(vec![key("default", "env"), key("k1", "old"), key("k1", "new")]);
+        let secrets: Vec<_> = merged.iter().map(|k| (k.kid.as_str(), k.secret.as_str())).collect();
+        assert_eq!(secrets, [("default", "env"), ("k1", "new")]);
+    }
+
+    #[test]
+    fn decodes_with_the_key_named_by_kid() {
+        let keys = JwtKeySet { keys: vec![key(DEFAULT_KEY_ID, "s0"), key("k1", "s1")], grace: Duration::minutes(15) };
+        let now = get_current_timestamp();
+
+        let claims: Claims = keys.decode(&token(Some("k1"), "s1", now)).unwrap();
+        assert_eq!(claims.sub, "alice");
+        assert!(keys.decode::<Claims>(&token(None, "s0", now)).is_some());
+        assert!(keys.decode::<Claims>(&token(Some("k1"), "s0", now)).is_none());
+        assert!(keys.decode::<Claims>(&token(Some("k2"), "s1", now)).is_none());
+    }
+
+    #[test]
+    fn retired_key_verifies_only_within_grace() {
+        let mut retired = key("k1", "s1");
+        retired.active_until = Some(Utc::now() - Duration::minutes(10));
+        let mut keys = JwtKeySet { keys: vec![retired], grace: Duration::minutes(15) };
+        let jwt = token(Some("k1"), "s1", get_current_timestamp());
+
+        assert!(keys.decode::<Claims>(&jwt).is_some());
+        assert!(keys.signing_key().is_none());
+        keys.grace = Duration::minutes(5);
+        assert!(keys.decode::<Claims>(&jwt).is_none());
+    }
+
+    #[test]
+    fn rejects_iat_in_the_future() {
+        let keys = JwtKeySet { keys: vec![key("k1", "s1")], grace: Duration::minutes(15) };
+        let now = get_current_timestamp();
+
+        assert!(keys.decode::<Claims>(&token(Some("k1"), "s1", now + JWT_LEEWAY_SECS)).is_some());
+        assert!(keys.decode::<Claims>(&token(Some("k1"), "s1", now + JWT_LEEWAY_SECS + 60)).is_none());
+    }
+}
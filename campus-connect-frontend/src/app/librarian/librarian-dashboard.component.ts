@@ -1034,8 +1034,8 @@ export class LibrarianDashboardComponent implements OnInit {
       error: () => {}
     });
 
-    this.libraryService.getBooks().subscribe({
-      next: (data: any) => this.allBooks = data || [],
+    this.libraryService.getAllBooks().subscribe({
+      next: (books: any[]) => this.allBooks = books,
       error: () => {}
     });
 
@@ -1207,12 +1207,11 @@ export class LibrarianDashboardComponent implements OnInit {
   }
 
   private _issueAfterAdd(): void {
-    // Refresh book list to find the newly added book by title
-    this.libraryService.getBooks().subscribe({
+    // Search the catalog for the newly added book by title
+    const title = this.olLendModal.book?.title || '';
+    this.libraryService.getAllBooks({ q: title }).subscribe({
       next: (books: any[]) => {
-        this.allBooks = books || [];
-        const title = this.olLendModal.book?.title || '';
-        const matched = this.allBooks.find((b: any) =>
+        const matched = books.find((b: any) =>
           b.title?.toLowerCase() === title.toLowerCase() && b.available_copies > 0
         );
         if (!matched) {
@@ -1273,11 +1272,10 @@ export class LibrarianDashboardComponent implements OnInit {
   }
 
   private _waitlistAfterAdd(): void {
-    this.libraryService.getBooks().subscribe({
+    const title = this.olWaitlistModal.book?.title || '';
+    this.libraryService.getAllBooks({ q: title }).subscribe({
       next: (books: any[]) => {
-        this.allBooks = books || [];
-        const title = this.olWaitlistModal.book?.title || '';
-        const matched = this.allBooks.find((b: any) =>
+        const matched = books.find((b: any) =>
           b.title?.toLowerCase() === title.toLowerCase()
         );
         if (!matched) {
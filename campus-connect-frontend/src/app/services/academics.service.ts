@@ -63,6 +63,18 @@ export class AcademicsService {
     return this.http.get(`${this.baseUrl}/api/attendance`);
   }
 
+  getMyEnrollments(): Observable<any> {
+    return this.http.get(`${this.baseUrl}/api/my/enrollments`);
+  }
+
+  getMyAttendance(): Observable<any> {
+    return this.http.get(`${this.baseUrl}/api/my/attendance`);
+  }
+
+  getMyGrades(): Observable<any> {
+    return this.http.get(`${this.baseUrl}/api/my/grades`);
+  }
+
   markAttendance(data: any): Observable<any> {
     return this.http.post(`${this.baseUrl}/api/attendance`, data);
   }
@@ -1,6 +1,7 @@
 import { Injectable } from '@angular/core';
 import { HttpClient } from '@angular/common/http';
-import { Observable } from 'rxjs';
+import { Observable, forkJoin, of } from 'rxjs';
+import { map, switchMap } from 'rxjs/operators';
 import { environment } from '../../environments/environment';
 
 @Injectable({ providedIn: 'root' })
@@ -13,8 +14,34 @@ export class LibraryService {
     return this.http.get(`${this.baseUrl}/api/student/books/${studentId}`);
   }
 
-  getBooks(): Observable<any> {
-    return this.http.get(`${this.baseUrl}/api/books`);
+  getBooks(filters?: { q?: string; category?: string; availableOnly?: boolean; page?: number; limit?: number }): Observable<any> {
+    let params = '';
+    if (filters) {
+      const parts: string[] = [];
+      if (filters.q) parts.push(`q=${encodeURIComponent(filters.q)}`);
+      if (filters.category) parts.push(`category=${encodeURIComponent(filters.category)}`);
+      if (filters.availableOnly) parts.push('available_only=true');
+      if (filters.page) parts.push(`page=${filters.page}`);
+      if (filters.limit) parts.push(`limit=${filters.limit}`);
+      if (parts.length) params = '?' + parts.join('&');
+    }
+    return this.http.get(`${this.baseUrl}/api/books${params}`);
+  }
+
+  /** Every matching book. /api/books returns at most 100 per page, so this walks all pages. */
+  getAllBooks(filters?: { q?: string; category?: string; availableOnly?: boolean }): Observable<any[]> {
+    const limit = 100;
+    return this.getBooks({ ...filters, page: 1, limit }).pipe(
+      switchMap((first: any) => {
+        const totalPages = first?.pagination?.total_pages || 1;
+        const rest: Observable<any>[] = [];
+        for (let page = 2; page <= totalPages; page++) {
+          rest.push(this.getBooks({ ...filters, page, limit }));
+        }
+        return rest.length ? forkJoin(rest).pipe(map(more => [first, ...more])) : of([first]);
+      }),
+      map((pages: any[]) => pages.flatMap(res => res?.data || []))
+    );
   }
 
   addBook(data: any): Observable<any> {
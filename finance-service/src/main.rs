@@ -2,8 +2,10 @@ use actix_web::{web, App, HttpServer, HttpResponse, HttpRequest, Error, middlewa
 use actix_cors::Cors;
 use mongodb::{Client, Collection, bson::{doc, oid::ObjectId}};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use chrono::{DateTime, Utc};
+use campus_auth::{load_jwt_keys, reload_jwt_keys, SharedKeySet, JWT_KEY_REFRESH_SECS};
+use chrono::{DateTime, Months, NaiveDate, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
 use std::env;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,6 +14,7 @@ struct Claims {
     role: String,
     campus_id: String,
     exp: usize,
+    iat: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -21,10 +24,122 @@ struct FeeStructure {
     student_id: String,
     fee_type: String, // tuition, hostel, library, misc
     amount: f64,
-    due_date: String,
+    #[serde(with = "due_date_format")]
+    due_date: NaiveDate,
+    // Original text of a due date the startup migration could not parse
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    legacy_due_date: Option<String>,
     status: String, // pending, paid, overdue
     campus_id: String,
     created_at: DateTime<Utc>,
+    // Set on fees generated from a fee template
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    template_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    semester: Option<String>,
+    // Late fee accrued so far; payable on top of amount
+    #[serde(default)]
+    late_fee: f64,
+    // Sum of successful payments; the fee becomes paid when payments and
+    // waivers together cover amount + late_fee
+    #[serde(default)]
+    amount_paid: f64,
+    // Sum of approved waivers
+    #[serde(default)]
+    waived_amount: f64,
+    // Set on installments created by an installment plan
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    parent_fee_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    installment_number: Option<u32>,
+    // Set on a fee split into installments; its status and totals are
+    // derived from the installments, which take the payments
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    installment_count: Option<u32>,
+    // Set on fees billed for a fine raised by another service
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    source: Option<FeeSource>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeSource {
+    service: String, // library, hostel
+    reference_id: String, // the fine's id in that service, e.g. a book issue id
+}
+
+// Fee due dates are BSON dates in MongoDB and "YYYY-MM-DD" in JSON. The driver
+// (de)serializes in non-human-readable mode, serde_json in human-readable mode.
+mod due_date_format {
+    use chrono::NaiveDate;
+    use mongodb::bson::Bson;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(date: &NaiveDate, serializer: S) -> Result<S::Ok, S::Error> {
+        if serializer.is_human_readable() {
+            serializer.serialize_str(&date.format("%Y-%m-%d").to_string())
+        } else {
+            super::due_date_bson(*date).serialize(serializer)
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<NaiveDate, D::Error> {
+        match Bson::deserialize(deserializer)? {
+            Bson::DateTime(date) => chrono::DateTime::from_timestamp_millis(date.timestamp_millis())
+                .map(|d| d.date_naive())
+                .ok_or_else(|| D::Error::custom("due date out of range")),
+            Bson::String(text) => super::parse_due_date(&text)
+                .ok_or_else(|| D::Error::custom(format!("invalid due date '{}'", text))),
+            other => Err(D::Error::custom(format!("invalid due date {}", other))),
+        }
+    }
+}
+
+fn due_date_bson(date: NaiveDate) -> mongodb::bson::DateTime {
+    mongodb::bson::DateTime::from_millis(date.and_time(chrono::NaiveTime::MIN).and_utc().timestamp_millis())
+}
+
+// 422 for a due date that is not an ISO date
+fn invalid_due_date(field: &str, value: &str) -> HttpResponse {
+    HttpResponse::UnprocessableEntity().json(serde_json::json!({
+        "error": format!("Invalid {} '{}': expected an ISO date such as 2024-03-31", field, value),
+        "field": field
+    }))
+}
+
+impl FeeStructure {
+    fn balance(&self) -> f64 {
+        ((self.amount + self.late_fee - self.amount_paid - self.waived_amount) * 100.0).round() / 100.0
+    }
+
+    // More than is owed, beyond rounding
+    fn exceeds_balance(&self, amount: f64) -> bool {
+        amount > self.balance() + AMOUNT_EPSILON
+    }
+
+    // Balance and status once `amount` is credited
+    fn after_credit(&self, amount: f64) -> (f64, &str) {
+        let remaining = ((self.balance() - amount) * 100.0).round() / 100.0;
+        let status = if remaining <= AMOUNT_EPSILON { "paid" } else { self.status.as_str() };
+        (remaining.max(0.0), status)
+    }
+}
+
+// Amounts within half a paisa/cent are treated as equal
+const AMOUNT_EPSILON: f64 = 0.005;
+
+// One late-fee charge added to an overdue fee, in the late_fee_accruals collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct LateFeeAccrual {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    fee_id: String,
+    student_id: String,
+    amount: f64,
+    policy: String, // flat, per_day
+    days_overdue: i64,
+    late_fee_total: f64,
+    campus_id: String,
+    accrued_at: DateTime<Utc>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -35,6 +150,15 @@ struct FeeRequest {
     due_date: String,
 }
 
+#[derive(Debug, Deserialize)]
+struct InstallmentPlanRequest {
+    installments: u32,
+    frequency: String, // monthly, term
+    // First installment's due date; defaults to the fee's due date
+    #[serde(default)]
+    start_date: Option<String>,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Payment {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -42,10 +166,60 @@ struct Payment {
     student_id: String,
     fee_id: String,
     amount: f64,
-    payment_method: String, // cash, card, upi, bank_transfer
+    payment_method: String, // cash, card, upi, bank_transfer, cheque, demand_draft, wallet
     transaction_id: String,
     payment_date: DateTime<Utc>,
     campus_id: String,
+    #[serde(default = "default_payment_status")]
+    status: String, // pending, success, failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gateway: Option<String>, // razorpay, stripe
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    gateway_order_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    failure_reason: Option<String>,
+    // Cheque and demand draft details and clearance state
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    instrument: Option<PaymentInstrument>,
+    // Set once a bank statement line has been matched to the payment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reconciliation_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reconciled_at: Option<DateTime<Utc>>,
+    // Recorded at the counter but not yet credited to its fee; a retry of
+    // the request finishes crediting it
+    #[serde(default)]
+    awaiting_credit: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PaymentInstrument {
+    bank_name: String,
+    instrument_number: String,
+    instrument_date: String,
+    status: String, // received, deposited, cleared, bounced
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    deposited_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cleared_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bounced_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bounce_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    bounce_fee_id: Option<String>,
+}
+
+fn is_instrument_method(payment_method: &str) -> bool {
+    matches!(payment_method, "cheque" | "demand_draft")
+}
+
+// Payment statuses that do not count as money collected
+const UNCOLLECTED_PAYMENT_STATUSES: [&str; 3] = ["pending", "failed", "bounced"];
+
+// Payments recorded before gateway checkout existed were all taken at the counter
+fn default_payment_status() -> String {
+    "success".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -54,7 +228,167 @@ struct PaymentRequest {
     fee_id: String,
     amount: f64,
     payment_method: String,
+    #[serde(default)]
     transaction_id: String,
+    // Required for cheque and demand_draft
+    bank_name: Option<String>,
+    instrument_number: Option<String>,
+    instrument_date: Option<String>, // YYYY-MM-DD
+}
+
+#[derive(Debug, Deserialize)]
+struct PaymentQuery {
+    instrument_status: Option<String>, // received, deposited, cleared, bounced
+}
+
+// One imported bank statement and how its lines matched recorded payments,
+// in the reconciliation_batches collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ReconciliationBatch {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    campus_id: String,
+    imported_by: String,
+    imported_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    statement_from: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    statement_to: Option<String>,
+    matched: u32,
+    ambiguous: u32,
+    unmatched: u32,
+    skipped: u32,
+    matched_amount: f64,
+    unmatched_amount: f64,
+    entries: Vec<StatementEntry>,
+    // Successful payments dated within the statement period that no line matched
+    unreconciled_payments: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StatementEntry {
+    row: usize, // 1-based line in the CSV, header included
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    date: Option<String>,
+    reference: String,
+    amount: f64,
+    status: String, // matched, ambiguous, unmatched, skipped
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    rule: Option<String>, // reference, reference_amount_tolerance, amount_date
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payment_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    candidates: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BounceRequest {
+    reason: String,
+}
+
+// Advance money held for a student, drawn on to pay fees
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Wallet {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    campus_id: String,
+    balance: f64,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct WalletTransaction {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    campus_id: String,
+    kind: String, // deposit, payment, refund
+    amount: f64,
+    balance_after: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fee_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payment_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    recorded_by: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WalletDepositRequest {
+    amount: f64,
+    // Receipt or bank reference for the money received
+    reference: Option<String>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WalletPaymentRequest {
+    fee_id: String,
+    amount: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WalletRefundRequest {
+    amount: f64,
+    note: String,
+    // Payment being refunded, if any; refunds against it cannot exceed its amount
+    payment_id: Option<String>,
+}
+
+// A set of fee heads charged to every student of a program in a semester
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeTemplate {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    program_code: String,
+    semester: String,
+    heads: Vec<FeeHead>,
+    campus_id: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeHead {
+    fee_type: String, // tuition, hostel, library, misc
+    amount: f64,
+    due_date: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FeeTemplateRequest {
+    name: String,
+    program_code: String,
+    semester: String,
+    heads: Vec<FeeHead>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExportQuery {
+    from: Option<String>, // YYYY-MM-DD, inclusive; payment date or fee due date
+    to: Option<String>,   // YYYY-MM-DD, inclusive
+    fee_type: Option<String>,
+    format: Option<String>, // csv (default), tally (payments only)
+}
+
+#[derive(Debug, Deserialize)]
+struct CollectionReportQuery {
+    from: Option<String>, // YYYY-MM-DD, inclusive
+    to: Option<String>,   // YYYY-MM-DD, inclusive
+    interval: Option<String>, // day, month (default)
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct CheckoutRequest {
+    fee_id: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -64,15 +398,81 @@ struct Invoice {
     invoice_number: String,
     student_id: String,
     items: Vec<InvoiceItem>,
+    total_amount: f64, // items plus tax
+    #[serde(default)]
+    tax_total: f64,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default = "default_sent_status")]
+    sent_status: String, // not_sent, sent, failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sent_to: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    sent_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    send_error: Option<String>,
+    // Invoices are never edited; a mistake is corrected by cancelling the
+    // invoice, which issues a credit note for its full amount
+    #[serde(default = "default_invoice_status")]
+    status: String, // issued, cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cancellation_reason: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cancelled_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cancelled_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    credit_note_id: Option<String>,
+}
+
+fn default_sent_status() -> String {
+    "not_sent".to_string()
+}
+
+fn default_invoice_status() -> String {
+    "issued".to_string()
+}
+
+// Reverses a cancelled invoice, in the credit_notes collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct CreditNote {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    credit_note_number: String, // CN-{campus}-{FY}-{seq}
+    invoice_id: String,
+    invoice_number: String,
+    student_id: String,
+    items: Vec<InvoiceItem>,
     total_amount: f64,
+    tax_total: f64,
+    reason: String,
     campus_id: String,
+    created_by: String,
     created_at: DateTime<Utc>,
 }
 
+#[derive(Debug, Serialize, Deserialize)]
+struct CancelInvoiceRequest {
+    reason: String,
+}
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct InvoiceItem {
     description: String,
     amount: f64,
+    #[serde(default)]
+    tax_rate: f64, // percent, e.g. 18 for 18% GST
+}
+
+impl InvoiceItem {
+    fn tax(&self) -> f64 {
+        (self.amount * self.tax_rate).round() / 100.0
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct SendInvoiceRequest {
+    email: Option<String>, // defaults to the student's account email
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -83,227 +483,5580 @@ struct InvoiceRequest {
 
 struct AppState {
     db: mongodb::Database,
-    jwt_secret: String,
+    // Verification keys by kid, reloaded from the jwt_keys collection
+    jwt_keys: SharedKeySet,
+    gateway: Option<GatewayConfig>,
+    late_fee_policy: LateFeePolicy,
+    bounce_charge: f64, // billed when a cheque or demand draft bounces
+    institution_name: String,
+    smtp: Option<SmtpConfig>, // None: outgoing mail is logged instead
+    reminders: ReminderSchedule,
+    fines: FineIntegration,
+    events: EventPublisher,
+    dunning_stages: Vec<DunningStage>, // ascending by days_overdue
 }
 
-fn extract_claims(req: &HttpRequest, jwt_secret: &str) -> Result<Claims, String> {
-    if let Some(auth_header) = req.headers().get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let token = &auth_str[7..];
-                
-                match decode::<Claims>(
-                    token,
-                    &DecodingKey::from_secret(jwt_secret.as_bytes()),
-                    &Validation::new(Algorithm::HS256),
-                ) {
-                    Ok(token_data) => return Ok(token_data.claims),
-                    Err(_) => return Err("Invalid token".to_string()),
-                }
-            }
+// Fines pushed or pulled from other services, from the FINE_* env vars
+#[derive(Clone)]
+struct FineIntegration {
+    api_key: Option<String>, // X-Integration-Key for service callers; None: staff tokens only
+    due_days: i64, // days after ingestion a fine falls due when the event has no due date
+}
+
+// A fine raised in another service, billed to the student as a fee
+#[derive(Debug, Serialize, Deserialize)]
+struct FineEvent {
+    source: String, // library (overdue fines), hostel (damage charges), hostel_rent (room allocations), mess (monthly mess bills)
+    reference_id: String,
+    student_id: String,
+    amount: f64,
+    // Required with X-Integration-Key; staff callers bill their own campus
+    campus_id: Option<String>,
+    due_date: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FineStatusQuery {
+    campus_id: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize)]
+struct FineSyncSummary {
+    ingested: u64,
+    status_updates: u64,
+}
+
+// Where payment events are delivered, from the EVENT_* env vars
+#[derive(Clone)]
+struct EventPublisher {
+    webhook_urls: Vec<String>, // empty: events are not published
+    secret: String, // signs each body; receivers check X-CampusConnect-Signature
+    max_attempts: u32,
+}
+
+// One event to one subscriber, in the event_deliveries collection. pending
+// deliveries are retried with exponential backoff until delivered, or failed
+// after max_attempts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EventDelivery {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    event_id: String,
+    event_type: String, // payment.completed, fee.overdue, refund.issued
+    url: String,
+    // The JSON body sent, kept as text so the signature always matches
+    payload: String,
+    status: String, // pending, delivered, failed
+    attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_status_code: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    next_attempt_at: mongodb::bson::DateTime,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventDeliveryQuery {
+    status: Option<String>,
+    event_type: Option<String>,
+}
+
+// When fee reminders go out, from the REMINDER_* env vars
+#[derive(Clone)]
+struct ReminderSchedule {
+    days_before: Vec<i64>, // e.g. [7, 1]: T-7 and T-1, largest first
+    overdue_every_days: i64,
+    payment_link: String, // ?fee_id=<id> is appended
+}
+
+// A request to waive part or all of a fee, in the fee_waivers collection.
+// requested -> recommended (hr) -> approved (admin); either step may reject.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeWaiver {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    fee_id: String,
+    student_id: String,
+    amount: f64,
+    justification: String,
+    status: String, // requested, recommended, approved, rejected
+    requested_by: String,
+    requested_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recommended_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    recommended_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decided_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    campus_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WaiverRequest {
+    fee_id: String,
+    amount: f64,
+    justification: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct WaiverDecisionRequest {
+    note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WaiverQuery {
+    status: Option<String>,
+    student_id: Option<String>,
+}
+
+// A change to money owed, in the finance_audit_log collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FinanceAuditEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    action: String, // waiver_approved
+    fee_id: String,
+    student_id: String,
+    amount: f64,
+    actor: String,
+    detail: String,
+    campus_id: String,
+    occurred_at: DateTime<Utc>,
+}
+
+// One change to a fee, in the fee_audit collection. Entries are only ever
+// inserted, never updated or deleted.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeAuditEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    fee_id: String,
+    student_id: String,
+    // created, payment_credited, waiver_credited, payment_reversed,
+    // marked_overdue, late_fee_accrued, installment_plan_created, installments_synced
+    action: String,
+    actor: String, // claims.sub, or system / gateway:<provider> / <service> for automatic changes
+    reason: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    before: Option<FeeSnapshot>, // None when the fee was created
+    after: FeeSnapshot,
+    campus_id: String,
+    occurred_at: DateTime<Utc>,
+}
+
+// The audited fields of a fee at one point in time
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeSnapshot {
+    amount: f64,
+    due_date: String,
+    status: String,
+    late_fee: f64,
+    amount_paid: f64,
+    waived_amount: f64,
+}
+
+impl From<&FeeStructure> for FeeSnapshot {
+    fn from(fee: &FeeStructure) -> Self {
+        FeeSnapshot {
+            amount: fee.amount,
+            due_date: fee.due_date.format("%Y-%m-%d").to_string(),
+            status: fee.status.clone(),
+            late_fee: fee.late_fee,
+            amount_paid: fee.amount_paid,
+            waived_amount: fee.waived_amount,
         }
     }
-    Err("No token provided".to_string())
 }
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "ok",
-        "service": "finance-service"
-    }))
+// One reminder email, in the fee_reminders collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeeReminder {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    fee_id: String,
+    student_id: String,
+    kind: String, // due_in_<days>, overdue
+    email: Option<String>,
+    amount: f64,
+    due_date: String,
+    status: String, // sent, failed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+    campus_id: String,
+    sent_at: DateTime<Utc>,
+}
+
+// A student's choice to stop fee reminders, in the reminder_preferences collection
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct ReminderPreference {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    campus_id: String,
+    opted_out: bool,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReminderPreferenceRequest {
+    opted_out: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReminderHistoryQuery {
+    student_id: Option<String>,
+    fee_id: Option<String>,
+}
+
+// Outcome of one reminder scan
+#[derive(Default, Serialize)]
+struct ReminderScanSummary {
+    sent: u64,
+    failed: u64,
+    opted_out: u64,
+}
+
+// Outbound SMTP settings, from the SMTP_* env vars shared with auth-service
+#[derive(Clone)]
+struct SmtpConfig {
+    host: String,
+    port: u16,
+    starttls: bool, // false for local catchers such as MailHog
+    username: Option<String>,
+    password: Option<String>,
+    from: String,
+}
+
+impl SmtpConfig {
+    fn from_env() -> Option<Self> {
+        let host = env::var("SMTP_HOST").ok().filter(|h| !h.is_empty())?;
+        Some(SmtpConfig {
+            host,
+            port: env::var("SMTP_PORT").ok().and_then(|p| p.parse().ok()).unwrap_or(587),
+            starttls: env::var("SMTP_STARTTLS").map(|v| v != "false").unwrap_or(true),
+            username: env::var("SMTP_USERNAME").ok(),
+            password: env::var("SMTP_PASSWORD").ok(),
+            from: env::var("SMTP_FROM").unwrap_or_else(|_| "CampusConnect <no-reply@campusconnect.local>".to_string()),
+        })
+    }
+}
+
+// Late fee charged on overdue fees, from the LATE_FEE_* env vars
+#[derive(Clone)]
+struct LateFeePolicy {
+    kind: String, // none, flat, per_day
+    amount: f64,
+    cap: Option<f64>, // per_day only
+    grace_days: i64,
+}
+
+impl LateFeePolicy {
+    // Total late fee owed on a fee this many days past its due date
+    fn late_fee_for(&self, days_overdue: i64) -> f64 {
+        let chargeable_days = days_overdue - self.grace_days;
+        if chargeable_days <= 0 {
+            return 0.0;
+        }
+        let total = match self.kind.as_str() {
+            "flat" => self.amount,
+            "per_day" => self.amount * chargeable_days as f64,
+            _ => 0.0,
+        };
+        let total = match self.cap {
+            Some(cap) => total.min(cap),
+            None => total,
+        };
+        (total * 100.0).round() / 100.0
+    }
+
+    // What to add to a fee already charged `charged` in late fees; never
+    // negative, so repeated scans charge nothing and a missed one catches up
+    fn late_fee_increase(&self, charged: f64, days_overdue: i64) -> f64 {
+        (((self.late_fee_for(days_overdue) - charged) * 100.0).round() / 100.0).max(0.0)
+    }
+}
+
+// Outcome of one overdue scan
+#[derive(Default, Serialize)]
+struct OverdueScanSummary {
+    marked_overdue: u64,
+    late_fees_accrued: u64,
+    late_fee_amount: f64,
+}
+
+// A dunning stage a student enters once their oldest overdue fee is at least
+// days_overdue days past due, from DUNNING_STAGES
+#[derive(Debug, Clone, Serialize)]
+struct DunningStage {
+    name: String, // e.g. reminder, warning, registration_hold, legal
+    days_overdue: i64,
+}
+
+// A student's current dunning stage, in the dunning_status collection.
+// Students with nothing overdue have no entry.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DunningStatus {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    campus_id: String,
+    stage: String,
+    days_overdue: i64, // of the oldest overdue fee
+    outstanding: f64,
+    overdue_fees: u32,
+    stage_entered_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DunningQuery {
+    stage: Option<String>,
+}
+
+// Outcome of one dunning scan
+#[derive(Default, Serialize)]
+struct DunningScanSummary {
+    students_in_dunning: u64,
+    stage_changes: u64,
+    cleared: u64,
+}
+
+// Online payment gateway credentials, from PAYMENT_GATEWAY and GATEWAY_* env vars
+#[derive(Clone)]
+struct GatewayConfig {
+    provider: String, // razorpay, stripe
+    key_id: String,
+    key_secret: String,
+    webhook_secret: String,
+    currency: String,
+}
+
+// An order created at the gateway for the client to complete
+struct GatewayOrder {
+    order_id: String,
+    client_secret: Option<String>, // Stripe only
+}
+
+// What a verified webhook says happened to an order
+struct GatewayOutcome {
+    order_id: String,
+    succeeded: bool,
+    transaction_id: String,
+    payment_method: String,
+    amount_minor: i64,
+    failure_reason: Option<String>,
+}
+
+// Stripe signatures older than this are rejected to stop replays
+const STRIPE_TOLERANCE_SECS: i64 = 300;
+
+fn extract_claims(req: &HttpRequest, jwt_keys: &SharedKeySet) -> Result<Claims, String> {
+    if let Some(auth_header) = req.headers().get("Authorization") {
+        if let Ok(auth_str) = auth_header.to_str() {
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                let keys = jwt_keys.read().expect("JWT key set lock poisoned");
+                return keys.decode(token).ok_or_else(|| "Invalid token".to_string());
+            }
+        }
+    }
+    Err("No token provided".to_string())
+}
+
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "service": "finance-service"
+    }))
+}
+
+// Fee Management
+async fn create_fee(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    fee_data: web::Json<FeeRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(due_date) = parse_due_date(&fee_data.due_date) else {
+        return Ok(invalid_due_date("due_date", &fee_data.due_date));
+    };
+
+    let collection: Collection<FeeStructure> = data.db.collection("fees");
+
+    let mut new_fee = FeeStructure {
+        id: None,
+        student_id: fee_data.student_id.clone(),
+        fee_type: fee_data.fee_type.clone(),
+        amount: fee_data.amount,
+        due_date,
+        legacy_due_date: None,
+        status: "pending".to_string(),
+        campus_id: claims.campus_id.clone(),
+        created_at: Utc::now(),
+        template_id: None,
+        semester: None,
+        late_fee: 0.0,
+        amount_paid: 0.0,
+        waived_amount: 0.0,
+        parent_fee_id: None,
+        installment_number: None,
+        installment_count: None,
+        source: None,
+    };
+
+    new_fee.id = collection
+        .insert_one(&new_fee, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .inserted_id
+        .as_object_id();
+    record_fees_created(&data.db, std::slice::from_ref(&new_fee), &claims.sub, "Fee created")
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Fee created successfully"
+    })))
+}
+
+async fn get_fees(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<FeeStructure> = data.db.collection("fees");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut fees = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(fee) => fees.push(fee),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(fees))
+}
+
+// Payment Management
+async fn create_payment(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    payment_data: web::Json<PaymentRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    let mut payment_data = payment_data.into_inner();
+
+    // Students pay online through /api/payments/checkout; only staff record
+    // payments taken at the counter
+    if claims.role == "student" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Use online checkout to pay fees"
+        })));
+    }
+
+    if payment_data.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Payment amount must be positive"
+        })));
+    }
+
+    let instrument = if is_instrument_method(&payment_data.payment_method) {
+        let field = |value: &Option<String>| value.as_deref().map(str::trim).filter(|v| !v.is_empty()).map(str::to_string);
+        let (Some(bank_name), Some(instrument_number), Some(instrument_date)) = (
+            field(&payment_data.bank_name),
+            field(&payment_data.instrument_number),
+            field(&payment_data.instrument_date),
+        ) else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "bank_name, instrument_number and instrument_date are required for cheque and demand_draft payments"
+            })));
+        };
+        if NaiveDate::parse_from_str(&instrument_date, "%Y-%m-%d").is_err() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid instrument_date '{}', expected YYYY-MM-DD", instrument_date)
+            })));
+        }
+        // The instrument itself identifies the payment when no transaction id is given
+        if payment_data.transaction_id.trim().is_empty() {
+            payment_data.transaction_id = format!("{}:{}:{}", payment_data.payment_method, bank_name, instrument_number);
+        }
+        Some(PaymentInstrument {
+            bank_name,
+            instrument_number,
+            instrument_date,
+            status: "received".to_string(),
+            deposited_at: None,
+            cleared_at: None,
+            bounced_at: None,
+            bounce_reason: None,
+            bounce_fee_id: None,
+        })
+    } else {
+        None
+    };
+
+    // A retried request finds the payment it already recorded
+    let collection: Collection<Payment> = data.db.collection("payments");
+    if let Some(existing) = find_payment_by_transaction(&collection, &payment_data.transaction_id, &claims.campus_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        return replay_payment(&data, existing, &payment_data, &claims.sub).await;
+    }
+
+    let fee_obj_id = ObjectId::parse_str(&payment_data.fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+
+    if fee.student_id != payment_data.student_id {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Fee does not belong to student '{}'", payment_data.student_id)
+        })));
+    }
+    if fee.installment_count.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee is paid in installments; pay the installment fees instead"
+        })));
+    }
+    if !fee_accepts_payments(&fee.status) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Fee is {} and cannot take payments", fee.status)
+        })));
+    }
+    if fee.exceeds_balance(payment_data.amount) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Amount {:.2} exceeds the outstanding balance of {:.2}", payment_data.amount, fee.balance())
+        })));
+    }
+
+    let mut new_payment = Payment {
+        id: None,
+        student_id: payment_data.student_id.clone(),
+        fee_id: payment_data.fee_id.clone(),
+        amount: payment_data.amount,
+        payment_method: payment_data.payment_method.clone(),
+        transaction_id: payment_data.transaction_id.clone(),
+        payment_date: Utc::now(),
+        campus_id: claims.campus_id.clone(),
+        status: "pending".to_string(),
+        gateway: None,
+        gateway_order_id: None,
+        failure_reason: None,
+        instrument,
+        reconciliation_id: None,
+        reconciled_at: None,
+        awaiting_credit: true,
+    };
+
+    // The unique (transaction_id, campus_id) index stops a concurrent retry
+    // from getting past this point twice
+    match collection.insert_one(&new_payment, None).await {
+        Ok(result) => new_payment.id = result.inserted_id.as_object_id(),
+        Err(e) if is_duplicate_key(&e) => {
+            let existing = find_payment_by_transaction(&collection, &payment_data.transaction_id, &claims.campus_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .ok_or_else(|| actix_web::error::ErrorInternalServerError(e))?;
+            return replay_payment(&data, existing, &payment_data, &claims.sub).await;
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    };
+
+    if !credit_recorded_payment(&data, &new_payment, &claims.sub)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        return Ok(fee_changed_response());
+    }
+
+    let (balance, fee_status) = fee.after_credit(payment_data.amount);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Payment recorded successfully",
+        "balance": balance,
+        "fee_status": fee_status
+    })))
+}
+
+// Credits a payment recorded by create_payment to its fee, at most once even
+// across retries and crashes: the fee remembers which payments it was
+// credited with. Returns false if the fee can no longer take the payment; it
+// is then kept as failed, and a retry of the request tries again.
+async fn credit_recorded_payment(data: &AppState, payment: &Payment, actor: &str) -> mongodb::error::Result<bool> {
+    let (Some(payment_id), Ok(fee_obj_id)) = (payment.id, ObjectId::parse_str(&payment.fee_id)) else {
+        return Ok(false);
+    };
+    let collection: Collection<Payment> = data.db.collection("payments");
+
+    let credited = credit_fee_once(&data.db, fee_obj_id, &payment.campus_id, "amount_paid", payment.amount, actor, Some(payment_id)).await?
+        || data.db
+            .collection::<FeeStructure>("fees")
+            .count_documents(doc! { "_id": fee_obj_id, "credited_payments": payment_id }, None)
+            .await?
+            > 0;
+    if !credited {
+        collection
+            .update_one(
+                doc! { "_id": payment_id, "awaiting_credit": true },
+                doc! { "$set": { "status": "failed", "failure_reason": "Fee changed while recording the payment" } },
+                None,
+            )
+            .await?;
+        return Ok(false);
+    }
+
+    // Whoever clears the flag publishes the event, so it goes out once
+    let cleared = collection
+        .update_one(
+            doc! { "_id": payment_id, "awaiting_credit": true },
+            doc! { "$set": { "status": "success", "awaiting_credit": false }, "$unset": { "failure_reason": "" } },
+            None,
+        )
+        .await?;
+    if cleared.modified_count == 1 {
+        publish_payment_completed(&data.db, &data.events, payment_id).await;
+    }
+    Ok(true)
+}
+
+fn fee_changed_response() -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({
+        "error": "Fee changed while recording the payment; reload and try again"
+    }))
+}
+
+// A retry of create_payment: finishes crediting the payment if the first
+// attempt did not get that far, then answers as replayed_payment_response
+async fn replay_payment(
+    data: &AppState,
+    mut existing: Payment,
+    request: &PaymentRequest,
+    actor: &str,
+) -> Result<HttpResponse, Error> {
+    if existing.awaiting_credit && same_payment(&existing, request) {
+        if !credit_recorded_payment(data, &existing, actor)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+        {
+            return Ok(fee_changed_response());
+        }
+        existing.status = "success".to_string();
+        existing.awaiting_credit = false;
+        existing.failure_reason = None;
+    }
+    Ok(replayed_payment_response(existing, request))
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref we)) if we.code == 11000
+    )
+}
+
+// Blank transaction ids (e.g. cash) are not deduplicated
+async fn find_payment_by_transaction(
+    collection: &Collection<Payment>,
+    transaction_id: &str,
+    campus_id: &str,
+) -> mongodb::error::Result<Option<Payment>> {
+    if transaction_id.is_empty() {
+        return Ok(None);
+    }
+    collection
+        .find_one(doc! { "transaction_id": transaction_id, "campus_id": campus_id }, None)
+        .await
+}
+
+// 200 with the stored payment when a retry repeats it exactly; 409 when the
+// transaction id was already used for a different payment
+fn same_payment(existing: &Payment, request: &PaymentRequest) -> bool {
+    existing.student_id == request.student_id
+        && existing.fee_id == request.fee_id
+        && (existing.amount - request.amount).abs() < AMOUNT_EPSILON
+}
+
+fn replayed_payment_response(existing: Payment, request: &PaymentRequest) -> HttpResponse {
+    if same_payment(&existing, request) {
+        HttpResponse::Ok().json(serde_json::json!({
+            "message": "Payment already recorded",
+            "payment": existing
+        }))
+    } else {
+        HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Transaction '{}' is already recorded for a different payment", request.transaction_id),
+            "payment": existing
+        }))
+    }
+}
+
+// ===== FEE AUDIT =====
+
+fn fee_audit_entry(action: &str, actor: &str, reason: &str, before: Option<&FeeStructure>, after: &FeeStructure) -> FeeAuditEntry {
+    FeeAuditEntry {
+        id: None,
+        fee_id: after.id.map(|id| id.to_hex()).unwrap_or_default(),
+        student_id: after.student_id.clone(),
+        action: action.to_string(),
+        actor: actor.to_string(),
+        reason: reason.to_string(),
+        before: before.map(FeeSnapshot::from),
+        after: FeeSnapshot::from(after),
+        campus_id: after.campus_id.clone(),
+        occurred_at: Utc::now(),
+    }
+}
+
+// Records newly inserted fees; fees without an id are skipped
+async fn record_fees_created(
+    db: &mongodb::Database,
+    fees: &[FeeStructure],
+    actor: &str,
+    reason: &str,
+) -> mongodb::error::Result<()> {
+    let entries: Vec<FeeAuditEntry> = fees
+        .iter()
+        .filter(|fee| fee.id.is_some())
+        .map(|fee| fee_audit_entry("created", actor, reason, None, fee))
+        .collect();
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let audit: Collection<FeeAuditEntry> = db.collection("fee_audit");
+    audit.insert_many(entries, None).await?;
+    Ok(())
+}
+
+// Records a change to a fee that has just been updated, reading its new state
+async fn record_fee_change(
+    db: &mongodb::Database,
+    fee_id: ObjectId,
+    action: &str,
+    actor: &str,
+    reason: &str,
+    before: &FeeStructure,
+) -> mongodb::error::Result<()> {
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let Some(after) = fee_collection.find_one(doc! { "_id": fee_id }, None).await? else {
+        return Ok(());
+    };
+    let audit: Collection<FeeAuditEntry> = db.collection("fee_audit");
+    audit
+        .insert_one(fee_audit_entry(action, actor, reason, Some(before), &after), None)
+        .await?;
+    Ok(())
+}
+
+async fn get_fee_history(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let fee_id = path.into_inner();
+    let fee_obj_id = ObjectId::parse_str(&fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+    if claims.role == "student" && fee.student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own fees"
+        })));
+    }
+
+    let audit: Collection<FeeAuditEntry> = data.db.collection("fee_audit");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "occurred_at": 1 })
+        .build();
+    let mut cursor = audit
+        .find(doc! { "fee_id": &fee_id, "campus_id": &claims.campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut history = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => history.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(history))
+}
+
+// Fee status transitions: pending -> overdue (overdue scan) and
+// pending/overdue -> paid (once payments cover the balance). Paid is final
+// except when a cheque or demand draft bounces, which reverts it to pending.
+fn fee_accepts_payments(status: &str) -> bool {
+    matches!(status, "pending" | "overdue")
+}
+
+// Adds a successful payment (field "amount_paid") or an approved waiver
+// ("waived_amount") to a fee and marks the fee paid once nothing is left
+// owing. Returns false, changing nothing, if the fee is not payable or the
+// amount exceeds the balance at the time of the update.
+async fn credit_fee(
+    db: &mongodb::Database,
+    fee_id: ObjectId,
+    campus_id: &str,
+    field: &str,
+    amount: f64,
+    actor: &str,
+) -> mongodb::error::Result<bool> {
+    credit_fee_once(db, fee_id, campus_id, field, amount, actor, None).await
+}
+
+// credit_fee that, given a payment id, also refuses a payment the fee was
+// already credited with and records it in the fee's credited_payments
+async fn credit_fee_once(
+    db: &mongodb::Database,
+    fee_id: ObjectId,
+    campus_id: &str,
+    field: &str,
+    amount: f64,
+    actor: &str,
+    payment_id: Option<ObjectId>,
+) -> mongodb::error::Result<bool> {
+    let credited = doc! { "$add": [
+        { "$ifNull": ["$amount_paid", 0.0] },
+        { "$ifNull": ["$waived_amount", 0.0] }
+    ] };
+    let credited_after = doc! { "$add": [credited.clone(), amount] };
+    let total_due = doc! { "$add": ["$amount", { "$ifNull": ["$late_fee", 0.0] }] };
+
+    let mut filter = doc! {
+        "_id": fee_id,
+        "campus_id": campus_id,
+        "status": { "$in": ["pending", "overdue"] },
+        "installment_count": null,
+        "$expr": { "$lte": [credited_after, { "$add": [total_due.clone(), AMOUNT_EPSILON] }] }
+    };
+    let mut update = vec![
+        doc! { "$set": { field: { "$add": [{ "$ifNull": [format!("${}", field), 0.0] }, amount] } } },
+        doc! { "$set": { "status": {
+            "$cond": [
+                { "$gte": [{ "$add": [credited, AMOUNT_EPSILON] }, total_due] },
+                "paid",
+                "$status"
+            ]
+        } } },
+    ];
+    if let Some(payment_id) = payment_id {
+        filter.insert("credited_payments", doc! { "$ne": payment_id });
+        update.push(doc! { "$set": { "credited_payments": {
+            "$concatArrays": [{ "$ifNull": ["$credited_payments", []] }, [payment_id]]
+        } } });
+    }
+
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let before = fee_collection
+        .find_one_and_update(filter, update, None)
+        .await?;
+    let Some(before) = before else {
+        return Ok(false);
+    };
+
+    let (action, reason) = if field == "waived_amount" {
+        ("waiver_credited", format!("Waiver of {:.2} approved", amount))
+    } else {
+        ("payment_credited", format!("Payment of {:.2} received", amount))
+    };
+    record_fee_change(db, fee_id, action, actor, &reason, &before).await?;
+    if let Some(parent_fee_id) = &before.parent_fee_id {
+        sync_parent_fee(db, parent_fee_id).await?;
+    }
+    Ok(true)
+}
+
+// Recomputes a planned fee from its installments: paid once every installment
+// is paid, overdue while any is overdue, pending otherwise. Payments, waivers
+// and late fees are summed so reports on the parent stay accurate.
+async fn sync_parent_fee(db: &mongodb::Database, parent_fee_id: &str) -> mongodb::error::Result<()> {
+    let Ok(parent_obj_id) = ObjectId::parse_str(parent_fee_id) else {
+        return Ok(());
+    };
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let mut cursor = fee_collection
+        .find(doc! { "parent_fee_id": parent_fee_id }, None)
+        .await?;
+
+    let (mut all_paid, mut any_overdue) = (true, false);
+    let (mut amount_paid, mut waived_amount, mut late_fee) = (0.0, 0.0, 0.0);
+    use futures::stream::StreamExt;
+    while let Some(installment) = cursor.next().await {
+        let installment = installment?;
+        all_paid &= installment.status == "paid";
+        any_overdue |= installment.status == "overdue";
+        amount_paid += installment.amount_paid;
+        waived_amount += installment.waived_amount;
+        late_fee += installment.late_fee;
+    }
+    let status = if all_paid {
+        "paid"
+    } else if any_overdue {
+        "overdue"
+    } else {
+        "pending"
+    };
+
+    let before = fee_collection
+        .find_one_and_update(
+            doc! { "_id": parent_obj_id },
+            doc! { "$set": {
+                "status": status,
+                "amount_paid": (amount_paid * 100.0_f64).round() / 100.0,
+                "waived_amount": (waived_amount * 100.0_f64).round() / 100.0,
+                "late_fee": (late_fee * 100.0_f64).round() / 100.0
+            } },
+            None,
+        )
+        .await?;
+    let changed = |old: f64, new: f64| (old - (new * 100.0_f64).round() / 100.0).abs() > AMOUNT_EPSILON;
+    if let Some(before) = before.filter(|b| {
+        b.status != status
+            || changed(b.amount_paid, amount_paid)
+            || changed(b.waived_amount, waived_amount)
+            || changed(b.late_fee, late_fee)
+    }) {
+        record_fee_change(db, parent_obj_id, "installments_synced", "system", "Recomputed from its installments", &before).await?;
+    }
+    Ok(())
+}
+
+async fn get_payments(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PaymentQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Payment> = data.db.collection("payments");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(instrument_status) = &query.instrument_status {
+        filter.insert("instrument.status", instrument_status);
+    }
+
+    let mut cursor = collection
+        .find(filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut payments = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(payment) => payments.push(payment),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(payments))
+}
+
+// ===== CHEQUE CLEARANCE =====
+
+async fn find_instrument_payment(
+    db: &mongodb::Database,
+    payment_id: &str,
+    campus_id: &str,
+) -> Result<Option<Payment>, Error> {
+    let payment_obj_id = ObjectId::parse_str(payment_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Payment> = db.collection("payments");
+    collection
+        .find_one(doc! { "_id": payment_obj_id, "campus_id": campus_id, "instrument": { "$exists": true } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Moves a cheque or demand draft from one clearance status to the next
+async fn advance_instrument(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    payment_id: String,
+    from: &str,
+    to: &str,
+    timestamp_field: &str,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let Some(payment) = find_instrument_payment(&data.db, &payment_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Cheque or demand draft payment not found"
+        })));
+    };
+
+    let collection: Collection<Payment> = data.db.collection("payments");
+    let result = collection
+        .update_one(
+            doc! { "_id": payment.id, "instrument.status": from },
+            doc! { "$set": {
+                "instrument.status": to,
+                format!("instrument.{}", timestamp_field): Utc::now().to_rfc3339()
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        let current = payment.instrument.map(|i| i.status).unwrap_or_default();
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Instrument is {}; only {} instruments can be marked {}", current, from, to)
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Instrument marked {}", to)
+    })))
+}
+
+async fn deposit_instrument(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    advance_instrument(data, req, path.into_inner(), "received", "deposited", "deposited_at").await
+}
+
+async fn clear_instrument(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    advance_instrument(data, req, path.into_inner(), "deposited", "cleared", "cleared_at").await
+}
+
+// A bounced instrument no longer pays the fee: its amount comes off the fee,
+// a paid fee reverts to pending, and the bounce charge is billed as a new fee
+async fn bounce_instrument(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    bounce_data: web::Json<BounceRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if bounce_data.reason.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A bounce reason is required"
+        })));
+    }
+
+    let Some(payment) = find_instrument_payment(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Cheque or demand draft payment not found"
+        })));
+    };
+
+    // Claim the bounce first so a repeated request cannot reverse the fee twice
+    let collection: Collection<Payment> = data.db.collection("payments");
+    let now = Utc::now();
+    let result = collection
+        .update_one(
+            doc! { "_id": payment.id, "instrument.status": { "$in": ["received", "deposited"] } },
+            doc! { "$set": {
+                "status": "bounced",
+                "failure_reason": format!("Instrument bounced: {}", bounce_data.reason.trim()),
+                "instrument.status": "bounced",
+                "instrument.bounced_at": now.to_rfc3339(),
+                "instrument.bounce_reason": bounce_data.reason.trim()
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        let current = payment.instrument.map(|i| i.status).unwrap_or_default();
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Instrument is {} and cannot bounce", current)
+        })));
+    }
+
+    let fee_obj_id = ObjectId::parse_str(&payment.fee_id)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let before = fee_collection
+        .find_one_and_update(
+            doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id },
+            vec![doc! { "$set": {
+                "amount_paid": { "$max": [0.0, { "$subtract": [{ "$ifNull": ["$amount_paid", 0.0] }, payment.amount] }] },
+                "status": { "$cond": [{ "$eq": ["$status", "paid"] }, "pending", "$status"] }
+            } }],
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if let Some(before) = &before {
+        let reason = format!("Instrument bounced: {}", bounce_data.reason.trim());
+        record_fee_change(&data.db, fee_obj_id, "payment_reversed", &claims.sub, &reason, before)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+    if let Some(parent_fee_id) = before.and_then(|fee| fee.parent_fee_id) {
+        sync_parent_fee(&data.db, &parent_fee_id)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    let mut bounce_fee_id = None;
+    if data.bounce_charge > 0.0 {
+        let mut bounce_fee = FeeStructure {
+            id: None,
+            student_id: payment.student_id.clone(),
+            fee_type: "cheque_bounce".to_string(),
+            amount: data.bounce_charge,
+            due_date: now.date_naive(),
+            legacy_due_date: None,
+            status: "pending".to_string(),
+            campus_id: claims.campus_id.clone(),
+            created_at: now,
+            template_id: None,
+            semester: None,
+            late_fee: 0.0,
+            amount_paid: 0.0,
+            waived_amount: 0.0,
+            parent_fee_id: None,
+            installment_number: None,
+            installment_count: None,
+            source: None,
+        };
+        bounce_fee.id = fee_collection
+            .insert_one(&bounce_fee, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .inserted_id
+            .as_object_id();
+        let reason = format!("Bounce charge for payment {}", payment.id.map(|id| id.to_hex()).unwrap_or_default());
+        record_fees_created(&data.db, std::slice::from_ref(&bounce_fee), &claims.sub, &reason)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        let fee_id = bounce_fee.id.map(|id| id.to_hex());
+        if let Some(fee_id) = &fee_id {
+            collection
+                .update_one(
+                    doc! { "_id": payment.id },
+                    doc! { "$set": { "instrument.bounce_fee_id": fee_id } },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+        bounce_fee_id = fee_id;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Instrument marked bounced; fee reverted",
+        "fee_id": payment.fee_id,
+        "bounce_charge": data.bounce_charge,
+        "bounce_fee_id": bounce_fee_id
+    })))
+}
+
+// ===== PAYMENT GATEWAY =====
+
+fn to_minor_units(amount: f64) -> i64 {
+    (amount * 100.0).round() as i64
+}
+
+async fn create_gateway_order(gateway: &GatewayConfig, fee: &FeeStructure, fee_id: &str) -> Result<GatewayOrder, String> {
+    let client = reqwest::Client::new();
+    let amount = to_minor_units(fee.balance());
+
+    let response = match gateway.provider.as_str() {
+        "razorpay" => client
+            .post("https://api.razorpay.com/v1/orders")
+            .basic_auth(&gateway.key_id, Some(&gateway.key_secret))
+            .json(&serde_json::json!({
+                "amount": amount,
+                "currency": gateway.currency.to_uppercase(),
+                "receipt": fee_id,
+                "notes": { "fee_id": fee_id, "student_id": &fee.student_id }
+            }))
+            .send()
+            .await,
+        _ => client
+            .post("https://api.stripe.com/v1/payment_intents")
+            .bearer_auth(&gateway.key_secret)
+            .form(&[
+                ("amount", amount.to_string()),
+                ("currency", gateway.currency.to_lowercase()),
+                ("metadata[fee_id]", fee_id.to_string()),
+                ("metadata[student_id]", fee.student_id.clone()),
+            ])
+            .send()
+            .await,
+    }
+    .map_err(|e| format!("Gateway request failed: {}", e))?;
+
+    if !response.status().is_success() {
+        return Err(format!("Gateway rejected the order ({})", response.status()));
+    }
+    let body: serde_json::Value = response
+        .json()
+        .await
+        .map_err(|e| format!("Invalid gateway response: {}", e))?;
+
+    let order_id = body["id"]
+        .as_str()
+        .ok_or("Gateway response has no order id")?
+        .to_string();
+    Ok(GatewayOrder {
+        order_id,
+        client_secret: body["client_secret"].as_str().map(|s| s.to_string()),
+    })
+}
+
+fn hmac_sha256_matches(secret: &str, message: &[u8], signature_hex: &str) -> bool {
+    let Ok(signature) = hex::decode(signature_hex.trim()) else {
+        return false;
+    };
+    let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+        return false;
+    };
+    mac.update(message);
+    mac.verify_slice(&signature).is_ok()
+}
+
+// Razorpay signs the raw body with the webhook secret
+fn verify_razorpay_signature(secret: &str, header: Option<&str>, body: &[u8]) -> bool {
+    header.is_some_and(|signature| hmac_sha256_matches(secret, body, signature))
+}
+
+// Stripe-Signature is "t=<unix time>,v1=<hex>[,v1=<hex>...]" over "<t>.<body>"
+fn verify_stripe_signature(secret: &str, header: Option<&str>, body: &[u8], now: i64) -> bool {
+    let Some(header) = header else {
+        return false;
+    };
+    let mut timestamp = None;
+    let mut signatures = Vec::new();
+    for part in header.split(',') {
+        match part.trim().split_once('=') {
+            Some(("t", value)) => timestamp = value.parse::<i64>().ok(),
+            Some(("v1", value)) => signatures.push(value),
+            _ => {}
+        }
+    }
+    let Some(timestamp) = timestamp else {
+        return false;
+    };
+    if (now - timestamp).abs() > STRIPE_TOLERANCE_SECS {
+        return false;
+    }
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(body);
+    signatures.iter().any(|signature| hmac_sha256_matches(secret, &signed, signature))
+}
+
+// Returns None for events that do not settle a payment
+fn parse_razorpay_event(event: &serde_json::Value) -> Option<GatewayOutcome> {
+    let succeeded = match event["event"].as_str()? {
+        "payment.captured" | "order.paid" => true,
+        "payment.failed" => false,
+        _ => return None,
+    };
+    let payment = &event["payload"]["payment"]["entity"];
+    Some(GatewayOutcome {
+        order_id: payment["order_id"].as_str()?.to_string(),
+        succeeded,
+        transaction_id: payment["id"].as_str().unwrap_or_default().to_string(),
+        payment_method: payment["method"].as_str().unwrap_or("gateway").to_string(),
+        amount_minor: payment["amount"].as_i64().unwrap_or(0),
+        failure_reason: payment["error_description"].as_str().map(|s| s.to_string()),
+    })
+}
+
+fn parse_stripe_event(event: &serde_json::Value) -> Option<GatewayOutcome> {
+    let succeeded = match event["type"].as_str()? {
+        "payment_intent.succeeded" => true,
+        "payment_intent.payment_failed" => false,
+        _ => return None,
+    };
+    let intent = &event["data"]["object"];
+    let order_id = intent["id"].as_str()?.to_string();
+    Some(GatewayOutcome {
+        transaction_id: intent["latest_charge"].as_str().unwrap_or(&order_id).to_string(),
+        order_id,
+        succeeded,
+        payment_method: intent["payment_method_types"][0].as_str().unwrap_or("card").to_string(),
+        amount_minor: intent["amount_received"].as_i64().or(intent["amount"].as_i64()).unwrap_or(0),
+        failure_reason: intent["last_payment_error"]["message"].as_str().map(|s| s.to_string()),
+    })
+}
+
+// Starts an online payment: creates a gateway order and a pending payment.
+// The fee is only marked paid once the gateway's webhook confirms it.
+async fn create_checkout(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    checkout_data: web::Json<CheckoutRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(gateway) = &data.gateway else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Online payments are not configured"
+        })));
+    };
+
+    let fee_obj_id = ObjectId::parse_str(&checkout_data.fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+
+    if claims.role == "student" && fee.student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only pay your own fees"
+        })));
+    }
+    if fee.installment_count.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee is paid in installments; pay the installment fees instead"
+        })));
+    }
+    if fee.status == "paid" {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Fee is already paid"
+        })));
+    }
+
+    let order = create_gateway_order(gateway, &fee, &checkout_data.fee_id)
+        .await
+        .map_err(actix_web::error::ErrorBadGateway)?;
+
+    let collection: Collection<Payment> = data.db.collection("payments");
+    let result = collection
+        .insert_one(Payment {
+            id: None,
+            student_id: fee.student_id.clone(),
+            fee_id: checkout_data.fee_id.clone(),
+            amount: fee.balance(),
+            payment_method: "gateway".to_string(),
+            transaction_id: String::new(),
+            payment_date: Utc::now(),
+            campus_id: claims.campus_id,
+            status: "pending".to_string(),
+            gateway: Some(gateway.provider.clone()),
+            gateway_order_id: Some(order.order_id.clone()),
+            failure_reason: None,
+            instrument: None,
+            reconciliation_id: None,
+            reconciled_at: None,
+            awaiting_credit: false,
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "payment_id": result.inserted_id,
+        "gateway": gateway.provider,
+        "order_id": order.order_id,
+        "client_secret": order.client_secret,
+        "key_id": gateway.key_id,
+        "amount": to_minor_units(fee.balance()),
+        "currency": gateway.currency
+    })))
+}
+
+// Gateway callback. Only requests with a valid signature can settle a payment;
+// redelivered events are acknowledged without changing anything.
+async fn payment_webhook(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let provider = path.into_inner();
+    let gateway = match &data.gateway {
+        Some(gateway) if gateway.provider == provider => gateway,
+        _ => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Unknown payment gateway"
+            })));
+        }
+    };
+
+    let header = |name: &str| req.headers().get(name).and_then(|v| v.to_str().ok());
+    let verified = match provider.as_str() {
+        "razorpay" => verify_razorpay_signature(&gateway.webhook_secret, header("X-Razorpay-Signature"), &body),
+        _ => verify_stripe_signature(&gateway.webhook_secret, header("Stripe-Signature"), &body, Utc::now().timestamp()),
+    };
+    if !verified {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid webhook signature"
+        })));
+    }
+
+    let event: serde_json::Value = serde_json::from_slice(&body)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let outcome = match provider.as_str() {
+        "razorpay" => parse_razorpay_event(&event),
+        _ => parse_stripe_event(&event),
+    };
+    let Some(outcome) = outcome else {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Event ignored" })));
+    };
+
+    let collection: Collection<Payment> = data.db.collection("payments");
+    let Some(payment) = collection
+        .find_one(doc! { "gateway": &provider, "gateway_order_id": &outcome.order_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        // Acknowledge so the gateway stops retrying an order we never created
+        return Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Unknown order" })));
+    };
+
+    let (status, failure_reason) = if !outcome.succeeded {
+        ("failed", outcome.failure_reason.unwrap_or_else(|| "Payment failed".to_string()))
+    } else if outcome.amount_minor != to_minor_units(payment.amount) {
+        ("failed", format!("Amount mismatch: gateway reported {} minor units", outcome.amount_minor))
+    } else {
+        ("success", String::new())
+    };
+
+    // A failed attempt can still be followed by a successful retry on the same order
+    let from: Vec<&str> = if status == "success" { vec!["pending", "failed"] } else { vec!["pending"] };
+    let mut update = doc! {
+        "status": status,
+        "transaction_id": &outcome.transaction_id,
+        "payment_method": &outcome.payment_method,
+        "payment_date": Utc::now().to_rfc3339()
+    };
+    if status == "failed" {
+        update.insert("failure_reason", &failure_reason);
+    }
+    let result = collection
+        .update_one(
+            doc! { "_id": payment.id, "status": { "$in": from } },
+            doc! { "$set": update },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.modified_count == 1 && status == "success" {
+        let fee_obj_id = ObjectId::parse_str(&payment.fee_id)
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        let applied = credit_fee(&data.db, fee_obj_id, &payment.campus_id, "amount_paid", payment.amount, &format!("gateway:{}", provider))
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if !applied {
+            // The gateway has taken the money, so keep the payment and flag it for staff
+            log::warn!(
+                "Gateway payment {} for fee {} exceeds the fee's balance; needs manual reconciliation",
+                outcome.order_id, payment.fee_id
+            );
+        }
+        if let Some(payment_id) = payment.id {
+            publish_payment_completed(&data.db, &data.events, payment_id).await;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "message": "Webhook processed" })))
+}
+
+// ===== OVERDUE FEES =====
+
+// Accepts ISO dates ("YYYY-MM-DD") and full RFC3339 timestamps
+fn parse_due_date(due_date: &str) -> Option<NaiveDate> {
+    let due_date = due_date.trim();
+    NaiveDate::parse_from_str(due_date, "%Y-%m-%d")
+        .ok()
+        .or_else(|| DateTime::parse_from_rfc3339(due_date).ok().map(|d| d.date_naive()))
+}
+
+// Flips unpaid fees past their due date to overdue and tops up their late fee to
+// what the policy says is owed. Accruing the difference keeps repeated or
+// missed runs from over- or under-charging. campus_id None scans every campus.
+async fn scan_overdue_fees(
+    db: &mongodb::Database,
+    campus_id: Option<&str>,
+    policy: &LateFeePolicy,
+    events: &EventPublisher,
+    actor: &str,
+) -> mongodb::error::Result<OverdueScanSummary> {
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let accrual_collection: Collection<LateFeeAccrual> = db.collection("late_fee_accruals");
+    let today = Utc::now().date_naive();
+
+    // Planned fees follow their installments and are never scanned themselves
+    let mut filter = doc! { "status": { "$in": ["pending", "overdue"] }, "installment_count": null };
+    if let Some(campus_id) = campus_id {
+        filter.insert("campus_id", campus_id);
+    }
+    let mut cursor = fee_collection.find(filter, None).await?;
+
+    let mut summary = OverdueScanSummary::default();
+    let mut parents_to_sync = std::collections::HashSet::new();
+    use futures::stream::StreamExt;
+    while let Some(fee) = cursor.next().await {
+        let fee = fee?;
+        let Some(fee_id) = fee.id else { continue };
+        let days_overdue = (today - fee.due_date).num_days();
+        if days_overdue <= 0 {
+            continue;
+        }
+
+        if fee.status == "pending" {
+            let result = fee_collection
+                .update_one(
+                    doc! { "_id": fee_id, "status": "pending" },
+                    doc! { "$set": { "status": "overdue" } },
+                    None,
+                )
+                .await?;
+            summary.marked_overdue += result.modified_count;
+            if result.modified_count == 0 {
+                // Paid in the meantime
+                continue;
+            }
+            let reason = format!("{} days past due date", days_overdue);
+            record_fee_change(db, fee_id, "marked_overdue", actor, &reason, &fee).await?;
+            if let Some(parent_fee_id) = &fee.parent_fee_id {
+                parents_to_sync.insert(parent_fee_id.clone());
+            }
+            publish_event(db, events, "fee.overdue", &fee.campus_id, serde_json::json!({
+                "fee_id": fee_id.to_hex(),
+                "student_id": &fee.student_id,
+                "fee_type": &fee.fee_type,
+                "amount": fee.amount,
+                "due_date": fee.due_date.format("%Y-%m-%d").to_string(),
+                "days_overdue": days_overdue,
+                "balance": fee.balance()
+            }))
+            .await;
+        }
+
+        let owed = policy.late_fee_for(days_overdue);
+        let delta = policy.late_fee_increase(fee.late_fee, days_overdue);
+        if delta <= 0.0 {
+            continue;
+        }
+        // Only apply on top of the late fee we read, so concurrent scans cannot double-charge
+        let result = fee_collection
+            .update_one(
+                doc! { "_id": fee_id, "status": "overdue", "late_fee": { "$in": [fee.late_fee, mongodb::bson::Bson::Null] } },
+                doc! { "$set": { "late_fee": owed } },
+                None,
+            )
+            .await?;
+        if result.modified_count == 0 {
+            continue;
+        }
+        let mut before = fee.clone();
+        before.status = "overdue".to_string();
+        let reason = format!("Late fee of {:.2} under the {} policy", delta, policy.kind);
+        record_fee_change(db, fee_id, "late_fee_accrued", actor, &reason, &before).await?;
+        accrual_collection
+            .insert_one(LateFeeAccrual {
+                id: None,
+                fee_id: fee_id.to_hex(),
+                student_id: fee.student_id.clone(),
+                amount: delta,
+                policy: policy.kind.clone(),
+                days_overdue,
+                late_fee_total: owed,
+                campus_id: fee.campus_id.clone(),
+                accrued_at: Utc::now(),
+            }, None)
+            .await?;
+        summary.late_fees_accrued += 1;
+        summary.late_fee_amount += delta;
+        if let Some(parent_fee_id) = &fee.parent_fee_id {
+            parents_to_sync.insert(parent_fee_id.clone());
+        }
+    }
+
+    for parent_fee_id in &parents_to_sync {
+        sync_parent_fee(db, parent_fee_id).await?;
+    }
+
+    summary.late_fee_amount = (summary.late_fee_amount * 100.0).round() / 100.0;
+    Ok(summary)
+}
+
+async fn run_overdue_scan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let summary = scan_overdue_fees(&data.db, Some(&claims.campus_id), &data.late_fee_policy, &data.events, &claims.sub)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+async fn get_fee_accruals(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let fee_id = path.into_inner();
+    let fee_obj_id = ObjectId::parse_str(&fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+    if claims.role == "student" && fee.student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own fees"
+        })));
+    }
+
+    let accrual_collection: Collection<LateFeeAccrual> = data.db.collection("late_fee_accruals");
+    let mut cursor = accrual_collection
+        .find(doc! { "fee_id": &fee_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut accruals = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(accrual) => accruals.push(accrual),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "fee_id": fee_id,
+        "amount": fee.amount,
+        "late_fee": fee.late_fee,
+        "amount_paid": fee.amount_paid,
+        "amount_due": fee.balance(),
+        "accruals": accruals
+    })))
+}
+
+// ===== INSTALLMENT PLANS =====
+
+const MAX_INSTALLMENTS: u32 = 24;
+
+// Months between installments for a plan frequency; a term is six months
+fn installment_interval_months(frequency: &str) -> Option<u32> {
+    match frequency {
+        "monthly" => Some(1),
+        "term" => Some(6),
+        _ => None,
+    }
+}
+
+// Splits amount into count equal parts rounded to the paisa, with the rounding
+// remainder on the last installment so the parts add up exactly
+fn split_installments(amount: f64, count: u32) -> Vec<f64> {
+    let total_minor = (amount * 100.0).round() as i64;
+    let share = total_minor / count as i64;
+    (0..count)
+        .map(|k| {
+            let minor = if k + 1 == count { total_minor - share * (count as i64 - 1) } else { share };
+            minor as f64 / 100.0
+        })
+        .collect()
+}
+
+async fn create_installment_plan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    plan_data: web::Json<InstallmentPlanRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if !(2..=MAX_INSTALLMENTS).contains(&plan_data.installments) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("installments must be between 2 and {}", MAX_INSTALLMENTS)
+        })));
+    }
+    let Some(interval) = installment_interval_months(&plan_data.frequency) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "frequency must be 'monthly' or 'term'"
+        })));
+    };
+
+    let fee_id = path.into_inner();
+    let fee_obj_id = ObjectId::parse_str(&fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+
+    if fee.installment_count.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee already has an installment plan"
+        })));
+    }
+    if fee.parent_fee_id.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Installments cannot be split further"
+        })));
+    }
+    if fee.status != "pending" || fee.amount_paid > 0.0 || fee.waived_amount > 0.0 || fee.late_fee > 0.0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only pending fees without payments, waivers or late fees can be split"
+        })));
+    }
+
+    let start_date = match &plan_data.start_date {
+        Some(start_date) => match parse_due_date(start_date) {
+            Some(date) => date,
+            None => return Ok(invalid_due_date("start_date", start_date)),
+        },
+        None => fee.due_date,
+    };
+
+    // Claim the fee first so concurrent requests cannot create two plans
+    let result = fee_collection
+        .update_one(
+            doc! {
+                "_id": fee_obj_id,
+                "status": "pending",
+                "installment_count": null,
+                "amount_paid": { "$in": [0.0, mongodb::bson::Bson::Null] },
+                "waived_amount": { "$in": [0.0, mongodb::bson::Bson::Null] },
+                "late_fee": { "$in": [0.0, mongodb::bson::Bson::Null] }
+            },
+            doc! { "$set": { "installment_count": plan_data.installments } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee changed while creating the plan; try again"
+        })));
+    }
+
+    let now = Utc::now();
+    let mut installments = Vec::new();
+    for (k, amount) in split_installments(fee.amount, plan_data.installments).into_iter().enumerate() {
+        let due_date = start_date
+            .checked_add_months(Months::new(k as u32 * interval))
+            .ok_or_else(|| actix_web::error::ErrorBadRequest("Installment due date out of range"))?;
+        installments.push(FeeStructure {
+            id: None,
+            student_id: fee.student_id.clone(),
+            fee_type: fee.fee_type.clone(),
+            amount,
+            due_date,
+            legacy_due_date: None,
+            status: "pending".to_string(),
+            campus_id: fee.campus_id.clone(),
+            created_at: now,
+            template_id: fee.template_id.clone(),
+            semester: fee.semester.clone(),
+            late_fee: 0.0,
+            amount_paid: 0.0,
+            waived_amount: 0.0,
+            parent_fee_id: Some(fee_id.clone()),
+            installment_number: Some(k as u32 + 1),
+            installment_count: None,
+            source: None,
+        });
+    }
+
+    let inserted = fee_collection
+        .insert_many(installments.clone(), None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    for (index, installment) in installments.iter_mut().enumerate() {
+        installment.id = inserted.inserted_ids.get(&index).and_then(|id| id.as_object_id());
+    }
+
+    let reason = format!("Split into {} {} installments", plan_data.installments, plan_data.frequency);
+    record_fee_change(&data.db, fee_obj_id, "installment_plan_created", &claims.sub, &reason, &fee)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    record_fees_created(&data.db, &installments, &claims.sub, &format!("Installment of fee {}", fee_id))
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Installment plan created",
+        "fee_id": fee_id,
+        "frequency": plan_data.frequency,
+        "installments": installments
+    })))
+}
+
+async fn get_installment_plan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let fee_id = path.into_inner();
+    let fee_obj_id = ObjectId::parse_str(&fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+    if claims.role == "student" && fee.student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own fees"
+        })));
+    }
+    if fee.installment_count.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Fee has no installment plan"
+        })));
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "installment_number": 1 })
+        .build();
+    let mut cursor = fee_collection
+        .find(doc! { "parent_fee_id": &fee_id, "campus_id": &claims.campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut installments = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(installment) => installments.push(installment),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "amount_due": fee.balance(),
+        "fee": fee,
+        "installments": installments
+    })))
+}
+
+// ===== STUDENT WALLETS =====
+
+// Adds delta to a student's wallet and returns the new balance. Credits
+// create the wallet on first use. Debits only apply while the balance covers
+// them, so the balance never goes negative; None means it did not.
+async fn adjust_wallet(
+    db: &mongodb::Database,
+    student_id: &str,
+    campus_id: &str,
+    delta: f64,
+) -> mongodb::error::Result<Option<f64>> {
+    use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+
+    let collection: Collection<Wallet> = db.collection("wallets");
+    let mut filter = doc! { "student_id": student_id, "campus_id": campus_id };
+    if delta < 0.0 {
+        filter.insert("balance", doc! { "$gte": -delta - AMOUNT_EPSILON });
+    }
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(delta > 0.0)
+        .return_document(ReturnDocument::After)
+        .build();
+    let wallet = collection
+        .find_one_and_update(
+            filter,
+            doc! {
+                "$inc": { "balance": delta },
+                "$set": { "updated_at": Utc::now().to_rfc3339() }
+            },
+            options,
+        )
+        .await?;
+    Ok(wallet.map(|w| (w.balance * 100.0).round() / 100.0))
+}
+
+async fn wallet_balance(db: &mongodb::Database, student_id: &str, campus_id: &str) -> mongodb::error::Result<f64> {
+    let collection: Collection<Wallet> = db.collection("wallets");
+    let wallet = collection
+        .find_one(doc! { "student_id": student_id, "campus_id": campus_id }, None)
+        .await?;
+    Ok(wallet.map(|w| (w.balance * 100.0).round() / 100.0).unwrap_or(0.0))
+}
+
+async fn get_wallet(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own wallet"
+        })));
+    }
+
+    let balance = wallet_balance(&data.db, &student_id, &claims.campus_id)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "balance": balance
+    })))
+}
+
+async fn get_wallet_transactions(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own wallet"
+        })));
+    }
+
+    let collection: Collection<WalletTransaction> = data.db.collection("wallet_transactions");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .build();
+    let mut cursor = collection
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut transactions = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(transaction) => transactions.push(transaction),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(transactions))
+}
+
+async fn deposit_to_wallet(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    deposit_data: web::Json<WalletDepositRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if deposit_data.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Deposit amount must be positive"
+        })));
+    }
+
+    let student_id = path.into_inner();
+    let balance = adjust_wallet(&data.db, &student_id, &claims.campus_id, deposit_data.amount)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .unwrap_or(deposit_data.amount);
+
+    let collection: Collection<WalletTransaction> = data.db.collection("wallet_transactions");
+    collection
+        .insert_one(WalletTransaction {
+            id: None,
+            student_id: student_id.clone(),
+            campus_id: claims.campus_id.clone(),
+            kind: "deposit".to_string(),
+            amount: deposit_data.amount,
+            balance_after: balance,
+            fee_id: None,
+            payment_id: None,
+            reference: deposit_data.reference.clone(),
+            note: deposit_data.note.clone(),
+            recorded_by: claims.sub.clone(),
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Deposit recorded",
+        "balance": balance
+    })))
+}
+
+async fn pay_from_wallet(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    payment_data: web::Json<WalletPaymentRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only pay your own fees"
+        })));
+    }
+    if payment_data.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Payment amount must be positive"
+        })));
+    }
+
+    let fee_obj_id = ObjectId::parse_str(&payment_data.fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+
+    if fee.student_id != student_id {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Fee does not belong to student '{}'", student_id)
+        })));
+    }
+    if fee.installment_count.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee is paid in installments; pay the installment fees instead"
+        })));
+    }
+    if !fee_accepts_payments(&fee.status) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Fee is {} and cannot take payments", fee.status)
+        })));
+    }
+    if fee.exceeds_balance(payment_data.amount) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Amount {:.2} exceeds the outstanding balance of {:.2}", payment_data.amount, fee.balance())
+        })));
+    }
+
+    // Take the money out of the wallet first; it goes back if the fee cannot take it
+    let Some(balance) = adjust_wallet(&data.db, &student_id, &claims.campus_id, -payment_data.amount)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Insufficient wallet balance"
+        })));
+    };
+
+    let payment_collection: Collection<Payment> = data.db.collection("payments");
+    let inserted = payment_collection
+        .insert_one(Payment {
+            id: None,
+            student_id: student_id.clone(),
+            fee_id: payment_data.fee_id.clone(),
+            amount: payment_data.amount,
+            payment_method: "wallet".to_string(),
+            transaction_id: String::new(),
+            payment_date: Utc::now(),
+            campus_id: claims.campus_id.clone(),
+            status: "success".to_string(),
+            gateway: None,
+            gateway_order_id: None,
+            failure_reason: None,
+            instrument: None,
+            reconciliation_id: None,
+            reconciled_at: None,
+            awaiting_credit: false,
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .inserted_id;
+
+    let applied = credit_fee(&data.db, fee_obj_id, &claims.campus_id, "amount_paid", payment_data.amount, &claims.sub)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !applied {
+        payment_collection
+            .delete_one(doc! { "_id": &inserted }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        adjust_wallet(&data.db, &student_id, &claims.campus_id, payment_data.amount)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee changed while recording the payment; reload and try again"
+        })));
+    }
+
+    if let Some(payment_id) = inserted.as_object_id() {
+        publish_payment_completed(&data.db, &data.events, payment_id).await;
+    }
+
+    let payment_id = inserted.as_object_id().map(|id| id.to_hex());
+    let transaction_collection: Collection<WalletTransaction> = data.db.collection("wallet_transactions");
+    transaction_collection
+        .insert_one(WalletTransaction {
+            id: None,
+            student_id: student_id.clone(),
+            campus_id: claims.campus_id.clone(),
+            kind: "payment".to_string(),
+            amount: payment_data.amount,
+            balance_after: balance,
+            fee_id: Some(payment_data.fee_id.clone()),
+            payment_id: payment_id.clone(),
+            reference: None,
+            note: None,
+            recorded_by: claims.sub.clone(),
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let (fee_balance, fee_status) = fee.after_credit(payment_data.amount);
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Fee paid from wallet",
+        "payment_id": payment_id,
+        "wallet_balance": balance,
+        "balance": fee_balance,
+        "fee_status": fee_status
+    })))
+}
+
+async fn refund_to_wallet(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    refund_data: web::Json<WalletRefundRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if refund_data.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Refund amount must be positive"
+        })));
+    }
+    if refund_data.note.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A note explaining the refund is required"
+        })));
+    }
+
+    let student_id = path.into_inner();
+    let transaction_collection: Collection<WalletTransaction> = data.db.collection("wallet_transactions");
+
+    if let Some(payment_id) = &refund_data.payment_id {
+        let payment_obj_id = ObjectId::parse_str(payment_id)
+            .map_err(actix_web::error::ErrorBadRequest)?;
+        let payment_collection: Collection<Payment> = data.db.collection("payments");
+        let Some(payment) = payment_collection
+            .find_one(doc! { "_id": payment_obj_id, "campus_id": &claims.campus_id, "student_id": &student_id }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+        else {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Payment not found for this student"
+            })));
+        };
+        if payment.status != "success" {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Payment is {} and cannot be refunded", payment.status)
+            })));
+        }
+
+        let refunded = aggregate_documents(&transaction_collection, vec![
+            doc! { "$match": { "kind": "refund", "payment_id": payment_id, "campus_id": &claims.campus_id } },
+            doc! { "$group": { "_id": null, "total": { "$sum": "$amount" } } },
+        ])
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .first()
+        .and_then(|d| d.get_f64("total").ok())
+        .unwrap_or(0.0);
+        if refunded + refund_data.amount > payment.amount + AMOUNT_EPSILON {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Refunds would exceed the payment amount of {:.2} ({:.2} already refunded)", payment.amount, refunded)
+            })));
+        }
+    }
+
+    let balance = adjust_wallet(&data.db, &student_id, &claims.campus_id, refund_data.amount)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .unwrap_or(refund_data.amount);
+
+    transaction_collection
+        .insert_one(WalletTransaction {
+            id: None,
+            student_id: student_id.clone(),
+            campus_id: claims.campus_id.clone(),
+            kind: "refund".to_string(),
+            amount: refund_data.amount,
+            balance_after: balance,
+            fee_id: None,
+            payment_id: refund_data.payment_id.clone(),
+            reference: None,
+            note: Some(refund_data.note.trim().to_string()),
+            recorded_by: claims.sub.clone(),
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    publish_event(&data.db, &data.events, "refund.issued", &claims.campus_id, serde_json::json!({
+        "student_id": &student_id,
+        "amount": refund_data.amount,
+        "payment_id": &refund_data.payment_id,
+        "note": refund_data.note.trim(),
+        "wallet_balance": balance
+    }))
+    .await;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Refund credited to wallet",
+        "balance": balance
+    })))
+}
+
+// ===== FINE INTEGRATION =====
+
+fn fine_fee_type(source: &str) -> Option<&'static str> {
+    match source {
+        "library" => Some("library_fine"),
+        "hostel" => Some("hostel_damage"),
+        // Billed as an ordinary hostel fee so it shows in hostel totals
+        "hostel_rent" => Some("hostel"),
+        "mess" => Some("mess_fee"),
+        _ => None,
+    }
+}
+
+// Service callers authenticate with the shared integration key and name the
+// campus; staff use their token and bill their own campus. Returns the campus
+// to act on. Rejections carry the JSON error response.
+fn fine_caller_campus(
+    req: &HttpRequest,
+    data: &AppState,
+    requested_campus: Option<&str>,
+) -> Result<String, Error> {
+    let reject = |response: HttpResponse| -> Error {
+        actix_web::error::InternalError::from_response("fine integration auth", response).into()
+    };
+
+    if let Some(key) = req.headers().get("X-Integration-Key").and_then(|v| v.to_str().ok()) {
+        let valid = data.fines.api_key.as_deref().is_some_and(|expected| {
+            expected.len() == key.len()
+                && expected.bytes().zip(key.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0
+        });
+        if !valid {
+            return Err(reject(HttpResponse::Unauthorized().json(serde_json::json!({
+                "error": "Invalid integration key"
+            }))));
+        }
+        return requested_campus
+            .filter(|c| !c.trim().is_empty())
+            .map(str::to_string)
+            .ok_or_else(|| reject(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "campus_id is required with an integration key"
+            }))));
+    }
+
+    let claims = extract_claims(req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    if claims.role != "hr" && claims.role != "admin" {
+        return Err(reject(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        }))));
+    }
+    Ok(claims.campus_id)
+}
+
+// Bills a fine as a fee unless it was billed before. Returns the fee id and
+// whether this call created it.
+async fn ingest_fine(
+    db: &mongodb::Database,
+    event: &FineEvent,
+    fee_type: &str,
+    campus_id: &str,
+    due_date: NaiveDate,
+) -> mongodb::error::Result<(String, bool)> {
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let source_filter = doc! {
+        "source.service": &event.source,
+        "source.reference_id": &event.reference_id,
+        "campus_id": campus_id
+    };
+    let mut fee = FeeStructure {
+        id: None,
+        student_id: event.student_id.clone(),
+        fee_type: fee_type.to_string(),
+        amount: (event.amount * 100.0).round() / 100.0,
+        due_date,
+        legacy_due_date: None,
+        status: "pending".to_string(),
+        campus_id: campus_id.to_string(),
+        created_at: Utc::now(),
+        template_id: None,
+        semester: None,
+        late_fee: 0.0,
+        amount_paid: 0.0,
+        waived_amount: 0.0,
+        parent_fee_id: None,
+        installment_number: None,
+        installment_count: None,
+        source: Some(FeeSource {
+            service: event.source.clone(),
+            reference_id: event.reference_id.clone(),
+        }),
+    };
+    match fee_collection.insert_one(&fee, None).await {
+        Ok(inserted) => {
+            fee.id = inserted.inserted_id.as_object_id();
+            let reason = format!("{} fine {}", event.source, event.reference_id);
+            record_fees_created(db, std::slice::from_ref(&fee), &event.source, &reason).await?;
+            Ok((fee.id.map(|id| id.to_hex()).unwrap_or_default(), true))
+        }
+        Err(e) if is_duplicate_key(&e) => {
+            let existing = fee_collection.find_one(source_filter, None).await?;
+            let fee_id = existing.and_then(|fee| fee.id).map(|id| id.to_hex()).ok_or(e)?;
+            Ok((fee_id, false))
+        }
+        Err(e) => Err(e),
+    }
+}
+
+async fn receive_fine_event(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    event: web::Json<FineEvent>,
+) -> Result<HttpResponse, Error> {
+    let campus_id = fine_caller_campus(&req, &data, event.campus_id.as_deref())?;
+
+    let Some(fee_type) = fine_fee_type(&event.source) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "source must be 'library', 'hostel', 'hostel_rent' or 'mess'"
+        })));
+    };
+    if event.reference_id.trim().is_empty() || event.student_id.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "reference_id and student_id are required"
+        })));
+    }
+    if event.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Fine amount must be positive"
+        })));
+    }
+    let due_date = match &event.due_date {
+        Some(due_date) => match parse_due_date(due_date) {
+            Some(date) => date,
+            None => return Ok(invalid_due_date("due_date", due_date)),
+        },
+        None => Utc::now().date_naive() + chrono::Duration::days(data.fines.due_days),
+    };
+
+    let (fee_id, created) = ingest_fine(&data.db, &event, fee_type, &campus_id, due_date)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let body = serde_json::json!({
+        "message": if created { "Fine billed" } else { "Fine already billed" },
+        "fee_id": fee_id
+    });
+    Ok(if created { HttpResponse::Created().json(body) } else { HttpResponse::Ok().json(body) })
+}
+
+// Lets the source service look up whether a fine has been paid
+async fn get_fine_status(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    query: web::Query<FineStatusQuery>,
+) -> Result<HttpResponse, Error> {
+    let campus_id = fine_caller_campus(&req, &data, query.campus_id.as_deref())?;
+    let (source, reference_id) = path.into_inner();
+
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! {
+            "source.service": &source,
+            "source.reference_id": &reference_id,
+            "campus_id": &campus_id
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fine has not been billed"
+            })));
+        }
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "source": source,
+        "reference_id": reference_id,
+        "fee_id": fee.id.map(|id| id.to_hex()),
+        "status": fee.status,
+        "amount": fee.amount,
+        "amount_paid": fee.amount_paid,
+        "waived_amount": fee.waived_amount,
+        "amount_due": fee.balance()
+    })))
+}
+
+// Pulls overdue fines recorded on library returns into fees, then writes each
+// fee's status back onto the book issue as fine_fee_id / fine_status so the
+// library service can show whether the fine is settled. Waivers approved in the
+// library (fine_waived) are left out of the bill, or credited to the fee if they
+// come after it; fine_waived_billed tracks how much of them the fee reflects.
+async fn sync_library_fines(db: &mongodb::Database, due_days: i64) -> mongodb::error::Result<FineSyncSummary> {
+    use futures::stream::StreamExt;
+    use mongodb::bson::Document;
+
+    let issue_collection: Collection<Document> = db.collection("book_issues");
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let mut summary = FineSyncSummary::default();
+
+    let mut cursor = issue_collection
+        .find(doc! { "fine_amount": { "$gt": 0.0 }, "fine_fee_id": null, "fine_status": { "$ne": "waived" } }, None)
+        .await?;
+    let due_date = Utc::now().date_naive() + chrono::Duration::days(due_days);
+    while let Some(issue) = cursor.next().await {
+        let issue = issue?;
+        let (Ok(issue_id), Ok(student_id), Ok(campus_id), Ok(fine_amount)) = (
+            issue.get_object_id("_id"),
+            issue.get_str("student_id"),
+            issue.get_str("campus_id"),
+            issue.get_f64("fine_amount"),
+        ) else {
+            continue;
+        };
+        let waived = issue.get_f64("fine_waived").unwrap_or(0.0);
+        let amount = fine_amount - waived;
+        if amount < AMOUNT_EPSILON {
+            continue;
+        }
+        let event = FineEvent {
+            source: "library".to_string(),
+            reference_id: issue_id.to_hex(),
+            student_id: student_id.to_string(),
+            amount,
+            campus_id: Some(campus_id.to_string()),
+            due_date: None,
+        };
+        let (fee_id, created) = ingest_fine(db, &event, "library_fine", campus_id, due_date).await?;
+        issue_collection
+            .update_one(
+                doc! { "_id": issue_id },
+                doc! { "$set": { "fine_fee_id": &fee_id, "fine_status": "pending", "fine_waived_billed": waived } },
+                None,
+            )
+            .await?;
+        if created {
+            summary.ingested += 1;
+        }
+    }
+
+    // Report status changes back until the fine is settled
+    let mut cursor = issue_collection
+        .find(doc! { "fine_fee_id": { "$ne": null }, "fine_status": { "$ne": "paid" } }, None)
+        .await?;
+    while let Some(issue) = cursor.next().await {
+        let issue = issue?;
+        let (Ok(issue_id), Ok(fee_id)) = (issue.get_object_id("_id"), issue.get_str("fine_fee_id")) else {
+            continue;
+        };
+        let Ok(fee_obj_id) = ObjectId::parse_str(fee_id) else { continue };
+
+        let waived = issue.get_f64("fine_waived").unwrap_or(0.0);
+        let waiver_due = waived - issue.get_f64("fine_waived_billed").unwrap_or(0.0);
+        if waiver_due >= AMOUNT_EPSILON {
+            if let Ok(campus_id) = issue.get_str("campus_id") {
+                let amount = (waiver_due * 100.0).round() / 100.0;
+                if credit_fee(db, fee_obj_id, campus_id, "waived_amount", amount, "library").await? {
+                    issue_collection
+                        .update_one(
+                            doc! { "_id": issue_id },
+                            doc! { "$set": { "fine_waived_billed": waived } },
+                            None,
+                        )
+                        .await?;
+                }
+            }
+        }
+
+        let Some(fee) = fee_collection.find_one(doc! { "_id": fee_obj_id }, None).await? else {
+            continue;
+        };
+        if issue.get_str("fine_status").ok() != Some(fee.status.as_str()) {
+            issue_collection
+                .update_one(
+                    doc! { "_id": issue_id },
+                    doc! { "$set": { "fine_status": &fee.status } },
+                    None,
+                )
+                .await?;
+            summary.status_updates += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+// Bills monthly mess bills generated by hostel-service, then reports each
+// fee's status back onto the bill as fee_id/fee_status
+async fn sync_mess_bills(db: &mongodb::Database, due_days: i64) -> mongodb::error::Result<FineSyncSummary> {
+    use futures::stream::StreamExt;
+    use mongodb::bson::Document;
+
+    let bill_collection: Collection<Document> = db.collection("mess_bills");
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let mut summary = FineSyncSummary::default();
+
+    let mut cursor = bill_collection
+        .find(doc! { "amount": { "$gt": 0.0 }, "fee_id": null }, None)
+        .await?;
+    let due_date = Utc::now().date_naive() + chrono::Duration::days(due_days);
+    while let Some(bill) = cursor.next().await {
+        let bill = bill?;
+        let (Ok(bill_id), Ok(student_id), Ok(campus_id), Ok(amount)) = (
+            bill.get_object_id("_id"),
+            bill.get_str("student_id"),
+            bill.get_str("campus_id"),
+            bill.get_f64("amount"),
+        ) else {
+            continue;
+        };
+        let event = FineEvent {
+            source: "mess".to_string(),
+            reference_id: bill_id.to_hex(),
+            student_id: student_id.to_string(),
+            amount,
+            campus_id: Some(campus_id.to_string()),
+            due_date: None,
+        };
+        let (fee_id, created) = ingest_fine(db, &event, "mess_fee", campus_id, due_date).await?;
+        bill_collection
+            .update_one(
+                doc! { "_id": bill_id },
+                doc! { "$set": { "fee_id": &fee_id, "fee_status": "pending" } },
+                None,
+            )
+            .await?;
+        if created {
+            summary.ingested += 1;
+        }
+    }
+
+    let mut cursor = bill_collection
+        .find(doc! { "fee_id": { "$ne": null }, "fee_status": { "$ne": "paid" } }, None)
+        .await?;
+    while let Some(bill) = cursor.next().await {
+        let bill = bill?;
+        let (Ok(bill_id), Ok(fee_id)) = (bill.get_object_id("_id"), bill.get_str("fee_id")) else {
+            continue;
+        };
+        let Ok(fee_obj_id) = ObjectId::parse_str(fee_id) else { continue };
+        let Some(fee) = fee_collection.find_one(doc! { "_id": fee_obj_id }, None).await? else {
+            continue;
+        };
+        if bill.get_str("fee_status").ok() != Some(fee.status.as_str()) {
+            bill_collection
+                .update_one(
+                    doc! { "_id": bill_id },
+                    doc! { "$set": { "fee_status": &fee.status } },
+                    None,
+                )
+                .await?;
+            summary.status_updates += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+async fn run_mess_bill_sync(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let summary = sync_mess_bills(&data.db, data.fines.due_days)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+async fn run_library_fine_sync(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let summary = sync_library_fines(&data.db, data.fines.due_days)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// ===== OUTBOUND EVENTS =====
+
+// Seconds to wait before retrying after the given number of failed attempts
+fn event_retry_delay(attempts: u32) -> i64 {
+    60 * 2_i64.pow(attempts.saturating_sub(1).min(10))
+}
+
+// Queues an event for every subscriber and starts delivering it right away.
+// Delivery failures never reach the caller; they are retried in the background.
+async fn publish_event(
+    db: &mongodb::Database,
+    events: &EventPublisher,
+    event_type: &str,
+    campus_id: &str,
+    data: serde_json::Value,
+) {
+    if events.webhook_urls.is_empty() {
+        return;
+    }
+    let event_id = ObjectId::new().to_hex();
+    let now = Utc::now();
+    let payload = serde_json::json!({
+        "id": &event_id,
+        "type": event_type,
+        "created_at": now.to_rfc3339(),
+        "campus_id": campus_id,
+        "data": data
+    })
+    .to_string();
+
+    let deliveries: Vec<EventDelivery> = events
+        .webhook_urls
+        .iter()
+        .map(|url| EventDelivery {
+            id: None,
+            event_id: event_id.clone(),
+            event_type: event_type.to_string(),
+            url: url.clone(),
+            payload: payload.clone(),
+            status: "pending".to_string(),
+            attempts: 0,
+            last_status_code: None,
+            last_error: None,
+            next_attempt_at: mongodb::bson::DateTime::from_millis(now.timestamp_millis()),
+            campus_id: campus_id.to_string(),
+            created_at: now,
+            delivered_at: None,
+        })
+        .collect();
+    let collection: Collection<EventDelivery> = db.collection("event_deliveries");
+    if let Err(e) = collection.insert_many(deliveries, None).await {
+        log::warn!("Could not queue {} event {}: {}", event_type, event_id, e);
+        return;
+    }
+
+    let db = db.clone();
+    let events = events.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = deliver_due_events(&db, &events).await {
+            log::warn!("Event delivery failed: {}", e);
+        }
+    });
+}
+
+async fn publish_payment_completed(db: &mongodb::Database, events: &EventPublisher, payment_id: ObjectId) {
+    if events.webhook_urls.is_empty() {
+        return;
+    }
+    let payment_collection: Collection<Payment> = db.collection("payments");
+    let payment = match payment_collection.find_one(doc! { "_id": payment_id }, None).await {
+        Ok(Some(payment)) => payment,
+        Ok(None) => return,
+        Err(e) => {
+            log::warn!("Could not load payment {} for its event: {}", payment_id, e);
+            return;
+        }
+    };
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let fee = match ObjectId::parse_str(&payment.fee_id) {
+        Ok(fee_obj_id) => fee_collection.find_one(doc! { "_id": fee_obj_id }, None).await.ok().flatten(),
+        Err(_) => None,
+    };
+
+    publish_event(db, events, "payment.completed", &payment.campus_id, serde_json::json!({
+        "payment_id": payment_id.to_hex(),
+        "student_id": &payment.student_id,
+        "fee_id": &payment.fee_id,
+        "fee_type": fee.as_ref().map(|f| f.fee_type.clone()),
+        "amount": payment.amount,
+        "payment_method": &payment.payment_method,
+        "transaction_id": &payment.transaction_id,
+        "fee_status": fee.as_ref().map(|f| f.status.clone()),
+        "fee_balance": fee.as_ref().map(|f| f.balance())
+    }))
+    .await;
+}
+
+// Sends every delivery that is due. Each one is leased before sending so
+// concurrent workers never post the same delivery twice at once.
+async fn deliver_due_events(db: &mongodb::Database, events: &EventPublisher) -> mongodb::error::Result<u64> {
+    use mongodb::bson::DateTime as BsonDateTime;
+
+    let collection: Collection<EventDelivery> = db.collection("event_deliveries");
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+    let mut delivered = 0;
+
+    loop {
+        let now = Utc::now();
+        let lease_until = BsonDateTime::from_millis((now + chrono::Duration::minutes(5)).timestamp_millis());
+        let Some(delivery) = collection
+            .find_one_and_update(
+                doc! { "status": "pending", "next_attempt_at": { "$lte": BsonDateTime::from_millis(now.timestamp_millis()) } },
+                doc! { "$set": { "next_attempt_at": lease_until } },
+                None,
+            )
+            .await?
+        else {
+            break;
+        };
+
+        let signature = {
+            let mut mac = Hmac::<Sha256>::new_from_slice(events.secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(delivery.payload.as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        };
+        let result = client
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header("X-CampusConnect-Event", &delivery.event_type)
+            .header("X-CampusConnect-Delivery", delivery.id.map(|id| id.to_hex()).unwrap_or_default())
+            .header("X-CampusConnect-Signature", signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let attempts = delivery.attempts + 1;
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+            Ok(response) => (Some(response.status().as_u16()), Some(format!("HTTP {}", response.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let mut update = doc! { "attempts": attempts };
+        if let Some(code) = status_code {
+            update.insert("last_status_code", code as i32);
+        }
+        match error {
+            None => {
+                update.insert("status", "delivered");
+                update.insert("delivered_at", Utc::now().to_rfc3339());
+                delivered += 1;
+            }
+            Some(error) => {
+                if attempts >= events.max_attempts {
+                    update.insert("status", "failed");
+                    log::warn!("Giving up on {} delivery to {}: {}", delivery.event_type, delivery.url, error);
+                } else {
+                    let retry_at = now + chrono::Duration::seconds(event_retry_delay(attempts));
+                    update.insert("next_attempt_at", BsonDateTime::from_millis(retry_at.timestamp_millis()));
+                }
+                update.insert("last_error", error);
+            }
+        }
+        collection
+            .update_one(doc! { "_id": delivery.id }, doc! { "$set": update }, None)
+            .await?;
+    }
+
+    Ok(delivered)
+}
+
+async fn get_event_deliveries(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<EventDeliveryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(status) = &query.status {
+        filter.insert("status", status);
+    }
+    if let Some(event_type) = &query.event_type {
+        filter.insert("event_type", event_type);
+    }
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .limit(200)
+        .build();
+
+    let collection: Collection<EventDelivery> = data.db.collection("event_deliveries");
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut deliveries = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(delivery) => deliveries.push(delivery),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+// Puts a failed delivery back in the queue with a fresh set of attempts
+async fn retry_event_delivery(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let delivery_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<EventDelivery> = data.db.collection("event_deliveries");
+    let result = collection
+        .update_one(
+            doc! { "_id": delivery_id, "campus_id": &claims.campus_id, "status": "failed" },
+            doc! { "$set": {
+                "status": "pending",
+                "attempts": 0,
+                "next_attempt_at": mongodb::bson::DateTime::now()
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Failed delivery not found"
+        })));
+    }
+
+    let db = data.db.clone();
+    let events = data.events.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = deliver_due_events(&db, &events).await {
+            log::warn!("Event delivery failed: {}", e);
+        }
+    });
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Delivery queued for retry"
+    })))
+}
+
+// ===== DUNNING =====
+
+// Parses "reminder:1,warning:15,..." into stages sorted by days overdue
+fn parse_dunning_stages(spec: &str) -> Result<Vec<DunningStage>, String> {
+    let mut stages = Vec::new();
+    for entry in spec.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+        let (name, days) = entry
+            .split_once(':')
+            .ok_or_else(|| format!("'{}' is not name:days", entry))?;
+        let days_overdue = days
+            .trim()
+            .parse::<i64>()
+            .ok()
+            .filter(|d| *d > 0)
+            .ok_or_else(|| format!("'{}' needs a positive number of days", entry))?;
+        stages.push(DunningStage { name: name.trim().to_string(), days_overdue });
+    }
+    stages.sort_by_key(|stage| stage.days_overdue);
+    Ok(stages)
+}
+
+fn dunning_stage_for(stages: &[DunningStage], days_overdue: i64) -> Option<&DunningStage> {
+    stages.iter().rev().find(|stage| days_overdue >= stage.days_overdue)
+}
+
+// Places every student with overdue fees in the stage matching their oldest
+// overdue fee and emits dunning.stage_changed whenever a student moves up,
+// down or out of dunning. campus_id None scans every campus.
+async fn scan_dunning(
+    db: &mongodb::Database,
+    campus_id: Option<&str>,
+    stages: &[DunningStage],
+    events: &EventPublisher,
+) -> mongodb::error::Result<DunningScanSummary> {
+    use futures::stream::StreamExt;
+    use std::collections::HashMap;
+
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let status_collection: Collection<DunningStatus> = db.collection("dunning_status");
+    let today = Utc::now().date_naive();
+    let now = Utc::now();
+
+    // (campus, student) -> (days overdue of the oldest fee, outstanding, fee count)
+    let mut filter = doc! { "status": "overdue", "installment_count": null };
+    if let Some(campus_id) = campus_id {
+        filter.insert("campus_id", campus_id);
+    }
+    let mut overdue: HashMap<(String, String), (i64, f64, u32)> = HashMap::new();
+    let mut cursor = fee_collection.find(filter, None).await?;
+    while let Some(fee) = cursor.next().await {
+        let fee = fee?;
+        let entry = overdue
+            .entry((fee.campus_id.clone(), fee.student_id.clone()))
+            .or_insert((0, 0.0, 0));
+        entry.0 = entry.0.max((today - fee.due_date).num_days());
+        entry.1 += fee.balance();
+        entry.2 += 1;
+    }
+
+    let mut current: HashMap<(String, String), DunningStatus> = HashMap::new();
+    let mut status_filter = doc! {};
+    if let Some(campus_id) = campus_id {
+        status_filter.insert("campus_id", campus_id);
+    }
+    let mut cursor = status_collection.find(status_filter, None).await?;
+    while let Some(status) = cursor.next().await {
+        let status = status?;
+        current.insert((status.campus_id.clone(), status.student_id.clone()), status);
+    }
+
+    let mut summary = DunningScanSummary::default();
+    for ((campus, student_id), (days_overdue, outstanding, overdue_fees)) in &overdue {
+        // Overdue, but not yet long enough for the first stage
+        let Some(stage) = dunning_stage_for(stages, *days_overdue) else {
+            continue;
+        };
+        let outstanding = (outstanding * 100.0).round() / 100.0;
+        let previous = current.remove(&(campus.clone(), student_id.clone()));
+        summary.students_in_dunning += 1;
+
+        let previous_stage = previous.as_ref().map(|p| p.stage.clone());
+        let stage_changed = previous_stage.as_deref() != Some(stage.name.as_str());
+        let stage_entered_at = match &previous {
+            Some(previous) if !stage_changed => previous.stage_entered_at,
+            _ => now,
+        };
+        let status = DunningStatus {
+            id: None,
+            student_id: student_id.clone(),
+            campus_id: campus.clone(),
+            stage: stage.name.clone(),
+            days_overdue: *days_overdue,
+            outstanding,
+            overdue_fees: *overdue_fees,
+            stage_entered_at,
+            updated_at: now,
+        };
+        status_collection
+            .replace_one(
+                doc! { "student_id": student_id, "campus_id": campus },
+                &status,
+                mongodb::options::ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await?;
+
+        if stage_changed {
+            summary.stage_changes += 1;
+            publish_event(db, events, "dunning.stage_changed", campus, serde_json::json!({
+                "student_id": student_id,
+                "from_stage": previous_stage,
+                "to_stage": &stage.name,
+                "days_overdue": days_overdue,
+                "outstanding": outstanding,
+                "overdue_fees": overdue_fees
+            }))
+            .await;
+        }
+    }
+
+    // Whoever is left is no longer overdue long enough for any stage
+    for ((campus, student_id), previous) in current {
+        status_collection.delete_one(doc! { "_id": previous.id }, None).await?;
+        summary.cleared += 1;
+        publish_event(db, events, "dunning.stage_changed", &campus, serde_json::json!({
+            "student_id": student_id,
+            "from_stage": previous.stage,
+            "to_stage": null
+        }))
+        .await;
+    }
+
+    Ok(summary)
+}
+
+async fn run_dunning_scan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let summary = scan_dunning(&data.db, Some(&claims.campus_id), &data.dunning_stages, &data.events)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// Students grouped by dunning stage, as of the last scan
+async fn get_dunning(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<DunningQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if let Some(stage) = &query.stage {
+        if !data.dunning_stages.iter().any(|s| &s.name == stage) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Unknown dunning stage '{}'", stage)
+            })));
+        }
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(stage) = &query.stage {
+        filter.insert("stage", stage);
+    }
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "days_overdue": -1 })
+        .build();
+    let collection: Collection<DunningStatus> = data.db.collection("dunning_status");
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut statuses = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(status) => statuses.push(status),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let stages: Vec<serde_json::Value> = data
+        .dunning_stages
+        .iter()
+        .filter(|stage| query.stage.as_ref().is_none_or(|s| s == &stage.name))
+        .map(|stage| {
+            let students: Vec<&DunningStatus> = statuses.iter().filter(|s| s.stage == stage.name).collect();
+            serde_json::json!({
+                "stage": &stage.name,
+                "days_overdue": stage.days_overdue,
+                "count": students.len(),
+                "outstanding": (students.iter().map(|s| s.outstanding).sum::<f64>() * 100.0).round() / 100.0,
+                "students": students
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({ "stages": stages })))
+}
+
+// ===== BANK RECONCILIATION =====
+
+// How far a statement line's date may be from the payment's date
+const RECONCILIATION_DATE_WINDOW_DAYS: i64 = 3;
+// Largest difference accepted when the reference matches, e.g. bank charges
+const RECONCILIATION_AMOUNT_TOLERANCE: f64 = 1.0;
+
+// Banks export dates in many layouts; these cover the common Indian ones
+fn parse_statement_date(value: &str) -> Option<NaiveDate> {
+    let value = value.trim();
+    ["%Y-%m-%d", "%d/%m/%Y", "%d-%m-%Y", "%d-%b-%Y", "%d %b %Y", "%d/%m/%y", "%d-%b-%y"]
+        .iter()
+        .find_map(|format| NaiveDate::parse_from_str(value, format).ok())
+}
+
+// "₹1,234.50", "1234.50 CR" and the like; None for blanks
+fn parse_statement_amount(value: &str) -> Option<f64> {
+    let cleaned: String = value
+        .chars()
+        .filter(|c| c.is_ascii_digit() || *c == '.' || *c == '-')
+        .collect();
+    cleaned.parse().ok()
+}
+
+// Lowercase alphanumerics only, so "UTR: 1234-5678" and "utr12345678" compare equal
+fn normalize_reference(value: &str) -> String {
+    value.chars().filter(|c| c.is_ascii_alphanumeric()).map(|c| c.to_ascii_lowercase()).collect()
+}
+
+// References on a payment that a bank may print in its narration
+fn payment_references(payment: &Payment) -> Vec<String> {
+    let instrument_number = payment.instrument.as_ref().map(|i| i.instrument_number.as_str());
+    [Some(payment.transaction_id.as_str()), payment.gateway_order_id.as_deref(), instrument_number]
+        .into_iter()
+        .flatten()
+        .map(normalize_reference)
+        // Short ids such as "123" would match half the statement
+        .filter(|r| r.len() >= 4)
+        .collect()
+}
+
+// The day money from the payment should reach the bank
+fn payment_bank_date(payment: &Payment) -> NaiveDate {
+    payment
+        .instrument
+        .as_ref()
+        .and_then(|i| i.cleared_at)
+        .unwrap_or(payment.payment_date)
+        .date_naive()
+}
+
+fn find_column(headers: &csv::StringRecord, names: &[&str]) -> Option<usize> {
+    headers
+        .iter()
+        .position(|h| names.contains(&h.trim().to_lowercase().as_str()))
+}
+
+// Accepts a bank statement as a CSV body and matches each credit to an
+// unreconciled payment: first by a reference the payment carries (exact
+// amount, then within a small tolerance), then by exact amount within a few
+// days. Lines with several equally good candidates are left for staff.
+async fn import_bank_statement(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Bytes,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(body.as_ref());
+    let headers = reader.headers().map_err(actix_web::error::ErrorBadRequest)?.clone();
+    let date_column = find_column(&headers, &["date", "transaction date", "txn date", "value date"]);
+    let reference_column = find_column(&headers, &["reference", "description", "narration", "particulars", "ref no", "utr"]);
+    let amount_column = find_column(&headers, &["credit", "deposit", "credit amount", "deposits", "amount"]);
+    let (Some(date_column), Some(reference_column), Some(amount_column)) = (date_column, reference_column, amount_column) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Statement needs date, reference (or description/narration) and credit (or amount) columns"
+        })));
+    };
+
+    let payment_collection: Collection<Payment> = data.db.collection("payments");
+    let mut cursor = payment_collection
+        .find(doc! {
+            "campus_id": &claims.campus_id,
+            "status": "success",
+            "reconciliation_id": null,
+            "payment_method": { "$ne": "wallet" }
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut payments = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(payment) => payments.push(payment),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let batch_id = ObjectId::new();
+    let mut used = std::collections::HashSet::new();
+    let mut entries = Vec::new();
+    let (mut from, mut to): (Option<NaiveDate>, Option<NaiveDate>) = (None, None);
+    for (index, record) in reader.records().enumerate() {
+        let row = index + 2;
+        let record = record.map_err(actix_web::error::ErrorBadRequest)?;
+        let field = |column: usize| record.get(column).unwrap_or("").to_string();
+        let reference = field(reference_column);
+        let amount = parse_statement_amount(&field(amount_column)).unwrap_or(0.0);
+        let date = parse_statement_date(&field(date_column));
+        let mut entry = StatementEntry {
+            row,
+            date: date.map(|d| d.format("%Y-%m-%d").to_string()),
+            reference: reference.clone(),
+            amount,
+            status: "skipped".to_string(),
+            rule: None,
+            payment_id: None,
+            candidates: Vec::new(),
+            note: None,
+        };
+        let Some(date) = date else {
+            entry.note = Some(format!("Unreadable date '{}'", field(date_column)));
+            entries.push(entry);
+            continue;
+        };
+        if amount <= 0.0 {
+            entry.note = Some("Not a credit".to_string());
+            entries.push(entry);
+            continue;
+        }
+        from = Some(from.map_or(date, |f| f.min(date)));
+        to = Some(to.map_or(date, |t| t.max(date)));
+
+        let normalized = normalize_reference(&reference);
+        let open: Vec<&Payment> = payments
+            .iter()
+            .filter(|p| p.id.is_some_and(|id| !used.contains(&id)))
+            .collect();
+        let by_reference: Vec<&Payment> = open
+            .iter()
+            .copied()
+            .filter(|p| payment_references(p).iter().any(|r| normalized.contains(r.as_str())))
+            .collect();
+        let exact = |p: &&Payment| (p.amount - amount).abs() < AMOUNT_EPSILON;
+        let within_days = |p: &&Payment| (payment_bank_date(p) - date).num_days().abs() <= RECONCILIATION_DATE_WINDOW_DAYS;
+
+        let reference_exact: Vec<&Payment> = by_reference.iter().copied().filter(exact).collect();
+        let reference_close: Vec<&Payment> = by_reference
+            .iter()
+            .copied()
+            .filter(|p| (p.amount - amount).abs() <= RECONCILIATION_AMOUNT_TOLERANCE)
+            .collect();
+        let amount_date: Vec<&Payment> = open.iter().copied().filter(exact).filter(within_days).collect();
+        let (rule, candidates) = if !reference_exact.is_empty() {
+            ("reference", reference_exact)
+        } else if !reference_close.is_empty() {
+            ("reference_amount_tolerance", reference_close)
+        } else {
+            ("amount_date", amount_date)
+        };
+
+        match candidates.as_slice() {
+            [] => entry.status = "unmatched".to_string(),
+            [payment] => {
+                let payment_id = payment.id.expect("filtered to stored payments");
+                used.insert(payment_id);
+                entry.status = "matched".to_string();
+                entry.rule = Some(rule.to_string());
+                entry.payment_id = Some(payment_id.to_hex());
+                if (payment.amount - amount).abs() >= AMOUNT_EPSILON {
+                    entry.note = Some(format!("Bank amount differs from payment by {:.2}", amount - payment.amount));
+                }
+            }
+            several => {
+                entry.status = "ambiguous".to_string();
+                entry.rule = Some(rule.to_string());
+                entry.candidates = several.iter().filter_map(|p| p.id.map(|id| id.to_hex())).collect();
+            }
+        }
+        entries.push(entry);
+    }
+
+    let now = Utc::now();
+    let mut matched_ids = Vec::new();
+    for payment_id in &used {
+        let result = payment_collection
+            .update_one(
+                doc! { "_id": payment_id, "reconciliation_id": null },
+                doc! { "$set": { "reconciliation_id": batch_id.to_hex(), "reconciled_at": now.to_rfc3339() } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if result.modified_count == 1 {
+            matched_ids.push(payment_id.to_hex());
+        }
+    }
+    // A concurrent import may have claimed a payment first
+    for entry in entries.iter_mut().filter(|e| e.status == "matched") {
+        if !entry.payment_id.as_ref().is_some_and(|id| matched_ids.contains(id)) {
+            entry.status = "unmatched".to_string();
+            entry.note = Some("Payment was reconciled by another import".to_string());
+            entry.payment_id = None;
+        }
+    }
+
+    let unreconciled_payments = match (from, to) {
+        (Some(from), Some(to)) => payments
+            .iter()
+            .filter(|p| p.id.is_some_and(|id| !used.contains(&id)))
+            .filter(|p| (from..=to).contains(&payment_bank_date(p)))
+            .filter_map(|p| p.id.map(|id| id.to_hex()))
+            .collect(),
+        _ => Vec::new(),
+    };
+    let count = |status: &str| entries.iter().filter(|e| e.status == status).count() as u32;
+    let total = |status: &str| {
+        (entries.iter().filter(|e| e.status == status).map(|e| e.amount).sum::<f64>() * 100.0).round() / 100.0
+    };
+    let batch = ReconciliationBatch {
+        id: Some(batch_id),
+        campus_id: claims.campus_id.clone(),
+        imported_by: claims.sub.clone(),
+        imported_at: now,
+        statement_from: from.map(|d| d.format("%Y-%m-%d").to_string()),
+        statement_to: to.map(|d| d.format("%Y-%m-%d").to_string()),
+        matched: count("matched"),
+        ambiguous: count("ambiguous"),
+        unmatched: count("unmatched"),
+        skipped: count("skipped"),
+        matched_amount: total("matched"),
+        unmatched_amount: total("unmatched"),
+        entries,
+        unreconciled_payments,
+    };
+
+    let collection: Collection<ReconciliationBatch> = data.db.collection("reconciliation_batches");
+    collection
+        .insert_one(&batch, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(batch))
+}
+
+async fn get_reconciliation_batches(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    // Line-level detail is only returned for a single batch
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "imported_at": -1 })
+        .projection(doc! { "entries": 0, "unreconciled_payments": 0 })
+        .build();
+    let collection: Collection<mongodb::bson::Document> = data.db.collection("reconciliation_batches");
+    let mut batches = collection
+        .find(doc! { "campus_id": &claims.campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut summaries = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = batches.next().await {
+        match result {
+            Ok(mut batch) => {
+                if let Ok(id) = batch.get_object_id("_id") {
+                    batch.insert("_id", id.to_hex());
+                }
+                summaries.push(batch);
+            }
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(summaries))
+}
+
+async fn get_reconciliation_batch(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let batch_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<ReconciliationBatch> = data.db.collection("reconciliation_batches");
+    match collection
+        .find_one(doc! { "_id": batch_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(batch) => Ok(HttpResponse::Ok().json(batch)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Reconciliation not found"
+        }))),
+    }
+}
+
+// ===== MIGRATIONS =====
+
+// Converts due dates stored as free text by earlier versions into BSON dates.
+// Text that is not an ISO date is kept in legacy_due_date and the fee is given
+// its creation date as due date, so staff can find and correct it.
+async fn migrate_due_dates(db: &mongodb::Database) -> mongodb::error::Result<(u64, u64)> {
+    let collection: Collection<mongodb::bson::Document> = db.collection("fees");
+    let mut cursor = collection
+        .find(doc! { "due_date": { "$type": "string" } }, None)
+        .await?;
+
+    let (mut converted, mut unparseable) = (0, 0);
+    use futures::stream::StreamExt;
+    while let Some(fee) = cursor.next().await {
+        let fee = fee?;
+        let Ok(id) = fee.get_object_id("_id") else { continue };
+        let text = fee.get_str("due_date").unwrap_or_default().to_string();
+        let update = match parse_due_date(&text) {
+            Some(date) => {
+                converted += 1;
+                doc! { "$set": { "due_date": due_date_bson(date) } }
+            }
+            None => {
+                unparseable += 1;
+                let fallback = fee
+                    .get_str("created_at")
+                    .ok()
+                    .and_then(|c| DateTime::parse_from_rfc3339(c).ok())
+                    .map(|c| c.date_naive())
+                    .unwrap_or_else(|| Utc::now().date_naive());
+                log::warn!("Fee {} has unparseable due date '{}'; using {} instead", id, text, fallback);
+                doc! { "$set": { "due_date": due_date_bson(fallback), "legacy_due_date": &text } }
+            }
+        };
+        collection.update_one(doc! { "_id": id }, update, None).await?;
+    }
+    Ok((converted, unparseable))
+}
+
+// ===== INDEXES =====
+
+// Payments that share a transaction id within a campus, which the unique
+// payments index cannot be built over: (campus_id, transaction_id, payment ids)
+async fn find_duplicate_transactions(db: &mongodb::Database) -> mongodb::error::Result<Vec<(String, String, Vec<String>)>> {
+    use futures::stream::StreamExt;
+
+    let pipeline = [
+        doc! { "$match": { "transaction_id": { "$gt": "" } } },
+        doc! { "$group": {
+            "_id": { "transaction_id": "$transaction_id", "campus_id": "$campus_id" },
+            "ids": { "$push": "$_id" },
+            "count": { "$sum": 1 }
+        } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+        doc! { "$sort": { "_id.campus_id": 1, "_id.transaction_id": 1 } },
+    ];
+    let mut cursor = db.collection::<Payment>("payments").aggregate(pipeline, None).await?;
+
+    let mut duplicates = Vec::new();
+    while let Some(group) = cursor.next().await {
+        let group = group?;
+        let key = group.get_document("_id").ok();
+        let field = |name: &str| key.and_then(|k| k.get_str(name).ok()).unwrap_or_default().to_string();
+        let ids = group
+            .get_array("ids")
+            .map(|ids| ids.iter().filter_map(|id| id.as_object_id()).map(|id| id.to_hex()).collect())
+            .unwrap_or_default();
+        duplicates.push((field("campus_id"), field("transaction_id"), ids));
+    }
+    Ok(duplicates)
+}
+
+async fn create_indexes(db: &mongodb::Database) -> mongodb::error::Result<()> {
+    use mongodb::{options::IndexOptions, IndexModel};
+
+    // Gateway payments have no transaction id until their webhook arrives
+    db.collection::<Payment>("payments")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "transaction_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .partial_filter_expression(doc! { "transaction_id": { "$gt": "" } })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+
+    // One fee per fine, however often the source service sends it
+    db.collection::<FeeStructure>("fees")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "source.service": 1, "source.reference_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .partial_filter_expression(doc! { "source.service": { "$exists": true } })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<FeeAuditEntry>("fee_audit")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "fee_id": 1, "occurred_at": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<DunningStatus>("dunning_status")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<Wallet>("wallets")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+// ===== FEE WAIVERS =====
+
+async fn find_waiver(
+    db: &mongodb::Database,
+    waiver_id: &str,
+    campus_id: &str,
+) -> Result<Option<FeeWaiver>, Error> {
+    let waiver_obj_id = ObjectId::parse_str(waiver_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<FeeWaiver> = db.collection("fee_waivers");
+    collection
+        .find_one(doc! { "_id": waiver_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Students request waivers on their own fees; staff may request on a student's behalf
+async fn create_waiver(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    waiver_data: web::Json<WaiverRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if waiver_data.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Waiver amount must be positive"
+        })));
+    }
+    if waiver_data.justification.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A justification is required"
+        })));
+    }
+
+    let fee_obj_id = ObjectId::parse_str(&waiver_data.fee_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let fee = match fee_collection
+        .find_one(doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(fee) => fee,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee not found"
+            })));
+        }
+    };
+
+    if claims.role == "student" && fee.student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only request waivers on your own fees"
+        })));
+    }
+    if fee.installment_count.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fee is paid in installments; waive the installment fees instead"
+        })));
+    }
+    if !fee_accepts_payments(&fee.status) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Fee is {} and cannot be waived", fee.status)
+        })));
+    }
+    if fee.exceeds_balance(waiver_data.amount) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Amount {:.2} exceeds the outstanding balance of {:.2}", waiver_data.amount, fee.balance())
+        })));
+    }
+
+    let collection: Collection<FeeWaiver> = data.db.collection("fee_waivers");
+    let open = collection
+        .count_documents(doc! {
+            "fee_id": &waiver_data.fee_id,
+            "campus_id": &claims.campus_id,
+            "status": { "$in": ["requested", "recommended"] }
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if open > 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "This fee already has a waiver request in progress"
+        })));
+    }
+
+    let result = collection
+        .insert_one(FeeWaiver {
+            id: None,
+            fee_id: waiver_data.fee_id.clone(),
+            student_id: fee.student_id,
+            amount: waiver_data.amount,
+            justification: waiver_data.justification.trim().to_string(),
+            status: "requested".to_string(),
+            requested_by: claims.sub,
+            requested_at: Utc::now(),
+            recommended_by: None,
+            recommended_at: None,
+            decided_by: None,
+            decided_at: None,
+            note: None,
+            campus_id: claims.campus_id,
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Waiver requested successfully",
+        "waiver_id": result.inserted_id
+    })))
+}
+
+async fn get_waivers(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<WaiverQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(status) = &query.status {
+        filter.insert("status", status);
+    }
+
+    let collection: Collection<FeeWaiver> = data.db.collection("fee_waivers");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "requested_at": -1 })
+        .build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut waivers = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(waiver) => waivers.push(waiver),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(waivers))
+}
+
+// First step: HR reviews the request and recommends it to an admin
+async fn recommend_waiver(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    decision: web::Json<WaiverDecisionRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let Some(waiver) = find_waiver(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Waiver not found"
+        })));
+    };
+
+    let collection: Collection<FeeWaiver> = data.db.collection("fee_waivers");
+    let result = collection
+        .update_one(
+            doc! { "_id": waiver.id, "status": "requested" },
+            doc! { "$set": {
+                "status": "recommended",
+                "recommended_by": &claims.sub,
+                "recommended_at": Utc::now().to_rfc3339(),
+                "note": &decision.note
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Waiver is {}; only requested waivers can be recommended", waiver.status)
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Waiver recommended for approval"
+    })))
+}
+
+// Second step: an admin approves a recommended waiver, which reduces the fee's
+// balance and is written to the finance audit log
+async fn approve_waiver(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    decision: web::Json<WaiverDecisionRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Admin role required"
+        })));
+    }
+
+    let Some(waiver) = find_waiver(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Waiver not found"
+        })));
+    };
+
+    // Claim the waiver first so two approvals cannot both credit the fee
+    let collection: Collection<FeeWaiver> = data.db.collection("fee_waivers");
+    let result = collection
+        .update_one(
+            doc! { "_id": waiver.id, "status": "recommended" },
+            doc! { "$set": {
+                "status": "approved",
+                "decided_by": &claims.sub,
+                "decided_at": Utc::now().to_rfc3339(),
+                "note": &decision.note
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Waiver is {}; only recommended waivers can be approved", waiver.status)
+        })));
+    }
+
+    let fee_obj_id = ObjectId::parse_str(&waiver.fee_id)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let applied = credit_fee(&data.db, fee_obj_id, &claims.campus_id, "waived_amount", waiver.amount, &claims.sub)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if !applied {
+        // Payments since the request left less owing than the waiver; send it back for review
+        collection
+            .update_one(
+                doc! { "_id": waiver.id },
+                doc! { "$set": { "status": "recommended" }, "$unset": { "decided_by": "", "decided_at": "" } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Waiver exceeds the fee's current balance or the fee is already settled"
+        })));
+    }
+
+    let audit: Collection<FinanceAuditEntry> = data.db.collection("finance_audit_log");
+    audit
+        .insert_one(FinanceAuditEntry {
+            id: None,
+            action: "waiver_approved".to_string(),
+            fee_id: waiver.fee_id.clone(),
+            student_id: waiver.student_id.clone(),
+            amount: waiver.amount,
+            actor: claims.sub.clone(),
+            detail: format!(
+                "Waiver {} requested by {}, recommended by {}: {}",
+                waiver.id.map(|id| id.to_hex()).unwrap_or_default(),
+                waiver.requested_by,
+                waiver.recommended_by.as_deref().unwrap_or("-"),
+                waiver.justification
+            ),
+            campus_id: claims.campus_id,
+            occurred_at: Utc::now(),
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Waiver approved",
+        "fee_id": waiver.fee_id,
+        "amount": waiver.amount
+    })))
+}
+
+// HR may reject a requested waiver; admins may reject at either step
+async fn reject_waiver(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    decision: web::Json<WaiverDecisionRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let rejectable: Vec<&str> = match claims.role.as_str() {
+        "admin" => vec!["requested", "recommended"],
+        "hr" => vec!["requested"],
+        _ => {
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Access denied: HR role required"
+            })));
+        }
+    };
+    if decision.note.as_deref().is_none_or(|n| n.trim().is_empty()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A note explaining the rejection is required"
+        })));
+    }
+
+    let Some(waiver) = find_waiver(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Waiver not found"
+        })));
+    };
+
+    let collection: Collection<FeeWaiver> = data.db.collection("fee_waivers");
+    let result = collection
+        .update_one(
+            doc! { "_id": waiver.id, "status": { "$in": &rejectable } },
+            doc! { "$set": {
+                "status": "rejected",
+                "decided_by": &claims.sub,
+                "decided_at": Utc::now().to_rfc3339(),
+                "note": &decision.note
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Waiver is {} and cannot be rejected by {}", waiver.status, claims.role)
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Waiver rejected"
+    })))
+}
+
+// ===== FEE REMINDERS =====
+
+// Which reminder, if any, a fee is due for today. Upcoming fees get the
+// tightest T-n reminder they have reached; overdue fees get one every
+// overdue_every_days.
+fn reminder_kind(schedule: &ReminderSchedule, days_until_due: i64) -> Option<String> {
+    if days_until_due < 0 {
+        return Some("overdue".to_string());
+    }
+    schedule
+        .days_before
+        .iter()
+        .filter(|d| days_until_due <= **d && days_until_due > 0)
+        .min()
+        .map(|d| format!("due_in_{}", d))
+}
+
+fn reminder_body(
+    schedule: &ReminderSchedule,
+    institution_name: &str,
+    fee: &FeeStructure,
+    fee_id: &str,
+    days_until_due: i64,
+) -> (String, String) {
+    let (subject, when) = if days_until_due < 0 {
+        (
+            format!("Overdue: {} fee", fee.fee_type),
+            format!("was due on {} ({} days ago). Late fees may apply.", fee.due_date, -days_until_due),
+        )
+    } else {
+        (
+            format!("Reminder: {} fee due on {}", fee.fee_type, fee.due_date),
+            format!("is due on {} ({} days from now).", fee.due_date, days_until_due),
+        )
+    };
+    let body = format!(
+        "Dear student,\n\nYour {} fee of {:.2} {}\n\nPay online: {}?fee_id={}\n\n{}\n\nTo stop these reminders, turn them off in your CampusConnect fee settings.",
+        fee.fee_type,
+        fee.balance(),
+        when,
+        schedule.payment_link,
+        fee_id,
+        institution_name
+    );
+    (subject, body)
+}
+
+// Emails every student whose unpaid fee has reached a reminder point and
+// records each attempt. An attempt (sent or failed) counts, so a student is
+// not re-sent the same reminder on every scan. campus_id None scans every campus.
+async fn scan_fee_reminders(
+    db: &mongodb::Database,
+    campus_id: Option<&str>,
+    schedule: &ReminderSchedule,
+    smtp: Option<&SmtpConfig>,
+    institution_name: &str,
+) -> mongodb::error::Result<ReminderScanSummary> {
+    let fee_collection: Collection<FeeStructure> = db.collection("fees");
+    let reminder_collection: Collection<FeeReminder> = db.collection("fee_reminders");
+    let preference_collection: Collection<ReminderPreference> = db.collection("reminder_preferences");
+    let today = Utc::now().date_naive();
+
+    let mut filter = doc! { "status": { "$in": ["pending", "overdue"] }, "installment_count": null };
+    if let Some(campus_id) = campus_id {
+        filter.insert("campus_id", campus_id);
+    }
+    let mut cursor = fee_collection.find(filter, None).await?;
+
+    let mut summary = ReminderScanSummary::default();
+    let mut opted_out: std::collections::HashMap<(String, String), bool> = std::collections::HashMap::new();
+    use futures::stream::StreamExt;
+    while let Some(fee) = cursor.next().await {
+        let fee = fee?;
+        let Some(fee_obj_id) = fee.id else { continue };
+        let days_until_due = (fee.due_date - today).num_days();
+        let Some(kind) = reminder_kind(schedule, days_until_due) else { continue };
+        let fee_id = fee_obj_id.to_hex();
+
+        let mut previous = doc! { "fee_id": &fee_id, "kind": &kind };
+        if kind == "overdue" {
+            let since = Utc::now() - chrono::Duration::days(schedule.overdue_every_days);
+            // Same format serde writes for sent_at, so string comparison is chronological
+            previous.insert("sent_at", doc! { "$gt": since.to_rfc3339_opts(chrono::SecondsFormat::AutoSi, true) });
+        }
+        if reminder_collection.count_documents(previous, None).await? > 0 {
+            continue;
+        }
+
+        let key = (fee.student_id.clone(), fee.campus_id.clone());
+        let student_opted_out = match opted_out.get(&key) {
+            Some(value) => *value,
+            None => {
+                let value = preference_collection
+                    .find_one(doc! { "student_id": &fee.student_id, "campus_id": &fee.campus_id }, None)
+                    .await?
+                    .is_some_and(|p| p.opted_out);
+                opted_out.insert(key, value);
+                value
+            }
+        };
+        if student_opted_out {
+            summary.opted_out += 1;
+            continue;
+        }
+
+        let email = student_email(db, &fee.student_id, &fee.campus_id).await?;
+        let result = match &email {
+            Some(email) => {
+                let (subject, body) = reminder_body(schedule, institution_name, &fee, &fee_id, days_until_due);
+                send_email(smtp, email, &subject, body, None).await
+            }
+            None => Err("Student has no email address on file".to_string()),
+        };
+        match &result {
+            Ok(()) => summary.sent += 1,
+            Err(e) => {
+                log::warn!("Fee reminder for {} ({}) failed: {}", fee.student_id, fee_id, e);
+                summary.failed += 1;
+            }
+        }
+
+        reminder_collection
+            .insert_one(FeeReminder {
+                id: None,
+                fee_id,
+                student_id: fee.student_id.clone(),
+                kind,
+                email,
+                amount: fee.balance(),
+                due_date: fee.due_date.to_string(),
+                status: if result.is_ok() { "sent" } else { "failed" }.to_string(),
+                error: result.err(),
+                campus_id: fee.campus_id.clone(),
+                sent_at: Utc::now(),
+            }, None)
+            .await?;
+    }
+
+    Ok(summary)
+}
+
+async fn run_reminder_scan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let summary = scan_fee_reminders(
+        &data.db,
+        Some(&claims.campus_id),
+        &data.reminders,
+        data.smtp.as_ref(),
+        &data.institution_name,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// Send history; students only see their own reminders
+async fn get_reminders(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ReminderHistoryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(fee_id) = &query.fee_id {
+        filter.insert("fee_id", fee_id);
+    }
+
+    let collection: Collection<FeeReminder> = data.db.collection("fee_reminders");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "sent_at": -1 })
+        .build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut reminders = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(reminder) => reminders.push(reminder),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(reminders))
+}
+
+async fn get_reminder_preference(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own reminder settings"
+        })));
+    }
+
+    let collection: Collection<ReminderPreference> = data.db.collection("reminder_preferences");
+    let preference = collection
+        .find_one(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "opted_out": preference.is_some_and(|p| p.opted_out)
+    })))
+}
+
+async fn update_reminder_preference(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    preference_data: web::Json<ReminderPreferenceRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only change your own reminder settings"
+        })));
+    }
+
+    let collection: Collection<ReminderPreference> = data.db.collection("reminder_preferences");
+    collection
+        .update_one(
+            doc! { "student_id": &student_id, "campus_id": &claims.campus_id },
+            doc! { "$set": {
+                "opted_out": preference_data.opted_out,
+                "updated_at": Utc::now().to_rfc3339()
+            } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "opted_out": preference_data.opted_out
+    })))
+}
+
+// ===== FEE TEMPLATES =====
+
+async fn create_fee_template(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    template_data: web::Json<FeeTemplateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let template_data = template_data.into_inner();
+    if template_data.name.trim().is_empty()
+        || template_data.program_code.trim().is_empty()
+        || template_data.semester.trim().is_empty()
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name, program_code and semester are required"
+        })));
+    }
+    if template_data.heads.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A fee template needs at least one fee head"
+        })));
+    }
+    if let Some(head) = template_data.heads.iter().find(|h| h.fee_type.trim().is_empty() || h.amount <= 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Fee head '{}' needs a fee_type and a positive amount", head.fee_type)
+        })));
+    }
+    if let Some(head) = template_data.heads.iter().find(|h| parse_due_date(&h.due_date).is_none()) {
+        return Ok(invalid_due_date(&format!("due_date of fee head '{}'", head.fee_type), &head.due_date));
+    }
+    let mut fee_types: Vec<&str> = template_data.heads.iter().map(|h| h.fee_type.as_str()).collect();
+    fee_types.sort();
+    fee_types.dedup();
+    if fee_types.len() != template_data.heads.len() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Each fee_type may appear only once in a template"
+        })));
+    }
+
+    let collection: Collection<FeeTemplate> = data.db.collection("fee_templates");
+    let result = collection
+        .insert_one(FeeTemplate {
+            id: None,
+            name: template_data.name,
+            program_code: template_data.program_code,
+            semester: template_data.semester,
+            heads: template_data.heads,
+            campus_id: claims.campus_id,
+            created_by: claims.sub,
+            created_at: Utc::now(),
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Fee template created successfully",
+        "template_id": result.inserted_id
+    })))
+}
+
+async fn get_fee_templates(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let collection: Collection<FeeTemplate> = data.db.collection("fee_templates");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut templates = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(template) => templates.push(template),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(templates))
+}
+
+// Students matching a template: assigned to its program (student_programs) and
+// enrolled in at least one course in its semester (enrollments). Both
+// collections are maintained by the academics service.
+async fn template_students(db: &mongodb::Database, template: &FeeTemplate) -> mongodb::error::Result<Vec<String>> {
+    let in_program = db
+        .collection::<mongodb::bson::Document>("student_programs")
+        .distinct("student_id", doc! { "program_code": &template.program_code, "campus_id": &template.campus_id }, None)
+        .await?;
+    let in_semester = db
+        .collection::<mongodb::bson::Document>("enrollments")
+        .distinct("student_id", doc! { "semester": &template.semester, "campus_id": &template.campus_id }, None)
+        .await?;
+
+    let in_semester: std::collections::HashSet<&str> = in_semester.iter().filter_map(|s| s.as_str()).collect();
+    let mut students: Vec<String> = in_program
+        .iter()
+        .filter_map(|s| s.as_str())
+        .filter(|s| in_semester.contains(s))
+        .map(|s| s.to_string())
+        .collect();
+    students.sort();
+    Ok(students)
+}
+
+// Generates a fee per head for every matching student. Fees this template
+// already generated are skipped, so applying it again only picks up newly
+// matching students.
+async fn apply_fee_template(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let template_id = path.into_inner();
+    let template_obj_id = ObjectId::parse_str(&template_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let template_collection: Collection<FeeTemplate> = data.db.collection("fee_templates");
+    let template = match template_collection
+        .find_one(doc! { "_id": template_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    {
+        Some(template) => template,
+        None => {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fee template not found"
+            })));
+        }
+    };
+
+    let students = template_students(&data.db, &template)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let existing = fee_collection
+        .distinct("student_id", doc! { "template_id": &template_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let existing: std::collections::HashSet<&str> = existing.iter().filter_map(|s| s.as_str()).collect();
+
+    let mut new_fees = Vec::new();
+    let mut skipped = Vec::new();
+    for student_id in &students {
+        if existing.contains(student_id.as_str()) {
+            skipped.push(student_id.clone());
+            continue;
+        }
+        for head in &template.heads {
+            new_fees.push(FeeStructure {
+                id: None,
+                student_id: student_id.clone(),
+                fee_type: head.fee_type.clone(),
+                amount: head.amount,
+                // Validated when the template was created
+                due_date: parse_due_date(&head.due_date).unwrap_or_else(|| Utc::now().date_naive()),
+                legacy_due_date: None,
+                status: "pending".to_string(),
+                campus_id: claims.campus_id.clone(),
+                created_at: Utc::now(),
+                template_id: Some(template_id.clone()),
+                semester: Some(template.semester.clone()),
+                late_fee: 0.0,
+                amount_paid: 0.0,
+                waived_amount: 0.0,
+                parent_fee_id: None,
+                installment_number: None,
+                installment_count: None,
+                source: None,
+            });
+        }
+    }
+
+    let created_students = students.len() - skipped.len();
+    if !new_fees.is_empty() {
+        let inserted = fee_collection
+            .insert_many(&new_fees, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        for (index, fee) in new_fees.iter_mut().enumerate() {
+            fee.id = inserted.inserted_ids.get(&index).and_then(|id| id.as_object_id());
+        }
+        record_fees_created(&data.db, &new_fees, &claims.sub, &format!("Generated from fee template {}", template_id))
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Fee template applied",
+        "matched_students": students.len(),
+        "created_students": created_students,
+        "created_fees": new_fees.len(),
+        "skipped_students": skipped.len(),
+        "skipped": skipped
+    })))
+}
+
+// Invoice Management
+
+// Indian financial year (April to March) a date falls in, e.g. "2024-25"
+fn financial_year(date: NaiveDate) -> String {
+    use chrono::Datelike;
+    let start = if date.month() >= 4 { date.year() } else { date.year() - 1 };
+    format!("{}-{:02}", start, (start + 1) % 100)
+}
+
+// Hands out the next number in a named sequence. The counters collection
+// holds one document per sequence and $inc with upsert keeps concurrent
+// callers from ever receiving the same number.
+async fn next_sequence(db: &mongodb::Database, name: &str) -> mongodb::error::Result<i64> {
+    use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+
+    let counters: Collection<mongodb::bson::Document> = db.collection("counters");
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    let counter = counters
+        .find_one_and_update(doc! { "_id": name }, doc! { "$inc": { "seq": 1_i64 } }, options)
+        .await?;
+    Ok(counter.and_then(|c| c.get_i64("seq").ok()).unwrap_or(1))
+}
+
+// Invoice numbers run from 1 in each campus and financial year:
+// INV-{campus}-{FY}-{seq}
+async fn next_invoice_number(db: &mongodb::Database, campus_id: &str, now: DateTime<Utc>) -> mongodb::error::Result<String> {
+    let year = financial_year(now.date_naive());
+    let seq = next_sequence(db, &format!("invoice:{}:{}", campus_id, year)).await?;
+    Ok(format!("INV-{}-{}-{:05}", campus_id, year, seq))
+}
+
+// Credit notes have their own sequence: CN-{campus}-{FY}-{seq}
+async fn next_credit_note_number(db: &mongodb::Database, campus_id: &str, now: DateTime<Utc>) -> mongodb::error::Result<String> {
+    let year = financial_year(now.date_naive());
+    let seq = next_sequence(db, &format!("credit_note:{}:{}", campus_id, year)).await?;
+    Ok(format!("CN-{}-{}-{:05}", campus_id, year, seq))
+}
+
+async fn create_invoice(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    invoice_data: web::Json<InvoiceRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Invoice> = data.db.collection("invoices");
+
+    if invoice_data.items.iter().any(|item| item.tax_rate < 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "tax_rate cannot be negative"
+        })));
+    }
+    let subtotal: f64 = invoice_data.items.iter().map(|item| item.amount).sum();
+    let tax_total: f64 = invoice_data.items.iter().map(|item| item.tax()).sum();
+    let now = Utc::now();
+    let invoice_number = next_invoice_number(&data.db, &claims.campus_id, now)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let new_invoice = Invoice {
+        id: None,
+        invoice_number: invoice_number.clone(),
+        student_id: invoice_data.student_id.clone(),
+        items: invoice_data.items.clone(),
+        total_amount: subtotal + tax_total,
+        tax_total,
+        campus_id: claims.campus_id,
+        created_at: now,
+        sent_status: default_sent_status(),
+        sent_to: None,
+        sent_at: None,
+        send_error: None,
+        status: default_invoice_status(),
+        cancellation_reason: None,
+        cancelled_by: None,
+        cancelled_at: None,
+        credit_note_id: None,
+    };
+
+    collection
+        .insert_one(new_invoice, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Invoice created successfully",
+        "invoice_number": invoice_number
+    })))
+}
+
+async fn get_invoices(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Invoice> = data.db.collection("invoices");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut invoices = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(invoice) => invoices.push(invoice),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(invoices))
+}
+
+// Renders an A4 invoice with one row per line item and the tax breakdown
+fn render_invoice_pdf(institution_name: &str, invoice: &Invoice) -> Result<Vec<u8>, String> {
+    use printpdf::{BuiltinFont, Line, Mm, PdfDocument, PdfLayerReference, Point};
+
+    const TOP: f32 = 277.0;
+    const BOTTOM: f32 = 25.0;
+    const LEFT: f32 = 20.0;
+    const RIGHT: f32 = 190.0;
+    const ROW: f32 = 6.0;
+
+    let (doc, page, layer) = PdfDocument::new(
+        format!("Invoice {}", invoice.invoice_number),
+        Mm(210.0),
+        Mm(297.0),
+        "Invoice",
+    );
+    let font = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| format!("Failed to load font: {}", e))?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| format!("Failed to load font: {}", e))?;
+
+    let rule = |layer: &PdfLayerReference, y: f32| {
+        layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(LEFT), Mm(y)), false),
+                (Point::new(Mm(RIGHT), Mm(y)), false),
+            ],
+            is_closed: false,
+        });
+    };
+
+    let mut layer = doc.get_page(page).get_layer(layer);
+    let mut y = TOP;
+
+    layer.use_text(institution_name, 18.0, Mm(LEFT), Mm(y), &bold);
+    y -= 8.0;
+    layer.use_text("Fee Invoice", 12.0, Mm(LEFT), Mm(y), &font);
+    y -= 6.0;
+    layer.use_text(format!("Campus: {}", invoice.campus_id), 10.0, Mm(LEFT), Mm(y), &font);
+    y -= 4.0;
+    rule(&layer, y);
+    y -= 8.0;
+
+    layer.use_text(format!("Invoice: {}", invoice.invoice_number), 10.0, Mm(LEFT), Mm(y), &bold);
+    layer.use_text(
+        format!("Date: {}", invoice.created_at.format("%Y-%m-%d")),
+        10.0,
+        Mm(130.0),
+        Mm(y),
+        &font,
+    );
+    y -= ROW;
+    layer.use_text(format!("Student ID: {}", invoice.student_id), 10.0, Mm(LEFT), Mm(y), &font);
+    if invoice.status == "cancelled" {
+        y -= ROW;
+        layer.use_text(
+            format!("CANCELLED: {}", invoice.cancellation_reason.as_deref().unwrap_or("")),
+            10.0,
+            Mm(LEFT),
+            Mm(y),
+            &bold,
+        );
+    }
+    y -= 10.0;
+
+    let columns: [(&str, f32); 4] = [
+        ("Description", LEFT),
+        ("Amount", 115.0),
+        ("Tax %", 140.0),
+        ("Tax", 165.0),
+    ];
+    for (label, x) in columns {
+        layer.use_text(label, 9.0, Mm(x), Mm(y), &bold);
+    }
+    y -= 2.0;
+    rule(&layer, y);
+    y -= 4.0;
+
+    for item in &invoice.items {
+        if y < BOTTOM {
+            let (page, page_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Invoice");
+            layer = doc.get_page(page).get_layer(page_layer);
+            y = TOP;
+        }
+        let description: String = item.description.chars().take(55).collect();
+        let cells = [
+            description,
+            format!("{:.2}", item.amount),
+            format!("{:.2}", item.tax_rate),
+            format!("{:.2}", item.tax()),
+        ];
+        for (cell, (_, x)) in cells.iter().zip(columns) {
+            layer.use_text(cell, 9.0, Mm(x), Mm(y), &font);
+        }
+        y -= ROW - 1.0;
+    }
+
+    if y - 4.0 * ROW < BOTTOM {
+        let (page, page_layer) = doc.add_page(Mm(210.0), Mm(297.0), "Invoice");
+        layer = doc.get_page(page).get_layer(page_layer);
+        y = TOP;
+    }
+    y -= 2.0;
+    rule(&layer, y);
+    y -= ROW;
+    let totals = [
+        ("Subtotal", invoice.total_amount - invoice.tax_total),
+        ("Tax", invoice.tax_total),
+        ("Total", invoice.total_amount),
+    ];
+    for (label, value) in totals {
+        let style = if label == "Total" { &bold } else { &font };
+        layer.use_text(label, 10.0, Mm(140.0), Mm(y), style);
+        layer.use_text(format!("{:.2}", value), 10.0, Mm(165.0), Mm(y), style);
+        y -= ROW;
+    }
+
+    doc.save_to_bytes()
+        .map_err(|e| format!("Failed to render invoice PDF: {}", e))
+}
+
+// Sends a plain-text email, optionally with a PDF attachment (filename, bytes),
+// or logs it when SMTP is not configured
+async fn send_email(
+    config: Option<&SmtpConfig>,
+    to: &str,
+    subject: &str,
+    body: String,
+    pdf: Option<(String, Vec<u8>)>,
+) -> Result<(), String> {
+    use lettre::{
+        message::{header::ContentType, Attachment, MultiPart, SinglePart},
+        transport::smtp::authentication::Credentials,
+        AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor,
+    };
+
+    let Some(config) = config else {
+        log::info!("SMTP not configured; email to {} ({}):\n{}", to, subject, body);
+        return Ok(());
+    };
+
+    let builder = Message::builder()
+        .from(config.from.parse().map_err(|_| "Invalid SMTP_FROM address".to_string())?)
+        .to(to.parse().map_err(|_| format!("Invalid recipient address '{}'", to))?)
+        .subject(subject);
+    let message = match pdf {
+        Some((filename, pdf)) => {
+            let pdf_type = ContentType::parse("application/pdf").map_err(|e| e.to_string())?;
+            builder.multipart(
+                MultiPart::mixed()
+                    .singlepart(SinglePart::plain(body))
+                    .singlepart(Attachment::new(filename).body(pdf, pdf_type)),
+            )
+        }
+        None => builder.header(ContentType::TEXT_PLAIN).body(body),
+    }
+    .map_err(|e| format!("Failed to build email: {}", e))?;
+
+    let mut transport = if config.starttls {
+        AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&config.host)
+            .map_err(|e| format!("Failed to configure SMTP relay: {}", e))?
+    } else {
+        AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(&config.host)
+    }
+    .port(config.port);
+
+    if let (Some(username), Some(password)) = (&config.username, &config.password) {
+        transport = transport.credentials(Credentials::new(username.clone(), password.clone()));
+    }
+
+    transport
+        .build()
+        .send(message)
+        .await
+        .map_err(|e| format!("Failed to send email: {}", e))?;
+    Ok(())
+}
+
+// Student accounts live in the auth service's users collection, keyed by username
+async fn student_email(
+    db: &mongodb::Database,
+    student_id: &str,
+    campus_id: &str,
+) -> mongodb::error::Result<Option<String>> {
+    let users: Collection<mongodb::bson::Document> = db.collection("users");
+    let user = users
+        .find_one(doc! { "username": student_id, "campus_id": campus_id }, None)
+        .await?;
+    Ok(user
+        .as_ref()
+        .and_then(|u| u.get_str("email").ok())
+        .filter(|e| !e.is_empty())
+        .map(|e| e.to_string()))
+}
+
+async fn find_invoice(
+    db: &mongodb::Database,
+    invoice_id: &str,
+    campus_id: &str,
+) -> Result<Option<Invoice>, Error> {
+    let invoice_obj_id = ObjectId::parse_str(invoice_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Invoice> = db.collection("invoices");
+    collection
+        .find_one(doc! { "_id": invoice_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn get_invoice_pdf(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(invoice) = find_invoice(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Invoice not found"
+        })));
+    };
+    if claims.role == "student" && invoice.student_id != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own invoices"
+        })));
+    }
+
+    let pdf = render_invoice_pdf(&data.institution_name, &invoice)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.pdf\"", invoice.invoice_number),
+        ))
+        .body(pdf))
+}
+
+// Emails the invoice PDF to the student and records whether delivery succeeded
+async fn send_invoice(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    send_data: Option<web::Json<SendInvoiceRequest>>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let Some(invoice) = find_invoice(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Invoice not found"
+        })));
+    };
+    if invoice.status == "cancelled" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Invoice is cancelled and cannot be sent"
+        })));
+    }
+
+    let email = match send_data.and_then(|d| d.into_inner().email).filter(|e| !e.trim().is_empty()) {
+        Some(email) => email,
+        None => {
+            match student_email(&data.db, &invoice.student_id, &claims.campus_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+            {
+                Some(email) => email,
+                None => {
+                    return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": "Student has no email address on file; pass one in the request body"
+                    })));
+                }
+            }
+        }
+    };
+
+    let pdf = render_invoice_pdf(&data.institution_name, &invoice)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let body = format!(
+        "Dear student,\n\nPlease find attached invoice {} for {:.2}.\n\n{}",
+        invoice.invoice_number, invoice.total_amount, data.institution_name
+    );
+    let result = send_email(
+        data.smtp.as_ref(),
+        &email,
+        &format!("Invoice {}", invoice.invoice_number),
+        body,
+        Some((format!("{}.pdf", invoice.invoice_number), pdf)),
+    )
+    .await;
+
+    let update = match &result {
+        Ok(()) => doc! { "$set": {
+            "sent_status": "sent",
+            "sent_to": &email,
+            "sent_at": Utc::now().to_rfc3339()
+        }, "$unset": { "send_error": "" } },
+        Err(e) => doc! { "$set": {
+            "sent_status": "failed",
+            "sent_to": &email,
+            "send_error": e
+        } },
+    };
+    let collection: Collection<Invoice> = data.db.collection("invoices");
+    collection
+        .update_one(doc! { "_id": invoice.id }, update, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match result {
+        Ok(()) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Invoice sent successfully",
+            "sent_to": email
+        }))),
+        Err(e) => Ok(HttpResponse::BadGateway().json(serde_json::json!({
+            "error": e
+        }))),
+    }
 }
 
-// Fee Management
-async fn create_fee(
+async fn cancel_invoice(
     data: web::Data<AppState>,
     req: HttpRequest,
-    fee_data: web::Json<FeeRequest>,
+    path: web::Path<String>,
+    cancel_data: web::Json<CancelInvoiceRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let collection: Collection<FeeStructure> = data.db.collection("fees");
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let reason = cancel_data.reason.trim();
+    if reason.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "A cancellation reason is required"
+        })));
+    }
 
-    let new_fee = FeeStructure {
-        id: None,
-        student_id: fee_data.student_id.clone(),
-        fee_type: fee_data.fee_type.clone(),
-        amount: fee_data.amount,
-        due_date: fee_data.due_date.clone(),
-        status: "pending".to_string(),
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
+    let Some(invoice) = find_invoice(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Invoice not found"
+        })));
     };
 
+    // Claim the cancellation first so a repeated request cannot issue two credit notes
+    let collection: Collection<Invoice> = data.db.collection("invoices");
+    let now = Utc::now();
+    let result = collection
+        .update_one(
+            doc! { "_id": invoice.id, "status": { "$ne": "cancelled" } },
+            doc! { "$set": {
+                "status": "cancelled",
+                "cancellation_reason": reason,
+                "cancelled_by": &claims.sub,
+                "cancelled_at": now.to_rfc3339()
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Invoice is already cancelled"
+        })));
+    }
+
+    let invoice_id = invoice.id.map(|id| id.to_hex()).unwrap_or_default();
+    let mut credit_note = CreditNote {
+        id: None,
+        credit_note_number: next_credit_note_number(&data.db, &claims.campus_id, now)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        invoice_id: invoice_id.clone(),
+        invoice_number: invoice.invoice_number.clone(),
+        student_id: invoice.student_id.clone(),
+        items: invoice.items.clone(),
+        total_amount: invoice.total_amount,
+        tax_total: invoice.tax_total,
+        reason: reason.to_string(),
+        campus_id: claims.campus_id.clone(),
+        created_by: claims.sub.clone(),
+        created_at: now,
+    };
+    let credit_note_collection: Collection<CreditNote> = data.db.collection("credit_notes");
+    credit_note.id = credit_note_collection
+        .insert_one(&credit_note, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .inserted_id
+        .as_object_id();
     collection
-        .insert_one(new_fee, None)
+        .update_one(
+            doc! { "_id": invoice.id },
+            doc! { "$set": { "credit_note_id": credit_note.id.map(|id| id.to_hex()) } },
+            None,
+        )
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Fee created successfully"
+        "message": "Invoice cancelled",
+        "invoice_id": invoice_id,
+        "credit_note": credit_note
     })))
 }
 
-async fn get_fees(
+async fn get_credit_notes(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
-
-    let collection: Collection<FeeStructure> = data.db.collection("fees");
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    }
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .build();
+    let collection: Collection<CreditNote> = data.db.collection("credit_notes");
     let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .find(filter, options)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let mut fees = Vec::new();
+    let mut credit_notes = Vec::new();
     use futures::stream::StreamExt;
-    
     while let Some(result) = cursor.next().await {
         match result {
-            Ok(fee) => fees.push(fee),
+            Ok(credit_note) => credit_notes.push(credit_note),
             Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
         }
     }
 
-    Ok(HttpResponse::Ok().json(fees))
+    Ok(HttpResponse::Ok().json(credit_notes))
 }
 
-// Payment Management
-async fn create_payment(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    payment_data: web::Json<PaymentRequest>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+// ===== EXPORTS =====
 
-    let collection: Collection<Payment> = data.db.collection("payments");
+fn csv_row<I, T>(fields: I) -> web::Bytes
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    // Writing to a Vec cannot fail
+    let _ = writer.write_record(fields);
+    web::Bytes::from(writer.into_inner().unwrap_or_default())
+}
 
-    let new_payment = Payment {
-        id: None,
-        student_id: payment_data.student_id.clone(),
-        fee_id: payment_data.fee_id.clone(),
-        amount: payment_data.amount,
-        payment_method: payment_data.payment_method.clone(),
-        transaction_id: payment_data.transaction_id.clone(),
-        payment_date: Utc::now(),
-        campus_id: claims.campus_id.clone(),
-    };
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
 
-    collection
-        .insert_one(new_payment, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+fn doc_string(document: &mongodb::bson::Document, key: &str) -> String {
+    match document.get(key) {
+        Some(mongodb::bson::Bson::String(value)) => value.clone(),
+        Some(mongodb::bson::Bson::ObjectId(id)) => id.to_hex(),
+        Some(mongodb::bson::Bson::Double(value)) => format!("{:.2}", value),
+        Some(mongodb::bson::Bson::Int32(value)) => value.to_string(),
+        Some(mongodb::bson::Bson::Int64(value)) => value.to_string(),
+        _ => String::new(),
+    }
+}
 
-    // Update fee status to paid
-    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
-    let fee_obj_id = ObjectId::parse_str(&payment_data.fee_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+fn export_response(content_type: &str, filename: &str) -> actix_web::HttpResponseBuilder {
+    let mut response = HttpResponse::Ok();
+    response.content_type(content_type).insert_header((
+        "Content-Disposition",
+        format!("attachment; filename=\"{}\"", filename),
+    ));
+    response
+}
 
-    fee_collection
-        .update_one(
-            doc! { "_id": fee_obj_id, "campus_id": &claims.campus_id },
-            doc! { "$set": { "status": "paid" } },
-            None,
-        )
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+const PAYMENT_CSV_HEADER: [&str; 9] = [
+    "payment_id", "payment_date", "student_id", "fee_id", "fee_type",
+    "amount", "payment_method", "transaction_id", "gateway",
+];
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Payment recorded successfully"
-    })))
+// One Tally receipt voucher: debit cash or bank, credit the fee type's income ledger
+fn tally_voucher(payment: &mongodb::bson::Document) -> web::Bytes {
+    let date: String = doc_string(payment, "payment_date").chars().filter(|c| c.is_ascii_digit()).take(8).collect();
+    let fee_type = doc_string(payment, "fee_type");
+    let method = doc_string(payment, "payment_method");
+    let amount = doc_string(payment, "amount");
+    let debit_ledger = if method == "cash" { "Cash" } else { "Bank Account" };
+    let mut income_ledger: String = fee_type.chars().take(1).flat_map(|c| c.to_uppercase()).collect();
+    income_ledger.push_str(fee_type.get(1..).unwrap_or_default());
+    income_ledger.push_str(" Fees");
+    web::Bytes::from(format!(
+        "<TALLYMESSAGE xmlns:UDF=\"TallyUDF\"><VOUCHER VCHTYPE=\"Receipt\" ACTION=\"Create\">\
+<DATE>{date}</DATE><VOUCHERTYPENAME>Receipt</VOUCHERTYPENAME><VOUCHERNUMBER>{number}</VOUCHERNUMBER>\
+<NARRATION>{narration}</NARRATION>\
+<ALLLEDGERENTRIES.LIST><LEDGERNAME>{debit}</LEDGERNAME><ISDEEMEDPOSITIVE>Yes</ISDEEMEDPOSITIVE><AMOUNT>-{amount}</AMOUNT></ALLLEDGERENTRIES.LIST>\
+<ALLLEDGERENTRIES.LIST><LEDGERNAME>{credit}</LEDGERNAME><ISDEEMEDPOSITIVE>No</ISDEEMEDPOSITIVE><AMOUNT>{amount}</AMOUNT></ALLLEDGERENTRIES.LIST>\
+</VOUCHER></TALLYMESSAGE>\n",
+        date = date,
+        number = xml_escape(&doc_string(payment, "_id")),
+        narration = xml_escape(&format!(
+            "{} fee from {} via {} (txn {})",
+            fee_type,
+            doc_string(payment, "student_id"),
+            method,
+            doc_string(payment, "transaction_id")
+        )),
+        debit = debit_ledger,
+        credit = xml_escape(&income_ledger),
+        amount = amount,
+    ))
 }
 
-async fn get_payments(
+// Streams successful payments as CSV or Tally XML vouchers
+async fn export_payments(
     data: web::Data<AppState>,
     req: HttpRequest,
+    query: web::Query<ExportQuery>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let collection: Collection<Payment> = data.db.collection("payments");
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let tally = match query.format.as_deref().unwrap_or("csv") {
+        "csv" => false,
+        "tally" => true,
+        other => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid format '{}', expected csv or tally", other)
+            })));
+        }
+    };
+    let date_range = match date_range_filter(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
+    let mut payment_match = doc! {
+        "campus_id": &claims.campus_id,
+        "status": { "$nin": UNCOLLECTED_PAYMENT_STATUSES.to_vec() }
+    };
+    if let Some(date_range) = date_range {
+        payment_match.insert("payment_date", date_range);
+    }
+    let mut pipeline = vec![
+        doc! { "$match": payment_match },
+        doc! { "$sort": { "payment_date": 1 } },
+        doc! { "$lookup": {
+            "from": "fees",
+            "let": { "fee_id": { "$convert": { "input": "$fee_id", "to": "objectId", "onError": null, "onNull": null } } },
+            "pipeline": [
+                { "$match": { "$expr": { "$eq": ["$_id", "$$fee_id"] } } },
+                { "$project": { "fee_type": 1 } }
+            ],
+            "as": "fee"
+        } },
+        doc! { "$addFields": { "fee_type": { "$ifNull": [{ "$first": "$fee.fee_type" }, "unknown"] } } },
+    ];
+    if let Some(fee_type) = &query.fee_type {
+        pipeline.push(doc! { "$match": { "fee_type": fee_type } });
+    }
+
+    let collection: Collection<Payment> = data.db.collection("payments");
+    let cursor = collection
+        .aggregate(pipeline, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let mut payments = Vec::new();
-    use futures::stream::StreamExt;
-    
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(payment) => payments.push(payment),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
+    use futures::stream::{self, StreamExt};
+    if tally {
+        let header = "<ENVELOPE><HEADER><TALLYREQUEST>Import Data</TALLYREQUEST></HEADER><BODY><IMPORTDATA>\
+<REQUESTDESC><REPORTNAME>Vouchers</REPORTNAME></REQUESTDESC><REQUESTDATA>\n";
+        let footer = "</REQUESTDATA></IMPORTDATA></BODY></ENVELOPE>\n";
+        let body = stream::once(async move { Ok(web::Bytes::from(header)) })
+            .chain(cursor.map(|payment| payment.map(|p| tally_voucher(&p))))
+            .chain(stream::once(async move { Ok::<_, mongodb::error::Error>(web::Bytes::from(footer)) }));
+        return Ok(export_response("application/xml", "payments-tally.xml").streaming(body));
     }
 
-    Ok(HttpResponse::Ok().json(payments))
+    let rows = cursor.map(|payment| {
+        payment.map(|p| {
+            csv_row(PAYMENT_CSV_HEADER.iter().map(|key| {
+                doc_string(&p, if *key == "payment_id" { "_id" } else { key })
+            }))
+        })
+    });
+    let body = stream::once(async move { Ok(csv_row(PAYMENT_CSV_HEADER)) }).chain(rows);
+    Ok(export_response("text/csv", "payments.csv").streaming(body))
 }
 
-// Invoice Management
-async fn create_invoice(
+// Streams fees as CSV, filtered by due date and fee type
+async fn export_fees(
     data: web::Data<AppState>,
     req: HttpRequest,
-    invoice_data: web::Json<InvoiceRequest>,
+    query: web::Query<ExportQuery>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let collection: Collection<Invoice> = data.db.collection("invoices");
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if query.format.as_deref().is_some_and(|f| f != "csv") {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Fees can only be exported as csv"
+        })));
+    }
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    let mut due_range = doc! {};
+    for (operator, value) in [("$gte", &query.from), ("$lte", &query.to)] {
+        let Some(value) = value else { continue };
+        match NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+            Ok(date) => due_range.insert(operator, due_date_bson(date)),
+            Err(_) => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid date '{}', expected YYYY-MM-DD", value)
+                })));
+            }
+        };
+    }
+    if !due_range.is_empty() {
+        filter.insert("due_date", due_range);
+    }
+    if let Some(fee_type) = &query.fee_type {
+        filter.insert("fee_type", fee_type);
+    }
 
-    let total: f64 = invoice_data.items.iter().map(|item| item.amount).sum();
-    let invoice_number = format!("INV-{}", Utc::now().timestamp());
+    let collection: Collection<FeeStructure> = data.db.collection("fees");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "due_date": 1, "student_id": 1 })
+        .build();
+    let cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let new_invoice = Invoice {
-        id: None,
-        invoice_number,
-        student_id: invoice_data.student_id.clone(),
-        items: invoice_data.items.clone(),
-        total_amount: total,
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
-    };
+    use futures::stream::{self, StreamExt};
+    let header = csv_row([
+        "fee_id", "student_id", "fee_type", "semester", "amount", "late_fee", "amount_paid",
+        "waived_amount", "balance", "due_date", "status", "created_at",
+    ]);
+    let rows = cursor.map(|fee| {
+        fee.map(|fee| {
+            csv_row([
+                fee.id.map(|id| id.to_hex()).unwrap_or_default(),
+                fee.student_id.clone(),
+                fee.fee_type.clone(),
+                fee.semester.clone().unwrap_or_default(),
+                format!("{:.2}", fee.amount),
+                format!("{:.2}", fee.late_fee),
+                format!("{:.2}", fee.amount_paid),
+                format!("{:.2}", fee.waived_amount),
+                format!("{:.2}", fee.balance()),
+                fee.due_date.to_string(),
+                fee.status.clone(),
+                fee.created_at.to_rfc3339(),
+            ])
+        })
+    });
+    let body = stream::once(async move { Ok(header) }).chain(rows);
+    Ok(export_response("text/csv", "fees.csv").streaming(body))
+}
 
-    collection
-        .insert_one(new_invoice, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+// ===== COLLECTION REPORTS =====
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Invoice created successfully"
-    })))
+// Inclusive YYYY-MM-DD bounds as a filter on a date or RFC3339 string field
+fn date_range_filter(
+    from: &Option<String>,
+    to: &Option<String>,
+) -> Result<Option<mongodb::bson::Document>, String> {
+    for date in [from, to].into_iter().flatten() {
+        if NaiveDate::parse_from_str(date, "%Y-%m-%d").is_err() {
+            return Err(format!("Invalid date '{}', expected YYYY-MM-DD", date));
+        }
+    }
+    let mut range = doc! {};
+    if let Some(from) = from {
+        range.insert("$gte", from);
+    }
+    if let Some(to) = to {
+        // Any timestamp on the last day sorts before "<to>T99"
+        range.insert("$lt", format!("{}T99", to));
+    }
+    Ok(if range.is_empty() { None } else { Some(range) })
 }
 
-async fn get_invoices(
+async fn aggregate_documents<T>(
+    collection: &Collection<T>,
+    pipeline: Vec<mongodb::bson::Document>,
+) -> mongodb::error::Result<Vec<mongodb::bson::Document>> {
+    use futures::stream::TryStreamExt;
+    collection.aggregate(pipeline, None).await?.try_collect().await
+}
+
+// Collected amounts by fee type, payment method and period, plus billed vs
+// collected vs outstanding per program, all computed in MongoDB
+async fn collections_report(
     data: web::Data<AppState>,
     req: HttpRequest,
+    query: web::Query<CollectionReportQuery>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let collection: Collection<Invoice> = data.db.collection("invoices");
-
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    if claims.role != "hr" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
 
-    let mut invoices = Vec::new();
-    use futures::stream::StreamExt;
-    
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(invoice) => invoices.push(invoice),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    let date_range = match date_range_filter(&query.from, &query.to) {
+        Ok(range) => range,
+        Err(e) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    // payment_date is stored as an RFC3339 string, so periods are string prefixes
+    let period_length = match query.interval.as_deref().unwrap_or("month") {
+        "day" => 10,
+        "month" => 7,
+        other => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid interval '{}', expected day or month", other)
+            })));
         }
+    };
+
+    // Payments recorded before gateway checkout have no status and count as successful
+    let mut payment_match = doc! {
+        "campus_id": &claims.campus_id,
+        "status": { "$nin": UNCOLLECTED_PAYMENT_STATUSES.to_vec() }
+    };
+    if let Some(date_range) = &date_range {
+        payment_match.insert("payment_date", date_range.clone());
     }
 
-    Ok(HttpResponse::Ok().json(invoices))
+    let total_group = |key: mongodb::bson::Bson| doc! {
+        "$group": { "_id": key, "amount": { "$sum": "$amount" }, "count": { "$sum": 1 } }
+    };
+    let payment_collection: Collection<Payment> = data.db.collection("payments");
+    let facets = aggregate_documents(&payment_collection, vec![
+        doc! { "$match": payment_match },
+        doc! { "$facet": {
+            "total": [ total_group(mongodb::bson::Bson::Null) ],
+            "by_payment_method": [
+                total_group("$payment_method".into()),
+                { "$sort": { "amount": -1 } },
+                { "$project": { "_id": 0, "payment_method": "$_id", "amount": 1, "count": 1 } }
+            ],
+            "by_period": [
+                total_group(doc! { "$substrBytes": ["$payment_date", 0, period_length] }.into()),
+                { "$sort": { "_id": 1 } },
+                { "$project": { "_id": 0, "period": "$_id", "amount": 1, "count": 1 } }
+            ],
+            "by_fee_type": [
+                { "$lookup": {
+                    "from": "fees",
+                    "let": { "fee_id": { "$convert": { "input": "$fee_id", "to": "objectId", "onError": null, "onNull": null } } },
+                    "pipeline": [ { "$match": { "$expr": { "$eq": ["$_id", "$$fee_id"] } } } ],
+                    "as": "fee"
+                } },
+                { "$unwind": { "path": "$fee", "preserveNullAndEmptyArrays": true } },
+                total_group(doc! { "$ifNull": ["$fee.fee_type", "unknown"] }.into()),
+                { "$sort": { "amount": -1 } },
+                { "$project": { "_id": 0, "fee_type": "$_id", "amount": 1, "count": 1 } }
+            ]
+        } },
+    ])
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let facets = facets.into_iter().next().unwrap_or_default();
+
+    let (collected, payment_count) = facets
+        .get_array("total")
+        .ok()
+        .and_then(|total| total.first())
+        .and_then(|total| total.as_document())
+        .map(|total| (
+            total.get("amount").and_then(|a| a.as_f64()).unwrap_or(0.0),
+            total.get("count").and_then(|c| c.as_i32()).unwrap_or(0),
+        ))
+        .unwrap_or((0.0, 0));
+
+    // Billed vs collected vs waived per program; programs come from the
+    // academics service's student_programs collection
+    let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
+    let by_program = aggregate_documents(&fee_collection, vec![
+        doc! { "$match": { "campus_id": &claims.campus_id, "installment_count": null } },
+        doc! { "$lookup": {
+            "from": "student_programs",
+            "let": { "student_id": "$student_id" },
+            "pipeline": [ { "$match": { "$expr": { "$and": [
+                { "$eq": ["$student_id", "$$student_id"] },
+                { "$eq": ["$campus_id", &claims.campus_id] }
+            ] } } } ],
+            "as": "program"
+        } },
+        doc! { "$unwind": { "path": "$program", "preserveNullAndEmptyArrays": true } },
+        doc! { "$addFields": {
+            "due": { "$add": ["$amount", { "$ifNull": ["$late_fee", 0] }] },
+            "waived": { "$ifNull": ["$waived_amount", 0] }
+        } },
+        // Fees paid before amount_paid was tracked count as fully collected
+        doc! { "$addFields": { "collected": { "$ifNull": [
+            "$amount_paid",
+            { "$cond": [{ "$eq": ["$status", "paid"] }, "$due", 0] }
+        ] } } },
+        doc! { "$addFields": { "remaining": { "$max": [0, { "$subtract": ["$due", { "$add": ["$collected", "$waived"] }] }] } } },
+        doc! { "$group": {
+            "_id": { "$ifNull": ["$program.program_code", "unassigned"] },
+            "billed": { "$sum": "$due" },
+            "collected": { "$sum": "$collected" },
+            "waived": { "$sum": "$waived" },
+            "outstanding": { "$sum": { "$cond": [{ "$eq": ["$status", "paid"] }, 0, "$remaining"] } },
+            "overdue": { "$sum": { "$cond": [{ "$eq": ["$status", "overdue"] }, "$remaining", 0] } },
+            "students": { "$addToSet": "$student_id" }
+        } },
+        doc! { "$sort": { "_id": 1 } },
+        doc! { "$project": {
+            "_id": 0, "program_code": "$_id", "billed": 1, "collected": 1, "waived": 1,
+            "outstanding": 1, "overdue": 1, "students": { "$size": "$students" }
+        } },
+    ])
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Invoiced revenue counts issued invoices only; cancelled ones are
+    // reported separately alongside the credit notes that reversed them
+    let mut invoice_match = doc! { "campus_id": &claims.campus_id };
+    if let Some(date_range) = date_range {
+        invoice_match.insert("created_at", date_range);
+    }
+    let invoice_collection: Collection<Invoice> = data.db.collection("invoices");
+    let invoiced = aggregate_documents(&invoice_collection, vec![
+        doc! { "$match": invoice_match },
+        doc! { "$group": {
+            "_id": { "$ifNull": ["$status", "issued"] },
+            "amount": { "$sum": "$total_amount" },
+            "tax": { "$sum": { "$ifNull": ["$tax_total", 0] } },
+            "count": { "$sum": 1 }
+        } },
+    ])
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let invoice_totals = |status: &str| {
+        invoiced
+            .iter()
+            .find(|group| group.get_str("_id").ok() == Some(status))
+            .map(|group| (
+                group.get("amount").and_then(|a| a.as_f64()).unwrap_or(0.0),
+                group.get("tax").and_then(|a| a.as_f64()).unwrap_or(0.0),
+                group.get("count").and_then(|c| c.as_i32()).unwrap_or(0),
+            ))
+            .unwrap_or((0.0, 0.0, 0))
+    };
+    let (invoiced_amount, invoiced_tax, invoice_count) = invoice_totals("issued");
+    let (cancelled_amount, _, cancelled_count) = invoice_totals("cancelled");
+
+    let facet = |name: &str| facets.get_array(name).cloned().unwrap_or_default();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "from": query.from,
+        "to": query.to,
+        "interval": if period_length == 10 { "day" } else { "month" },
+        "total_collected": collected,
+        "payment_count": payment_count,
+        "by_fee_type": facet("by_fee_type"),
+        "by_payment_method": facet("by_payment_method"),
+        "by_period": facet("by_period"),
+        "by_program": by_program,
+        "invoiced": {
+            "amount": invoiced_amount,
+            "tax": invoiced_tax,
+            "count": invoice_count,
+            "cancelled_amount": cancelled_amount,
+            "cancelled_count": cancelled_count
+        }
+    })))
 }
 
 // ===== HR DASHBOARD ENDPOINTS =====
@@ -313,8 +6066,8 @@ async fn hr_fee_summary(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     if claims.role != "hr" && claims.role != "admin" {
         return Ok(HttpResponse::Forbidden().json(serde_json::json!({
@@ -325,9 +6078,9 @@ async fn hr_fee_summary(
     let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
 
     let mut cursor = fee_collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .find(doc! { "campus_id": &claims.campus_id, "installment_count": null }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let mut fees = Vec::new();
     use futures::stream::StreamExt;
@@ -385,7 +6138,7 @@ async fn hr_fee_summary(
         "total_fees": total_fees,
         "total_paid": total_paid,
         "total_pending": total_pending,
-        "collection_rate": if total_fees > 0.0 { (total_paid / total_fees * 100.0) } else { 0.0 },
+        "collection_rate": if total_fees > 0.0 { total_paid / total_fees * 100.0 } else { 0.0 },
         "category_breakdown": {
             "tuition": tuition_total,
             "hostel": hostel_total,
@@ -409,8 +6162,8 @@ async fn hr_student_fees(
     req: HttpRequest,
     query: web::Query<std::collections::HashMap<String, String>>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     if claims.role != "hr" && claims.role != "admin" {
         return Ok(HttpResponse::Forbidden().json(serde_json::json!({
@@ -421,9 +6174,9 @@ async fn hr_student_fees(
     let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
 
     let mut cursor = fee_collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .find(doc! { "campus_id": &claims.campus_id, "installment_count": null }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let mut fees = Vec::new();
     use futures::stream::StreamExt;
@@ -500,8 +6253,8 @@ async fn get_student_fees(
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     let student_id = path.into_inner();
     if claims.role == "student" && claims.sub != student_id {
@@ -513,11 +6266,11 @@ async fn get_student_fees(
     let fee_collection: Collection<FeeStructure> = data.db.collection("fees");
     let payment_collection: Collection<Payment> = data.db.collection("payments");
 
-    // Get all fees for this student
+    // Get all fees for this student; planned fees are listed through their installments
     let mut fee_cursor = fee_collection
-        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id, "installment_count": null }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let mut fees = Vec::new();
     use futures::stream::StreamExt;
@@ -532,7 +6285,7 @@ async fn get_student_fees(
     let mut payment_cursor = payment_collection
         .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let mut payments = Vec::new();
     while let Some(result) = payment_cursor.next().await {
@@ -590,6 +6343,38 @@ async fn main() -> std::io::Result<()> {
     let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8082".to_string());
 
+    // Online payments are enabled only when a gateway and its secrets are configured
+    let gateway = match env::var("PAYMENT_GATEWAY").map(|g| g.trim().to_lowercase()) {
+        Ok(provider) if provider == "razorpay" || provider == "stripe" => {
+            let key_secret = env::var("GATEWAY_KEY_SECRET").expect("GATEWAY_KEY_SECRET must be set");
+            let webhook_secret = env::var("GATEWAY_WEBHOOK_SECRET").expect("GATEWAY_WEBHOOK_SECRET must be set");
+            Some(GatewayConfig {
+                key_id: env::var("GATEWAY_KEY_ID").unwrap_or_default(),
+                key_secret,
+                webhook_secret,
+                currency: env::var("GATEWAY_CURRENCY").unwrap_or_else(|_| "INR".to_string()),
+                provider,
+            })
+        }
+        Ok(other) if !other.is_empty() => panic!("Unsupported PAYMENT_GATEWAY '{}'", other),
+        _ => None,
+    };
+
+    let late_fee_policy = LateFeePolicy {
+        kind: env::var("LATE_FEE_POLICY").map(|p| p.trim().to_lowercase()).unwrap_or_else(|_| "none".to_string()),
+        amount: env::var("LATE_FEE_AMOUNT").ok().and_then(|v| v.parse().ok()).unwrap_or(0.0),
+        cap: env::var("LATE_FEE_CAP").ok().and_then(|v| v.parse().ok()),
+        grace_days: env::var("LATE_FEE_GRACE_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(0),
+    };
+    if !["none", "flat", "per_day"].contains(&late_fee_policy.kind.as_str()) {
+        panic!("Unsupported LATE_FEE_POLICY '{}'", late_fee_policy.kind);
+    }
+    // Hours between automatic overdue scans; 0 disables the job
+    let overdue_scan_hours = env::var("OVERDUE_SCAN_INTERVAL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(24);
+
     println!("💰 Starting Finance Service...");
     println!("📡 Connecting to MongoDB: {}", mongodb_uri);
 
@@ -599,12 +6384,222 @@ async fn main() -> std::io::Result<()> {
     
     let db = client.database(&database_name);
 
+    let access_ttl = env::var("ACCESS_TOKEN_TTL_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(24 * 60);
+    let jwt_keys: SharedKeySet = std::sync::Arc::new(std::sync::RwLock::new(
+        load_jwt_keys(&db, &jwt_secret, chrono::Duration::minutes(access_ttl))
+            .await
+            .expect("Failed to load JWT keys"),
+    ));
+
+    // Pick up keys the auth service adds or retires
+    {
+        let db = db.clone();
+        let jwt_keys = jwt_keys.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(JWT_KEY_REFRESH_SECS));
+            loop {
+                interval.tick().await;
+                reload_jwt_keys(&db, &jwt_secret, &jwt_keys).await;
+            }
+        });
+    }
+
+    // Payments recorded twice before the unique index existed have to be
+    // sorted out by hand: each may already have been credited to its fee
+    let duplicates = find_duplicate_transactions(&db)
+        .await
+        .expect("Failed to check payments for duplicate transaction ids");
+    if !duplicates.is_empty() {
+        eprintln!("❌ These transaction ids are recorded on more than one payment. Remove or correct the extra payments (see SETUP.md) and restart:");
+        for (campus_id, transaction_id, ids) in &duplicates {
+            eprintln!("   campus {}, transaction {}: payments {}", campus_id, transaction_id, ids.join(", "));
+        }
+        std::process::exit(1);
+    }
+
+    create_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+
+    let (converted, unparseable) = migrate_due_dates(&db)
+        .await
+        .expect("Failed to migrate fee due dates");
+    if converted + unparseable > 0 {
+        println!("📅 Migrated {} fee due dates ({} unparseable, see legacy_due_date)", converted + unparseable, unparseable);
+    }
+
     println!("✅ Connected to MongoDB");
     println!("🚀 Server starting on http://127.0.0.1:{}", port);
 
+    match &gateway {
+        Some(gateway) => println!("💳 Payment gateway: {}", gateway.provider),
+        None => println!("💳 Payment gateway not configured; online checkout disabled"),
+    }
+
+    let institution_name = env::var("INSTITUTION_NAME").unwrap_or_else(|_| "CampusConnect".to_string());
+    let smtp = SmtpConfig::from_env();
+    let bounce_charge = env::var("CHEQUE_BOUNCE_CHARGE")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(500.0);
+
+    let mut reminder_days: Vec<i64> = env::var("REMINDER_DAYS_BEFORE")
+        .unwrap_or_else(|_| "7,1".to_string())
+        .split(',')
+        .filter_map(|d| d.trim().parse().ok())
+        .filter(|d| *d > 0)
+        .collect();
+    reminder_days.sort_unstable_by(|a, b| b.cmp(a));
+    reminder_days.dedup();
+    let reminders = ReminderSchedule {
+        days_before: reminder_days,
+        overdue_every_days: env::var("REMINDER_OVERDUE_EVERY_DAYS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .filter(|d: &i64| *d > 0)
+            .unwrap_or(7),
+        payment_link: env::var("PAYMENT_LINK_URL").unwrap_or_else(|_| "http://localhost:4200/fees/pay".to_string()),
+    };
+    // Hours between automatic reminder scans; 0 disables the job
+    let reminder_scan_hours = env::var("REMINDER_SCAN_INTERVAL_HOURS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(24);
+
+    if reminder_scan_hours > 0 {
+        let db = db.clone();
+        let schedule = reminders.clone();
+        let smtp = smtp.clone();
+        let institution_name = institution_name.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(reminder_scan_hours * 3600));
+            loop {
+                interval.tick().await;
+                match scan_fee_reminders(&db, None, &schedule, smtp.as_ref(), &institution_name).await {
+                    Ok(summary) => log::info!(
+                        "Reminder scan: {} sent, {} failed, {} opted out",
+                        summary.sent, summary.failed, summary.opted_out
+                    ),
+                    Err(e) => log::warn!("Reminder scan failed: {}", e),
+                }
+            }
+        });
+    }
+
+    let events = EventPublisher {
+        webhook_urls: env::var("EVENT_WEBHOOK_URLS")
+            .map(|v| v.split(',').map(|u| u.trim().to_string()).filter(|u| !u.is_empty()).collect())
+            .unwrap_or_default(),
+        secret: env::var("EVENT_WEBHOOK_SECRET").unwrap_or_default(),
+        max_attempts: env::var("EVENT_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(8),
+    };
+    if !events.webhook_urls.is_empty() && events.secret.is_empty() {
+        panic!("EVENT_WEBHOOK_SECRET must be set when EVENT_WEBHOOK_URLS is");
+    }
+    if events.webhook_urls.is_empty() {
+        println!("📣 No event webhooks configured; payment events are not published");
+    } else {
+        println!("📣 Publishing payment events to {} webhook(s)", events.webhook_urls.len());
+    }
+
+    // Picks up retries; new events are sent as soon as they are published
+    if !events.webhook_urls.is_empty() {
+        let db = db.clone();
+        let events = events.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                if let Err(e) = deliver_due_events(&db, &events).await {
+                    log::warn!("Event delivery failed: {}", e);
+                }
+            }
+        });
+    }
+
+    let dunning_stages = parse_dunning_stages(
+        &env::var("DUNNING_STAGES").unwrap_or_else(|_| "reminder:1,warning:15,registration_hold:30,legal:60".to_string()),
+    )
+    .unwrap_or_else(|e| panic!("Invalid DUNNING_STAGES: {}", e));
+
+    if overdue_scan_hours > 0 {
+        let db = db.clone();
+        let policy = late_fee_policy.clone();
+        let events = events.clone();
+        let dunning_stages = dunning_stages.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(overdue_scan_hours * 3600));
+            loop {
+                interval.tick().await;
+                match scan_overdue_fees(&db, None, &policy, &events, "system").await {
+                    Ok(summary) => log::info!(
+                        "Overdue scan: {} marked overdue, {} late fees accrued ({:.2})",
+                        summary.marked_overdue, summary.late_fees_accrued, summary.late_fee_amount
+                    ),
+                    Err(e) => log::warn!("Overdue scan failed: {}", e),
+                }
+                // Stages follow from what the overdue scan just marked
+                match scan_dunning(&db, None, &dunning_stages, &events).await {
+                    Ok(summary) => log::info!(
+                        "Dunning scan: {} students in dunning, {} stage changes, {} cleared",
+                        summary.students_in_dunning, summary.stage_changes, summary.cleared
+                    ),
+                    Err(e) => log::warn!("Dunning scan failed: {}", e),
+                }
+            }
+        });
+    }
+
+    let fines = FineIntegration {
+        api_key: env::var("FINE_INTEGRATION_KEY").ok().filter(|k| !k.is_empty()),
+        due_days: env::var("FINE_DUE_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(14),
+    };
+    // Minutes between pulls of library fines and mess bills; 0 disables the job
+    let fine_sync_minutes = env::var("FINE_SYNC_INTERVAL_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(15);
+
+    if fine_sync_minutes > 0 {
+        let db = db.clone();
+        let due_days = fines.due_days;
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(fine_sync_minutes * 60));
+            loop {
+                interval.tick().await;
+                match sync_library_fines(&db, due_days).await {
+                    Ok(summary) => log::info!(
+                        "Library fine sync: {} fines billed, {} statuses reported back",
+                        summary.ingested, summary.status_updates
+                    ),
+                    Err(e) => log::warn!("Library fine sync failed: {}", e),
+                }
+                match sync_mess_bills(&db, due_days).await {
+                    Ok(summary) => log::info!(
+                        "Mess bill sync: {} bills billed, {} statuses reported back",
+                        summary.ingested, summary.status_updates
+                    ),
+                    Err(e) => log::warn!("Mess bill sync failed: {}", e),
+                }
+            }
+        });
+    }
+
     let app_state = web::Data::new(AppState {
         db,
-        jwt_secret,
+        jwt_keys,
+        gateway,
+        late_fee_policy,
+        bounce_charge,
+        institution_name,
+        smtp,
+        reminders,
+        fines,
+        events,
+        dunning_stages,
     });
 
     HttpServer::new(move || {
@@ -618,19 +6613,172 @@ async fn main() -> std::io::Result<()> {
             // Fee routes
             .route("/api/fees", web::post().to(create_fee))
             .route("/api/fees", web::get().to(get_fees))
+            .route("/api/fees/export", web::get().to(export_fees))
+            .route("/api/fees/overdue/scan", web::post().to(run_overdue_scan))
+            .route("/api/fees/{id}/late-fees", web::get().to(get_fee_accruals))
+            .route("/api/fees/{id}/history", web::get().to(get_fee_history))
+            .route("/api/fees/{id}/plan", web::post().to(create_installment_plan))
+            .route("/api/fees/{id}/plan", web::get().to(get_installment_plan))
+            .route("/api/fee-templates", web::post().to(create_fee_template))
+            .route("/api/fee-templates", web::get().to(get_fee_templates))
+            .route("/api/fee-templates/{id}/apply", web::post().to(apply_fee_template))
             // Payment routes
             .route("/api/payments", web::post().to(create_payment))
             .route("/api/payments", web::get().to(get_payments))
+            .route("/api/payments/export", web::get().to(export_payments))
+            .route("/api/payments/{id}/deposit", web::put().to(deposit_instrument))
+            .route("/api/payments/{id}/clear", web::put().to(clear_instrument))
+            .route("/api/payments/{id}/bounce", web::put().to(bounce_instrument))
+            .route("/api/payments/checkout", web::post().to(create_checkout))
+            .route("/api/payments/webhook/{gateway}", web::post().to(payment_webhook))
+            // Fine integration routes
+            .route("/api/integrations/fines", web::post().to(receive_fine_event))
+            .route("/api/integrations/fines/library/sync", web::post().to(run_library_fine_sync))
+            .route("/api/integrations/fines/mess/sync", web::post().to(run_mess_bill_sync))
+            .route("/api/integrations/fines/{source}/{reference_id}", web::get().to(get_fine_status))
+            // Reconciliation routes
+            .service(
+                web::resource("/api/reconciliation/import")
+                    // Statements for a busy month run to a few megabytes
+                    .app_data(web::PayloadConfig::new(10 * 1024 * 1024))
+                    .route(web::post().to(import_bank_statement)),
+            )
+            .route("/api/reconciliation", web::get().to(get_reconciliation_batches))
+            .route("/api/reconciliation/{id}", web::get().to(get_reconciliation_batch))
+            // Dunning routes
+            .route("/api/dunning", web::get().to(get_dunning))
+            .route("/api/dunning/scan", web::post().to(run_dunning_scan))
+            // Outbound event routes
+            .route("/api/events/deliveries", web::get().to(get_event_deliveries))
+            .route("/api/events/deliveries/{id}/retry", web::post().to(retry_event_delivery))
+            // Wallet routes
+            .route("/api/wallets/{student_id}", web::get().to(get_wallet))
+            .route("/api/wallets/{student_id}/transactions", web::get().to(get_wallet_transactions))
+            .route("/api/wallets/{student_id}/deposit", web::post().to(deposit_to_wallet))
+            .route("/api/wallets/{student_id}/pay", web::post().to(pay_from_wallet))
+            .route("/api/wallets/{student_id}/refund", web::post().to(refund_to_wallet))
             // Invoice routes
+            .route("/api/invoices/{id}/cancel", web::post().to(cancel_invoice))
+            .route("/api/credit-notes", web::get().to(get_credit_notes))
             .route("/api/invoices", web::post().to(create_invoice))
             .route("/api/invoices", web::get().to(get_invoices))
+            .route("/api/invoices/{id}/pdf", web::get().to(get_invoice_pdf))
+            .route("/api/invoices/{id}/send", web::post().to(send_invoice))
             // Student Dashboard routes
             .route("/api/student/fees/{student_id}", web::get().to(get_student_fees))
+            .route("/api/student/reminders/{student_id}", web::get().to(get_reminder_preference))
+            .route("/api/student/reminders/{student_id}", web::put().to(update_reminder_preference))
+            // Waiver routes
+            .route("/api/waivers", web::post().to(create_waiver))
+            .route("/api/waivers", web::get().to(get_waivers))
+            .route("/api/waivers/{id}/recommend", web::put().to(recommend_waiver))
+            .route("/api/waivers/{id}/approve", web::put().to(approve_waiver))
+            .route("/api/waivers/{id}/reject", web::put().to(reject_waiver))
+            // Reminder routes
+            .route("/api/reminders", web::get().to(get_reminders))
+            .route("/api/reminders/scan", web::post().to(run_reminder_scan))
             // HR Dashboard routes
             .route("/api/hr/fees/summary", web::get().to(hr_fee_summary))
             .route("/api/hr/fees/students", web::get().to(hr_student_fees))
+            // Report routes
+            .route("/api/reports/collections", web::get().to(collections_report))
     })
     .bind(format!("127.0.0.1:{}", port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(kind: &str, amount: f64, cap: Option<f64>, grace_days: i64) -> LateFeePolicy {
+        LateFeePolicy { kind: kind.to_string(), amount, cap, grace_days }
+    }
+
+    #[test]
+    fn late_fee_waits_out_the_grace_period() {
+        let per_day = policy("per_day", 10.0, None, 3);
+        assert_eq!(per_day.late_fee_for(2), 0.0);
+        assert_eq!(per_day.late_fee_for(3), 0.0);
+        assert_eq!(per_day.late_fee_for(4), 10.0);
+        assert_eq!(per_day.late_fee_for(10), 70.0);
+
+        let flat = policy("flat", 250.0, None, 5);
+        assert_eq!(flat.late_fee_for(5), 0.0);
+        assert_eq!(flat.late_fee_for(6), 250.0);
+        assert_eq!(flat.late_fee_for(60), 250.0);
+    }
+
+    #[test]
+    fn per_day_late_fee_stops_at_the_cap() {
+        let capped = policy("per_day", 12.5, Some(100.0), 0);
+        assert_eq!(capped.late_fee_for(7), 87.5);
+        assert_eq!(capped.late_fee_for(8), 100.0);
+        assert_eq!(capped.late_fee_for(365), 100.0);
+        assert_eq!(policy("none", 50.0, None, 0).late_fee_for(30), 0.0);
+    }
+
+    #[test]
+    fn late_fee_increase_tops_up_to_what_is_owed() {
+        let per_day = policy("per_day", 10.0, Some(55.0), 1);
+        // First scan, a rerun the same day, then one after two missed days
+        assert_eq!(per_day.late_fee_increase(0.0, 3), 20.0);
+        assert_eq!(per_day.late_fee_increase(20.0, 3), 0.0);
+        assert_eq!(per_day.late_fee_increase(20.0, 6), 30.0);
+        // Never refunds when the policy is lowered or the cap is reached
+        assert_eq!(per_day.late_fee_increase(80.0, 30), 0.0);
+    }
+
+    fn fee(amount: f64, late_fee: f64, amount_paid: f64, waived_amount: f64) -> FeeStructure {
+        FeeStructure {
+            id: Some(ObjectId::new()),
+            student_id: "student-1".to_string(),
+            fee_type: "tuition".to_string(),
+            amount,
+            due_date: NaiveDate::from_ymd_opt(2024, 3, 31).unwrap(),
+            legacy_due_date: None,
+            status: "pending".to_string(),
+            campus_id: "campus-1".to_string(),
+            created_at: Utc::now(),
+            template_id: None,
+            semester: None,
+            late_fee,
+            amount_paid,
+            waived_amount,
+            parent_fee_id: None,
+            installment_number: None,
+            installment_count: None,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn balance_counts_late_fees_payments_and_waivers() {
+        assert_eq!(fee(1000.0, 0.0, 0.0, 0.0).balance(), 1000.0);
+        assert_eq!(fee(1000.0, 45.5, 300.0, 200.0).balance(), 545.5);
+        // Ten payments of 0.1 leave no float residue
+        assert_eq!(fee(1.0, 0.0, (0..10).map(|_| 0.1).sum(), 0.0).balance(), 0.0);
+    }
+
+    #[test]
+    fn payments_may_exceed_the_balance_only_by_rounding() {
+        let partly_paid = fee(1000.0, 20.0, 500.0, 0.0);
+        assert!(!partly_paid.exceeds_balance(520.0));
+        assert!(!partly_paid.exceeds_balance(520.004));
+        assert!(partly_paid.exceeds_balance(520.01));
+        assert!(fee(1000.0, 0.0, 1000.0, 0.0).exceeds_balance(0.01));
+    }
+
+    #[test]
+    fn credit_marks_the_fee_paid_once_nothing_is_owed() {
+        let partly_paid = fee(1000.0, 20.0, 500.0, 0.0);
+        assert_eq!(partly_paid.after_credit(200.0), (320.0, "pending"));
+        assert_eq!(partly_paid.after_credit(520.0), (0.0, "paid"));
+        assert_eq!(partly_paid.after_credit(519.996), (0.0, "paid"));
+
+        let mut overdue = fee(1000.0, 0.0, 0.0, 0.0);
+        overdue.status = "overdue".to_string();
+        assert_eq!(overdue.after_credit(999.0), (1.0, "overdue"));
+    }
+}
@@ -2,8 +2,11 @@ use actix_web::{web, App, HttpServer, HttpResponse, HttpRequest, Error, middlewa
 use actix_cors::Cors;
 use mongodb::{Client, Collection, bson::{doc, oid::ObjectId}};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use chrono::{DateTime, Utc};
+use campus_auth::{load_jwt_keys, reload_jwt_keys, SharedKeySet, JWT_KEY_REFRESH_SECS};
+use chrono::{DateTime, Datelike, NaiveDate, Utc};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use std::collections::HashMap;
 use std::env;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,13 +15,44 @@ struct Claims {
     role: String,
     campus_id: String,
     exp: usize,
+    iat: usize,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Hostel {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    hostel_type: String, // boys, girls, coed
+    gender_policy: String, // male, female, mixed
+    warden_employee_id: Option<String>,
+    total_capacity: i32,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HostelRequest {
+    name: String,
+    hostel_type: String,
+    gender_policy: String,
+    warden_employee_id: Option<String>,
+    total_capacity: i32,
+}
+
+const HOSTEL_TYPES: &[&str] = &["boys", "girls", "coed"];
+const GENDER_POLICIES: &[&str] = &["male", "female", "mixed"];
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct Room {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     id: Option<ObjectId>,
     room_number: String,
+    // Rooms created before hostels were modelled only carry hostel_name
+    #[serde(default)]
+    hostel_id: Option<String>,
     hostel_name: String,
     capacity: i32,
     occupied: i32,
@@ -26,12 +60,15 @@ struct Room {
     floor: i32,
     campus_id: String,
     created_at: DateTime<Utc>,
+    // Retired rooms keep their residents but take no new allocations
+    #[serde(default)]
+    out_of_service: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct RoomRequest {
     room_number: String,
-    hostel_name: String,
+    hostel_id: String,
     capacity: i32,
     room_type: String,
     floor: i32,
@@ -48,12 +85,189 @@ struct RoomAllocation {
     allocation_date: DateTime<Utc>,
     status: String, // active, vacated
     campus_id: String,
+    #[serde(default)]
+    vacated_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    duration_months: Option<i32>,
+    #[serde(default)]
+    fee_amount: Option<f64>,
+    // Hostel fee raised in finance-service for this stay
+    #[serde(default)]
+    fee_id: Option<String>,
+    #[serde(default)]
+    fee_status: Option<String>, // unbilled, not_applicable, or the fee's status in finance
+    #[serde(default)]
+    checked_in_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    vacate_reason: Option<String>, // vacated, expelled, transferred, duplicate
+    // Allocation this one replaced when the student changed rooms
+    #[serde(default)]
+    transferred_from: Option<String>,
+    // Reference the fee is billed under in finance; a transfer keeps the
+    // original allocation's, since the fee moves with the student
+    #[serde(default)]
+    billing_reference_id: Option<String>,
+}
+
+impl RoomAllocation {
+    // Allocations billed before billing_reference_id existed were billed under
+    // their own id; transfers made then point back at the original
+    fn billing_reference(&self) -> String {
+        self.billing_reference_id
+            .clone()
+            .or_else(|| self.transferred_from.clone())
+            .or_else(|| self.id.map(|id| id.to_hex()))
+            .unwrap_or_default()
+    }
+
+    // The stay that replaces this one when the student moves into `room`
+    fn transferred_to(&self, room: &Room, now: DateTime<Utc>) -> RoomAllocation {
+        RoomAllocation {
+            id: None,
+            student_id: self.student_id.clone(),
+            room_id: room.id.map(|id| id.to_hex()).unwrap_or_default(),
+            hostel_name: room.hostel_name.clone(),
+            room_number: room.room_number.clone(),
+            allocation_date: now,
+            status: "active".to_string(),
+            campus_id: self.campus_id.clone(),
+            vacated_at: None,
+            duration_months: self.duration_months,
+            fee_amount: self.fee_amount,
+            fee_id: self.fee_id.clone(),
+            fee_status: self.fee_status.clone(),
+            checked_in_at: self.checked_in_at.map(|_| now),
+            vacate_reason: None,
+            transferred_from: self.id.map(|id| id.to_hex()),
+            billing_reference_id: Some(self.billing_reference()),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RoomQuery {
+    hostel_id: Option<String>,
+}
+
+// Omitted fields are left unchanged
+#[derive(Debug, Deserialize)]
+struct RoomUpdateRequest {
+    room_number: Option<String>,
+    capacity: Option<i32>,
+    room_type: Option<String>,
+    floor: Option<i32>,
+    out_of_service: Option<bool>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct AllocationRequest {
     student_id: String,
     room_id: String,
+    duration_months: Option<i32>,
+}
+
+// Monthly rent for a room type. A card for a specific hostel overrides the
+// campus-wide card (hostel_id None) for the same room type.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RateCard {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    hostel_id: Option<String>,
+    room_type: String,
+    monthly_rate: f64,
+    campus_id: String,
+    updated_by: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RateCardRequest {
+    hostel_id: Option<String>,
+    room_type: String,
+    monthly_rate: f64,
+}
+
+// Served seniors first, then in order of application
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct WaitlistEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    year_of_study: i32,
+    // Preferences; None accepts any hostel or room type
+    hostel_id: Option<String>,
+    room_type: Option<String>,
+    status: String, // waiting, allocated, withdrawn
+    applied_at: DateTime<Utc>,
+    allocated_room_id: Option<String>,
+    resolved_at: Option<DateTime<Utc>>,
+    campus_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct WaitlistRequest {
+    student_id: String,
+    year_of_study: i32,
+    hostel_id: Option<String>,
+    room_type: Option<String>,
+}
+
+const ALLOCATION_STRATEGIES: &[&str] = &["seniority", "merit", "lottery"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AllocationApplication {
+    student_id: String,
+    #[serde(default)]
+    year_of_study: i32,
+    merit_score: Option<f64>,
+    gender: Option<String>, // male, female; checked against the hostel's gender_policy
+    hostel_id: Option<String>,
+    room_type: Option<String>,
+    // Roommates are paired only when both name each other
+    roommate_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AutoAllocationRequest {
+    strategy: Option<String>,
+    seed: Option<i64>, // lottery only; a run with the same seed draws the same order
+    applications: Vec<AllocationApplication>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PlannedAssignment {
+    student_id: String,
+    room_id: String,
+    hostel_id: Option<String>,
+    hostel_name: String,
+    room_number: String,
+    preference_met: bool,
+    roommate_id: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct UnassignedApplication {
+    student_id: String,
+    reason: String,
+}
+
+// A proposed batch allocation; nothing is allocated until it is committed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AllocationRun {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    strategy: String,
+    seed: Option<i64>,
+    status: String, // draft, committed
+    assignments: Vec<PlannedAssignment>,
+    unassigned: Vec<UnassignedApplication>,
+    created_by: String,
+    created_at: DateTime<Utc>,
+    committed_at: Option<DateTime<Utc>>,
+    // Assignments that no longer fit when the run was committed
+    #[serde(default)]
+    skipped_on_commit: Vec<UnassignedApplication>,
+    campus_id: String,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -68,6 +282,30 @@ struct MaintenanceRequest {
     reported_by: String,
     campus_id: String,
     created_at: DateTime<Utc>,
+    // Resolved from hostel_name when the request is raised
+    #[serde(default)]
+    hostel_id: Option<String>,
+    #[serde(default)]
+    estimated_cost: Option<f64>,
+    #[serde(default)]
+    actual_cost: Option<f64>,
+    // When the actual cost was recorded; spend is reported by this month
+    #[serde(default)]
+    spent_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    vendor: Option<MaintenanceVendor>,
+    #[serde(default)]
+    resolved_at: Option<DateTime<Utc>>,
+    // employee_id of hostel staff handling the work
+    #[serde(default)]
+    assigned_to: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MaintenanceVendor {
+    name: String,
+    contact: Option<String>,
+    invoice_number: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -76,208 +314,2654 @@ struct MaintenanceRequestData {
     hostel_name: String,
     issue_type: String,
     description: String,
+    estimated_cost: Option<f64>,
 }
 
-struct AppState {
-    db: mongodb::Database,
-    jwt_secret: String,
+#[derive(Debug, Deserialize)]
+struct MaintenanceUpdateRequest {
+    status: Option<String>,
+    estimated_cost: Option<f64>,
+    actual_cost: Option<f64>,
+    vendor: Option<MaintenanceVendor>,
+    // Must be on the hostel's staff roster
+    assigned_to: Option<String>,
 }
 
-fn extract_claims(req: &HttpRequest, jwt_secret: &str) -> Result<Claims, String> {
-    if let Some(auth_header) = req.headers().get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let token = &auth_str[7..];
-                
-                match decode::<Claims>(
-                    token,
-                    &DecodingKey::from_secret(jwt_secret.as_bytes()),
-                    &Validation::new(Algorithm::HS256),
-                ) {
-                    Ok(token_data) => return Ok(token_data.claims),
-                    Err(_) => return Err("Invalid token".to_string()),
-                }
-            }
-        }
-    }
-    Err("No token provided".to_string())
+const MAINTENANCE_STATUSES: &[&str] = &["pending", "in_progress", "resolved"];
+
+// Monthly maintenance allowance for one hostel
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MaintenanceBudget {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    hostel_id: String,
+    month: String, // YYYY-MM
+    amount: f64,
+    campus_id: String,
+    updated_by: String,
+    updated_at: DateTime<Utc>,
 }
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "ok",
-        "service": "hostel-service"
-    }))
+#[derive(Debug, Deserialize)]
+struct MaintenanceBudgetRequest {
+    hostel_id: String,
+    month: String,
+    amount: f64,
 }
 
-// Room Management
-async fn create_room(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    room_data: web::Json<RoomRequest>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+#[derive(Debug, Deserialize)]
+struct MaintenanceBudgetQuery {
+    hostel_id: Option<String>,
+    month: Option<String>,
+}
 
-    let collection: Collection<Room> = data.db.collection("rooms");
+#[derive(Debug, Deserialize)]
+struct MaintenanceCostQuery {
+    from: Option<String>,
+    to: Option<String>,
+    hostel_id: Option<String>,
+}
 
-    let new_room = Room {
-        id: None,
-        room_number: room_data.room_number.clone(),
-        hostel_name: room_data.hostel_name.clone(),
-        capacity: room_data.capacity,
-        occupied: 0,
-        room_type: room_data.room_type.clone(),
-        floor: room_data.floor,
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
-    };
+// Spend, estimates and budget for one hostel in one month
+#[derive(Debug, Serialize, Default, Clone)]
+struct MonthlyMaintenanceCost {
+    month: String,
+    requests: u32,
+    estimated: f64,
+    actual: f64,
+    budget: Option<f64>,
+    variance: Option<f64>,
+    over_budget: bool,
+}
 
-    collection
-        .insert_one(new_room, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Serialize, Default)]
+struct HostelMaintenanceCost {
+    hostel_id: Option<String>,
+    hostel_name: String,
+    requests: u32,
+    estimated: f64,
+    actual: f64,
+    budget: f64,
+    variance: f64,
+    over_budget: bool,
+    // Estimates on requests that have no actual cost yet
+    open_estimates: f64,
+    months: Vec<MonthlyMaintenanceCost>,
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Room created successfully"
-    })))
+// ===== ROSTER =====
+
+const STAFF_ROLES: &[&str] = &["warden", "caretaker"];
+// morning 06:00-14:00, evening 14:00-22:00, night 22:00-06:00 (starts on the date)
+const DUTY_SHIFTS: &[&str] = &["morning", "evening", "night"];
+// Longest range one roster request can fill
+const MAX_ROSTER_DAYS: i64 = 62;
+
+// An hr-service employee posted to a hostel
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct HostelStaff {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    hostel_id: String,
+    employee_id: String,
+    role: String, // warden, caretaker
+    campus_id: String,
+    assigned_by: String,
+    assigned_at: DateTime<Utc>,
 }
 
-async fn get_rooms(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+#[derive(Debug, Deserialize)]
+struct HostelStaffRequest {
+    employee_id: String,
+    role: String,
+}
 
-    let collection: Collection<Room> = data.db.collection("rooms");
+// One staff member on one shift on one date
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DutyShift {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    hostel_id: String,
+    employee_id: String,
+    role: String,
+    date: NaiveDate,
+    shift: String, // morning, evening, night
+    campus_id: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
+}
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct RosterRequest {
+    employee_id: String,
+    shift: String,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+}
 
-    let mut rooms = Vec::new();
-    use futures::stream::StreamExt;
-    
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(room) => rooms.push(room),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
-    }
+#[derive(Debug, Deserialize)]
+struct RosterQuery {
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    employee_id: Option<String>,
+}
 
-    Ok(HttpResponse::Ok().json(rooms))
+#[derive(Debug, Deserialize)]
+struct OnDutyQuery {
+    hostel_id: Option<String>,
+    date: Option<NaiveDate>,
+    shift: Option<String>,
 }
 
-// Room Allocation
-async fn allocate_room(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    allocation_data: web::Json<AllocationRequest>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+// ===== ROOM CHANGES =====
 
-    let room_collection: Collection<Room> = data.db.collection("rooms");
-    let allocation_collection: Collection<RoomAllocation> = data.db.collection("room_allocations");
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RoomChangeRequest {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    current_allocation_id: String,
+    reason: String,
+    preferred_hostel_id: Option<String>,
+    preferred_room_id: Option<String>,
+    status: String, // pending, approved, rejected, cancelled
+    requested_at: DateTime<Utc>,
+    reviewed_by: Option<String>,
+    reviewed_at: Option<DateTime<Utc>>,
+    review_note: Option<String>,
+    // Allocation created by the transfer on approval
+    new_allocation_id: Option<String>,
+    campus_id: String,
+}
 
-    // Get room details
-    let room_obj_id = ObjectId::parse_str(&allocation_data.room_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+#[derive(Debug, Deserialize)]
+struct RoomChangeRequestData {
+    student_id: String,
+    reason: String,
+    preferred_hostel_id: Option<String>,
+    preferred_room_id: Option<String>,
+}
 
-    let room = room_collection
-        .find_one(doc! { "_id": room_obj_id, "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct RoomChangeApproval {
+    // Overrides the student's preference
+    room_id: Option<String>,
+    note: Option<String>,
+}
 
-    let room = match room {
-        Some(r) => r,
-        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Room not found"
-        }))),
-    };
+#[derive(Debug, Deserialize)]
+struct RoomChangeQuery {
+    status: Option<String>,
+    student_id: Option<String>,
+}
 
-    // Check if room is available
-    if room.occupied >= room.capacity {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Room is full"
-        })));
-    }
+// ===== GATE =====
 
-    // Create allocation
-    let new_allocation = RoomAllocation {
-        id: None,
-        student_id: allocation_data.student_id.clone(),
-        room_id: allocation_data.room_id.clone(),
-        hostel_name: room.hostel_name.clone(),
-        room_number: room.room_number.clone(),
-        allocation_date: Utc::now(),
-        status: "active".to_string(),
-        campus_id: claims.campus_id.clone(),
-    };
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct GatePass {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    reason: String,
+    destination: Option<String>,
+    status: String, // pending, approved, rejected, cancelled, out, returned
+    requested_at: DateTime<Utc>,
+    reviewed_by: Option<String>,
+    reviewed_at: Option<DateTime<Utc>>,
+    review_note: Option<String>,
+    checked_out_at: Option<DateTime<Utc>>,
+    checked_in_at: Option<DateTime<Utc>>,
+    campus_id: String,
+    // Out-pass for nights away; approval notifies the guardian
+    #[serde(default)]
+    overnight: bool,
+}
 
-    allocation_collection
-        .insert_one(new_allocation, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct GatePassRequest {
+    student_id: String,
+    from_date: NaiveDate,
+    to_date: NaiveDate,
+    reason: String,
+    destination: Option<String>,
+    // Implied when to_date is after from_date
+    #[serde(default)]
+    overnight: bool,
+}
 
-    // Update room occupied count
-    room_collection
-        .update_one(
-            doc! { "_id": room_obj_id },
-            doc! { "$inc": { "occupied": 1 } },
-            None,
-        )
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct GatePassReview {
+    note: Option<String>,
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Room allocated successfully"
-    })))
+#[derive(Debug, Deserialize)]
+struct GatePassQuery {
+    status: Option<String>,
+    student_id: Option<String>,
 }
 
-async fn get_allocations(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+// One resident passing the hostel gate
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MovementLog {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    direction: String, // out, in
+    gate_pass_id: Option<String>,
+    recorded_by: String,
+    recorded_at: DateTime<Utc>,
+    campus_id: String,
+}
 
-    let collection: Collection<RoomAllocation> = data.db.collection("room_allocations");
+#[derive(Debug, Deserialize)]
+struct MovementRequest {
+    student_id: String,
+    direction: String,
+    gate_pass_id: Option<String>,
+}
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct MovementQuery {
+    student_id: Option<String>,
+    date: Option<NaiveDate>,
+}
 
-    let mut allocations = Vec::new();
-    use futures::stream::StreamExt;
-    
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(allocation) => allocations.push(allocation),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
-    }
+// A gate device reading, kept as the device reported it
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EntryLog {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    device_id: String,
+    // Per-device counter; a resent event carries the same number
+    sequence: i64,
+    student_id: String,
+    direction: String, // in, out
+    // Whole seconds, so stored timestamps order correctly as strings
+    timestamp: DateTime<Utc>,
+    received_at: DateTime<Utc>,
+    campus_id: String,
+}
 
-    Ok(HttpResponse::Ok().json(allocations))
+#[derive(Debug, Deserialize)]
+struct EntryEvent {
+    device_id: String,
+    sequence: i64,
+    student_id: String,
+    direction: String,
+    timestamp: DateTime<Utc>,
 }
 
-// Maintenance Management
-async fn create_maintenance_request(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    maintenance_data: web::Json<MaintenanceRequestData>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+#[derive(Debug, Deserialize)]
+struct EntryLogBatch {
+    campus_id: String,
+    events: Vec<EntryEvent>,
+}
 
-    let collection: Collection<MaintenanceRequest> = data.db.collection("maintenance_requests");
+#[derive(Debug, Deserialize)]
+struct EntryLogQuery {
+    from: DateTime<Utc>,
+    to: DateTime<Utc>,
+    direction: Option<String>,
+    student_id: Option<String>,
+    device_id: Option<String>,
+}
 
-    let new_request = MaintenanceRequest {
-        id: None,
-        room_number: maintenance_data.room_number.clone(),
+const MAX_ENTRY_BATCH: usize = 500;
+
+// Device clocks ahead of ours by more than this are rejected
+const ENTRY_CLOCK_SKEW_SECS: i64 = 300;
+
+const ROLL_CALL_STATUSES: &[&str] = &["present", "absent", "on_leave"];
+
+// A resident's status at one night's roll call
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct RollCallEntry {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    date: NaiveDate,
+    status: String, // present, absent, on_leave
+    // Pass covering the night, for absences and leave
+    gate_pass_id: Option<String>,
+    marked_by: String,
+    marked_at: DateTime<Utc>,
+    campus_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RollCallMark {
+    student_id: String,
+    status: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct RollCallRequest {
+    hostel_id: String,
+    date: NaiveDate,
+    entries: Vec<RollCallMark>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RollCallQuery {
+    hostel_id: String,
+    date: NaiveDate,
+}
+
+#[derive(Debug, Deserialize)]
+struct RollCallReportQuery {
+    student_id: String,
+    month: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct HostelAlert {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    kind: String, // unexcused_absence
+    student_id: String,
+    hostel_id: String,
+    date: NaiveDate,
+    message: String,
+    status: String, // open, acknowledged, resolved
+    acknowledged_by: Option<String>,
+    created_at: DateTime<Utc>,
+    campus_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AlertQuery {
+    status: Option<String>,
+    hostel_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OutsideReportQuery {
+    hostel_id: Option<String>,
+}
+
+// ===== DISCIPLINE =====
+
+const INCIDENT_SEVERITIES: &[&str] = &["minor", "moderate", "major", "critical"];
+const DISCIPLINARY_ACTIONS: &[&str] = &["warning", "fine", "expulsion"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DisciplinaryAction {
+    action: String, // warning, fine, expulsion
+    notes: Option<String>,
+    amount: Option<f64>, // fines only
+    // Fines are billed in finance-service as hostel charges
+    fee_id: Option<String>,
+    taken_by: String,
+    taken_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DisciplinaryIncident {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: Option<String>,
+    category: String, // e.g. curfew, noise, damage, ragging, substance
+    severity: String, // minor, moderate, major, critical
+    description: String,
+    evidence_ref: Option<String>, // document or CCTV reference kept elsewhere
+    occurred_at: DateTime<Utc>,
+    reported_by: String,
+    actions: Vec<DisciplinaryAction>,
+    status: String, // open, closed
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IncidentRequest {
+    student_id: String,
+    category: String,
+    severity: String,
+    description: String,
+    evidence_ref: Option<String>,
+    occurred_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DisciplinaryActionRequest {
+    action: String,
+    notes: Option<String>,
+    amount: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct IncidentQuery {
+    status: Option<String>,
+    hostel_id: Option<String>,
+    severity: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OccupancyReportQuery {
+    hostel_id: Option<String>,
+    trend_months: Option<u32>,
+}
+
+// Splits are relative weights, e.g. {"male": 60, "female": 40}
+#[derive(Debug, Deserialize)]
+struct CapacityPlanRequest {
+    batch_size: u32,
+    gender_split: HashMap<String, f64>,
+    program_split: Option<HashMap<String, f64>>,
+    // Limits the simulation to these hostels
+    hostel_ids: Option<Vec<String>>,
+}
+
+// Free beds on one floor and how many of the incoming batch it would take
+#[derive(Debug, Serialize)]
+struct FloorPlan {
+    hostel_id: String,
+    hostel_name: String,
+    gender_policy: String,
+    floor: i32,
+    vacant: u32,
+    placed: u32,
+    remaining: u32,
+}
+
+#[derive(Debug, Serialize)]
+struct SegmentPlan {
+    gender: String,
+    program: Option<String>,
+    students: u32,
+    placed: u32,
+    shortfall: u32,
+}
+
+const MAX_PLAN_BATCH: u32 = 50_000;
+
+// ===== MESS =====
+
+const MEALS: &[&str] = &["breakfast", "lunch", "snacks", "dinner"];
+const WEEKDAYS: &[&str] = &["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+const DIETS: &[&str] = &["veg", "non_veg"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MenuDay {
+    day: String, // monday .. sunday
+    #[serde(default)]
+    breakfast: Vec<String>,
+    #[serde(default)]
+    lunch: Vec<String>,
+    #[serde(default)]
+    snacks: Vec<String>,
+    #[serde(default)]
+    dinner: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MessMenu {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    hostel_id: String,
+    week_start: NaiveDate, // always a Monday
+    days: Vec<MenuDay>,
+    campus_id: String,
+    updated_by: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessMenuRequest {
+    hostel_id: String,
+    week_start: NaiveDate,
+    days: Vec<MenuDay>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessMenuQuery {
+    hostel_id: String,
+    week_start: Option<NaiveDate>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MealPlan {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    diet: String, // veg, non_veg
+    opt_out_weeks: Vec<NaiveDate>, // Mondays of weeks the student skips the mess
+    status: String, // active, cancelled
+    started_on: NaiveDate,
+    ended_on: Option<NaiveDate>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealPlanRequest {
+    student_id: String,
+    diet: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealPlanUpdate {
+    diet: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct OptOutRequest {
+    week_start: NaiveDate,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MealAttendance {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    date: NaiveDate,
+    meal: String,
+    marked_by: String,
+    campus_id: String,
+    marked_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealAttendanceRequest {
+    hostel_id: String,
+    date: NaiveDate,
+    meal: String,
+    student_ids: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealAttendanceQuery {
+    hostel_id: String,
+    date: NaiveDate,
+    meal: Option<String>,
+}
+
+// One per student per month; finance-service bills it as a "mess" fine
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MessBill {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    month: String, // YYYY-MM
+    diet: String,
+    billable_days: i32,
+    opted_out_days: i32,
+    meals_attended: u64,
+    daily_rate: f64,
+    amount: f64,
+    // Set by finance-service once the bill is raised as a fee
+    fee_id: Option<String>,
+    fee_status: Option<String>,
+    campus_id: String,
+    generated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessBillRequest {
+    month: String,
+    hostel_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MessBillQuery {
+    month: Option<String>,
+    student_id: Option<String>,
+}
+
+// A resident's rating of one meal; one per student per meal
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct MealFeedback {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    hostel_id: String,
+    date: NaiveDate,
+    meal: String,
+    rating: i32, // 1-5
+    comment: Option<String>,
+    // What the published menu listed for that meal, so dishes can be rated
+    menu_items: Vec<String>,
+    campus_id: String,
+    submitted_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealFeedbackRequest {
+    date: Option<NaiveDate>,
+    meal: String,
+    rating: i32,
+    comment: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealFeedbackSummaryQuery {
+    hostel_id: String,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct MealFeedbackTrendQuery {
+    hostel_id: String,
+    meal: Option<String>,
+    weeks: Option<u32>,
+}
+
+// Feedback is taken for today's and yesterday's meals only
+const FEEDBACK_WINDOW_DAYS: i64 = 1;
+
+// Daily mess charge per diet, from the MESS_*_DAILY_RATE env vars
+#[derive(Clone, Copy)]
+struct MessRates {
+    veg: f64,
+    non_veg: f64,
+}
+
+impl MessRates {
+    fn daily_rate(&self, diet: &str) -> f64 {
+        if diet == "non_veg" { self.non_veg } else { self.veg }
+    }
+}
+
+// Raises hostel fees through finance-service's fine integration
+#[derive(Clone)]
+struct FinanceClient {
+    base_url: String, // FINANCE_SERVICE_URL
+    api_key: Option<String>, // FINE_INTEGRATION_KEY; unset: allocations stay unbilled
+    http: reqwest::Client,
+}
+
+impl FinanceClient {
+    // Returns the finance fee id; billing the same reference twice returns the same fee
+    async fn bill(
+        &self,
+        source: &str,
+        reference_id: &str,
+        student_id: &str,
+        amount: f64,
+        campus_id: &str,
+    ) -> Result<String, String> {
+        let api_key = self.api_key.as_deref().ok_or("FINE_INTEGRATION_KEY is not set")?;
+        let response = self.http
+            .post(format!("{}/api/integrations/fines", self.base_url))
+            .header("X-Integration-Key", api_key)
+            .json(&serde_json::json!({
+                "source": source,
+                "reference_id": reference_id,
+                "student_id": student_id,
+                "amount": amount,
+                "campus_id": campus_id
+            }))
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!("finance-service returned {}", response.status()));
+        }
+        let body: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        body["fee_id"].as_str().map(str::to_string).ok_or_else(|| "No fee_id in response".to_string())
+    }
+
+    // Status and amount still due of the fee billed under an allocation's billing reference
+    async fn fee_status(&self, reference_id: &str, campus_id: &str) -> Result<(String, f64), String> {
+        let api_key = self.api_key.as_deref().ok_or("FINE_INTEGRATION_KEY is not set")?;
+        let response = self.http
+            .get(format!("{}/api/integrations/fines/hostel_rent/{}", self.base_url, reference_id))
+            .query(&[("campus_id", campus_id)])
+            .header("X-Integration-Key", api_key)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+
+        if !response.status().is_success() {
+            return Err(format!("finance-service returned {}", response.status()));
+        }
+        let body: serde_json::Value = response.json().await.map_err(|e| e.to_string())?;
+        Ok((
+            body["status"].as_str().unwrap_or_default().to_string(),
+            body["amount_due"].as_f64().unwrap_or_default(),
+        ))
+    }
+}
+
+struct AppState {
+    db: mongodb::Database,
+    // Verification keys by kid, reloaded from the jwt_keys collection
+    jwt_keys: SharedKeySet,
+    mess_rates: MessRates,
+    allocation_strategy: String,
+    finance: FinanceClient,
+    default_stay_months: i32,
+    device_api_key: Option<String>, // GATE_DEVICE_API_KEY; unset: ingestion disabled
+    events: EventPublisher,
+}
+
+// Where hostel events are delivered, from the EVENT_* env vars
+#[derive(Clone)]
+struct EventPublisher {
+    webhook_urls: Vec<String>, // empty: events are not published
+    secret: String, // signs each body; receivers check X-CampusConnect-Signature
+    max_attempts: u32,
+}
+
+// One event to one subscriber, in the event_deliveries collection. pending
+// deliveries are retried with exponential backoff until delivered, or failed
+// after max_attempts.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EventDelivery {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    event_id: String,
+    event_type: String, // out_pass.approved
+    url: String,
+    // The JSON body sent, kept as text so the signature always matches
+    payload: String,
+    status: String, // pending, delivered, failed
+    attempts: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_status_code: Option<u16>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_error: Option<String>,
+    next_attempt_at: mongodb::bson::DateTime,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    delivered_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EventDeliveryQuery {
+    status: Option<String>,
+    event_type: Option<String>,
+}
+
+fn extract_claims(req: &HttpRequest, jwt_keys: &SharedKeySet) -> Result<Claims, String> {
+    if let Some(auth_header) = req.headers().get("Authorization") {
+        if let Ok(auth_str) = auth_header.to_str() {
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                let keys = jwt_keys.read().expect("JWT key set lock poisoned");
+                return keys.decode(token).ok_or_else(|| "Invalid token".to_string());
+            }
+        }
+    }
+    Err("No token provided".to_string())
+}
+
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "service": "hostel-service"
+    }))
+}
+
+fn is_hostel_admin(claims: &Claims) -> bool {
+    claims.role == "admin" || claims.role == "warden"
+}
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref we)) if we.code == 11000
+    )
+}
+
+fn validate_hostel(hostel_data: &HostelRequest) -> Result<(), String> {
+    if hostel_data.name.trim().is_empty() {
+        return Err("Hostel name is required".to_string());
+    }
+    if !HOSTEL_TYPES.contains(&hostel_data.hostel_type.as_str()) {
+        return Err(format!("hostel_type must be one of: {}", HOSTEL_TYPES.join(", ")));
+    }
+    if !GENDER_POLICIES.contains(&hostel_data.gender_policy.as_str()) {
+        return Err(format!("gender_policy must be one of: {}", GENDER_POLICIES.join(", ")));
+    }
+    if hostel_data.total_capacity <= 0 {
+        return Err("total_capacity must be positive".to_string());
+    }
+    Ok(())
+}
+
+async fn find_hostel(
+    db: &mongodb::Database,
+    hostel_id: &str,
+    campus_id: &str,
+) -> Result<Option<Hostel>, Error> {
+    let hostel_obj_id = ObjectId::parse_str(hostel_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    db.collection::<Hostel>("hostels")
+        .find_one(doc! { "_id": hostel_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Sum of the capacities of every room already placed in a hostel
+async fn hostel_room_capacity(db: &mongodb::Database, hostel_id: &str) -> Result<i32, Error> {
+    let mut cursor = db
+        .collection::<Room>("rooms")
+        .find(doc! { "hostel_id": hostel_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut total = 0;
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(room) => total += room.capacity,
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(total)
+}
+
+// ===== HOSTELS =====
+
+async fn create_hostel(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    hostel_data: web::Json<HostelRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    if let Err(e) = validate_hostel(&hostel_data) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let collection: Collection<Hostel> = data.db.collection("hostels");
+
+    let new_hostel = Hostel {
+        id: None,
+        name: hostel_data.name.trim().to_string(),
+        hostel_type: hostel_data.hostel_type.clone(),
+        gender_policy: hostel_data.gender_policy.clone(),
+        warden_employee_id: hostel_data.warden_employee_id.clone(),
+        total_capacity: hostel_data.total_capacity,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+        updated_at: None,
+    };
+
+    match collection.insert_one(new_hostel, None).await {
+        Ok(result) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Hostel created successfully",
+            "hostel_id": result.inserted_id.as_object_id().map(|id| id.to_hex())
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "A hostel with this name already exists"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn get_hostels(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Hostel> = data.db.collection("hostels");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut hostels = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(hostel) => hostels.push(hostel),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(hostels))
+}
+
+async fn get_hostel(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let hostel_id = path.into_inner();
+    let hostel = match find_hostel(&data.db, &hostel_id, &claims.campus_id).await? {
+        Some(h) => h,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Hostel not found"
+        }))),
+    };
+
+    let allotted_capacity = hostel_room_capacity(&data.db, &hostel_id).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "hostel": hostel,
+        "allotted_capacity": allotted_capacity,
+        "unallotted_capacity": hostel.total_capacity - allotted_capacity
+    })))
+}
+
+async fn update_hostel(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    hostel_data: web::Json<HostelRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    if let Err(e) = validate_hostel(&hostel_data) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let hostel_id = path.into_inner();
+    let hostel = match find_hostel(&data.db, &hostel_id, &claims.campus_id).await? {
+        Some(h) => h,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Hostel not found"
+        }))),
+    };
+
+    let allotted_capacity = hostel_room_capacity(&data.db, &hostel_id).await?;
+    if hostel_data.total_capacity < allotted_capacity {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "total_capacity is below the capacity of the hostel's rooms",
+            "allotted_capacity": allotted_capacity
+        })));
+    }
+
+    let name = hostel_data.name.trim().to_string();
+    let update = doc! {
+        "$set": {
+            "name": &name,
+            "hostel_type": &hostel_data.hostel_type,
+            "gender_policy": &hostel_data.gender_policy,
+            "warden_employee_id": &hostel_data.warden_employee_id,
+            "total_capacity": hostel_data.total_capacity,
+            "updated_at": Utc::now().to_rfc3339(),
+        }
+    };
+
+    let collection: Collection<Hostel> = data.db.collection("hostels");
+    match collection.update_one(doc! { "_id": hostel.id }, update, None).await {
+        Ok(_) => {}
+        Err(e) if is_duplicate_key(&e) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "A hostel with this name already exists"
+            })));
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+
+    // Rooms keep a copy of the name for display; keep it in step on rename
+    if name != hostel.name {
+        data.db
+            .collection::<Room>("rooms")
+            .update_many(
+                doc! { "hostel_id": &hostel_id },
+                doc! { "$set": { "hostel_name": &name } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Hostel updated successfully"
+    })))
+}
+
+async fn delete_hostel(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let hostel_id = path.into_inner();
+    let hostel = match find_hostel(&data.db, &hostel_id, &claims.campus_id).await? {
+        Some(h) => h,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Hostel not found"
+        }))),
+    };
+
+    let room_count = data.db
+        .collection::<Room>("rooms")
+        .count_documents(doc! { "hostel_id": &hostel_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if room_count > 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Hostel still has rooms; remove them first",
+            "room_count": room_count
+        })));
+    }
+
+    data.db
+        .collection::<Hostel>("hostels")
+        .delete_one(doc! { "_id": hostel.id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Hostel deleted successfully"
+    })))
+}
+
+// Room Management
+async fn create_room(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    room_data: web::Json<RoomRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if room_data.capacity <= 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "capacity must be positive"
+        })));
+    }
+
+    let hostel = match find_hostel(&data.db, &room_data.hostel_id, &claims.campus_id).await? {
+        Some(h) => h,
+        None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Hostel not found"
+        }))),
+    };
+
+    let allotted_capacity = hostel_room_capacity(&data.db, &room_data.hostel_id).await?;
+    if allotted_capacity + room_data.capacity > hostel.total_capacity {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Room would exceed the hostel's total capacity",
+            "total_capacity": hostel.total_capacity,
+            "allotted_capacity": allotted_capacity
+        })));
+    }
+
+    let collection: Collection<Room> = data.db.collection("rooms");
+
+    let new_room = Room {
+        id: None,
+        room_number: room_data.room_number.clone(),
+        hostel_id: Some(room_data.hostel_id.clone()),
+        hostel_name: hostel.name,
+        capacity: room_data.capacity,
+        occupied: 0,
+        room_type: room_data.room_type.clone(),
+        floor: room_data.floor,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+        out_of_service: false,
+    };
+
+    collection
+        .insert_one(new_room, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room created successfully"
+    })))
+}
+
+async fn get_rooms(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<RoomQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Room> = data.db.collection("rooms");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(hostel_id) = &query.hostel_id {
+        filter.insert("hostel_id", hostel_id);
+    }
+
+    let mut cursor = collection
+        .find(filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut rooms = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(room) => rooms.push(room),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(rooms))
+}
+
+async fn update_room(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    update: web::Json<RoomUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let room_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let room_collection: Collection<Room> = data.db.collection("rooms");
+    let Some(room) = room_collection
+        .find_one(doc! { "_id": room_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Room not found"
+        })));
+    };
+
+    let mut set = doc! {};
+    let mut filter = doc! { "_id": room_obj_id };
+    if let Some(capacity) = update.capacity {
+        if capacity <= 0 {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "capacity must be positive"
+            })));
+        }
+        if let Some(hostel_id) = &room.hostel_id {
+            if let Some(hostel) = find_hostel(&data.db, hostel_id, &claims.campus_id).await? {
+                let allotted = hostel_room_capacity(&data.db, hostel_id).await? - room.capacity;
+                if allotted + capacity > hostel.total_capacity {
+                    return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                        "error": "Room would exceed the hostel's total capacity",
+                        "total_capacity": hostel.total_capacity,
+                        "allotted_capacity": allotted
+                    })));
+                }
+            }
+        }
+        // Checked in the update itself so an allocation in between cannot slip under
+        filter.insert("occupied", doc! { "$lte": capacity });
+        set.insert("capacity", capacity);
+    }
+    if let Some(room_number) = &update.room_number {
+        if room_number.trim().is_empty() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "room_number cannot be empty"
+            })));
+        }
+        set.insert("room_number", room_number.trim());
+    }
+    if let Some(room_type) = &update.room_type {
+        set.insert("room_type", room_type);
+    }
+    if let Some(floor) = update.floor {
+        set.insert("floor", floor);
+    }
+    if let Some(out_of_service) = update.out_of_service {
+        set.insert("out_of_service", out_of_service);
+    }
+    if set.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+
+    let result = room_collection
+        .update_one(filter, doc! { "$set": set }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "capacity cannot be below the number of current residents",
+            "occupied": room.occupied
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room updated successfully"
+    })))
+}
+
+// Deletes an empty room. A room with residents is taken out of service
+// instead, so it can be deleted once they have moved out.
+async fn delete_room(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let room_id = path.into_inner();
+    let room_obj_id = ObjectId::parse_str(&room_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let room_collection: Collection<Room> = data.db.collection("rooms");
+
+    // Stop new allocations first so none can land between the check and the delete
+    let room = room_collection
+        .find_one_and_update(
+            doc! { "_id": room_obj_id, "campus_id": &claims.campus_id },
+            doc! { "$set": { "out_of_service": true } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if room.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Room not found"
+        })));
+    }
+
+    let active_allocations = data.db
+        .collection::<RoomAllocation>("room_allocations")
+        .count_documents(doc! { "room_id": &room_id, "status": "active" }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if active_allocations > 0 {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Room has residents; marked out of service instead of deleting",
+            "deleted": false,
+            "active_allocations": active_allocations
+        })));
+    }
+
+    room_collection
+        .delete_one(doc! { "_id": room_obj_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room deleted successfully",
+        "deleted": true
+    })))
+}
+
+// Room Allocation
+async fn allocate_room(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    allocation_data: web::Json<AllocationRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let room_collection: Collection<Room> = data.db.collection("rooms");
+
+    // Get room details
+    let room_obj_id = ObjectId::parse_str(&allocation_data.room_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let room = room_collection
+        .find_one(doc! { "_id": room_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let room = match room {
+        Some(r) => r,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Room not found"
+        }))),
+    };
+
+    if let Some(existing) = find_active_allocation(&data.db, &allocation_data.student_id, &claims.campus_id).await? {
+        return Ok(already_allocated(existing));
+    }
+
+    let duration_months = allocation_data.duration_months.unwrap_or(data.default_stay_months);
+    if duration_months <= 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "duration_months must be positive"
+        })));
+    }
+
+    let allocation = match assign_room(&data, &room, &allocation_data.student_id, duration_months).await {
+        Ok(Some(allocation)) => allocation,
+        Ok(None) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Room is full",
+                "hint": "Add the student to the waitlist with POST /api/waitlist"
+            })));
+        }
+        // Lost a race with another request for the same student
+        Err(e) if is_duplicate_key(&e) => {
+            let existing = find_active_allocation(&data.db, &allocation_data.student_id, &claims.campus_id).await?;
+            return match existing {
+                Some(existing) => Ok(already_allocated(existing)),
+                None => Err(actix_web::error::ErrorInternalServerError(e)),
+            };
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room allocated successfully",
+        "allocation": allocation
+    })))
+}
+
+async fn find_active_allocation(
+    db: &mongodb::Database,
+    student_id: &str,
+    campus_id: &str,
+) -> Result<Option<RoomAllocation>, Error> {
+    db.collection::<RoomAllocation>("room_allocations")
+        .find_one(doc! { "student_id": student_id, "status": "active", "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+fn already_allocated(existing: RoomAllocation) -> HttpResponse {
+    HttpResponse::Conflict().json(serde_json::json!({
+        "error": "Student already has an active room allocation",
+        "allocation": existing
+    }))
+}
+
+// Rate card for a room: the hostel's own card, else the campus-wide one
+async fn find_rate_card(db: &mongodb::Database, room: &Room) -> mongodb::error::Result<Option<RateCard>> {
+    let collection: Collection<RateCard> = db.collection("rate_cards");
+    if room.hostel_id.is_some() {
+        let card = collection
+            .find_one(
+                doc! { "hostel_id": &room.hostel_id, "room_type": &room.room_type, "campus_id": &room.campus_id },
+                None,
+            )
+            .await?;
+        if card.is_some() {
+            return Ok(card);
+        }
+    }
+    collection
+        .find_one(
+            doc! { "hostel_id": null, "room_type": &room.room_type, "campus_id": &room.campus_id },
+            None,
+        )
+        .await
+}
+
+// Raises the allocation's fee in finance and records the outcome on it.
+// Finance being unreachable leaves the allocation "unbilled" for a retry.
+async fn bill_allocation(state: &AppState, allocation: &mut RoomAllocation) -> mongodb::error::Result<()> {
+    let (Some(allocation_id), Some(amount)) = (allocation.id, allocation.fee_amount) else {
+        return Ok(());
+    };
+
+    let reference_id = allocation.billing_reference();
+    let billed = state.finance
+        .bill("hostel_rent", &reference_id, &allocation.student_id, amount, &allocation.campus_id)
+        .await;
+    let (fee_id, fee_status) = match billed {
+        Ok(fee_id) => (Some(fee_id), "pending"),
+        Err(e) => {
+            log::warn!("Could not bill hostel fee for allocation {}: {}", allocation_id, e);
+            (None, "unbilled")
+        }
+    };
+
+    state.db
+        .collection::<RoomAllocation>("room_allocations")
+        .update_one(
+            doc! { "_id": allocation_id },
+            doc! { "$set": { "fee_id": &fee_id, "fee_status": fee_status, "billing_reference_id": &reference_id } },
+            None,
+        )
+        .await?;
+    allocation.fee_id = fee_id;
+    allocation.billing_reference_id = Some(reference_id);
+    allocation.fee_status = Some(fee_status.to_string());
+    Ok(())
+}
+
+// The capacity check and the increment are one atomic update, so concurrent
+// allocations cannot overfill the room. Returns false if the room is full.
+async fn claim_bed(db: &mongodb::Database, room: &Room) -> mongodb::error::Result<bool> {
+    let claimed = db
+        .collection::<Room>("rooms")
+        .find_one_and_update(
+            doc! {
+                "_id": room.id,
+                "out_of_service": { "$ne": true },
+                "$expr": { "$lt": ["$occupied", "$capacity"] }
+            },
+            doc! { "$inc": { "occupied": 1 } },
+            None,
+        )
+        .await?;
+    Ok(claimed.is_some())
+}
+
+async fn return_bed(db: &mongodb::Database, room: &Room) -> mongodb::error::Result<()> {
+    db.collection::<Room>("rooms")
+        .update_one(
+            doc! { "_id": room.id, "occupied": { "$gt": 0 } },
+            doc! { "$inc": { "occupied": -1 } },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+// Takes a bed, records the allocation, closes any waitlist entry and bills
+// the stay at the room's rate card. Returns None if the room is full.
+async fn assign_room(
+    state: &AppState,
+    room: &Room,
+    student_id: &str,
+    duration_months: i32,
+) -> mongodb::error::Result<Option<RoomAllocation>> {
+    let db = &state.db;
+    let room_id = room.id.map(|id| id.to_hex()).unwrap_or_default();
+
+    if !claim_bed(db, room).await? {
+        return Ok(None);
+    }
+
+    let rate_card = find_rate_card(db, room).await?;
+    let fee_amount = rate_card.map(|card| (card.monthly_rate * duration_months as f64 * 100.0).round() / 100.0);
+
+    let mut new_allocation = RoomAllocation {
+        id: None,
+        student_id: student_id.to_string(),
+        room_id: room_id.clone(),
+        hostel_name: room.hostel_name.clone(),
+        room_number: room.room_number.clone(),
+        allocation_date: Utc::now(),
+        status: "active".to_string(),
+        campus_id: room.campus_id.clone(),
+        vacated_at: None,
+        duration_months: Some(duration_months),
+        fee_amount,
+        fee_id: None,
+        fee_status: Some(if fee_amount.is_some() { "unbilled" } else { "not_applicable" }.to_string()),
+        checked_in_at: None,
+        vacate_reason: None,
+        transferred_from: None,
+        billing_reference_id: None,
+    };
+
+    let result = match db.collection::<RoomAllocation>("room_allocations")
+        .insert_one(&new_allocation, None)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => {
+            // Give the bed back, e.g. when the student already has a room
+            return_bed(db, room).await?;
+            return Err(e);
+        }
+    };
+    new_allocation.id = result.inserted_id.as_object_id();
+
+    db.collection::<WaitlistEntry>("room_waitlist")
+        .update_one(
+            doc! { "student_id": student_id, "status": "waiting", "campus_id": &room.campus_id },
+            doc! { "$set": {
+                "status": "allocated",
+                "allocated_room_id": &room_id,
+                "resolved_at": Utc::now().to_rfc3339()
+            } },
+            None,
+        )
+        .await?;
+
+    bill_allocation(state, &mut new_allocation).await?;
+
+    Ok(Some(new_allocation))
+}
+
+// Fills free beds in a room from the waitlist; returns who was allocated
+async fn allocate_from_waitlist(state: &AppState, room_obj_id: ObjectId) -> mongodb::error::Result<Vec<String>> {
+    let db = &state.db;
+    let room_collection: Collection<Room> = db.collection("rooms");
+    let waitlist_collection: Collection<WaitlistEntry> = db.collection("room_waitlist");
+    let mut allocated = Vec::new();
+
+    loop {
+        let Some(room) = room_collection.find_one(doc! { "_id": room_obj_id }, None).await? else {
+            break;
+        };
+        if room.occupied >= room.capacity {
+            break;
+        }
+
+        let filter = doc! {
+            "status": "waiting",
+            "campus_id": &room.campus_id,
+            "$and": [
+                { "$or": [{ "hostel_id": null }, { "hostel_id": &room.hostel_id }] },
+                { "$or": [{ "room_type": null }, { "room_type": &room.room_type }] }
+            ]
+        };
+        let options = mongodb::options::FindOneOptions::builder()
+            .sort(doc! { "year_of_study": -1, "applied_at": 1 })
+            .build();
+        let Some(entry) = waitlist_collection.find_one(filter, options).await? else {
+            break;
+        };
+
+        match assign_room(state, &room, &entry.student_id, state.default_stay_months).await {
+            Ok(Some(_)) => allocated.push(entry.student_id),
+            Ok(None) => break,
+            // Housed some other way since joining; drop them from the queue
+            Err(e) if is_duplicate_key(&e) => {
+                waitlist_collection
+                    .update_one(
+                        doc! { "_id": entry.id },
+                        doc! { "$set": { "status": "withdrawn", "resolved_at": Utc::now().to_rfc3339() } },
+                        None,
+                    )
+                    .await?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+
+    Ok(allocated)
+}
+
+async fn vacate_allocation(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let allocation_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let vacated = release_allocation(&data, doc! { "_id": allocation_obj_id, "campus_id": &claims.campus_id }, "vacated")
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let Some((_, allocated_from_waitlist)) = vacated else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Active allocation not found"
+        })));
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Allocation vacated",
+        "allocated_from_waitlist": allocated_from_waitlist
+    })))
+}
+
+// Vacates the active allocation matching `filter`, frees its bed and offers
+// the bed to the waitlist. Returns the allocation and who got the bed.
+async fn release_allocation(
+    state: &AppState,
+    mut filter: mongodb::bson::Document,
+    reason: &str,
+) -> mongodb::error::Result<Option<(RoomAllocation, Vec<String>)>> {
+    filter.insert("status", "active");
+    let allocation = state.db
+        .collection::<RoomAllocation>("room_allocations")
+        .find_one_and_update(
+            filter,
+            doc! { "$set": { "status": "vacated", "vacated_at": Utc::now().to_rfc3339(), "vacate_reason": reason } },
+            None,
+        )
+        .await?;
+
+    let Some(allocation) = allocation else { return Ok(None) };
+    let Ok(room_obj_id) = ObjectId::parse_str(&allocation.room_id) else {
+        return Ok(Some((allocation, Vec::new())));
+    };
+
+    state.db
+        .collection::<Room>("rooms")
+        .update_one(
+            doc! { "_id": room_obj_id, "occupied": { "$gt": 0 } },
+            doc! { "$inc": { "occupied": -1 } },
+            None,
+        )
+        .await?;
+
+    let allocated_from_waitlist = allocate_from_waitlist(state, room_obj_id).await?;
+    Ok(Some((allocation, allocated_from_waitlist)))
+}
+
+// Moves a student from their active allocation into `room`. The new stay
+// keeps the original fee and check-in, since the student is already billed
+// and in residence. The old bed goes to the waitlist. Returns None if the
+// room is full or the allocation is no longer active.
+async fn transfer_allocation(
+    state: &AppState,
+    current: &RoomAllocation,
+    room: &Room,
+) -> mongodb::error::Result<Option<RoomAllocation>> {
+    let db = &state.db;
+    if !claim_bed(db, room).await? {
+        return Ok(None);
+    }
+
+    // The old allocation must be closed first: a student has one active stay
+    let Some((old, _)) = release_allocation(state, doc! { "_id": current.id }, "transferred").await? else {
+        return_bed(db, room).await?;
+        return Ok(None);
+    };
+
+    let mut new_allocation = old.transferred_to(room, Utc::now());
+
+    let result = match db.collection::<RoomAllocation>("room_allocations")
+        .insert_one(&new_allocation, None)
+        .await
+    {
+        Ok(result) => result,
+        Err(e) => {
+            return_bed(db, room).await?;
+            return Err(e);
+        }
+    };
+    new_allocation.id = result.inserted_id.as_object_id();
+    Ok(Some(new_allocation))
+}
+
+async fn find_allocation(
+    db: &mongodb::Database,
+    allocation_id: &str,
+    campus_id: &str,
+) -> Result<Option<RoomAllocation>, Error> {
+    let allocation_obj_id = ObjectId::parse_str(allocation_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    db.collection::<RoomAllocation>("room_allocations")
+        .find_one(doc! { "_id": allocation_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Retries billing for an allocation finance could not be reached for
+async fn rebill_allocation(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let Some(mut allocation) = find_allocation(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Allocation not found"
+        })));
+    };
+    if allocation.fee_status.as_deref() != Some("unbilled") {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Allocation has no unbilled fee",
+            "fee_status": allocation.fee_status
+        })));
+    }
+
+    bill_allocation(&data, &mut allocation)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if allocation.fee_id.is_none() {
+        return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+            "error": "finance-service could not bill the fee; try again later"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Hostel fee billed",
+        "fee_id": allocation.fee_id
+    })))
+}
+
+// Moving in requires the stay's hostel fee to be paid in full
+async fn check_in_allocation(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let Some(allocation) = find_allocation(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Allocation not found"
+        })));
+    };
+    if allocation.status != "active" || allocation.checked_in_at.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Allocation is not awaiting check-in"
+        })));
+    }
+
+    let allocation_id = allocation.id.map(|id| id.to_hex()).unwrap_or_default();
+    let fee_status = match allocation.fee_status.as_deref() {
+        Some("not_applicable") | None => None,
+        _ if allocation.fee_id.is_none() => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Hostel fee has not been billed yet",
+                "fee_status": allocation.fee_status
+            })));
+        }
+        _ => {
+            let reference_id = allocation.billing_reference();
+            let (status, amount_due) = match data.finance.fee_status(&reference_id, &claims.campus_id).await {
+                Ok(result) => result,
+                Err(e) => {
+                    log::warn!("Could not check hostel fee for allocation {}: {}", allocation_id, e);
+                    return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                        "error": "Could not confirm the hostel fee with finance-service"
+                    })));
+                }
+            };
+            if amount_due > 0.005 {
+                data.db
+                    .collection::<RoomAllocation>("room_allocations")
+                    .update_one(doc! { "_id": allocation.id }, doc! { "$set": { "fee_status": &status } }, None)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+                return Ok(HttpResponse::PaymentRequired().json(serde_json::json!({
+                    "error": "Hostel fee must be paid before check-in",
+                    "fee_id": allocation.fee_id,
+                    "fee_status": status,
+                    "amount_due": amount_due
+                })));
+            }
+            Some(status)
+        }
+    };
+
+    let mut update = doc! { "checked_in_at": Utc::now().to_rfc3339() };
+    if let Some(status) = fee_status {
+        update.insert("fee_status", status);
+    }
+    data.db
+        .collection::<RoomAllocation>("room_allocations")
+        .update_one(doc! { "_id": allocation.id }, doc! { "$set": update }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Student checked in"
+    })))
+}
+
+// ===== RATE CARDS =====
+
+async fn upsert_rate_card(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    card_data: web::Json<RateCardRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if card_data.monthly_rate < 0.0 || card_data.room_type.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "room_type is required and monthly_rate cannot be negative"
+        })));
+    }
+    if let Some(hostel_id) = &card_data.hostel_id {
+        if find_hostel(&data.db, hostel_id, &claims.campus_id).await?.is_none() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Hostel not found"
+            })));
+        }
+    }
+
+    let card = RateCard {
+        id: None,
+        hostel_id: card_data.hostel_id.clone(),
+        room_type: card_data.room_type.trim().to_string(),
+        monthly_rate: (card_data.monthly_rate * 100.0).round() / 100.0,
+        campus_id: claims.campus_id.clone(),
+        updated_by: claims.sub.clone(),
+        updated_at: Utc::now(),
+    };
+
+    data.db
+        .collection::<RateCard>("rate_cards")
+        .replace_one(
+            doc! { "hostel_id": &card.hostel_id, "room_type": &card.room_type, "campus_id": &card.campus_id },
+            &card,
+            mongodb::options::ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Rate card saved",
+        "rate_card": card
+    })))
+}
+
+async fn get_rate_cards(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut cursor = data.db
+        .collection::<RateCard>("rate_cards")
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut cards = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(card) => cards.push(card),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(cards))
+}
+
+async fn delete_rate_card(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let card_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let result = data.db
+        .collection::<RateCard>("rate_cards")
+        .delete_one(doc! { "_id": card_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.deleted_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Rate card not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Rate card deleted"
+    })))
+}
+
+// ===== AUTO ALLOCATION =====
+
+// xorshift64*; good enough to shuffle a lottery without another dependency
+struct Lottery(u64);
+
+impl Lottery {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_F491_4F6C_DD1D)
+    }
+
+    fn shuffle<T>(&mut self, items: &mut [T]) {
+        for i in (1..items.len()).rev() {
+            let j = (self.next() % (i as u64 + 1)) as usize;
+            items.swap(i, j);
+        }
+    }
+}
+
+fn order_applications(applications: &mut [AllocationApplication], strategy: &str, seed: i64) {
+    match strategy {
+        "merit" => applications.sort_by(|a, b| {
+            b.merit_score.unwrap_or(0.0).total_cmp(&a.merit_score.unwrap_or(0.0))
+        }),
+        "lottery" => Lottery((seed as u64).max(1)).shuffle(applications),
+        // Stable, so ties keep the order they were submitted in
+        _ => applications.sort_by_key(|a| std::cmp::Reverse(a.year_of_study)),
+    }
+}
+
+fn room_admits(room: &Room, application: &AllocationApplication, gender_policies: &HashMap<String, String>) -> bool {
+    let policy = room.hostel_id.as_ref().and_then(|id| gender_policies.get(id));
+    match (policy, &application.gender) {
+        (Some(policy), Some(gender)) => policy == "mixed" || policy == gender,
+        _ => true,
+    }
+}
+
+fn room_matches_preference(room: &Room, application: &AllocationApplication) -> bool {
+    application.hostel_id.as_ref().is_none_or(|id| room.hostel_id.as_ref() == Some(id))
+        && application.room_type.as_ref().is_none_or(|t| &room.room_type == t)
+}
+
+// Index of the best room with `beds` free: preferred rooms first, and within
+// those, partly filled rooms before empty ones so rooms fill up in turn
+fn pick_room(
+    rooms: &[Room],
+    applicants: &[&AllocationApplication],
+    beds: i32,
+    gender_policies: &HashMap<String, String>,
+) -> Option<(usize, bool)> {
+    let lead = applicants[0];
+    let fits = |room: &Room| {
+        room.capacity - room.occupied >= beds
+            && applicants.iter().all(|a| room_admits(room, a, gender_policies))
+    };
+    let best = |preferred: bool| {
+        rooms
+            .iter()
+            .enumerate()
+            .filter(|(_, room)| fits(room) && (!preferred || room_matches_preference(room, lead)))
+            .min_by_key(|(_, room)| (room.occupied == 0, room.capacity - room.occupied))
+            .map(|(i, _)| i)
+    };
+    best(true).map(|i| (i, true)).or_else(|| best(false).map(|i| (i, false)))
+}
+
+fn plan_allocation(
+    applications: &[AllocationApplication],
+    rooms: &mut [Room],
+    gender_policies: &HashMap<String, String>,
+) -> (Vec<PlannedAssignment>, Vec<UnassignedApplication>) {
+    let mut assignments = Vec::new();
+    let mut unassigned = Vec::new();
+    let mut placed: Vec<String> = Vec::new();
+
+    let mut assign = |room: &mut Room, application: &AllocationApplication, preference_met: bool, roommate_id: Option<String>| {
+        room.occupied += 1;
+        assignments.push(PlannedAssignment {
+            student_id: application.student_id.clone(),
+            room_id: room.id.map(|id| id.to_hex()).unwrap_or_default(),
+            hostel_id: room.hostel_id.clone(),
+            hostel_name: room.hostel_name.clone(),
+            room_number: room.room_number.clone(),
+            preference_met,
+            roommate_id,
+        });
+    };
+
+    for application in applications {
+        if placed.contains(&application.student_id) {
+            continue;
+        }
+
+        // A mutual pair is placed together when the higher-ranked one comes up
+        let roommate = application.roommate_id.as_ref().and_then(|roommate_id| {
+            applications.iter().find(|other| {
+                &other.student_id == roommate_id
+                    && other.roommate_id.as_ref() == Some(&application.student_id)
+                    && !placed.contains(&other.student_id)
+            })
+        });
+
+        if let Some(roommate) = roommate {
+            if let Some((i, preference_met)) = pick_room(rooms, &[application, roommate], 2, gender_policies) {
+                assign(&mut rooms[i], application, preference_met, Some(roommate.student_id.clone()));
+                assign(&mut rooms[i], roommate, preference_met, Some(application.student_id.clone()));
+                placed.push(application.student_id.clone());
+                placed.push(roommate.student_id.clone());
+                continue;
+            }
+        }
+
+        match pick_room(rooms, &[application], 1, gender_policies) {
+            Some((i, preference_met)) => assign(&mut rooms[i], application, preference_met, None),
+            None => unassigned.push(UnassignedApplication {
+                student_id: application.student_id.clone(),
+                reason: "No vacant room accepts this student".to_string(),
+            }),
+        }
+        placed.push(application.student_id.clone());
+    }
+
+    (assignments, unassigned)
+}
+
+async fn auto_allocate(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    run_data: web::Json<AutoAllocationRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let strategy = run_data.strategy.clone().unwrap_or_else(|| data.allocation_strategy.clone());
+    if !ALLOCATION_STRATEGIES.contains(&strategy.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("strategy must be one of: {}", ALLOCATION_STRATEGIES.join(", "))
+        })));
+    }
+    let seed = (strategy == "lottery")
+        .then(|| run_data.seed.unwrap_or_else(|| Utc::now().timestamp_nanos_opt().unwrap_or_default()));
+
+    use futures::stream::StreamExt;
+
+    // Students who already live in the hostel, or appear twice, are not planned
+    let mut applications = Vec::new();
+    let mut unassigned = Vec::new();
+    let allocation_collection: Collection<RoomAllocation> = data.db.collection("room_allocations");
+    for application in &run_data.applications {
+        if applications.iter().any(|a: &AllocationApplication| a.student_id == application.student_id) {
+            unassigned.push(UnassignedApplication {
+                student_id: application.student_id.clone(),
+                reason: "Duplicate application".to_string(),
+            });
+            continue;
+        }
+        let active = allocation_collection
+            .find_one(
+                doc! { "student_id": &application.student_id, "status": "active", "campus_id": &claims.campus_id },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if active.is_some() {
+            unassigned.push(UnassignedApplication {
+                student_id: application.student_id.clone(),
+                reason: "Student already has an active room allocation".to_string(),
+            });
+            continue;
+        }
+        applications.push(application.clone());
+    }
+    order_applications(&mut applications, &strategy, seed.unwrap_or_default());
+
+    let mut gender_policies = HashMap::new();
+    let mut cursor = data.db
+        .collection::<Hostel>("hostels")
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let hostel = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(id) = hostel.id {
+            gender_policies.insert(id.to_hex(), hostel.gender_policy);
+        }
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "hostel_name": 1, "floor": 1, "room_number": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<Room>("rooms")
+        .find(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "out_of_service": { "$ne": true },
+                "$expr": { "$lt": ["$occupied", "$capacity"] }
+            },
+            options,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut rooms = Vec::new();
+    while let Some(result) = cursor.next().await {
+        rooms.push(result.map_err(actix_web::error::ErrorInternalServerError)?);
+    }
+
+    let (assignments, not_placed) = plan_allocation(&applications, &mut rooms, &gender_policies);
+    unassigned.extend(not_placed);
+
+    let mut run = AllocationRun {
+        id: None,
+        strategy,
+        seed,
+        status: "draft".to_string(),
+        assignments,
+        unassigned,
+        created_by: claims.sub.clone(),
+        created_at: Utc::now(),
+        committed_at: None,
+        skipped_on_commit: Vec::new(),
+        campus_id: claims.campus_id,
+    };
+
+    let result = data.db
+        .collection::<AllocationRun>("allocation_runs")
+        .insert_one(&run, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    run.id = result.inserted_id.as_object_id();
+
+    Ok(HttpResponse::Ok().json(run))
+}
+
+async fn get_allocation_run(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let run_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let run = data.db
+        .collection::<AllocationRun>("allocation_runs")
+        .find_one(doc! { "_id": run_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match run {
+        Some(run) => Ok(HttpResponse::Ok().json(run)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Allocation run not found"
+        }))),
+    }
+}
+
+// Applies a draft run. Rooms may have filled since the draft was made, so
+// each assignment is checked again and skipped if it no longer fits.
+async fn commit_allocation_run(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let run_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let run_collection: Collection<AllocationRun> = data.db.collection("allocation_runs");
+
+    // Claim the draft so a double submit cannot apply it twice
+    let run = run_collection
+        .find_one_and_update(
+            doc! { "_id": run_obj_id, "status": "draft", "campus_id": &claims.campus_id },
+            doc! { "$set": { "status": "committed", "committed_at": Utc::now().to_rfc3339() } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let Some(run) = run else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Draft allocation run not found"
+        })));
+    };
+
+    let room_collection: Collection<Room> = data.db.collection("rooms");
+    let allocation_collection: Collection<RoomAllocation> = data.db.collection("room_allocations");
+    let mut allocated = 0;
+    let mut skipped = Vec::new();
+
+    for assignment in &run.assignments {
+        let active = allocation_collection
+            .find_one(
+                doc! { "student_id": &assignment.student_id, "status": "active", "campus_id": &claims.campus_id },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if active.is_some() {
+            skipped.push(UnassignedApplication {
+                student_id: assignment.student_id.clone(),
+                reason: "Student was allocated a room after the run was drafted".to_string(),
+            });
+            continue;
+        }
+
+        let room = match ObjectId::parse_str(&assignment.room_id) {
+            Ok(room_obj_id) => room_collection
+                .find_one(doc! { "_id": room_obj_id, "campus_id": &claims.campus_id }, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?,
+            Err(_) => None,
+        };
+        match room {
+            Some(room) => {
+                match assign_room(&data, &room, &assignment.student_id, data.default_stay_months).await {
+                    Ok(Some(_)) => allocated += 1,
+                    Ok(None) => skipped.push(UnassignedApplication {
+                        student_id: assignment.student_id.clone(),
+                        reason: "Room is no longer available".to_string(),
+                    }),
+                    Err(e) if is_duplicate_key(&e) => skipped.push(UnassignedApplication {
+                        student_id: assignment.student_id.clone(),
+                        reason: "Student was allocated a room after the run was drafted".to_string(),
+                    }),
+                    Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+                }
+            }
+            None => skipped.push(UnassignedApplication {
+                student_id: assignment.student_id.clone(),
+                reason: "Room is no longer available".to_string(),
+            }),
+        }
+    }
+
+    run_collection
+        .update_one(
+            doc! { "_id": run_obj_id },
+            doc! { "$set": { "skipped_on_commit": mongodb::bson::to_bson(&skipped).map_err(actix_web::error::ErrorInternalServerError)? } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Allocation run committed",
+        "allocated": allocated,
+        "skipped": skipped
+    })))
+}
+
+// ===== WAITLIST =====
+
+async fn join_waitlist(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    waitlist_data: web::Json<WaitlistRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role == "student" && claims.sub != waitlist_data.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only join the waitlist yourself"
+        })));
+    }
+    if claims.role != "student" && !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if waitlist_data.year_of_study <= 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "year_of_study must be positive"
+        })));
+    }
+    if let Some(hostel_id) = &waitlist_data.hostel_id {
+        if find_hostel(&data.db, hostel_id, &claims.campus_id).await?.is_none() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Hostel not found"
+            })));
+        }
+    }
+
+    let active_allocation = data.db
+        .collection::<RoomAllocation>("room_allocations")
+        .find_one(
+            doc! { "student_id": &waitlist_data.student_id, "status": "active", "campus_id": &claims.campus_id },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if active_allocation.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student already has an active room allocation"
+        })));
+    }
+
+    let entry = WaitlistEntry {
+        id: None,
+        student_id: waitlist_data.student_id.clone(),
+        year_of_study: waitlist_data.year_of_study,
+        hostel_id: waitlist_data.hostel_id.clone(),
+        room_type: waitlist_data.room_type.clone(),
+        status: "waiting".to_string(),
+        applied_at: Utc::now(),
+        allocated_room_id: None,
+        resolved_at: None,
+        campus_id: claims.campus_id,
+    };
+
+    let collection: Collection<WaitlistEntry> = data.db.collection("room_waitlist");
+    match collection.insert_one(entry, None).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Added to waitlist"
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student is already on the waitlist"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn leave_waitlist(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only leave the waitlist yourself"
+        })));
+    }
+    if claims.role != "student" && !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let collection: Collection<WaitlistEntry> = data.db.collection("room_waitlist");
+    let result = collection
+        .update_one(
+            doc! { "student_id": &student_id, "status": "waiting", "campus_id": &claims.campus_id },
+            doc! { "$set": { "status": "withdrawn", "resolved_at": Utc::now().to_rfc3339() } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Student is not on the waitlist"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Removed from waitlist"
+    })))
+}
+
+// Waiting entries of a campus in priority order
+async fn waiting_entries(db: &mongodb::Database, campus_id: &str) -> Result<Vec<WaitlistEntry>, Error> {
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "year_of_study": -1, "applied_at": 1 })
+        .build();
+
+    let mut cursor = db
+        .collection::<WaitlistEntry>("room_waitlist")
+        .find(doc! { "status": "waiting", "campus_id": campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut entries = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(entries)
+}
+
+async fn get_waitlist(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let entries = waiting_entries(&data.db, &claims.campus_id).await?;
+    let ranked: Vec<_> = entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| serde_json::json!({ "position": i + 1, "entry": entry }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(ranked))
+}
+
+async fn get_waitlist_position(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own waitlist position"
+        })));
+    }
+
+    let entries = waiting_entries(&data.db, &claims.campus_id).await?;
+    let total = entries.len();
+    match entries.into_iter().enumerate().find(|(_, e)| e.student_id == student_id) {
+        Some((i, entry)) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "position": i + 1,
+            "waiting": total,
+            "entry": entry
+        }))),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Student is not on the waitlist"
+        }))),
+    }
+}
+
+async fn get_allocations(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<RoomAllocation> = data.db.collection("room_allocations");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut allocations = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(allocation) => allocations.push(allocation),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(allocations))
+}
+
+// Every stay a student has had, oldest first, for dues clearance and certificates
+async fn get_student_allocations(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own allocations"
+        })));
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "allocation_date": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<RoomAllocation>("room_allocations")
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut allocations = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(allocation) => allocations.push(allocation),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let now = Utc::now();
+    let mut total_days = 0;
+    let mut history = Vec::new();
+    for allocation in &allocations {
+        let id = allocation.id.map(|id| id.to_hex());
+        let transferred_to = allocations
+            .iter()
+            .find(|next| next.transferred_from.is_some() && next.transferred_from == id)
+            .and_then(|next| next.id)
+            .map(|id| id.to_hex());
+        let days = (allocation.vacated_at.unwrap_or(now) - allocation.allocation_date).num_days();
+        total_days += days;
+        let mut entry = serde_json::to_value(allocation).map_err(actix_web::error::ErrorInternalServerError)?;
+        entry["days"] = serde_json::json!(days);
+        entry["transferred_to"] = serde_json::json!(transferred_to);
+        history.push(entry);
+    }
+
+    // Fees still open in finance as of the last check-in or billing attempt
+    let unsettled_fees: Vec<_> = allocations
+        .iter()
+        .filter(|a| !matches!(a.fee_status.as_deref(), None | Some("paid") | Some("not_applicable")))
+        .map(|a| serde_json::json!({
+            "allocation_id": a.id.map(|id| id.to_hex()),
+            "fee_id": a.fee_id,
+            "fee_status": a.fee_status,
+            "fee_amount": a.fee_amount
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "current_allocation_id": allocations
+            .iter()
+            .find(|a| a.status == "active")
+            .and_then(|a| a.id)
+            .map(|id| id.to_hex()),
+        "stays": allocations.len(),
+        "transfers": allocations.iter().filter(|a| a.transferred_from.is_some()).count(),
+        "total_days": total_days,
+        "unsettled_fees": unsettled_fees,
+        "allocations": history
+    })))
+}
+
+// Maintenance Management
+async fn create_maintenance_request(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    maintenance_data: web::Json<MaintenanceRequestData>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if maintenance_data.estimated_cost.is_some_and(|cost| cost < 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "estimated_cost cannot be negative"
+        })));
+    }
+
+    let collection: Collection<MaintenanceRequest> = data.db.collection("maintenance_requests");
+
+    let hostel = data.db
+        .collection::<Hostel>("hostels")
+        .find_one(doc! { "name": &maintenance_data.hostel_name, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let new_request = MaintenanceRequest {
+        id: None,
+        room_number: maintenance_data.room_number.clone(),
         hostel_name: maintenance_data.hostel_name.clone(),
         issue_type: maintenance_data.issue_type.clone(),
         description: maintenance_data.description.clone(),
@@ -285,43 +2969,3716 @@ async fn create_maintenance_request(
         reported_by: claims.sub.clone(),
         campus_id: claims.campus_id,
         created_at: Utc::now(),
+        hostel_id: hostel.and_then(|h| h.id).map(|id| id.to_hex()),
+        estimated_cost: maintenance_data.estimated_cost.map(|cost| (cost * 100.0).round() / 100.0),
+        actual_cost: None,
+        spent_at: None,
+        vendor: None,
+        resolved_at: None,
+        assigned_to: None,
+    };
+
+    collection
+        .insert_one(new_request, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Maintenance request created successfully"
+    })))
+}
+
+async fn get_maintenance_requests(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<MaintenanceRequest> = data.db.collection("maintenance_requests");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut requests = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(request) => requests.push(request),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+// Status, costs and vendor details are updated by the warden as work progresses
+async fn update_maintenance_request(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    update: web::Json<MaintenanceUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let request_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<MaintenanceRequest> = data.db.collection("maintenance_requests");
+    let Some(existing) = collection
+        .find_one(doc! { "_id": request_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Maintenance request not found"
+        })));
+    };
+
+    if [update.estimated_cost, update.actual_cost].iter().flatten().any(|cost| *cost < 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Costs cannot be negative"
+        })));
+    }
+
+    let mut set = doc! {};
+    if let Some(status) = &update.status {
+        if !MAINTENANCE_STATUSES.contains(&status.as_str()) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("status must be one of: {}", MAINTENANCE_STATUSES.join(", "))
+            })));
+        }
+        if status == "resolved" && existing.resolved_at.is_none() {
+            set.insert("resolved_at", Utc::now().to_rfc3339());
+        }
+        set.insert("status", status);
+    }
+    if let Some(cost) = update.estimated_cost {
+        set.insert("estimated_cost", (cost * 100.0).round() / 100.0);
+    }
+    if let Some(cost) = update.actual_cost {
+        set.insert("actual_cost", (cost * 100.0).round() / 100.0);
+        // Corrections keep the month the spend was first booked in
+        if existing.spent_at.is_none() {
+            set.insert("spent_at", Utc::now().to_rfc3339());
+        }
+    }
+    if let Some(vendor) = &update.vendor {
+        if vendor.name.trim().is_empty() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "vendor name cannot be empty"
+            })));
+        }
+        set.insert("vendor", mongodb::bson::to_bson(vendor).map_err(actix_web::error::ErrorInternalServerError)?);
+    }
+    if let Some(employee_id) = &update.assigned_to {
+        let Some(hostel_id) = &existing.hostel_id else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Request is not linked to a registered hostel, so it has no staff to assign"
+            })));
+        };
+        if find_hostel_staff(&data.db, hostel_id, employee_id, &claims.campus_id).await?.is_none() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("{} is not on the staff roster for this hostel", employee_id)
+            })));
+        }
+        set.insert("assigned_to", employee_id);
+    }
+    if set.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+
+    collection
+        .update_one(doc! { "_id": request_obj_id }, doc! { "$set": set }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Maintenance request updated successfully"
+    })))
+}
+
+async fn upsert_maintenance_budget(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    budget_data: web::Json<MaintenanceBudgetRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if month_bounds(&budget_data.month).is_none() || budget_data.amount < 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "month must be in YYYY-MM format and amount cannot be negative"
+        })));
+    }
+    if find_hostel(&data.db, &budget_data.hostel_id, &claims.campus_id).await?.is_none() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Hostel not found"
+        })));
+    }
+
+    let budget = MaintenanceBudget {
+        id: None,
+        hostel_id: budget_data.hostel_id.clone(),
+        month: budget_data.month.clone(),
+        amount: (budget_data.amount * 100.0).round() / 100.0,
+        campus_id: claims.campus_id.clone(),
+        updated_by: claims.sub.clone(),
+        updated_at: Utc::now(),
+    };
+
+    data.db
+        .collection::<MaintenanceBudget>("maintenance_budgets")
+        .replace_one(
+            doc! { "hostel_id": &budget.hostel_id, "month": &budget.month, "campus_id": &budget.campus_id },
+            &budget,
+            mongodb::options::ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Maintenance budget saved",
+        "budget": budget
+    })))
+}
+
+async fn get_maintenance_budgets(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MaintenanceBudgetQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(hostel_id) = &query.hostel_id {
+        filter.insert("hostel_id", hostel_id);
+    }
+    if let Some(month) = &query.month {
+        filter.insert("month", month);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "month": 1, "hostel_id": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<MaintenanceBudget>("maintenance_budgets")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut budgets = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(budget) => budgets.push(budget),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(budgets))
+}
+
+// Spend per hostel and month against budget, for the facilities office
+async fn get_maintenance_cost_report(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MaintenanceCostQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    // Year to date unless a range is given
+    let today = Utc::now().date_naive();
+    let to = query.to.clone().unwrap_or_else(|| today.format("%Y-%m").to_string());
+    let from = query.from.clone().unwrap_or_else(|| format!("{}-01", to.get(..4).unwrap_or_default()));
+    let (Some((range_start, _)), Some((_, range_end))) = (month_bounds(&from), month_bounds(&to)) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from and to must be in YYYY-MM format"
+        })));
+    };
+    if range_start > range_end {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must not be after to"
+        })));
+    }
+
+    use futures::stream::StreamExt;
+
+    let mut hostel_filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(hostel_id) = &query.hostel_id {
+        hostel_filter.insert("hostel_id", hostel_id);
+    }
+
+    // Keyed by hostel id, or by name for requests raised against an unknown hostel
+    let mut hostels: HashMap<String, HostelMaintenanceCost> = HashMap::new();
+    let mut months: HashMap<(String, String), MonthlyMaintenanceCost> = HashMap::new();
+    let mut vendors: HashMap<String, (u32, f64)> = HashMap::new();
+
+    let mut spend_filter = hostel_filter.clone();
+    spend_filter.insert("actual_cost", doc! { "$ne": null });
+    spend_filter.insert("spent_at", doc! {
+        "$gte": range_start.to_string(),
+        "$lt": (range_end + chrono::Duration::days(1)).to_string()
+    });
+    let mut cursor = data.db
+        .collection::<MaintenanceRequest>("maintenance_requests")
+        .find(spend_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let request = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let (Some(actual), Some(spent_at)) = (request.actual_cost, request.spent_at) else {
+            continue;
+        };
+        let key = request.hostel_id.clone().unwrap_or_else(|| request.hostel_name.clone());
+        let month = spent_at.format("%Y-%m").to_string();
+        let entry = months.entry((key.clone(), month.clone())).or_default();
+        entry.month = month;
+        entry.requests += 1;
+        entry.actual += actual;
+        entry.estimated += request.estimated_cost.unwrap_or(0.0);
+        let hostel = hostels.entry(key).or_default();
+        hostel.hostel_id = request.hostel_id.clone();
+        hostel.hostel_name = request.hostel_name.clone();
+        if let Some(vendor) = &request.vendor {
+            let totals = vendors.entry(vendor.name.clone()).or_default();
+            totals.0 += 1;
+            totals.1 += actual;
+        }
+    }
+
+    let mut open_filter = hostel_filter.clone();
+    open_filter.insert("actual_cost", doc! { "$eq": null });
+    open_filter.insert("estimated_cost", doc! { "$ne": null });
+    open_filter.insert("status", doc! { "$ne": "resolved" });
+    let mut cursor = data.db
+        .collection::<MaintenanceRequest>("maintenance_requests")
+        .find(open_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let request = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let key = request.hostel_id.clone().unwrap_or_else(|| request.hostel_name.clone());
+        let hostel = hostels.entry(key).or_default();
+        hostel.hostel_id = request.hostel_id.clone();
+        hostel.hostel_name = request.hostel_name.clone();
+        hostel.open_estimates += request.estimated_cost.unwrap_or(0.0);
+    }
+
+    let mut budget_filter = hostel_filter;
+    budget_filter.insert("month", doc! { "$gte": &from, "$lte": &to });
+    let mut cursor = data.db
+        .collection::<MaintenanceBudget>("maintenance_budgets")
+        .find(budget_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let budget = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let entry = months.entry((budget.hostel_id.clone(), budget.month.clone())).or_default();
+        entry.month = budget.month.clone();
+        entry.budget = Some(budget.amount);
+        let hostel = hostels.entry(budget.hostel_id.clone()).or_default();
+        hostel.hostel_id = Some(budget.hostel_id);
+    }
+
+    // Budgets may exist for hostels with no requests yet
+    for (key, hostel) in hostels.iter_mut() {
+        if hostel.hostel_name.is_empty() {
+            if let Some(found) = find_hostel(&data.db, key, &claims.campus_id).await? {
+                hostel.hostel_name = found.name;
+            }
+        }
+    }
+
+    let round = |amount: f64| (amount * 100.0).round() / 100.0;
+    for ((key, _), mut month) in months {
+        month.actual = round(month.actual);
+        month.estimated = round(month.estimated);
+        month.variance = month.budget.map(|budget| round(budget - month.actual));
+        month.over_budget = month.budget.is_some_and(|budget| month.actual > budget);
+        let hostel = hostels.entry(key).or_default();
+        hostel.requests += month.requests;
+        hostel.actual += month.actual;
+        hostel.estimated += month.estimated;
+        hostel.budget += month.budget.unwrap_or(0.0);
+        hostel.months.push(month);
+    }
+
+    let mut report: Vec<HostelMaintenanceCost> = hostels.into_values().collect();
+    for hostel in report.iter_mut() {
+        hostel.actual = round(hostel.actual);
+        hostel.estimated = round(hostel.estimated);
+        hostel.budget = round(hostel.budget);
+        hostel.open_estimates = round(hostel.open_estimates);
+        hostel.variance = round(hostel.budget - hostel.actual);
+        hostel.over_budget = hostel.actual > hostel.budget;
+        hostel.months.sort_by(|a, b| a.month.cmp(&b.month));
+    }
+    report.sort_by(|a, b| a.hostel_name.cmp(&b.hostel_name));
+
+    let mut vendors: Vec<_> = vendors.into_iter().collect();
+    vendors.sort_by(|a, b| b.1 .1.total_cmp(&a.1 .1));
+    let by_vendor: Vec<_> = vendors
+        .into_iter()
+        .map(|(vendor, (requests, actual))| serde_json::json!({
+            "vendor": vendor,
+            "requests": requests,
+            "actual": round(actual)
+        }))
+        .collect();
+
+    let budget: f64 = report.iter().map(|h| h.budget).sum();
+    let actual: f64 = report.iter().map(|h| h.actual).sum();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "from": from,
+        "to": to,
+        "totals": {
+            "requests": report.iter().map(|h| h.requests).sum::<u32>(),
+            "estimated": round(report.iter().map(|h| h.estimated).sum()),
+            "actual": round(actual),
+            "budget": round(budget),
+            "variance": round(budget - actual),
+            "open_estimates": round(report.iter().map(|h| h.open_estimates).sum())
+        },
+        "hostels": report,
+        "by_vendor": by_vendor
+    })))
+}
+
+// ===== ROSTER =====
+
+async fn find_hostel_staff(
+    db: &mongodb::Database,
+    hostel_id: &str,
+    employee_id: &str,
+    campus_id: &str,
+) -> Result<Option<HostelStaff>, Error> {
+    db.collection::<HostelStaff>("hostel_staff")
+        .find_one(doc! { "hostel_id": hostel_id, "employee_id": employee_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn add_hostel_staff(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    staff_data: web::Json<HostelStaffRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !STAFF_ROLES.contains(&staff_data.role.as_str()) || staff_data.employee_id.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("employee_id is required and role must be one of: {}", STAFF_ROLES.join(", "))
+        })));
+    }
+
+    let hostel_id = path.into_inner();
+    if find_hostel(&data.db, &hostel_id, &claims.campus_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Hostel not found"
+        })));
+    }
+
+    let staff = HostelStaff {
+        id: None,
+        hostel_id,
+        employee_id: staff_data.employee_id.trim().to_string(),
+        role: staff_data.role.clone(),
+        campus_id: claims.campus_id.clone(),
+        assigned_by: claims.sub.clone(),
+        assigned_at: Utc::now(),
+    };
+
+    match data.db
+        .collection::<HostelStaff>("hostel_staff")
+        .insert_one(&staff, None)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Staff member added to hostel",
+            "staff": staff
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Employee is already on this hostel's staff"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn get_hostel_staff(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut cursor = data.db
+        .collection::<HostelStaff>("hostel_staff")
+        .find(doc! { "hostel_id": path.into_inner(), "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut staff = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(member) => staff.push(member),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(staff))
+}
+
+// Removes the staff member and their shifts from today on
+async fn remove_hostel_staff(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let (hostel_id, employee_id) = path.into_inner();
+    let result = data.db
+        .collection::<HostelStaff>("hostel_staff")
+        .delete_one(doc! { "hostel_id": &hostel_id, "employee_id": &employee_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.deleted_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Staff member not found for this hostel"
+        })));
+    }
+
+    let shifts = data.db
+        .collection::<DutyShift>("duty_shifts")
+        .delete_many(
+            doc! {
+                "hostel_id": &hostel_id,
+                "employee_id": &employee_id,
+                "campus_id": &claims.campus_id,
+                "date": { "$gte": Utc::now().date_naive().to_string() }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Staff member removed from hostel",
+        "shifts_removed": shifts.deleted_count
+    })))
+}
+
+// Puts a staff member on the same shift for every date in a range
+async fn add_roster_shifts(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    roster_data: web::Json<RosterRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !DUTY_SHIFTS.contains(&roster_data.shift.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("shift must be one of: {}", DUTY_SHIFTS.join(", "))
+        })));
+    }
+    let days = (roster_data.to_date - roster_data.from_date).num_days() + 1;
+    if !(1..=MAX_ROSTER_DAYS).contains(&days) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("to_date must be on or after from_date, and a range covers at most {} days", MAX_ROSTER_DAYS)
+        })));
+    }
+
+    let hostel_id = path.into_inner();
+    let Some(staff) = find_hostel_staff(&data.db, &hostel_id, &roster_data.employee_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Employee is not on this hostel's staff; add them first"
+        })));
+    };
+
+    let collection: Collection<DutyShift> = data.db.collection("duty_shifts");
+    let mut added = Vec::new();
+    let mut already_rostered = Vec::new();
+    for offset in 0..days {
+        let date = roster_data.from_date + chrono::Duration::days(offset);
+        let shift = DutyShift {
+            id: None,
+            hostel_id: hostel_id.clone(),
+            employee_id: staff.employee_id.clone(),
+            role: staff.role.clone(),
+            date,
+            shift: roster_data.shift.clone(),
+            campus_id: claims.campus_id.clone(),
+            created_by: claims.sub.clone(),
+            created_at: Utc::now(),
+        };
+        match collection.insert_one(shift, None).await {
+            Ok(_) => added.push(date),
+            Err(e) if is_duplicate_key(&e) => already_rostered.push(date),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "added": added,
+        "already_rostered": already_rostered
+    })))
+}
+
+async fn get_roster(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<RosterQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    // The coming week unless a range is given
+    let from = query.from.unwrap_or_else(|| Utc::now().date_naive());
+    let to = query.to.unwrap_or(from + chrono::Duration::days(6));
+    let mut filter = doc! {
+        "hostel_id": path.into_inner(),
+        "campus_id": &claims.campus_id,
+        "date": { "$gte": from.to_string(), "$lte": to.to_string() }
+    };
+    if let Some(employee_id) = &query.employee_id {
+        filter.insert("employee_id", employee_id);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "date": 1, "shift": 1, "employee_id": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<DutyShift>("duty_shifts")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut shifts = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(shift) => shifts.push(shift),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(shifts))
+}
+
+async fn delete_roster_shift(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let shift_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let result = data.db
+        .collection::<DutyShift>("duty_shifts")
+        .delete_one(doc! { "_id": shift_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.deleted_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Shift not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Shift removed"
+    })))
+}
+
+// Who is on duty tonight by default; any staff member or resident may ask
+async fn get_on_duty(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<OnDutyQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let shift = query.shift.clone().unwrap_or_else(|| "night".to_string());
+    if !DUTY_SHIFTS.contains(&shift.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("shift must be one of: {}", DUTY_SHIFTS.join(", "))
+        })));
+    }
+    let date = query.date.unwrap_or_else(|| Utc::now().date_naive());
+
+    let mut filter = doc! { "campus_id": &claims.campus_id, "date": date.to_string(), "shift": &shift };
+    if let Some(hostel_id) = &query.hostel_id {
+        filter.insert("hostel_id", hostel_id);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "hostel_id": 1, "role": -1, "employee_id": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<DutyShift>("duty_shifts")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut on_duty = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(duty) => on_duty.push(serde_json::json!({
+                "hostel_id": duty.hostel_id,
+                "employee_id": duty.employee_id,
+                "role": duty.role,
+                "shift_id": duty.id.map(|id| id.to_hex())
+            })),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "date": date,
+        "shift": shift,
+        "on_duty": on_duty
+    })))
+}
+
+// ===== MESS =====
+
+fn week_start_of(date: NaiveDate) -> NaiveDate {
+    date - chrono::Duration::days(date.weekday().num_days_from_monday() as i64)
+}
+
+// First and last day of a YYYY-MM month
+fn month_bounds(month: &str) -> Option<(NaiveDate, NaiveDate)> {
+    let first = NaiveDate::parse_from_str(&format!("{}-01", month), "%Y-%m-%d").ok()?;
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd_opt(first.year() + 1, 1, 1)?
+    } else {
+        NaiveDate::from_ymd_opt(first.year(), first.month() + 1, 1)?
+    };
+    Some((first, next.pred_opt()?))
+}
+
+// Hostel of the student's active allocation, via the allocated room
+async fn student_hostel_id(
+    db: &mongodb::Database,
+    student_id: &str,
+    campus_id: &str,
+) -> Result<Option<String>, Error> {
+    let allocation = db
+        .collection::<RoomAllocation>("room_allocations")
+        .find_one(doc! { "student_id": student_id, "status": "active", "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let Some(allocation) = allocation else { return Ok(None) };
+    let Ok(room_obj_id) = ObjectId::parse_str(&allocation.room_id) else { return Ok(None) };
+
+    let room = db
+        .collection::<Room>("rooms")
+        .find_one(doc! { "_id": room_obj_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(room.and_then(|r| r.hostel_id))
+}
+
+async fn find_active_plan(
+    db: &mongodb::Database,
+    student_id: &str,
+    campus_id: &str,
+) -> Result<Option<MealPlan>, Error> {
+    db.collection::<MealPlan>("meal_plans")
+        .find_one(doc! { "student_id": student_id, "status": "active", "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn upsert_mess_menu(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    menu_data: web::Json<MessMenuRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    if menu_data.week_start.weekday() != chrono::Weekday::Mon {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "week_start must be a Monday"
+        })));
+    }
+    let mut seen = Vec::new();
+    for day in &menu_data.days {
+        if !WEEKDAYS.contains(&day.day.as_str()) || seen.contains(&day.day) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Invalid or repeated day '{}'", day.day)
+            })));
+        }
+        seen.push(day.day.clone());
+    }
+
+    if find_hostel(&data.db, &menu_data.hostel_id, &claims.campus_id).await?.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Hostel not found"
+        })));
+    }
+
+    let menu = MessMenu {
+        id: None,
+        hostel_id: menu_data.hostel_id.clone(),
+        week_start: menu_data.week_start,
+        days: menu_data.days.clone(),
+        campus_id: claims.campus_id.clone(),
+        updated_by: claims.sub.clone(),
+        updated_at: Utc::now(),
+    };
+
+    let collection: Collection<MessMenu> = data.db.collection("mess_menus");
+    collection
+        .replace_one(
+            doc! {
+                "hostel_id": &menu.hostel_id,
+                "week_start": menu.week_start.to_string(),
+                "campus_id": &claims.campus_id
+            },
+            &menu,
+            mongodb::options::ReplaceOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Menu saved successfully"
+    })))
+}
+
+async fn get_mess_menu(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MessMenuQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    // Defaults to the current week
+    let week_start = week_start_of(query.week_start.unwrap_or_else(|| Utc::now().date_naive()));
+
+    let collection: Collection<MessMenu> = data.db.collection("mess_menus");
+    let menu = collection
+        .find_one(
+            doc! {
+                "hostel_id": &query.hostel_id,
+                "week_start": week_start.to_string(),
+                "campus_id": &claims.campus_id
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match menu {
+        Some(menu) => Ok(HttpResponse::Ok().json(menu)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No menu published for this week"
+        }))),
+    }
+}
+
+async fn subscribe_meal_plan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    plan_data: web::Json<MealPlanRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role == "student" && claims.sub != plan_data.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only manage your own meal plan"
+        })));
+    }
+    if claims.role != "student" && !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !DIETS.contains(&plan_data.diet.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("diet must be one of: {}", DIETS.join(", "))
+        })));
+    }
+
+    let Some(hostel_id) = student_hostel_id(&data.db, &plan_data.student_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Student has no active room allocation in a registered hostel"
+        })));
+    };
+
+    if let Some(existing) = find_active_plan(&data.db, &plan_data.student_id, &claims.campus_id).await? {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student already has an active meal plan",
+            "plan": existing
+        })));
+    }
+
+    let plan = MealPlan {
+        id: None,
+        student_id: plan_data.student_id.clone(),
+        hostel_id,
+        diet: plan_data.diet.clone(),
+        opt_out_weeks: Vec::new(),
+        status: "active".to_string(),
+        started_on: Utc::now().date_naive(),
+        ended_on: None,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<MealPlan> = data.db.collection("meal_plans");
+    match collection.insert_one(&plan, None).await {
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Subscribed to meal plan",
+            "plan": plan
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student already has an active meal plan"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn get_meal_plan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own meal plan"
+        })));
+    }
+
+    match find_active_plan(&data.db, &student_id, &claims.campus_id).await? {
+        Some(plan) => Ok(HttpResponse::Ok().json(plan)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No active meal plan"
+        }))),
+    }
+}
+
+async fn update_meal_plan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    update: web::Json<MealPlanUpdate>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only manage your own meal plan"
+        })));
+    }
+    if claims.role != "student" && !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !DIETS.contains(&update.diet.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("diet must be one of: {}", DIETS.join(", "))
+        })));
+    }
+
+    let collection: Collection<MealPlan> = data.db.collection("meal_plans");
+    let result = collection
+        .update_one(
+            doc! { "student_id": &student_id, "status": "active", "campus_id": &claims.campus_id },
+            doc! { "$set": { "diet": &update.diet } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No active meal plan"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Meal plan updated"
+    })))
+}
+
+async fn cancel_meal_plan(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only manage your own meal plan"
+        })));
+    }
+    if claims.role != "student" && !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let collection: Collection<MealPlan> = data.db.collection("meal_plans");
+    let result = collection
+        .update_one(
+            doc! { "student_id": &student_id, "status": "active", "campus_id": &claims.campus_id },
+            doc! { "$set": { "status": "cancelled", "ended_on": Utc::now().date_naive().to_string() } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No active meal plan"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Meal plan cancelled"
+    })))
+}
+
+// Students may only opt out of weeks that have not started; wardens may
+// also change past weeks
+async fn set_meal_opt_out(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    opt_out: web::Json<OptOutRequest>,
+    opting_out: bool,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only manage your own meal plan"
+        })));
+    }
+    if claims.role != "student" && !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if opt_out.week_start.weekday() != chrono::Weekday::Mon {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "week_start must be a Monday"
+        })));
+    }
+    if claims.role == "student" && opt_out.week_start <= week_start_of(Utc::now().date_naive()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Only future weeks can be changed"
+        })));
+    }
+
+    let week = opt_out.week_start.to_string();
+    let update = if opting_out {
+        doc! { "$addToSet": { "opt_out_weeks": &week } }
+    } else {
+        doc! { "$pull": { "opt_out_weeks": &week } }
+    };
+
+    let collection: Collection<MealPlan> = data.db.collection("meal_plans");
+    let result = collection
+        .update_one(
+            doc! { "student_id": &student_id, "status": "active", "campus_id": &claims.campus_id },
+            update,
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No active meal plan"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": if opting_out { "Opted out of week" } else { "Opt-out removed" },
+        "week_start": week
+    })))
+}
+
+async fn add_meal_opt_out(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    opt_out: web::Json<OptOutRequest>,
+) -> Result<HttpResponse, Error> {
+    set_meal_opt_out(data, req, path, opt_out, true).await
+}
+
+async fn remove_meal_opt_out(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    opt_out: web::Json<OptOutRequest>,
+) -> Result<HttpResponse, Error> {
+    set_meal_opt_out(data, req, path, opt_out, false).await
+}
+
+async fn mark_meal_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    attendance_data: web::Json<MealAttendanceRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !MEALS.contains(&attendance_data.meal.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("meal must be one of: {}", MEALS.join(", "))
+        })));
+    }
+
+    let collection: Collection<MealAttendance> = data.db.collection("meal_attendance");
+    let week_start = week_start_of(attendance_data.date);
+    let mut marked = Vec::new();
+    let mut already_marked = Vec::new();
+    let mut skipped = Vec::new();
+
+    for student_id in &attendance_data.student_ids {
+        // Only subscribers of this hostel who have not opted out that week
+        let eligible = match find_active_plan(&data.db, student_id, &claims.campus_id).await? {
+            Some(plan) => plan.hostel_id == attendance_data.hostel_id
+                && plan.started_on <= attendance_data.date
+                && !plan.opt_out_weeks.contains(&week_start),
+            None => false,
+        };
+        if !eligible {
+            skipped.push(student_id.clone());
+            continue;
+        }
+
+        let entry = MealAttendance {
+            id: None,
+            student_id: student_id.clone(),
+            hostel_id: attendance_data.hostel_id.clone(),
+            date: attendance_data.date,
+            meal: attendance_data.meal.clone(),
+            marked_by: claims.sub.clone(),
+            campus_id: claims.campus_id.clone(),
+            marked_at: Utc::now(),
+        };
+        match collection.insert_one(entry, None).await {
+            Ok(_) => marked.push(student_id.clone()),
+            Err(e) if is_duplicate_key(&e) => already_marked.push(student_id.clone()),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "marked": marked,
+        "already_marked": already_marked,
+        "skipped": skipped
+    })))
+}
+
+async fn get_meal_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MealAttendanceQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut filter = doc! {
+        "hostel_id": &query.hostel_id,
+        "date": query.date.to_string(),
+        "campus_id": &claims.campus_id
+    };
+    if let Some(meal) = &query.meal {
+        filter.insert("meal", meal);
+    }
+
+    let collection: Collection<MealAttendance> = data.db.collection("meal_attendance");
+    let mut cursor = collection
+        .find(filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut entries = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": entries.len(),
+        "entries": entries
+    })))
+}
+
+// Bills every day the plan was active in the month except opted-out weeks,
+// at the plan's diet rate. Bills already raised in finance are left alone.
+async fn generate_mess_bills(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    bill_data: web::Json<MessBillRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let Some((month_start, month_end)) = month_bounds(&bill_data.month) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "month must be in YYYY-MM format"
+        })));
+    };
+
+    let mut filter = doc! {
+        "campus_id": &claims.campus_id,
+        "started_on": { "$lte": month_end.to_string() },
+        "$or": [
+            { "ended_on": null },
+            { "ended_on": { "$gte": month_start.to_string() } }
+        ]
+    };
+    if let Some(hostel_id) = &bill_data.hostel_id {
+        filter.insert("hostel_id", hostel_id);
+    }
+
+    let plan_collection: Collection<MealPlan> = data.db.collection("meal_plans");
+    let attendance_collection: Collection<MealAttendance> = data.db.collection("meal_attendance");
+    let bill_collection: Collection<MessBill> = data.db.collection("mess_bills");
+
+    let mut cursor = plan_collection
+        .find(filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut plans = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(plan) => plans.push(plan),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let mut generated = 0;
+    let mut already_billed = 0;
+    let mut total_amount = 0.0;
+
+    for plan in plans {
+        let bill_filter = doc! {
+            "student_id": &plan.student_id,
+            "month": &bill_data.month,
+            "campus_id": &claims.campus_id
+        };
+        let existing = bill_collection
+            .find_one(bill_filter.clone(), None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if existing.is_some_and(|bill| bill.fee_id.is_some()) {
+            already_billed += 1;
+            continue;
+        }
+
+        let from = plan.started_on.max(month_start);
+        let to = plan.ended_on.map_or(month_end, |ended| ended.min(month_end));
+        let mut billable_days = 0;
+        let mut opted_out_days = 0;
+        let mut day = from;
+        while day <= to {
+            if plan.opt_out_weeks.contains(&week_start_of(day)) {
+                opted_out_days += 1;
+            } else {
+                billable_days += 1;
+            }
+            day = match day.succ_opt() {
+                Some(next) => next,
+                None => break,
+            };
+        }
+
+        let meals_attended = attendance_collection
+            .count_documents(
+                doc! {
+                    "student_id": &plan.student_id,
+                    "campus_id": &claims.campus_id,
+                    "date": { "$gte": month_start.to_string(), "$lte": month_end.to_string() }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let daily_rate = data.mess_rates.daily_rate(&plan.diet);
+        let amount = (billable_days as f64 * daily_rate * 100.0).round() / 100.0;
+        let bill = MessBill {
+            id: None,
+            student_id: plan.student_id.clone(),
+            hostel_id: plan.hostel_id.clone(),
+            month: bill_data.month.clone(),
+            diet: plan.diet.clone(),
+            billable_days,
+            opted_out_days,
+            meals_attended,
+            daily_rate,
+            amount,
+            fee_id: None,
+            fee_status: None,
+            campus_id: claims.campus_id.clone(),
+            generated_at: Utc::now(),
+        };
+        bill_collection
+            .replace_one(
+                bill_filter,
+                &bill,
+                mongodb::options::ReplaceOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        generated += 1;
+        total_amount += amount;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "month": bill_data.month,
+        "generated": generated,
+        "already_billed": already_billed,
+        "total_amount": (total_amount * 100.0_f64).round() / 100.0
+    })))
+}
+
+async fn get_mess_bills(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MessBillQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(month) = &query.month {
+        filter.insert("month", month);
+    }
+
+    let collection: Collection<MessBill> = data.db.collection("mess_bills");
+    let mut cursor = collection
+        .find(filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut bills = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(bill) => bills.push(bill),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(bills))
+}
+
+// ===== MESS FEEDBACK =====
+
+async fn submit_meal_feedback(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    feedback_data: web::Json<MealFeedbackRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "student" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Only residents can rate meals"
+        })));
+    }
+    if !MEALS.contains(&feedback_data.meal.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("meal must be one of: {}", MEALS.join(", "))
+        })));
+    }
+    if !(1..=5).contains(&feedback_data.rating) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "rating must be between 1 and 5"
+        })));
+    }
+    let today = Utc::now().date_naive();
+    let date = feedback_data.date.unwrap_or(today);
+    if date > today || date < today - chrono::Duration::days(FEEDBACK_WINDOW_DAYS) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Feedback can only be given for today's or yesterday's meals"
+        })));
+    }
+
+    let Some(plan) = find_active_plan(&data.db, &claims.sub, &claims.campus_id).await? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Only students with an active meal plan can rate meals"
+        })));
+    };
+
+    let menu = data.db
+        .collection::<MessMenu>("mess_menus")
+        .find_one(
+            doc! { "hostel_id": &plan.hostel_id, "week_start": week_start_of(date).to_string(), "campus_id": &claims.campus_id },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let weekday = WEEKDAYS[date.weekday().num_days_from_monday() as usize];
+    let menu_items = menu
+        .and_then(|menu| menu.days.into_iter().find(|d| d.day == weekday))
+        .map(|day| match feedback_data.meal.as_str() {
+            "breakfast" => day.breakfast,
+            "lunch" => day.lunch,
+            "snacks" => day.snacks,
+            _ => day.dinner,
+        })
+        .unwrap_or_default();
+
+    let feedback = MealFeedback {
+        id: None,
+        student_id: claims.sub.clone(),
+        hostel_id: plan.hostel_id,
+        date,
+        meal: feedback_data.meal.clone(),
+        rating: feedback_data.rating,
+        comment: feedback_data.comment.as_ref().map(|c| c.trim().to_string()).filter(|c| !c.is_empty()),
+        menu_items,
+        campus_id: claims.campus_id,
+        submitted_at: Utc::now(),
+    };
+
+    match data.db
+        .collection::<MealFeedback>("meal_feedback")
+        .insert_one(&feedback, None)
+        .await
+    {
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Thanks for your feedback",
+            "feedback": feedback
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "You have already rated this meal"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn find_meal_feedback(
+    db: &mongodb::Database,
+    filter: mongodb::bson::Document,
+) -> Result<Vec<MealFeedback>, Error> {
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "submitted_at": -1 })
+        .build();
+    let mut cursor = db
+        .collection::<MealFeedback>("meal_feedback")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut feedback = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => feedback.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+    Ok(feedback)
+}
+
+fn average_rating<'a>(ratings: impl Iterator<Item = &'a MealFeedback>) -> (usize, f64) {
+    let (count, total) = ratings.fold((0, 0), |(n, sum), f| (n + 1, sum + f.rating));
+    let average = if count > 0 { (total as f64 / count as f64 * 100.0).round() / 100.0 } else { 0.0 };
+    (count, average)
+}
+
+// Ratings per meal and per dish for the mess committee
+async fn get_meal_feedback_summary(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MealFeedbackSummaryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    // Last 30 days unless a range is given
+    let to = query.to.unwrap_or_else(|| Utc::now().date_naive());
+    let from = query.from.unwrap_or(to - chrono::Duration::days(29));
+    if from > to {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must not be after to"
+        })));
+    }
+
+    let feedback = find_meal_feedback(&data.db, doc! {
+        "hostel_id": &query.hostel_id,
+        "campus_id": &claims.campus_id,
+        "date": { "$gte": from.to_string(), "$lte": to.to_string() }
+    }).await?;
+
+    let by_meal: Vec<_> = MEALS
+        .iter()
+        .map(|meal| {
+            let of_meal: Vec<&MealFeedback> = feedback.iter().filter(|f| f.meal == *meal).collect();
+            let (count, average) = average_rating(of_meal.iter().copied());
+            let distribution: Vec<usize> = (1..=5).map(|r| of_meal.iter().filter(|f| f.rating == r).count()).collect();
+            serde_json::json!({
+                "meal": meal,
+                "responses": count,
+                "average_rating": average,
+                "distribution": distribution
+            })
+        })
+        .collect();
+
+    let mut dishes: HashMap<&str, Vec<&MealFeedback>> = HashMap::new();
+    for entry in &feedback {
+        for item in &entry.menu_items {
+            dishes.entry(item.as_str()).or_default().push(entry);
+        }
+    }
+    let mut by_dish: Vec<(&str, usize, f64)> = dishes
+        .into_iter()
+        .map(|(dish, entries)| {
+            let (count, average) = average_rating(entries.into_iter());
+            (dish, count, average)
+        })
+        .collect();
+    // Lowest rated first: these are what the committee acts on
+    by_dish.sort_by(|a, b| a.2.total_cmp(&b.2).then_with(|| a.0.cmp(b.0)));
+
+    let (responses, average) = average_rating(feedback.iter());
+    let recent_comments: Vec<_> = feedback
+        .iter()
+        .filter(|f| f.comment.is_some())
+        .take(20)
+        .map(|f| serde_json::json!({
+            "date": f.date,
+            "meal": f.meal,
+            "rating": f.rating,
+            "comment": f.comment
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "hostel_id": query.hostel_id,
+        "from": from,
+        "to": to,
+        "responses": responses,
+        "average_rating": average,
+        "by_meal": by_meal,
+        "by_dish": by_dish
+            .into_iter()
+            .map(|(dish, count, average)| serde_json::json!({
+                "dish": dish,
+                "responses": count,
+                "average_rating": average
+            }))
+            .collect::<Vec<_>>(),
+        "recent_comments": recent_comments
+    })))
+}
+
+// Weekly average rating, oldest week first
+async fn get_meal_feedback_trend(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MealFeedbackTrendQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if let Some(meal) = &query.meal {
+        if !MEALS.contains(&meal.as_str()) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("meal must be one of: {}", MEALS.join(", "))
+            })));
+        }
+    }
+
+    let weeks = query.weeks.unwrap_or(8).clamp(1, 52);
+    let current_week = week_start_of(Utc::now().date_naive());
+    let first_week = current_week - chrono::Duration::weeks(weeks as i64 - 1);
+
+    let mut filter = doc! {
+        "hostel_id": &query.hostel_id,
+        "campus_id": &claims.campus_id,
+        "date": { "$gte": first_week.to_string() }
+    };
+    if let Some(meal) = &query.meal {
+        filter.insert("meal", meal);
+    }
+    let feedback = find_meal_feedback(&data.db, filter).await?;
+
+    let trend: Vec<_> = (0..weeks as i64)
+        .map(|offset| {
+            let week_start = first_week + chrono::Duration::weeks(offset);
+            let (responses, average) = average_rating(feedback.iter().filter(|f| week_start_of(f.date) == week_start));
+            serde_json::json!({
+                "week_start": week_start,
+                "responses": responses,
+                "average_rating": if responses > 0 { Some(average) } else { None }
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "hostel_id": query.hostel_id,
+        "meal": query.meal,
+        "weeks": trend
+    })))
+}
+
+// ===== ROOM CHANGES =====
+
+async fn find_room_change(
+    db: &mongodb::Database,
+    request_id: &str,
+    campus_id: &str,
+) -> Result<Option<RoomChangeRequest>, Error> {
+    let request_obj_id = ObjectId::parse_str(request_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    db.collection::<RoomChangeRequest>("room_change_requests")
+        .find_one(doc! { "_id": request_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn find_room(db: &mongodb::Database, room_id: &str, campus_id: &str) -> Result<Option<Room>, Error> {
+    let room_obj_id = ObjectId::parse_str(room_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    db.collection::<Room>("rooms")
+        .find_one(doc! { "_id": room_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn request_room_change(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    change_data: web::Json<RoomChangeRequestData>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role == "student" && claims.sub != change_data.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only request a room change for yourself"
+        })));
+    }
+    if change_data.reason.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "reason is required"
+        })));
+    }
+
+    let Some(current) = find_active_allocation(&data.db, &change_data.student_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Student has no active room allocation"
+        })));
+    };
+    if let Some(room_id) = &change_data.preferred_room_id {
+        if *room_id == current.room_id {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Student is already in the preferred room"
+            })));
+        }
+        if find_room(&data.db, room_id, &claims.campus_id).await?.is_none() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Preferred room not found"
+            })));
+        }
+    }
+    if let Some(hostel_id) = &change_data.preferred_hostel_id {
+        if find_hostel(&data.db, hostel_id, &claims.campus_id).await?.is_none() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Preferred hostel not found"
+            })));
+        }
+    }
+
+    let change = RoomChangeRequest {
+        id: None,
+        student_id: change_data.student_id.clone(),
+        current_allocation_id: current.id.map(|id| id.to_hex()).unwrap_or_default(),
+        reason: change_data.reason.trim().to_string(),
+        preferred_hostel_id: change_data.preferred_hostel_id.clone(),
+        preferred_room_id: change_data.preferred_room_id.clone(),
+        status: "pending".to_string(),
+        requested_at: Utc::now(),
+        reviewed_by: None,
+        reviewed_at: None,
+        review_note: None,
+        new_allocation_id: None,
+        campus_id: claims.campus_id,
+    };
+
+    match data.db
+        .collection::<RoomChangeRequest>("room_change_requests")
+        .insert_one(change, None)
+        .await
+    {
+        Ok(result) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Room change requested",
+            "room_change_id": result.inserted_id.as_object_id().map(|id| id.to_hex())
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student already has a pending room change request"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn get_room_changes(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<RoomChangeQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(status) = &query.status {
+        filter.insert("status", status);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "requested_at": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<RoomChangeRequest>("room_change_requests")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut changes = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(change) => changes.push(change),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(changes))
+}
+
+// Approval moves the student straight away. The target is the room the
+// warden names, else the student's preferred room, else the first room
+// with a free bed in the preferred hostel.
+async fn approve_room_change(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    approval: web::Json<RoomChangeApproval>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let Some(change) = find_room_change(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Room change request not found"
+        })));
+    };
+    if change.status != "pending" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A {} room change request cannot be approved", change.status)
+        })));
+    }
+
+    let Some(current) = find_active_allocation(&data.db, &change.student_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student no longer has an active room allocation"
+        })));
+    };
+
+    let target = match approval.room_id.as_ref().or(change.preferred_room_id.as_ref()) {
+        Some(room_id) => find_room(&data.db, room_id, &claims.campus_id).await?,
+        None => match &change.preferred_hostel_id {
+            Some(hostel_id) => data.db
+                .collection::<Room>("rooms")
+                .find_one(
+                    doc! {
+                        "hostel_id": hostel_id,
+                        "campus_id": &claims.campus_id,
+                        "_id": { "$ne": ObjectId::parse_str(&current.room_id).ok() },
+                        "out_of_service": { "$ne": true },
+                        "$expr": { "$lt": ["$occupied", "$capacity"] }
+                    },
+                    mongodb::options::FindOneOptions::builder().sort(doc! { "room_number": 1 }).build(),
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?,
+            None => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "No room given; pass room_id to choose one"
+                })));
+            }
+        },
+    };
+    let Some(room) = target else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "No room with a free bed was found for this request"
+        })));
+    };
+    if room.id.map(|id| id.to_hex()).as_deref() == Some(current.room_id.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Student is already in this room"
+        })));
+    }
+
+    // Claim the request first so two approvals cannot both move the student
+    let change_collection: Collection<RoomChangeRequest> = data.db.collection("room_change_requests");
+    let claimed = change_collection
+        .update_one(
+            doc! { "_id": change.id, "status": "pending" },
+            doc! { "$set": {
+                "status": "approved",
+                "reviewed_by": &claims.sub,
+                "reviewed_at": Utc::now().to_rfc3339(),
+                "review_note": &approval.note
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if claimed.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Room change request was already reviewed"
+        })));
+    }
+
+    let transferred = transfer_allocation(&data, &current, &room).await;
+    let new_allocation = match transferred {
+        Ok(Some(allocation)) => allocation,
+        Ok(None) | Err(_) => {
+            change_collection
+                .update_one(
+                    doc! { "_id": change.id },
+                    doc! { "$set": { "status": "pending", "reviewed_by": null, "reviewed_at": null, "review_note": null } },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return match transferred {
+                Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+                _ => Ok(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "Room is full or unavailable; the request is still pending"
+                }))),
+            };
+        }
+    };
+
+    let new_allocation_id = new_allocation.id.map(|id| id.to_hex());
+    change_collection
+        .update_one(
+            doc! { "_id": change.id },
+            doc! { "$set": { "new_allocation_id": &new_allocation_id } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room change approved and student transferred",
+        "allocation": new_allocation
+    })))
+}
+
+async fn reject_room_change(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    review: web::Json<GatePassReview>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let request_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let result = data.db
+        .collection::<RoomChangeRequest>("room_change_requests")
+        .update_one(
+            doc! { "_id": request_obj_id, "status": "pending", "campus_id": &claims.campus_id },
+            doc! { "$set": {
+                "status": "rejected",
+                "reviewed_by": &claims.sub,
+                "reviewed_at": Utc::now().to_rfc3339(),
+                "review_note": &review.note
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Pending room change request not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room change rejected"
+    })))
+}
+
+async fn cancel_room_change(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(change) = find_room_change(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Room change request not found"
+        })));
+    };
+    if claims.role == "student" && claims.sub != change.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only cancel your own room change request"
+        })));
+    }
+
+    let result = data.db
+        .collection::<RoomChangeRequest>("room_change_requests")
+        .update_one(
+            doc! { "_id": change.id, "status": "pending" },
+            doc! { "$set": { "status": "cancelled" } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A {} room change request cannot be cancelled", change.status)
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Room change request cancelled"
+    })))
+}
+
+// ===== GATE =====
+
+// Security staff record movements and validate passes at the gate
+fn is_gate_staff(claims: &Claims) -> bool {
+    claims.role == "security" || is_hostel_admin(claims)
+}
+
+async fn find_gate_pass(
+    db: &mongodb::Database,
+    pass_id: &str,
+    campus_id: &str,
+) -> Result<Option<GatePass>, Error> {
+    let pass_obj_id = ObjectId::parse_str(pass_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    db.collection::<GatePass>("gate_passes")
+        .find_one(doc! { "_id": pass_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Why a pass cannot be used to leave today, if it cannot
+fn gate_pass_problem(pass: &GatePass, today: NaiveDate) -> Option<&'static str> {
+    if pass.status != "approved" {
+        Some("Gate pass is not approved")
+    } else if today < pass.from_date {
+        Some("Gate pass is not valid yet")
+    } else if today > pass.to_date {
+        Some("Gate pass has expired")
+    } else {
+        None
+    }
+}
+
+async fn request_gate_pass(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    pass_data: web::Json<GatePassRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role == "student" && claims.sub != pass_data.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only request your own gate pass"
+        })));
+    }
+    if pass_data.to_date < pass_data.from_date || pass_data.from_date < Utc::now().date_naive() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Leave dates must not be in the past and to_date must not precede from_date"
+        })));
+    }
+    if pass_data.reason.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "reason is required"
+        })));
+    }
+
+    let overnight = pass_data.overnight || pass_data.to_date > pass_data.from_date;
+    if overnight && pass_data.destination.as_deref().is_none_or(|d| d.trim().is_empty()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "destination is required for overnight leave"
+        })));
+    }
+
+    let Some(hostel_id) = student_hostel_id(&data.db, &pass_data.student_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Student has no active room allocation in a registered hostel"
+        })));
+    };
+
+    let pass = GatePass {
+        id: None,
+        student_id: pass_data.student_id.clone(),
+        hostel_id,
+        from_date: pass_data.from_date,
+        to_date: pass_data.to_date,
+        reason: pass_data.reason.trim().to_string(),
+        destination: pass_data.destination.clone(),
+        status: "pending".to_string(),
+        requested_at: Utc::now(),
+        reviewed_by: None,
+        reviewed_at: None,
+        review_note: None,
+        checked_out_at: None,
+        checked_in_at: None,
+        campus_id: claims.campus_id,
+        overnight,
+    };
+
+    let result = data.db
+        .collection::<GatePass>("gate_passes")
+        .insert_one(pass, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Gate pass requested",
+        "gate_pass_id": result.inserted_id.as_object_id().map(|id| id.to_hex())
+    })))
+}
+
+async fn get_gate_passes(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<GatePassQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(status) = &query.status {
+        filter.insert("status", status);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "requested_at": -1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<GatePass>("gate_passes")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut passes = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(pass) => passes.push(pass),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(passes))
+}
+
+// Wardens decide pending passes; the decision is final
+async fn review_gate_pass(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    review: web::Json<GatePassReview>,
+    approve: bool,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let pass_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let status = if approve { "approved" } else { "rejected" };
+
+    let reviewed = data.db
+        .collection::<GatePass>("gate_passes")
+        .find_one_and_update(
+            doc! { "_id": pass_obj_id, "status": "pending", "campus_id": &claims.campus_id },
+            doc! { "$set": {
+                "status": status,
+                "reviewed_by": &claims.sub,
+                "reviewed_at": Utc::now().to_rfc3339(),
+                "review_note": &review.note
+            } },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let Some(pass) = reviewed else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Pending gate pass not found"
+        })));
+    };
+
+    // The notification service looks up the registered guardian and tells them
+    if approve && pass.overnight {
+        publish_event(&data.db, &data.events, "out_pass.approved", &pass.campus_id, serde_json::json!({
+            "gate_pass_id": pass.id.map(|id| id.to_hex()),
+            "student_id": &pass.student_id,
+            "hostel_id": &pass.hostel_id,
+            "from_date": pass.from_date,
+            "to_date": pass.to_date,
+            "destination": &pass.destination,
+            "reason": &pass.reason,
+            "approved_by": &claims.sub,
+            "note": &review.note
+        }))
+        .await;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Gate pass {}", status)
+    })))
+}
+
+async fn approve_gate_pass(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    review: web::Json<GatePassReview>,
+) -> Result<HttpResponse, Error> {
+    review_gate_pass(data, req, path, review, true).await
+}
+
+async fn reject_gate_pass(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    review: web::Json<GatePassReview>,
+) -> Result<HttpResponse, Error> {
+    review_gate_pass(data, req, path, review, false).await
+}
+
+async fn cancel_gate_pass(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let pass_id = path.into_inner();
+    let Some(pass) = find_gate_pass(&data.db, &pass_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Gate pass not found"
+        })));
+    };
+    if claims.role == "student" && claims.sub != pass.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only cancel your own gate pass"
+        })));
+    }
+
+    // Once the student has left, the pass is closed by checking back in
+    let result = data.db
+        .collection::<GatePass>("gate_passes")
+        .update_one(
+            doc! { "_id": pass.id, "status": { "$in": ["pending", "approved"] } },
+            doc! { "$set": { "status": "cancelled" } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A {} gate pass cannot be cancelled", pass.status)
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Gate pass cancelled"
+    })))
+}
+
+async fn validate_gate_pass(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_gate_staff(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Security, warden or admin role required"
+        })));
+    }
+
+    let Some(pass) = find_gate_pass(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "valid": false,
+            "error": "Gate pass not found"
+        })));
+    };
+
+    let problem = gate_pass_problem(&pass, Utc::now().date_naive());
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "valid": problem.is_none(),
+        "reason": problem,
+        "gate_pass": pass
+    })))
+}
+
+async fn record_movement(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    movement_data: web::Json<MovementRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_gate_staff(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Security, warden or admin role required"
+        })));
+    }
+    if movement_data.direction != "out" && movement_data.direction != "in" {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "direction must be 'out' or 'in'"
+        })));
+    }
+
+    let Some(hostel_id) = student_hostel_id(&data.db, &movement_data.student_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Student is not a resident of a registered hostel"
+        })));
+    };
+
+    let log_collection: Collection<MovementLog> = data.db.collection("movement_logs");
+    let last = log_collection
+        .find_one(
+            doc! { "student_id": &movement_data.student_id, "campus_id": &claims.campus_id },
+            mongodb::options::FindOneOptions::builder().sort(doc! { "recorded_at": -1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Residents are inside until their first recorded exit
+    let currently_out = last.as_ref().is_some_and(|l| l.direction == "out");
+    if currently_out == (movement_data.direction == "out") {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": if currently_out { "Student is already outside" } else { "Student is already inside" }
+        })));
+    }
+
+    let pass_collection: Collection<GatePass> = data.db.collection("gate_passes");
+    let now = Utc::now();
+    let gate_pass_id = if movement_data.direction == "out" {
+        // Leaving on a pass consumes it; day outings need no pass
+        if let Some(pass_id) = &movement_data.gate_pass_id {
+            let Some(pass) = find_gate_pass(&data.db, pass_id, &claims.campus_id).await? else {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Gate pass not found"
+                })));
+            };
+            if pass.student_id != movement_data.student_id {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "Gate pass belongs to another student"
+                })));
+            }
+            if let Some(problem) = gate_pass_problem(&pass, now.date_naive()) {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": problem })));
+            }
+            pass_collection
+                .update_one(
+                    doc! { "_id": pass.id },
+                    doc! { "$set": { "status": "out", "checked_out_at": now.to_rfc3339() } },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+        movement_data.gate_pass_id.clone()
+    } else {
+        // Coming back closes the pass the student left on
+        let pass_id = last.and_then(|l| l.gate_pass_id);
+        if let Some(pass_id) = &pass_id {
+            if let Ok(pass_obj_id) = ObjectId::parse_str(pass_id) {
+                pass_collection
+                    .update_one(
+                        doc! { "_id": pass_obj_id, "status": "out" },
+                        doc! { "$set": { "status": "returned", "checked_in_at": now.to_rfc3339() } },
+                        None,
+                    )
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+        }
+        pass_id
+    };
+
+    let entry = MovementLog {
+        id: None,
+        student_id: movement_data.student_id.clone(),
+        hostel_id,
+        direction: movement_data.direction.clone(),
+        gate_pass_id,
+        recorded_by: claims.sub.clone(),
+        recorded_at: now,
+        campus_id: claims.campus_id,
+    };
+
+    log_collection
+        .insert_one(&entry, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": if entry.direction == "out" { "Check-out recorded" } else { "Check-in recorded" },
+        "movement": entry
+    })))
+}
+
+async fn get_movements(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<MovementQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if !is_gate_staff(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Security, warden or admin role required"
+        })));
+    } else if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(date) = query.date {
+        // recorded_at is stored as RFC 3339, so a day is a string prefix range
+        let next = date.succ_opt().unwrap_or(date);
+        filter.insert("recorded_at", doc! { "$gte": date.to_string(), "$lt": next.to_string() });
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "recorded_at": -1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<MovementLog>("movement_logs")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut movements = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(movement) => movements.push(movement),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(movements))
+}
+
+// Residents whose latest movement is an exit
+async fn get_outside_report(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<OutsideReportQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_gate_staff(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Security, warden or admin role required"
+        })));
+    }
+
+    let mut match_stage = doc! { "campus_id": &claims.campus_id };
+    if let Some(hostel_id) = &query.hostel_id {
+        match_stage.insert("hostel_id", hostel_id);
+    }
+    let pipeline = vec![
+        doc! { "$match": match_stage },
+        doc! { "$sort": { "recorded_at": -1 } },
+        doc! { "$group": { "_id": "$student_id", "last": { "$first": "$$ROOT" } } },
+        doc! { "$replaceRoot": { "newRoot": "$last" } },
+        doc! { "$match": { "direction": "out" } },
+        doc! { "$sort": { "recorded_at": 1 } },
+    ];
+
+    let mut cursor = data.db
+        .collection::<MovementLog>("movement_logs")
+        .aggregate(pipeline, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let today = Utc::now().date_naive();
+    let mut outside = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let document = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let movement: MovementLog = mongodb::bson::from_document(document)
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        // Out on an expired pass, or out overnight without one
+        let pass = match &movement.gate_pass_id {
+            Some(pass_id) => find_gate_pass(&data.db, pass_id, &claims.campus_id).await?,
+            None => None,
+        };
+        let overdue = match &pass {
+            Some(pass) => today > pass.to_date,
+            None => movement.recorded_at.date_naive() < today,
+        };
+
+        outside.push(serde_json::json!({
+            "student_id": movement.student_id,
+            "hostel_id": movement.hostel_id,
+            "out_since": movement.recorded_at,
+            "gate_pass_id": movement.gate_pass_id,
+            "expected_return": pass.map(|p| p.to_date),
+            "overdue": overdue
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": outside.len(),
+        "students": outside
+    })))
+}
+
+// ===== ENTRY LOGS =====
+
+// Batches from biometric/RFID gate devices. Devices resend on failure, so an
+// event already stored for (device_id, sequence) is counted as a duplicate.
+async fn ingest_entry_logs(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    batch: web::Json<EntryLogBatch>,
+) -> Result<HttpResponse, Error> {
+    let Some(expected) = data.device_api_key.as_deref() else {
+        return Ok(HttpResponse::ServiceUnavailable().json(serde_json::json!({
+            "error": "Gate device ingestion is not configured"
+        })));
+    };
+    let key = req.headers().get("X-Device-Key").and_then(|v| v.to_str().ok()).unwrap_or("");
+    let valid = expected.len() == key.len()
+        && expected.bytes().zip(key.bytes()).fold(0u8, |acc, (a, b)| acc | (a ^ b)) == 0;
+    if !valid {
+        return Ok(HttpResponse::Unauthorized().json(serde_json::json!({
+            "error": "Invalid device key"
+        })));
+    }
+
+    if batch.campus_id.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "campus_id is required"
+        })));
+    }
+    if batch.events.len() > MAX_ENTRY_BATCH {
+        return Ok(HttpResponse::PayloadTooLarge().json(serde_json::json!({
+            "error": format!("A batch can hold at most {} events", MAX_ENTRY_BATCH)
+        })));
+    }
+
+    use chrono::SubsecRound;
+
+    let collection: Collection<EntryLog> = data.db.collection("entry_logs");
+    let latest_allowed = Utc::now() + chrono::Duration::seconds(ENTRY_CLOCK_SKEW_SECS);
+    let mut accepted = 0;
+    let mut duplicates = 0;
+    let mut rejected = Vec::new();
+
+    for (index, event) in batch.events.iter().enumerate() {
+        let problem = if event.device_id.trim().is_empty() || event.student_id.trim().is_empty() {
+            Some("device_id and student_id are required")
+        } else if event.direction != "in" && event.direction != "out" {
+            Some("direction must be 'in' or 'out'")
+        } else if event.timestamp > latest_allowed {
+            Some("timestamp is in the future")
+        } else {
+            None
+        };
+        if let Some(reason) = problem {
+            rejected.push(serde_json::json!({
+                "index": index,
+                "device_id": event.device_id,
+                "sequence": event.sequence,
+                "reason": reason
+            }));
+            continue;
+        }
+
+        let log = EntryLog {
+            id: None,
+            device_id: event.device_id.trim().to_string(),
+            sequence: event.sequence,
+            student_id: event.student_id.trim().to_string(),
+            direction: event.direction.clone(),
+            timestamp: event.timestamp.trunc_subsecs(0),
+            received_at: Utc::now(),
+            campus_id: batch.campus_id.clone(),
+        };
+        match collection.insert_one(&log, None).await {
+            Ok(_) => accepted += 1,
+            Err(e) if is_duplicate_key(&e) => duplicates += 1,
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "accepted": accepted,
+        "duplicates": duplicates,
+        "rejected": rejected
+    })))
+}
+
+// Who passed a gate in a time window, for investigations
+async fn get_entry_logs(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<EntryLogQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_gate_staff(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Security, warden or admin role required"
+        })));
+    }
+    if query.from > query.to {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must not be after to"
+        })));
+    }
+
+    // Same format the timestamps are stored in
+    let bound = |t: &DateTime<Utc>| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let mut filter = doc! {
+        "campus_id": &claims.campus_id,
+        "timestamp": { "$gte": bound(&query.from), "$lte": bound(&query.to) }
+    };
+    if let Some(direction) = &query.direction {
+        filter.insert("direction", direction);
+    }
+    if let Some(student_id) = &query.student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(device_id) = &query.device_id {
+        filter.insert("device_id", device_id);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "timestamp": 1, "device_id": 1, "sequence": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<EntryLog>("entry_logs")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut events = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(event) => events.push(event),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let mut students: Vec<&str> = events.iter().map(|e| e.student_id.as_str()).collect();
+    students.sort_unstable();
+    students.dedup();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "count": events.len(),
+        "students": students,
+        "events": events
+    })))
+}
+
+// ===== ROLL CALL =====
+
+// An approved pass (or one the student left on) covering the night
+async fn covering_gate_pass(
+    db: &mongodb::Database,
+    student_id: &str,
+    date: NaiveDate,
+    campus_id: &str,
+) -> Result<Option<GatePass>, Error> {
+    db.collection::<GatePass>("gate_passes")
+        .find_one(
+            doc! {
+                "student_id": student_id,
+                "campus_id": campus_id,
+                "status": { "$in": ["approved", "out", "returned"] },
+                "from_date": { "$lte": date.to_string() },
+                "to_date": { "$gte": date.to_string() }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn mark_roll_call(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    roll_data: web::Json<RollCallRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if roll_data.date > Utc::now().date_naive() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Roll call cannot be marked for a future date"
+        })));
+    }
+    if let Some(bad) = roll_data.entries.iter().find(|e| !ROLL_CALL_STATUSES.contains(&e.status.as_str())) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Invalid status '{}' for {}; use one of: {}", bad.status, bad.student_id, ROLL_CALL_STATUSES.join(", "))
+        })));
+    }
+
+    let roll_collection: Collection<RollCallEntry> = data.db.collection("roll_calls");
+    let alert_collection: Collection<HostelAlert> = data.db.collection("hostel_alerts");
+    let mut marked = 0;
+    let mut skipped = Vec::new();
+    let mut alerts = Vec::new();
+
+    for mark in &roll_data.entries {
+        let hostel_id = student_hostel_id(&data.db, &mark.student_id, &claims.campus_id).await?;
+        if hostel_id.as_deref() != Some(roll_data.hostel_id.as_str()) {
+            skipped.push(mark.student_id.clone());
+            continue;
+        }
+
+        let gate_pass = if mark.status == "present" {
+            None
+        } else {
+            covering_gate_pass(&data.db, &mark.student_id, roll_data.date, &claims.campus_id).await?
+        };
+
+        let entry = RollCallEntry {
+            id: None,
+            student_id: mark.student_id.clone(),
+            hostel_id: roll_data.hostel_id.clone(),
+            date: roll_data.date,
+            status: mark.status.clone(),
+            gate_pass_id: gate_pass.as_ref().and_then(|p| p.id).map(|id| id.to_hex()),
+            marked_by: claims.sub.clone(),
+            marked_at: Utc::now(),
+            campus_id: claims.campus_id.clone(),
+        };
+        let key = doc! {
+            "student_id": &mark.student_id,
+            "date": roll_data.date.to_string(),
+            "campus_id": &claims.campus_id
+        };
+        // Re-marking a night replaces the earlier mark
+        roll_collection
+            .replace_one(key.clone(), &entry, mongodb::options::ReplaceOptions::builder().upsert(true).build())
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        marked += 1;
+
+        let mut alert_key = key;
+        alert_key.insert("kind", "unexcused_absence");
+        if mark.status == "absent" && gate_pass.is_none() {
+            let alert = HostelAlert {
+                id: None,
+                kind: "unexcused_absence".to_string(),
+                student_id: mark.student_id.clone(),
+                hostel_id: roll_data.hostel_id.clone(),
+                date: roll_data.date,
+                message: format!("{} was absent at roll call on {} without an approved gate pass", mark.student_id, roll_data.date),
+                status: "open".to_string(),
+                acknowledged_by: None,
+                created_at: Utc::now(),
+                campus_id: claims.campus_id.clone(),
+            };
+            let existing = alert_collection
+                .find_one(alert_key, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            match existing {
+                Some(existing) if existing.status != "resolved" => {}
+                Some(existing) => {
+                    alert_collection
+                        .update_one(doc! { "_id": existing.id }, doc! { "$set": { "status": "open" } }, None)
+                        .await
+                        .map_err(actix_web::error::ErrorInternalServerError)?;
+                    alerts.push(alert);
+                }
+                None => {
+                    alert_collection
+                        .insert_one(&alert, None)
+                        .await
+                        .map_err(actix_web::error::ErrorInternalServerError)?;
+                    alerts.push(alert);
+                }
+            }
+        } else {
+            // A corrected mark clears the alert it raised
+            alert_collection
+                .update_one(alert_key, doc! { "$set": { "status": "resolved" } }, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "marked": marked,
+        "skipped": skipped,
+        "alerts_raised": alerts.len(),
+        "alerts": alerts
+    })))
+}
+
+// One night's roll call for a hostel, with residents not yet marked
+async fn get_roll_call(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<RollCallQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    use futures::stream::StreamExt;
+
+    let mut cursor = data.db
+        .collection::<RollCallEntry>("roll_calls")
+        .find(
+            doc! { "hostel_id": &query.hostel_id, "date": query.date.to_string(), "campus_id": &claims.campus_id },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut entries = Vec::new();
+    while let Some(result) = cursor.next().await {
+        entries.push(result.map_err(actix_web::error::ErrorInternalServerError)?);
+    }
+
+    let mut room_ids = Vec::new();
+    let mut cursor = data.db
+        .collection::<Room>("rooms")
+        .find(doc! { "hostel_id": &query.hostel_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let room = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        room_ids.extend(room.id.map(|id| id.to_hex()));
+    }
+
+    let mut unmarked = Vec::new();
+    let mut cursor = data.db
+        .collection::<RoomAllocation>("room_allocations")
+        .find(doc! { "room_id": { "$in": room_ids }, "status": "active" }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let allocation = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if !entries.iter().any(|e| e.student_id == allocation.student_id) {
+            unmarked.push(allocation.student_id);
+        }
+    }
+
+    let count = |status: &str| entries.iter().filter(|e| e.status == status).count();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "date": query.date,
+        "present": count("present"),
+        "absent": count("absent"),
+        "on_leave": count("on_leave"),
+        "unmarked": unmarked,
+        "entries": entries
+    })))
+}
+
+// Monthly attendance for one student, night by night
+async fn get_roll_call_report(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<RollCallReportQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role == "student" && claims.sub != query.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own attendance"
+        })));
+    }
+    let Some((month_start, month_end)) = month_bounds(&query.month) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "month must be in YYYY-MM format"
+        })));
+    };
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "date": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<RollCallEntry>("roll_calls")
+        .find(
+            doc! {
+                "student_id": &query.student_id,
+                "campus_id": &claims.campus_id,
+                "date": { "$gte": month_start.to_string(), "$lte": month_end.to_string() }
+            },
+            options,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut nights = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => nights.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let count = |status: &str| nights.iter().filter(|e| e.status == status).count();
+    let present = count("present");
+    let marked = nights.len();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": query.student_id,
+        "month": query.month,
+        "present": present,
+        "absent": count("absent"),
+        "unexcused_absences": nights.iter().filter(|e| e.status == "absent" && e.gate_pass_id.is_none()).count(),
+        "on_leave": count("on_leave"),
+        "nights_marked": marked,
+        "attendance_percent": if marked > 0 {
+            (present as f64 / marked as f64 * 1000.0).round() / 10.0
+        } else {
+            0.0
+        },
+        "nights": nights
+    })))
+}
+
+async fn get_alerts(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AlertQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    filter.insert("status", query.status.clone().unwrap_or_else(|| "open".to_string()));
+    if let Some(hostel_id) = &query.hostel_id {
+        filter.insert("hostel_id", hostel_id);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "date": -1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<HostelAlert>("hostel_alerts")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut alerts = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(alert) => alerts.push(alert),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(alerts))
+}
+
+async fn acknowledge_alert(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let alert_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let result = data.db
+        .collection::<HostelAlert>("hostel_alerts")
+        .update_one(
+            doc! { "_id": alert_obj_id, "status": "open", "campus_id": &claims.campus_id },
+            doc! { "$set": { "status": "acknowledged", "acknowledged_by": &claims.sub } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Open alert not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Alert acknowledged"
+    })))
+}
+
+// ===== DISCIPLINE =====
+
+async fn find_incident(
+    db: &mongodb::Database,
+    incident_id: &str,
+    campus_id: &str,
+) -> Result<Option<DisciplinaryIncident>, Error> {
+    let incident_obj_id = ObjectId::parse_str(incident_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    db.collection::<DisciplinaryIncident>("disciplinary_incidents")
+        .find_one(doc! { "_id": incident_obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn create_incident(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    incident_data: web::Json<IncidentRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !INCIDENT_SEVERITIES.contains(&incident_data.severity.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("severity must be one of: {}", INCIDENT_SEVERITIES.join(", "))
+        })));
+    }
+    if incident_data.category.trim().is_empty() || incident_data.description.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "category and description are required"
+        })));
+    }
+
+    let incident = DisciplinaryIncident {
+        id: None,
+        student_id: incident_data.student_id.clone(),
+        hostel_id: student_hostel_id(&data.db, &incident_data.student_id, &claims.campus_id).await?,
+        category: incident_data.category.trim().to_lowercase(),
+        severity: incident_data.severity.clone(),
+        description: incident_data.description.trim().to_string(),
+        evidence_ref: incident_data.evidence_ref.clone(),
+        occurred_at: incident_data.occurred_at.unwrap_or_else(Utc::now),
+        reported_by: claims.sub.clone(),
+        actions: Vec::new(),
+        status: "open".to_string(),
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let result = data.db
+        .collection::<DisciplinaryIncident>("disciplinary_incidents")
+        .insert_one(incident, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Incident recorded",
+        "incident_id": result.inserted_id.as_object_id().map(|id| id.to_hex())
+    })))
+}
+
+async fn get_incidents(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<IncidentQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(status) = &query.status {
+        filter.insert("status", status);
+    }
+    if let Some(hostel_id) = &query.hostel_id {
+        filter.insert("hostel_id", hostel_id);
+    }
+    if let Some(severity) = &query.severity {
+        filter.insert("severity", severity);
+    }
+
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "occurred_at": -1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<DisciplinaryIncident>("disciplinary_incidents")
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut incidents = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(incident) => incidents.push(incident),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(incidents))
+}
+
+// Fines are billed to the student; expulsion vacates their room
+async fn take_disciplinary_action(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    action_data: web::Json<DisciplinaryActionRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+    if !DISCIPLINARY_ACTIONS.contains(&action_data.action.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("action must be one of: {}", DISCIPLINARY_ACTIONS.join(", "))
+        })));
+    }
+    let amount = match (action_data.action.as_str(), action_data.amount) {
+        ("fine", Some(amount)) if amount > 0.0 => Some((amount * 100.0).round() / 100.0),
+        ("fine", _) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "A fine needs a positive amount"
+            })));
+        }
+        _ => None,
+    };
+
+    let Some(incident) = find_incident(&data.db, &path.into_inner(), &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Incident not found"
+        })));
+    };
+    if incident.status != "open" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Incident is closed"
+        })));
+    }
+    let incident_id = incident.id.map(|id| id.to_hex()).unwrap_or_default();
+
+    let mut fee_id = None;
+    if let Some(amount) = amount {
+        // One reference per action, so a retried request is not billed twice
+        let reference_id = format!("{}-{}", incident_id, incident.actions.len() + 1);
+        match data.finance
+            .bill("hostel", &reference_id, &incident.student_id, amount, &claims.campus_id)
+            .await
+        {
+            Ok(id) => fee_id = Some(id),
+            Err(e) => {
+                log::warn!("Could not bill disciplinary fine for incident {}: {}", incident_id, e);
+                return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                    "error": "finance-service could not bill the fine; try again later"
+                })));
+            }
+        }
+    }
+
+    let mut vacated = None;
+    if action_data.action == "expulsion" {
+        vacated = release_allocation(
+            &data,
+            doc! { "student_id": &incident.student_id, "campus_id": &claims.campus_id },
+            "expelled",
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    let action = DisciplinaryAction {
+        action: action_data.action.clone(),
+        notes: action_data.notes.clone(),
+        amount,
+        fee_id,
+        taken_by: claims.sub.clone(),
+        taken_at: Utc::now(),
+    };
+    data.db
+        .collection::<DisciplinaryIncident>("disciplinary_incidents")
+        .update_one(
+            doc! { "_id": incident.id },
+            doc! { "$push": { "actions": mongodb::bson::to_bson(&action).map_err(actix_web::error::ErrorInternalServerError)? } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Action recorded",
+        "action": action,
+        "vacated_allocation": vacated.as_ref().map(|(allocation, _)| allocation),
+        "allocated_from_waitlist": vacated.map(|(_, students)| students).unwrap_or_default()
+    })))
+}
+
+async fn close_incident(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let incident_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let result = data.db
+        .collection::<DisciplinaryIncident>("disciplinary_incidents")
+        .update_one(
+            doc! { "_id": incident_obj_id, "status": "open", "campus_id": &claims.campus_id },
+            doc! { "$set": { "status": "closed" } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Open incident not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Incident closed"
+    })))
+}
+
+// Wardens only; students do not see their own record here
+async fn get_student_disciplinary_history(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let student_id = path.into_inner();
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "occurred_at": 1 })
+        .build();
+    let mut cursor = data.db
+        .collection::<DisciplinaryIncident>("disciplinary_incidents")
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut incidents = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(incident) => incidents.push(incident),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let count = |action: &str| incidents.iter().flat_map(|i| &i.actions).filter(|a| a.action == action).count();
+    let summary = serde_json::json!({
+        "incidents": incidents.len(),
+        "open": incidents.iter().filter(|i| i.status == "open").count(),
+        "warnings": count("warning"),
+        "fines": count("fine"),
+        "fined_amount": incidents.iter().flat_map(|i| &i.actions).filter_map(|a| a.amount).sum::<f64>(),
+        "expelled": count("expulsion") > 0
+    });
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "summary": summary,
+        "incidents": incidents
+    })))
+}
+
+// ===== OCCUPANCY REPORT =====
+
+// Capacity, occupancy and vacancies of the rooms grouped under `key`
+fn occupancy_group(key: impl Into<mongodb::bson::Bson>) -> Vec<mongodb::bson::Document> {
+    vec![
+        doc! { "$group": {
+            "_id": key.into(),
+            "rooms": { "$sum": 1 },
+            "empty_rooms": { "$sum": { "$cond": [{ "$eq": ["$occupied", 0] }, 1, 0] } },
+            "capacity": { "$sum": "$capacity" },
+            "occupied": { "$sum": "$occupied" }
+        } },
+        doc! { "$project": {
+            "_id": 0,
+            "group": "$_id",
+            "rooms": 1,
+            "empty_rooms": 1,
+            "capacity": 1,
+            "occupied": 1,
+            "vacancies": { "$subtract": ["$capacity", "$occupied"] },
+            "occupancy_percent": { "$cond": [
+                { "$gt": ["$capacity", 0] },
+                { "$round": [{ "$multiply": [{ "$divide": ["$occupied", "$capacity"] }, 100] }, 1] },
+                0
+            ] }
+        } },
+        doc! { "$sort": { "group": 1 } },
+    ]
+}
+
+async fn get_occupancy_report(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<OccupancyReportQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut room_filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(hostel_id) = &query.hostel_id {
+        room_filter.insert("hostel_id", hostel_id);
+    }
+
+    let pipeline = vec![
+        doc! { "$match": room_filter.clone() },
+        doc! { "$facet": {
+            "totals": occupancy_group(mongodb::bson::Bson::Null),
+            "by_hostel": occupancy_group(doc! { "hostel_id": "$hostel_id", "hostel_name": "$hostel_name" }),
+            "by_floor": occupancy_group(doc! { "hostel_id": "$hostel_id", "hostel_name": "$hostel_name", "floor": "$floor" }),
+            "by_room_type": occupancy_group(doc! { "room_type": "$room_type" }),
+        } },
+    ];
+
+    use futures::stream::StreamExt;
+
+    let rooms: Collection<Room> = data.db.collection("rooms");
+    let mut cursor = rooms
+        .aggregate(pipeline, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let facets = match cursor.next().await {
+        Some(result) => result.map_err(actix_web::error::ErrorInternalServerError)?,
+        None => mongodb::bson::Document::new(),
     };
+    let facet = |name: &str| -> serde_json::Value {
+        facets
+            .get_array(name)
+            .ok()
+            .and_then(|groups| serde_json::to_value(groups).ok())
+            .unwrap_or_else(|| serde_json::json!([]))
+    };
+    let totals = facet("totals").get(0).cloned().unwrap_or_else(|| serde_json::json!({
+        "rooms": 0, "empty_rooms": 0, "capacity": 0, "occupied": 0, "vacancies": 0, "occupancy_percent": 0
+    }));
+    let capacity = totals["capacity"].as_i64().unwrap_or_default();
+
+    // Trend: residents on the last day of each month, counted from the
+    // allocation history against today's capacity
+    let mut allocation_filter = doc! { "campus_id": &claims.campus_id };
+    if query.hostel_id.is_some() {
+        let mut room_ids = Vec::new();
+        let mut cursor = rooms
+            .find(room_filter, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        while let Some(result) = cursor.next().await {
+            let room = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            room_ids.extend(room.id.map(|id| id.to_hex()));
+        }
+        allocation_filter.insert("room_id", doc! { "$in": room_ids });
+    }
 
-    collection
-        .insert_one(new_request, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    let allocations: Collection<RoomAllocation> = data.db.collection("room_allocations");
+    let trend_months = query.trend_months.unwrap_or(6).clamp(1, 24);
+    let today = Utc::now().date_naive();
+    let mut month_start = NaiveDate::from_ymd_opt(today.year(), today.month(), 1).unwrap_or(today);
+    let mut trend = Vec::new();
+    for i in 0..trend_months {
+        let next_month = month_bounds(&month_start.format("%Y-%m").to_string())
+            .and_then(|(_, end)| end.succ_opt())
+            .unwrap_or(month_start);
+        // The current month is measured as of now
+        let cutoff = if i == 0 { Utc::now().to_rfc3339() } else { next_month.to_string() };
+
+        let mut filter = allocation_filter.clone();
+        filter.insert("allocation_date", doc! { "$lt": &cutoff });
+        filter.insert("$or", vec![
+            doc! { "vacated_at": null },
+            doc! { "vacated_at": { "$gte": &cutoff } },
+        ]);
+        let occupied = allocations
+            .count_documents(filter, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        trend.push(serde_json::json!({
+            "month": month_start.format("%Y-%m").to_string(),
+            "occupied": occupied,
+            "occupancy_percent": if capacity > 0 {
+                (occupied as f64 / capacity as f64 * 1000.0).round() / 10.0
+            } else {
+                0.0
+            }
+        }));
+
+        month_start = match month_start.pred_opt() {
+            Some(last_of_previous) => NaiveDate::from_ymd_opt(last_of_previous.year(), last_of_previous.month(), 1)
+                .unwrap_or(last_of_previous),
+            None => break,
+        };
+    }
+    trend.reverse();
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Maintenance request created successfully"
+        "totals": totals,
+        "by_hostel": facet("by_hostel"),
+        "by_floor": facet("by_floor"),
+        "by_room_type": facet("by_room_type"),
+        "trend": trend
     })))
 }
 
-async fn get_maintenance_requests(
+// ===== CAPACITY PLANNING =====
+
+// Splits `total` by weight, handing leftover units to the largest remainders
+// so the parts always add up to `total`
+fn apportion(total: u32, weights: &[(String, f64)]) -> Vec<(String, u32)> {
+    let sum: f64 = weights.iter().map(|(_, w)| w).sum();
+    let mut parts: Vec<(String, u32, f64)> = weights
+        .iter()
+        .map(|(name, w)| {
+            let exact = total as f64 * w / sum;
+            (name.clone(), exact.floor() as u32, exact - exact.floor())
+        })
+        .collect();
+    let assigned: u32 = parts.iter().map(|p| p.1).sum();
+    let mut by_remainder: Vec<usize> = (0..parts.len()).collect();
+    by_remainder.sort_by(|&a, &b| parts[b].2.total_cmp(&parts[a].2));
+    for &i in by_remainder.iter().take(total.saturating_sub(assigned) as usize) {
+        parts[i].1 += 1;
+    }
+    parts.into_iter().map(|(name, count, _)| (name, count)).collect()
+}
+
+// Largest weights first, names breaking ties, so plans are reproducible
+fn split_weights(split: &HashMap<String, f64>) -> Vec<(String, f64)> {
+    let mut weights: Vec<(String, f64)> = split.iter().map(|(k, v)| (k.trim().to_lowercase(), *v)).collect();
+    weights.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    weights
+}
+
+// Simulates placing an incoming batch into today's vacancies. Each segment
+// (gender, then program within it) fills its own gender's hostels floor by
+// floor, keeping a cohort together; mixed hostels take the overflow once
+// every segment has used its dedicated space.
+async fn plan_capacity(
     data: web::Data<AppState>,
     req: HttpRequest,
+    plan_data: web::Json<CapacityPlanRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let collection: Collection<MaintenanceRequest> = data.db.collection("maintenance_requests");
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    if plan_data.batch_size == 0 || plan_data.batch_size > MAX_PLAN_BATCH {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("batch_size must be between 1 and {}", MAX_PLAN_BATCH)
+        })));
+    }
+    let genders = split_weights(&plan_data.gender_split);
+    if genders.is_empty() || genders.iter().any(|(g, _)| g != "male" && g != "female") {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "gender_split needs weights for male and/or female"
+        })));
+    }
+    let programs = plan_data.program_split.as_ref().map(split_weights).unwrap_or_default();
+    let all_weights = genders.iter().chain(programs.iter());
+    if all_weights.clone().any(|(_, w)| !w.is_finite() || *w < 0.0)
+        || genders.iter().map(|(_, w)| w).sum::<f64>() <= 0.0
+        || (!programs.is_empty() && programs.iter().map(|(_, w)| w).sum::<f64>() <= 0.0)
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Split weights must be non-negative and add up to more than zero"
+        })));
+    }
+
+    let mut segments = Vec::new();
+    for (gender, students) in apportion(plan_data.batch_size, &genders) {
+        if programs.is_empty() {
+            segments.push(SegmentPlan { gender, program: None, students, placed: 0, shortfall: 0 });
+            continue;
+        }
+        for (program, count) in apportion(students, &programs) {
+            segments.push(SegmentPlan { gender: gender.clone(), program: Some(program), students: count, placed: 0, shortfall: 0 });
+        }
+    }
 
-    let mut requests = Vec::new();
     use futures::stream::StreamExt;
-    
+
+    let mut hostel_filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(hostel_ids) = &plan_data.hostel_ids {
+        let ids: Vec<ObjectId> = hostel_ids.iter().filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+        hostel_filter.insert("_id", doc! { "$in": ids });
+    }
+    let mut hostels = HashMap::new();
+    let mut cursor = data.db
+        .collection::<Hostel>("hostels")
+        .find(hostel_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
     while let Some(result) = cursor.next().await {
-        match result {
-            Ok(request) => requests.push(request),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        let hostel = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(id) = hostel.id {
+            hostels.insert(id.to_hex(), hostel);
         }
     }
 
-    Ok(HttpResponse::Ok().json(requests))
+    // Rooms outside a registered hostel have no gender policy to plan against
+    let mut floors: Vec<FloorPlan> = Vec::new();
+    let mut unassigned_vacancy = 0;
+    let mut cursor = data.db
+        .collection::<Room>("rooms")
+        .find(doc! { "campus_id": &claims.campus_id, "out_of_service": { "$ne": true } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let room = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let vacant = (room.capacity - room.occupied).max(0) as u32;
+        let Some(hostel) = room.hostel_id.as_ref().and_then(|id| hostels.get(id)) else {
+            if room.hostel_id.is_none() {
+                unassigned_vacancy += vacant;
+            }
+            continue;
+        };
+        let hostel_id = room.hostel_id.clone().unwrap_or_default();
+        match floors.iter_mut().find(|f| f.hostel_id == hostel_id && f.floor == room.floor) {
+            Some(floor) => floor.vacant += vacant,
+            None => floors.push(FloorPlan {
+                hostel_id,
+                hostel_name: hostel.name.clone(),
+                gender_policy: hostel.gender_policy.clone(),
+                floor: room.floor,
+                vacant,
+                placed: 0,
+                remaining: vacant,
+            }),
+        }
+    }
+    floors.sort_by(|a, b| a.hostel_name.cmp(&b.hostel_name).then(a.floor.cmp(&b.floor)));
+
+    for pass_policy in [None, Some("mixed")] {
+        for segment in segments.iter_mut() {
+            let policy = pass_policy.unwrap_or(segment.gender.as_str());
+            for floor in floors.iter_mut().filter(|f| f.gender_policy == policy) {
+                let take = floor.remaining.min(segment.students - segment.placed);
+                floor.remaining -= take;
+                floor.placed += take;
+                segment.placed += take;
+            }
+        }
+    }
+    for segment in segments.iter_mut() {
+        segment.shortfall = segment.students - segment.placed;
+    }
+
+    let by_gender: Vec<_> = genders
+        .iter()
+        .map(|(gender, _)| {
+            let of_gender = segments.iter().filter(|s| &s.gender == gender);
+            let students: u32 = of_gender.clone().map(|s| s.students).sum();
+            let placed: u32 = of_gender.map(|s| s.placed).sum();
+            serde_json::json!({
+                "gender": gender,
+                "students": students,
+                "placed": placed,
+                "shortfall": students - placed,
+                // Beds only this gender can use, before mixed hostels
+                "dedicated_vacancy": floors.iter().filter(|f| &f.gender_policy == gender).map(|f| f.vacant).sum::<u32>()
+            })
+        })
+        .collect();
+
+    let placed: u32 = segments.iter().map(|s| s.placed).sum();
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "batch_size": plan_data.batch_size,
+        "total_vacancy": floors.iter().map(|f| f.vacant).sum::<u32>(),
+        "mixed_vacancy": floors.iter().filter(|f| f.gender_policy == "mixed").map(|f| f.vacant).sum::<u32>(),
+        "placed": placed,
+        "shortfall": plan_data.batch_size - placed,
+        "unassigned_vacancy": unassigned_vacancy,
+        "by_gender": by_gender,
+        "segments": segments,
+        "floors": floors
+    })))
 }
 
 // ===== STUDENT DASHBOARD - HOSTEL STATUS =====
@@ -331,8 +6688,8 @@ async fn get_student_hostel_status(
     req: HttpRequest,
     path: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     let student_id = path.into_inner();
     if claims.role == "student" && claims.sub != student_id {
@@ -352,18 +6709,18 @@ async fn get_student_hostel_status(
             "campus_id": &claims.campus_id
         }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     match allocation {
         Some(alloc) => {
             // Get room details
             let room_obj_id = ObjectId::parse_str(&alloc.room_id)
-                .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+                .map_err(actix_web::error::ErrorBadRequest)?;
 
             let room = room_collection
                 .find_one(doc! { "_id": room_obj_id }, None)
                 .await
-                .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+                .map_err(actix_web::error::ErrorInternalServerError)?;
 
             Ok(HttpResponse::Ok().json(serde_json::json!({
                 "student_id": student_id,
@@ -384,6 +6741,498 @@ async fn get_student_hostel_status(
     }
 }
 
+// ===== OUTBOUND EVENTS =====
+
+// Seconds to wait before retrying after the given number of failed attempts
+fn event_retry_delay(attempts: u32) -> i64 {
+    60 * 2_i64.pow(attempts.saturating_sub(1).min(10))
+}
+
+// Queues an event for every subscriber and starts delivering it right away.
+// Delivery failures never reach the caller; they are retried in the background.
+async fn publish_event(
+    db: &mongodb::Database,
+    events: &EventPublisher,
+    event_type: &str,
+    campus_id: &str,
+    data: serde_json::Value,
+) {
+    if events.webhook_urls.is_empty() {
+        return;
+    }
+    let event_id = ObjectId::new().to_hex();
+    let now = Utc::now();
+    let payload = serde_json::json!({
+        "id": &event_id,
+        "type": event_type,
+        "created_at": now.to_rfc3339(),
+        "campus_id": campus_id,
+        "data": data
+    })
+    .to_string();
+
+    let deliveries: Vec<EventDelivery> = events
+        .webhook_urls
+        .iter()
+        .map(|url| EventDelivery {
+            id: None,
+            event_id: event_id.clone(),
+            event_type: event_type.to_string(),
+            url: url.clone(),
+            payload: payload.clone(),
+            status: "pending".to_string(),
+            attempts: 0,
+            last_status_code: None,
+            last_error: None,
+            next_attempt_at: mongodb::bson::DateTime::from_millis(now.timestamp_millis()),
+            campus_id: campus_id.to_string(),
+            created_at: now,
+            delivered_at: None,
+        })
+        .collect();
+    let collection: Collection<EventDelivery> = db.collection("event_deliveries");
+    if let Err(e) = collection.insert_many(deliveries, None).await {
+        log::warn!("Could not queue {} event {}: {}", event_type, event_id, e);
+        return;
+    }
+
+    let db = db.clone();
+    let events = events.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = deliver_due_events(&db, &events).await {
+            log::warn!("Event delivery failed: {}", e);
+        }
+    });
+}
+
+// Sends every delivery that is due. Each one is leased before sending so
+// concurrent workers never post the same delivery twice at once.
+async fn deliver_due_events(db: &mongodb::Database, events: &EventPublisher) -> mongodb::error::Result<u64> {
+    use mongodb::bson::DateTime as BsonDateTime;
+
+    let collection: Collection<EventDelivery> = db.collection("event_deliveries");
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(10))
+        .build()
+        .unwrap_or_default();
+    let mut delivered = 0;
+
+    loop {
+        let now = Utc::now();
+        let lease_until = BsonDateTime::from_millis((now + chrono::Duration::minutes(5)).timestamp_millis());
+        let Some(delivery) = collection
+            .find_one_and_update(
+                doc! { "status": "pending", "next_attempt_at": { "$lte": BsonDateTime::from_millis(now.timestamp_millis()) } },
+                doc! { "$set": { "next_attempt_at": lease_until } },
+                None,
+            )
+            .await?
+        else {
+            break;
+        };
+
+        let signature = {
+            let mut mac = Hmac::<Sha256>::new_from_slice(events.secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(delivery.payload.as_bytes());
+            hex::encode(mac.finalize().into_bytes())
+        };
+        let result = client
+            .post(&delivery.url)
+            .header("Content-Type", "application/json")
+            .header("X-CampusConnect-Event", &delivery.event_type)
+            .header("X-CampusConnect-Delivery", delivery.id.map(|id| id.to_hex()).unwrap_or_default())
+            .header("X-CampusConnect-Signature", signature)
+            .body(delivery.payload.clone())
+            .send()
+            .await;
+
+        let attempts = delivery.attempts + 1;
+        let (status_code, error) = match result {
+            Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+            Ok(response) => (Some(response.status().as_u16()), Some(format!("HTTP {}", response.status()))),
+            Err(e) => (None, Some(e.to_string())),
+        };
+        let mut update = doc! { "attempts": attempts };
+        if let Some(code) = status_code {
+            update.insert("last_status_code", code as i32);
+        }
+        match error {
+            None => {
+                update.insert("status", "delivered");
+                update.insert("delivered_at", Utc::now().to_rfc3339());
+                delivered += 1;
+            }
+            Some(error) => {
+                if attempts >= events.max_attempts {
+                    update.insert("status", "failed");
+                    log::warn!("Giving up on {} delivery to {}: {}", delivery.event_type, delivery.url, error);
+                } else {
+                    let retry_at = now + chrono::Duration::seconds(event_retry_delay(attempts));
+                    update.insert("next_attempt_at", BsonDateTime::from_millis(retry_at.timestamp_millis()));
+                }
+                update.insert("last_error", error);
+            }
+        }
+        collection
+            .update_one(doc! { "_id": delivery.id }, doc! { "$set": update }, None)
+            .await?;
+    }
+
+    Ok(delivered)
+}
+
+async fn get_event_deliveries(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<EventDeliveryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(status) = &query.status {
+        filter.insert("status", status);
+    }
+    if let Some(event_type) = &query.event_type {
+        filter.insert("event_type", event_type);
+    }
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": -1 })
+        .limit(200)
+        .build();
+
+    let collection: Collection<EventDelivery> = data.db.collection("event_deliveries");
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut deliveries = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(delivery) => deliveries.push(delivery),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(deliveries))
+}
+
+// Puts a failed delivery back in the queue with a fresh set of attempts
+async fn retry_event_delivery(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hostel_admin(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Warden or admin role required"
+        })));
+    }
+
+    let delivery_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<EventDelivery> = data.db.collection("event_deliveries");
+    let result = collection
+        .update_one(
+            doc! { "_id": delivery_id, "campus_id": &claims.campus_id, "status": "failed" },
+            doc! { "$set": {
+                "status": "pending",
+                "attempts": 0,
+                "next_attempt_at": mongodb::bson::DateTime::now()
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Failed delivery not found"
+        })));
+    }
+
+    let db = data.db.clone();
+    let events = data.events.clone();
+    actix_web::rt::spawn(async move {
+        if let Err(e) = deliver_due_events(&db, &events).await {
+            log::warn!("Event delivery failed: {}", e);
+        }
+    });
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Delivery queued for retry"
+    })))
+}
+
+// ===== INDEXES =====
+
+// Of one student's active allocations, the ones to close so a single stay
+// remains: the student keeps the room they checked into, else the earliest
+fn surplus_allocations(mut allocations: Vec<RoomAllocation>) -> Vec<RoomAllocation> {
+    allocations.sort_by_key(|a| (a.checked_in_at.is_none(), a.allocation_date));
+    allocations.into_iter().skip(1).collect()
+}
+
+// Closes duplicate active allocations left by concurrent requests before the
+// unique index existed, so that index can be built. Each closed allocation
+// gives its bed back. Returns the closed allocations for the startup report.
+async fn close_duplicate_allocations(db: &mongodb::Database) -> mongodb::error::Result<Vec<RoomAllocation>> {
+    use futures::stream::StreamExt;
+
+    let collection: Collection<RoomAllocation> = db.collection("room_allocations");
+    let pipeline = [
+        doc! { "$match": { "status": "active" } },
+        doc! { "$group": {
+            "_id": { "student_id": "$student_id", "campus_id": "$campus_id" },
+            "count": { "$sum": 1 }
+        } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+    ];
+    let mut groups = collection.aggregate(pipeline, None).await?;
+    let mut students = Vec::new();
+    while let Some(group) = groups.next().await {
+        if let Ok(key) = group?.get_document("_id") {
+            students.push(key.clone());
+        }
+    }
+
+    let mut closed = Vec::new();
+    for student in students {
+        let mut filter = student;
+        filter.insert("status", "active");
+        let mut cursor = collection.find(filter, None).await?;
+        let mut active = Vec::new();
+        while let Some(allocation) = cursor.next().await {
+            active.push(allocation?);
+        }
+        for allocation in surplus_allocations(active) {
+            let result = collection
+                .update_one(
+                    doc! { "_id": allocation.id, "status": "active" },
+                    doc! { "$set": {
+                        "status": "vacated",
+                        "vacate_reason": "duplicate",
+                        "vacated_at": Utc::now().to_rfc3339()
+                    } },
+                    None,
+                )
+                .await?;
+            if result.modified_count == 1 {
+                if let Ok(room_id) = ObjectId::parse_str(&allocation.room_id) {
+                    db.collection::<Room>("rooms")
+                        .update_one(
+                            doc! { "_id": room_id, "occupied": { "$gt": 0 } },
+                            doc! { "$inc": { "occupied": -1 } },
+                            None,
+                        )
+                        .await?;
+                }
+                closed.push(allocation);
+            }
+        }
+    }
+    Ok(closed)
+}
+
+async fn create_indexes(db: &mongodb::Database) -> mongodb::error::Result<()> {
+    use mongodb::{options::IndexOptions, IndexModel};
+
+    db.collection::<Hostel>("hostels")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "name": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    // A student holds at most one active room at a time
+    db.collection::<RoomAllocation>("room_allocations")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .partial_filter_expression(doc! { "status": "active" })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+
+    // A student waits in at most one queue at a time
+    db.collection::<WaitlistEntry>("room_waitlist")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .partial_filter_expression(doc! { "status": "waiting" })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+
+    // One open room change request per student
+    db.collection::<RoomChangeRequest>("room_change_requests")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .partial_filter_expression(doc! { "status": "pending" })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+
+    // Latest movement per student drives the gate checks and outside report
+    db.collection::<MovementLog>("movement_logs")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "recorded_at": -1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<EntryLog>("entry_logs")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "device_id": 1, "sequence": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<EntryLog>("entry_logs")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "timestamp": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<HostelStaff>("hostel_staff")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "hostel_id": 1, "employee_id": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<DutyShift>("duty_shifts")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "hostel_id": 1, "employee_id": 1, "date": 1, "shift": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<MealFeedback>("meal_feedback")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "date": 1, "meal": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<RollCallEntry>("roll_calls")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "date": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<MaintenanceBudget>("maintenance_budgets")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "hostel_id": 1, "month": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<RateCard>("rate_cards")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "hostel_id": 1, "room_type": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<MealPlan>("meal_plans")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .unique(true)
+                        .partial_filter_expression(doc! { "status": "active" })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<MealAttendance>("meal_attendance")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "date": 1, "meal": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<MessBill>("mess_bills")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "month": 1, "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
@@ -393,6 +7242,54 @@ async fn main() -> std::io::Result<()> {
     let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| "campusconnect".to_string());
     let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8083".to_string());
+    // Default order for POST /api/allocations/auto: seniority, merit or lottery
+    let allocation_strategy = env::var("ALLOCATION_STRATEGY")
+        .ok()
+        .filter(|s| ALLOCATION_STRATEGIES.contains(&s.as_str()))
+        .unwrap_or_else(|| "seniority".to_string());
+    // Length of a stay billed when an allocation does not say
+    let default_stay_months = env::var("HOSTEL_STAY_MONTHS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .filter(|m| *m > 0)
+        .unwrap_or(6);
+    let finance = FinanceClient {
+        base_url: env::var("FINANCE_SERVICE_URL")
+            .unwrap_or_else(|_| "http://127.0.0.1:8082".to_string())
+            .trim_end_matches('/')
+            .to_string(),
+        api_key: env::var("FINE_INTEGRATION_KEY").ok().filter(|k| !k.is_empty()),
+        http: reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client"),
+    };
+    if finance.api_key.is_none() {
+        println!("⚠️  FINE_INTEGRATION_KEY not set; hostel fees will not be billed");
+    }
+    let device_api_key = env::var("GATE_DEVICE_API_KEY").ok().filter(|k| !k.is_empty());
+    if device_api_key.is_none() {
+        println!("⚠️  GATE_DEVICE_API_KEY not set; gate device ingestion is disabled");
+    }
+    let events = EventPublisher {
+        webhook_urls: env::var("EVENT_WEBHOOK_URLS")
+            .map(|v| v.split(',').map(|u| u.trim().to_string()).filter(|u| !u.is_empty()).collect())
+            .unwrap_or_default(),
+        secret: env::var("EVENT_WEBHOOK_SECRET").unwrap_or_default(),
+        max_attempts: env::var("EVENT_MAX_ATTEMPTS").ok().and_then(|v| v.parse().ok()).unwrap_or(8),
+    };
+    if !events.webhook_urls.is_empty() && events.secret.is_empty() {
+        panic!("EVENT_WEBHOOK_SECRET must be set when EVENT_WEBHOOK_URLS is");
+    }
+    if events.webhook_urls.is_empty() {
+        println!("📣 No event webhooks configured; guardians are not notified of out-passes");
+    } else {
+        println!("📣 Publishing hostel events to {} webhook(s)", events.webhook_urls.len());
+    }
+    let mess_rates = MessRates {
+        veg: env::var("MESS_VEG_DAILY_RATE").ok().and_then(|v| v.parse().ok()).unwrap_or(120.0),
+        non_veg: env::var("MESS_NON_VEG_DAILY_RATE").ok().and_then(|v| v.parse().ok()).unwrap_or(150.0),
+    };
 
     println!("🏠 Starting Hostel Service...");
     println!("📡 Connecting to MongoDB: {}", mongodb_uri);
@@ -403,12 +7300,77 @@ async fn main() -> std::io::Result<()> {
     
     let db = client.database(&database_name);
 
+    let access_ttl = env::var("ACCESS_TOKEN_TTL_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(24 * 60);
+    let jwt_keys: SharedKeySet = std::sync::Arc::new(std::sync::RwLock::new(
+        load_jwt_keys(&db, &jwt_secret, chrono::Duration::minutes(access_ttl))
+            .await
+            .expect("Failed to load JWT keys"),
+    ));
+
+    // Pick up keys the auth service adds or retires
+    {
+        let db = db.clone();
+        let jwt_keys = jwt_keys.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(JWT_KEY_REFRESH_SECS));
+            loop {
+                interval.tick().await;
+                reload_jwt_keys(&db, &jwt_secret, &jwt_keys).await;
+            }
+        });
+    }
+
+    // Must run before create_indexes, which makes active allocations unique per student
+    let closed = close_duplicate_allocations(&db)
+        .await
+        .expect("Failed to close duplicate allocations");
+    for allocation in &closed {
+        println!(
+            "🛏️  Closed duplicate allocation {} of student {} (campus {}, {} room {}){}",
+            allocation.id.map(|id| id.to_hex()).unwrap_or_default(),
+            allocation.student_id,
+            allocation.campus_id,
+            allocation.hostel_name,
+            allocation.room_number,
+            allocation.fee_id.as_ref().map(|fee_id| format!("; cancel its fee {} in finance", fee_id)).unwrap_or_default()
+        );
+    }
+
+    create_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+
     println!("✅ Connected to MongoDB");
+
+    // Picks up retries; new events are sent as soon as they are published
+    if !events.webhook_urls.is_empty() {
+        let db = db.clone();
+        let events = events.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(30));
+            loop {
+                interval.tick().await;
+                if let Err(e) = deliver_due_events(&db, &events).await {
+                    log::warn!("Event delivery failed: {}", e);
+                }
+            }
+        });
+    }
+
     println!("🚀 Server starting on http://127.0.0.1:{}", port);
 
     let app_state = web::Data::new(AppState {
         db,
-        jwt_secret,
+        jwt_keys,
+        mess_rates,
+        allocation_strategy,
+        finance,
+        default_stay_months,
+        device_api_key,
+        events,
     });
 
     HttpServer::new(move || {
@@ -419,15 +7381,103 @@ async fn main() -> std::io::Result<()> {
             .wrap(middleware::Logger::default())
             .app_data(app_state.clone())
             .route("/health", web::get().to(health_check))
+            // Hostel routes
+            .route("/api/hostels", web::post().to(create_hostel))
+            .route("/api/hostels", web::get().to(get_hostels))
+            .route("/api/hostels/{id}", web::get().to(get_hostel))
+            .route("/api/hostels/{id}", web::put().to(update_hostel))
+            .route("/api/hostels/{id}", web::delete().to(delete_hostel))
+            // Roster routes
+            .route("/api/hostels/{id}/staff", web::post().to(add_hostel_staff))
+            .route("/api/hostels/{id}/staff", web::get().to(get_hostel_staff))
+            .route("/api/hostels/{id}/staff/{employee_id}", web::delete().to(remove_hostel_staff))
+            .route("/api/hostels/{id}/roster", web::post().to(add_roster_shifts))
+            .route("/api/hostels/{id}/roster", web::get().to(get_roster))
+            .route("/api/roster/on-duty", web::get().to(get_on_duty))
+            .route("/api/roster/{id}", web::delete().to(delete_roster_shift))
             // Room routes
             .route("/api/rooms", web::post().to(create_room))
             .route("/api/rooms", web::get().to(get_rooms))
+            .route("/api/rooms/{id}", web::put().to(update_room))
+            .route("/api/rooms/{id}", web::delete().to(delete_room))
             // Allocation routes
             .route("/api/allocations", web::post().to(allocate_room))
             .route("/api/allocations", web::get().to(get_allocations))
+            .route("/api/allocations/auto", web::post().to(auto_allocate))
+            .route("/api/allocations/auto/{id}", web::get().to(get_allocation_run))
+            .route("/api/allocations/auto/{id}/commit", web::post().to(commit_allocation_run))
+            .route("/api/allocations/{id}/vacate", web::post().to(vacate_allocation))
+            .route("/api/allocations/{id}/bill", web::post().to(rebill_allocation))
+            .route("/api/allocations/{id}/check-in", web::post().to(check_in_allocation))
+            // Rate card routes
+            .route("/api/rate-cards", web::put().to(upsert_rate_card))
+            .route("/api/rate-cards", web::get().to(get_rate_cards))
+            .route("/api/rate-cards/{id}", web::delete().to(delete_rate_card))
+            // Waitlist routes
+            .route("/api/waitlist", web::post().to(join_waitlist))
+            .route("/api/waitlist", web::get().to(get_waitlist))
+            .route("/api/waitlist/{student_id}", web::get().to(get_waitlist_position))
+            .route("/api/waitlist/{student_id}", web::delete().to(leave_waitlist))
             // Maintenance routes
             .route("/api/maintenance", web::post().to(create_maintenance_request))
             .route("/api/maintenance", web::get().to(get_maintenance_requests))
+            .route("/api/maintenance/budgets", web::put().to(upsert_maintenance_budget))
+            .route("/api/maintenance/budgets", web::get().to(get_maintenance_budgets))
+            .route("/api/maintenance/{id}", web::put().to(update_maintenance_request))
+            .route("/api/reports/maintenance-costs", web::get().to(get_maintenance_cost_report))
+            // Room change routes
+            .route("/api/room-changes", web::post().to(request_room_change))
+            .route("/api/room-changes", web::get().to(get_room_changes))
+            .route("/api/room-changes/{id}/approve", web::post().to(approve_room_change))
+            .route("/api/room-changes/{id}/reject", web::post().to(reject_room_change))
+            .route("/api/room-changes/{id}/cancel", web::post().to(cancel_room_change))
+            // Gate routes
+            .route("/api/gate-passes", web::post().to(request_gate_pass))
+            .route("/api/gate-passes", web::get().to(get_gate_passes))
+            .route("/api/gate-passes/{id}/approve", web::post().to(approve_gate_pass))
+            .route("/api/gate-passes/{id}/reject", web::post().to(reject_gate_pass))
+            .route("/api/gate-passes/{id}/cancel", web::post().to(cancel_gate_pass))
+            .route("/api/gate-passes/{id}/validate", web::get().to(validate_gate_pass))
+            // Event routes
+            .route("/api/events/deliveries", web::get().to(get_event_deliveries))
+            .route("/api/events/deliveries/{id}/retry", web::post().to(retry_event_delivery))
+            .route("/api/movements", web::post().to(record_movement))
+            .route("/api/movements", web::get().to(get_movements))
+            .route("/api/reports/outside", web::get().to(get_outside_report))
+            // Gate device routes
+            .route("/api/entry-logs/ingest", web::post().to(ingest_entry_logs))
+            .route("/api/entry-logs", web::get().to(get_entry_logs))
+            .route("/api/reports/occupancy", web::get().to(get_occupancy_report))
+            .route("/api/reports/capacity-plan", web::post().to(plan_capacity))
+            // Roll call routes
+            .route("/api/roll-call", web::post().to(mark_roll_call))
+            .route("/api/roll-call", web::get().to(get_roll_call))
+            .route("/api/roll-call/report", web::get().to(get_roll_call_report))
+            .route("/api/alerts", web::get().to(get_alerts))
+            .route("/api/alerts/{id}/acknowledge", web::post().to(acknowledge_alert))
+            // Discipline routes
+            .route("/api/disciplinary/incidents", web::post().to(create_incident))
+            .route("/api/disciplinary/incidents", web::get().to(get_incidents))
+            .route("/api/disciplinary/incidents/{id}/actions", web::post().to(take_disciplinary_action))
+            .route("/api/disciplinary/incidents/{id}/close", web::post().to(close_incident))
+            .route("/api/students/{student_id}/allocations", web::get().to(get_student_allocations))
+            .route("/api/students/{student_id}/disciplinary", web::get().to(get_student_disciplinary_history))
+            // Mess routes
+            .route("/api/mess/menus", web::put().to(upsert_mess_menu))
+            .route("/api/mess/menus", web::get().to(get_mess_menu))
+            .route("/api/mess/plans", web::post().to(subscribe_meal_plan))
+            .route("/api/mess/plans/{student_id}", web::get().to(get_meal_plan))
+            .route("/api/mess/plans/{student_id}", web::put().to(update_meal_plan))
+            .route("/api/mess/plans/{student_id}", web::delete().to(cancel_meal_plan))
+            .route("/api/mess/plans/{student_id}/opt-outs", web::post().to(add_meal_opt_out))
+            .route("/api/mess/plans/{student_id}/opt-outs", web::delete().to(remove_meal_opt_out))
+            .route("/api/mess/attendance", web::post().to(mark_meal_attendance))
+            .route("/api/mess/attendance", web::get().to(get_meal_attendance))
+            .route("/api/mess/bills/generate", web::post().to(generate_mess_bills))
+            .route("/api/mess/bills", web::get().to(get_mess_bills))
+            .route("/api/mess/feedback", web::post().to(submit_meal_feedback))
+            .route("/api/mess/feedback/summary", web::get().to(get_meal_feedback_summary))
+            .route("/api/mess/feedback/trend", web::get().to(get_meal_feedback_trend))
             // Student Dashboard routes
             .route("/api/student/hostel-status/{student_id}", web::get().to(get_student_hostel_status))
     })
@@ -435,3 +7485,206 @@ async fn main() -> std::io::Result<()> {
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn room(number: &str) -> Room {
+        Room {
+            id: Some(ObjectId::new()),
+            room_number: number.to_string(),
+            hostel_id: None,
+            hostel_name: "North Block".to_string(),
+            capacity: 2,
+            occupied: 0,
+            room_type: "double".to_string(),
+            floor: 1,
+            campus_id: "campus-1".to_string(),
+            created_at: Utc::now(),
+            out_of_service: false,
+        }
+    }
+
+    fn billed_allocation() -> RoomAllocation {
+        let mut allocation = RoomAllocation {
+            id: Some(ObjectId::new()),
+            student_id: "student-1".to_string(),
+            room_id: String::new(),
+            hostel_name: "North Block".to_string(),
+            room_number: "101".to_string(),
+            allocation_date: Utc::now(),
+            status: "active".to_string(),
+            campus_id: "campus-1".to_string(),
+            vacated_at: None,
+            duration_months: Some(10),
+            fee_amount: Some(50000.0),
+            fee_id: Some(ObjectId::new().to_hex()),
+            fee_status: Some("pending".to_string()),
+            checked_in_at: None,
+            vacate_reason: None,
+            transferred_from: None,
+            billing_reference_id: None,
+        };
+        allocation.billing_reference_id = Some(allocation.billing_reference());
+        allocation
+    }
+
+    #[test]
+    fn transfer_before_check_in_keeps_the_billed_reference() {
+        let original = billed_allocation();
+        let billed_under = original.id.unwrap().to_hex();
+
+        let mut moved = original.transferred_to(&room("202"), Utc::now());
+        moved.id = Some(ObjectId::new());
+        let mut moved_again = moved.transferred_to(&room("303"), Utc::now());
+        moved_again.id = Some(ObjectId::new());
+
+        // Check-in asks finance for the fee under this reference
+        for allocation in [&moved, &moved_again] {
+            assert_eq!(allocation.billing_reference(), billed_under);
+            assert_eq!(allocation.fee_id, original.fee_id);
+            assert!(allocation.checked_in_at.is_none());
+        }
+        assert_eq!(moved_again.transferred_from, moved.id.map(|id| id.to_hex()));
+    }
+
+    #[test]
+    fn legacy_allocations_resolve_their_billing_reference() {
+        let mut original = billed_allocation();
+        original.billing_reference_id = None;
+        assert_eq!(original.billing_reference(), original.id.unwrap().to_hex());
+
+        // Transferred before billing_reference_id was stored
+        let mut moved = original.transferred_to(&room("202"), Utc::now());
+        moved.id = Some(ObjectId::new());
+        moved.billing_reference_id = None;
+        assert_eq!(moved.billing_reference(), original.id.unwrap().to_hex());
+    }
+
+    #[test]
+    fn surplus_allocations_keep_the_checked_in_stay() {
+        let mut first = billed_allocation();
+        first.allocation_date = Utc::now() - chrono::Duration::days(2);
+        let mut checked_in = billed_allocation();
+        checked_in.checked_in_at = Some(Utc::now());
+        let later = billed_allocation();
+
+        let closed = surplus_allocations(vec![later.clone(), checked_in.clone(), first.clone()]);
+        let closed_ids: Vec<_> = closed.iter().map(|a| a.id).collect();
+        assert_eq!(closed_ids.len(), 2);
+        assert!(!closed_ids.contains(&checked_in.id));
+
+        // Without a check-in the earliest allocation is kept
+        let closed = surplus_allocations(vec![later.clone(), first.clone()]);
+        assert_eq!(closed.iter().map(|a| a.id).collect::<Vec<_>>(), vec![later.id]);
+        assert!(surplus_allocations(vec![first]).is_empty());
+    }
+
+    #[test]
+    fn transfer_after_check_in_stays_checked_in() {
+        let mut original = billed_allocation();
+        original.checked_in_at = Some(Utc::now());
+        assert!(original.transferred_to(&room("202"), Utc::now()).checked_in_at.is_some());
+    }
+
+    fn application(student_id: &str, year_of_study: i32, merit_score: Option<f64>) -> AllocationApplication {
+        AllocationApplication {
+            student_id: student_id.to_string(),
+            year_of_study,
+            merit_score,
+            gender: None,
+            hostel_id: None,
+            room_type: None,
+            roommate_id: None,
+        }
+    }
+
+    fn student_ids(applications: &[AllocationApplication]) -> Vec<&str> {
+        applications.iter().map(|a| a.student_id.as_str()).collect()
+    }
+
+    #[test]
+    fn seniority_puts_later_years_first_and_keeps_ties_in_order() {
+        let mut applications = vec![
+            application("a", 1, None),
+            application("b", 3, None),
+            application("c", 2, None),
+            application("d", 3, None),
+        ];
+        order_applications(&mut applications, "seniority", 0);
+        assert_eq!(student_ids(&applications), ["b", "d", "c", "a"]);
+    }
+
+    #[test]
+    fn merit_ranks_by_score_with_missing_scores_last() {
+        let mut applications = vec![
+            application("a", 1, Some(71.5)),
+            application("b", 4, None),
+            application("c", 2, Some(88.0)),
+            application("d", 3, Some(71.5)),
+        ];
+        order_applications(&mut applications, "merit", 0);
+        assert_eq!(student_ids(&applications), ["c", "a", "d", "b"]);
+    }
+
+    #[test]
+    fn lottery_is_a_permutation_reproducible_from_its_seed() {
+        let applications: Vec<_> = (0..20).map(|i| application(&format!("s{:02}", i), 1, None)).collect();
+        let draw = |seed: i64| {
+            let mut drawn = applications.clone();
+            order_applications(&mut drawn, "lottery", seed);
+            student_ids(&drawn).into_iter().map(str::to_string).collect::<Vec<_>>()
+        };
+
+        let first = draw(42);
+        assert_eq!(first, draw(42));
+        assert_ne!(first, draw(43));
+        let mut sorted = first.clone();
+        sorted.sort();
+        assert_eq!(sorted, student_ids(&applications));
+    }
+
+    #[test]
+    fn plan_pairs_mutual_roommates_and_respects_gender_policy() {
+        let hostel_id = ObjectId::new().to_hex();
+        let mut womens = room("101");
+        womens.hostel_id = Some(hostel_id.clone());
+        let mut rooms = vec![womens, room("102")];
+        let policies = HashMap::from([(hostel_id, "female".to_string())]);
+
+        let mut ana = application("ana", 2, None);
+        ana.gender = Some("female".to_string());
+        ana.roommate_id = Some("bea".to_string());
+        let mut bea = application("bea", 2, None);
+        bea.gender = Some("female".to_string());
+        bea.roommate_id = Some("ana".to_string());
+        let mut carl = application("carl", 3, None);
+        carl.gender = Some("male".to_string());
+        // Names ana, but ana did not name him back
+        carl.roommate_id = Some("ana".to_string());
+
+        let (assignments, unassigned) = plan_allocation(&[carl, ana, bea], &mut rooms, &policies);
+        let placed: Vec<_> = assignments
+            .iter()
+            .map(|a| (a.student_id.as_str(), a.room_number.as_str(), a.roommate_id.as_deref()))
+            .collect();
+        assert_eq!(placed, [
+            ("carl", "102", None),
+            ("ana", "101", Some("bea")),
+            ("bea", "101", Some("ana")),
+        ]);
+        assert!(unassigned.is_empty());
+        assert_eq!(rooms.iter().map(|r| r.occupied).collect::<Vec<_>>(), [2, 1]);
+    }
+
+    #[test]
+    fn plan_reports_students_no_room_admits() {
+        let mut rooms = vec![room("101")];
+        rooms[0].occupied = 2;
+        let (assignments, unassigned) = plan_allocation(&[application("a", 1, None)], &mut rooms, &HashMap::new());
+        assert!(assignments.is_empty());
+        assert_eq!(unassigned.len(), 1);
+        assert_eq!(unassigned[0].student_id, "a");
+    }
+}
@@ -2,8 +2,8 @@ use actix_web::{web, App, HttpServer, HttpResponse, HttpRequest, Error, middlewa
 use actix_cors::Cors;
 use mongodb::{Client, Collection, bson::{doc, oid::ObjectId}};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use chrono::{DateTime, Utc};
+use campus_auth::{load_jwt_keys, reload_jwt_keys, SharedKeySet, JWT_KEY_REFRESH_SECS};
+use chrono::{DateTime, Datelike, Local, NaiveDate, NaiveDateTime, NaiveTime, Timelike, Utc, Weekday};
 use std::env;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,6 +12,7 @@ struct Claims {
     role: String,
     campus_id: String,
     exp: usize,
+    iat: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -46,8 +47,11 @@ struct LeaveRequest {
     id: Option<ObjectId>,
     employee_id: String,
     leave_type: String, // sick, casual, vacation
-    from_date: String,
+    from_date: String, // YYYY-MM-DD
     to_date: String,
+    // Days the employee would otherwise have worked: weekends and holidays are left out
+    #[serde(default)]
+    working_days: i64,
     reason: String,
     status: String, // pending, approved, rejected
     campus_id: String,
@@ -63,6 +67,29 @@ struct LeaveRequestData {
     reason: String,
 }
 
+const LEAVE_TYPES: [&str; 3] = ["sick", "casual", "vacation"];
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Holiday {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    date: NaiveDate,
+    name: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct HolidayRequest {
+    date: NaiveDate,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HolidayQuery {
+    year: Option<i32>,
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 struct LeaveApproval {
     request_id: String,
@@ -81,7 +108,24 @@ struct Payroll {
     allowances: f64,
     deductions: f64,
     net_salary: f64,
+    // Loss of pay from attendance, already taken out of net_salary
+    #[serde(default)]
+    lop_days: f64,
+    #[serde(default)]
+    lop_deduction: f64,
+    // Income tax withheld this month; part of deductions
+    #[serde(default)]
+    tds: f64,
     payment_status: String, // pending, paid
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paid_on: Option<NaiveDate>,
+    // Bank transaction or batch reference
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payment_reference: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paid_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    paid_at: Option<DateTime<Utc>>,
     campus_id: String,
     created_at: DateTime<Utc>,
 }
@@ -93,33 +137,519 @@ struct PayrollRequest {
     year: i32,
     allowances: f64,
     deductions: f64,
+    // Overrides the loss-of-pay days worked out from attendance
+    lop_days: Option<f64>,
+}
+
+// One per employee per day. Times are local wall-clock times, as punched.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Attendance {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    employee_id: String,
+    date: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    check_in: Option<NaiveTime>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    check_out: Option<NaiveTime>,
+    status: String, // present, late, half_day, absent
+    #[serde(default)]
+    worked_minutes: i64,
+    source: String, // manual, device
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    device_id: Option<String>,
+    campus_id: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AttendancePunch {
+    employee_id: String,
+    // Local date and time; defaults to now. Only HR may set it.
+    at: Option<NaiveDateTime>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DevicePunch {
+    employee_id: String,
+    timestamp: NaiveDateTime,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DevicePunchBatch {
+    device_id: String,
+    punches: Vec<DevicePunch>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttendanceQuery {
+    employee_id: Option<String>,
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AttendanceSummaryQuery {
+    employee_id: Option<String>,
+    year: Option<i32>,
+    month: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct AttendanceSummary {
+    employee_id: String,
+    year: i32,
+    month: u32,
+    // Working days in the whole month, and those up to today
+    working_days: i64,
+    days_counted: i64,
+    present: i64,
+    late: i64,
+    half_day: i64,
+    absent: i64,
+    on_leave: i64,
+    // Checked in but never out; counted as worked until HR corrects them
+    missing_checkout: i64,
+    // Days with any attendance record, working day or not
+    recorded_days: i64,
+    lop_days: f64,
+}
+
+// Late and half-day thresholds, from the environment
+struct AttendanceRules {
+    office_start: NaiveTime,
+    late_grace_minutes: i64,
+    half_day_min_hours: f64,
+    full_day_min_hours: f64,
+    // Every this many late days cost half a day; 0 turns it off
+    lates_per_half_day: i64,
+}
+
+// Where an employee's salary is credited. Kept apart from the faculty record,
+// which any signed-in user can list.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct BankAccount {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    employee_id: String,
+    account_name: String,
+    account_number: String,
+    ifsc: String,
+    campus_id: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct BankAccountRequest {
+    account_name: String,
+    account_number: String,
+    ifsc: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PaymentRequest {
+    // Date the bank credited the salary; defaults to today
+    paid_on: Option<NaiveDate>,
+    reference: String,
+}
+
+// Either explicit ids or a whole month's batch
+#[derive(Debug, Serialize, Deserialize)]
+struct BulkPaymentRequest {
+    payroll_ids: Option<Vec<String>>,
+    month: Option<String>,
+    year: Option<i32>,
+    paid_on: Option<NaiveDate>,
+    reference: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct PayrollExportQuery {
+    month: String,
+    year: i32,
+    /// "csv" (default) or "neft"
+    format: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TaxSlab {
+    // Upper bound of taxable income for this rate; None for the top slab
+    up_to: Option<f64>,
+    rate: f64, // percent
+}
+
+// Income tax rules of one regime for one financial year (April to March)
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TaxSlabTable {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    financial_year: String, // e.g. 2024-25
+    regime: String,         // new, old
+    slabs: Vec<TaxSlab>,
+    #[serde(default)]
+    standard_deduction: f64,
+    // No tax is due on taxable income up to this amount (section 87A rebate)
+    #[serde(default)]
+    rebate_limit: f64,
+    #[serde(default)]
+    cess_rate: f64, // percent, on top of the slab tax
+    // Most that can be claimed per declaration section, e.g. {"80C": 150000};
+    // sections not listed cannot be claimed under this regime
+    #[serde(default)]
+    deduction_limits: std::collections::HashMap<String, f64>,
+    campus_id: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TaxSlabTableRequest {
+    financial_year: String,
+    regime: String,
+    slabs: Vec<TaxSlab>,
+    standard_deduction: Option<f64>,
+    rebate_limit: Option<f64>,
+    cess_rate: Option<f64>,
+    deduction_limits: Option<std::collections::HashMap<String, f64>>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct DeclarationItem {
+    section: String, // e.g. 80C, 80D, HRA
+    description: String,
+    amount: f64,
+}
+
+// An employee's chosen regime and declared investments and exemptions for a year
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct TaxDeclaration {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    employee_id: String,
+    financial_year: String,
+    regime: String,
+    items: Vec<DeclarationItem>,
+    campus_id: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct TaxDeclarationRequest {
+    financial_year: String,
+    regime: String,
+    items: Vec<DeclarationItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FinancialYearQuery {
+    financial_year: Option<String>,
+}
+
+const TAX_REGIMES: [&str; 2] = ["new", "old"];
+
+// A file in an employee's record, kept in the employee_documents GridFS bucket
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EmployeeDocument {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    employee_id: String,
+    doc_type: String, // contract, certificate, id, visa, passport, other
+    title: String,
+    file_id: ObjectId,
+    filename: String,
+    content_type: String,
+    size: i64,
+    // Visas, passports and certifications lapse; contracts and degrees usually don't
+    #[serde(default)]
+    expires_on: Option<NaiveDate>,
+    // Set once the expiry scan has flagged the document; cleared when the expiry changes
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expiry_alerted_at: Option<DateTime<Utc>>,
+    uploaded_by: String,
+    campus_id: String,
+    uploaded_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct DocumentUpdateRequest {
+    title: Option<String>,
+    doc_type: Option<String>,
+    expires_on: Option<NaiveDate>,
+    // Drops the expiry date, e.g. for a document uploaded under the wrong type
+    #[serde(default)]
+    clear_expiry: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct DocumentQuery {
+    doc_type: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpiringDocumentQuery {
+    days: Option<i64>,
+    employee_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct ExpiringDocument {
+    #[serde(flatten)]
+    document: EmployeeDocument,
+    // Negative once the document has lapsed
+    days_left: i64,
+}
+
+const DOCUMENT_TYPES: [&str; 6] = ["contract", "certificate", "id", "visa", "passport", "other"];
+
+// Something reviewers score an employee on, from 1 to RATING_SCALE
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Kpi {
+    code: String,
+    name: String,
+    #[serde(default)]
+    description: String,
+    // Share of the overall score; the weights of a cycle add up to 100
+    weight: f64,
+}
+
+// One round of appraisals, e.g. "2024-25 annual", with the KPIs everyone in it is scored on
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AppraisalCycle {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    self_assessment_due: NaiveDate,
+    review_due: NaiveDate,
+    kpis: Vec<Kpi>,
+    // Suggested increment percent for each final rating
+    #[serde(default)]
+    increment_guide: std::collections::HashMap<String, f64>,
+    status: String, // draft, open, closed
+    created_by: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AppraisalCycleRequest {
+    name: String,
+    period_start: NaiveDate,
+    period_end: NaiveDate,
+    self_assessment_due: NaiveDate,
+    review_due: NaiveDate,
+    kpis: Vec<Kpi>,
+    #[serde(default)]
+    increment_guide: std::collections::HashMap<String, f64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenCycleRequest {
+    // Defaults to every faculty member on the campus
+    employee_ids: Option<Vec<String>>,
+    // Employee id to the employee id of their reviewer
+    #[serde(default)]
+    reviewers: std::collections::HashMap<String, String>,
 }
 
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct KpiScore {
+    kpi: String, // KPI code
+    score: i64,
+    #[serde(default)]
+    comment: String,
+}
+
+// One employee's appraisal in a cycle
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Appraisal {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    cycle_id: ObjectId,
+    employee_id: String,
+    reviewer_id: Option<String>,
+    status: String, // pending_self, self_submitted, reviewed, finalized
+    #[serde(default)]
+    self_scores: Vec<KpiScore>,
+    #[serde(default)]
+    self_comments: String,
+    self_submitted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    review_scores: Vec<KpiScore>,
+    #[serde(default)]
+    reviewer_comments: String,
+    reviewed_at: Option<DateTime<Utc>>,
+    // Weighted average of the reviewer's scores
+    weighted_score: Option<f64>,
+    final_rating: Option<String>,
+    #[serde(default)]
+    final_comments: String,
+    finalized_by: Option<String>,
+    finalized_at: Option<DateTime<Utc>>,
+    increment_id: Option<ObjectId>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct AppraisalScoresRequest {
+    scores: Vec<KpiScore>,
+    #[serde(default)]
+    comments: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ReviewerRequest {
+    reviewer_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct FinalizeAppraisalRequest {
+    // Defaults to the rating the weighted score falls in
+    final_rating: Option<String>,
+    #[serde(default)]
+    comments: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AppraisalQuery {
+    cycle_id: Option<String>,
+    employee_id: Option<String>,
+    reviewer_id: Option<String>,
+    status: Option<String>,
+}
+
+// A salary increment decided on a finalized appraisal. Recorded once it takes
+// effect: the faculty salary, and so payroll, changes with it.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Increment {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    employee_id: String,
+    appraisal_id: ObjectId,
+    cycle_id: ObjectId,
+    previous_salary: f64,
+    percent: f64,
+    new_salary: f64,
+    effective_from: NaiveDate,
+    #[serde(default)]
+    remarks: String,
+    decided_by: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct IncrementRequest {
+    // Defaults to the cycle's increment guide for the final rating
+    percent: Option<f64>,
+    // Defaults to today
+    effective_from: Option<NaiveDate>,
+    #[serde(default)]
+    remarks: String,
+}
+
+const RATING_SCALE: i64 = 5;
+// Lowest weighted score for each final rating, best first
+const RATING_BANDS: [(&str, f64); 5] = [
+    ("outstanding", 4.5),
+    ("exceeds_expectations", 3.5),
+    ("meets_expectations", 2.5),
+    ("needs_improvement", 1.5),
+    ("unsatisfactory", 0.0),
+];
+
 struct AppState {
     db: mongodb::Database,
-    jwt_secret: String,
+    // Verification keys by kid, reloaded from the jwt_keys collection
+    jwt_keys: SharedKeySet,
+    // Days of the week nobody works, from WEEKEND_DAYS
+    weekend: Vec<Weekday>,
+    attendance_rules: AttendanceRules,
+    // Institution account salaries are paid from, for NEFT files
+    payroll_debit_account: Option<String>,
+    // How far ahead document expiries are flagged, from DOCUMENT_EXPIRY_ALERT_DAYS
+    document_alert_days: i64,
 }
 
-fn extract_claims(req: &HttpRequest, jwt_secret: &str) -> Result<Claims, String> {
+fn extract_claims(req: &HttpRequest, jwt_keys: &SharedKeySet) -> Result<Claims, String> {
     if let Some(auth_header) = req.headers().get("Authorization") {
         if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let token = &auth_str[7..];
-                
-                match decode::<Claims>(
-                    token,
-                    &DecodingKey::from_secret(jwt_secret.as_bytes()),
-                    &Validation::new(Algorithm::HS256),
-                ) {
-                    Ok(token_data) => return Ok(token_data.claims),
-                    Err(_) => return Err("Invalid token".to_string()),
-                }
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                let keys = jwt_keys.read().expect("JWT key set lock poisoned");
+                return keys.decode(token).ok_or_else(|| "Invalid token".to_string());
             }
         }
     }
     Err("No token provided".to_string())
 }
 
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref we)) if we.code == 11000
+    )
+}
+
+fn is_hr(claims: &Claims) -> bool {
+    claims.role == "hr" || claims.role == "admin"
+}
+
+fn parse_date(value: &str, field: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(value.trim(), "%Y-%m-%d")
+        .map_err(|_| format!("{} must be a date in YYYY-MM-DD format", field))
+}
+
+// Parses WEEKEND_DAYS, e.g. "saturday,sunday"; unknown names are skipped
+fn parse_weekend(raw: &str) -> Vec<Weekday> {
+    raw.split(',')
+        .filter_map(|d| d.trim().parse().ok())
+        .collect()
+}
+
+// Holidays are stored as YYYY-MM-DD strings, so the range compares strings
+async fn holidays_between(
+    db: &mongodb::Database,
+    campus_id: &str,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> mongodb::error::Result<std::collections::HashSet<NaiveDate>> {
+    use futures::stream::StreamExt;
+
+    let collection: Collection<Holiday> = db.collection("holidays");
+    let mut cursor = collection
+        .find(
+            doc! {
+                "campus_id": campus_id,
+                "date": { "$gte": from.to_string(), "$lte": to.to_string() }
+            },
+            None,
+        )
+        .await?;
+    let mut dates = std::collections::HashSet::new();
+    while let Some(holiday) = cursor.next().await {
+        dates.insert(holiday?.date);
+    }
+    Ok(dates)
+}
+
+// Counts the days from `from` to `to`, inclusive, that are neither weekend days nor holidays
+fn working_days(
+    from: NaiveDate,
+    to: NaiveDate,
+    weekend: &[Weekday],
+    holidays: &std::collections::HashSet<NaiveDate>,
+) -> i64 {
+    from.iter_days()
+        .take_while(|d| *d <= to)
+        .filter(|d| !weekend.contains(&d.weekday()) && !holidays.contains(d))
+        .count() as i64
+}
+
 async fn health_check() -> HttpResponse {
     HttpResponse::Ok().json(serde_json::json!({
         "status": "ok",
@@ -133,8 +663,8 @@ async fn add_faculty(
     req: HttpRequest,
     faculty_data: web::Json<FacultyRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     let collection: Collection<Faculty> = data.db.collection("faculty");
 
@@ -154,7 +684,7 @@ async fn add_faculty(
     collection
         .insert_one(new_faculty, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "Faculty added successfully"
@@ -165,15 +695,15 @@ async fn get_faculty(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     let collection: Collection<Faculty> = data.db.collection("faculty");
 
     let mut cursor = collection
         .find(doc! { "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let mut faculty_list = Vec::new();
     use futures::stream::StreamExt;
@@ -185,93 +715,2772 @@ async fn get_faculty(
         }
     }
 
-    Ok(HttpResponse::Ok().json(faculty_list))
+    Ok(HttpResponse::Ok().json(faculty_list))
+}
+
+// Bank Accounts
+// IFSC: four letters for the bank, a zero, then six characters for the branch
+fn valid_ifsc(ifsc: &str) -> bool {
+    let bytes = ifsc.as_bytes();
+    bytes.len() == 11
+        && bytes[..4].iter().all(u8::is_ascii_uppercase)
+        && bytes[4] == b'0'
+        && bytes[5..].iter().all(u8::is_ascii_alphanumeric)
+}
+
+async fn set_bank_account(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    account_data: web::Json<BankAccountRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let employee_id = path.into_inner();
+    if !faculty_exists(&data.db, &employee_id, &claims.campus_id).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    }
+
+    let account_number: String = account_data.account_number.chars().filter(|c| !c.is_whitespace()).collect();
+    if !(9..=18).contains(&account_number.len()) || !account_number.chars().all(|c| c.is_ascii_digit()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "account_number must be 9 to 18 digits"
+        })));
+    }
+    let ifsc = account_data.ifsc.trim().to_uppercase();
+    if !valid_ifsc(&ifsc) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "ifsc must be an 11-character IFSC code, e.g. SBIN0001234"
+        })));
+    }
+    if account_data.account_name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "account_name is required"
+        })));
+    }
+
+    let collection: Collection<BankAccount> = data.db.collection("bank_accounts");
+    collection
+        .update_one(
+            doc! { "campus_id": &claims.campus_id, "employee_id": &employee_id },
+            doc! { "$set": {
+                "account_name": account_data.account_name.trim(),
+                "account_number": &account_number,
+                "ifsc": &ifsc,
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Bank account saved successfully"
+    })))
+}
+
+async fn get_bank_account(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let collection: Collection<BankAccount> = data.db.collection("bank_accounts");
+    let account = collection
+        .find_one(doc! { "campus_id": &claims.campus_id, "employee_id": path.into_inner() }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match account {
+        Some(account) => Ok(HttpResponse::Ok().json(account)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No bank account on record"
+        }))),
+    }
+}
+
+// Employee Documents
+// Largest file accepted by the upload endpoint
+const DOCUMENT_MAX_BYTES: usize = 10 * 1024 * 1024;
+// Largest accepted value for a plain-text multipart field
+const DOCUMENT_MAX_FIELD_BYTES: usize = 1024;
+// Scans and PDFs only; anything else is refused rather than served back later
+const DOCUMENT_CONTENT_TYPES: [(&str, &str); 4] = [
+    ("pdf", "application/pdf"),
+    ("jpg", "image/jpeg"),
+    ("jpeg", "image/jpeg"),
+    ("png", "image/png"),
+];
+
+fn documents_bucket(db: &mongodb::Database) -> mongodb::gridfs::GridFsBucket {
+    db.gridfs_bucket(
+        mongodb::options::GridFsBucketOptions::builder()
+            .bucket_name("employee_documents".to_string())
+            .build(),
+    )
+}
+
+// HR sees every employee's documents; staff see their own, their username
+// being their employee id
+fn can_access_documents(claims: &Claims, employee_id: &str) -> bool {
+    is_hr(claims) || claims.sub == employee_id
+}
+
+// The stored content type comes from the file extension, not the client
+fn document_content_type(filename: &str) -> Option<&'static str> {
+    let extension = filename.rsplit_once('.')?.1.to_lowercase();
+    DOCUMENT_CONTENT_TYPES
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, content_type)| *content_type)
+}
+
+async fn find_document(db: &mongodb::Database, id: &str, campus_id: &str) -> Result<Option<EmployeeDocument>, Error> {
+    let id = ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<EmployeeDocument> = db.collection("employee_documents");
+    collection
+        .find_one(doc! { "_id": id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Documents with an expiry date up to `until`, soonest first. Lapsed ones are included.
+async fn expiring_documents(
+    db: &mongodb::Database,
+    campus_id: Option<&str>,
+    employee_id: Option<&str>,
+    until: NaiveDate,
+) -> mongodb::error::Result<Vec<EmployeeDocument>> {
+    use futures::stream::StreamExt;
+
+    // Dates are stored as YYYY-MM-DD, so the cut-off compares strings
+    let mut filter = doc! { "expires_on": { "$lte": until.to_string() } };
+    if let Some(campus_id) = campus_id {
+        filter.insert("campus_id", campus_id);
+    }
+    if let Some(employee_id) = employee_id {
+        filter.insert("employee_id", employee_id);
+    }
+
+    let collection: Collection<EmployeeDocument> = db.collection("employee_documents");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "expires_on": 1 })
+        .build();
+    let mut cursor = collection.find(filter, options).await?;
+
+    let mut documents = Vec::new();
+    while let Some(result) = cursor.next().await {
+        documents.push(result?);
+    }
+    Ok(documents)
+}
+
+// Logs each document coming up for expiry once, across all campuses, and
+// stamps it so the next scan skips it
+async fn scan_expiring_documents(db: &mongodb::Database, alert_days: i64) -> mongodb::error::Result<usize> {
+    let today = Local::now().date_naive();
+    let documents = expiring_documents(db, None, None, today + chrono::Duration::days(alert_days)).await?;
+
+    let collection: Collection<EmployeeDocument> = db.collection("employee_documents");
+    let mut alerted = 0;
+    for document in documents.iter().filter(|d| d.expiry_alerted_at.is_none()) {
+        let (Some(id), Some(expires_on)) = (document.id, document.expires_on) else {
+            continue;
+        };
+        log::warn!(
+            "Document expiring: {} '{}' of employee {} (campus {}) expires on {}",
+            document.doc_type, document.title, document.employee_id, document.campus_id, expires_on
+        );
+        collection
+            .update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "expiry_alerted_at": mongodb::bson::to_bson(&Utc::now())? } },
+                None,
+            )
+            .await?;
+        alerted += 1;
+    }
+    Ok(alerted)
+}
+
+async fn upload_document(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    mut payload: actix_multipart::Multipart,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    let employee_id = path.into_inner();
+
+    if !can_access_documents(&claims, &employee_id) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if !faculty_exists(&data.db, &employee_id, &claims.campus_id).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    }
+
+    use futures::io::AsyncWriteExt;
+    use futures::stream::StreamExt;
+
+    let invalid_body = |e: actix_multipart::MultipartError| {
+        actix_web::error::ErrorBadRequest(format!("Invalid multipart body: {}", e))
+    };
+
+    let bucket = documents_bucket(&data.db);
+    let mut fields: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut upload: Option<(ObjectId, String, &'static str, usize)> = None;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(invalid_body)?;
+        let name = field.name().unwrap_or_default().to_string();
+
+        if name == "file" {
+            if upload.is_some() {
+                return Err(actix_web::error::ErrorBadRequest("Only one file can be uploaded at a time"));
+            }
+            let filename = field
+                .content_disposition()
+                .and_then(|cd| cd.get_filename())
+                .map(|f| f.rsplit(['/', '\\']).next().unwrap_or(f).to_string())
+                .filter(|f| !f.trim().is_empty())
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("The file part needs a filename"))?;
+            let content_type = document_content_type(&filename)
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("Only PDF, JPEG and PNG files can be uploaded"))?;
+
+            let mut stream = bucket.open_upload_stream(&filename, None);
+            let file_id = stream
+                .id()
+                .as_object_id()
+                .ok_or_else(|| actix_web::error::ErrorInternalServerError("GridFS file id is not an ObjectId"))?;
+            let mut size = 0;
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(invalid_body)?;
+                size += chunk.len();
+                if size > DOCUMENT_MAX_BYTES {
+                    stream.abort().await.map_err(actix_web::error::ErrorInternalServerError)?;
+                    return Err(actix_web::error::ErrorBadRequest(format!(
+                        "File exceeds the {} MB limit",
+                        DOCUMENT_MAX_BYTES / (1024 * 1024)
+                    )));
+                }
+                stream.write_all(&chunk).await.map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            stream.close().await.map_err(actix_web::error::ErrorInternalServerError)?;
+            upload = Some((file_id, filename, content_type, size));
+        } else {
+            let mut value = Vec::new();
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(invalid_body)?;
+                value.extend_from_slice(&chunk);
+                if value.len() > DOCUMENT_MAX_FIELD_BYTES {
+                    return Err(actix_web::error::ErrorBadRequest(format!("Field '{}' is too long", name)));
+                }
+            }
+            let value = String::from_utf8(value)
+                .map_err(|_| actix_web::error::ErrorBadRequest(format!("Field '{}' must be UTF-8 text", name)))?;
+            fields.insert(name, value);
+        }
+    }
+
+    let Some((file_id, filename, content_type, size)) = upload else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'file' is required"
+        })));
+    };
+
+    // Validate the text fields only now that the whole body has been read,
+    // and drop the stored file if they are rejected
+    let doc_type = fields.get("doc_type").map(|t| t.trim().to_lowercase()).unwrap_or_default();
+    let title = fields
+        .get("title")
+        .map(|t| t.trim().to_string())
+        .filter(|t| !t.is_empty())
+        .unwrap_or_else(|| filename.clone());
+    let expires_on = match fields.get("expires_on").map(|d| d.trim()).filter(|d| !d.is_empty()) {
+        Some(value) => parse_date(value, "expires_on").map(Some),
+        None => Ok(None),
+    };
+    let rejected = if !DOCUMENT_TYPES.contains(&doc_type.as_str()) {
+        Some(format!("doc_type must be one of: {}", DOCUMENT_TYPES.join(", ")))
+    } else {
+        expires_on.as_ref().err().cloned()
+    };
+    if let Some(e) = rejected {
+        bucket
+            .delete(file_id.into())
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let document = EmployeeDocument {
+        id: None,
+        employee_id,
+        doc_type,
+        title,
+        file_id,
+        filename,
+        content_type: content_type.to_string(),
+        size: size as i64,
+        expires_on: expires_on.unwrap_or_default(),
+        expiry_alerted_at: None,
+        uploaded_by: claims.sub,
+        campus_id: claims.campus_id,
+        uploaded_at: Utc::now(),
+        updated_at: None,
+    };
+
+    let collection: Collection<EmployeeDocument> = data.db.collection("employee_documents");
+    let result = collection
+        .insert_one(&document, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Document uploaded successfully",
+        "id": result.inserted_id
+    })))
+}
+
+async fn get_documents(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<DocumentQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    let employee_id = path.into_inner();
+
+    if !can_access_documents(&claims, &employee_id) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id, "employee_id": &employee_id };
+    if let Some(doc_type) = query.doc_type.as_deref().filter(|t| !t.is_empty()) {
+        filter.insert("doc_type", doc_type);
+    }
+
+    let collection: Collection<EmployeeDocument> = data.db.collection("employee_documents");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "uploaded_at": -1 })
+        .build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut documents = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(document) => documents.push(document),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(documents))
+}
+
+async fn download_document(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    // Someone else's document answers 404 as well, so ids can't be probed
+    let document = find_document(&data.db, &path, &claims.campus_id)
+        .await?
+        .filter(|d| can_access_documents(&claims, &d.employee_id));
+    let Some(document) = document else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Document not found"
+        })));
+    };
+
+    let mut bytes = Vec::with_capacity(document.size.max(0) as usize);
+    documents_bucket(&data.db)
+        .download_to_futures_0_3_writer(document.file_id.into(), &mut bytes)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(document.content_type.as_str())
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", document.filename.replace('"', "")),
+        ))
+        .body(bytes))
+}
+
+// Corrects the title or type, or records a renewed expiry date
+async fn update_document(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<DocumentUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let Some(document) = find_document(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Document not found"
+        })));
+    };
+
+    let mut set = doc! { "updated_at": mongodb::bson::to_bson(&Utc::now()).map_err(actix_web::error::ErrorInternalServerError)? };
+    let mut unset = doc! {};
+    if let Some(title) = &body.title {
+        if title.trim().is_empty() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "title must not be blank"
+            })));
+        }
+        set.insert("title", title.trim());
+    }
+    if let Some(doc_type) = &body.doc_type {
+        let doc_type = doc_type.trim().to_lowercase();
+        if !DOCUMENT_TYPES.contains(&doc_type.as_str()) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("doc_type must be one of: {}", DOCUMENT_TYPES.join(", "))
+            })));
+        }
+        set.insert("doc_type", doc_type);
+    }
+    if body.clear_expiry {
+        set.insert("expires_on", mongodb::bson::Bson::Null);
+        unset.insert("expiry_alerted_at", "");
+    } else if let Some(expires_on) = body.expires_on {
+        set.insert("expires_on", expires_on.to_string());
+        // A new date gets its own alert
+        if document.expires_on != Some(expires_on) {
+            unset.insert("expiry_alerted_at", "");
+        }
+    }
+
+    let mut update = doc! { "$set": set };
+    if !unset.is_empty() {
+        update.insert("$unset", unset);
+    }
+
+    let collection: Collection<EmployeeDocument> = data.db.collection("employee_documents");
+    collection
+        .update_one(doc! { "_id": document.id }, update, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Document updated successfully"
+    })))
+}
+
+// HR can delete any document; staff only ones they uploaded themselves
+async fn delete_document(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let document = find_document(&data.db, &path, &claims.campus_id)
+        .await?
+        .filter(|d| can_access_documents(&claims, &d.employee_id));
+    let Some(document) = document else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Document not found"
+        })));
+    };
+    if !is_hr(&claims) && document.uploaded_by != claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: you can only delete documents you uploaded"
+        })));
+    }
+
+    let collection: Collection<EmployeeDocument> = data.db.collection("employee_documents");
+    collection
+        .delete_one(doc! { "_id": document.id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    documents_bucket(&data.db)
+        .delete(document.file_id.into())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Document deleted successfully"
+    })))
+}
+
+// Documents expiring within `days` (default DOCUMENT_EXPIRY_ALERT_DAYS), including
+// lapsed ones. Staff only see their own.
+async fn get_expiring_documents(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ExpiringDocumentQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let days = query.days.unwrap_or(data.document_alert_days);
+    if !(0..=3650).contains(&days) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "days must be between 0 and 3650"
+        })));
+    }
+    let employee_id = if is_hr(&claims) {
+        query.employee_id.clone()
+    } else {
+        Some(claims.sub.clone())
+    };
+
+    let today = Local::now().date_naive();
+    let documents = expiring_documents(
+        &data.db,
+        Some(&claims.campus_id),
+        employee_id.as_deref(),
+        today + chrono::Duration::days(days),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let documents: Vec<ExpiringDocument> = documents
+        .into_iter()
+        .filter_map(|document| {
+            let days_left = (document.expires_on? - today).num_days();
+            Some(ExpiringDocument { document, days_left })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(documents))
+}
+
+// Leave Management
+async fn create_leave_request(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    leave_data: web::Json<LeaveRequestData>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !LEAVE_TYPES.contains(&leave_data.leave_type.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("leave_type must be one of: {}", LEAVE_TYPES.join(", "))
+        })));
+    }
+    let (from_date, to_date) = match (
+        parse_date(&leave_data.from_date, "from_date"),
+        parse_date(&leave_data.to_date, "to_date"),
+    ) {
+        (Ok(from), Ok(to)) => (from, to),
+        (Err(e), _) | (_, Err(e)) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+    };
+    if from_date > to_date {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from_date must not be after to_date"
+        })));
+    }
+
+    let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
+    let faculty = faculty_collection
+        .find_one(doc! { "employee_id": &leave_data.employee_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if faculty.is_none() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    }
+
+    let collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+
+    // Rejected requests don't hold the dates; dates are YYYY-MM-DD, so strings compare in date order
+    let overlapping = collection
+        .find_one(
+            doc! {
+                "employee_id": &leave_data.employee_id,
+                "campus_id": &claims.campus_id,
+                "status": { "$in": ["pending", "approved"] },
+                "from_date": { "$lte": to_date.to_string() },
+                "to_date": { "$gte": from_date.to_string() }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if let Some(existing) = overlapping {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!(
+                "Overlaps a {} leave request from {} to {}",
+                existing.status, existing.from_date, existing.to_date
+            ),
+            "request_id": existing.id
+        })));
+    }
+
+    let holidays = holidays_between(&data.db, &claims.campus_id, from_date, to_date)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let days = working_days(from_date, to_date, &data.weekend, &holidays);
+    if days == 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The leave covers no working days"
+        })));
+    }
+
+    let new_request = LeaveRequest {
+        id: None,
+        employee_id: leave_data.employee_id.clone(),
+        leave_type: leave_data.leave_type.clone(),
+        from_date: from_date.to_string(),
+        to_date: to_date.to_string(),
+        working_days: days,
+        reason: leave_data.reason.clone(),
+        status: "pending".to_string(),
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let result = collection
+        .insert_one(new_request, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Leave request submitted successfully",
+        "id": result.inserted_id,
+        "working_days": days
+    })))
+}
+
+async fn get_leave_requests(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut requests = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(request) => requests.push(request),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+async fn approve_leave(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    approval_data: web::Json<LeaveApproval>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+
+    let request_obj_id = ObjectId::parse_str(&approval_data.request_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    collection
+        .update_one(
+            doc! { "_id": request_obj_id, "campus_id": &claims.campus_id },
+            doc! { "$set": { "status": &approval_data.status } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Leave request updated successfully"
+    })))
+}
+
+// Holidays
+async fn add_holiday(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    holiday_data: web::Json<HolidayRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if holiday_data.name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name is required"
+        })));
+    }
+
+    let collection: Collection<Holiday> = data.db.collection("holidays");
+
+    let new_holiday = Holiday {
+        id: None,
+        date: holiday_data.date,
+        name: holiday_data.name.trim().to_string(),
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    // Leave requests already made keep their day count
+    match collection.insert_one(&new_holiday, None).await {
+        Ok(result) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "message": "Holiday added successfully",
+            "id": result.inserted_id
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} is already a holiday", new_holiday.date)
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn get_holidays(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<HolidayQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Holiday> = data.db.collection("holidays");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(year) = query.year {
+        filter.insert("date", doc! {
+            "$gte": format!("{:04}-01-01", year),
+            "$lt": format!("{:04}-01-01", year + 1)
+        });
+    }
+
+    let mut cursor = collection
+        .find(filter, mongodb::options::FindOptions::builder().sort(doc! { "date": 1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut holidays = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(holiday) => holidays.push(holiday),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(holidays))
+}
+
+async fn delete_holiday(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let holiday_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Holiday> = data.db.collection("holidays");
+
+    let result = collection
+        .delete_one(doc! { "_id": holiday_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.deleted_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Holiday not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Holiday deleted successfully"
+    })))
+}
+
+// Attendance
+impl AttendanceRules {
+    // Status of a day from its punches; a day without check-out is judged on check-in alone
+    fn status(&self, check_in: NaiveTime, check_out: Option<NaiveTime>) -> (&'static str, i64) {
+        let late = check_in > self.office_start + chrono::Duration::minutes(self.late_grace_minutes);
+        let Some(check_out) = check_out else {
+            return (if late { "late" } else { "present" }, 0);
+        };
+        let worked_minutes = (check_out - check_in).num_minutes();
+        let hours = worked_minutes as f64 / 60.0;
+        let status = if hours < self.half_day_min_hours {
+            "absent"
+        } else if hours < self.full_day_min_hours {
+            "half_day"
+        } else if late {
+            "late"
+        } else {
+            "present"
+        };
+        (status, worked_minutes)
+    }
+}
+
+fn month_bounds(year: i32, month: u32) -> Option<(NaiveDate, NaiveDate)> {
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let last = first.checked_add_months(chrono::Months::new(1))?.pred_opt()?;
+    Some((first, last))
+}
+
+// Punch times are kept to the second
+fn punch_time(at: NaiveDateTime) -> NaiveTime {
+    at.time().with_nanosecond(0).unwrap_or(at.time())
+}
+
+// Recomputes status and worked time after the punches of a day change.
+// A single device punch shows up as check_out == check_in and is dropped.
+async fn refresh_attendance(
+    collection: &Collection<Attendance>,
+    rules: &AttendanceRules,
+    campus_id: &str,
+    employee_id: &str,
+    date: NaiveDate,
+) -> mongodb::error::Result<Option<Attendance>> {
+    let filter = doc! { "campus_id": campus_id, "employee_id": employee_id, "date": date.to_string() };
+    let Some(mut record) = collection.find_one(filter.clone(), None).await? else {
+        return Ok(None);
+    };
+    let Some(check_in) = record.check_in else {
+        return Ok(Some(record));
+    };
+    record.check_out = record.check_out.filter(|out| *out > check_in);
+    let (status, worked_minutes) = rules.status(check_in, record.check_out);
+    record.status = status.to_string();
+    record.worked_minutes = worked_minutes;
+
+    let check_out = match record.check_out {
+        Some(out) => mongodb::bson::Bson::String(out.to_string()),
+        None => mongodb::bson::Bson::Null,
+    };
+    collection
+        .update_one(
+            filter,
+            doc! { "$set": {
+                "check_out": check_out,
+                "status": status,
+                "worked_minutes": worked_minutes
+            } },
+            None,
+        )
+        .await?;
+    Ok(Some(record))
+}
+
+async fn faculty_exists(db: &mongodb::Database, employee_id: &str, campus_id: &str) -> Result<bool, Error> {
+    let collection: Collection<Faculty> = db.collection("faculty");
+    let faculty = collection
+        .find_one(doc! { "employee_id": employee_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(faculty.is_some())
+}
+
+async fn check_in(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    punch: web::Json<AttendancePunch>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if punch.at.is_some() && !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: only HR can record a check-in for another time"
+        })));
+    }
+    if !faculty_exists(&data.db, &punch.employee_id, &claims.campus_id).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    }
+
+    let at = punch.at.unwrap_or_else(|| Local::now().naive_local());
+    let date = at.date();
+    let time = punch_time(at);
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let result = collection
+        .update_one(
+            doc! { "campus_id": &claims.campus_id, "employee_id": &punch.employee_id, "date": date.to_string() },
+            doc! { "$setOnInsert": {
+                "check_in": time.to_string(),
+                "status": "present",
+                "worked_minutes": 0_i64,
+                "source": "manual",
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.upserted_id.is_none() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} has already checked in on {}", punch.employee_id, date)
+        })));
+    }
+
+    let record = refresh_attendance(&collection, &data.attendance_rules, &claims.campus_id, &punch.employee_id, date)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Checked in successfully",
+        "attendance": record
+    })))
+}
+
+async fn check_out(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    punch: web::Json<AttendancePunch>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if punch.at.is_some() && !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: only HR can record a check-out for another time"
+        })));
+    }
+
+    let at = punch.at.unwrap_or_else(|| Local::now().naive_local());
+    let date = at.date();
+    let time = punch_time(at);
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let filter = doc! { "campus_id": &claims.campus_id, "employee_id": &punch.employee_id, "date": date.to_string() };
+    let record = collection
+        .find_one(filter.clone(), None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let Some(checked_in) = record.and_then(|r| r.check_in) else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} has not checked in on {}", punch.employee_id, date)
+        })));
+    };
+    if time <= checked_in {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Check-out must be after the check-in at {}", checked_in)
+        })));
+    }
+
+    collection
+        .update_one(
+            filter,
+            doc! { "$set": {
+                "check_out": time.to_string(),
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let record = refresh_attendance(&collection, &data.attendance_rules, &claims.campus_id, &punch.employee_id, date)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Checked out successfully",
+        "attendance": record
+    })))
+}
+
+// Punches from a biometric or card reader. The earliest punch of a day is the
+// check-in and the latest the check-out, so batches can be resent safely.
+async fn ingest_device_punches(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    batch: web::Json<DevicePunchBatch>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if batch.device_id.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "device_id is required"
+        })));
+    }
+
+    use futures::stream::StreamExt;
+    let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
+    let mut cursor = faculty_collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut employees = std::collections::HashSet::new();
+    while let Some(result) = cursor.next().await {
+        let faculty = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        employees.insert(faculty.employee_id);
+    }
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let now = mongodb::bson::to_bson(&Utc::now()).map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut touched = std::collections::BTreeSet::new();
+    let mut rejected = Vec::new();
+    for (index, punch) in batch.punches.iter().enumerate() {
+        if !employees.contains(&punch.employee_id) {
+            rejected.push(serde_json::json!({
+                "index": index,
+                "employee_id": punch.employee_id,
+                "error": "Faculty not found"
+            }));
+            continue;
+        }
+        let date = punch.timestamp.date();
+        let time = punch_time(punch.timestamp).to_string();
+        collection
+            .update_one(
+                doc! { "campus_id": &claims.campus_id, "employee_id": &punch.employee_id, "date": date.to_string() },
+                doc! {
+                    "$min": { "check_in": &time },
+                    "$max": { "check_out": &time },
+                    "$set": { "device_id": batch.device_id.trim(), "updated_at": now.clone() },
+                    "$setOnInsert": { "status": "present", "worked_minutes": 0_i64, "source": "device" }
+                },
+                mongodb::options::UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        touched.insert((punch.employee_id.clone(), date));
+    }
+
+    for (employee_id, date) in &touched {
+        refresh_attendance(&collection, &data.attendance_rules, &claims.campus_id, employee_id, *date)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "accepted": batch.punches.len() - rejected.len(),
+        "days_updated": touched.len(),
+        "rejected": rejected
+    })))
+}
+
+async fn get_attendance(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AttendanceQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let today = Local::now().date_naive();
+    let (month_start, _) = month_bounds(today.year(), today.month()).unwrap_or((today, today));
+    let from = query.from.unwrap_or(month_start);
+    let to = query.to.unwrap_or(today);
+    if from > to {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must not be after to"
+        })));
+    }
+
+    // Dates are stored as YYYY-MM-DD, so the range compares strings
+    let mut filter = doc! {
+        "campus_id": &claims.campus_id,
+        "date": { "$gte": from.to_string(), "$lte": to.to_string() }
+    };
+    if let Some(employee_id) = &query.employee_id {
+        filter.insert("employee_id", employee_id);
+    }
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let mut cursor = collection
+        .find(
+            filter,
+            mongodb::options::FindOptions::builder().sort(doc! { "date": 1, "employee_id": 1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut records = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(record) => records.push(record),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(records))
+}
+
+// Tallies one employee's month. Working days up to today with neither a
+// record nor approved leave count as absent; later days are not counted yet.
+async fn attendance_summary(
+    data: &AppState,
+    campus_id: &str,
+    employee_id: &str,
+    year: i32,
+    month: u32,
+) -> mongodb::error::Result<Option<AttendanceSummary>> {
+    use futures::stream::StreamExt;
+
+    let Some((first, last)) = month_bounds(year, month) else {
+        return Ok(None);
+    };
+    let holidays = holidays_between(&data.db, campus_id, first, last).await?;
+    let is_working = |d: &NaiveDate| !data.weekend.contains(&d.weekday()) && !holidays.contains(d);
+
+    let collection: Collection<Attendance> = data.db.collection("attendance");
+    let mut cursor = collection
+        .find(
+            doc! {
+                "campus_id": campus_id,
+                "employee_id": employee_id,
+                "date": { "$gte": first.to_string(), "$lte": last.to_string() }
+            },
+            None,
+        )
+        .await?;
+    let mut records = std::collections::HashMap::new();
+    while let Some(record) = cursor.next().await {
+        let record = record?;
+        records.insert(record.date, record);
+    }
+
+    let leave_collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+    let mut cursor = leave_collection
+        .find(
+            doc! {
+                "campus_id": campus_id,
+                "employee_id": employee_id,
+                "status": "approved",
+                "from_date": { "$lte": last.to_string() },
+                "to_date": { "$gte": first.to_string() }
+            },
+            None,
+        )
+        .await?;
+    let mut leave_days = std::collections::HashSet::new();
+    while let Some(leave) = cursor.next().await {
+        let leave = leave?;
+        // Requests from before dates were validated may not parse
+        let (Ok(from), Ok(to)) = (parse_date(&leave.from_date, "from_date"), parse_date(&leave.to_date, "to_date")) else {
+            continue;
+        };
+        leave_days.extend(from.iter_days().take_while(|d| *d <= to));
+    }
+
+    let today = Local::now().date_naive();
+    let mut summary = AttendanceSummary {
+        employee_id: employee_id.to_string(),
+        year,
+        month,
+        working_days: 0,
+        days_counted: 0,
+        present: 0,
+        late: 0,
+        half_day: 0,
+        absent: 0,
+        on_leave: 0,
+        missing_checkout: 0,
+        recorded_days: records.len() as i64,
+        lop_days: 0.0,
+    };
+    for date in first.iter_days().take_while(|d| *d <= last).filter(|d| is_working(d)) {
+        summary.working_days += 1;
+        let record = records.get(&date);
+        if date > today || (date == today && record.is_none()) {
+            continue;
+        }
+        summary.days_counted += 1;
+        match record {
+            Some(record) => {
+                if record.check_in.is_some() && record.check_out.is_none() && date < today {
+                    summary.missing_checkout += 1;
+                }
+                match record.status.as_str() {
+                    "late" => summary.late += 1,
+                    "half_day" => summary.half_day += 1,
+                    "absent" => summary.absent += 1,
+                    _ => summary.present += 1,
+                }
+            }
+            None if leave_days.contains(&date) => summary.on_leave += 1,
+            None => summary.absent += 1,
+        }
+    }
+
+    let late_penalty = if data.attendance_rules.lates_per_half_day > 0 {
+        (summary.late / data.attendance_rules.lates_per_half_day) as f64 * 0.5
+    } else {
+        0.0
+    };
+    summary.lop_days = summary.absent as f64 + summary.half_day as f64 * 0.5 + late_penalty;
+    Ok(Some(summary))
+}
+
+async fn get_attendance_summary(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AttendanceSummaryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let today = Local::now().date_naive();
+    let year = query.year.unwrap_or(today.year());
+    let month = query.month.unwrap_or(today.month());
+    if month_bounds(year, month).is_none() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "month must be between 1 and 12"
+        })));
+    }
+
+    let employee_ids = match &query.employee_id {
+        Some(employee_id) => vec![employee_id.clone()],
+        None => {
+            use futures::stream::StreamExt;
+            let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
+            let mut cursor = faculty_collection
+                .find(
+                    doc! { "campus_id": &claims.campus_id },
+                    mongodb::options::FindOptions::builder().sort(doc! { "employee_id": 1 }).build(),
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            let mut ids = Vec::new();
+            while let Some(result) = cursor.next().await {
+                ids.push(result.map_err(actix_web::error::ErrorInternalServerError)?.employee_id);
+            }
+            ids
+        }
+    };
+
+    let mut summaries = Vec::new();
+    for employee_id in &employee_ids {
+        if let Some(summary) = attendance_summary(&data, &claims.campus_id, employee_id, year, month)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+        {
+            summaries.push(summary);
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(summaries))
+}
+
+// "February", "feb" and "2" are all February
+fn month_number(month: &str) -> Option<u32> {
+    let month = month.trim();
+    if let Ok(number) = month.parse::<u32>() {
+        return (1..=12).contains(&number).then_some(number);
+    }
+    month.parse::<chrono::Month>().ok().map(|m| m.number_from_month())
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+// Income Tax
+// Financial years run April to March: March 2025 is in 2024-25
+fn financial_year(year: i32, month: u32) -> (String, i32) {
+    let start = if month >= 4 { year } else { year - 1 };
+    (format!("{}-{:02}", start, (start + 1) % 100), start)
+}
+
+// Months since the April the financial year starts in, 0 to 11
+fn financial_month_index(fy_start: i32, year: i32, month: u32) -> Option<i32> {
+    let index = (year - fy_start) * 12 + month as i32 - 4;
+    (0..12).contains(&index).then_some(index)
+}
+
+fn valid_financial_year(fy: &str) -> bool {
+    let Some((start, end)) = fy.split_once('-') else {
+        return false;
+    };
+    start.len() == 4
+        && end.len() == 2
+        && matches!((start.parse::<i32>(), end.parse::<i32>()), (Ok(s), Ok(e)) if e == (s + 1) % 100)
+}
+
+impl TaxSlabTable {
+    // Declared amounts per section, each capped at its limit
+    fn exemptions(&self, declaration: Option<&TaxDeclaration>) -> f64 {
+        let mut per_section: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+        for item in declaration.map(|d| d.items.as_slice()).unwrap_or_default() {
+            *per_section.entry(item.section.trim().to_uppercase()).or_default() += item.amount;
+        }
+        per_section
+            .iter()
+            .filter_map(|(section, amount)| self.deduction_limits.get(section).map(|limit| amount.min(*limit)))
+            .sum()
+    }
+
+    fn taxable_income(&self, gross: f64, declaration: Option<&TaxDeclaration>) -> f64 {
+        (gross - self.standard_deduction - self.exemptions(declaration)).max(0.0)
+    }
+
+    fn annual_tax(&self, taxable: f64) -> f64 {
+        if taxable <= self.rebate_limit {
+            return 0.0;
+        }
+        let mut tax = 0.0;
+        let mut lower = 0.0;
+        for slab in &self.slabs {
+            let upper = slab.up_to.unwrap_or(f64::INFINITY);
+            if taxable > lower {
+                tax += (taxable.min(upper) - lower) * slab.rate / 100.0;
+            }
+            lower = upper;
+        }
+        round2(tax * (1.0 + self.cess_rate / 100.0))
+    }
+}
+
+async fn find_declaration(
+    db: &mongodb::Database,
+    campus_id: &str,
+    employee_id: &str,
+    fy: &str,
+) -> mongodb::error::Result<Option<TaxDeclaration>> {
+    let collection: Collection<TaxDeclaration> = db.collection("tax_declarations");
+    collection
+        .find_one(doc! { "campus_id": campus_id, "employee_id": employee_id, "financial_year": fy }, None)
+        .await
+}
+
+async fn find_slab_table(
+    db: &mongodb::Database,
+    campus_id: &str,
+    fy: &str,
+    regime: &str,
+) -> mongodb::error::Result<Option<TaxSlabTable>> {
+    let collection: Collection<TaxSlabTable> = db.collection("tax_slabs");
+    collection
+        .find_one(doc! { "campus_id": campus_id, "financial_year": fy, "regime": regime }, None)
+        .await
+}
+
+// Salary taxed for a payroll month: what was actually earned after loss of pay
+fn taxable_gross(payroll: &Payroll) -> f64 {
+    payroll.basic_salary + payroll.allowances - payroll.lop_deduction
+}
+
+// An employee's payroll records in a financial year, in month order
+async fn financial_year_payroll(
+    db: &mongodb::Database,
+    campus_id: &str,
+    employee_id: &str,
+    fy_start: i32,
+) -> mongodb::error::Result<Vec<(i32, Payroll)>> {
+    use futures::stream::StreamExt;
+
+    let collection: Collection<Payroll> = db.collection("payroll");
+    let mut cursor = collection
+        .find(
+            doc! {
+                "campus_id": campus_id,
+                "employee_id": employee_id,
+                "year": { "$in": [fy_start, fy_start + 1] }
+            },
+            None,
+        )
+        .await?;
+    let mut records = Vec::new();
+    while let Some(record) = cursor.next().await {
+        let record = record?;
+        let index = month_number(&record.month).and_then(|m| financial_month_index(fy_start, record.year, m));
+        if let Some(index) = index {
+            records.push((index, record));
+        }
+    }
+    records.sort_by_key(|(index, _)| *index);
+    Ok(records)
+}
+
+// TDS for one month: the year's tax on salary paid so far plus this month's
+// salary for the rest of the year, less tax already deducted, spread over the
+// months left. Declarations changed mid-year are picked up from the next payroll.
+async fn monthly_tds(
+    db: &mongodb::Database,
+    campus_id: &str,
+    employee_id: &str,
+    year: i32,
+    month: u32,
+    gross: f64,
+) -> mongodb::error::Result<f64> {
+    let (fy, fy_start) = financial_year(year, month);
+    let declaration = find_declaration(db, campus_id, employee_id, &fy).await?;
+    let regime = declaration.as_ref().map_or("new", |d| d.regime.as_str());
+    let Some(table) = find_slab_table(db, campus_id, &fy, regime).await? else {
+        return Ok(0.0);
+    };
+
+    let current = financial_month_index(fy_start, year, month).unwrap_or(0);
+    let (paid_gross, paid_tds) = financial_year_payroll(db, campus_id, employee_id, fy_start)
+        .await?
+        .iter()
+        .filter(|(index, _)| *index < current)
+        .fold((0.0, 0.0), |(gross, tds), (_, p)| (gross + taxable_gross(p), tds + p.tds));
+
+    Ok(tds_due(&table, declaration.as_ref(), current, paid_gross, paid_tds, gross))
+}
+
+// The arithmetic behind monthly_tds, for month `current` (0 = April) given
+// what earlier payrolls in the year paid and deducted
+fn tds_due(
+    table: &TaxSlabTable,
+    declaration: Option<&TaxDeclaration>,
+    current: i32,
+    paid_gross: f64,
+    paid_tds: f64,
+    gross: f64,
+) -> f64 {
+    let months_left = (12 - current) as f64;
+    let projected = paid_gross + gross * months_left;
+    let tax = table.annual_tax(table.taxable_income(projected, declaration));
+    round2(((tax - paid_tds) / months_left).max(0.0))
+}
+
+async fn set_tax_slabs(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    table_data: web::Json<TaxSlabTableRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let table_data = table_data.into_inner();
+    if !valid_financial_year(&table_data.financial_year) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "financial_year must look like 2024-25"
+        })));
+    }
+    if !TAX_REGIMES.contains(&table_data.regime.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("regime must be one of: {}", TAX_REGIMES.join(", "))
+        })));
+    }
+    if let Err(e) = validate_slabs(&table_data.slabs) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+    let standard_deduction = table_data.standard_deduction.unwrap_or(0.0);
+    let rebate_limit = table_data.rebate_limit.unwrap_or(0.0);
+    let cess_rate = table_data.cess_rate.unwrap_or(0.0);
+    let deduction_limits: std::collections::HashMap<String, f64> = table_data
+        .deduction_limits
+        .unwrap_or_default()
+        .into_iter()
+        .map(|(section, limit)| (section.trim().to_uppercase(), limit))
+        .collect();
+    if standard_deduction < 0.0
+        || rebate_limit < 0.0
+        || !(0.0..=100.0).contains(&cess_rate)
+        || deduction_limits.values().any(|l| *l < 0.0)
+    {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Amounts must not be negative and cess_rate must be between 0 and 100"
+        })));
+    }
+
+    let collection: Collection<TaxSlabTable> = data.db.collection("tax_slabs");
+    let limits = mongodb::bson::to_bson(&deduction_limits).map_err(actix_web::error::ErrorInternalServerError)?;
+    let slabs = mongodb::bson::to_bson(&table_data.slabs).map_err(actix_web::error::ErrorInternalServerError)?;
+    collection
+        .update_one(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "financial_year": &table_data.financial_year,
+                "regime": &table_data.regime
+            },
+            doc! { "$set": {
+                "slabs": slabs,
+                "standard_deduction": standard_deduction,
+                "rebate_limit": rebate_limit,
+                "cess_rate": cess_rate,
+                "deduction_limits": limits,
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Tax slabs saved successfully"
+    })))
+}
+
+// Slabs go up in order and the last one has no upper bound
+fn validate_slabs(slabs: &[TaxSlab]) -> Result<(), String> {
+    if slabs.is_empty() {
+        return Err("At least one slab is required".to_string());
+    }
+    let mut lower = 0.0;
+    for (i, slab) in slabs.iter().enumerate() {
+        if !(0.0..=100.0).contains(&slab.rate) {
+            return Err("Slab rates must be between 0 and 100".to_string());
+        }
+        match slab.up_to {
+            Some(_) if i == slabs.len() - 1 => return Err("The last slab must have no up_to".to_string()),
+            Some(up_to) if up_to <= lower => return Err("Slab up_to values must increase".to_string()),
+            Some(up_to) => lower = up_to,
+            None if i != slabs.len() - 1 => return Err("Only the last slab may have no up_to".to_string()),
+            None => {}
+        }
+    }
+    Ok(())
+}
+
+async fn get_tax_slabs(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<FinancialYearQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(fy) = &query.financial_year {
+        filter.insert("financial_year", fy);
+    }
+
+    let collection: Collection<TaxSlabTable> = data.db.collection("tax_slabs");
+    let mut cursor = collection
+        .find(
+            filter,
+            mongodb::options::FindOptions::builder().sort(doc! { "financial_year": -1, "regime": 1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut tables = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(table) => tables.push(table),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(tables))
+}
+
+async fn set_tax_declaration(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    declaration_data: web::Json<TaxDeclarationRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let employee_id = path.into_inner();
+    let declaration_data = declaration_data.into_inner();
+    if !valid_financial_year(&declaration_data.financial_year) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "financial_year must look like 2024-25"
+        })));
+    }
+    if !TAX_REGIMES.contains(&declaration_data.regime.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("regime must be one of: {}", TAX_REGIMES.join(", "))
+        })));
+    }
+    if declaration_data.items.iter().any(|i| i.section.trim().is_empty() || i.amount < 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Each item needs a section and an amount that is not negative"
+        })));
+    }
+    if !faculty_exists(&data.db, &employee_id, &claims.campus_id).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    }
+
+    let items: Vec<DeclarationItem> = declaration_data
+        .items
+        .into_iter()
+        .map(|i| DeclarationItem {
+            section: i.section.trim().to_uppercase(),
+            description: i.description.trim().to_string(),
+            amount: round2(i.amount),
+        })
+        .collect();
+
+    let collection: Collection<TaxDeclaration> = data.db.collection("tax_declarations");
+    collection
+        .update_one(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "employee_id": &employee_id,
+                "financial_year": &declaration_data.financial_year
+            },
+            doc! { "$set": {
+                "regime": &declaration_data.regime,
+                "items": mongodb::bson::to_bson(&items).map_err(actix_web::error::ErrorInternalServerError)?,
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Tax declaration saved successfully"
+    })))
+}
+
+async fn get_tax_declaration(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<FinancialYearQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let today = Local::now().date_naive();
+    let fy = query
+        .financial_year
+        .clone()
+        .unwrap_or_else(|| financial_year(today.year(), today.month()).0);
+    let declaration = find_declaration(&data.db, &claims.campus_id, &path.into_inner(), &fy)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match declaration {
+        Some(declaration) => Ok(HttpResponse::Ok().json(declaration)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No tax declaration for {}", fy)
+        }))),
+    }
+}
+
+// Salary and TDS month by month, and the tax due on the salary paid so far
+async fn get_tax_statement(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<FinancialYearQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let employee_id = path.into_inner();
+    let today = Local::now().date_naive();
+    let fy = query
+        .financial_year
+        .clone()
+        .unwrap_or_else(|| financial_year(today.year(), today.month()).0);
+    if !valid_financial_year(&fy) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "financial_year must look like 2024-25"
+        })));
+    }
+    let fy_start: i32 = fy[..4].parse().map_err(actix_web::error::ErrorBadRequest)?;
+
+    let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
+    let Some(faculty) = faculty_collection
+        .find_one(doc! { "employee_id": &employee_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    };
+
+    let records = financial_year_payroll(&data.db, &claims.campus_id, &employee_id, fy_start)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let declaration = find_declaration(&data.db, &claims.campus_id, &employee_id, &fy)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let regime = declaration.as_ref().map_or("new", |d| d.regime.as_str()).to_string();
+    let table = find_slab_table(&data.db, &claims.campus_id, &fy, &regime)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let months: Vec<serde_json::Value> = records
+        .iter()
+        .map(|(_, p)| {
+            serde_json::json!({
+                "month": p.month,
+                "year": p.year,
+                "gross_salary": round2(taxable_gross(p)),
+                "tds": p.tds,
+                "payment_status": p.payment_status
+            })
+        })
+        .collect();
+    let gross_salary = round2(records.iter().map(|(_, p)| taxable_gross(p)).sum());
+    let tds_deducted = round2(records.iter().map(|(_, p)| p.tds).sum());
+
+    let (exemptions, standard_deduction, taxable_income, tax) = match &table {
+        Some(table) => {
+            let taxable = table.taxable_income(gross_salary, declaration.as_ref());
+            (
+                round2(table.exemptions(declaration.as_ref())),
+                table.standard_deduction,
+                round2(taxable),
+                table.annual_tax(taxable),
+            )
+        }
+        None => (0.0, 0.0, gross_salary, 0.0),
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "employee_id": employee_id,
+        "employee_name": faculty.name,
+        "financial_year": fy,
+        "regime": regime,
+        "tax_configured": table.is_some(),
+        "months": months,
+        "gross_salary": gross_salary,
+        "standard_deduction": standard_deduction,
+        "exemptions": exemptions,
+        "declarations": declaration.map(|d| d.items).unwrap_or_default(),
+        "taxable_income": taxable_income,
+        "tax_on_salary_paid": tax,
+        "tds_deducted": tds_deducted,
+        // Positive: still to be deducted; negative: deducted in excess
+        "balance": round2(tax - tds_deducted)
+    })))
+}
+
+// Appraisals
+// Codes are unique and the weights add up to 100
+fn validate_kpis(kpis: &[Kpi]) -> Result<(), String> {
+    if kpis.is_empty() {
+        return Err("At least one KPI is required".to_string());
+    }
+    let mut codes = std::collections::HashSet::new();
+    for kpi in kpis {
+        if kpi.code.trim().is_empty() || kpi.name.trim().is_empty() {
+            return Err("Every KPI needs a code and a name".to_string());
+        }
+        if !codes.insert(kpi.code.as_str()) {
+            return Err(format!("KPI code '{}' is used twice", kpi.code));
+        }
+        if kpi.weight <= 0.0 {
+            return Err(format!("KPI '{}' needs a positive weight", kpi.code));
+        }
+    }
+    let total: f64 = kpis.iter().map(|k| k.weight).sum();
+    if (total - 100.0).abs() > 0.01 {
+        return Err(format!("KPI weights must add up to 100, not {}", round2(total)));
+    }
+    Ok(())
+}
+
+fn validate_cycle(cycle: &AppraisalCycleRequest) -> Result<(), String> {
+    if cycle.name.trim().is_empty() {
+        return Err("name must not be blank".to_string());
+    }
+    if cycle.period_start > cycle.period_end {
+        return Err("period_start must not be after period_end".to_string());
+    }
+    if cycle.self_assessment_due > cycle.review_due {
+        return Err("self_assessment_due must not be after review_due".to_string());
+    }
+    validate_kpis(&cycle.kpis)?;
+    for (rating, percent) in &cycle.increment_guide {
+        if !RATING_BANDS.iter().any(|(band, _)| band == rating) {
+            return Err(format!("'{}' in increment_guide is not a rating", rating));
+        }
+        if !(0.0..=100.0).contains(percent) {
+            return Err("increment_guide percents must be between 0 and 100".to_string());
+        }
+    }
+    Ok(())
+}
+
+// Every KPI of the cycle scored exactly once, on the rating scale
+fn validate_scores(kpis: &[Kpi], scores: &[KpiScore]) -> Result<(), String> {
+    for score in scores {
+        if !kpis.iter().any(|k| k.code == score.kpi) {
+            return Err(format!("'{}' is not a KPI of this cycle", score.kpi));
+        }
+        if !(1..=RATING_SCALE).contains(&score.score) {
+            return Err(format!("Scores must be between 1 and {}", RATING_SCALE));
+        }
+    }
+    for kpi in kpis {
+        match scores.iter().filter(|s| s.kpi == kpi.code).count() {
+            0 => return Err(format!("KPI '{}' has no score", kpi.code)),
+            1 => {}
+            _ => return Err(format!("KPI '{}' is scored twice", kpi.code)),
+        }
+    }
+    Ok(())
+}
+
+// Scores must have been validated against the KPIs
+fn weighted_score(kpis: &[Kpi], scores: &[KpiScore]) -> f64 {
+    let total: f64 = kpis
+        .iter()
+        .filter_map(|kpi| {
+            let score = scores.iter().find(|s| s.kpi == kpi.code)?;
+            Some(kpi.weight * score.score as f64)
+        })
+        .sum();
+    round2(total / 100.0)
+}
+
+fn rating_for(score: f64) -> &'static str {
+    RATING_BANDS
+        .iter()
+        .find(|(_, floor)| score >= *floor)
+        .map_or("unsatisfactory", |(rating, _)| *rating)
+}
+
+// Employees see the reviewer's scores only once HR has finalized the appraisal
+fn appraisal_view(claims: &Claims, mut appraisal: Appraisal) -> Appraisal {
+    if !is_hr(claims) && claims.sub == appraisal.employee_id && appraisal.status != "finalized" {
+        appraisal.review_scores.clear();
+        appraisal.reviewer_comments.clear();
+        appraisal.weighted_score = None;
+    }
+    appraisal
+}
+
+async fn find_cycle(db: &mongodb::Database, id: ObjectId, campus_id: &str) -> Result<Option<AppraisalCycle>, Error> {
+    let collection: Collection<AppraisalCycle> = db.collection("appraisal_cycles");
+    collection
+        .find_one(doc! { "_id": id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// The appraisal and its cycle, if the caller is HR, the employee or the reviewer
+async fn find_appraisal(
+    db: &mongodb::Database,
+    claims: &Claims,
+    id: &str,
+) -> Result<Option<(Appraisal, AppraisalCycle)>, Error> {
+    let id = ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Appraisal> = db.collection("appraisals");
+    let appraisal = collection
+        .find_one(doc! { "_id": id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .filter(|a| is_hr(claims) || claims.sub == a.employee_id || a.reviewer_id.as_deref() == Some(claims.sub.as_str()));
+    let Some(appraisal) = appraisal else {
+        return Ok(None);
+    };
+    let cycle = find_cycle(db, appraisal.cycle_id, &claims.campus_id).await?;
+    Ok(cycle.map(|cycle| (appraisal, cycle)))
+}
+
+async fn create_appraisal_cycle(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    cycle_data: web::Json<AppraisalCycleRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let cycle_data = cycle_data.into_inner();
+    if let Err(e) = validate_cycle(&cycle_data) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let cycle = AppraisalCycle {
+        id: None,
+        name: cycle_data.name.trim().to_string(),
+        period_start: cycle_data.period_start,
+        period_end: cycle_data.period_end,
+        self_assessment_due: cycle_data.self_assessment_due,
+        review_due: cycle_data.review_due,
+        kpis: cycle_data.kpis,
+        increment_guide: cycle_data.increment_guide,
+        status: "draft".to_string(),
+        created_by: claims.sub,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+        updated_at: None,
+    };
+
+    let collection: Collection<AppraisalCycle> = data.db.collection("appraisal_cycles");
+    let result = collection
+        .insert_one(&cycle, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Appraisal cycle created successfully",
+        "id": result.inserted_id
+    })))
+}
+
+// Drafts are only listed for HR
+async fn get_appraisal_cycles(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if !is_hr(&claims) {
+        filter.insert("status", doc! { "$ne": "draft" });
+    }
+
+    let collection: Collection<AppraisalCycle> = data.db.collection("appraisal_cycles");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "period_start": -1 })
+        .build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut cycles = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(cycle) => cycles.push(cycle),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(cycles))
+}
+
+// KPIs and dates can be changed until the cycle opens
+async fn update_appraisal_cycle(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    cycle_data: web::Json<AppraisalCycleRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let cycle_id = ObjectId::parse_str(path.into_inner()).map_err(actix_web::error::ErrorBadRequest)?;
+    let Some(cycle) = find_cycle(&data.db, cycle_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal cycle not found"
+        })));
+    };
+    if cycle.status != "draft" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only draft cycles can be edited"
+        })));
+    }
+    let cycle_data = cycle_data.into_inner();
+    if let Err(e) = validate_cycle(&cycle_data) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let updated = AppraisalCycle {
+        name: cycle_data.name.trim().to_string(),
+        period_start: cycle_data.period_start,
+        period_end: cycle_data.period_end,
+        self_assessment_due: cycle_data.self_assessment_due,
+        review_due: cycle_data.review_due,
+        kpis: cycle_data.kpis,
+        increment_guide: cycle_data.increment_guide,
+        updated_at: Some(Utc::now()),
+        ..cycle
+    };
+
+    let collection: Collection<AppraisalCycle> = data.db.collection("appraisal_cycles");
+    // Guarded on the status so a cycle opened meanwhile keeps the KPIs it was opened with
+    let result = collection
+        .replace_one(doc! { "_id": cycle_id, "status": "draft" }, &updated, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only draft cycles can be edited"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Appraisal cycle updated successfully"
+    })))
+}
+
+// Starts the cycle: every employee in it gets an appraisal awaiting their
+// self-assessment. Opening an open cycle again adds anyone missing, such as new joiners.
+async fn open_appraisal_cycle(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<OpenCycleRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let cycle_id = ObjectId::parse_str(path.into_inner()).map_err(actix_web::error::ErrorBadRequest)?;
+    let Some(cycle) = find_cycle(&data.db, cycle_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal cycle not found"
+        })));
+    };
+    if cycle.status == "closed" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The cycle is closed"
+        })));
+    }
+
+    use futures::stream::StreamExt;
+
+    let body = body.into_inner();
+    let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
+    let mut cursor = faculty_collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut staff = std::collections::HashSet::new();
+    while let Some(result) = cursor.next().await {
+        let faculty = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        staff.insert(faculty.employee_id);
+    }
+
+    let employee_ids: Vec<String> = match body.employee_ids {
+        Some(ids) => ids,
+        None => staff.iter().cloned().collect(),
+    };
+    let unknown: Vec<&String> = employee_ids
+        .iter()
+        .chain(body.reviewers.keys())
+        .chain(body.reviewers.values())
+        .filter(|id| !staff.contains(*id))
+        .collect();
+    if !unknown.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Unknown employee ids",
+            "employee_ids": unknown
+        })));
+    }
+    if let Some((employee_id, _)) = body.reviewers.iter().find(|(e, r)| e == r) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("{} cannot review their own appraisal", employee_id)
+        })));
+    }
+
+    let collection: Collection<Appraisal> = data.db.collection("appraisals");
+    let created_at = mongodb::bson::to_bson(&Utc::now()).map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut added = 0;
+    for employee_id in &employee_ids {
+        let result = collection
+            .update_one(
+                doc! { "campus_id": &claims.campus_id, "cycle_id": cycle_id, "employee_id": employee_id },
+                doc! { "$setOnInsert": {
+                    "reviewer_id": body.reviewers.get(employee_id),
+                    "status": "pending_self",
+                    "self_submitted_at": null,
+                    "reviewed_at": null,
+                    "weighted_score": null,
+                    "final_rating": null,
+                    "finalized_by": null,
+                    "finalized_at": null,
+                    "increment_id": null,
+                    "created_at": created_at.clone(),
+                } },
+                mongodb::options::UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if result.upserted_id.is_some() {
+            added += 1;
+        }
+    }
+
+    let cycles: Collection<AppraisalCycle> = data.db.collection("appraisal_cycles");
+    cycles
+        .update_one(
+            doc! { "_id": cycle_id },
+            doc! { "$set": {
+                "status": "open",
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Appraisal cycle opened",
+        "appraisals_created": added
+    })))
+}
+
+// Locks the cycle. Appraisals not finalized by then stay as they are.
+async fn close_appraisal_cycle(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let cycle_id = ObjectId::parse_str(path.into_inner()).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let cycles: Collection<AppraisalCycle> = data.db.collection("appraisal_cycles");
+    let result = cycles
+        .update_one(
+            doc! { "_id": cycle_id, "campus_id": &claims.campus_id, "status": "open" },
+            doc! { "$set": {
+                "status": "closed",
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only open cycles can be closed"
+        })));
+    }
+
+    let appraisals: Collection<Appraisal> = data.db.collection("appraisals");
+    let unfinalized = appraisals
+        .count_documents(
+            doc! { "campus_id": &claims.campus_id, "cycle_id": cycle_id, "status": { "$ne": "finalized" } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Appraisal cycle closed",
+        "unfinalized": unfinalized
+    })))
+}
+
+// HR sees every appraisal; staff see their own and the ones they review
+async fn get_appraisals(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<AppraisalQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(cycle_id) = query.cycle_id.as_deref().filter(|c| !c.is_empty()) {
+        filter.insert("cycle_id", ObjectId::parse_str(cycle_id).map_err(actix_web::error::ErrorBadRequest)?);
+    }
+    if let Some(employee_id) = query.employee_id.as_deref().filter(|e| !e.is_empty()) {
+        filter.insert("employee_id", employee_id);
+    }
+    if let Some(reviewer_id) = query.reviewer_id.as_deref().filter(|r| !r.is_empty()) {
+        filter.insert("reviewer_id", reviewer_id);
+    }
+    if let Some(status) = query.status.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("status", status);
+    }
+    if !is_hr(&claims) {
+        filter.insert("$or", vec![doc! { "employee_id": &claims.sub }, doc! { "reviewer_id": &claims.sub }]);
+    }
+
+    let collection: Collection<Appraisal> = data.db.collection("appraisals");
+    let options = mongodb::options::FindOptions::builder()
+        .sort(doc! { "created_at": -1, "employee_id": 1 })
+        .build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut appraisals = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(appraisal) => appraisals.push(appraisal_view(&claims, appraisal)),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(appraisals))
+}
+
+// The appraisal with the KPIs it is scored on
+async fn get_appraisal(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some((appraisal, cycle)) = find_appraisal(&data.db, &claims, &path).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal not found"
+        })));
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "appraisal": appraisal_view(&claims, appraisal),
+        "cycle": cycle
+    })))
+}
+
+async fn assign_reviewer(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ReviewerRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let Some((appraisal, _)) = find_appraisal(&data.db, &claims, &path).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal not found"
+        })));
+    };
+    if appraisal.status == "finalized" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The appraisal is already finalized"
+        })));
+    }
+    if body.reviewer_id == appraisal.employee_id {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Employees cannot review their own appraisal"
+        })));
+    }
+    if !faculty_exists(&data.db, &body.reviewer_id, &claims.campus_id).await? {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Reviewer not found"
+        })));
+    }
+
+    let collection: Collection<Appraisal> = data.db.collection("appraisals");
+    collection
+        .update_one(
+            doc! { "_id": appraisal.id },
+            doc! { "$set": { "reviewer_id": &body.reviewer_id } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Reviewer assigned successfully"
+    })))
+}
+
+// Can be resubmitted until the reviewer has scored it
+async fn submit_self_assessment(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<AppraisalScoresRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some((appraisal, cycle)) = find_appraisal(&data.db, &claims, &path).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal not found"
+        })));
+    };
+    if claims.sub != appraisal.employee_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: only the employee can submit their self-assessment"
+        })));
+    }
+    if cycle.status != "open" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The appraisal cycle is not open"
+        })));
+    }
+    if Local::now().date_naive() > cycle.self_assessment_due {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Self-assessments were due by {}", cycle.self_assessment_due)
+        })));
+    }
+    if let Err(e) = validate_scores(&cycle.kpis, &body.scores) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let collection: Collection<Appraisal> = data.db.collection("appraisals");
+    let result = collection
+        .update_one(
+            doc! { "_id": appraisal.id, "status": { "$in": ["pending_self", "self_submitted"] } },
+            doc! { "$set": {
+                "self_scores": mongodb::bson::to_bson(&body.scores)
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                "self_comments": body.comments.trim(),
+                "self_submitted_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                "status": "self_submitted"
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The appraisal has already been reviewed"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Self-assessment submitted successfully"
+    })))
+}
+
+// The reviewer scores every KPI. Without a self-assessment, reviewing has to
+// wait for the self-assessment due date. Can be revised until finalized.
+async fn submit_review(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<AppraisalScoresRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some((appraisal, cycle)) = find_appraisal(&data.db, &claims, &path).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal not found"
+        })));
+    };
+    if appraisal.reviewer_id.as_deref() != Some(claims.sub.as_str()) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: you are not the reviewer of this appraisal"
+        })));
+    }
+    if cycle.status != "open" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The appraisal cycle is not open"
+        })));
+    }
+    let mut reviewable = vec!["self_submitted", "reviewed"];
+    if Local::now().date_naive() > cycle.self_assessment_due {
+        reviewable.push("pending_self");
+    }
+    if !reviewable.contains(&appraisal.status.as_str()) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("An appraisal that is {} cannot be reviewed", appraisal.status)
+        })));
+    }
+    if let Err(e) = validate_scores(&cycle.kpis, &body.scores) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let score = weighted_score(&cycle.kpis, &body.scores);
+    let collection: Collection<Appraisal> = data.db.collection("appraisals");
+    let result = collection
+        .update_one(
+            doc! { "_id": appraisal.id, "status": { "$in": reviewable } },
+            doc! { "$set": {
+                "review_scores": mongodb::bson::to_bson(&body.scores)
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                "reviewer_comments": body.comments.trim(),
+                "reviewed_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                "weighted_score": score,
+                "status": "reviewed"
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The appraisal has changed; reload it and try again"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Review submitted successfully",
+        "weighted_score": score,
+        "suggested_rating": rating_for(score)
+    })))
 }
 
-// Leave Management
-async fn create_leave_request(
+// HR settles the final rating, by default the one the weighted score falls in
+async fn finalize_appraisal(
     data: web::Data<AppState>,
     req: HttpRequest,
-    leave_data: web::Json<LeaveRequestData>,
+    path: web::Path<String>,
+    body: web::Json<FinalizeAppraisalRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
-
-    let collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let new_request = LeaveRequest {
-        id: None,
-        employee_id: leave_data.employee_id.clone(),
-        leave_type: leave_data.leave_type.clone(),
-        from_date: leave_data.from_date.clone(),
-        to_date: leave_data.to_date.clone(),
-        reason: leave_data.reason.clone(),
-        status: "pending".to_string(),
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let Some((appraisal, cycle)) = find_appraisal(&data.db, &claims, &path).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal not found"
+        })));
     };
+    if cycle.status != "open" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The appraisal cycle is not open"
+        })));
+    }
+    let (true, Some(score)) = (appraisal.status == "reviewed", appraisal.weighted_score) else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only reviewed appraisals can be finalized"
+        })));
+    };
+    let final_rating = body.final_rating.clone().unwrap_or_else(|| rating_for(score).to_string());
+    if !RATING_BANDS.iter().any(|(rating, _)| *rating == final_rating) {
+        let ratings: Vec<&str> = RATING_BANDS.iter().map(|(rating, _)| *rating).collect();
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("final_rating must be one of: {}", ratings.join(", "))
+        })));
+    }
 
-    collection
-        .insert_one(new_request, None)
+    let collection: Collection<Appraisal> = data.db.collection("appraisals");
+    let result = collection
+        .update_one(
+            doc! { "_id": appraisal.id, "status": "reviewed" },
+            doc! { "$set": {
+                "final_rating": &final_rating,
+                "final_comments": body.comments.trim(),
+                "finalized_by": &claims.sub,
+                "finalized_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?,
+                "status": "finalized"
+            } },
+            None,
+        )
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Only reviewed appraisals can be finalized"
+        })));
+    }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Leave request submitted successfully"
+        "message": "Appraisal finalized",
+        "weighted_score": score,
+        "final_rating": final_rating
     })))
 }
 
-async fn get_leave_requests(
+// Records the increment decided on a finalized appraisal and raises the
+// faculty salary, which the next payroll picks up
+async fn record_increment(
     data: web::Data<AppState>,
     req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<IncrementRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    let Some((appraisal, cycle)) = find_appraisal(&data.db, &claims, &path).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Appraisal not found"
+        })));
+    };
+    let (Some(appraisal_id), Some(final_rating)) = (appraisal.id, appraisal.final_rating.as_deref()) else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Increments are decided on finalized appraisals"
+        })));
+    };
+    if appraisal.increment_id.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "An increment is already recorded for this appraisal",
+            "increment_id": appraisal.increment_id
+        })));
+    }
+    let Some(percent) = body.percent.or_else(|| cycle.increment_guide.get(final_rating).copied()) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("percent is required: the cycle has no increment guide for {}", final_rating)
+        })));
+    };
+    if !(0.0..=100.0).contains(&percent) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "percent must be between 0 and 100"
+        })));
+    }
+    let today = Local::now().date_naive();
+    let effective_from = body.effective_from.unwrap_or(today);
+    if effective_from > today {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "effective_from must not be in the future; record the increment once it takes effect"
+        })));
+    }
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
+    let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
+    let faculty = faculty_collection
+        .find_one(doc! { "employee_id": &appraisal.employee_id, "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let Some(faculty) = faculty else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Faculty not found"
+        })));
+    };
 
-    let mut requests = Vec::new();
-    use futures::stream::StreamExt;
-    
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(request) => requests.push(request),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
+    let increment = Increment {
+        id: None,
+        employee_id: appraisal.employee_id.clone(),
+        appraisal_id,
+        cycle_id: appraisal.cycle_id,
+        previous_salary: faculty.salary,
+        percent,
+        new_salary: round2(faculty.salary * (1.0 + percent / 100.0)),
+        effective_from,
+        remarks: body.remarks.trim().to_string(),
+        decided_by: claims.sub.clone(),
+        campus_id: claims.campus_id.clone(),
+        created_at: Utc::now(),
+    };
+    let increments: Collection<Increment> = data.db.collection("increments");
+    let increment_id = increments
+        .insert_one(&increment, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .inserted_id;
+
+    // Claim the appraisal; a concurrent request that got there first wins
+    let appraisals: Collection<Appraisal> = data.db.collection("appraisals");
+    let claimed = appraisals
+        .update_one(
+            doc! { "_id": appraisal_id, "increment_id": null },
+            doc! { "$set": { "increment_id": &increment_id } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if claimed.matched_count == 0 {
+        increments
+            .delete_one(doc! { "_id": &increment_id }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "An increment is already recorded for this appraisal"
+        })));
     }
 
-    Ok(HttpResponse::Ok().json(requests))
+    faculty_collection
+        .update_one(
+            doc! { "employee_id": &appraisal.employee_id, "campus_id": &claims.campus_id },
+            doc! { "$set": { "salary": increment.new_salary } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Increment recorded successfully",
+        "id": increment_id,
+        "previous_salary": increment.previous_salary,
+        "percent": percent,
+        "new_salary": increment.new_salary
+    })))
 }
 
-async fn approve_leave(
+// Finalized appraisals of one employee, newest cycle first, with the increment
+// each one led to
+async fn get_appraisal_history(
     data: web::Data<AppState>,
     req: HttpRequest,
-    approval_data: web::Json<LeaveApproval>,
+    path: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+    let employee_id = path.into_inner();
 
-    let collection: Collection<LeaveRequest> = data.db.collection("leave_requests");
+    if !is_hr(&claims) && claims.sub != employee_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
 
-    let request_obj_id = ObjectId::parse_str(&approval_data.request_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+    use futures::stream::StreamExt;
 
-    collection
-        .update_one(
-            doc! { "_id": request_obj_id, "campus_id": &claims.campus_id },
-            doc! { "$set": { "status": &approval_data.status } },
-            None,
-        )
+    let filter = doc! { "campus_id": &claims.campus_id, "employee_id": &employee_id };
+    let mut cycles = std::collections::HashMap::new();
+    let cycle_collection: Collection<AppraisalCycle> = data.db.collection("appraisal_cycles");
+    let mut cursor = cycle_collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let cycle = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(id) = cycle.id {
+            cycles.insert(id, cycle);
+        }
+    }
+
+    let mut increments = std::collections::HashMap::new();
+    let increment_collection: Collection<Increment> = data.db.collection("increments");
+    let mut cursor = increment_collection
+        .find(filter.clone(), None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let increment = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        increments.insert(increment.appraisal_id, increment);
+    }
+
+    let mut finalized = filter;
+    finalized.insert("status", "finalized");
+    let appraisal_collection: Collection<Appraisal> = data.db.collection("appraisals");
+    let mut cursor = appraisal_collection
+        .find(finalized, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut history = Vec::new();
+    while let Some(result) = cursor.next().await {
+        let appraisal = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let Some(cycle) = cycles.get(&appraisal.cycle_id) else {
+            continue;
+        };
+        history.push((cycle.period_start, serde_json::json!({
+            "appraisal_id": appraisal.id,
+            "cycle_id": appraisal.cycle_id,
+            "cycle_name": cycle.name,
+            "period_start": cycle.period_start,
+            "period_end": cycle.period_end,
+            "weighted_score": appraisal.weighted_score,
+            "final_rating": appraisal.final_rating,
+            "final_comments": appraisal.final_comments,
+            "finalized_at": appraisal.finalized_at,
+            "increment": appraisal.id.and_then(|id| increments.get(&id)),
+        })));
+    }
+    history.sort_by_key(|(period_start, _)| std::cmp::Reverse(*period_start));
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Leave request updated successfully"
+        "employee_id": employee_id,
+        "appraisals": history.into_iter().map(|(_, entry)| entry).collect::<Vec<_>>()
     })))
 }
 
@@ -281,8 +3490,8 @@ async fn create_payroll(
     req: HttpRequest,
     payroll_data: web::Json<PayrollRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     let faculty_collection: Collection<Faculty> = data.db.collection("faculty");
     let payroll_collection: Collection<Payroll> = data.db.collection("payroll");
@@ -291,7 +3500,7 @@ async fn create_payroll(
     let faculty = faculty_collection
         .find_one(doc! { "employee_id": &payroll_data.employee_id, "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let faculty = match faculty {
         Some(f) => f,
@@ -300,8 +3509,47 @@ async fn create_payroll(
         }))),
     };
 
+    let Some(month) = month_number(&payroll_data.month) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "month must be a month name or a number from 1 to 12"
+        })));
+    };
+    if payroll_data.lop_days.is_some_and(|d| d < 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "lop_days must not be negative"
+        })));
+    }
+
+    let summary = attendance_summary(&data, &claims.campus_id, &payroll_data.employee_id, payroll_data.year, month)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let working_days = summary.as_ref().map_or(0, |s| s.working_days);
+    // Without any attendance on record for the month, attendance isn't tracked for this employee
+    let lop_days = payroll_data.lop_days.unwrap_or_else(|| {
+        summary
+            .as_ref()
+            .filter(|s| s.recorded_days > 0)
+            .map_or(0.0, |s| s.lop_days)
+    });
+
     let basic_salary = faculty.salary;
-    let net_salary = basic_salary + payroll_data.allowances - payroll_data.deductions;
+    let lop_deduction = if working_days > 0 {
+        round2(basic_salary / working_days as f64 * lop_days.min(working_days as f64))
+    } else {
+        0.0
+    };
+    let tds = monthly_tds(
+        &data.db,
+        &claims.campus_id,
+        &payroll_data.employee_id,
+        payroll_data.year,
+        month,
+        basic_salary + payroll_data.allowances - lop_deduction,
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+    let deductions = payroll_data.deductions + tds;
+    let net_salary = basic_salary + payroll_data.allowances - deductions - lop_deduction;
 
     let new_payroll = Payroll {
         id: None,
@@ -311,9 +3559,16 @@ async fn create_payroll(
         year: payroll_data.year,
         basic_salary,
         allowances: payroll_data.allowances,
-        deductions: payroll_data.deductions,
+        deductions,
         net_salary,
+        lop_days,
+        lop_deduction,
+        tds,
         payment_status: "pending".to_string(),
+        paid_on: None,
+        payment_reference: None,
+        paid_by: None,
+        paid_at: None,
         campus_id: claims.campus_id,
         created_at: Utc::now(),
     };
@@ -321,11 +3576,14 @@ async fn create_payroll(
     payroll_collection
         .insert_one(new_payroll, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
         "message": "Payroll created successfully",
-        "net_salary": net_salary
+        "net_salary": net_salary,
+        "lop_days": lop_days,
+        "lop_deduction": lop_deduction,
+        "tds": tds
     })))
 }
 
@@ -333,15 +3591,15 @@ async fn get_payroll(
     data: web::Data<AppState>,
     req: HttpRequest,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
     let collection: Collection<Payroll> = data.db.collection("payroll");
 
     let mut cursor = collection
         .find(doc! { "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
     let mut payroll_records = Vec::new();
     use futures::stream::StreamExt;
@@ -356,6 +3614,416 @@ async fn get_payroll(
     Ok(HttpResponse::Ok().json(payroll_records))
 }
 
+// Payroll records of one month; month is stored as entered ("February", "Feb" or "2")
+async fn payroll_batch(
+    db: &mongodb::Database,
+    campus_id: &str,
+    month: u32,
+    year: i32,
+    status: &str,
+) -> mongodb::error::Result<Vec<Payroll>> {
+    use futures::stream::StreamExt;
+
+    let collection: Collection<Payroll> = db.collection("payroll");
+    let mut cursor = collection
+        .find(
+            doc! { "campus_id": campus_id, "year": year, "payment_status": status },
+            mongodb::options::FindOptions::builder().sort(doc! { "employee_id": 1 }).build(),
+        )
+        .await?;
+    let mut batch = Vec::new();
+    while let Some(record) = cursor.next().await {
+        let record = record?;
+        if month_number(&record.month) == Some(month) {
+            batch.push(record);
+        }
+    }
+    Ok(batch)
+}
+
+fn payment_update(claims: &Claims, paid_on: Option<NaiveDate>, reference: &str) -> Result<mongodb::bson::Document, Error> {
+    Ok(doc! { "$set": {
+        "payment_status": "paid",
+        "paid_on": paid_on.unwrap_or_else(|| Local::now().date_naive()).to_string(),
+        "payment_reference": reference.trim(),
+        "paid_by": &claims.sub,
+        "paid_at": mongodb::bson::to_bson(&Utc::now())
+            .map_err(actix_web::error::ErrorInternalServerError)?
+    } })
+}
+
+async fn pay_payroll(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    payment: web::Json<PaymentRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if payment.reference.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "reference is required"
+        })));
+    }
+
+    let payroll_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Payroll> = data.db.collection("payroll");
+
+    let result = collection
+        .update_one(
+            doc! { "_id": payroll_obj_id, "campus_id": &claims.campus_id, "payment_status": "pending" },
+            payment_update(&claims, payment.paid_on, &payment.reference)?,
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        let exists = collection
+            .find_one(doc! { "_id": payroll_obj_id, "campus_id": &claims.campus_id }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(match exists {
+            Some(_) => HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Payroll is already paid"
+            })),
+            None => HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Payroll not found"
+            })),
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Payroll marked as paid"
+    })))
+}
+
+// Marks a batch paid once the bank has processed the transfer file
+async fn pay_payroll_bulk(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    payment: web::Json<BulkPaymentRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+    if payment.reference.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "reference is required"
+        })));
+    }
+
+    let ids = match (&payment.payroll_ids, &payment.month, payment.year) {
+        (Some(ids), None, None) => {
+            let mut parsed = Vec::new();
+            for id in ids {
+                parsed.push(ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?);
+            }
+            parsed
+        }
+        (None, Some(month), Some(year)) => {
+            let Some(month) = month_number(month) else {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "month must be a month name or a number from 1 to 12"
+                })));
+            };
+            payroll_batch(&data.db, &claims.campus_id, month, year, "pending")
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .into_iter()
+                .filter_map(|p| p.id)
+                .collect()
+        }
+        _ => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Give either payroll_ids, or month and year"
+            })));
+        }
+    };
+    if ids.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "No pending payroll to mark as paid"
+        })));
+    }
+
+    // Records already paid are left as they are
+    let collection: Collection<Payroll> = data.db.collection("payroll");
+    let result = collection
+        .update_many(
+            doc! { "_id": { "$in": &ids }, "campus_id": &claims.campus_id, "payment_status": "pending" },
+            payment_update(&claims, payment.paid_on, &payment.reference)?,
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Payroll marked as paid",
+        "paid": result.modified_count,
+        "skipped": ids.len() as u64 - result.modified_count
+    })))
+}
+
+// One row per salary; with a debit account the rows follow the bulk-NEFT layout
+fn transfer_file(
+    batch: &[Payroll],
+    accounts: &std::collections::HashMap<String, BankAccount>,
+    neft_debit_account: Option<&str>,
+    narration: &str,
+) -> Result<Vec<u8>, String> {
+    let mut writer = csv::Writer::from_writer(Vec::new());
+    let header: &[&str] = match neft_debit_account {
+        Some(_) => &[
+            "Transaction Type", "Debit Account Number", "Beneficiary Account Number", "Beneficiary Name",
+            "IFSC", "Amount", "Value Date", "Narration", "Customer Reference",
+        ],
+        None => &["Employee ID", "Employee Name", "Account Name", "Account Number", "IFSC", "Amount", "Narration"],
+    };
+    writer.write_record(header).map_err(|e| e.to_string())?;
+
+    let value_date = Local::now().date_naive().format("%d/%m/%Y").to_string();
+    for record in batch {
+        let account = &accounts[&record.employee_id];
+        let amount = format!("{:.2}", record.net_salary);
+        let row = match neft_debit_account {
+            Some(debit_account) => vec![
+                "N".to_string(),
+                debit_account.to_string(),
+                account.account_number.clone(),
+                account.account_name.clone(),
+                account.ifsc.clone(),
+                amount,
+                value_date.clone(),
+                narration.to_string(),
+                record.id.map(|id| id.to_hex()).unwrap_or_default(),
+            ],
+            None => vec![
+                record.employee_id.clone(),
+                record.employee_name.clone(),
+                account.account_name.clone(),
+                account.account_number.clone(),
+                account.ifsc.clone(),
+                amount,
+                narration.to_string(),
+            ],
+        };
+        writer.write_record(&row).map_err(|e| e.to_string())?;
+    }
+    writer.into_inner().map_err(|e| e.to_string())
+}
+
+// Bank upload file for a month's pending salaries. "csv" is a plain sheet for
+// review; "neft" follows the usual corporate bulk-NEFT layout, debiting
+// PAYROLL_DEBIT_ACCOUNT.
+async fn export_payroll(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PayrollExportQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_hr(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: HR role required"
+        })));
+    }
+
+    let Some(month) = month_number(&query.month) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "month must be a month name or a number from 1 to 12"
+        })));
+    };
+    let neft = match query.format.as_deref() {
+        None | Some("csv") => false,
+        Some("neft") => true,
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "format must be 'csv' or 'neft'"
+            })));
+        }
+    };
+    if neft && data.payroll_debit_account.is_none() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "PAYROLL_DEBIT_ACCOUNT is not configured"
+        })));
+    }
+
+    let batch = payroll_batch(&data.db, &claims.campus_id, month, query.year, "pending")
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if batch.is_empty() {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "No pending payroll for that month"
+        })));
+    }
+
+    use futures::stream::StreamExt;
+    let account_collection: Collection<BankAccount> = data.db.collection("bank_accounts");
+    let employee_ids: Vec<&str> = batch.iter().map(|p| p.employee_id.as_str()).collect();
+    let mut cursor = account_collection
+        .find(doc! { "campus_id": &claims.campus_id, "employee_id": { "$in": &employee_ids } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut accounts = std::collections::HashMap::new();
+    while let Some(result) = cursor.next().await {
+        let account = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        accounts.insert(account.employee_id.clone(), account);
+    }
+
+    // A partial file would silently leave people unpaid
+    let missing: Vec<&str> = employee_ids.iter().copied().filter(|id| !accounts.contains_key(*id)).collect();
+    if !missing.is_empty() {
+        return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": "Some employees have no bank account on record",
+            "employee_ids": missing
+        })));
+    }
+
+    let month_name = chrono::Month::try_from(month as u8).map(|m| m.name()).unwrap_or("");
+    let narration = format!("Salary {} {}", month_name, query.year);
+
+    let debit_account = if neft { data.payroll_debit_account.as_deref() } else { None };
+    let bytes = transfer_file(&batch, &accounts, debit_account, &narration)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let filename = format!("payroll-{}-{:02}-{}.csv", query.year, month, if neft { "neft" } else { "transfer" });
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv")
+        .insert_header(("Content-Disposition", format!("attachment; filename=\"{}\"", filename)))
+        .body(bytes))
+}
+
+async fn create_indexes(db: &mongodb::Database) -> mongodb::error::Result<()> {
+    use mongodb::{options::IndexOptions, IndexModel};
+
+    db.collection::<Holiday>("holidays")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "date": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    // Overlap checks on new leave requests
+    db.collection::<LeaveRequest>("leave_requests")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1, "from_date": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<Attendance>("attendance")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1, "date": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<TaxSlabTable>("tax_slabs")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "financial_year": 1, "regime": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<TaxDeclaration>("tax_declarations")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1, "financial_year": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<EmployeeDocument>("employee_documents")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1, "uploaded_at": -1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    // Expiry scans and the expiring list
+    db.collection::<EmployeeDocument>("employee_documents")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "expires_on": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<Appraisal>("appraisals")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "cycle_id": 1, "employee_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    // Appraisal history
+    db.collection::<Appraisal>("appraisals")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1, "status": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<Increment>("increments")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1, "effective_from": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<BankAccount>("bank_accounts")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "employee_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn env_or<T: std::str::FromStr>(name: &str, default: T) -> T {
+    env::var(name).ok().and_then(|v| v.parse().ok()).unwrap_or(default)
+}
+
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv::dotenv().ok();
@@ -365,6 +4033,19 @@ async fn main() -> std::io::Result<()> {
     let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| "campusconnect".to_string());
     let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8085".to_string());
+    let weekend = parse_weekend(&env::var("WEEKEND_DAYS").unwrap_or_else(|_| "saturday,sunday".to_string()));
+    let payroll_debit_account = env::var("PAYROLL_DEBIT_ACCOUNT").ok().filter(|a| !a.trim().is_empty());
+    let document_alert_days = env_or("DOCUMENT_EXPIRY_ALERT_DAYS", 30);
+    let attendance_rules = AttendanceRules {
+        office_start: env::var("OFFICE_START_TIME")
+            .ok()
+            .and_then(|v| NaiveTime::parse_from_str(&v, "%H:%M").ok())
+            .unwrap_or(NaiveTime::from_hms_opt(9, 0, 0).expect("valid time")),
+        late_grace_minutes: env_or("LATE_GRACE_MINUTES", 15),
+        half_day_min_hours: env_or("HALF_DAY_MIN_HOURS", 4.0),
+        full_day_min_hours: env_or("FULL_DAY_MIN_HOURS", 8.0),
+        lates_per_half_day: env_or("LATES_PER_HALF_DAY", 3),
+    };
 
     println!("👥 Starting HR Service...");
     println!("📡 Connecting to MongoDB: {}", mongodb_uri);
@@ -375,12 +4056,59 @@ async fn main() -> std::io::Result<()> {
     
     let db = client.database(&database_name);
 
+    let access_ttl = env::var("ACCESS_TOKEN_TTL_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(24 * 60);
+    let jwt_keys: SharedKeySet = std::sync::Arc::new(std::sync::RwLock::new(
+        load_jwt_keys(&db, &jwt_secret, chrono::Duration::minutes(access_ttl))
+            .await
+            .expect("Failed to load JWT keys"),
+    ));
+
+    // Pick up keys the auth service adds or retires
+    {
+        let db = db.clone();
+        let jwt_keys = jwt_keys.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(JWT_KEY_REFRESH_SECS));
+            loop {
+                interval.tick().await;
+                reload_jwt_keys(&db, &jwt_secret, &jwt_keys).await;
+            }
+        });
+    }
+
+    create_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+
     println!("✅ Connected to MongoDB");
+
+    // Hours between document expiry scans; 0 disables the job
+    let document_scan_hours: u64 = env_or("DOCUMENT_EXPIRY_SCAN_INTERVAL_HOURS", 24);
+    if document_scan_hours > 0 {
+        let db = db.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(document_scan_hours * 3600));
+            loop {
+                interval.tick().await;
+                match scan_expiring_documents(&db, document_alert_days).await {
+                    Ok(alerted) => log::info!("Document expiry scan: {} newly flagged", alerted),
+                    Err(e) => log::warn!("Document expiry scan failed: {}", e),
+                }
+            }
+        });
+    }
     println!("🚀 Server starting on http://127.0.0.1:{}", port);
 
     let app_state = web::Data::new(AppState {
         db,
-        jwt_secret,
+        jwt_keys,
+        weekend,
+        attendance_rules,
+        payroll_debit_account,
+        document_alert_days,
     });
 
     HttpServer::new(move || {
@@ -394,15 +4122,171 @@ async fn main() -> std::io::Result<()> {
             // Faculty routes
             .route("/api/faculty", web::post().to(add_faculty))
             .route("/api/faculty", web::get().to(get_faculty))
+            .route("/api/faculty/{employee_id}/bank-account", web::put().to(set_bank_account))
+            .route("/api/faculty/{employee_id}/bank-account", web::get().to(get_bank_account))
+            .route("/api/faculty/{employee_id}/documents", web::post().to(upload_document))
+            .route("/api/faculty/{employee_id}/documents", web::get().to(get_documents))
+            // Document routes
+            .route("/api/documents/expiring", web::get().to(get_expiring_documents))
+            .route("/api/documents/{id}/download", web::get().to(download_document))
+            .route("/api/documents/{id}", web::put().to(update_document))
+            .route("/api/documents/{id}", web::delete().to(delete_document))
             // Leave routes
             .route("/api/leave", web::post().to(create_leave_request))
             .route("/api/leave", web::get().to(get_leave_requests))
             .route("/api/leave/approve", web::put().to(approve_leave))
+            // Holiday routes
+            .route("/api/holidays", web::post().to(add_holiday))
+            .route("/api/holidays", web::get().to(get_holidays))
+            .route("/api/holidays/{id}", web::delete().to(delete_holiday))
+            // Attendance routes
+            .route("/api/attendance", web::get().to(get_attendance))
+            .route("/api/attendance/check-in", web::post().to(check_in))
+            .route("/api/attendance/check-out", web::post().to(check_out))
+            .route("/api/attendance/device", web::post().to(ingest_device_punches))
+            .route("/api/attendance/summary", web::get().to(get_attendance_summary))
+            // Appraisal routes
+            .route("/api/appraisals/cycles", web::post().to(create_appraisal_cycle))
+            .route("/api/appraisals/cycles", web::get().to(get_appraisal_cycles))
+            .route("/api/appraisals/cycles/{id}", web::put().to(update_appraisal_cycle))
+            .route("/api/appraisals/cycles/{id}/open", web::put().to(open_appraisal_cycle))
+            .route("/api/appraisals/cycles/{id}/close", web::put().to(close_appraisal_cycle))
+            .route("/api/appraisals/history/{employee_id}", web::get().to(get_appraisal_history))
+            .route("/api/appraisals", web::get().to(get_appraisals))
+            .route("/api/appraisals/{id}", web::get().to(get_appraisal))
+            .route("/api/appraisals/{id}/reviewer", web::put().to(assign_reviewer))
+            .route("/api/appraisals/{id}/self-assessment", web::put().to(submit_self_assessment))
+            .route("/api/appraisals/{id}/review", web::put().to(submit_review))
+            .route("/api/appraisals/{id}/finalize", web::put().to(finalize_appraisal))
+            .route("/api/appraisals/{id}/increment", web::post().to(record_increment))
             // Payroll routes
             .route("/api/payroll", web::post().to(create_payroll))
             .route("/api/payroll", web::get().to(get_payroll))
+            .route("/api/payroll/pay", web::put().to(pay_payroll_bulk))
+            .route("/api/payroll/export", web::get().to(export_payroll))
+            .route("/api/payroll/{id}/pay", web::put().to(pay_payroll))
+            // Income tax routes
+            .route("/api/tax/slabs", web::put().to(set_tax_slabs))
+            .route("/api/tax/slabs", web::get().to(get_tax_slabs))
+            .route("/api/tax/declarations/{employee_id}", web::put().to(set_tax_declaration))
+            .route("/api/tax/declarations/{employee_id}", web::get().to(get_tax_declaration))
+            .route("/api/tax/statement/{employee_id}", web::get().to(get_tax_statement))
     })
     .bind(format!("127.0.0.1:{}", port))?
     .run()
     .await
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // New regime, FY 2024-25
+    fn new_regime() -> TaxSlabTable {
+        let slab = |up_to: Option<f64>, rate: f64| TaxSlab { up_to, rate };
+        TaxSlabTable {
+            id: None,
+            financial_year: "2024-25".to_string(),
+            regime: "new".to_string(),
+            slabs: vec![
+                slab(Some(300000.0), 0.0),
+                slab(Some(700000.0), 5.0),
+                slab(Some(1000000.0), 10.0),
+                slab(Some(1200000.0), 15.0),
+                slab(Some(1500000.0), 20.0),
+                slab(None, 30.0),
+            ],
+            standard_deduction: 75000.0,
+            rebate_limit: 700000.0,
+            cess_rate: 4.0,
+            deduction_limits: std::collections::HashMap::new(),
+            campus_id: "campus-1".to_string(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn annual_tax_matches_known_figures() {
+        let table = new_regime();
+        let cases = [
+            (0.0, 0.0),
+            (300000.0, 0.0),
+            (700000.0, 0.0), // rebate
+            (750000.0, 26000.0),
+            (1000000.0, 52000.0),
+            (1500000.0, 145600.0),
+            (2000000.0, 301600.0),
+        ];
+        for (taxable, expected) in cases {
+            assert_eq!(table.annual_tax(taxable), expected, "taxable {}", taxable);
+        }
+    }
+
+    #[test]
+    fn taxable_income_applies_standard_deduction() {
+        let table = new_regime();
+        assert_eq!(table.taxable_income(1075000.0, None), 1000000.0);
+        assert_eq!(table.taxable_income(50000.0, None), 0.0);
+    }
+
+    #[test]
+    fn validate_slabs_rejects_malformed_tables() {
+        let slab = |up_to: Option<f64>, rate: f64| TaxSlab { up_to, rate };
+        let cases: Vec<(Vec<TaxSlab>, bool)> = vec![
+            (new_regime().slabs, true),
+            (vec![slab(None, 10.0)], true),
+            (vec![], false),
+            (vec![slab(Some(100.0), 0.0), slab(None, 101.0)], false),
+            (vec![slab(Some(100.0), 0.0), slab(None, -1.0)], false),
+            (vec![slab(Some(100.0), 0.0), slab(Some(200.0), 5.0)], false),
+            (vec![slab(Some(200.0), 0.0), slab(Some(100.0), 5.0), slab(None, 10.0)], false),
+            (vec![slab(Some(100.0), 0.0), slab(Some(100.0), 5.0), slab(None, 10.0)], false),
+            (vec![slab(None, 0.0), slab(None, 5.0)], false),
+            (vec![slab(Some(0.0), 0.0), slab(None, 5.0)], false),
+        ];
+        for (slabs, ok) in cases {
+            assert_eq!(validate_slabs(&slabs).is_ok(), ok, "{:?}", slabs);
+        }
+    }
+
+    // Runs a year of payrolls and returns (TDS deducted in total, tax due on
+    // what was actually paid)
+    fn run_year(monthly_gross: &[f64; 12]) -> (f64, f64) {
+        let table = new_regime();
+        let (mut paid_gross, mut paid_tds) = (0.0, 0.0);
+        for (current, gross) in monthly_gross.iter().enumerate() {
+            paid_tds += tds_due(&table, None, current as i32, paid_gross, paid_tds, *gross);
+            paid_gross += gross;
+        }
+        (round2(paid_tds), table.annual_tax(table.taxable_income(paid_gross, None)))
+    }
+
+    #[test]
+    fn year_end_true_up_comes_to_zero() {
+        let mut raise = [100000.0; 12];
+        raise[6..].fill(130000.0);
+        let mut loss_of_pay = [125000.0; 12];
+        loss_of_pay[3] = 60000.0;
+        let mut below_rebate_until_raise = [50000.0; 12];
+        below_rebate_until_raise[9..].fill(200000.0);
+
+        let cases = [
+            ("flat", [125000.0; 12]),
+            ("under rebate", [60000.0; 12]),
+            ("raise", raise),
+            ("loss of pay", loss_of_pay),
+            ("late raise", below_rebate_until_raise),
+        ];
+        for (name, monthly_gross) in cases {
+            let (deducted, due) = run_year(&monthly_gross);
+            assert!((deducted - due).abs() < 0.01, "{}: deducted {} but {} is due", name, deducted, due);
+        }
+    }
+
+    #[test]
+    fn flat_salary_spreads_tds_evenly() {
+        let table = new_regime();
+        // 15 lakh gross: 14.25 lakh taxable, tax 1,25,000 + 4% cess
+        assert_eq!(tds_due(&table, None, 0, 0.0, 0.0, 125000.0), round2(130000.0 / 12.0));
+    }
+}
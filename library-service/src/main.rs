@@ -1,9 +1,9 @@
 use actix_web::{web, App, HttpServer, HttpResponse, HttpRequest, Error, middleware};
 use actix_cors::Cors;
-use mongodb::{Client, Collection, bson::{doc, oid::ObjectId}};
+use mongodb::{Client, Collection, bson::{doc, oid::ObjectId, Document}, options::FindOptions};
 use serde::{Deserialize, Serialize};
-use jsonwebtoken::{decode, DecodingKey, Validation, Algorithm};
-use chrono::{DateTime, Utc, Duration};
+use campus_auth::{load_jwt_keys, reload_jwt_keys, SharedKeySet, JWT_KEY_REFRESH_SECS};
+use chrono::{DateTime, NaiveDate, Utc, Duration};
 use std::env;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -12,6 +12,7 @@ struct Claims {
     role: String,
     campus_id: String,
     exp: usize,
+    iat: usize,
 }
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
@@ -26,17 +27,70 @@ struct Book {
     available_copies: i32,
     campus_id: String,
     created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cover_url: Option<String>,
+    #[serde(default = "default_book_status")]
+    status: String, // active, withdrawn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_at: Option<DateTime<Utc>>,
+    // Withdrawn (weeded) books are kept for the circulation history
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    withdrawn_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    withdrawn_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    withdrawal_reason: Option<String>,
+}
+
+fn default_book_status() -> String {
+    "active".to_string()
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 struct BookRequest {
     isbn: String,
-    title: String,
-    author: String,
-    category: String,
+    // Filled in from the ISBN lookup when left out
+    title: Option<String>,
+    author: Option<String>,
+    category: Option<String>,
     total_copies: i32,
 }
 
+#[derive(Debug, Deserialize)]
+struct BookUpdateRequest {
+    isbn: Option<String>,
+    title: Option<String>,
+    author: Option<String>,
+    category: Option<String>,
+    total_copies: Option<i32>,
+}
+
+/// Query params for DELETE /api/books/{id}
+#[derive(Debug, Deserialize)]
+struct BookWithdrawQuery {
+    /// e.g. "damaged", "lost", "outdated edition"
+    reason: Option<String>,
+}
+
+/// Query params for GET /api/books
+#[derive(Debug, Deserialize)]
+struct BookQuery {
+    /// Text search across title, author and ISBN
+    q: Option<String>,
+    category: Option<String>,
+    /// Only books with at least one copy on the shelf
+    available_only: Option<bool>,
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Sort field, prefixed with '-' for descending (default: relevance with q, else title)
+    sort: Option<String>,
+    /// Also list withdrawn books
+    include_withdrawn: Option<bool>,
+}
+
+/// Fields GET /api/books can sort by.
+const BOOK_SORT_FIELDS: [&str; 5] = ["title", "author", "category", "available_copies", "created_at"];
+
 #[derive(Debug, Serialize, Deserialize, Clone)]
 struct BookIssue {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
@@ -46,10 +100,99 @@ struct BookIssue {
     student_id: String,
     issue_date: DateTime<Utc>,
     due_date: DateTime<Utc>,
+    #[serde(default, deserialize_with = "legacy_datetime")]
     return_date: Option<DateTime<Utc>>,
     status: String, // issued, returned, overdue
     fine_amount: f64,
     campus_id: String,
+    // Written by the finance service once the fine is billed as a fee
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fine_fee_id: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fine_status: Option<String>, // pending, overdue, paid, waived (fully waived before billing)
+    #[serde(default)]
+    renewal_count: i32,
+    // Sum of approved fine waivers; the student owes fine_amount - fine_waived
+    #[serde(default)]
+    fine_waived: f64,
+    // Part of fine_waived the finance service has already credited to the billed fee
+    #[serde(default)]
+    fine_waived_billed: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    accession_number: Option<String>,
+    // Numbers of the receipts handed out at the desk, for reprints
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    issue_receipt_number: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    return_receipt_number: Option<String>,
+    // Open days the fine was charged for; closed days are not counted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fine_days: Option<i64>,
+}
+
+// Older returns stored return_date as a BSON date rather than an RFC 3339 string
+fn legacy_datetime<'de, D: serde::Deserializer<'de>>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stored {
+        Text(DateTime<Utc>),
+        Bson(mongodb::bson::DateTime),
+    }
+    Ok(Option::<Stored>::deserialize(deserializer)?.and_then(|stored| match stored {
+        Stored::Text(date) => Some(date),
+        Stored::Bson(date) => DateTime::from_timestamp_millis(date.timestamp_millis()),
+    }))
+}
+
+/// Query params for GET /api/students/{id}/history and /api/my/history
+#[derive(Debug, Deserialize)]
+struct HistoryQuery {
+    /// Only issues made in this calendar year
+    year: Option<i32>,
+    page: Option<u64>,
+    limit: Option<u64>,
+}
+
+/// Query params for POST /api/books/import
+#[derive(Debug, Deserialize)]
+struct ImportQuery {
+    /// "csv" or "marc"; taken from the file extension when left out
+    format: Option<String>,
+    /// Validate and report without changing the catalog
+    dry_run: Option<bool>,
+}
+
+/// Query params for GET /api/issues
+#[derive(Debug, Deserialize)]
+struct IssueListQuery {
+    /// issued, overdue, returned or returned_with_fine
+    status: Option<String>,
+    student_id: Option<String>,
+    book_id: Option<String>,
+    /// Only unreturned issues past their due date, whether or not already marked overdue
+    overdue_only: Option<bool>,
+    /// Issue dates covered, inclusive
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    page: Option<u64>,
+    limit: Option<u64>,
+    /// Sort field, prefixed with '-' for descending (default: -issue_date)
+    sort: Option<String>,
+}
+
+/// Fields GET /api/issues can sort by.
+const ISSUE_SORT_FIELDS: [&str; 3] = ["due_date", "issue_date", "return_date"];
+
+/// Query params for GET /api/reports/circulation
+#[derive(Debug, Deserialize)]
+struct CirculationQuery {
+    /// Issue dates covered, inclusive (default: the last 365 days)
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+    /// Length of the most-issued list (default 10)
+    top: Option<usize>,
+    /// Titles older than this with no issue in this many years count as zero-circulation (default 3)
+    idle_years: Option<i64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -57,6 +200,8 @@ struct IssueRequest {
     book_id: String,
     student_id: String,
     days: i64,
+    // The copy handed over, if copies have accession numbers
+    accession_number: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -72,8 +217,18 @@ struct WaitingListEntry {
     book_title: String,
     student_id: String,
     queued_at: DateTime<Utc>,
-    status: String, // waiting, notified, fulfilled, cancelled
+    status: String, // waiting, notified, fulfilled, cancelled, expired
     campus_id: String,
+    // A notified hold has a copy set aside (taken out of available_copies) until it is
+    // collected or hold_expires_at passes
+    #[serde(default)]
+    copy_held: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notified_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    hold_expires_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    expired_at: Option<DateTime<Utc>>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -82,662 +237,8737 @@ struct WaitingListRequest {
     student_id: String,
 }
 
-struct AppState {
-    db: mongodb::Database,
-    jwt_secret: String,
+// Managed subject or sub-subject; books store the category name
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Category {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    slug: String,
+    // None for subjects; set for sub-subjects
+    #[serde(default)]
+    parent_id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    description: Option<String>,
+    // Slugs of merged or former names that still resolve here
+    #[serde(default)]
+    aliases: Vec<String>,
+    // Academic courses whose students are pointed to this subject
+    #[serde(default)]
+    course_codes: Vec<String>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
 }
 
-fn extract_claims(req: &HttpRequest, jwt_secret: &str) -> Result<Claims, String> {
-    if let Some(auth_header) = req.headers().get("Authorization") {
-        if let Ok(auth_str) = auth_header.to_str() {
-            if auth_str.starts_with("Bearer ") {
-                let token = &auth_str[7..];
-                
-                match decode::<Claims>(
-                    token,
-                    &DecodingKey::from_secret(jwt_secret.as_bytes()),
-                    &Validation::new(Algorithm::HS256),
-                ) {
-                    Ok(token_data) => return Ok(token_data.claims),
-                    Err(_) => return Err("Invalid token".to_string()),
-                }
-            }
-        }
-    }
-    Err("No token provided".to_string())
+#[derive(Debug, Deserialize)]
+struct CategoryRequest {
+    name: String,
+    parent_id: Option<String>,
+    description: Option<String>,
+    course_codes: Option<Vec<String>>,
 }
 
-async fn health_check() -> HttpResponse {
-    HttpResponse::Ok().json(serde_json::json!({
-        "status": "ok",
-        "service": "library-service"
-    }))
+#[derive(Debug, Deserialize)]
+struct CategoryUpdateRequest {
+    name: Option<String>,
+    // "" moves a sub-subject to the top level
+    parent_id: Option<String>,
+    description: Option<String>,
+    // Replaces the mapped courses
+    course_codes: Option<Vec<String>>,
 }
 
-// Book Management
-async fn add_book(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    book_data: web::Json<BookRequest>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
-
-    let collection: Collection<Book> = data.db.collection("books");
+#[derive(Debug, Deserialize)]
+struct RecommendationQuery {
+    limit: Option<usize>,
+}
 
-    let new_book = Book {
-        id: None,
-        isbn: book_data.isbn.clone(),
-        title: book_data.title.clone(),
-        author: book_data.author.clone(),
-        category: book_data.category.clone(),
-        total_copies: book_data.total_copies,
-        available_copies: book_data.total_copies,
-        campus_id: claims.campus_id,
-        created_at: Utc::now(),
-    };
+#[derive(Debug, Deserialize)]
+struct CategoryMergeRequest {
+    // Category names or free-text values as found on books
+    sources: Vec<String>,
+    target_id: String,
+}
 
-    collection
-        .insert_one(new_book, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+// A title borrowed from a partner library for one student
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct IllRequest {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    student_id: String,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    isbn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    status: String, // requested, ordered, received, issued, returned_to_lender, cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    partner_library: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    partner_reference: Option<String>,
+    lending_fee: f64,
+    postage: f64,
+    // Due back from the student
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    due_date: Option<NaiveDate>,
+    // Due back at the partner library
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    lender_due_date: Option<NaiveDate>,
+    history: Vec<IllStatusChange>,
+    requested_at: DateTime<Utc>,
+    campus_id: String,
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Book added successfully"
-    })))
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct IllStatusChange {
+    status: String,
+    changed_by: String,
+    changed_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
 }
 
-async fn get_books(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+#[derive(Debug, Deserialize)]
+struct IllRequestCreate {
+    // Required for librarians; students always request for themselves
+    student_id: Option<String>,
+    title: String,
+    author: Option<String>,
+    isbn: Option<String>,
+    notes: Option<String>,
+}
 
-    let collection: Collection<Book> = data.db.collection("books");
+#[derive(Debug, Deserialize)]
+struct IllStatusUpdate {
+    status: String,
+    partner_library: Option<String>,
+    partner_reference: Option<String>,
+    lending_fee: Option<f64>,
+    postage: Option<f64>,
+    due_date: Option<NaiveDate>,
+    lender_due_date: Option<NaiveDate>,
+    note: Option<String>,
+}
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct IllQuery {
+    status: Option<String>,
+    student_id: Option<String>,
+}
 
-    let mut books = Vec::new();
-    use futures::stream::StreamExt;
-    
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(book) => books.push(book),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
-    }
+// A PDF stored in GridFS, a web link, or a licensed e-journal
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EResource {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    kind: String, // pdf, link, ejournal
+    title: String,
+    description: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    url: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    file_id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    filename: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    size: Option<i64>,
+    access: String, // all, staff
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    license: Option<EResourceLicense>,
+    added_by: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    updated_at: Option<DateTime<Utc>>,
+}
 
-    Ok(HttpResponse::Ok().json(books))
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct EResourceLicense {
+    provider: String,
+    starts_on: NaiveDate,
+    expires_on: NaiveDate,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    cost: Option<f64>,
 }
 
-// Issue Book
-async fn issue_book(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    issue_data: web::Json<IssueRequest>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+#[derive(Debug, Deserialize)]
+struct EResourceRequest {
+    kind: String, // link, ejournal
+    title: String,
+    url: String,
+    description: Option<String>,
+    category: Option<String>,
+    access: Option<String>,
+    license: Option<EResourceLicense>,
+}
 
-    let book_collection: Collection<Book> = data.db.collection("books");
-    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+#[derive(Debug, Deserialize)]
+struct EResourceUpdateRequest {
+    title: Option<String>,
+    description: Option<String>,
+    category: Option<String>,
+    url: Option<String>,
+    access: Option<String>,
+    license: Option<EResourceLicense>,
+}
 
-    // Get book details
-    let book_obj_id = ObjectId::parse_str(&issue_data.book_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+#[derive(Debug, Deserialize)]
+struct EResourceQuery {
+    kind: Option<String>,
+    category: Option<String>,
+    q: Option<String>,
+}
 
-    let book = book_collection
-        .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct ExpiringLicenseQuery {
+    days: Option<i64>,
+}
 
-    let book = match book {
-        Some(b) => b,
-        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Book not found"
-        }))),
-    };
+// One view or download of an e-resource
+#[derive(Debug, Serialize, Deserialize)]
+struct EResourceAccess {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    resource_id: ObjectId,
+    user_id: String,
+    role: String,
+    action: String, // view, download
+    accessed_at: DateTime<Utc>,
+    campus_id: String,
+}
 
-    // Check availability
-    if book.available_copies <= 0 {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Book not available"
-        })));
-    }
+// Money set aside for buying books in one financial year
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct AcquisitionFund {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    code: String,
+    name: String,
+    financial_year: String, // e.g. 2024-25
+    allocated: f64,
+    // Open on purchase orders, not yet received
+    committed: f64,
+    spent: f64,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
 
-    // Create issue record
-    let issue_date = Utc::now();
-    let due_date = issue_date + Duration::days(issue_data.days);
+#[derive(Debug, Deserialize)]
+struct FundRequest {
+    code: String,
+    name: String,
+    financial_year: Option<String>,
+    allocated: f64,
+}
 
-    let new_issue = BookIssue {
-        id: None,
-        book_id: issue_data.book_id.clone(),
-        book_title: book.title.clone(),
-        student_id: issue_data.student_id.clone(),
-        issue_date,
-        due_date,
-        return_date: None,
-        status: "issued".to_string(),
-        fine_amount: 0.0,
-        campus_id: claims.campus_id.clone(),
-    };
+#[derive(Debug, Deserialize)]
+struct FundUpdateRequest {
+    name: Option<String>,
+    allocated: Option<f64>,
+}
 
-    issue_collection
-        .insert_one(new_issue, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct FundQuery {
+    financial_year: Option<String>,
+}
 
-    // Update book availability
-    book_collection
-        .update_one(
-            doc! { "_id": book_obj_id },
-            doc! { "$inc": { "available_copies": -1 } },
-            None,
-        )
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Vendor {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    contact_name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    email: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    phone: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    address: Option<String>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Book issued successfully",
-        "due_date": due_date
-    })))
+#[derive(Debug, Deserialize)]
+struct VendorRequest {
+    name: String,
+    contact_name: Option<String>,
+    email: Option<String>,
+    phone: Option<String>,
+    address: Option<String>,
 }
 
-// Return Book
-async fn return_book(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    return_data: web::Json<ReturnRequest>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+// A title someone has asked the library to buy
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PurchaseRequest {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    isbn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    quantity: i32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    estimated_unit_price: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    justification: Option<String>,
+    status: String, // pending, approved, rejected, ordered, received
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fund_id: Option<ObjectId>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    purchase_order_id: Option<ObjectId>,
+    requested_by: String,
+    requester_role: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reviewed_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    reviewed_at: Option<DateTime<Utc>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    review_note: Option<String>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
 
-    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
-    let book_collection: Collection<Book> = data.db.collection("books");
+#[derive(Debug, Deserialize)]
+struct PurchaseRequestCreate {
+    title: String,
+    author: Option<String>,
+    isbn: Option<String>,
+    category: Option<String>,
+    quantity: Option<i32>,
+    estimated_unit_price: Option<f64>,
+    justification: Option<String>,
+}
 
-    // Get issue record
-    let issue_obj_id = ObjectId::parse_str(&return_data.issue_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+#[derive(Debug, Deserialize)]
+struct PurchaseRequestQuery {
+    status: Option<String>,
+}
 
-    let issue = issue_collection
-        .find_one(doc! { "_id": issue_obj_id, "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct PurchaseReview {
+    // Required when approving
+    fund_id: Option<String>,
+    note: Option<String>,
+}
 
-    let issue = match issue {
-        Some(i) => i,
-        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
-            "error": "Issue record not found"
-        }))),
-    };
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PurchaseOrder {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    po_number: String,
+    vendor_id: ObjectId,
+    vendor_name: String,
+    fund_id: ObjectId,
+    lines: Vec<PurchaseOrderLine>,
+    total: f64,
+    status: String, // open, partially_received, received, cancelled
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    notes: Option<String>,
+    created_by: String,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closed_at: Option<DateTime<Utc>>,
+}
 
-    // Calculate fine if overdue
-    let return_date = Utc::now();
-    let mut fine_amount = 0.0;
-    let mut status = "returned".to_string();
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PurchaseOrderLine {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    request_id: Option<ObjectId>,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    isbn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    quantity: i32,
+    unit_price: f64,
+    received_quantity: i32,
+    // Catalog entry the received copies went to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    book_id: Option<ObjectId>,
+}
 
-    if return_date > issue.due_date {
-        let overdue_days = (return_date - issue.due_date).num_days();
-        fine_amount = overdue_days as f64 * 5.0; // $5 per day
-        status = "returned_with_fine".to_string();
-    }
+#[derive(Debug, Deserialize)]
+struct PurchaseOrderCreate {
+    vendor_id: String,
+    lines: Vec<PurchaseOrderLineRequest>,
+    notes: Option<String>,
+}
 
-    // Update issue record
-    issue_collection
-        .update_one(
-            doc! { "_id": issue_obj_id },
-            doc! {
-                "$set": {
-                    "return_date": mongodb::bson::DateTime::from_millis(return_date.timestamp_millis()),
-                    "status": &status,
-                    "fine_amount": fine_amount
-                }
-            },
-            None,
-        )
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct PurchaseOrderLineRequest {
+    request_id: String,
+    // Defaults to the quantity on the purchase request
+    quantity: Option<i32>,
+    unit_price: f64,
+}
 
-    // Update book availability
-    let book_obj_id = ObjectId::parse_str(&issue.book_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+#[derive(Debug, Deserialize)]
+struct PurchaseOrderQuery {
+    status: Option<String>,
+    fund_id: Option<String>,
+}
 
-    book_collection
-        .update_one(
-            doc! { "_id": book_obj_id },
-            doc! { "$inc": { "available_copies": 1 } },
-            None,
-        )
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+#[derive(Debug, Deserialize)]
+struct ReceiveRequest {
+    items: Vec<ReceiveItem>,
+}
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Book returned successfully",
-        "fine_amount": fine_amount
-    })))
+#[derive(Debug, Deserialize)]
+struct ReceiveItem {
+    // Index into the order's lines
+    line: usize,
+    quantity: i32,
 }
 
-// Get all issues
-async fn get_issues(
-    data: web::Data<AppState>,
+// A journal or magazine subscription; its numbers arrive on a schedule rather than as catalog copies
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct Periodical {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    title: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    issn: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    publisher: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    vendor_id: Option<ObjectId>,
+    frequency: String, // one of PERIODICAL_FREQUENCIES
+    // First expected issue; later ones follow at the frequency
+    start_date: NaiveDate,
+    // Subscription must be renewed by this date
+    end_date: NaiveDate,
+    cost: f64,
+    // An expected issue is missing once it is this many days late
+    claim_after_days: i64,
+    status: String, // active, cancelled
+    renewals: Vec<PeriodicalRenewal>,
+    campus_id: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PeriodicalRenewal {
+    previous_end_date: NaiveDate,
+    end_date: NaiveDate,
+    cost: f64,
+    renewed_by: String,
+    renewed_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeriodicalRequest {
+    title: String,
+    issn: Option<String>,
+    publisher: Option<String>,
+    vendor_id: Option<String>,
+    frequency: String,
+    start_date: NaiveDate,
+    end_date: NaiveDate,
+    cost: f64,
+    claim_after_days: Option<i64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeriodicalUpdateRequest {
+    title: Option<String>,
+    publisher: Option<String>,
+    claim_after_days: Option<i64>,
+    status: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeriodicalQuery {
+    status: Option<String>,
+    q: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct PeriodicalRenewRequest {
+    end_date: NaiveDate,
+    cost: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RenewalsDueQuery {
+    days: Option<i64>,
+}
+
+// One number of a periodical as checked in at the desk
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PeriodicalIssue {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    periodical_id: ObjectId,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    volume: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    issue_number: Option<String>,
+    cover_date: NaiveDate,
+    // The expected issue this number fulfils
+    expected_date: NaiveDate,
+    received_by: String,
+    received_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    campus_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct CheckInRequest {
+    volume: Option<String>,
+    issue_number: Option<String>,
+    cover_date: Option<NaiveDate>,
+    note: Option<String>,
+}
+
+// A chase sent to the publisher or vendor for an issue that never arrived
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct PeriodicalClaim {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    periodical_id: ObjectId,
+    expected_date: NaiveDate,
+    status: String, // open, resolved
+    claim_count: i32,
+    first_claimed_at: DateTime<Utc>,
+    last_claimed_at: DateTime<Utc>,
+    claimed_by: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    resolved_at: Option<DateTime<Utc>>,
+    campus_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClaimRequest {
+    expected_date: NaiveDate,
+    note: Option<String>,
+}
+
+// A request to waive part or all of an overdue fine, in the fine_waivers collection.
+// requested -> approved (possibly for less than asked) or rejected.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FineWaiver {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    issue_id: String,
+    student_id: String,
+    book_title: String,
+    amount: f64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    approved_amount: Option<f64>,
+    reason: String,
+    status: String, // requested, approved, rejected
+    requested_by: String,
+    requested_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decided_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    decided_at: Option<DateTime<Utc>>,
+    history: Vec<FineWaiverEvent>,
+    campus_id: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FineWaiverEvent {
+    action: String, // requested, approved, rejected
+    actor: String,
+    at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    amount: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FineWaiverRequest {
+    issue_id: String,
+    amount: f64,
+    reason: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct FineWaiverDecision {
+    // Approve less than was asked for; defaults to the requested amount
+    amount: Option<f64>,
+    note: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FineWaiverQuery {
+    status: Option<String>,
+    student_id: Option<String>,
+}
+
+// One physical copy of a book, identified by its accession number
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct BookCopy {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    accession_number: String,
+    book_id: String,
+    // Shelf the copy belongs on, e.g. "A-12"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    status: String, // available, on_loan, missing, lost
+    campus_id: String,
+    created_at: DateTime<Utc>,
+    // Last time a stock audit found it on a shelf
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    last_seen_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CopyRegistrationRequest {
+    accession_numbers: Vec<String>,
+    location: Option<String>,
+}
+
+// A stock verification session, limited to a shelf range and/or a category
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StockAudit {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    name: String,
+    // Copies whose location starts with this, e.g. "A-" for the A shelves
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    status: String, // open, closed
+    started_by: String,
+    started_at: DateTime<Utc>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closed_by: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    closed_at: Option<DateTime<Utc>>,
+    // Counts of what closing changed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    outcome: Option<AuditOutcome>,
+    campus_id: String,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
+struct AuditOutcome {
+    marked_missing: u64,
+    restored: u64,
+    relocated: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct StockAuditScan {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    audit_id: ObjectId,
+    accession_number: String,
+    // Shelf it was found on, when the scanner records one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    location: Option<String>,
+    scanned_by: String,
+    scanned_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct StockAuditRequest {
+    name: String,
+    location_prefix: Option<String>,
+    category: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditScanRequest {
+    accession_numbers: Vec<String>,
+    location: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditCloseRequest {
+    // Each defaults to true
+    mark_missing: Option<bool>,
+    restore_found: Option<bool>,
+    update_locations: Option<bool>,
+}
+
+// One per campus; days of the week the library is closed
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct LibraryCalendar {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    campus_id: String,
+    weekly_off: Vec<String>, // lowercase weekday names, e.g. "sunday"
+    updated_by: String,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct LibraryHoliday {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    campus_id: String,
+    date: NaiveDate,
+    name: String,
+    created_by: String,
+    created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WeeklyOffRequest {
+    weekly_off: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HolidayRequest {
+    date: NaiveDate,
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HolidayUpdateRequest {
+    date: Option<NaiveDate>,
+    name: Option<String>,
+}
+
+/// Query params for GET /api/library/calendar
+#[derive(Debug, Deserialize)]
+struct CalendarQuery {
+    /// Holidays on or after this date (default: today)
+    from: Option<NaiveDate>,
+    /// Holidays on or before this date (default: no limit)
+    to: Option<NaiveDate>,
+}
+
+// A book librarians pick for the student portal home page
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct FeaturedBook {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    id: Option<ObjectId>,
+    campus_id: String,
+    book_id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    note: Option<String>, // e.g. "Book of the month"
+    #[serde(default)]
+    position: i32, // lower comes first
+    added_by: String,
+    added_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeaturedBookRequest {
+    book_id: String,
+    note: Option<String>,
+    position: Option<i32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FeaturedBookUpdateRequest {
+    note: Option<String>,
+    position: Option<i32>,
+}
+
+/// Query params for GET /api/books/new-arrivals
+#[derive(Debug, Deserialize)]
+struct NewArrivalsQuery {
+    /// Books added in this many days (default 30, max 365)
+    days: Option<i64>,
+    /// Most books returned (default 20, max 100)
+    limit: Option<i64>,
+}
+
+struct AppState {
+    db: mongodb::Database,
+    // Verification keys by kid, reloaded from the jwt_keys collection
+    jwt_keys: SharedKeySet,
+    loan_period_days: i64,
+    max_renewals: i32,
+    isbn_lookup: IsbnLookup,
+    // Days a student has to collect a book held for them
+    hold_pickup_days: i64,
+    // Books a borrower may have on loan at once, by role
+    loan_limits: std::collections::HashMap<String, i64>,
+    // Printed at the top of receipts
+    institution_name: String,
+    // User ids of librarians who may approve fine waivers
+    librarians_in_charge: Vec<String>,
+}
+
+impl AppState {
+    // Roles without their own limit get the student limit
+    fn loan_limit(&self, role: &str) -> i64 {
+        self.loan_limits
+            .get(role)
+            .or_else(|| self.loan_limits.get("student"))
+            .copied()
+            .unwrap_or(DEFAULT_STUDENT_LOAN_LIMIT)
+    }
+}
+
+const DEFAULT_STUDENT_LOAN_LIMIT: i64 = 3;
+
+// Parses LOAN_LIMITS, e.g. "student=3,teacher=10"; malformed entries are skipped
+fn parse_loan_limits(raw: &str) -> std::collections::HashMap<String, i64> {
+    raw.split(',')
+        .filter_map(|entry| {
+            let (role, limit) = entry.split_once('=')?;
+            let limit = limit.trim().parse::<i64>().ok().filter(|l| *l >= 0)?;
+            Some((role.trim().to_lowercase(), limit))
+        })
+        .filter(|(role, _)| !role.is_empty())
+        .collect()
+}
+
+// Looks up book metadata by ISBN: Open Library first, then Google Books
+#[derive(Clone)]
+struct IsbnLookup {
+    google_api_key: Option<String>, // GOOGLE_BOOKS_API_KEY; optional, raises the quota
+    http: reqwest::Client,
+}
+
+#[derive(Debug, Serialize)]
+struct BookMetadata {
+    isbn: String,
+    title: String,
+    author: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    category: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    cover_url: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    publisher: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    published_date: Option<String>,
+    source: String, // openlibrary, googlebooks
+}
+
+// Strips hyphens and spaces and checks the ISBN-10/ISBN-13 check digit
+fn normalize_isbn(raw: &str) -> Option<String> {
+    let isbn: String = raw
+        .chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .map(|c| c.to_ascii_uppercase())
+        .collect();
+    let digit = |c: char| c.to_digit(10);
+    match isbn.len() {
+        10 => {
+            let mut sum = 0;
+            for (i, c) in isbn.chars().enumerate() {
+                let value = match (i, c) {
+                    (9, 'X') => 10,
+                    _ => digit(c)?,
+                };
+                sum += value * (10 - i as u32);
+            }
+            (sum % 11 == 0).then_some(isbn)
+        }
+        13 => {
+            let mut sum = 0;
+            for (i, c) in isbn.chars().enumerate() {
+                sum += digit(c)? * if i % 2 == 0 { 1 } else { 3 };
+            }
+            (sum % 10 == 0).then_some(isbn)
+        }
+        _ => None,
+    }
+}
+
+fn json_str(value: &serde_json::Value, pointer: &str) -> Option<String> {
+    value
+        .pointer(pointer)
+        .and_then(|v| v.as_str())
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(str::to_string)
+}
+
+fn json_names(value: &serde_json::Value, pointer: &str, key: Option<&str>) -> Vec<String> {
+    value
+        .pointer(pointer)
+        .and_then(|v| v.as_array())
+        .map(|items| {
+            items
+                .iter()
+                .filter_map(|item| match key {
+                    Some(key) => item.get(key).and_then(|v| v.as_str()),
+                    None => item.as_str(),
+                })
+                .map(str::to_string)
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+impl IsbnLookup {
+    // Ok(None) when neither provider knows the ISBN; Err when both failed to answer
+    async fn lookup(&self, isbn: &str) -> Result<Option<BookMetadata>, String> {
+        let open_library = self.open_library(isbn).await;
+        if let Ok(Some(metadata)) = &open_library {
+            if metadata.category.is_some() && metadata.cover_url.is_some() {
+                return open_library;
+            }
+        }
+        let google = self.google_books(isbn).await;
+        match (open_library, google) {
+            // Open Library wins on title and author; Google Books fills the gaps
+            (Ok(Some(mut metadata)), Ok(Some(other))) => {
+                metadata.category = metadata.category.or(other.category);
+                metadata.cover_url = metadata.cover_url.or(other.cover_url);
+                metadata.publisher = metadata.publisher.or(other.publisher);
+                metadata.published_date = metadata.published_date.or(other.published_date);
+                Ok(Some(metadata))
+            }
+            (Ok(Some(metadata)), _) | (_, Ok(Some(metadata))) => Ok(Some(metadata)),
+            (Err(a), Err(b)) => Err(format!("{}; {}", a, b)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn open_library(&self, isbn: &str) -> Result<Option<BookMetadata>, String> {
+        let body: serde_json::Value = self.http
+            .get("https://openlibrary.org/api/books")
+            .query(&[("bibkeys", format!("ISBN:{}", isbn).as_str()), ("format", "json"), ("jscmd", "data")])
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Open Library: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Open Library: {}", e))?;
+
+        let Some(book) = body.get(format!("ISBN:{}", isbn)) else {
+            return Ok(None);
+        };
+        let Some(mut title) = json_str(book, "/title") else {
+            return Ok(None);
+        };
+        if let Some(subtitle) = json_str(book, "/subtitle") {
+            title = format!("{}: {}", title, subtitle);
+        }
+        Ok(Some(BookMetadata {
+            isbn: isbn.to_string(),
+            title,
+            author: json_names(book, "/authors", Some("name")).join(", "),
+            category: json_names(book, "/subjects", Some("name")).into_iter().next(),
+            cover_url: json_str(book, "/cover/large").or_else(|| json_str(book, "/cover/medium")),
+            publisher: json_names(book, "/publishers", Some("name")).into_iter().next(),
+            published_date: json_str(book, "/publish_date"),
+            source: "openlibrary".to_string(),
+        }))
+    }
+
+    async fn google_books(&self, isbn: &str) -> Result<Option<BookMetadata>, String> {
+        let mut request = self.http
+            .get("https://www.googleapis.com/books/v1/volumes")
+            .query(&[("q", format!("isbn:{}", isbn))]);
+        if let Some(key) = &self.google_api_key {
+            request = request.query(&[("key", key)]);
+        }
+        let body: serde_json::Value = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| format!("Google Books: {}", e))?
+            .json()
+            .await
+            .map_err(|e| format!("Google Books: {}", e))?;
+
+        let Some(info) = body.pointer("/items/0/volumeInfo") else {
+            return Ok(None);
+        };
+        let Some(mut title) = json_str(info, "/title") else {
+            return Ok(None);
+        };
+        if let Some(subtitle) = json_str(info, "/subtitle") {
+            title = format!("{}: {}", title, subtitle);
+        }
+        Ok(Some(BookMetadata {
+            isbn: isbn.to_string(),
+            title,
+            author: json_names(info, "/authors", None).join(", "),
+            category: json_names(info, "/categories", None).into_iter().next(),
+            // Google serves covers over http by default
+            cover_url: json_str(info, "/imageLinks/thumbnail").map(|u| u.replacen("http://", "https://", 1)),
+            publisher: json_str(info, "/publisher"),
+            published_date: json_str(info, "/publishedDate"),
+            source: "googlebooks".to_string(),
+        }))
+    }
+}
+
+fn extract_claims(req: &HttpRequest, jwt_keys: &SharedKeySet) -> Result<Claims, String> {
+    if let Some(auth_header) = req.headers().get("Authorization") {
+        if let Ok(auth_str) = auth_header.to_str() {
+            if let Some(token) = auth_str.strip_prefix("Bearer ") {
+                let keys = jwt_keys.read().expect("JWT key set lock poisoned");
+                return keys.decode(token).ok_or_else(|| "Invalid token".to_string());
+            }
+        }
+    }
+    Err("No token provided".to_string())
+}
+
+fn regex_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\^$.|?*+()[]{}".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// Parses "field" / "-field" into a sort document, limited to `allowed` fields.
+fn parse_sort(sort: Option<&str>, allowed: &[&str], default: Document) -> Result<Document, String> {
+    let Some(sort) = sort.map(str::trim).filter(|s| !s.is_empty()) else {
+        return Ok(default);
+    };
+    let (field, direction) = match sort.strip_prefix('-') {
+        Some(field) => (field, -1),
+        None => (sort, 1),
+    };
+    if !allowed.contains(&field) {
+        return Err(format!(
+            "Invalid sort field '{}'. Must be one of: {}",
+            field,
+            allowed.join(", ")
+        ));
+    }
+    Ok(doc! { field: direction })
+}
+
+// The text index splits ISBNs on hyphens, so ISBN-like queries match by prefix instead
+fn looks_like_isbn(q: &str) -> bool {
+    q.chars().any(|c| c.is_ascii_digit())
+        && q.chars().all(|c| c.is_ascii_digit() || c == '-' || c == 'X' || c == 'x')
+}
+
+async fn health_check() -> HttpResponse {
+    HttpResponse::Ok().json(serde_json::json!({
+        "status": "ok",
+        "service": "library-service"
+    }))
+}
+
+// Book Management
+async fn add_book(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    book_data: web::Json<BookRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Book> = data.db.collection("books");
+    let book_data = book_data.into_inner();
+
+    let given = |value: Option<String>| value.map(|v| v.trim().to_string()).filter(|v| !v.is_empty());
+    let (mut title, mut author, mut category) =
+        (given(book_data.title), given(book_data.author), given(book_data.category));
+    let mut cover_url = None;
+
+    // An ISBN plus a copy count is enough; the rest comes from the lookup
+    if title.is_none() || author.is_none() || category.is_none() {
+        let Some(isbn) = normalize_isbn(&book_data.isbn) else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "title, author and category are required unless isbn is a valid ISBN-10 or ISBN-13"
+            })));
+        };
+        let metadata = match data.isbn_lookup.lookup(&isbn).await {
+            Ok(Some(metadata)) => metadata,
+            Ok(None) => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("No metadata found for ISBN {}; enter title, author and category", isbn)
+            }))),
+            Err(e) => return Ok(HttpResponse::BadGateway().json(serde_json::json!({
+                "error": format!("ISBN lookup failed: {}", e)
+            }))),
+        };
+        title = title.or(Some(metadata.title));
+        author = author.or(Some(metadata.author).filter(|a| !a.is_empty()));
+        category = category.or(metadata.category);
+        cover_url = metadata.cover_url;
+    }
+
+    let (Some(title), Some(author), Some(category)) = (title, author, category) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "The ISBN lookup did not return every field; enter the missing author or category"
+        })));
+    };
+    let category = match resolve_category(&data.db, &claims.campus_id, &category).await? {
+        Ok(category) => category,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+
+    let new_book = Book {
+        id: None,
+        isbn: book_data.isbn.trim().to_string(),
+        title,
+        author,
+        category,
+        total_copies: book_data.total_copies,
+        available_copies: book_data.total_copies,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+        cover_url,
+        status: "active".to_string(),
+        updated_at: None,
+        withdrawn_at: None,
+        withdrawn_by: None,
+        withdrawal_reason: None,
+    };
+
+    collection
+        .insert_one(new_book, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Book added successfully"
+    })))
+}
+
+// Look up title, author, category and cover for an ISBN
+async fn lookup_isbn(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(isbn) = normalize_isbn(&path) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid ISBN; expected a valid ISBN-10 or ISBN-13"
+        })));
+    };
+
+    match data.isbn_lookup.lookup(&isbn).await {
+        Ok(Some(metadata)) => Ok(HttpResponse::Ok().json(metadata)),
+        Ok(None) => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": format!("No metadata found for ISBN {}", isbn)
+        }))),
+        Err(e) => Ok(HttpResponse::BadGateway().json(serde_json::json!({
+            "error": format!("ISBN lookup failed: {}", e)
+        }))),
+    }
+}
+
+async fn get_books(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<BookQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Book> = data.db.collection("books");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    let mut text_search = false;
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        if looks_like_isbn(q) {
+            filter.insert("isbn", doc! { "$regex": format!("^{}", regex_escape(q)), "$options": "i" });
+        } else {
+            filter.insert("$text", doc! { "$search": q });
+            text_search = true;
+        }
+    }
+    if let Some(category) = query.category.as_deref().map(str::trim).filter(|c| !c.is_empty()) {
+        filter.insert("category", category);
+    }
+    if query.available_only.unwrap_or(false) {
+        filter.insert("available_copies", doc! { "$gt": 0 });
+    }
+    if !query.include_withdrawn.unwrap_or(false) {
+        filter.insert("status", doc! { "$ne": "withdrawn" });
+    }
+
+    let default_sort = if text_search {
+        doc! { "score": { "$meta": "textScore" } }
+    } else {
+        doc! { "title": 1 }
+    };
+    let mut sort = match parse_sort(query.sort.as_deref(), &BOOK_SORT_FIELDS, default_sort) {
+        Ok(sort) => sort,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    // Tie-break on _id so pages don't overlap
+    sort.insert("_id", 1);
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut options = FindOptions::builder()
+        .skip((page - 1) * limit)
+        .limit(limit as i64)
+        .sort(sort)
+        .build();
+    if text_search {
+        options.projection = Some(doc! { "score": { "$meta": "textScore" } });
+    }
+
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut books = Vec::new();
+    use futures::stream::StreamExt;
+    
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(book) => books.push(book),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "data": books,
+        "pagination": {
+            "page": page,
+            "limit": limit,
+            "total": total,
+            "total_pages": (total as f64 / limit as f64).ceil() as u64
+        }
+    })))
+}
+
+// Borrowers are users from the auth service, keyed by username. Ids without
+// an account (e.g. students added before accounts existed) borrow as students.
+async fn borrower_role(db: &mongodb::Database, student_id: &str, campus_id: &str) -> Result<String, Error> {
+    let users: Collection<Document> = db.collection("users");
+    let user = users
+        .find_one(doc! { "username": student_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(user
+        .as_ref()
+        .and_then(|u| u.get_str("role").ok())
+        .unwrap_or("student")
+        .to_string())
+}
+
+// Issue Book
+async fn issue_book(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    issue_data: web::Json<IssueRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+
+    // Get book details
+    let book_obj_id = ObjectId::parse_str(&issue_data.book_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let book = book_collection
+        .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let book = match book {
+        Some(b) => b,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Book not found"
+        }))),
+    };
+
+    if book.status == "withdrawn" {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Book has been withdrawn"
+        })));
+    }
+
+    // One copy of a title per borrower at a time
+    let active_filter = doc! {
+        "student_id": &issue_data.student_id,
+        "campus_id": &claims.campus_id,
+        "status": { "$in": ["issued", "overdue"] },
+        "return_date": null
+    };
+    let mut duplicate_filter = active_filter.clone();
+    duplicate_filter.insert("book_id", &issue_data.book_id);
+    let duplicate = issue_collection
+        .find_one(duplicate_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if let Some(existing) = duplicate {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Student already has this book on loan",
+            "code": "already_issued",
+            "issue_id": existing.id,
+            "due_date": existing.due_date
+        })));
+    }
+
+    let role = borrower_role(&data.db, &issue_data.student_id, &claims.campus_id).await?;
+    let limit = data.loan_limit(&role);
+    let active = issue_collection
+        .count_documents(active_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if active as i64 >= limit {
+        return Ok(HttpResponse::UnprocessableEntity().json(serde_json::json!({
+            "error": format!("Loan limit reached: a {} may have {} books on loan at once", role, limit),
+            "code": "loan_limit_reached",
+            "role": role,
+            "limit": limit,
+            "active_loans": active
+        })));
+    }
+
+    // A copy held for this student's hold is already off the shelf
+    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+    let held_entry = waitlist_collection
+        .find_one_and_update(
+            doc! {
+                "book_id": &issue_data.book_id,
+                "student_id": &issue_data.student_id,
+                "status": "notified",
+                "copy_held": true,
+                "campus_id": &claims.campus_id
+            },
+            doc! { "$set": { "status": "fulfilled", "copy_held": false } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let held = held_entry.is_some();
+
+    // Check availability
+    if !held && book.available_copies <= 0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Book not available"
+        })));
+    }
+
+    // Lending a specific copy keeps its status right for stock audits
+    let accession_number = issue_data
+        .accession_number
+        .as_deref()
+        .map(normalize_accession)
+        .filter(|n| !n.is_empty());
+    if let Some(number) = &accession_number {
+        let copies: Collection<BookCopy> = data.db.collection("book_copies");
+        let claimed = copies
+            .update_one(
+                doc! {
+                    "campus_id": &claims.campus_id,
+                    "accession_number": number,
+                    "book_id": &issue_data.book_id,
+                    "status": "available"
+                },
+                doc! { "$set": { "status": "on_loan" } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if claimed.modified_count == 0 {
+            if let Some(entry) = held_entry {
+                waitlist_collection
+                    .update_one(
+                        doc! { "_id": entry.id },
+                        doc! { "$set": { "status": "notified", "copy_held": true } },
+                        None,
+                    )
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("{} is not an available copy of this book", number)
+            })));
+        }
+    }
+
+    // Create issue record
+    let issue_date = Utc::now();
+    let due_date = closed_days(&data.db, &claims.campus_id)
+        .await?
+        .next_open(issue_date + Duration::days(issue_data.days));
+    let receipt_number = next_receipt_number(&data.db, &claims.campus_id, "issue")
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut new_issue = BookIssue {
+        id: None,
+        book_id: issue_data.book_id.clone(),
+        book_title: book.title.clone(),
+        student_id: issue_data.student_id.clone(),
+        issue_date,
+        due_date,
+        return_date: None,
+        status: "issued".to_string(),
+        fine_amount: 0.0,
+        campus_id: claims.campus_id.clone(),
+        fine_fee_id: None,
+        fine_status: None,
+        renewal_count: 0,
+        fine_waived: 0.0,
+        fine_waived_billed: 0.0,
+        accession_number,
+        issue_receipt_number: Some(receipt_number.clone()),
+        return_receipt_number: None,
+        fine_days: None,
+    };
+
+    let inserted = match issue_collection.insert_one(&new_issue, None).await {
+        Ok(inserted) => inserted,
+        // A concurrent request issued the same book to this borrower first;
+        // hand back the copy and the hold claimed above
+        Err(e) if is_duplicate_key(&e) => {
+            if let Some(number) = &new_issue.accession_number {
+                data.db
+                    .collection::<BookCopy>("book_copies")
+                    .update_one(
+                        doc! { "campus_id": &claims.campus_id, "accession_number": number, "status": "on_loan" },
+                        doc! { "$set": { "status": "available" } },
+                        None,
+                    )
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            if let Some(entry) = held_entry {
+                waitlist_collection
+                    .update_one(
+                        doc! { "_id": entry.id },
+                        doc! { "$set": { "status": "notified", "copy_held": true } },
+                        None,
+                    )
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Student already has this book on loan",
+                "code": "already_issued"
+            })));
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    };
+    new_issue.id = inserted.inserted_id.as_object_id();
+
+    // Update book availability
+    if !held {
+        book_collection
+            .update_one(
+                doc! { "_id": book_obj_id },
+                doc! { "$inc": { "available_copies": -1 } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Book issued successfully",
+        "issue_id": new_issue.id,
+        "due_date": due_date,
+        "receipt": build_receipt(&new_issue, Some(&book), "issue", receipt_number)
+    })))
+}
+
+// Return Book
+async fn return_book(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    return_data: web::Json<ReturnRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let book_collection: Collection<Book> = data.db.collection("books");
+
+    // Get issue record
+    let issue_obj_id = ObjectId::parse_str(&return_data.issue_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let issue = issue_collection
+        .find_one(doc! { "_id": issue_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let issue = match issue {
+        Some(i) => i,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue record not found"
+        }))),
+    };
+
+    // Calculate fine if overdue
+    let return_date = Utc::now();
+    let mut fine_amount = 0.0;
+    let mut fine_days = 0;
+    let mut status = "returned".to_string();
+
+    if return_date > issue.due_date {
+        fine_days = closed_days(&data.db, &claims.campus_id)
+            .await?
+            .overdue_days(issue.due_date, return_date);
+        fine_amount = fine_days as f64 * 5.0; // $5 per open day
+        status = "returned_with_fine".to_string();
+    }
+    let receipt_number = next_receipt_number(&data.db, &claims.campus_id, "return")
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Update issue record
+    issue_collection
+        .update_one(
+            doc! { "_id": issue_obj_id },
+            doc! {
+                "$set": {
+                    "return_date": mongodb::bson::to_bson(&return_date)
+                        .map_err(actix_web::error::ErrorInternalServerError)?,
+                    "status": &status,
+                    "fine_amount": fine_amount,
+                    "fine_days": fine_days,
+                    "return_receipt_number": &receipt_number
+                }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Update book availability
+    let book_obj_id = ObjectId::parse_str(&issue.book_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    book_collection
+        .update_one(
+            doc! { "_id": book_obj_id },
+            doc! { "$inc": { "available_copies": 1 } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let book = book_collection
+        .find_one(doc! { "_id": book_obj_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if let Some(number) = &issue.accession_number {
+        let copies: Collection<BookCopy> = data.db.collection("book_copies");
+        copies
+            .update_one(
+                doc! { "campus_id": &claims.campus_id, "accession_number": number, "status": "on_loan" },
+                doc! { "$set": { "status": "available" } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    let returned = BookIssue {
+        return_date: Some(return_date),
+        status,
+        fine_amount,
+        fine_days: Some(fine_days),
+        return_receipt_number: Some(receipt_number.clone()),
+        ..issue
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Book returned successfully",
+        "fine_amount": fine_amount,
+        "receipt": build_receipt(&returned, book.as_ref(), "return", receipt_number)
+    })))
+}
+
+// Update book details; copy counts move total and available together
+async fn update_book(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    update: web::Json<BookUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<Book> = data.db.collection("books");
+    let book_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let book = collection
+        .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let book = match book {
+        Some(b) => b,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Book not found"
+        }))),
+    };
+
+    if book.status == "withdrawn" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book has been withdrawn"
+        })));
+    }
+
+    let mut set = doc! { "updated_at": Utc::now().to_rfc3339() };
+    for (field, value) in [
+        ("isbn", &update.isbn),
+        ("title", &update.title),
+        ("author", &update.author),
+        ("category", &update.category),
+    ] {
+        if let Some(value) = value {
+            if value.trim().is_empty() {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("{} must not be blank", field)
+                })));
+            }
+            set.insert(field, value.trim());
+        }
+    }
+    if let Some(category) = &update.category {
+        match resolve_category(&data.db, &claims.campus_id, category).await? {
+            Ok(category) => set.insert("category", category),
+            Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+        };
+    }
+
+    let mut filter = doc! { "_id": book_obj_id, "status": { "$ne": "withdrawn" } };
+    let mut update_doc = doc! {};
+    if let Some(total_copies) = update.total_copies {
+        let issued = book.total_copies - book.available_copies;
+        if total_copies < issued.max(0) {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("total_copies cannot be less than the {} copies currently issued", issued)
+            })));
+        }
+        let delta = total_copies - book.total_copies;
+        if delta != 0 {
+            // Copies issued since the read above must stay covered
+            filter.insert("total_copies", book.total_copies);
+            if delta < 0 {
+                filter.insert("available_copies", doc! { "$gte": -delta });
+            }
+            update_doc.insert("$inc", doc! { "total_copies": delta, "available_copies": delta });
+        }
+    }
+    update_doc.insert("$set", set);
+
+    let result = collection
+        .update_one(filter, update_doc, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book was changed by another request; try again"
+        })));
+    }
+
+    let book = collection
+        .find_one(doc! { "_id": book_obj_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(book))
+}
+
+// Withdraw (weed) a book; the record is kept for circulation history
+async fn withdraw_book(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<BookWithdrawQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<Book> = data.db.collection("books");
+    let book_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let book = collection
+        .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let book = match book {
+        Some(b) => b,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Book not found"
+        }))),
+    };
+
+    if book.status == "withdrawn" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book is already withdrawn"
+        })));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut set = doc! {
+        "status": "withdrawn",
+        "withdrawn_at": &now,
+        "withdrawn_by": &claims.sub,
+        "updated_at": &now
+    };
+    if let Some(reason) = query.reason.as_deref().map(str::trim).filter(|r| !r.is_empty()) {
+        set.insert("withdrawal_reason", reason);
+    }
+
+    // Every copy must be back on the shelf
+    let result = collection
+        .update_one(
+            doc! {
+                "_id": book_obj_id,
+                "status": { "$ne": "withdrawn" },
+                "$expr": { "$gte": ["$available_copies", "$total_copies"] }
+            },
+            doc! { "$set": set },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book has copies on loan or held for pickup; they must be back on the shelf before it is withdrawn"
+        })));
+    }
+
+    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+    let cancelled = waitlist_collection
+        .update_many(
+            doc! {
+                "book_id": book_obj_id.to_hex(),
+                "status": { "$in": ["waiting", "notified"] },
+                "campus_id": &claims.campus_id
+            },
+            doc! { "$set": { "status": "cancelled" } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Book withdrawn",
+        "waitlist_cancelled": cancelled.modified_count
+    })))
+}
+
+// Renew an issued book for another loan period
+async fn renew_issue(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+
+    let issue_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let issue = issue_collection
+        .find_one(doc! { "_id": issue_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let issue = match issue {
+        Some(i) => i,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue record not found"
+        }))),
+    };
+
+    if claims.role == "student" && claims.sub != issue.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Students can only renew their own books"
+        })));
+    }
+
+    if issue.return_date.is_some() || issue.status.starts_with("returned") {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Book has already been returned"
+        })));
+    }
+
+    // Renewing an overdue book would wipe out the fine it has accrued
+    if Utc::now() > issue.due_date {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Overdue books cannot be renewed; return the book instead"
+        })));
+    }
+
+    if issue.renewal_count >= data.max_renewals {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Renewal limit of {} reached", data.max_renewals)
+        })));
+    }
+
+    let reserved = waitlist_collection
+        .find_one(doc! {
+            "book_id": &issue.book_id,
+            "student_id": { "$ne": &issue.student_id },
+            "status": { "$in": ["waiting", "notified"] },
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if reserved.is_some() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book is reserved by another student and cannot be renewed"
+        })));
+    }
+
+    let due_date = closed_days(&data.db, &claims.campus_id)
+        .await?
+        .next_open(issue.due_date + Duration::days(data.loan_period_days));
+    let renewal_count = issue.renewal_count + 1;
+
+    // Issues created before renewals existed have no renewal_count
+    let current_count = if issue.renewal_count == 0 {
+        doc! { "$in": [0, mongodb::bson::Bson::Null] }
+    } else {
+        doc! { "$eq": issue.renewal_count }
+    };
+
+    let result = issue_collection
+        .update_one(
+            doc! {
+                "_id": issue_obj_id,
+                "status": &issue.status,
+                "renewal_count": current_count
+            },
+            doc! {
+                "$set": {
+                    "due_date": mongodb::bson::to_bson(&due_date)
+                        .map_err(actix_web::error::ErrorInternalServerError)?,
+                    "renewal_count": renewal_count
+                }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Issue was changed by another request; try again"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Book renewed successfully",
+        "due_date": due_date,
+        "renewal_count": renewal_count,
+        "renewals_left": data.max_renewals - renewal_count
+    })))
+}
+
+// List issues, filtered and paginated
+async fn get_issues(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<IssueListQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+
+    // Students only ever see their own issues
+    let student_id = if claims.role == "student" {
+        Some(claims.sub.as_str())
+    } else {
+        query.student_id.as_deref().map(str::trim).filter(|s| !s.is_empty())
+    };
+    if let Some(student_id) = student_id {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(book_id) = query.book_id.as_deref().map(str::trim).filter(|b| !b.is_empty()) {
+        filter.insert("book_id", book_id);
+    }
+    if let Some(status) = query.status.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        if !["issued", "overdue", "returned", "returned_with_fine"].contains(&status) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "status must be one of: issued, overdue, returned, returned_with_fine"
+            })));
+        }
+        filter.insert("status", status);
+    }
+    if query.overdue_only.unwrap_or(false) {
+        // Overdue status is only written by the dashboard, so go by due_date
+        if !filter.contains_key("status") {
+            filter.insert("status", doc! { "$in": ["issued", "overdue"] });
+        }
+        filter.insert("return_date", mongodb::bson::Bson::Null);
+        let now = mongodb::bson::to_bson(&Utc::now()).map_err(actix_web::error::ErrorInternalServerError)?;
+        filter.insert("due_date", doc! { "$lt": now });
+    }
+    if let (Some(from), Some(to)) = (query.from, query.to) {
+        if from > to {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "from must not be after to"
+            })));
+        }
+    }
+    // issue_date is stored as an RFC 3339 string, so the range compares strings
+    let mut issue_range = Document::new();
+    if let Some(from) = query.from {
+        issue_range.insert("$gte", from.to_string());
+    }
+    if let Some(to) = query.to {
+        issue_range.insert("$lt", (to + Duration::days(1)).to_string());
+    }
+    if !issue_range.is_empty() {
+        filter.insert("issue_date", issue_range);
+    }
+
+    let mut sort = match parse_sort(query.sort.as_deref(), &ISSUE_SORT_FIELDS, doc! { "issue_date": -1 }) {
+        Ok(sort) => sort,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    // Tie-break on _id so pages don't overlap
+    sort.insert("_id", 1);
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let total = collection
+        .count_documents(filter.clone(), None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let options = FindOptions::builder()
+        .skip((page - 1) * limit)
+        .limit(limit as i64)
+        .sort(sort)
+        .build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut issues = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(issue) => issues.push(issue),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "data": issues,
+        "pagination": {
+            "page": page,
+            "limit": limit,
+            "total": total,
+            "total_pages": (total as f64 / limit as f64).ceil() as u64
+        }
+    })))
+}
+
+// ===== LIBRARIAN DASHBOARD ENDPOINTS =====
+
+// Get all currently borrowed books
+async fn librarian_borrowed_books(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let now = Utc::now();
+
+    // First, auto-mark overdue: any issued book past due_date
+    let now_bson = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
+    let _ = collection
+        .update_many(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "status": "issued",
+                "due_date": { "$lt": now_bson }
+            },
+            doc! { "$set": { "status": "overdue" } },
+            None,
+        )
+        .await;
+
+    let mut cursor = collection
+        .find(doc! {
+            "campus_id": &claims.campus_id,
+            "status": { "$in": ["issued", "overdue"] }
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut borrowed = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(issue) => borrowed.push(issue),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let overdue_count = borrowed.iter().filter(|b| b.status == "overdue").count();
+    let issued_count = borrowed.iter().filter(|b| b.status == "issued").count();
+
+    // Calculate days remaining/overdue for each
+    let enriched: Vec<serde_json::Value> = borrowed.iter().map(|b| {
+        let days_diff = (b.due_date - now).num_days();
+        serde_json::json!({
+            "_id": b.id,
+            "book_id": b.book_id,
+            "book_title": b.book_title,
+            "student_id": b.student_id,
+            "issue_date": b.issue_date,
+            "due_date": b.due_date,
+            "status": b.status,
+            "days_remaining": if days_diff >= 0 { days_diff } else { 0 },
+            "days_overdue": if days_diff < 0 { -days_diff } else { 0 }
+        })
+    }).collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total_borrowed": borrowed.len(),
+        "issued_count": issued_count,
+        "overdue_count": overdue_count,
+        "books": enriched
+    })))
+}
+
+// Get returned books history
+async fn librarian_returned_books(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+
+    let mut cursor = collection
+        .find(doc! {
+            "campus_id": &claims.campus_id,
+            "status": { "$in": ["returned", "returned_with_fine"] }
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut returned = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(issue) => returned.push(issue),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let total_fines: f64 = returned.iter().map(|r| r.fine_amount).sum();
+    let total_waived: f64 = returned.iter().map(|r| r.fine_waived).sum();
+    let with_fine: Vec<&BookIssue> = returned.iter().filter(|r| r.fine_amount > 0.0).collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total_returned": returned.len(),
+        "total_fines_collected": round2(total_fines - total_waived),
+        "total_fines_waived": round2(total_waived),
+        "returned_with_fine": with_fine.len(),
+        "books": returned
+    })))
+}
+
+// Get library dashboard summary
+async fn librarian_summary(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+
+    // Count books
+    let total_books = book_collection
+        .count_documents(doc! { "campus_id": &claims.campus_id, "status": { "$ne": "withdrawn" } }, None)
+        .await
+        .unwrap_or(0) as i64;
+
+    // Count currently issued
+    let now = Utc::now();
+    let now_bson = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
+
+    // Auto-mark overdue
+    let _ = issue_collection
+        .update_many(
+            doc! { "campus_id": &claims.campus_id, "status": "issued", "due_date": { "$lt": now_bson } },
+            doc! { "$set": { "status": "overdue" } },
+            None,
+        )
+        .await;
+
+    let borrowed_count = issue_collection
+        .count_documents(doc! { "campus_id": &claims.campus_id, "status": { "$in": ["issued", "overdue"] } }, None)
+        .await
+        .unwrap_or(0) as i64;
+
+    let overdue_count = issue_collection
+        .count_documents(doc! { "campus_id": &claims.campus_id, "status": "overdue" }, None)
+        .await
+        .unwrap_or(0) as i64;
+
+    let returned_count = issue_collection
+        .count_documents(doc! { "campus_id": &claims.campus_id, "status": { "$in": ["returned", "returned_with_fine"] } }, None)
+        .await
+        .unwrap_or(0) as i64;
+
+    let waiting_count = waitlist_collection
+        .count_documents(doc! { "campus_id": &claims.campus_id, "status": "waiting" }, None)
+        .await
+        .unwrap_or(0) as i64;
+
+    // Due within 2 days (approaching due)
+    let two_days = now + Duration::days(2);
+    let two_days_bson = mongodb::bson::DateTime::from_millis(two_days.timestamp_millis());
+    let due_soon_count = issue_collection
+        .count_documents(doc! {
+            "campus_id": &claims.campus_id,
+            "status": "issued",
+            "due_date": { "$lte": two_days_bson, "$gte": now_bson }
+        }, None)
+        .await
+        .unwrap_or(0) as i64;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total_books": total_books,
+        "currently_borrowed": borrowed_count,
+        "overdue": overdue_count,
+        "returned": returned_count,
+        "waiting_list": waiting_count,
+        "due_soon": due_soon_count
+    })))
+}
+
+// Add to waiting list
+async fn add_to_waitlist(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    waitlist_data: web::Json<WaitingListRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+
+    // Get book title
+    let book_obj_id = ObjectId::parse_str(&waitlist_data.book_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let book = book_collection
+        .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let book = match book {
+        Some(b) => b,
+        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Book not found"
+        }))),
+    };
+
+    if book.status == "withdrawn" {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Book has been withdrawn"
+        })));
+    }
+
+    // Check if already on waitlist
+    let existing = waitlist_collection
+        .find_one(doc! {
+            "book_id": &waitlist_data.book_id,
+            "student_id": &waitlist_data.student_id,
+            "status": { "$in": ["waiting", "notified"] },
+            "campus_id": &claims.campus_id
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if existing.is_some() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Student is already on the waiting list for this book"
+        })));
+    }
+
+    let entry = WaitingListEntry {
+        id: None,
+        book_id: waitlist_data.book_id.clone(),
+        book_title: book.title.clone(),
+        student_id: waitlist_data.student_id.clone(),
+        queued_at: Utc::now(),
+        status: "waiting".to_string(),
+        campus_id: claims.campus_id,
+        copy_held: false,
+        notified_at: None,
+        hold_expires_at: None,
+        expired_at: None,
+    };
+
+    waitlist_collection
+        .insert_one(entry, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Added to waiting list"
+    })))
+}
+
+// Get waiting list
+async fn get_waitlist(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id, "status": "waiting" }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut entries = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => entries.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "total_waiting": entries.len(),
+        "entries": entries
+    })))
+}
+
+// Notify/fulfill/cancel waiting list entry
+async fn update_waitlist_status(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let (entry_id, new_status) = path.into_inner();
+    if new_status != "notified" && new_status != "fulfilled" && new_status != "cancelled" {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid status. Use: notified, fulfilled, cancelled"
+        })));
+    }
+
+    let collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+    let obj_id = ObjectId::parse_str(&entry_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let Some(entry) = collection
+        .find_one(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Waiting list entry not found"
+        })));
+    };
+
+    if new_status == "notified" {
+        // Notifying sets a copy aside, as the hold job does when it promotes
+        if entry.status != "waiting" {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Entry is {}; only waiting entries can be notified", entry.status)
+            })));
+        }
+        if !hold_copy(&data.db, &entry.book_id).await.map_err(actix_web::error::ErrorInternalServerError)? {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "No copy is available to hold"
+            })));
+        }
+        let now = Utc::now();
+        let result = collection
+            .update_one(
+                doc! { "_id": obj_id, "status": "waiting" },
+                doc! { "$set": {
+                    "status": "notified",
+                    "copy_held": true,
+                    "notified_at": mongodb::bson::to_bson(&now).map_err(actix_web::error::ErrorInternalServerError)?,
+                    "hold_expires_at": mongodb::bson::to_bson(&(now + Duration::days(data.hold_pickup_days)))
+                        .map_err(actix_web::error::ErrorInternalServerError)?
+                } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if result.modified_count == 0 {
+            release_held_copy(&data.db, &entry.book_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "Entry changed while it was being notified"
+            })));
+        }
+    } else {
+        let before = collection
+            .find_one_and_update(
+                doc! { "_id": obj_id, "status": { "$in": ["waiting", "notified"] } },
+                doc! { "$set": { "status": &new_status, "copy_held": false } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        let Some(before) = before else {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Entry is {} and can no longer be changed", entry.status)
+            })));
+        };
+        // A copy set aside for this student goes back on the shelf; the hold job passes it on
+        if before.copy_held {
+            release_held_copy(&data.db, &before.book_id)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Waiting list entry updated to {}", new_status)
+    })))
+}
+
+// ===== STUDENT DASHBOARD - BORROWED BOOKS =====
+
+async fn get_student_books(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own borrowed books"
+        })));
+    }
+
+    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+
+    let mut cursor = collection
+        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut all_books = Vec::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(issue) => all_books.push(issue),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let currently_borrowed: Vec<&BookIssue> = all_books.iter()
+        .filter(|b| b.status == "issued")
+        .collect();
+
+    let returned: Vec<&BookIssue> = all_books.iter()
+        .filter(|b| b.status == "returned" || b.status == "returned_with_fine")
+        .collect();
+
+    // Inter-library loans are listed apart from the library's own books
+    let ill_items = active_ill_items(&data.db, &student_id, &claims.campus_id).await?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "currently_borrowed": currently_borrowed,
+        "returned_books": returned,
+        "ill_items": ill_items,
+        "total_borrowed": currently_borrowed.len(),
+        "total_returned": returned.len()
+    })))
+}
+
+// ===== READING HISTORY =====
+
+async fn reading_history(
+    data: &AppState,
+    claims: &Claims,
+    student_id: &str,
+    query: &HistoryQuery,
+) -> Result<HttpResponse, Error> {
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+
+    let mut filter = doc! { "student_id": student_id, "campus_id": &claims.campus_id };
+    if let Some(year) = query.year {
+        if !(1900..=9999).contains(&year) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "year must be between 1900 and 9999"
+            })));
+        }
+        // issue_date is stored as an RFC 3339 string, so a year is a string range
+        filter.insert("issue_date", doc! {
+            "$gte": format!("{:04}-01-01", year),
+            "$lt": format!("{:04}-01-01", year + 1)
+        });
+    }
+
+    let page = query.page.unwrap_or(1).max(1);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+
+    let total = issue_collection
+        .count_documents(filter.clone(), None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut fines = issue_collection
+        .aggregate(vec![
+            doc! { "$match": filter.clone() },
+            doc! { "$group": {
+                "_id": null,
+                "total_fines": { "$sum": "$fine_amount" },
+                "waived_fines": { "$sum": { "$ifNull": ["$fine_waived", 0.0] } },
+                "unpaid_fines": { "$sum": {
+                    "$cond": [
+                        { "$eq": ["$fine_status", "paid"] },
+                        0.0,
+                        { "$subtract": ["$fine_amount", { "$ifNull": ["$fine_waived", 0.0] }] }
+                    ]
+                } }
+            } },
+        ], None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    use futures::stream::StreamExt;
+
+    let (total_fines, waived_fines, unpaid_fines) = match fines.next().await {
+        Some(row) => {
+            let row = row.map_err(actix_web::error::ErrorInternalServerError)?;
+            (
+                row.get_f64("total_fines").unwrap_or(0.0),
+                row.get_f64("waived_fines").unwrap_or(0.0),
+                row.get_f64("unpaid_fines").unwrap_or(0.0),
+            )
+        }
+        None => (0.0, 0.0, 0.0),
+    };
+
+    let options = FindOptions::builder()
+        .skip((page - 1) * limit)
+        .limit(limit as i64)
+        .sort(doc! { "issue_date": -1, "_id": -1 })
+        .build();
+    let mut cursor = issue_collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut issues = Vec::new();
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(issue) => issues.push(issue),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    // Holds are current, so the year filter does not apply to them
+    let mut cursor = waitlist_collection
+        .find(
+            doc! {
+                "student_id": student_id,
+                "status": { "$in": ["waiting", "notified"] },
+                "campus_id": &claims.campus_id
+            },
+            FindOptions::builder().sort(doc! { "queued_at": 1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut holds = Vec::new();
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(entry) => holds.push(entry),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "data": issues,
+        "holds": holds,
+        "total_fines": total_fines,
+        "waived_fines": round2(waived_fines),
+        "unpaid_fines": round2(unpaid_fines),
+        "pagination": {
+            "page": page,
+            "limit": limit,
+            "total": total,
+            "total_pages": (total as f64 / limit as f64).ceil() as u64
+        }
+    })))
+}
+
+// Reading history of one student
+async fn get_student_history(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let student_id = path.into_inner();
+    if claims.role == "student" && claims.sub != student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own reading history"
+        })));
+    }
+
+    reading_history(&data, &claims, &student_id, &query).await
+}
+
+// Reading history of the signed-in student
+async fn get_my_history(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<HistoryQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    reading_history(&data, &claims, &claims.sub, &query).await
+}
+
+// ===== FINE WAIVERS =====
+
+const AMOUNT_EPSILON: f64 = 0.005;
+
+// Waivers are approved by an admin or a librarian named in LIBRARIANS_IN_CHARGE
+fn can_decide_fine_waivers(data: &AppState, claims: &Claims) -> bool {
+    claims.role == "admin"
+        || (claims.role == "librarian" && data.librarians_in_charge.iter().any(|id| id == &claims.sub))
+}
+
+async fn create_fine_waiver(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<FineWaiverRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "student" && !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.reason.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "reason must not be blank"
+        })));
+    }
+    if body.amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Waiver amount must be positive"
+        })));
+    }
+
+    let issue_id = ObjectId::parse_str(&body.issue_id).map_err(actix_web::error::ErrorBadRequest)?;
+    let issues: Collection<BookIssue> = data.db.collection("book_issues");
+    let Some(issue) = issues
+        .find_one(doc! { "_id": issue_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue not found"
+        })));
+    };
+
+    if claims.role == "student" && claims.sub != issue.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only request waivers on your own fines"
+        })));
+    }
+    if issue.fine_amount <= 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "This issue has no fine"
+        })));
+    }
+    if issue.fine_status.as_deref() == Some("paid") {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fine has already been paid"
+        })));
+    }
+    let outstanding = round2(issue.fine_amount - issue.fine_waived);
+    if body.amount > outstanding + AMOUNT_EPSILON {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Amount {:.2} exceeds the unwaived fine of {:.2}", body.amount, outstanding)
+        })));
+    }
+
+    let collection: Collection<FineWaiver> = data.db.collection("fine_waivers");
+    let open = collection
+        .count_documents(doc! { "issue_id": &body.issue_id, "status": "requested" }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if open > 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "This fine already has a waiver request in progress"
+        })));
+    }
+
+    let now = Utc::now();
+    let amount = round2(body.amount);
+    let reason = body.reason.trim().to_string();
+    let waiver = FineWaiver {
+        id: None,
+        issue_id: body.issue_id,
+        student_id: issue.student_id,
+        book_title: issue.book_title,
+        amount,
+        approved_amount: None,
+        reason: reason.clone(),
+        status: "requested".to_string(),
+        requested_by: claims.sub.clone(),
+        requested_at: now,
+        decided_by: None,
+        decided_at: None,
+        history: vec![FineWaiverEvent {
+            action: "requested".to_string(),
+            actor: claims.sub,
+            at: now,
+            amount: Some(amount),
+            note: Some(reason),
+        }],
+        campus_id: claims.campus_id,
+    };
+
+    let result = collection
+        .insert_one(&waiver, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Waiver requested successfully",
+        "id": result.inserted_id
+    })))
+}
+
+async fn get_fine_waivers(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<FineWaiverQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied"
+        })));
+    } else if let Some(student_id) = query.student_id.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(status) = query.status.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("status", status);
+    }
+
+    let collection: Collection<FineWaiver> = data.db.collection("fine_waivers");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "requested_at": -1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut waivers = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(waiver) => waivers.push(waiver),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(waivers))
+}
+
+// Approve (in full or in part) or reject a waiver request. An approved amount
+// is added to the issue's fine_waived; the finance service leaves it out of the
+// billed fee, or credits it to the fee if the fine was already billed.
+async fn decide_fine_waiver(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    body: web::Json<FineWaiverDecision>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !can_decide_fine_waivers(&data, &claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian-in-charge approval required"
+        })));
+    }
+
+    let (waiver_id, action) = path.into_inner();
+    let status = match action.as_str() {
+        "approve" => "approved",
+        "reject" => "rejected",
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let waiver_id = ObjectId::parse_str(&waiver_id).map_err(actix_web::error::ErrorBadRequest)?;
+
+    let collection: Collection<FineWaiver> = data.db.collection("fine_waivers");
+    let Some(waiver) = collection
+        .find_one(doc! { "_id": waiver_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Waiver not found"
+        })));
+    };
+    if waiver.requested_by == claims.sub {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "A waiver must be decided by someone other than its requester"
+        })));
+    }
+
+    let body = body.into_inner();
+    let amount = match status {
+        "approved" => {
+            let amount = round2(body.amount.unwrap_or(waiver.amount));
+            if amount <= 0.0 || amount > waiver.amount + AMOUNT_EPSILON {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Approved amount must be between 0 and the requested {:.2}", waiver.amount)
+                })));
+            }
+            Some(amount)
+        }
+        _ => None,
+    };
+
+    let now = Utc::now();
+    let event = FineWaiverEvent {
+        action: status.to_string(),
+        actor: claims.sub.clone(),
+        at: now,
+        amount,
+        note: body.note.filter(|n| !n.trim().is_empty()),
+    };
+    let mut set = doc! {
+        "status": status,
+        "decided_by": &claims.sub,
+        "decided_at": now.to_rfc3339()
+    };
+    if let Some(amount) = amount {
+        set.insert("approved_amount", amount);
+    }
+
+    // Claim the request first so two approvals cannot both reduce the fine
+    let result = collection
+        .update_one(
+            doc! { "_id": waiver_id, "status": "requested" },
+            doc! {
+                "$set": set,
+                "$push": { "history": mongodb::bson::to_bson(&event).map_err(actix_web::error::ErrorInternalServerError)? }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Waiver is {}; only requested waivers can be decided", waiver.status)
+        })));
+    }
+
+    let Some(amount) = amount else {
+        return Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Waiver rejected"
+        })));
+    };
+
+    let issue_id = ObjectId::parse_str(&waiver.issue_id).map_err(actix_web::error::ErrorInternalServerError)?;
+    let issues: Collection<BookIssue> = data.db.collection("book_issues");
+    let issue = issues
+        .find_one_and_update(
+            doc! {
+                "_id": issue_id,
+                "fine_status": { "$ne": "paid" },
+                "$expr": { "$lte": [
+                    { "$add": [{ "$ifNull": ["$fine_waived", 0.0] }, amount] },
+                    { "$add": ["$fine_amount", AMOUNT_EPSILON] }
+                ] }
+            },
+            doc! { "$inc": { "fine_waived": amount } },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let Some(issue) = issue else {
+        // The fine was paid or waived since the request; send it back for review
+        collection
+            .update_one(
+                doc! { "_id": waiver_id },
+                doc! {
+                    "$set": { "status": "requested" },
+                    "$unset": { "decided_by": "", "decided_at": "", "approved_amount": "" },
+                    "$pop": { "history": 1 }
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Waiver exceeds the unwaived fine or the fine is already paid"
+        })));
+    };
+
+    // Nothing left to bill
+    if issue.fine_fee_id.is_none() && issue.fine_waived + AMOUNT_EPSILON >= issue.fine_amount {
+        issues
+            .update_one(doc! { "_id": issue_id }, doc! { "$set": { "fine_status": "waived" } }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Waiver approved",
+        "approved_amount": amount,
+        "fine_amount": issue.fine_amount,
+        "fine_waived": round2(issue.fine_waived),
+        "fine_due": round2(issue.fine_amount - issue.fine_waived)
+    })))
+}
+
+// ===== CATEGORIES =====
+
+fn is_duplicate_key(e: &mongodb::error::Error) -> bool {
+    matches!(
+        *e.kind,
+        mongodb::error::ErrorKind::Write(mongodb::error::WriteFailure::WriteError(ref we)) if we.code == 11000
+    )
+}
+
+// "Comp. Sci" and "comp-sci" share the slug "comp-sci"
+fn normalize_course_codes(codes: Vec<String>) -> Vec<String> {
+    let mut codes: Vec<String> = codes
+        .into_iter()
+        .map(|c| c.trim().to_uppercase())
+        .filter(|c| !c.is_empty())
+        .collect();
+    codes.sort();
+    codes.dedup();
+    codes
+}
+
+fn category_slug(name: &str) -> String {
+    let mut slug = String::with_capacity(name.len());
+    for c in name.trim().chars() {
+        if c.is_alphanumeric() {
+            slug.extend(c.to_lowercase());
+        } else if !slug.ends_with('-') && !slug.is_empty() {
+            slug.push('-');
+        }
+    }
+    slug.trim_end_matches('-').to_string()
+}
+
+// Maps a category as typed to its managed name. Campuses that have not set up
+// categories yet keep accepting free text.
+async fn resolve_category(
+    db: &mongodb::Database,
+    campus_id: &str,
+    input: &str,
+) -> Result<Result<String, String>, Error> {
+    let collection: Collection<Category> = db.collection("categories");
+    let managed = collection
+        .count_documents(doc! { "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if managed == 0 {
+        return Ok(Ok(input.trim().to_string()));
+    }
+
+    let slug = category_slug(input);
+    let category = collection
+        .find_one(doc! {
+            "campus_id": campus_id,
+            "$or": [{ "slug": &slug }, { "aliases": &slug }]
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(category.map(|c| c.name).ok_or_else(|| {
+        format!("Unknown category '{}'; add it under /api/categories first", input.trim())
+    }))
+}
+
+async fn find_category(db: &mongodb::Database, id: &str, campus_id: &str) -> Result<Option<Category>, Error> {
+    let obj_id = ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Category> = db.collection("categories");
+    collection
+        .find_one(doc! { "_id": obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Only subjects (top-level categories) can be parents, so the tree is two levels deep
+async fn check_category_parent(
+    db: &mongodb::Database,
+    parent_id: &str,
+    campus_id: &str,
+) -> Result<Result<ObjectId, String>, Error> {
+    match find_category(db, parent_id, campus_id).await? {
+        None => Ok(Err("Parent category not found".to_string())),
+        Some(parent) if parent.parent_id.is_some() => {
+            Ok(Err(format!("'{}' is a sub-subject and cannot have children", parent.name)))
+        }
+        Some(parent) => Ok(parent.id.ok_or_else(|| "Parent category has no id".to_string())),
+    }
+}
+
+// A slug used as another category's alias is taken too
+async fn category_slug_taken(
+    db: &mongodb::Database,
+    slug: &str,
+    campus_id: &str,
+    except: Option<ObjectId>,
+) -> Result<bool, Error> {
+    let collection: Collection<Category> = db.collection("categories");
+    let mut filter = doc! { "campus_id": campus_id, "$or": [{ "slug": slug }, { "aliases": slug }] };
+    if let Some(except) = except {
+        filter.insert("_id", doc! { "$ne": except });
+    }
+    let taken = collection
+        .find_one(filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(taken.is_some())
+}
+
+async fn create_category(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<CategoryRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    let name = body.name.trim().to_string();
+    let slug = category_slug(&name);
+    if slug.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name must contain letters or digits"
+        })));
+    }
+
+    let parent_id = match body.parent_id.as_deref().filter(|p| !p.is_empty()) {
+        Some(parent_id) => match check_category_parent(&data.db, parent_id, &claims.campus_id).await? {
+            Ok(parent_id) => Some(parent_id),
+            Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+        },
+        None => None,
+    };
+
+    if category_slug_taken(&data.db, &slug, &claims.campus_id, None).await? {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A category matching '{}' already exists", name)
+        })));
+    }
+
+    let category = Category {
+        id: None,
+        name,
+        slug,
+        parent_id,
+        description: body.description,
+        aliases: Vec::new(),
+        course_codes: normalize_course_codes(body.course_codes.unwrap_or_default()),
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<Category> = data.db.collection("categories");
+    match collection.insert_one(&category, None).await {
+        Ok(result) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "message": "Category created successfully",
+            "id": result.inserted_id
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("A category matching '{}' already exists", category.name)
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+// Subjects with their sub-subjects and how many books each holds
+async fn get_categories(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let collection: Collection<Category> = data.db.collection("categories");
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, FindOptions::builder().sort(doc! { "name": 1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut categories = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(category) => categories.push(category),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let counts = book_counts_by_category(&data.db, &claims.campus_id).await?;
+    let count = |name: &str| counts.get(name).copied().unwrap_or(0);
+
+    let tree: Vec<serde_json::Value> = categories
+        .iter()
+        .filter(|c| c.parent_id.is_none())
+        .map(|subject| {
+            let children: Vec<serde_json::Value> = categories
+                .iter()
+                .filter(|c| c.parent_id.is_some() && c.parent_id == subject.id)
+                .map(|child| serde_json::json!({
+                    "_id": child.id,
+                    "name": child.name,
+                    "slug": child.slug,
+                    "description": child.description,
+                    "aliases": child.aliases,
+                    "book_count": count(&child.name)
+                }))
+                .collect();
+            serde_json::json!({
+                "_id": subject.id,
+                "name": subject.name,
+                "slug": subject.slug,
+                "description": subject.description,
+                "aliases": subject.aliases,
+                "book_count": count(&subject.name),
+                "children": children
+            })
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(tree))
+}
+
+async fn book_counts_by_category(
+    db: &mongodb::Database,
+    campus_id: &str,
+) -> Result<std::collections::HashMap<String, i64>, Error> {
+    let collection: Collection<Book> = db.collection("books");
+    let mut cursor = collection
+        .aggregate(vec![
+            doc! { "$match": { "campus_id": campus_id, "status": { "$ne": "withdrawn" } } },
+            doc! { "$group": { "_id": "$category", "books": { "$sum": 1 } } },
+        ], None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut counts = std::collections::HashMap::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let row = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Ok(category) = row.get_str("_id") {
+            counts.insert(category.to_string(), row.get_i32("books").unwrap_or(0) as i64);
+        }
+    }
+    Ok(counts)
+}
+
+// Rename, re-parent or describe a category; renaming relabels its books
+async fn update_category(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<CategoryUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(category) = find_category(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Category not found"
+        })));
+    };
+
+    let collection: Collection<Category> = data.db.collection("categories");
+    let body = body.into_inner();
+    let mut update = doc! {};
+    let mut set = doc! {};
+
+    if let Some(description) = body.description {
+        set.insert("description", description);
+    }
+
+    if let Some(course_codes) = body.course_codes {
+        set.insert("course_codes", normalize_course_codes(course_codes));
+    }
+
+    if let Some(parent_id) = body.parent_id {
+        if parent_id.is_empty() {
+            set.insert("parent_id", mongodb::bson::Bson::Null);
+        } else {
+            let has_children = collection
+                .find_one(doc! { "parent_id": category.id, "campus_id": &claims.campus_id }, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .is_some();
+            if has_children {
+                return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                    "error": "A subject with sub-subjects cannot be moved under another subject"
+                })));
+            }
+            let parent_id = match check_category_parent(&data.db, &parent_id, &claims.campus_id).await? {
+                Ok(parent_id) => parent_id,
+                Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+            };
+            if Some(parent_id) == category.id {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": "A category cannot be its own parent"
+                })));
+            }
+            set.insert("parent_id", parent_id);
+        }
+    }
+
+    let mut renamed = None;
+    if let Some(name) = body.name.map(|n| n.trim().to_string()).filter(|n| *n != category.name) {
+        let slug = category_slug(&name);
+        if slug.is_empty() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "name must contain letters or digits"
+            })));
+        }
+        if category_slug_taken(&data.db, &slug, &claims.campus_id, category.id).await? {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("A category matching '{}' already exists", name)
+            })));
+        }
+        set.insert("name", &name);
+        set.insert("slug", &slug);
+        // The old name keeps resolving to this category
+        if slug != category.slug {
+            update.insert("$addToSet", doc! { "aliases": &category.slug });
+            update.insert("$pull", doc! { "aliases": &slug });
+        }
+        renamed = Some(name);
+    }
+
+    if set.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+    update.insert("$set", set);
+
+    match collection.update_one(doc! { "_id": category.id }, update, None).await {
+        Ok(_) => {}
+        Err(e) if is_duplicate_key(&e) => return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "A category with that name already exists"
+        }))),
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+
+    let mut books_updated = 0;
+    if let Some(name) = &renamed {
+        let book_collection: Collection<Book> = data.db.collection("books");
+        books_updated = book_collection
+            .update_many(
+                doc! { "campus_id": &claims.campus_id, "category": &category.name },
+                doc! { "$set": { "category": name } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+            .modified_count;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Category updated successfully",
+        "books_updated": books_updated
+    })))
+}
+
+async fn delete_category(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(category) = find_category(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Category not found"
+        })));
+    };
+
+    let collection: Collection<Category> = data.db.collection("categories");
+    let has_children = collection
+        .find_one(doc! { "parent_id": category.id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .is_some();
+    if has_children {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Delete or move the sub-subjects first"
+        })));
+    }
+
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let books = book_collection
+        .count_documents(doc! { "campus_id": &claims.campus_id, "category": &category.name }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if books > 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} books use this category; merge it into another category instead", books)
+        })));
+    }
+
+    collection
+        .delete_one(doc! { "_id": category.id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Category deleted successfully"
+    })))
+}
+
+// Book category values that match no managed category, for migration
+async fn get_unmanaged_categories(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<Category> = data.db.collection("categories");
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut names = std::collections::HashSet::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let category = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        names.insert(category.name);
+    }
+
+    let counts = book_counts_by_category(&data.db, &claims.campus_id).await?;
+    let mut unmanaged: Vec<(String, i64)> = counts
+        .into_iter()
+        .filter(|(category, _)| !names.contains(category))
+        .collect();
+    unmanaged.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+
+    let unmanaged: Vec<serde_json::Value> = unmanaged
+        .into_iter()
+        .map(|(category, books)| serde_json::json!({
+            "category": category,
+            "slug": category_slug(&category),
+            "books": books
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(unmanaged))
+}
+
+// Fold free-text values and duplicate categories into one managed category
+async fn merge_categories(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<CategoryMergeRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    let Some(target) = find_category(&data.db, &body.target_id, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Target category not found"
+        })));
+    };
+
+    let sources: Vec<String> = body
+        .sources
+        .iter()
+        .map(|s| s.trim().to_string())
+        .filter(|s| !category_slug(s).is_empty())
+        .collect();
+    if sources.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "sources must list at least one category"
+        })));
+    }
+    let source_slugs: Vec<String> = sources
+        .iter()
+        .map(|s| category_slug(s))
+        .filter(|s| *s != target.slug)
+        .collect();
+
+    let collection: Collection<Category> = data.db.collection("categories");
+    let mut cursor = collection
+        .find(doc! {
+            "campus_id": &claims.campus_id,
+            "slug": { "$in": &source_slugs },
+            "_id": { "$ne": target.id }
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut merged = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        merged.push(result.map_err(actix_web::error::ErrorInternalServerError)?);
+    }
+
+    let merged_ids: Vec<ObjectId> = merged.iter().filter_map(|c| c.id).collect();
+
+    // The target may itself be a sub-subject of a category being merged away
+    let target_parent = target.parent_id.filter(|p| !merged_ids.contains(p));
+    if target_parent != target.parent_id {
+        collection
+            .update_one(doc! { "_id": target.id }, doc! { "$set": { "parent_id": mongodb::bson::Bson::Null } }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    // Sub-subjects of merged categories move under the target, keeping the tree two levels deep
+    collection
+        .update_many(
+            doc! {
+                "parent_id": { "$in": &merged_ids },
+                "_id": { "$ne": target.id },
+                "campus_id": &claims.campus_id
+            },
+            doc! { "$set": { "parent_id": target_parent.or(target.id) } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let categories_removed = collection
+        .delete_many(doc! { "_id": { "$in": &merged_ids } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .deleted_count;
+
+    let mut aliases: Vec<String> = source_slugs.clone();
+    for category in &merged {
+        aliases.push(category.slug.clone());
+        aliases.extend(category.aliases.iter().cloned());
+    }
+    aliases.retain(|a| *a != target.slug);
+    aliases.sort();
+    aliases.dedup();
+    let course_codes: Vec<String> = merged.iter().flat_map(|c| c.course_codes.iter().cloned()).collect();
+    collection
+        .update_one(
+            doc! { "_id": target.id },
+            doc! { "$addToSet": {
+                "aliases": { "$each": &aliases },
+                "course_codes": { "$each": &course_codes }
+            } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Relabel books whose category matches a source by spelling, case aside, or by merged name
+    let mut matches: Vec<mongodb::bson::Document> = sources
+        .iter()
+        .map(|s| doc! { "category": { "$regex": format!("^{}$", regex_escape(s)), "$options": "i" } })
+        .collect();
+    for category in &merged {
+        matches.push(doc! { "category": &category.name });
+    }
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let books_updated = book_collection
+        .update_many(
+            doc! { "campus_id": &claims.campus_id, "$or": matches },
+            doc! { "$set": { "category": &target.name } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .modified_count;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Merged into {}", target.name),
+        "books_updated": books_updated,
+        "categories_removed": categories_removed,
+        "aliases": aliases
+    })))
+}
+
+// ===== INTER-LIBRARY LOANS =====
+
+// Where an ILL request may move next; returned_to_lender and cancelled are final
+fn next_ill_statuses(status: &str) -> &'static [&'static str] {
+    match status {
+        "requested" => &["ordered", "cancelled"],
+        "ordered" => &["received", "cancelled"],
+        "received" => &["issued", "returned_to_lender"],
+        "issued" => &["returned_to_lender"],
+        _ => &[],
+    }
+}
+
+// Request a title the library does not hold from a partner library
+async fn create_ill_request(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<IllRequestCreate>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let body = body.into_inner();
+    let student_id = if claims.role == "student" {
+        if body.student_id.as_ref().is_some_and(|s| *s != claims.sub) {
+            return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+                "error": "Students can only request inter-library loans for themselves"
+            })));
+        }
+        claims.sub.clone()
+    } else if claims.role == "librarian" || claims.role == "admin" {
+        match body.student_id.filter(|s| !s.trim().is_empty()) {
+            Some(student_id) => student_id,
+            None => return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "student_id is required"
+            }))),
+        }
+    } else {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Only students and librarians can request inter-library loans"
+        })));
+    };
+
+    if body.title.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "title must not be blank"
+        })));
+    }
+
+    // Titles already on the shelf go through the normal issue and waitlist flow
+    if let Some(isbn) = body.isbn.as_deref().map(str::trim).filter(|i| !i.is_empty()) {
+        let book_collection: Collection<Book> = data.db.collection("books");
+        let held = book_collection
+            .find_one(doc! {
+                "isbn": isbn,
+                "status": { "$ne": "withdrawn" },
+                "campus_id": &claims.campus_id
+            }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(book) = held {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": "This title is in the catalog; issue it or join its waiting list",
+                "book_id": book.id
+            })));
+        }
+    }
+
+    let now = Utc::now();
+    let request = IllRequest {
+        id: None,
+        student_id,
+        title: body.title.trim().to_string(),
+        author: body.author.filter(|a| !a.trim().is_empty()),
+        isbn: body.isbn.filter(|i| !i.trim().is_empty()),
+        notes: body.notes,
+        status: "requested".to_string(),
+        partner_library: None,
+        partner_reference: None,
+        lending_fee: 0.0,
+        postage: 0.0,
+        due_date: None,
+        lender_due_date: None,
+        history: vec![IllStatusChange {
+            status: "requested".to_string(),
+            changed_by: claims.sub,
+            changed_at: now,
+            note: None,
+        }],
+        requested_at: now,
+        campus_id: claims.campus_id,
+    };
+
+    let collection: Collection<IllRequest> = data.db.collection("ill_requests");
+    let result = collection
+        .insert_one(&request, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Inter-library loan requested",
+        "id": result.inserted_id
+    })))
+}
+
+// Librarians see every request; students see their own
+async fn get_ill_requests(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<IllQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role == "student" {
+        filter.insert("student_id", &claims.sub);
+    } else if let Some(student_id) = query.student_id.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("student_id", student_id);
+    }
+    if let Some(status) = query.status.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("status", status);
+    }
+
+    let collection: Collection<IllRequest> = data.db.collection("ill_requests");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "requested_at": -1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut requests = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(request) => requests.push(request),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+// Move an ILL request along requested → ordered → received → issued → returned_to_lender
+async fn update_ill_status(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<IllStatusUpdate>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<IllRequest> = data.db.collection("ill_requests");
+    let Some(request) = collection
+        .find_one(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Inter-library loan request not found"
+        })));
+    };
+
+    let body = body.into_inner();
+    let allowed = next_ill_statuses(&request.status);
+    if !allowed.contains(&body.status.as_str()) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Cannot move a {} request to {}", request.status, body.status),
+            "allowed": allowed
+        })));
+    }
+
+    let now = Utc::now();
+    let mut set = doc! { "status": &body.status };
+
+    let partner_library = body
+        .partner_library
+        .filter(|p| !p.trim().is_empty())
+        .or(request.partner_library);
+    if body.status == "ordered" && partner_library.is_none() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "partner_library is required to order the item"
+        })));
+    }
+    if let Some(partner_library) = partner_library {
+        set.insert("partner_library", partner_library.trim());
+    }
+    if let Some(reference) = body.partner_reference.filter(|r| !r.trim().is_empty()) {
+        set.insert("partner_reference", reference.trim());
+    }
+    for (field, value) in [("lending_fee", body.lending_fee), ("postage", body.postage)] {
+        if let Some(value) = value {
+            if value < 0.0 {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("{} must not be negative", field)
+                })));
+            }
+            set.insert(field, value);
+        }
+    }
+    if let Some(lender_due_date) = body.lender_due_date {
+        set.insert("lender_due_date", lender_due_date.to_string());
+    }
+
+    if body.status == "issued" {
+        // The student must bring the item back before it is due at the lender
+        let lender_due_date = body.lender_due_date.or(request.lender_due_date);
+        let Some(due_date) = body.due_date.or(lender_due_date) else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "due_date is required to issue the item"
+            })));
+        };
+        if lender_due_date.is_some_and(|lender| due_date > lender) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "due_date must not be after the lender's due date"
+            })));
+        }
+        set.insert("due_date", due_date.to_string());
+    }
+
+    let change = IllStatusChange {
+        status: body.status.clone(),
+        changed_by: claims.sub,
+        changed_at: now,
+        note: body.note.filter(|n| !n.trim().is_empty()),
+    };
+
+    let updated = collection
+        .find_one_and_update(
+            doc! { "_id": obj_id, "status": &request.status },
+            doc! {
+                "$set": set,
+                "$push": {
+                    "history": mongodb::bson::to_bson(&change)
+                        .map_err(actix_web::error::ErrorInternalServerError)?
+                }
+            },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match updated {
+        Some(updated) => Ok(HttpResponse::Ok().json(updated)),
+        None => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Request was updated by someone else; reload and try again"
+        }))),
+    }
+}
+
+async fn active_ill_items(db: &mongodb::Database, student_id: &str, campus_id: &str) -> Result<Vec<IllRequest>, Error> {
+    let collection: Collection<IllRequest> = db.collection("ill_requests");
+    let mut cursor = collection
+        .find(
+            doc! {
+                "student_id": student_id,
+                "status": { "$in": ["requested", "ordered", "received", "issued"] },
+                "campus_id": campus_id
+            },
+            FindOptions::builder().sort(doc! { "requested_at": -1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut items = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(item) => items.push(item),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+    Ok(items)
+}
+
+// ===== E-RESOURCES =====
+
+// Largest PDF accepted by the upload endpoint
+const E_RESOURCE_MAX_BYTES: usize = 50 * 1024 * 1024;
+// Largest accepted value for a plain-text multipart field
+const E_RESOURCE_MAX_FIELD_BYTES: usize = 4 * 1024;
+
+fn e_resources_bucket(db: &mongodb::Database) -> mongodb::gridfs::GridFsBucket {
+    db.gridfs_bucket(
+        mongodb::options::GridFsBucketOptions::builder()
+            .bucket_name("e_resources".to_string())
+            .build(),
+    )
+}
+
+fn is_librarian(claims: &Claims) -> bool {
+    claims.role == "librarian" || claims.role == "admin"
+}
+
+// Students see "all" resources; every other role also sees "staff" ones
+fn can_see_e_resource(claims: &Claims, resource: &EResource) -> bool {
+    claims.role != "student" || resource.access == "all"
+}
+
+fn license_expired(resource: &EResource, today: NaiveDate) -> bool {
+    resource.license.as_ref().is_some_and(|l| l.expires_on < today)
+}
+
+fn validate_e_resource_access(access: &str) -> Result<(), String> {
+    match access {
+        "all" | "staff" => Ok(()),
+        _ => Err(format!("Invalid access '{}'. Must be: all or staff", access)),
+    }
+}
+
+fn validate_license(license: &EResourceLicense) -> Result<(), String> {
+    if license.provider.trim().is_empty() {
+        return Err("license.provider must not be blank".to_string());
+    }
+    if license.expires_on < license.starts_on {
+        return Err("license.expires_on must not be before license.starts_on".to_string());
+    }
+    Ok(())
+}
+
+async fn find_e_resource(
+    db: &mongodb::Database,
+    id: &str,
+    campus_id: &str,
+) -> Result<Option<EResource>, Error> {
+    let obj_id = ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<EResource> = db.collection("e_resources");
+    collection
+        .find_one(doc! { "_id": obj_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+// Refuses resources the caller cannot see or whose license has lapsed
+fn check_e_resource_access(claims: &Claims, resource: &EResource) -> Option<HttpResponse> {
+    if !can_see_e_resource(claims, resource) {
+        return Some(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "E-resource not found"
+        })));
+    }
+    if !is_librarian(claims) && license_expired(resource, Utc::now().date_naive()) {
+        let expires_on = resource.license.as_ref().map(|l| l.expires_on);
+        return Some(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "The license for this resource has expired",
+            "expired_on": expires_on
+        })));
+    }
+    None
+}
+
+async fn record_e_resource_access(
+    db: &mongodb::Database,
+    claims: &Claims,
+    resource_id: ObjectId,
+    action: &str,
+) -> Result<(), Error> {
+    let collection: Collection<EResourceAccess> = db.collection("e_resource_access");
+    collection
+        .insert_one(
+            EResourceAccess {
+                id: None,
+                resource_id,
+                user_id: claims.sub.clone(),
+                role: claims.role.clone(),
+                action: action.to_string(),
+                accessed_at: Utc::now(),
+                campus_id: claims.campus_id.clone(),
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(())
+}
+
+// Add a link or licensed e-journal
+async fn add_e_resource(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<EResourceRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.kind != "link" && body.kind != "ejournal" {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Invalid kind. Use: link, ejournal (upload PDFs to /api/e-resources/upload)"
+        })));
+    }
+    if body.title.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "title must not be blank"
+        })));
+    }
+    if !body.url.starts_with("https://") && !body.url.starts_with("http://") {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "url must be an http(s) URL"
+        })));
+    }
+    let access = body.access.unwrap_or_else(|| "all".to_string());
+    if let Err(e) = validate_e_resource_access(&access) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+    match &body.license {
+        Some(license) => {
+            if let Err(e) = validate_license(license) {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+            }
+        }
+        None if body.kind == "ejournal" => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "E-journals need a license"
+            })));
+        }
+        None => {}
+    }
+
+    let resource = EResource {
+        id: None,
+        kind: body.kind,
+        title: body.title.trim().to_string(),
+        description: body.description.unwrap_or_default(),
+        category: body.category,
+        url: Some(body.url),
+        file_id: None,
+        filename: None,
+        content_type: None,
+        size: None,
+        access,
+        license: body.license,
+        added_by: claims.sub,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+        updated_at: None,
+    };
+
+    let collection: Collection<EResource> = data.db.collection("e_resources");
+    let result = collection
+        .insert_one(&resource, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "E-resource added successfully",
+        "id": result.inserted_id
+    })))
+}
+
+// Upload a PDF: multipart/form-data with a `file` part and `title`,
+// optional `description`, `category` and `access` text parts
+async fn upload_e_resource(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    mut payload: actix_multipart::Multipart,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    use futures::io::AsyncWriteExt;
+    use futures::stream::StreamExt;
+
+    let invalid_body = |e: actix_multipart::MultipartError| {
+        actix_web::error::ErrorBadRequest(format!("Invalid multipart body: {}", e))
+    };
+
+    let bucket = e_resources_bucket(&data.db);
+    let mut fields: std::collections::HashMap<String, String> = std::collections::HashMap::new();
+    let mut upload: Option<(ObjectId, String, usize)> = None;
+
+    while let Some(field) = payload.next().await {
+        let mut field = field.map_err(invalid_body)?;
+        let name = field.name().unwrap_or_default().to_string();
+
+        if name == "file" {
+            if upload.is_some() {
+                return Err(actix_web::error::ErrorBadRequest("Only one file can be uploaded at a time"));
+            }
+            let filename = field
+                .content_disposition()
+                .and_then(|cd| cd.get_filename())
+                .map(|f| f.rsplit(['/', '\\']).next().unwrap_or(f).to_string())
+                .filter(|f| !f.trim().is_empty())
+                .ok_or_else(|| actix_web::error::ErrorBadRequest("The file part needs a filename"))?;
+            let is_pdf = field.content_type().is_some_and(|m| m.essence_str() == "application/pdf")
+                || filename.to_lowercase().ends_with(".pdf");
+            if !is_pdf {
+                return Err(actix_web::error::ErrorBadRequest("Only PDF files can be uploaded"));
+            }
+
+            let mut stream = bucket.open_upload_stream(&filename, None);
+            let file_id = stream
+                .id()
+                .as_object_id()
+                .ok_or_else(|| actix_web::error::ErrorInternalServerError("GridFS file id is not an ObjectId"))?;
+            let mut size = 0;
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(invalid_body)?;
+                size += chunk.len();
+                if size > E_RESOURCE_MAX_BYTES {
+                    stream.abort().await.map_err(actix_web::error::ErrorInternalServerError)?;
+                    return Err(actix_web::error::ErrorBadRequest(format!(
+                        "File exceeds the {} MB limit",
+                        E_RESOURCE_MAX_BYTES / (1024 * 1024)
+                    )));
+                }
+                stream.write_all(&chunk).await.map_err(actix_web::error::ErrorInternalServerError)?;
+            }
+            stream.close().await.map_err(actix_web::error::ErrorInternalServerError)?;
+            upload = Some((file_id, filename, size));
+        } else {
+            let mut value = Vec::new();
+            while let Some(chunk) = field.next().await {
+                let chunk = chunk.map_err(invalid_body)?;
+                value.extend_from_slice(&chunk);
+                if value.len() > E_RESOURCE_MAX_FIELD_BYTES {
+                    return Err(actix_web::error::ErrorBadRequest(format!("Field '{}' is too long", name)));
+                }
+            }
+            let value = String::from_utf8(value)
+                .map_err(|_| actix_web::error::ErrorBadRequest(format!("Field '{}' must be UTF-8 text", name)))?;
+            fields.insert(name, value);
+        }
+    }
+
+    let Some((file_id, filename, size)) = upload else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'file' is required"
+        })));
+    };
+
+    // Validate the text fields only now that the whole body has been read,
+    // and drop the stored file if they are rejected
+    let title = fields.get("title").map(|t| t.trim().to_string()).unwrap_or_default();
+    let access = fields.get("access").cloned().unwrap_or_else(|| "all".to_string());
+    let rejected = if title.is_empty() {
+        Some("title must not be blank".to_string())
+    } else {
+        validate_e_resource_access(&access).err()
+    };
+    if let Some(e) = rejected {
+        bucket
+            .delete(file_id.into())
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+    }
+
+    let resource = EResource {
+        id: None,
+        kind: "pdf".to_string(),
+        title,
+        description: fields.get("description").cloned().unwrap_or_default(),
+        category: fields.get("category").cloned().filter(|c| !c.trim().is_empty()),
+        url: None,
+        file_id: Some(file_id),
+        filename: Some(filename),
+        content_type: Some("application/pdf".to_string()),
+        size: Some(size as i64),
+        access,
+        license: None,
+        added_by: claims.sub,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+        updated_at: None,
+    };
+
+    let collection: Collection<EResource> = data.db.collection("e_resources");
+    let result = collection
+        .insert_one(&resource, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "E-resource uploaded successfully",
+        "id": result.inserted_id
+    })))
+}
+
+// List e-resources the caller can open
+async fn get_e_resources(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<EResourceQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(kind) = query.kind.as_deref().filter(|k| !k.is_empty()) {
+        filter.insert("kind", kind);
+    }
+    if let Some(category) = query.category.as_deref().filter(|c| !c.is_empty()) {
+        filter.insert("category", category);
+    }
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        filter.insert("title", doc! { "$regex": regex_escape(q), "$options": "i" });
+    }
+    if claims.role == "student" {
+        filter.insert("access", "all");
+    }
+    // Lapsed licenses are hidden from readers but stay visible to librarians
+    if !is_librarian(&claims) {
+        filter.insert("license.expires_on", doc! { "$not": { "$lt": Utc::now().date_naive().to_string() } });
+    }
+
+    let collection: Collection<EResource> = data.db.collection("e_resources");
+    let options = FindOptions::builder().sort(doc! { "title": 1 }).build();
+    let mut cursor = collection
+        .find(filter, options)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut resources = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(resource) => resources.push(resource),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(resources))
+}
+
+// Open an e-resource; counts as a view
+async fn view_e_resource(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(resource) = find_e_resource(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "E-resource not found"
+        })));
+    };
+    if let Some(refused) = check_e_resource_access(&claims, &resource) {
+        return Ok(refused);
+    }
+
+    if let Some(id) = resource.id {
+        record_e_resource_access(&data.db, &claims, id, "view").await?;
+    }
+
+    Ok(HttpResponse::Ok().json(resource))
+}
+
+// Download an uploaded PDF; counts as a download
+async fn download_e_resource(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(resource) = find_e_resource(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "E-resource not found"
+        })));
+    };
+    if let Some(refused) = check_e_resource_access(&claims, &resource) {
+        return Ok(refused);
+    }
+    let (Some(id), Some(file_id)) = (resource.id, resource.file_id) else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "This e-resource is a link and has no file to download"
+        })));
+    };
+
+    let mut bytes = Vec::with_capacity(resource.size.unwrap_or(0).max(0) as usize);
+    e_resources_bucket(&data.db)
+        .download_to_futures_0_3_writer(file_id.into(), &mut bytes)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    record_e_resource_access(&data.db, &claims, id, "download").await?;
+
+    let filename = resource.filename.unwrap_or_else(|| format!("{}.pdf", id.to_hex()));
+    Ok(HttpResponse::Ok()
+        .content_type(resource.content_type.as_deref().unwrap_or("application/pdf"))
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}\"", filename.replace('"', "")),
+        ))
+        .body(bytes))
+}
+
+// Update metadata, access or license (e.g. a renewed e-journal subscription)
+async fn update_e_resource(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<EResourceUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(resource) = find_e_resource(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "E-resource not found"
+        })));
+    };
+
+    let body = body.into_inner();
+    let mut set = doc! { "updated_at": Utc::now().to_rfc3339() };
+    if let Some(title) = body.title {
+        if title.trim().is_empty() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "title must not be blank"
+            })));
+        }
+        set.insert("title", title.trim());
+    }
+    if let Some(description) = body.description {
+        set.insert("description", description);
+    }
+    if let Some(category) = body.category {
+        set.insert("category", category);
+    }
+    if let Some(url) = body.url {
+        if resource.kind == "pdf" {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "Uploaded PDFs have no url"
+            })));
+        }
+        if !url.starts_with("https://") && !url.starts_with("http://") {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "url must be an http(s) URL"
+            })));
+        }
+        set.insert("url", url);
+    }
+    if let Some(access) = body.access {
+        if let Err(e) = validate_e_resource_access(&access) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+        set.insert("access", access);
+    }
+    if let Some(license) = body.license {
+        if let Err(e) = validate_license(&license) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e })));
+        }
+        set.insert(
+            "license",
+            mongodb::bson::to_bson(&license).map_err(actix_web::error::ErrorInternalServerError)?,
+        );
+    }
+
+    let collection: Collection<EResource> = data.db.collection("e_resources");
+    let updated = collection
+        .find_one_and_update(
+            doc! { "_id": resource.id },
+            doc! { "$set": set },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(updated))
+}
+
+async fn delete_e_resource(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<EResource> = data.db.collection("e_resources");
+    let Some(resource) = collection
+        .find_one_and_delete(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "E-resource not found"
+        })));
+    };
+
+    // Access logs are kept for usage reporting
+    if let Some(file_id) = resource.file_id {
+        e_resources_bucket(&data.db)
+            .delete(file_id.into())
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "E-resource deleted successfully"
+    })))
+}
+
+// Views and downloads of one resource, per reader
+async fn e_resource_usage(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(resource) = find_e_resource(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "E-resource not found"
+        })));
+    };
+
+    let collection: Collection<EResourceAccess> = data.db.collection("e_resource_access");
+    let pipeline = vec![
+        doc! { "$match": { "resource_id": resource.id, "campus_id": &claims.campus_id } },
+        doc! { "$group": {
+            "_id": { "user_id": "$user_id", "role": "$role" },
+            "views": { "$sum": { "$cond": [{ "$eq": ["$action", "view"] }, 1, 0] } },
+            "downloads": { "$sum": { "$cond": [{ "$eq": ["$action", "download"] }, 1, 0] } },
+            "last_accessed_at": { "$max": "$accessed_at" }
+        } },
+        doc! { "$sort": { "last_accessed_at": -1 } },
+    ];
+    let mut cursor = collection
+        .aggregate(pipeline, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut readers = Vec::new();
+    let (mut views, mut downloads) = (0, 0);
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let row = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let key = row.get_document("_id").cloned().unwrap_or_default();
+        let reader_views = row.get_i32("views").unwrap_or(0);
+        let reader_downloads = row.get_i32("downloads").unwrap_or(0);
+        views += reader_views;
+        downloads += reader_downloads;
+        readers.push(serde_json::json!({
+            "user_id": key.get_str("user_id").unwrap_or_default(),
+            "role": key.get_str("role").unwrap_or_default(),
+            "views": reader_views,
+            "downloads": reader_downloads,
+            "last_accessed_at": row.get_str("last_accessed_at").unwrap_or_default()
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "resource_id": resource.id,
+        "title": resource.title,
+        "views": views,
+        "downloads": downloads,
+        "unique_readers": readers.len(),
+        "readers": readers
+    })))
+}
+
+// Licensed resources expiring within `days` (default 30), including lapsed ones
+async fn expiring_e_resource_licenses(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ExpiringLicenseQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let days = query.days.unwrap_or(30).clamp(0, 365);
+    let until = Utc::now().date_naive() + Duration::days(days);
+
+    let collection: Collection<EResource> = data.db.collection("e_resources");
+    let options = FindOptions::builder().sort(doc! { "license.expires_on": 1 }).build();
+    let mut cursor = collection
+        .find(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "license.expires_on": { "$lte": until.to_string() }
+            },
+            options,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let today = Utc::now().date_naive();
+    let mut licenses = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let resource = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let Some(license) = &resource.license else { continue };
+        licenses.push(serde_json::json!({
+            "resource_id": resource.id,
+            "title": resource.title,
+            "kind": resource.kind,
+            "provider": license.provider,
+            "expires_on": license.expires_on,
+            "days_left": (license.expires_on - today).num_days(),
+            "expired": license.expires_on < today
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(licenses))
+}
+
+// ===== BULK IMPORT =====
+
+// Largest catalog file accepted by the import endpoint
+const IMPORT_MAX_BYTES: usize = 10 * 1024 * 1024;
+const IMPORT_MAX_ROWS: usize = 5000;
+
+// One catalog entry read from a CSV row or MARC record
+#[derive(Debug, Default)]
+struct ImportRow {
+    isbn: String,
+    title: String,
+    author: String,
+    category: String,
+    copies: Option<String>,
+}
+
+// Columns: isbn, title, author, category, copies (header names, any order)
+fn parse_import_csv(bytes: &[u8]) -> Result<Vec<ImportRow>, String> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).flexible(true).from_reader(bytes);
+    let headers = reader.headers().map_err(|e| format!("Invalid CSV header: {}", e))?.clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let Some(isbn_col) = column("isbn") else {
+        return Err("The CSV needs an isbn column".to_string());
+    };
+    let (title_col, author_col, category_col) = (column("title"), column("author"), column("category"));
+    let copies_col = column("copies").or_else(|| column("total_copies"));
+
+    let mut rows = Vec::new();
+    for record in reader.records() {
+        let record = record.map_err(|e| format!("Invalid CSV: {}", e))?;
+        let get = |col: Option<usize>| col.and_then(|c| record.get(c)).unwrap_or_default().to_string();
+        rows.push(ImportRow {
+            isbn: get(Some(isbn_col)),
+            title: get(title_col),
+            author: get(author_col),
+            category: get(category_col),
+            copies: copies_col.and_then(|c| record.get(c)).filter(|c| !c.is_empty()).map(str::to_string),
+        });
+    }
+    Ok(rows)
+}
+
+// MARC 21 in ISO 2709 transmission format. Reads 020$a (ISBN), 245$a$b (title),
+// 100$a/110$a/700$a (author) and the first 650$a/653$a (category); one copy per record.
+fn parse_import_marc(bytes: &[u8]) -> Result<Vec<ImportRow>, String> {
+    const FIELD_END: u8 = 0x1e;
+    const RECORD_END: u8 = 0x1d;
+    const SUBFIELD: u8 = 0x1f;
+
+    let mut rows = Vec::new();
+    let mut rest = bytes;
+    while rest.iter().any(|b| !b.is_ascii_whitespace()) {
+        let start = rest.iter().position(|b| !b.is_ascii_whitespace()).unwrap_or(0);
+        rest = &rest[start..];
+        let number = rows.len() + 1;
+        let field = |range: std::ops::Range<usize>, bytes: &[u8]| -> Option<usize> {
+            std::str::from_utf8(bytes.get(range)?).ok()?.parse().ok()
+        };
+        let Some(length) = field(0..5, rest).filter(|l| *l >= 24 && *l <= rest.len()) else {
+            return Err(format!("MARC record {} has an invalid length", number));
+        };
+        let record = &rest[..length];
+        rest = &rest[length..];
+        let Some(base) = field(12..17, record).filter(|b| *b <= record.len()) else {
+            return Err(format!("MARC record {} has an invalid base address", number));
+        };
+
+        let mut fields: Vec<(String, Vec<(char, String)>)> = Vec::new();
+        let mut entry = 24;
+        while entry + 12 <= base && record[entry] != FIELD_END {
+            let tag = String::from_utf8_lossy(&record[entry..entry + 3]).to_string();
+            let (Some(len), Some(pos)) = (field(entry + 3..entry + 7, record), field(entry + 7..entry + 12, record)) else {
+                return Err(format!("MARC record {} has an invalid directory", number));
+            };
+            entry += 12;
+            let Some(data) = record.get(base + pos..base + pos + len) else {
+                return Err(format!("MARC record {} has a field outside the record", number));
+            };
+            let data = data.strip_suffix(&[FIELD_END]).unwrap_or(data);
+            let subfields = data
+                .split(|b| *b == SUBFIELD)
+                .skip(1)
+                .filter_map(|sub| {
+                    let text = String::from_utf8_lossy(sub);
+                    let mut chars = text.chars();
+                    chars.next().map(|code| (code, chars.as_str().trim().to_string()))
+                })
+                .collect();
+            fields.push((tag, subfields));
+        }
+        if record.last() != Some(&RECORD_END) {
+            return Err(format!("MARC record {} is not terminated", number));
+        }
+
+        let first = |tags: &[&str], code: char| -> String {
+            fields
+                .iter()
+                .filter(|(tag, _)| tags.contains(&tag.as_str()))
+                .flat_map(|(_, subs)| subs.iter())
+                .find(|(c, v)| *c == code && !v.is_empty())
+                .map(|(_, v)| v.clone())
+                .unwrap_or_default()
+        };
+        // ISBD punctuation closes each subfield: "Effective Java :" / "Bloch, Joshua,"
+        let tidy = |s: String| s.trim_end_matches([' ', '/', ':', ';', ',', '.']).to_string();
+
+        let mut title = tidy(first(&["245"], 'a'));
+        let subtitle = tidy(first(&["245"], 'b'));
+        if !subtitle.is_empty() {
+            title = format!("{}: {}", title, subtitle);
+        }
+        rows.push(ImportRow {
+            // "9780134685991 (paperback)"
+            isbn: first(&["020"], 'a').split_whitespace().next().unwrap_or_default().to_string(),
+            title,
+            author: tidy(first(&["100", "110", "700"], 'a')),
+            category: tidy(first(&["650", "653"], 'a')),
+            copies: None,
+        });
+    }
+    Ok(rows)
+}
+
+// Import a catalog file: multipart/form-data with a `file` part (.csv or .mrc/.marc).
+// Books whose ISBN is already catalogued get the extra copies instead of a duplicate.
+async fn import_books(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<ImportQuery>,
+    mut payload: actix_multipart::Multipart,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    use futures::stream::StreamExt;
+
+    let mut upload: Option<(String, Vec<u8>)> = None;
+    while let Some(field) = payload.next().await {
+        let mut field = field
+            .map_err(|e| actix_web::error::ErrorBadRequest(format!("Invalid multipart body: {}", e)))?;
+        if field.name() != Some("file") {
+            continue;
+        }
+        let filename = field
+            .content_disposition()
+            .and_then(|cd| cd.get_filename())
+            .unwrap_or_default()
+            .to_lowercase();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = field.next().await {
+            let chunk = chunk
+                .map_err(|e| actix_web::error::ErrorBadRequest(format!("Invalid multipart body: {}", e)))?;
+            bytes.extend_from_slice(&chunk);
+            if bytes.len() > IMPORT_MAX_BYTES {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "File exceeds the {} MB limit",
+                    IMPORT_MAX_BYTES / (1024 * 1024)
+                )));
+            }
+        }
+        upload = Some((filename, bytes));
+    }
+
+    let Some((filename, bytes)) = upload else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "'file' is required"
+        })));
+    };
+
+    let format = match query.format.as_deref() {
+        Some(format) => format.to_string(),
+        None if filename.ends_with(".mrc") || filename.ends_with(".marc") => "marc".to_string(),
+        None => "csv".to_string(),
+    };
+    let parsed = match format.as_str() {
+        "csv" => parse_import_csv(&bytes),
+        "marc" => parse_import_marc(&bytes),
+        _ => Err("Invalid format. Use: csv, marc".to_string()),
+    };
+    let rows = match parsed {
+        Ok(rows) => rows,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
+    if rows.len() > IMPORT_MAX_ROWS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("At most {} rows can be imported at once", IMPORT_MAX_ROWS)
+        })));
+    }
+
+    let dry_run = query.dry_run.unwrap_or(false);
+    let collection: Collection<Book> = data.db.collection("books");
+
+    // Existing catalog by normalised ISBN; rows added below join it so repeats in the file merge too
+    let mut catalog: std::collections::HashMap<String, (Option<ObjectId>, String)> = std::collections::HashMap::new();
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let key = normalize_isbn(&book.isbn).unwrap_or_else(|| book.isbn.trim().to_string());
+        catalog.insert(key, (book.id, book.status));
+    }
+
+    let mut categories: std::collections::HashMap<String, Result<String, String>> = std::collections::HashMap::new();
+    let mut report = Vec::new();
+    let (mut created, mut copies_added, mut failed) = (0, 0, 0);
+
+    for (index, row) in rows.into_iter().enumerate() {
+        // Header is line 1 in a CSV; MARC counts records
+        let line = if format == "csv" { index + 2 } else { index + 1 };
+
+        let outcome: Result<(&str, Option<ObjectId>, i32), String> = async {
+            let isbn = normalize_isbn(&row.isbn)
+                .ok_or_else(|| format!("Invalid ISBN '{}'", row.isbn))?;
+            let copies = match &row.copies {
+                Some(copies) => copies
+                    .parse::<i32>()
+                    .ok()
+                    .filter(|c| (1..=1000).contains(c))
+                    .ok_or_else(|| format!("Invalid copies '{}'", copies))?,
+                None => 1,
+            };
+
+            if let Some((id, status)) = catalog.get(&isbn) {
+                if status == "withdrawn" {
+                    return Err("A withdrawn book has this ISBN".to_string());
+                }
+                if !dry_run {
+                    collection
+                        .update_one(
+                            doc! { "_id": id },
+                            doc! {
+                                "$inc": { "total_copies": copies, "available_copies": copies },
+                                "$set": { "updated_at": Utc::now().to_rfc3339() }
+                            },
+                            None,
+                        )
+                        .await
+                        .map_err(|e| e.to_string())?;
+                }
+                return Ok(("copies_added", *id, copies));
+            }
+
+            for (field, value) in [("title", &row.title), ("author", &row.author), ("category", &row.category)] {
+                if value.trim().is_empty() {
+                    return Err(format!("{} is required", field));
+                }
+            }
+            let category = match categories.get(&row.category) {
+                Some(resolved) => resolved.clone(),
+                None => {
+                    let resolved = resolve_category(&data.db, &claims.campus_id, &row.category)
+                        .await
+                        .map_err(|e| e.to_string())?;
+                    categories.insert(row.category.clone(), resolved.clone());
+                    resolved
+                }
+            }?;
+
+            let book = Book {
+                id: None,
+                isbn: row.isbn.trim().to_string(),
+                title: row.title.trim().to_string(),
+                author: row.author.trim().to_string(),
+                category,
+                total_copies: copies,
+                available_copies: copies,
+                campus_id: claims.campus_id.clone(),
+                created_at: Utc::now(),
+                cover_url: None,
+                status: "active".to_string(),
+                updated_at: None,
+                withdrawn_at: None,
+                withdrawn_by: None,
+                withdrawal_reason: None,
+            };
+            let id = if dry_run {
+                None
+            } else {
+                collection
+                    .insert_one(&book, None)
+                    .await
+                    .map_err(|e| e.to_string())?
+                    .inserted_id
+                    .as_object_id()
+            };
+            catalog.insert(isbn, (id, book.status));
+            Ok(("created", id, copies))
+        }
+        .await;
+
+        report.push(match outcome {
+            Ok((status, book_id, copies)) => {
+                if status == "created" { created += 1 } else { copies_added += 1 }
+                serde_json::json!({
+                    "line": line,
+                    "isbn": row.isbn,
+                    "title": row.title,
+                    "status": status,
+                    "book_id": book_id,
+                    "copies": copies
+                })
+            }
+            Err(error) => {
+                failed += 1;
+                serde_json::json!({
+                    "line": line,
+                    "isbn": row.isbn,
+                    "title": row.title,
+                    "status": "error",
+                    "error": error
+                })
+            }
+        });
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "format": format,
+        "dry_run": dry_run,
+        "created": created,
+        "copies_added": copies_added,
+        "failed": failed,
+        "rows": report
+    })))
+}
+
+// ===== REPORTS =====
+
+// Circulation figures for purchasing and weeding decisions
+async fn circulation_report(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<CirculationQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let today = Utc::now().date_naive();
+    let to = query.to.unwrap_or(today);
+    let from = query.from.unwrap_or(to - Duration::days(365));
+    if from > to {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "from must not be after to"
+        })));
+    }
+    let top = query.top.unwrap_or(10).clamp(1, 100);
+    let idle_years = query.idle_years.unwrap_or(3).clamp(1, 50);
+
+    use futures::stream::StreamExt;
+
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let mut cursor = book_collection
+        .find(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut books = std::collections::HashMap::new();
+    while let Some(result) = cursor.next().await {
+        let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(id) = book.id {
+            books.insert(id.to_hex(), book);
+        }
+    }
+
+    // issue_date is stored as an RFC 3339 string, so the range compares strings
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let mut cursor = issue_collection
+        .find(doc! {
+            "campus_id": &claims.campus_id,
+            "issue_date": {
+                "$gte": from.to_string(),
+                "$lt": (to + Duration::days(1)).to_string()
+            }
+        }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut issues_per_book: std::collections::HashMap<String, (String, i64)> = std::collections::HashMap::new();
+    let mut per_category_month: std::collections::BTreeMap<(String, String), i64> = std::collections::BTreeMap::new();
+    let mut total_issues = 0;
+    let (mut loan_days, mut returned) = (0.0, 0);
+    let (mut fines_assessed, mut fines_paid, mut fines_waived) = (0.0, 0.0, 0.0);
+
+    while let Some(result) = cursor.next().await {
+        let issue = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        total_issues += 1;
+
+        let entry = issues_per_book
+            .entry(issue.book_id.clone())
+            .or_insert_with(|| (issue.book_title.clone(), 0));
+        entry.1 += 1;
+
+        let category = books
+            .get(&issue.book_id)
+            .map(|b| b.category.clone())
+            .unwrap_or_else(|| "Unknown".to_string());
+        let month = issue.issue_date.format("%Y-%m").to_string();
+        *per_category_month.entry((month, category)).or_insert(0) += 1;
+
+        if let Some(return_date) = issue.return_date {
+            loan_days += (return_date - issue.issue_date).num_minutes() as f64 / (24.0 * 60.0);
+            returned += 1;
+        }
+        fines_assessed += issue.fine_amount;
+        fines_waived += issue.fine_waived;
+        if issue.fine_status.as_deref() == Some("paid") {
+            fines_paid += issue.fine_amount - issue.fine_waived;
+        }
+    }
+
+    let mut most_issued: Vec<(String, String, i64)> = issues_per_book
+        .into_iter()
+        .map(|(book_id, (title, issues))| (book_id, title, issues))
+        .collect();
+    most_issued.sort_by(|a, b| b.2.cmp(&a.2).then_with(|| a.1.cmp(&b.1)));
+    let most_issued: Vec<serde_json::Value> = most_issued
+        .into_iter()
+        .take(top)
+        .map(|(book_id, title, issues)| {
+            let author = books.get(&book_id).map(|b| b.author.clone());
+            serde_json::json!({ "book_id": book_id, "title": title, "author": author, "issues": issues })
+        })
+        .collect();
+
+    let issues_per_category: Vec<serde_json::Value> = per_category_month
+        .into_iter()
+        .map(|((month, category), issues)| serde_json::json!({
+            "month": month,
+            "category": category,
+            "issues": issues
+        }))
+        .collect();
+
+    // Titles on the shelf for idle_years or more without a single issue in that time
+    let idle_since = Utc::now() - Duration::days(365 * idle_years);
+    let recently_issued: std::collections::HashSet<String> = issue_collection
+        .distinct(
+            "book_id",
+            doc! {
+                "campus_id": &claims.campus_id,
+                "issue_date": { "$gte": mongodb::bson::to_bson(&idle_since).map_err(actix_web::error::ErrorInternalServerError)? }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .filter_map(|id| id.as_str().map(str::to_string))
+        .collect();
+
+    let mut zero_circulation: Vec<&Book> = books
+        .iter()
+        .filter(|(id, book)| {
+            book.status != "withdrawn" && book.created_at <= idle_since && !recently_issued.contains(*id)
+        })
+        .map(|(_, book)| book)
+        .collect();
+    zero_circulation.sort_by_key(|b| b.created_at);
+    let zero_circulation: Vec<serde_json::Value> = zero_circulation
+        .into_iter()
+        .map(|book| serde_json::json!({
+            "book_id": book.id,
+            "title": book.title,
+            "author": book.author,
+            "category": book.category,
+            "total_copies": book.total_copies,
+            "added_on": book.created_at.date_naive()
+        }))
+        .collect();
+
+    let average_loan_days = if returned > 0 {
+        Some((loan_days / returned as f64 * 10.0).round() / 10.0)
+    } else {
+        None
+    };
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "from": from,
+        "to": to,
+        "total_issues": total_issues,
+        "most_issued": most_issued,
+        "issues_per_category": issues_per_category,
+        "average_loan_days": average_loan_days,
+        "fine_revenue": {
+            "assessed": round2(fines_assessed),
+            "paid": round2(fines_paid),
+            "waived": round2(fines_waived),
+            "outstanding": round2(fines_assessed - fines_paid - fines_waived)
+        },
+        "idle_years": idle_years,
+        "zero_circulation": zero_circulation
+    })))
+}
+
+// ===== ACQUISITIONS =====
+
+// Indian financial year (April to March) a date falls in, e.g. "2024-25"
+fn financial_year(date: NaiveDate) -> String {
+    use chrono::Datelike;
+    let start = if date.month() >= 4 { date.year() } else { date.year() - 1 };
+    format!("{}-{:02}", start, (start + 1) % 100)
+}
+
+// Hands out the next number in a named sequence. The counters collection
+// holds one document per sequence and $inc with upsert keeps concurrent
+// callers from ever receiving the same number.
+async fn next_sequence(db: &mongodb::Database, name: &str) -> mongodb::error::Result<i64> {
+    use mongodb::options::{FindOneAndUpdateOptions, ReturnDocument};
+
+    let counters: Collection<Document> = db.collection("counters");
+    let options = FindOneAndUpdateOptions::builder()
+        .upsert(true)
+        .return_document(ReturnDocument::After)
+        .build();
+    let counter = counters
+        .find_one_and_update(doc! { "_id": name }, doc! { "$inc": { "seq": 1_i64 } }, options)
+        .await?;
+    Ok(counter.and_then(|c| c.get_i64("seq").ok()).unwrap_or(1))
+}
+
+fn round2(value: f64) -> f64 {
+    (value * 100.0).round() / 100.0
+}
+
+fn fund_available(fund: &AcquisitionFund) -> f64 {
+    round2(fund.allocated - fund.committed - fund.spent)
+}
+
+async fn create_fund(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<FundRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.code.trim().is_empty() || body.name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "code and name are required"
+        })));
+    }
+    if body.allocated < 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "allocated must not be negative"
+        })));
+    }
+
+    let fund = AcquisitionFund {
+        id: None,
+        code: body.code.trim().to_uppercase(),
+        name: body.name.trim().to_string(),
+        financial_year: body
+            .financial_year
+            .unwrap_or_else(|| financial_year(Utc::now().date_naive())),
+        allocated: round2(body.allocated),
+        committed: 0.0,
+        spent: 0.0,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+    match collection.insert_one(&fund, None).await {
+        Ok(result) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "message": "Fund created successfully",
+            "id": result.inserted_id
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Fund {} already exists for {}", fund.code, fund.financial_year)
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+// Raise or cut a fund's allocation; it cannot drop below what is committed and spent
+async fn update_fund(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FundUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let fund_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let mut set = doc! {};
+    if let Some(name) = body.name.as_deref().map(str::trim).filter(|n| !n.is_empty()) {
+        set.insert("name", name);
+    }
+    let mut filter = doc! { "_id": fund_id, "campus_id": &claims.campus_id };
+    if let Some(allocated) = body.allocated {
+        let allocated = round2(allocated);
+        filter.insert("$expr", doc! { "$lte": [{ "$add": ["$committed", "$spent"] }, allocated] });
+        set.insert("allocated", allocated);
+    }
+    if set.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+
+    let collection: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+    let updated = collection
+        .find_one_and_update(
+            filter,
+            doc! { "$set": set },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match updated {
+        Some(fund) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "fund": &fund,
+            "available": fund_available(&fund)
+        }))),
+        None => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Fund not found, or the allocation is below what is already committed and spent"
+        }))),
+    }
+}
+
+async fn get_funds(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<FundQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(year) = query.financial_year.as_deref().filter(|y| !y.is_empty()) {
+        filter.insert("financial_year", year);
+    }
+
+    let collection: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "financial_year": -1, "code": 1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut funds = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let fund = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let available = fund_available(&fund);
+        funds.push(serde_json::json!({ "fund": fund, "available": available }));
+    }
+
+    Ok(HttpResponse::Ok().json(funds))
+}
+
+async fn create_vendor(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<VendorRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name is required"
+        })));
+    }
+
+    let vendor = Vendor {
+        id: None,
+        name: body.name.trim().to_string(),
+        contact_name: body.contact_name,
+        email: body.email,
+        phone: body.phone,
+        address: body.address,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<Vendor> = data.db.collection("vendors");
+    match collection.insert_one(&vendor, None).await {
+        Ok(result) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "message": "Vendor added successfully",
+            "id": result.inserted_id
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Vendor '{}' already exists", vendor.name)
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
+
+async fn get_vendors(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<Vendor> = data.db.collection("vendors");
+    let mut cursor = collection
+        .find(doc! { "campus_id": &claims.campus_id }, FindOptions::builder().sort(doc! { "name": 1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut vendors = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(vendor) => vendors.push(vendor),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(vendors))
+}
+
+// Anyone on campus can suggest a title for purchase
+async fn create_purchase_request(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PurchaseRequestCreate>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let body = body.into_inner();
+    if body.title.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "title must not be blank"
+        })));
+    }
+    let quantity = body.quantity.unwrap_or(1);
+    if !(1..=500).contains(&quantity) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "quantity must be between 1 and 500"
+        })));
+    }
+    let isbn = match body.isbn.as_deref().map(str::trim).filter(|i| !i.is_empty()) {
+        Some(raw) => match normalize_isbn(raw) {
+            Some(isbn) => Some(isbn),
+            None => {
+                return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                    "error": format!("Invalid ISBN '{}'", raw)
+                })))
+            }
+        },
+        None => None,
+    };
+    if body.estimated_unit_price.is_some_and(|p| p < 0.0) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "estimated_unit_price must not be negative"
+        })));
+    }
+
+    let request = PurchaseRequest {
+        id: None,
+        title: body.title.trim().to_string(),
+        author: body.author.filter(|a| !a.trim().is_empty()),
+        isbn,
+        category: body.category.filter(|c| !c.trim().is_empty()),
+        quantity,
+        estimated_unit_price: body.estimated_unit_price,
+        justification: body.justification,
+        status: "pending".to_string(),
+        fund_id: None,
+        purchase_order_id: None,
+        requested_by: claims.sub,
+        requester_role: claims.role,
+        reviewed_by: None,
+        reviewed_at: None,
+        review_note: None,
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<PurchaseRequest> = data.db.collection("purchase_requests");
+    let result = collection
+        .insert_one(&request, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Purchase request submitted",
+        "id": result.inserted_id
+    })))
+}
+
+// Librarians see every request; others see the ones they raised
+async fn get_purchase_requests(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PurchaseRequestQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if claims.role != "librarian" && claims.role != "admin" {
+        filter.insert("requested_by", &claims.sub);
+    }
+    if let Some(status) = query.status.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("status", status);
+    }
+
+    let collection: Collection<PurchaseRequest> = data.db.collection("purchase_requests");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "created_at": -1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut requests = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(request) => requests.push(request),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(requests))
+}
+
+// Approve (against a fund) or reject a pending purchase request
+async fn review_purchase_request(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<(String, String)>,
+    body: web::Json<PurchaseReview>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let (request_id, action) = path.into_inner();
+    let status = match action.as_str() {
+        "approve" => "approved",
+        "reject" => "rejected",
+        _ => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let request_id = ObjectId::parse_str(&request_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+
+    let body = body.into_inner();
+    let mut set = doc! {
+        "status": status,
+        "reviewed_by": &claims.sub,
+        "reviewed_at": Utc::now().to_rfc3339()
+    };
+    if let Some(note) = body.note.filter(|n| !n.trim().is_empty()) {
+        set.insert("review_note", note);
+    }
+
+    if status == "approved" {
+        let Some(fund_id) = body.fund_id.as_deref() else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "fund_id is required to approve a purchase"
+            })));
+        };
+        let fund_id = ObjectId::parse_str(fund_id).map_err(actix_web::error::ErrorBadRequest)?;
+        let funds: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+        let fund = funds
+            .find_one(doc! { "_id": fund_id, "campus_id": &claims.campus_id }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        if fund.is_none() {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": "Fund not found"
+            })));
+        }
+        set.insert("fund_id", fund_id);
+    }
+
+    let collection: Collection<PurchaseRequest> = data.db.collection("purchase_requests");
+    let updated = collection
+        .find_one_and_update(
+            doc! { "_id": request_id, "status": "pending", "campus_id": &claims.campus_id },
+            doc! { "$set": set },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match updated {
+        Some(request) => Ok(HttpResponse::Ok().json(request)),
+        None => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Purchase request not found or already reviewed"
+        }))),
+    }
+}
+
+// Order approved requests from a vendor, committing the total against their fund
+async fn create_purchase_order(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PurchaseOrderCreate>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.lines.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "lines must not be empty"
+        })));
+    }
+
+    let vendor_id = ObjectId::parse_str(&body.vendor_id).map_err(actix_web::error::ErrorBadRequest)?;
+    let vendors: Collection<Vendor> = data.db.collection("vendors");
+    let Some(vendor) = vendors
+        .find_one(doc! { "_id": vendor_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Vendor not found"
+        })));
+    };
+
+    let requests: Collection<PurchaseRequest> = data.db.collection("purchase_requests");
+    let mut lines = Vec::new();
+    let mut fund_id = None;
+    for line in &body.lines {
+        let request_id = ObjectId::parse_str(&line.request_id).map_err(actix_web::error::ErrorBadRequest)?;
+        let Some(request) = requests
+            .find_one(doc! { "_id": request_id, "campus_id": &claims.campus_id }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?
+        else {
+            return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                "error": format!("Purchase request {} not found", line.request_id)
+            })));
+        };
+        if request.status != "approved" {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("Purchase request for '{}' is {}, not approved", request.title, request.status)
+            })));
+        }
+        if fund_id.is_some() && fund_id != request.fund_id {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "All lines of a purchase order must be charged to the same fund"
+            })));
+        }
+        fund_id = request.fund_id;
+        if line.unit_price < 0.0 {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "unit_price must not be negative"
+            })));
+        }
+        let quantity = line.quantity.unwrap_or(request.quantity);
+        if !(1..=500).contains(&quantity) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "quantity must be between 1 and 500"
+            })));
+        }
+        lines.push(PurchaseOrderLine {
+            request_id: Some(request_id),
+            title: request.title,
+            author: request.author,
+            isbn: request.isbn,
+            category: request.category,
+            quantity,
+            unit_price: round2(line.unit_price),
+            received_quantity: 0,
+            book_id: None,
+        });
+    }
+    let Some(fund_id) = fund_id else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "The approved requests have no fund"
+        })));
+    };
+
+    let total = round2(lines.iter().map(|l| l.unit_price * l.quantity as f64).sum());
+
+    // Commit the money first; the $expr keeps the fund from going over its allocation
+    let funds: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+    let committed = funds
+        .update_one(
+            doc! {
+                "_id": fund_id,
+                "$expr": { "$gte": [
+                    { "$subtract": ["$allocated", { "$add": ["$committed", "$spent"] }] },
+                    total
+                ] }
+            },
+            doc! { "$inc": { "committed": total } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if committed.matched_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("The fund does not have {:.2} available", total)
+        })));
+    }
+
+    let now = Utc::now();
+    let seq = next_sequence(
+        &data.db,
+        &format!("purchase_order:{}:{}", claims.campus_id, financial_year(now.date_naive())),
+    )
+    .await
+    .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let order = PurchaseOrder {
+        id: None,
+        po_number: format!("PO-{}-{}-{:05}", claims.campus_id, financial_year(now.date_naive()), seq),
+        vendor_id,
+        vendor_name: vendor.name,
+        fund_id,
+        lines,
+        total,
+        status: "open".to_string(),
+        notes: body.notes,
+        created_by: claims.sub,
+        campus_id: claims.campus_id,
+        created_at: now,
+        closed_at: None,
+    };
+
+    let orders: Collection<PurchaseOrder> = data.db.collection("purchase_orders");
+    let order_id = orders
+        .insert_one(&order, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .inserted_id;
+
+    let request_ids: Vec<ObjectId> = order.lines.iter().filter_map(|l| l.request_id).collect();
+    requests
+        .update_many(
+            doc! { "_id": { "$in": request_ids } },
+            doc! { "$set": { "status": "ordered", "purchase_order_id": &order_id } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Purchase order created",
+        "id": order_id,
+        "po_number": order.po_number,
+        "total": total
+    })))
+}
+
+async fn get_purchase_orders(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PurchaseOrderQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(status) = query.status.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("status", status);
+    }
+    if let Some(fund_id) = query.fund_id.as_deref().filter(|f| !f.is_empty()) {
+        filter.insert("fund_id", ObjectId::parse_str(fund_id).map_err(actix_web::error::ErrorBadRequest)?);
+    }
+
+    let collection: Collection<PurchaseOrder> = data.db.collection("purchase_orders");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "created_at": -1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut orders = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(order) => orders.push(order),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(orders))
+}
+
+// Adds received copies to the catalog: more copies of a catalogued ISBN, else a new book
+async fn catalogue_received_copies(
+    db: &mongodb::Database,
+    campus_id: &str,
+    line: &PurchaseOrderLine,
+    quantity: i32,
+) -> Result<Result<ObjectId, String>, Error> {
+    let books: Collection<Book> = db.collection("books");
+
+    if let Some(book_id) = line.book_id {
+        books
+            .update_one(
+                doc! { "_id": book_id },
+                doc! { "$inc": { "total_copies": quantity, "available_copies": quantity } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        return Ok(Ok(book_id));
+    }
+
+    if let Some(isbn) = line.isbn.as_deref().and_then(normalize_isbn) {
+        let mut cursor = books
+            .find(doc! { "campus_id": campus_id, "status": { "$ne": "withdrawn" } }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        use futures::stream::StreamExt;
+        while let Some(result) = cursor.next().await {
+            let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            if normalize_isbn(&book.isbn).as_deref() == Some(isbn.as_str()) {
+                let Some(book_id) = book.id else { continue };
+                books
+                    .update_one(
+                        doc! { "_id": book_id },
+                        doc! { "$inc": { "total_copies": quantity, "available_copies": quantity } },
+                        None,
+                    )
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+                return Ok(Ok(book_id));
+            }
+        }
+    }
+
+    let (Some(isbn), Some(author), Some(category)) = (&line.isbn, &line.author, &line.category) else {
+        return Ok(Err(format!(
+            "'{}' is not in the catalog; the purchase request needs isbn, author and category to add it",
+            line.title
+        )));
+    };
+    let category = match resolve_category(db, campus_id, category).await? {
+        Ok(category) => category,
+        Err(e) => return Ok(Err(e)),
+    };
+
+    let book = Book {
+        id: None,
+        isbn: normalize_isbn(isbn).unwrap_or_else(|| isbn.trim().to_string()),
+        title: line.title.clone(),
+        author: author.clone(),
+        category,
+        total_copies: quantity,
+        available_copies: quantity,
+        campus_id: campus_id.to_string(),
+        created_at: Utc::now(),
+        cover_url: None,
+        status: "active".to_string(),
+        updated_at: None,
+        withdrawn_at: None,
+        withdrawn_by: None,
+        withdrawal_reason: None,
+    };
+    let inserted = books
+        .insert_one(&book, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(inserted
+        .inserted_id
+        .as_object_id()
+        .ok_or_else(|| "Inserted book has no ObjectId".to_string()))
+}
+
+// Record delivered copies; they are catalogued and move from committed to spent
+async fn receive_purchase_order(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ReceiveRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let order_id = ObjectId::parse_str(path.into_inner()).map_err(actix_web::error::ErrorBadRequest)?;
+    let orders: Collection<PurchaseOrder> = data.db.collection("purchase_orders");
+    let Some(mut order) = orders
+        .find_one(doc! { "_id": order_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Purchase order not found"
+        })));
+    };
+    if order.status != "open" && order.status != "partially_received" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("Purchase order is {}", order.status)
+        })));
+    }
+
+    // Check every line before touching the catalog
+    let body = body.into_inner();
+    for item in &body.items {
+        let Some(line) = order.lines.get(item.line) else {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!("Line {} does not exist", item.line)
+            })));
+        };
+        if item.quantity < 1 || item.quantity > line.quantity - line.received_quantity {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": format!(
+                    "Line {} ('{}') has {} copies outstanding",
+                    item.line, line.title, line.quantity - line.received_quantity
+                )
+            })));
+        }
+    }
+
+    let mut received_value = 0.0;
+    let mut results = Vec::new();
+    for item in &body.items {
+        let line = &order.lines[item.line];
+        match catalogue_received_copies(&data.db, &claims.campus_id, line, item.quantity).await? {
+            Ok(book_id) => {
+                let line = &mut order.lines[item.line];
+                line.received_quantity += item.quantity;
+                line.book_id = Some(book_id);
+                received_value += line.unit_price * item.quantity as f64;
+                results.push(serde_json::json!({
+                    "line": item.line,
+                    "title": line.title,
+                    "received": item.quantity,
+                    "book_id": book_id
+                }));
+            }
+            Err(error) => results.push(serde_json::json!({
+                "line": item.line,
+                "title": line.title,
+                "error": error
+            })),
+        }
+    }
+    let received_value = round2(received_value);
+
+    let complete = order.lines.iter().all(|l| l.received_quantity >= l.quantity);
+    let any_received = order.lines.iter().any(|l| l.received_quantity > 0);
+    let status = if complete { "received" } else if any_received { "partially_received" } else { "open" };
+    let mut set = doc! {
+        "lines": mongodb::bson::to_bson(&order.lines).map_err(actix_web::error::ErrorInternalServerError)?,
+        "status": status
+    };
+    if complete {
+        set.insert("closed_at", Utc::now().to_rfc3339());
+    }
+    orders
+        .update_one(doc! { "_id": order_id }, doc! { "$set": set }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    if received_value > 0.0 {
+        let funds: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+        funds
+            .update_one(
+                doc! { "_id": order.fund_id },
+                doc! { "$inc": { "committed": -received_value, "spent": received_value } },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+    }
+
+    let fulfilled: Vec<ObjectId> = order
+        .lines
+        .iter()
+        .filter(|l| l.received_quantity >= l.quantity)
+        .filter_map(|l| l.request_id)
+        .collect();
+    let requests: Collection<PurchaseRequest> = data.db.collection("purchase_requests");
+    requests
+        .update_many(
+            doc! { "_id": { "$in": fulfilled }, "status": "ordered" },
+            doc! { "$set": { "status": "received" } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "po_number": order.po_number,
+        "status": status,
+        "received_value": received_value,
+        "lines": results
+    })))
+}
+
+// Cancel what is still outstanding on an order and release its commitment
+async fn cancel_purchase_order(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let order_id = ObjectId::parse_str(path.into_inner()).map_err(actix_web::error::ErrorBadRequest)?;
+    let orders: Collection<PurchaseOrder> = data.db.collection("purchase_orders");
+    let Some(order) = orders
+        .find_one_and_update(
+            doc! {
+                "_id": order_id,
+                "campus_id": &claims.campus_id,
+                "status": { "$in": ["open", "partially_received"] }
+            },
+            doc! { "$set": { "status": "cancelled", "closed_at": Utc::now().to_rfc3339() } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Purchase order not found or already closed"
+        })));
+    };
+
+    let released = round2(
+        order
+            .lines
+            .iter()
+            .map(|l| l.unit_price * (l.quantity - l.received_quantity) as f64)
+            .sum(),
+    );
+    let funds: Collection<AcquisitionFund> = data.db.collection("acquisition_funds");
+    funds
+        .update_one(doc! { "_id": order.fund_id }, doc! { "$inc": { "committed": -released } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Requests not yet delivered can go on another order
+    let reopened: Vec<ObjectId> = order
+        .lines
+        .iter()
+        .filter(|l| l.received_quantity < l.quantity)
+        .filter_map(|l| l.request_id)
+        .collect();
+    let requests: Collection<PurchaseRequest> = data.db.collection("purchase_requests");
+    requests
+        .update_many(
+            doc! { "_id": { "$in": reopened }, "status": "ordered" },
+            doc! {
+                "$set": { "status": "approved" },
+                "$unset": { "purchase_order_id": "" }
+            },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": format!("Purchase order {} cancelled", order.po_number),
+        "released": released
+    })))
+}
+
+// ===== PERIODICALS =====
+
+const PERIODICAL_FREQUENCIES: &[&str] =
+    &["weekly", "fortnightly", "monthly", "bimonthly", "quarterly", "semiannual", "annual"];
+
+// Date of the n-th issue after the first. Counting from the start keeps monthly
+// issues on the same day of the month instead of drifting after a short month.
+fn expected_issue(start: NaiveDate, frequency: &str, n: u32) -> Option<NaiveDate> {
+    let months = match frequency {
+        "weekly" => return start.checked_add_signed(Duration::days(7 * n as i64)),
+        "fortnightly" => return start.checked_add_signed(Duration::days(14 * n as i64)),
+        "monthly" => 1,
+        "bimonthly" => 2,
+        "quarterly" => 3,
+        "semiannual" => 6,
+        "annual" => 12,
+        _ => return None,
+    };
+    start.checked_add_months(chrono::Months::new(months * n))
+}
+
+// Every issue expected from the start of the subscription up to `until`
+fn expected_issues(periodical: &Periodical, until: NaiveDate) -> Vec<NaiveDate> {
+    let until = until.min(periodical.end_date);
+    let mut dates = Vec::new();
+    for n in 0..10_000 {
+        match expected_issue(periodical.start_date, &periodical.frequency, n) {
+            Some(date) if date <= until => dates.push(date),
+            _ => break,
+        }
+    }
+    dates
+}
+
+async fn find_periodical(
+    db: &mongodb::Database,
+    id: &str,
+    campus_id: &str,
+) -> Result<Option<Periodical>, Error> {
+    let id = ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<Periodical> = db.collection("periodicals");
+    collection
+        .find_one(doc! { "_id": id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn received_issue_dates(
+    db: &mongodb::Database,
+    periodical_id: ObjectId,
+) -> Result<std::collections::HashSet<NaiveDate>, Error> {
+    let collection: Collection<PeriodicalIssue> = db.collection("periodical_issues");
+    let mut cursor = collection
+        .find(doc! { "periodical_id": periodical_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut dates = std::collections::HashSet::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let issue = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        dates.insert(issue.expected_date);
+    }
+    Ok(dates)
+}
+
+async fn create_periodical(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<PeriodicalRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.title.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "title must not be blank"
+        })));
+    }
+    if !PERIODICAL_FREQUENCIES.contains(&body.frequency.as_str()) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("frequency must be one of: {}", PERIODICAL_FREQUENCIES.join(", "))
+        })));
+    }
+    if body.end_date <= body.start_date {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "end_date must be after start_date"
+        })));
+    }
+    if body.cost < 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "cost must not be negative"
+        })));
+    }
+    let claim_after_days = body.claim_after_days.unwrap_or(30);
+    if !(1..=365).contains(&claim_after_days) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "claim_after_days must be between 1 and 365"
+        })));
+    }
+
+    let vendor_id = match body.vendor_id.as_deref().filter(|v| !v.is_empty()) {
+        Some(vendor_id) => {
+            let vendor_id = ObjectId::parse_str(vendor_id).map_err(actix_web::error::ErrorBadRequest)?;
+            let vendors: Collection<Vendor> = data.db.collection("vendors");
+            let vendor = vendors
+                .find_one(doc! { "_id": vendor_id, "campus_id": &claims.campus_id }, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            if vendor.is_none() {
+                return Ok(HttpResponse::NotFound().json(serde_json::json!({
+                    "error": "Vendor not found"
+                })));
+            }
+            Some(vendor_id)
+        }
+        None => None,
+    };
+
+    let periodical = Periodical {
+        id: None,
+        title: body.title.trim().to_string(),
+        issn: body.issn.map(|i| i.trim().to_uppercase()).filter(|i| !i.is_empty()),
+        publisher: body.publisher.filter(|p| !p.trim().is_empty()),
+        vendor_id,
+        frequency: body.frequency,
+        start_date: body.start_date,
+        end_date: body.end_date,
+        cost: body.cost,
+        claim_after_days,
+        status: "active".to_string(),
+        renewals: Vec::new(),
+        campus_id: claims.campus_id,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<Periodical> = data.db.collection("periodicals");
+    let result = collection
+        .insert_one(&periodical, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Subscription added successfully",
+        "id": result.inserted_id
+    })))
+}
+
+async fn get_periodicals(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PeriodicalQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let mut filter = doc! { "campus_id": &claims.campus_id };
+    if let Some(status) = query.status.as_deref().filter(|s| !s.is_empty()) {
+        filter.insert("status", status);
+    }
+    if let Some(q) = query.q.as_deref().map(str::trim).filter(|q| !q.is_empty()) {
+        filter.insert("title", doc! { "$regex": regex_escape(q), "$options": "i" });
+    }
+
+    let collection: Collection<Periodical> = data.db.collection("periodicals");
+    let mut cursor = collection
+        .find(filter, FindOptions::builder().sort(doc! { "title": 1 }).build())
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut periodicals = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(periodical) => periodicals.push(periodical),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(periodicals))
+}
+
+// A subscription with the issues received so far and the ones overdue for a claim
+async fn get_periodical(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let Some(periodical) = find_periodical(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        })));
+    };
+    let periodical_id = periodical.id.unwrap_or_default();
+
+    let issues_collection: Collection<PeriodicalIssue> = data.db.collection("periodical_issues");
+    let mut cursor = issues_collection
+        .find(
+            doc! { "periodical_id": periodical_id },
+            FindOptions::builder().sort(doc! { "expected_date": -1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut issues = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(issue) => issues.push(issue),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    let claims_collection: Collection<PeriodicalClaim> = data.db.collection("periodical_claims");
+    let mut cursor = claims_collection
+        .find(doc! { "periodical_id": periodical_id, "status": "open" }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut open_claims = std::collections::HashMap::new();
+    while let Some(result) = cursor.next().await {
+        let claim = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        open_claims.insert(claim.expected_date, claim);
+    }
+
+    let received: std::collections::HashSet<NaiveDate> = issues.iter().map(|i| i.expected_date).collect();
+    let today = Utc::now().date_naive();
+    let missing: Vec<serde_json::Value> = expected_issues(&periodical, today - Duration::days(periodical.claim_after_days))
+        .into_iter()
+        .filter(|date| !received.contains(date))
+        .map(|date| serde_json::json!({
+            "expected_date": date,
+            "days_late": (today - date).num_days(),
+            "claim": open_claims.get(&date)
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "periodical": periodical,
+        "issues": issues,
+        "missing": missing
+    })))
+}
+
+async fn update_periodical(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<PeriodicalUpdateRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let periodical_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let body = body.into_inner();
+    let mut set = doc! {};
+    if let Some(title) = body.title.as_deref().map(str::trim).filter(|t| !t.is_empty()) {
+        set.insert("title", title);
+    }
+    if let Some(publisher) = body.publisher {
+        set.insert("publisher", publisher);
+    }
+    if let Some(days) = body.claim_after_days {
+        if !(1..=365).contains(&days) {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "claim_after_days must be between 1 and 365"
+            })));
+        }
+        set.insert("claim_after_days", days);
+    }
+    if let Some(status) = body.status {
+        if status != "active" && status != "cancelled" {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "status must be active or cancelled"
+            })));
+        }
+        set.insert("status", status);
+    }
+    if set.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+
+    let collection: Collection<Periodical> = data.db.collection("periodicals");
+    let updated = collection
+        .find_one_and_update(
+            doc! { "_id": periodical_id, "campus_id": &claims.campus_id },
+            doc! { "$set": set },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match updated {
+        Some(periodical) => Ok(HttpResponse::Ok().json(periodical)),
+        None => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        }))),
+    }
+}
+
+// Record an arriving number against the issue it was expected as
+async fn check_in_periodical_issue(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<CheckInRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(periodical) = find_periodical(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        })));
+    };
+    let periodical_id = periodical.id.unwrap_or_default();
+
+    let body = body.into_inner();
+    let cover_date = body.cover_date.unwrap_or_else(|| Utc::now().date_naive());
+    let Some(expected_date) = expected_issues(&periodical, cover_date).last().copied() else {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("{} is before the subscription starts on {}", cover_date, periodical.start_date)
+        })));
+    };
+
+    let issue = PeriodicalIssue {
+        id: None,
+        periodical_id,
+        volume: body.volume.filter(|v| !v.trim().is_empty()),
+        issue_number: body.issue_number.filter(|n| !n.trim().is_empty()),
+        cover_date,
+        expected_date,
+        received_by: claims.sub,
+        received_at: Utc::now(),
+        note: body.note,
+        campus_id: claims.campus_id,
+    };
+
+    let collection: Collection<PeriodicalIssue> = data.db.collection("periodical_issues");
+    let inserted_id = match collection.insert_one(&issue, None).await {
+        Ok(result) => result.inserted_id,
+        Err(e) if is_duplicate_key(&e) => {
+            return Ok(HttpResponse::Conflict().json(serde_json::json!({
+                "error": format!("The issue expected on {} has already been checked in", expected_date)
+            })))
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    };
+
+    let claims_collection: Collection<PeriodicalClaim> = data.db.collection("periodical_claims");
+    claims_collection
+        .update_many(
+            doc! {
+                "periodical_id": periodical_id,
+                "expected_date": expected_date.to_string(),
+                "status": "open"
+            },
+            doc! { "$set": { "status": "resolved", "resolved_at": Utc::now().to_rfc3339() } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Issue checked in",
+        "id": inserted_id,
+        "expected_date": expected_date
+    })))
+}
+
+// Claim (or chase again) an expected issue that has not arrived
+async fn claim_periodical_issue(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<ClaimRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(periodical) = find_periodical(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        })));
+    };
+    let periodical_id = periodical.id.unwrap_or_default();
+
+    let body = body.into_inner();
+    if !expected_issues(&periodical, Utc::now().date_naive()).contains(&body.expected_date) {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("No issue was due on {}", body.expected_date)
+        })));
+    }
+    if received_issue_dates(&data.db, periodical_id).await?.contains(&body.expected_date) {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("The issue expected on {} has already been checked in", body.expected_date)
+        })));
+    }
+
+    let now = Utc::now().to_rfc3339();
+    let mut set = doc! { "last_claimed_at": &now, "claimed_by": &claims.sub };
+    if let Some(note) = body.note.filter(|n| !n.trim().is_empty()) {
+        set.insert("note", note);
+    }
+
+    let collection: Collection<PeriodicalClaim> = data.db.collection("periodical_claims");
+    let claim = collection
+        .find_one_and_update(
+            doc! {
+                "periodical_id": periodical_id,
+                "expected_date": body.expected_date.to_string(),
+                "status": "open"
+            },
+            doc! {
+                "$inc": { "claim_count": 1 },
+                "$set": set,
+                "$setOnInsert": { "first_claimed_at": &now, "campus_id": &claims.campus_id }
+            },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .upsert(true)
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "periodical": periodical.title,
+        "publisher": periodical.publisher,
+        "claim": claim
+    })))
+}
+
+async fn get_periodical_claims(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<PeriodicalQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let status = query.status.as_deref().filter(|s| !s.is_empty()).unwrap_or("open");
+    let collection: Collection<PeriodicalClaim> = data.db.collection("periodical_claims");
+    let mut cursor = collection
+        .find(
+            doc! { "campus_id": &claims.campus_id, "status": status },
+            FindOptions::builder().sort(doc! { "expected_date": 1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut results = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(claim) => results.push(claim),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+// Extend a subscription to a new end date at the renewal price
+async fn renew_periodical(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<PeriodicalRenewRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(periodical) = find_periodical(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Subscription not found"
+        })));
+    };
+    if periodical.status != "active" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Cancelled subscriptions cannot be renewed"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.end_date <= periodical.end_date {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("end_date must be after the current end date {}", periodical.end_date)
+        })));
+    }
+    if body.cost < 0.0 {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "cost must not be negative"
+        })));
+    }
+
+    let renewal = PeriodicalRenewal {
+        previous_end_date: periodical.end_date,
+        end_date: body.end_date,
+        cost: body.cost,
+        renewed_by: claims.sub,
+        renewed_at: Utc::now(),
+    };
+
+    // Matching the old end date keeps two librarians from renewing the same term twice
+    let collection: Collection<Periodical> = data.db.collection("periodicals");
+    let updated = collection
+        .find_one_and_update(
+            doc! { "_id": periodical.id, "end_date": periodical.end_date.to_string() },
+            doc! {
+                "$set": { "end_date": body.end_date.to_string(), "cost": body.cost },
+                "$push": { "renewals": mongodb::bson::to_bson(&renewal).map_err(actix_web::error::ErrorInternalServerError)? }
+            },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .return_document(mongodb::options::ReturnDocument::After)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    match updated {
+        Some(periodical) => Ok(HttpResponse::Ok().json(periodical)),
+        None => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Subscription was renewed by someone else; reload and try again"
+        }))),
+    }
+}
+
+// Active subscriptions ending within the next `days` days, including ones already lapsed
+async fn periodical_renewals_due(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<RenewalsDueQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let days = query.days.unwrap_or(60).clamp(1, 365);
+    let today = Utc::now().date_naive();
+    let until = today + Duration::days(days);
+
+    let collection: Collection<Periodical> = data.db.collection("periodicals");
+    let mut cursor = collection
+        .find(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "status": "active",
+                "end_date": { "$lte": until.to_string() }
+            },
+            FindOptions::builder().sort(doc! { "end_date": 1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut due = Vec::new();
+    let mut total_cost = 0.0;
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let periodical = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        total_cost += periodical.cost;
+        due.push(serde_json::json!({
+            "id": periodical.id,
+            "title": periodical.title,
+            "issn": periodical.issn,
+            "publisher": periodical.publisher,
+            "vendor_id": periodical.vendor_id,
+            "end_date": periodical.end_date,
+            "days_left": (periodical.end_date - today).num_days(),
+            "lapsed": periodical.end_date < today,
+            "cost": periodical.cost
+        }));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "until": until,
+        "subscriptions": due,
+        "total_cost": round2(total_cost)
+    })))
+}
+
+// ===== HOLD EXPIRY AND PROMOTION =====
+
+#[derive(Debug, Default, Serialize)]
+struct HoldSummary {
+    expired: u64,
+    promoted: u64,
+}
+
+// Takes a copy off the shelf for a promoted hold. Returns false if no copy is free.
+async fn hold_copy(db: &mongodb::Database, book_id: &str) -> mongodb::error::Result<bool> {
+    let Ok(book_obj_id) = ObjectId::parse_str(book_id) else {
+        return Ok(false);
+    };
+    let books: Collection<Book> = db.collection("books");
+    let result = books
+        .update_one(
+            doc! { "_id": book_obj_id, "status": { "$ne": "withdrawn" }, "available_copies": { "$gt": 0 } },
+            doc! { "$inc": { "available_copies": -1 } },
+            None,
+        )
+        .await?;
+    Ok(result.modified_count > 0)
+}
+
+// Puts a copy that was held for pickup back on the shelf
+async fn release_held_copy(db: &mongodb::Database, book_id: &str) -> mongodb::error::Result<()> {
+    let Ok(book_obj_id) = ObjectId::parse_str(book_id) else {
+        return Ok(());
+    };
+    let books: Collection<Book> = db.collection("books");
+    books
+        .update_one(
+            doc! { "_id": book_obj_id, "$expr": { "$lt": ["$available_copies", "$total_copies"] } },
+            doc! { "$inc": { "available_copies": 1 } },
+            None,
+        )
+        .await?;
+    Ok(())
+}
+
+// Promotes the oldest waiting hold on a book if a copy can be set aside for it
+async fn promote_next_hold(
+    db: &mongodb::Database,
+    book_id: &str,
+    campus_id: &str,
+    pickup_days: i64,
+) -> mongodb::error::Result<bool> {
+    if !hold_copy(db, book_id).await? {
+        return Ok(false);
+    }
+
+    let now = Utc::now();
+    let waitlist: Collection<WaitingListEntry> = db.collection("waiting_list");
+    let promoted = waitlist
+        .find_one_and_update(
+            doc! { "book_id": book_id, "campus_id": campus_id, "status": "waiting" },
+            doc! { "$set": {
+                "status": "notified",
+                "copy_held": true,
+                "notified_at": mongodb::bson::to_bson(&now)?,
+                "hold_expires_at": mongodb::bson::to_bson(&(now + Duration::days(pickup_days)))?
+            } },
+            mongodb::options::FindOneAndUpdateOptions::builder()
+                .sort(doc! { "queued_at": 1, "_id": 1 })
+                .build(),
+        )
+        .await?;
+
+    if promoted.is_none() {
+        // Someone else emptied the queue in the meantime
+        release_held_copy(db, book_id).await?;
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+// Expires holds not collected within the pickup window, returning their
+// copies to the shelf, then promotes waiting students for every book with a
+// free copy. campus_id limits the run to one campus.
+async fn process_holds(
+    db: &mongodb::Database,
+    campus_id: Option<&str>,
+    pickup_days: i64,
+) -> mongodb::error::Result<HoldSummary> {
+    use futures::stream::StreamExt;
+
+    let waitlist: Collection<WaitingListEntry> = db.collection("waiting_list");
+    let mut summary = HoldSummary::default();
+    let now = Utc::now();
+
+    let mut filter = doc! {
+        "status": "notified",
+        "copy_held": true,
+        "hold_expires_at": { "$lt": mongodb::bson::to_bson(&now)? }
+    };
+    if let Some(campus_id) = campus_id {
+        filter.insert("campus_id", campus_id);
+    }
+    let mut cursor = waitlist.find(filter, None).await?;
+    while let Some(entry) = cursor.next().await {
+        let entry = entry?;
+        let result = waitlist
+            .update_one(
+                doc! { "_id": entry.id, "status": "notified" },
+                doc! { "$set": {
+                    "status": "expired",
+                    "copy_held": false,
+                    "expired_at": mongodb::bson::to_bson(&now)?
+                } },
+                None,
+            )
+            .await?;
+        if result.modified_count > 0 {
+            release_held_copy(db, &entry.book_id).await?;
+            summary.expired += 1;
+        }
+    }
+
+    let mut filter = doc! { "status": "waiting" };
+    if let Some(campus_id) = campus_id {
+        filter.insert("campus_id", campus_id);
+    }
+    let mut queues = waitlist
+        .aggregate(
+            vec![
+                doc! { "$match": filter },
+                doc! { "$group": { "_id": { "book_id": "$book_id", "campus_id": "$campus_id" } } },
+            ],
+            None,
+        )
+        .await?;
+    while let Some(queue) = queues.next().await {
+        let queue = queue?;
+        let Ok(key) = queue.get_document("_id") else { continue };
+        let (Ok(book_id), Ok(campus_id)) = (key.get_str("book_id"), key.get_str("campus_id")) else {
+            continue;
+        };
+        while promote_next_hold(db, book_id, campus_id, pickup_days).await? {
+            summary.promoted += 1;
+        }
+    }
+
+    Ok(summary)
+}
+
+// Runs the hold job for the caller's campus straight away
+async fn run_hold_processing(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let summary = process_holds(&data.db, Some(&claims.campus_id), data.hold_pickup_days)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Ok().json(summary))
+}
+
+// ===== RECOMMENDATIONS =====
+
+// Score weights: an author the student has read counts most, then a
+// course-mapped category, then a category from their history. Similar
+// readers add up to RECOMMEND_SIMILAR_WEIGHT per fully overlapping reader.
+const RECOMMEND_AUTHOR_WEIGHT: f64 = 3.0;
+const RECOMMEND_COURSE_WEIGHT: f64 = 2.0;
+const RECOMMEND_CATEGORY_WEIGHT: f64 = 1.0;
+const RECOMMEND_SIMILAR_WEIGHT: f64 = 4.0;
+// How many of the students sharing the most loans are treated as similar readers
+const RECOMMEND_NEIGHBOURS: i64 = 50;
+
+// Course codes from the student's most recent semester of enrollments
+async fn current_course_codes(
+    db: &mongodb::Database,
+    student_id: &str,
+    campus_id: &str,
+) -> Result<Vec<String>, Error> {
+    let enrollments: Collection<Document> = db.collection("enrollments");
+    let mut cursor = enrollments
+        .find(
+            doc! { "student_id": student_id, "campus_id": campus_id },
+            FindOptions::builder().sort(doc! { "enrolled_at": -1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut semester = None;
+    let mut codes = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let enrollment = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let (Ok(code), Ok(this_semester)) = (enrollment.get_str("course_code"), enrollment.get_str("semester")) else {
+            continue;
+        };
+        match &semester {
+            None => semester = Some(this_semester.to_string()),
+            Some(latest) if latest != this_semester => break,
+            _ => {}
+        }
+        codes.push(code.to_uppercase());
+    }
+    codes.sort();
+    codes.dedup();
+    Ok(codes)
+}
+
+// Category names mapped to the given courses, with the courses behind each.
+// Sub-subjects of a mapped subject count too.
+async fn course_categories(
+    db: &mongodb::Database,
+    course_codes: &[String],
+    campus_id: &str,
+) -> Result<std::collections::HashMap<String, Vec<String>>, Error> {
+    let mut mapped = std::collections::HashMap::new();
+    if course_codes.is_empty() {
+        return Ok(mapped);
+    }
+
+    let collection: Collection<Category> = db.collection("categories");
+    let mut cursor = collection
+        .find(doc! { "campus_id": campus_id, "course_codes": { "$in": course_codes } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut parents = std::collections::HashMap::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        let category = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let courses: Vec<String> = category
+            .course_codes
+            .iter()
+            .filter(|c| course_codes.contains(c))
+            .cloned()
+            .collect();
+        if let Some(id) = category.id {
+            parents.insert(id, courses.clone());
+        }
+        mapped.insert(category.name, courses);
+    }
+
+    let parent_ids: Vec<ObjectId> = parents.keys().copied().collect();
+    let mut cursor = collection
+        .find(doc! { "campus_id": campus_id, "parent_id": { "$in": parent_ids } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let child = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let inherited = child.parent_id.and_then(|p| parents.get(&p)).cloned().unwrap_or_default();
+        let courses = mapped.entry(child.name).or_insert_with(Vec::new);
+        courses.extend(inherited);
+        courses.sort();
+        courses.dedup();
+    }
+
+    Ok(mapped)
+}
+
+// Titles suggested for the signed-in student from their loans, their courses
+// and what students with overlapping loans went on to borrow
+async fn get_my_recommendations(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<RecommendationQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let limit = query.limit.unwrap_or(10).clamp(1, 50);
+    let student_id = claims.sub.as_str();
+    let campus_id = claims.campus_id.as_str();
+
+    let issues: Collection<BookIssue> = data.db.collection("book_issues");
+    let books: Collection<Book> = data.db.collection("books");
+    use futures::stream::StreamExt;
+
+    // What the student has read
+    let borrowed: Vec<String> = issues
+        .distinct("book_id", doc! { "student_id": student_id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .filter_map(|id| id.as_str().map(str::to_string))
+        .collect();
+    let borrowed_ids: Vec<ObjectId> = borrowed.iter().filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+
+    let mut authors = std::collections::HashSet::new();
+    let mut read_categories: std::collections::HashMap<String, usize> = std::collections::HashMap::new();
+    let mut cursor = books
+        .find(doc! { "_id": { "$in": &borrowed_ids } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        authors.insert(book.author);
+        *read_categories.entry(book.category).or_insert(0) += 1;
+    }
+
+    let course_codes = current_course_codes(&data.db, student_id, campus_id).await?;
+    let course_map = course_categories(&data.db, &course_codes, campus_id).await?;
+
+    // Similar readers: students who borrowed the same titles, weighted by the
+    // share of this student's titles they have in common
+    let mut similar: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+    if !borrowed.is_empty() {
+        let mut cursor = issues
+            .aggregate(
+                vec![
+                    doc! { "$match": {
+                        "campus_id": campus_id,
+                        "book_id": { "$in": &borrowed },
+                        "student_id": { "$ne": student_id }
+                    } },
+                    doc! { "$group": { "_id": "$student_id", "books": { "$addToSet": "$book_id" } } },
+                    doc! { "$project": { "overlap": { "$size": "$books" } } },
+                    doc! { "$sort": { "overlap": -1, "_id": 1 } },
+                    doc! { "$limit": RECOMMEND_NEIGHBOURS },
+                ],
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        while let Some(result) = cursor.next().await {
+            let row = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            if let (Ok(neighbour), Ok(overlap)) = (row.get_str("_id"), row.get_i32("overlap")) {
+                similar.insert(neighbour.to_string(), overlap as f64 / borrowed.len() as f64);
+            }
+        }
+    }
+
+    let mut similar_scores: std::collections::HashMap<String, (f64, usize)> = std::collections::HashMap::new();
+    if !similar.is_empty() {
+        let neighbours: Vec<&String> = similar.keys().collect();
+        let mut cursor = issues
+            .aggregate(
+                vec![
+                    doc! { "$match": {
+                        "campus_id": campus_id,
+                        "student_id": { "$in": neighbours },
+                        "book_id": { "$nin": &borrowed }
+                    } },
+                    doc! { "$group": { "_id": { "book_id": "$book_id", "student_id": "$student_id" } } },
+                ],
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        while let Some(result) = cursor.next().await {
+            let row = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            let Ok(key) = row.get_document("_id") else { continue };
+            let (Ok(book_id), Ok(neighbour)) = (key.get_str("book_id"), key.get_str("student_id")) else {
+                continue;
+            };
+            let entry = similar_scores.entry(book_id.to_string()).or_insert((0.0, 0));
+            entry.0 += similar.get(neighbour).copied().unwrap_or(0.0);
+            entry.1 += 1;
+        }
+    }
+
+    let mut criteria = Vec::new();
+    if !authors.is_empty() {
+        criteria.push(doc! { "author": { "$in": authors.iter().collect::<Vec<_>>() } });
+    }
+    let categories: Vec<&String> = read_categories.keys().chain(course_map.keys()).collect();
+    if !categories.is_empty() {
+        criteria.push(doc! { "category": { "$in": categories } });
+    }
+    let similar_ids: Vec<ObjectId> = similar_scores.keys().filter_map(|id| ObjectId::parse_str(id).ok()).collect();
+    if !similar_ids.is_empty() {
+        criteria.push(doc! { "_id": { "$in": similar_ids } });
+    }
+
+    let mut recommendations = Vec::new();
+    if !criteria.is_empty() {
+        let mut cursor = books
+            .find(
+                doc! {
+                    "campus_id": campus_id,
+                    "status": { "$ne": "withdrawn" },
+                    "_id": { "$nin": &borrowed_ids },
+                    "$or": criteria
+                },
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        let most_read = read_categories.values().copied().max().unwrap_or(1) as f64;
+        while let Some(result) = cursor.next().await {
+            let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            let book_id = book.id.map(|id| id.to_hex()).unwrap_or_default();
+            let mut score = 0.0;
+            let mut reasons = Vec::new();
+
+            if authors.contains(&book.author) {
+                score += RECOMMEND_AUTHOR_WEIGHT;
+                reasons.push(format!("Also by {}", book.author));
+            }
+            if let Some(courses) = course_map.get(&book.category) {
+                score += RECOMMEND_COURSE_WEIGHT;
+                reasons.push(format!("Recommended for {}", courses.join(", ")));
+            }
+            if let Some(&count) = read_categories.get(&book.category) {
+                score += RECOMMEND_CATEGORY_WEIGHT * count as f64 / most_read;
+                reasons.push(format!("You often read {}", book.category));
+            }
+            if let Some(&(similarity, readers)) = similar_scores.get(&book_id) {
+                score += RECOMMEND_SIMILAR_WEIGHT * similarity;
+                reasons.push(format!(
+                    "Borrowed by {} student{} with similar reading",
+                    readers,
+                    if readers == 1 { "" } else { "s" }
+                ));
+            }
+
+            recommendations.push((score, book, reasons));
+        }
+    }
+
+    // Nothing to go on yet: fall back to what the campus borrows most
+    let fallback = recommendations.is_empty();
+    if fallback {
+        let since = mongodb::bson::to_bson(&(Utc::now() - Duration::days(365)))
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        let mut cursor = issues
+            .aggregate(
+                vec![
+                    doc! { "$match": { "campus_id": campus_id, "issue_date": { "$gte": since } } },
+                    doc! { "$group": { "_id": "$book_id", "issues": { "$sum": 1 } } },
+                    doc! { "$sort": { "issues": -1, "_id": 1 } },
+                    doc! { "$limit": (limit * 2) as i64 },
+                ],
+                None,
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        let mut popular = Vec::new();
+        while let Some(result) = cursor.next().await {
+            let row = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            if let (Ok(book_id), Ok(count)) = (row.get_str("_id"), row.get_i32("issues")) {
+                if let Ok(book_id) = ObjectId::parse_str(book_id) {
+                    popular.push((book_id, count));
+                }
+            }
+        }
+        for (book_id, count) in popular {
+            let book = books
+                .find_one(doc! { "_id": book_id, "status": { "$ne": "withdrawn" } }, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            if let Some(book) = book {
+                recommendations.push((count as f64, book, vec![format!("Issued {} times this year", count)]));
+            }
+        }
+    }
+
+    // Highest score first; among equals, titles on the shelf now
+    recommendations.sort_by(|a, b| {
+        b.0.partial_cmp(&a.0)
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then((b.1.available_copies > 0).cmp(&(a.1.available_copies > 0)))
+            .then(a.1.title.cmp(&b.1.title))
+    });
+    recommendations.truncate(limit);
+
+    let recommendations: Vec<serde_json::Value> = recommendations
+        .into_iter()
+        .map(|(score, book, reasons)| serde_json::json!({
+            "book_id": book.id,
+            "title": book.title,
+            "author": book.author,
+            "category": book.category,
+            "available_copies": book.available_copies,
+            "score": if fallback { 0.0 } else { round2(score) },
+            "reasons": reasons
+        }))
+        .collect();
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "student_id": student_id,
+        "based_on": {
+            "books_borrowed": borrowed.len(),
+            "courses": course_codes,
+            "similar_readers": similar.len()
+        },
+        "fallback": fallback,
+        "recommendations": recommendations
+    })))
+}
+
+// ===== STOCK AUDIT =====
+
+const AUDIT_MAX_SCANS: usize = 1000;
+
+fn normalize_accession(raw: &str) -> String {
+    raw.trim().to_uppercase()
+}
+
+// Give existing copies of a book their accession numbers
+async fn register_copies(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<CopyRegistrationRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let book_id = path.into_inner();
+    let book_obj_id = ObjectId::parse_str(&book_id).map_err(actix_web::error::ErrorBadRequest)?;
+    let books: Collection<Book> = data.db.collection("books");
+    let Some(book) = books
+        .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Book not found"
+        })));
+    };
+
+    let body = body.into_inner();
+    let mut numbers: Vec<String> = body.accession_numbers.iter().map(|n| normalize_accession(n)).collect();
+    numbers.retain(|n| !n.is_empty());
+    numbers.sort();
+    numbers.dedup();
+    if numbers.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "accession_numbers must not be empty"
+        })));
+    }
+
+    let copies: Collection<BookCopy> = data.db.collection("book_copies");
+    let registered = copies
+        .count_documents(doc! { "book_id": &book_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if registered as usize + numbers.len() > book.total_copies.max(0) as usize {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!(
+                "The book has {} copies and {} already have accession numbers",
+                book.total_copies, registered
+            )
+        })));
+    }
+
+    let location = body.location.map(|l| l.trim().to_uppercase()).filter(|l| !l.is_empty());
+    let now = Utc::now();
+    let (mut added, mut duplicates) = (Vec::new(), Vec::new());
+    for number in numbers {
+        let copy = BookCopy {
+            id: None,
+            accession_number: number.clone(),
+            book_id: book_id.clone(),
+            location: location.clone(),
+            status: "available".to_string(),
+            campus_id: claims.campus_id.clone(),
+            created_at: now,
+            last_seen_at: None,
+        };
+        match copies.insert_one(&copy, None).await {
+            Ok(_) => added.push(number),
+            Err(e) if is_duplicate_key(&e) => duplicates.push(number),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "added": added,
+        "already_registered": duplicates
+    })))
+}
+
+async fn get_book_copies(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let copies: Collection<BookCopy> = data.db.collection("book_copies");
+    let mut cursor = copies
+        .find(
+            doc! { "book_id": path.into_inner(), "campus_id": &claims.campus_id },
+            FindOptions::builder().sort(doc! { "accession_number": 1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut results = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(copy) => results.push(copy),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(results))
+}
+
+async fn find_audit(db: &mongodb::Database, id: &str, campus_id: &str) -> Result<Option<StockAudit>, Error> {
+    let id = ObjectId::parse_str(id).map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<StockAudit> = db.collection("stock_audits");
+    collection
+        .find_one(doc! { "_id": id, "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)
+}
+
+async fn open_stock_audit(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<StockAuditRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let body = body.into_inner();
+    if body.name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name must not be blank"
+        })));
+    }
+
+    let audit = StockAudit {
+        id: None,
+        name: body.name.trim().to_string(),
+        location_prefix: body.location_prefix.map(|l| l.trim().to_uppercase()).filter(|l| !l.is_empty()),
+        category: body.category.map(|c| c.trim().to_string()).filter(|c| !c.is_empty()),
+        status: "open".to_string(),
+        started_by: claims.sub,
+        started_at: Utc::now(),
+        closed_by: None,
+        closed_at: None,
+        outcome: None,
+        campus_id: claims.campus_id,
+    };
+
+    let collection: Collection<StockAudit> = data.db.collection("stock_audits");
+    let result = collection
+        .insert_one(&audit, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    Ok(HttpResponse::Created().json(serde_json::json!({
+        "message": "Audit opened",
+        "id": result.inserted_id
+    })))
+}
+
+async fn get_stock_audits(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let collection: Collection<StockAudit> = data.db.collection("stock_audits");
+    let mut cursor = collection
+        .find(
+            doc! { "campus_id": &claims.campus_id },
+            FindOptions::builder().sort(doc! { "started_at": -1 }).build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut audits = Vec::new();
+    use futures::stream::StreamExt;
+
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(audit) => audits.push(audit),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(audits))
+}
+
+// Record accession numbers found on the shelves; scanning one again updates where it was found
+async fn scan_audit_copies(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<AuditScanRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(audit) = find_audit(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Audit not found"
+        })));
+    };
+    if audit.status != "open" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Audit is closed"
+        })));
+    }
+
+    let body = body.into_inner();
+    let mut numbers: Vec<String> = body.accession_numbers.iter().map(|n| normalize_accession(n)).collect();
+    let mut unique = std::collections::HashSet::new();
+    numbers.retain(|n| !n.is_empty() && unique.insert(n.clone()));
+    if numbers.is_empty() || numbers.len() > AUDIT_MAX_SCANS {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": format!("Send between 1 and {} accession numbers", AUDIT_MAX_SCANS)
+        })));
+    }
+    let location = body.location.map(|l| l.trim().to_uppercase()).filter(|l| !l.is_empty());
+
+    let copies: Collection<BookCopy> = data.db.collection("book_copies");
+    let mut cursor = copies
+        .find(doc! { "campus_id": &claims.campus_id, "accession_number": { "$in": &numbers } }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut known = std::collections::HashMap::new();
+    use futures::stream::StreamExt;
+    while let Some(result) = cursor.next().await {
+        let copy = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        known.insert(copy.accession_number.clone(), copy);
+    }
+
+    let scans: Collection<StockAuditScan> = data.db.collection("stock_audit_scans");
+    let now = Utc::now();
+    let mut results = Vec::new();
+    for number in &numbers {
+        let mut set = doc! {
+            "scanned_by": &claims.sub,
+            "scanned_at": mongodb::bson::to_bson(&now).map_err(actix_web::error::ErrorInternalServerError)?
+        };
+        if let Some(location) = &location {
+            set.insert("location", location);
+        }
+        scans
+            .update_one(
+                doc! { "audit_id": audit.id, "accession_number": number },
+                doc! { "$set": set },
+                mongodb::options::UpdateOptions::builder().upsert(true).build(),
+            )
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+
+        // Immediate feedback for the person at the shelf; the report has the full picture
+        let result = match known.get(number) {
+            None => "unknown",
+            Some(copy) if copy.status == "lost" || copy.status == "missing" => "marked_lost",
+            Some(copy) if copy.status == "on_loan" => "on_loan",
+            Some(copy) if location.is_some() && copy.location.is_some() && copy.location != location => "misplaced",
+            Some(_) => "ok",
+        };
+        results.push(serde_json::json!({ "accession_number": number, "result": result }));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "scanned": results.len(),
+        "results": results
+    })))
+}
+
+struct AuditFindings {
+    scanned: usize,
+    // Expected on the shelf but not scanned
+    missing: Vec<BookCopy>,
+    // Found somewhere other than where they belong: (copy, where it was found)
+    misplaced: Vec<(BookCopy, Option<String>)>,
+    found_but_marked_lost: Vec<BookCopy>,
+    // Found on a shelf while recorded as on loan
+    on_loan_but_found: Vec<BookCopy>,
+    unknown: Vec<String>,
+    titles: std::collections::HashMap<String, String>,
+}
+
+fn copy_in_audit_scope(audit: &StockAudit, copy: &BookCopy, scope_books: &Option<std::collections::HashSet<String>>) -> bool {
+    let in_location = match &audit.location_prefix {
+        Some(prefix) => copy.location.as_deref().is_some_and(|l| l.starts_with(prefix.as_str())),
+        None => true,
+    };
+    let in_category = scope_books.as_ref().is_none_or(|books| books.contains(&copy.book_id));
+    in_location && in_category
+}
+
+async fn audit_findings(db: &mongodb::Database, audit: &StockAudit) -> Result<AuditFindings, Error> {
+    use futures::stream::StreamExt;
+
+    let books: Collection<Book> = db.collection("books");
+    let mut book_filter = doc! { "campus_id": &audit.campus_id };
+    if let Some(category) = &audit.category {
+        book_filter.insert("category", category);
+    }
+    let mut titles = std::collections::HashMap::new();
+    let mut cursor = books
+        .find(book_filter, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(id) = book.id {
+            titles.insert(id.to_hex(), book.title);
+        }
+    }
+    let scope_books: Option<std::collections::HashSet<String>> =
+        audit.category.as_ref().map(|_| titles.keys().cloned().collect());
+
+    let scans: Collection<StockAuditScan> = db.collection("stock_audit_scans");
+    let mut found: std::collections::HashMap<String, Option<String>> = std::collections::HashMap::new();
+    let mut cursor = scans
+        .find(doc! { "audit_id": audit.id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let scan = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        found.insert(scan.accession_number, scan.location);
+    }
+
+    let mut findings = AuditFindings {
+        scanned: found.len(),
+        missing: Vec::new(),
+        misplaced: Vec::new(),
+        found_but_marked_lost: Vec::new(),
+        on_loan_but_found: Vec::new(),
+        unknown: Vec::new(),
+        titles: std::collections::HashMap::new(),
+    };
+
+    let copies: Collection<BookCopy> = db.collection("book_copies");
+    let mut seen = std::collections::HashSet::new();
+    let mut cursor = copies
+        .find(doc! { "campus_id": &audit.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    while let Some(result) = cursor.next().await {
+        let copy = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        let in_scope = copy_in_audit_scope(audit, &copy, &scope_books);
+        match found.get(&copy.accession_number) {
+            Some(found_at) => {
+                seen.insert(copy.accession_number.clone());
+                if copy.status == "lost" || copy.status == "missing" {
+                    findings.found_but_marked_lost.push(copy.clone());
+                } else if copy.status == "on_loan" {
+                    findings.on_loan_but_found.push(copy.clone());
+                }
+                let wrong_shelf = found_at.is_some() && copy.location.is_some() && *found_at != copy.location;
+                if !in_scope || wrong_shelf {
+                    findings.misplaced.push((copy, found_at.clone()));
+                }
+            }
+            None if in_scope && copy.status == "available" => findings.missing.push(copy),
+            None => {}
+        }
+    }
+    findings.unknown = found.into_keys().filter(|n| !seen.contains(n)).collect();
+    findings.unknown.sort();
+
+    // Titles for every copy in the findings, including books outside the audit's category
+    let mut wanted: Vec<ObjectId> = Vec::new();
+    for copy in findings
+        .missing
+        .iter()
+        .chain(findings.misplaced.iter().map(|(c, _)| c))
+        .chain(findings.found_but_marked_lost.iter())
+        .chain(findings.on_loan_but_found.iter())
+    {
+        match titles.get(&copy.book_id) {
+            Some(title) => {
+                findings.titles.insert(copy.book_id.clone(), title.clone());
+            }
+            None => wanted.extend(ObjectId::parse_str(&copy.book_id).ok()),
+        }
+    }
+    if !wanted.is_empty() {
+        let mut cursor = books
+            .find(doc! { "_id": { "$in": wanted } }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?;
+        while let Some(result) = cursor.next().await {
+            let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+            if let Some(id) = book.id {
+                findings.titles.insert(id.to_hex(), book.title);
+            }
+        }
+    }
+
+    Ok(findings)
+}
+
+fn audit_report_json(audit: &StockAudit, findings: &AuditFindings) -> serde_json::Value {
+    let copy_json = |copy: &BookCopy| serde_json::json!({
+        "accession_number": copy.accession_number,
+        "book_id": copy.book_id,
+        "title": findings.titles.get(&copy.book_id),
+        "location": copy.location,
+        "status": copy.status
+    });
+
+    serde_json::json!({
+        "audit": audit,
+        "scanned": findings.scanned,
+        "missing": findings.missing.iter().map(copy_json).collect::<Vec<_>>(),
+        "misplaced": findings.misplaced.iter().map(|(copy, found_at)| {
+            let mut entry = copy_json(copy);
+            entry["found_at"] = serde_json::json!(found_at);
+            entry
+        }).collect::<Vec<_>>(),
+        "found_but_marked_lost": findings.found_but_marked_lost.iter().map(copy_json).collect::<Vec<_>>(),
+        "on_loan_but_found": findings.on_loan_but_found.iter().map(copy_json).collect::<Vec<_>>(),
+        "unknown": findings.unknown
+    })
+}
+
+async fn stock_audit_report(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(audit) = find_audit(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Audit not found"
+        })));
+    };
+
+    let findings = audit_findings(&data.db, &audit).await?;
+    Ok(HttpResponse::Ok().json(audit_report_json(&audit, &findings)))
+}
+
+// Close the audit and apply its findings to the copies in bulk
+async fn close_stock_audit(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<AuditCloseRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if claims.role != "librarian" && claims.role != "admin" {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
+
+    let Some(audit) = find_audit(&data.db, &path, &claims.campus_id).await? else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Audit not found"
+        })));
+    };
+
+    // Claim the audit first so scans and a second close cannot interleave
+    let audits: Collection<StockAudit> = data.db.collection("stock_audits");
+    let now = Utc::now();
+    let claimed = audits
+        .update_one(
+            doc! { "_id": audit.id, "status": "open" },
+            doc! { "$set": { "status": "closed", "closed_by": &claims.sub, "closed_at": now.to_rfc3339() } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if claimed.modified_count == 0 {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Audit is already closed"
+        })));
+    }
+
+    let findings = audit_findings(&data.db, &audit).await?;
+    let copies: Collection<BookCopy> = data.db.collection("book_copies");
+    let books: Collection<Book> = data.db.collection("books");
+    let mut outcome = AuditOutcome::default();
+    let seen_at = mongodb::bson::to_bson(&now).map_err(actix_web::error::ErrorInternalServerError)?;
+
+    // Copies not on the shelf can't be lent, so they leave available_copies
+    if body.mark_missing.unwrap_or(true) {
+        for copy in &findings.missing {
+            let result = copies
+                .update_one(
+                    doc! { "_id": copy.id, "status": "available" },
+                    doc! { "$set": { "status": "missing" } },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            if result.modified_count > 0 {
+                outcome.marked_missing += 1;
+                if let Ok(book_id) = ObjectId::parse_str(&copy.book_id) {
+                    books
+                        .update_one(
+                            doc! { "_id": book_id, "available_copies": { "$gt": 0 } },
+                            doc! { "$inc": { "available_copies": -1 } },
+                            None,
+                        )
+                        .await
+                        .map_err(actix_web::error::ErrorInternalServerError)?;
+                }
+            }
+        }
+    }
+
+    if body.restore_found.unwrap_or(true) {
+        for copy in &findings.found_but_marked_lost {
+            let result = copies
+                .update_one(
+                    doc! { "_id": copy.id, "status": { "$in": ["lost", "missing"] } },
+                    doc! { "$set": { "status": "available" } },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            if result.modified_count > 0 {
+                outcome.restored += 1;
+                if let Ok(book_id) = ObjectId::parse_str(&copy.book_id) {
+                    books
+                        .update_one(
+                            doc! { "_id": book_id, "$expr": { "$lt": ["$available_copies", "$total_copies"] } },
+                            doc! { "$inc": { "available_copies": 1 } },
+                            None,
+                        )
+                        .await
+                        .map_err(actix_web::error::ErrorInternalServerError)?;
+                }
+            }
+        }
+    }
+
+    if body.update_locations.unwrap_or(true) {
+        for (copy, found_at) in &findings.misplaced {
+            if let Some(found_at) = found_at {
+                copies
+                    .update_one(doc! { "_id": copy.id }, doc! { "$set": { "location": found_at } }, None)
+                    .await
+                    .map_err(actix_web::error::ErrorInternalServerError)?;
+                outcome.relocated += 1;
+            }
+        }
+    }
+
+    // Everything scanned was seen today
+    let scanned: Vec<String> = data
+        .db
+        .collection::<StockAuditScan>("stock_audit_scans")
+        .distinct("accession_number", doc! { "audit_id": audit.id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .into_iter()
+        .filter_map(|n| n.as_str().map(str::to_string))
+        .collect();
+    copies
+        .update_many(
+            doc! { "campus_id": &claims.campus_id, "accession_number": { "$in": scanned } },
+            doc! { "$set": { "last_seen_at": seen_at } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    audits
+        .update_one(
+            doc! { "_id": audit.id },
+            doc! { "$set": { "outcome": mongodb::bson::to_bson(&outcome).map_err(actix_web::error::ErrorInternalServerError)? } },
+            None,
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let mut report = audit_report_json(&audit, &findings);
+    report["outcome"] = serde_json::json!(outcome);
+    Ok(HttpResponse::Ok().json(report))
+}
+
+// ===== RECEIPTS =====
+
+// Receipts are rebuilt from the issue record, so a reprint always matches
+// what the desk handed out; only the number is stored on the issue.
+#[derive(Debug, Serialize)]
+struct Receipt {
+    receipt_number: String,
+    kind: String, // issue, return
+    issue_id: Option<ObjectId>,
+    campus_id: String,
+    student_id: String,
+    book_id: String,
+    book_title: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    author: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    isbn: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    accession_number: Option<String>,
+    issue_date: DateTime<Utc>,
+    due_date: DateTime<Utc>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    return_date: Option<DateTime<Utc>>,
+    renewal_count: i32,
+    days_overdue: i64,
+    fine_amount: f64,
+    fine_waived: f64,
+    fine_due: f64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    fine_status: Option<String>,
+}
+
+/// Query params for GET /api/issues/{id}/receipt
+#[derive(Debug, Deserialize)]
+struct ReceiptQuery {
+    /// "issue" or "return" (default: return once the book is back, else issue)
+    kind: Option<String>,
+    /// "json" (default) or "pdf"
+    format: Option<String>,
+}
+
+async fn next_receipt_number(db: &mongodb::Database, campus_id: &str, kind: &str) -> mongodb::error::Result<String> {
+    let prefix = if kind == "return" { "RET" } else { "ISS" };
+    let year = financial_year(Utc::now().date_naive());
+    let seq = next_sequence(db, &format!("receipt:{}:{}:{}", kind, campus_id, year)).await?;
+    Ok(format!("{}-{}-{}-{:06}", prefix, campus_id, year, seq))
+}
+
+fn build_receipt(issue: &BookIssue, book: Option<&Book>, kind: &str, receipt_number: String) -> Receipt {
+    let returned = kind == "return";
+    // Returns made before the library calendar were charged for every day late
+    let days_overdue = match issue.return_date {
+        Some(return_date) if returned => issue
+            .fine_days
+            .unwrap_or_else(|| (return_date - issue.due_date).num_days().max(0)),
+        _ => 0,
+    };
+    let (fine_amount, fine_waived) = if returned { (issue.fine_amount, issue.fine_waived) } else { (0.0, 0.0) };
+    Receipt {
+        receipt_number,
+        kind: kind.to_string(),
+        issue_id: issue.id,
+        campus_id: issue.campus_id.clone(),
+        student_id: issue.student_id.clone(),
+        book_id: issue.book_id.clone(),
+        book_title: issue.book_title.clone(),
+        author: book.map(|b| b.author.clone()),
+        isbn: book.map(|b| b.isbn.clone()).filter(|i| !i.is_empty()),
+        accession_number: issue.accession_number.clone(),
+        issue_date: issue.issue_date,
+        due_date: issue.due_date,
+        return_date: if returned { issue.return_date } else { None },
+        renewal_count: issue.renewal_count,
+        days_overdue,
+        fine_amount,
+        fine_waived,
+        fine_due: round2((fine_amount - fine_waived).max(0.0)),
+        fine_status: if returned { issue.fine_status.clone() } else { None },
+    }
+}
+
+// Renders a one-page A5 receipt for the circulation desk printer
+fn render_receipt_pdf(institution_name: &str, receipt: &Receipt) -> Result<Vec<u8>, String> {
+    use printpdf::{BuiltinFont, Line, Mm, PdfDocument, Point};
+
+    const TOP: f32 = 195.0;
+    const LEFT: f32 = 15.0;
+    const RIGHT: f32 = 133.0;
+    const VALUE: f32 = 55.0;
+    const ROW: f32 = 6.0;
+
+    let title = if receipt.kind == "return" { "Return Receipt" } else { "Issue Receipt" };
+    let (doc, page, layer) = PdfDocument::new(
+        format!("{} {}", title, receipt.receipt_number),
+        Mm(148.0),
+        Mm(210.0),
+        "Receipt",
+    );
+    let font = doc
+        .add_builtin_font(BuiltinFont::Helvetica)
+        .map_err(|e| format!("Failed to load font: {}", e))?;
+    let bold = doc
+        .add_builtin_font(BuiltinFont::HelveticaBold)
+        .map_err(|e| format!("Failed to load font: {}", e))?;
+
+    let layer = doc.get_page(page).get_layer(layer);
+    let rule = |y: f32| {
+        layer.add_line(Line {
+            points: vec![
+                (Point::new(Mm(LEFT), Mm(y)), false),
+                (Point::new(Mm(RIGHT), Mm(y)), false),
+            ],
+            is_closed: false,
+        });
+    };
+
+    let mut y = TOP;
+    layer.use_text(institution_name, 14.0, Mm(LEFT), Mm(y), &bold);
+    y -= 7.0;
+    layer.use_text(format!("Library {}", title), 11.0, Mm(LEFT), Mm(y), &font);
+    y -= 5.0;
+    layer.use_text(format!("Campus: {}", receipt.campus_id), 9.0, Mm(LEFT), Mm(y), &font);
+    y -= 4.0;
+    rule(y);
+    y -= 7.0;
+
+    let date = |d: DateTime<Utc>| d.format("%Y-%m-%d %H:%M UTC").to_string();
+    let mut rows = vec![
+        ("Receipt", receipt.receipt_number.clone()),
+        ("Student ID", receipt.student_id.clone()),
+        ("Title", receipt.book_title.chars().take(45).collect()),
+    ];
+    if let Some(author) = &receipt.author {
+        rows.push(("Author", author.chars().take(45).collect()));
+    }
+    if let Some(isbn) = &receipt.isbn {
+        rows.push(("ISBN", isbn.clone()));
+    }
+    if let Some(accession_number) = &receipt.accession_number {
+        rows.push(("Accession No.", accession_number.clone()));
+    }
+    rows.push(("Issued", date(receipt.issue_date)));
+    rows.push(("Due", date(receipt.due_date)));
+    if receipt.renewal_count > 0 {
+        rows.push(("Renewals", receipt.renewal_count.to_string()));
+    }
+    if let Some(return_date) = receipt.return_date {
+        rows.push(("Returned", date(return_date)));
+        rows.push(("Days overdue", receipt.days_overdue.to_string()));
+        rows.push(("Fine", format!("{:.2}", receipt.fine_amount)));
+        if receipt.fine_waived > 0.0 {
+            rows.push(("Waived", format!("{:.2}", receipt.fine_waived)));
+        }
+    }
+    for (label, value) in &rows {
+        layer.use_text(*label, 10.0, Mm(LEFT), Mm(y), &bold);
+        layer.use_text(value, 10.0, Mm(VALUE), Mm(y), &font);
+        y -= ROW;
+    }
+
+    if receipt.kind == "return" {
+        y -= 2.0;
+        rule(y);
+        y -= ROW;
+        layer.use_text("Amount due", 11.0, Mm(LEFT), Mm(y), &bold);
+        layer.use_text(format!("{:.2}", receipt.fine_due), 11.0, Mm(VALUE), Mm(y), &bold);
+    } else {
+        y -= 2.0;
+        rule(y);
+        y -= ROW;
+        layer.use_text("Please return the book by the due date.", 9.0, Mm(LEFT), Mm(y), &font);
+    }
+
+    doc.save_to_bytes()
+        .map_err(|e| format!("Failed to render receipt PDF: {}", e))
+}
+
+// Reprints the issue or return receipt of an issue. Issues made before
+// receipts existed get their number on the first reprint.
+async fn get_issue_receipt(
+    data: web::Data<AppState>,
     req: HttpRequest,
+    path: web::Path<String>,
+    query: web::Query<ReceiptQuery>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
-
-    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id }, None)
+    let issue_obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let Some(mut issue) = issue_collection
+        .find_one(doc! { "_id": issue_obj_id, "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Issue record not found"
+        })));
+    };
+    if claims.role == "student" && claims.sub != issue.student_id {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: You can only view your own receipts"
+        })));
+    }
 
-    let mut issues = Vec::new();
+    let kind = match query.kind.as_deref() {
+        Some(kind @ ("issue" | "return")) => kind,
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "kind must be 'issue' or 'return'"
+            })));
+        }
+        None if issue.return_date.is_some() => "return",
+        None => "issue",
+    };
+    let pdf = match query.format.as_deref() {
+        None | Some("json") => false,
+        Some("pdf") => true,
+        Some(_) => {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "format must be 'json' or 'pdf'"
+            })));
+        }
+    };
+    if kind == "return" && issue.return_date.is_none() {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book has not been returned yet"
+        })));
+    }
+
+    let field = if kind == "return" { "return_receipt_number" } else { "issue_receipt_number" };
+    let stored = if kind == "return" { &issue.return_receipt_number } else { &issue.issue_receipt_number };
+    let receipt_number = match stored.clone() {
+        Some(number) => number,
+        None => {
+            let number = next_receipt_number(&data.db, &claims.campus_id, kind)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            issue_collection
+                .update_one(
+                    doc! { "_id": issue_obj_id, field: null },
+                    doc! { "$set": { field: &number } },
+                    None,
+                )
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?;
+            // A concurrent reprint may have numbered it first
+            issue = issue_collection
+                .find_one(doc! { "_id": issue_obj_id }, None)
+                .await
+                .map_err(actix_web::error::ErrorInternalServerError)?
+                .ok_or_else(|| actix_web::error::ErrorNotFound("Issue record not found"))?;
+            let stored = if kind == "return" { &issue.return_receipt_number } else { &issue.issue_receipt_number };
+            stored.clone().unwrap_or(number)
+        }
+    };
+
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let book = match ObjectId::parse_str(&issue.book_id) {
+        Ok(book_obj_id) => book_collection
+            .find_one(doc! { "_id": book_obj_id }, None)
+            .await
+            .map_err(actix_web::error::ErrorInternalServerError)?,
+        Err(_) => None,
+    };
+    let receipt = build_receipt(&issue, book.as_ref(), kind, receipt_number);
+
+    if !pdf {
+        return Ok(HttpResponse::Ok().json(receipt));
+    }
+    let bytes = render_receipt_pdf(&data.institution_name, &receipt)
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    Ok(HttpResponse::Ok()
+        .content_type("application/pdf")
+        .insert_header((
+            "Content-Disposition",
+            format!("attachment; filename=\"{}.pdf\"", receipt.receipt_number),
+        ))
+        .body(bytes))
+}
+
+// ===== LIBRARY CALENDAR =====
+
+// Days the library is closed. Due dates that land on one move to the next
+// open day, and closed days do not count towards overdue fines. Dates are
+// taken in UTC, like every other date in the service.
+struct ClosedDays {
+    weekly_off: Vec<chrono::Weekday>,
+    holidays: std::collections::HashSet<NaiveDate>,
+}
+
+impl ClosedDays {
+    fn is_closed(&self, date: NaiveDate) -> bool {
+        use chrono::Datelike;
+        self.weekly_off.contains(&date.weekday()) || self.holidays.contains(&date)
+    }
+
+    // Moves a due date off closed days, keeping the time of day. Weekly offs
+    // never cover the whole week, so only a run of holidays can stop the
+    // search, and a year of them is treated as a data error.
+    fn next_open(&self, due: DateTime<Utc>) -> DateTime<Utc> {
+        let mut date = due;
+        for _ in 0..366 {
+            if !self.is_closed(date.date_naive()) {
+                return date;
+            }
+            date += Duration::days(1);
+        }
+        due
+    }
+
+    // Whole days late, leaving out the days the library was closed
+    fn overdue_days(&self, due: DateTime<Utc>, returned: DateTime<Utc>) -> i64 {
+        let late = (returned - due).num_days();
+        (1..=late)
+            .filter(|d| !self.is_closed(due.date_naive() + Duration::days(*d)))
+            .count() as i64
+    }
+}
+
+async fn closed_days(db: &mongodb::Database, campus_id: &str) -> Result<ClosedDays, Error> {
     use futures::stream::StreamExt;
-    
+
+    let calendars: Collection<LibraryCalendar> = db.collection("library_calendars");
+    let weekly_off = calendars
+        .find_one(doc! { "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?
+        .map(|c| c.weekly_off.iter().filter_map(|d| d.parse().ok()).collect())
+        .unwrap_or_default();
+
+    let holidays: Collection<LibraryHoliday> = db.collection("library_holidays");
+    let mut cursor = holidays
+        .find(doc! { "campus_id": campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut dates = std::collections::HashSet::new();
     while let Some(result) = cursor.next().await {
-        match result {
-            Ok(issue) => issues.push(issue),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
+        let holiday = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        dates.insert(holiday.date);
     }
 
-    Ok(HttpResponse::Ok().json(issues))
+    Ok(ClosedDays { weekly_off, holidays: dates })
 }
 
-// ===== LIBRARIAN DASHBOARD ENDPOINTS =====
+// Accepts full or short weekday names in any case; stores full lowercase names
+fn parse_weekly_off(days: &[String]) -> Result<Vec<String>, String> {
+    const NAMES: [&str; 7] = ["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
 
-// Get all currently borrowed books
-async fn librarian_borrowed_books(
+    let mut parsed = Vec::new();
+    for day in days {
+        let weekday: chrono::Weekday = day
+            .trim()
+            .parse()
+            .map_err(|_| format!("'{}' is not a day of the week", day.trim()))?;
+        let name = NAMES[weekday.num_days_from_monday() as usize].to_string();
+        if !parsed.contains(&name) {
+            parsed.push(name);
+        }
+    }
+    if parsed.len() == NAMES.len() {
+        return Err("The library must be open on at least one day of the week".to_string());
+    }
+    parsed.sort_by_key(|d| NAMES.iter().position(|n| n == d));
+    Ok(parsed)
+}
+
+async fn get_library_calendar(
     data: web::Data<AppState>,
     req: HttpRequest,
+    query: web::Query<CalendarQuery>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    if claims.role != "librarian" && claims.role != "admin" {
-        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
-            "error": "Access denied: Librarian role required"
-        })));
-    }
+    let calendars: Collection<LibraryCalendar> = data.db.collection("library_calendars");
+    let calendar = calendars
+        .find_one(doc! { "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let collection: Collection<BookIssue> = data.db.collection("book_issues");
-    let now = Utc::now();
+    // NaiveDate is stored as YYYY-MM-DD, so the range compares strings
+    let from = query.from.unwrap_or_else(|| Utc::now().date_naive());
+    let mut range = doc! { "$gte": from.to_string() };
+    if let Some(to) = query.to {
+        range.insert("$lte", to.to_string());
+    }
 
-    // First, auto-mark overdue: any issued book past due_date
-    let now_bson = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
-    let _ = collection
-        .update_many(
-            doc! {
-                "campus_id": &claims.campus_id,
-                "status": "issued",
-                "due_date": { "$lt": now_bson }
-            },
-            doc! { "$set": { "status": "overdue" } },
-            None,
+    let holidays: Collection<LibraryHoliday> = data.db.collection("library_holidays");
+    let mut cursor = holidays
+        .find(
+            doc! { "campus_id": &claims.campus_id, "date": range },
+            FindOptions::builder().sort(doc! { "date": 1 }).build(),
         )
-        .await;
-
-    let mut cursor = collection
-        .find(doc! {
-            "campus_id": &claims.campus_id,
-            "status": { "$in": ["issued", "overdue"] }
-        }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let mut borrowed = Vec::new();
     use futures::stream::StreamExt;
+    let mut list = Vec::new();
     while let Some(result) = cursor.next().await {
         match result {
-            Ok(issue) => borrowed.push(issue),
+            Ok(holiday) => list.push(holiday),
             Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
         }
     }
 
-    let overdue_count = borrowed.iter().filter(|b| b.status == "overdue").count();
-    let issued_count = borrowed.iter().filter(|b| b.status == "issued").count();
-
-    // Calculate days remaining/overdue for each
-    let enriched: Vec<serde_json::Value> = borrowed.iter().map(|b| {
-        let days_diff = (b.due_date - now).num_days();
-        serde_json::json!({
-            "_id": b.id,
-            "book_id": b.book_id,
-            "book_title": b.book_title,
-            "student_id": b.student_id,
-            "issue_date": b.issue_date,
-            "due_date": b.due_date,
-            "status": b.status,
-            "days_remaining": if days_diff >= 0 { days_diff } else { 0 },
-            "days_overdue": if days_diff < 0 { -days_diff } else { 0 }
-        })
-    }).collect();
-
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "total_borrowed": borrowed.len(),
-        "issued_count": issued_count,
-        "overdue_count": overdue_count,
-        "books": enriched
+        "campus_id": &claims.campus_id,
+        "weekly_off": calendar.as_ref().map(|c| c.weekly_off.clone()).unwrap_or_default(),
+        "updated_at": calendar.as_ref().map(|c| c.updated_at),
+        "holidays": list
     })))
 }
 
-// Get returned books history
-async fn librarian_returned_books(
+// Replaces the weekly off days. Loans already issued keep their due dates.
+async fn set_weekly_off(
     data: web::Data<AppState>,
     req: HttpRequest,
+    body: web::Json<WeeklyOffRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    if claims.role != "librarian" && claims.role != "admin" {
+    if !is_librarian(&claims) {
         return Ok(HttpResponse::Forbidden().json(serde_json::json!({
             "error": "Access denied: Librarian role required"
         })));
     }
 
-    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+    let weekly_off = match parse_weekly_off(&body.weekly_off) {
+        Ok(days) => days,
+        Err(e) => return Ok(HttpResponse::BadRequest().json(serde_json::json!({ "error": e }))),
+    };
 
-    let mut cursor = collection
-        .find(doc! {
-            "campus_id": &claims.campus_id,
-            "status": { "$in": ["returned", "returned_with_fine"] }
-        }, None)
+    let calendars: Collection<LibraryCalendar> = data.db.collection("library_calendars");
+    calendars
+        .update_one(
+            doc! { "campus_id": &claims.campus_id },
+            doc! { "$set": {
+                "weekly_off": &weekly_off,
+                "updated_by": &claims.sub,
+                "updated_at": mongodb::bson::to_bson(&Utc::now())
+                    .map_err(actix_web::error::ErrorInternalServerError)?
+            } },
+            mongodb::options::UpdateOptions::builder().upsert(true).build(),
+        )
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
 
-    let mut returned = Vec::new();
-    use futures::stream::StreamExt;
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(issue) => returned.push(issue),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Weekly off days updated",
+        "weekly_off": weekly_off
+    })))
+}
+
+async fn add_library_holiday(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    body: web::Json<HolidayRequest>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
     }
 
-    let total_fines: f64 = returned.iter().map(|r| r.fine_amount).sum();
-    let with_fine: Vec<&BookIssue> = returned.iter().filter(|r| r.fine_amount > 0.0).collect();
+    let body = body.into_inner();
+    if body.name.trim().is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "name is required"
+        })));
+    }
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "total_returned": returned.len(),
-        "total_fines_collected": total_fines,
-        "returned_with_fine": with_fine.len(),
-        "books": returned
-    })))
+    let holiday = LibraryHoliday {
+        id: None,
+        campus_id: claims.campus_id,
+        date: body.date,
+        name: body.name.trim().to_string(),
+        created_by: claims.sub,
+        created_at: Utc::now(),
+    };
+
+    let collection: Collection<LibraryHoliday> = data.db.collection("library_holidays");
+    match collection.insert_one(&holiday, None).await {
+        Ok(result) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "message": "Holiday added successfully",
+            "id": result.inserted_id
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} is already a holiday", holiday.date)
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
 }
 
-// Get library dashboard summary
-async fn librarian_summary(
+async fn update_library_holiday(
     data: web::Data<AppState>,
     req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<HolidayUpdateRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    if claims.role != "librarian" && claims.role != "admin" {
+    if !is_librarian(&claims) {
         return Ok(HttpResponse::Forbidden().json(serde_json::json!({
             "error": "Access denied: Librarian role required"
         })));
     }
 
-    let book_collection: Collection<Book> = data.db.collection("books");
-    let issue_collection: Collection<BookIssue> = data.db.collection("book_issues");
-    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+    let obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
 
-    // Count books
-    let total_books = book_collection
-        .count_documents(doc! { "campus_id": &claims.campus_id }, None)
+    let mut set = doc! {};
+    if let Some(date) = body.date {
+        set.insert("date", date.to_string());
+    }
+    if let Some(name) = &body.name {
+        if name.trim().is_empty() {
+            return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+                "error": "name must not be blank"
+            })));
+        }
+        set.insert("name", name.trim());
+    }
+    if set.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+
+    let collection: Collection<LibraryHoliday> = data.db.collection("library_holidays");
+    match collection
+        .update_one(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, doc! { "$set": set }, None)
         .await
-        .unwrap_or(0) as i64;
+    {
+        Ok(result) if result.matched_count == 0 => Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Holiday not found"
+        }))),
+        Ok(_) => Ok(HttpResponse::Ok().json(serde_json::json!({
+            "message": "Holiday updated successfully"
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Another holiday is already on that date"
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+}
 
-    // Count currently issued
-    let now = Utc::now();
-    let now_bson = mongodb::bson::DateTime::from_millis(now.timestamp_millis());
+async fn delete_library_holiday(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    path: web::Path<String>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    // Auto-mark overdue
-    let _ = issue_collection
-        .update_many(
-            doc! { "campus_id": &claims.campus_id, "status": "issued", "due_date": { "$lt": now_bson } },
-            doc! { "$set": { "status": "overdue" } },
-            None,
-        )
-        .await;
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
 
-    let borrowed_count = issue_collection
-        .count_documents(doc! { "campus_id": &claims.campus_id, "status": { "$in": ["issued", "overdue"] } }, None)
+    let obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<LibraryHoliday> = data.db.collection("library_holidays");
+    let result = collection
+        .delete_one(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, None)
         .await
-        .unwrap_or(0) as i64;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.deleted_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Holiday not found"
+        })));
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "message": "Holiday deleted successfully"
+    })))
+}
+
+// ===== NEW ARRIVALS AND FEATURED =====
+
+async fn get_new_arrivals(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+    query: web::Query<NewArrivalsQuery>,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
+
+    let days = query.days.unwrap_or(30).clamp(1, 365);
+    let limit = query.limit.unwrap_or(20).clamp(1, 100);
+    let since = mongodb::bson::to_bson(&(Utc::now() - Duration::days(days)))
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    let collection: Collection<Book> = data.db.collection("books");
+    let mut cursor = collection
+        .find(
+            doc! {
+                "campus_id": &claims.campus_id,
+                "status": { "$ne": "withdrawn" },
+                "created_at": { "$gte": since }
+            },
+            FindOptions::builder()
+                .sort(doc! { "created_at": -1, "_id": -1 })
+                .limit(limit)
+                .build(),
+        )
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+
+    use futures::stream::StreamExt;
+    let mut books = Vec::new();
+    while let Some(result) = cursor.next().await {
+        match result {
+            Ok(book) => books.push(book),
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
+
+    Ok(HttpResponse::Ok().json(serde_json::json!({
+        "days": days,
+        "data": books
+    })))
+}
+
+// Featured books in display order, with their catalog entries. Books
+// withdrawn since they were featured are left out.
+async fn get_featured_books(
+    data: web::Data<AppState>,
+    req: HttpRequest,
+) -> Result<HttpResponse, Error> {
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    let overdue_count = issue_collection
-        .count_documents(doc! { "campus_id": &claims.campus_id, "status": "overdue" }, None)
-        .await
-        .unwrap_or(0) as i64;
+    use futures::stream::StreamExt;
 
-    let returned_count = issue_collection
-        .count_documents(doc! { "campus_id": &claims.campus_id, "status": { "$in": ["returned", "returned_with_fine"] } }, None)
+    let featured_collection: Collection<FeaturedBook> = data.db.collection("featured_books");
+    let mut cursor = featured_collection
+        .find(
+            doc! { "campus_id": &claims.campus_id },
+            FindOptions::builder().sort(doc! { "position": 1, "added_at": -1 }).build(),
+        )
         .await
-        .unwrap_or(0) as i64;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut featured = Vec::new();
+    while let Some(result) = cursor.next().await {
+        featured.push(result.map_err(actix_web::error::ErrorInternalServerError)?);
+    }
 
-    let waiting_count = waitlist_collection
-        .count_documents(doc! { "campus_id": &claims.campus_id, "status": "waiting" }, None)
+    let ids: Vec<ObjectId> = featured
+        .iter()
+        .filter_map(|f| ObjectId::parse_str(&f.book_id).ok())
+        .collect();
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let mut cursor = book_collection
+        .find(
+            doc! { "_id": { "$in": ids }, "campus_id": &claims.campus_id, "status": { "$ne": "withdrawn" } },
+            None,
+        )
         .await
-        .unwrap_or(0) as i64;
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    let mut books = std::collections::HashMap::new();
+    while let Some(result) = cursor.next().await {
+        let book = result.map_err(actix_web::error::ErrorInternalServerError)?;
+        if let Some(id) = book.id {
+            books.insert(id.to_hex(), book);
+        }
+    }
 
-    // Due within 2 days (approaching due)
-    let two_days = now + Duration::days(2);
-    let two_days_bson = mongodb::bson::DateTime::from_millis(two_days.timestamp_millis());
-    let due_soon_count = issue_collection
-        .count_documents(doc! {
-            "campus_id": &claims.campus_id,
-            "status": "issued",
-            "due_date": { "$lte": two_days_bson, "$gte": now_bson }
-        }, None)
-        .await
-        .unwrap_or(0) as i64;
+    let list: Vec<serde_json::Value> = featured
+        .iter()
+        .filter_map(|f| {
+            let book = books.get(&f.book_id)?;
+            Some(serde_json::json!({
+                "_id": f.id,
+                "note": f.note,
+                "position": f.position,
+                "added_at": f.added_at,
+                "book": book
+            }))
+        })
+        .collect();
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "total_books": total_books,
-        "currently_borrowed": borrowed_count,
-        "overdue": overdue_count,
-        "returned": returned_count,
-        "waiting_list": waiting_count,
-        "due_soon": due_soon_count
-    })))
+    Ok(HttpResponse::Ok().json(list))
 }
 
-// Add to waiting list
-async fn add_to_waitlist(
+async fn add_featured_book(
     data: web::Data<AppState>,
     req: HttpRequest,
-    waitlist_data: web::Json<WaitingListRequest>,
+    body: web::Json<FeaturedBookRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
-
-    let book_collection: Collection<Book> = data.db.collection("books");
-    let waitlist_collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    // Get book title
-    let book_obj_id = ObjectId::parse_str(&waitlist_data.book_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
+    if !is_librarian(&claims) {
+        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
+            "error": "Access denied: Librarian role required"
+        })));
+    }
 
-    let book = book_collection
+    let body = body.into_inner();
+    let book_obj_id = ObjectId::parse_str(&body.book_id)
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let book_collection: Collection<Book> = data.db.collection("books");
+    let Some(book) = book_collection
         .find_one(doc! { "_id": book_obj_id, "campus_id": &claims.campus_id }, None)
         .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
-    let book = match book {
-        Some(b) => b,
-        None => return Ok(HttpResponse::NotFound().json(serde_json::json!({
+        .map_err(actix_web::error::ErrorInternalServerError)?
+    else {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
             "error": "Book not found"
-        }))),
+        })));
     };
-
-    // Check if already on waitlist
-    let existing = waitlist_collection
-        .find_one(doc! {
-            "book_id": &waitlist_data.book_id,
-            "student_id": &waitlist_data.student_id,
-            "status": "waiting",
-            "campus_id": &claims.campus_id
-        }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
-    if existing.is_some() {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Student is already on the waiting list for this book"
+    if book.status == "withdrawn" {
+        return Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": "Book has been withdrawn"
         })));
     }
 
-    let entry = WaitingListEntry {
+    let featured = FeaturedBook {
         id: None,
-        book_id: waitlist_data.book_id.clone(),
-        book_title: book.title.clone(),
-        student_id: waitlist_data.student_id.clone(),
-        queued_at: Utc::now(),
-        status: "waiting".to_string(),
         campus_id: claims.campus_id,
+        book_id: book_obj_id.to_hex(),
+        note: body.note.map(|n| n.trim().to_string()).filter(|n| !n.is_empty()),
+        position: body.position.unwrap_or(0),
+        added_by: claims.sub,
+        added_at: Utc::now(),
     };
 
-    waitlist_collection
-        .insert_one(entry, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": "Added to waiting list"
-    })))
+    let collection: Collection<FeaturedBook> = data.db.collection("featured_books");
+    match collection.insert_one(&featured, None).await {
+        Ok(result) => Ok(HttpResponse::Created().json(serde_json::json!({
+            "message": "Book featured successfully",
+            "id": result.inserted_id
+        }))),
+        Err(e) if is_duplicate_key(&e) => Ok(HttpResponse::Conflict().json(serde_json::json!({
+            "error": format!("{} is already featured", book.title)
+        }))),
+        Err(e) => Err(actix_web::error::ErrorInternalServerError(e)),
+    }
 }
 
-// Get waiting list
-async fn get_waitlist(
+async fn update_featured_book(
     data: web::Data<AppState>,
     req: HttpRequest,
+    path: web::Path<String>,
+    body: web::Json<FeaturedBookUpdateRequest>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    if claims.role != "librarian" && claims.role != "admin" {
+    if !is_librarian(&claims) {
         return Ok(HttpResponse::Forbidden().json(serde_json::json!({
             "error": "Access denied: Librarian role required"
         })));
     }
 
-    let collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
-
-    let mut cursor = collection
-        .find(doc! { "campus_id": &claims.campus_id, "status": "waiting" }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    let obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
 
-    let mut entries = Vec::new();
-    use futures::stream::StreamExt;
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(entry) => entries.push(entry),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    let mut update = doc! {};
+    let mut set = doc! {};
+    if let Some(position) = body.position {
+        set.insert("position", position);
+    }
+    // An empty note clears it
+    match body.note.as_deref().map(str::trim) {
+        Some("") => {
+            update.insert("$unset", doc! { "note": "" });
+        }
+        Some(note) => {
+            set.insert("note", note);
         }
+        None => {}
+    }
+    if !set.is_empty() {
+        update.insert("$set", set);
+    }
+    if update.is_empty() {
+        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
+            "error": "Nothing to update"
+        })));
+    }
+
+    let collection: Collection<FeaturedBook> = data.db.collection("featured_books");
+    let result = collection
+        .update_one(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, update, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.matched_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Featured book not found"
+        })));
     }
 
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "total_waiting": entries.len(),
-        "entries": entries
+        "message": "Featured book updated successfully"
     })))
 }
 
-// Notify/fulfill waiting list entry
-async fn update_waitlist_status(
+async fn remove_featured_book(
     data: web::Data<AppState>,
     req: HttpRequest,
-    path: web::Path<(String, String)>,
+    path: web::Path<String>,
 ) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+    let claims = extract_claims(&req, &data.jwt_keys)
+        .map_err(actix_web::error::ErrorUnauthorized)?;
 
-    if claims.role != "librarian" && claims.role != "admin" {
+    if !is_librarian(&claims) {
         return Ok(HttpResponse::Forbidden().json(serde_json::json!({
             "error": "Access denied: Librarian role required"
         })));
     }
 
-    let (entry_id, new_status) = path.into_inner();
-    if new_status != "notified" && new_status != "fulfilled" && new_status != "cancelled" {
-        return Ok(HttpResponse::BadRequest().json(serde_json::json!({
-            "error": "Invalid status. Use: notified, fulfilled, cancelled"
+    let obj_id = ObjectId::parse_str(path.into_inner())
+        .map_err(actix_web::error::ErrorBadRequest)?;
+    let collection: Collection<FeaturedBook> = data.db.collection("featured_books");
+    let result = collection
+        .delete_one(doc! { "_id": obj_id, "campus_id": &claims.campus_id }, None)
+        .await
+        .map_err(actix_web::error::ErrorInternalServerError)?;
+    if result.deleted_count == 0 {
+        return Ok(HttpResponse::NotFound().json(serde_json::json!({
+            "error": "Featured book not found"
         })));
     }
 
-    let collection: Collection<WaitingListEntry> = data.db.collection("waiting_list");
-    let obj_id = ObjectId::parse_str(&entry_id)
-        .map_err(|e| actix_web::error::ErrorBadRequest(e))?;
-
-    collection
-        .update_one(
-            doc! { "_id": obj_id, "campus_id": &claims.campus_id },
-            doc! { "$set": { "status": &new_status } },
-            None,
-        )
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
-
     Ok(HttpResponse::Ok().json(serde_json::json!({
-        "message": format!("Waiting list entry updated to {}", new_status)
+        "message": "Book removed from featured"
     })))
 }
 
-// ===== STUDENT DASHBOARD - BORROWED BOOKS =====
+// ===== INDEXES =====
 
-async fn get_student_books(
-    data: web::Data<AppState>,
-    req: HttpRequest,
-    path: web::Path<String>,
-) -> Result<HttpResponse, Error> {
-    let claims = extract_claims(&req, &data.jwt_secret)
-        .map_err(|e| actix_web::error::ErrorUnauthorized(e))?;
+// Borrowers with more than one active loan of the same book, which the
+// unique active-loan index cannot be built over:
+// (campus_id, student_id, book_id, issue ids)
+async fn find_duplicate_loans(db: &mongodb::Database) -> mongodb::error::Result<Vec<(String, String, String, Vec<String>)>> {
+    use futures::stream::StreamExt;
 
-    let student_id = path.into_inner();
-    if claims.role == "student" && claims.sub != student_id {
-        return Ok(HttpResponse::Forbidden().json(serde_json::json!({
-            "error": "Access denied: You can only view your own borrowed books"
-        })));
+    let pipeline = [
+        doc! { "$match": { "status": { "$in": ["issued", "overdue"] } } },
+        doc! { "$group": {
+            "_id": { "campus_id": "$campus_id", "student_id": "$student_id", "book_id": "$book_id" },
+            "ids": { "$push": "$_id" },
+            "count": { "$sum": 1 }
+        } },
+        doc! { "$match": { "count": { "$gt": 1 } } },
+        doc! { "$sort": { "_id.campus_id": 1, "_id.student_id": 1 } },
+    ];
+    let mut cursor = db.collection::<BookIssue>("book_issues").aggregate(pipeline, None).await?;
+
+    let mut duplicates = Vec::new();
+    while let Some(group) = cursor.next().await {
+        let group = group?;
+        let key = group.get_document("_id").ok();
+        let field = |name: &str| key.and_then(|k| k.get_str(name).ok()).unwrap_or_default().to_string();
+        let ids = group
+            .get_array("ids")
+            .map(|ids| ids.iter().filter_map(|id| id.as_object_id()).map(|id| id.to_hex()).collect())
+            .unwrap_or_default();
+        duplicates.push((field("campus_id"), field("student_id"), field("book_id"), ids));
     }
+    Ok(duplicates)
+}
 
-    let collection: Collection<BookIssue> = data.db.collection("book_issues");
+// One active loan of a title per borrower, behind the check in issue_book.
+// $in in a partial filter needs MongoDB 6.0.
+async fn create_active_loan_index(db: &mongodb::Database) -> mongodb::error::Result<()> {
+    use mongodb::{options::IndexOptions, IndexModel};
 
-    let mut cursor = collection
-        .find(doc! { "student_id": &student_id, "campus_id": &claims.campus_id }, None)
-        .await
-        .map_err(|e| actix_web::error::ErrorInternalServerError(e))?;
+    db.collection::<BookIssue>("book_issues")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "student_id": 1, "book_id": 1, "campus_id": 1 })
+                .options(
+                    IndexOptions::builder()
+                        .name("active_loan_unique".to_string())
+                        .unique(true)
+                        .partial_filter_expression(doc! { "status": { "$in": ["issued", "overdue"] } })
+                        .build(),
+                )
+                .build(),
+            None,
+        )
+        .await?;
+    Ok(())
+}
 
-    let mut all_books = Vec::new();
-    use futures::stream::StreamExt;
-    while let Some(result) = cursor.next().await {
-        match result {
-            Ok(issue) => all_books.push(issue),
-            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
-        }
-    }
+async fn create_indexes(db: &mongodb::Database) -> mongodb::error::Result<()> {
+    use mongodb::{options::IndexOptions, IndexModel};
 
-    let currently_borrowed: Vec<&BookIssue> = all_books.iter()
-        .filter(|b| b.status == "issued")
-        .collect();
+    let books = db.collection::<Book>("books");
 
-    let returned: Vec<&BookIssue> = all_books.iter()
-        .filter(|b| b.status == "returned" || b.status == "returned_with_fine")
-        .collect();
+    // Catalog search; $text queries always carry the campus_id equality
+    books
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "title": "text", "author": "text", "isbn": "text" })
+                .build(),
+            None,
+        )
+        .await?;
 
-    Ok(HttpResponse::Ok().json(serde_json::json!({
-        "student_id": student_id,
-        "currently_borrowed": currently_borrowed,
-        "returned_books": returned,
-        "total_borrowed": currently_borrowed.len(),
-        "total_returned": returned.len()
-    })))
+    books
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "category": 1, "title": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    books
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "title": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    books
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "isbn": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<LibraryCalendar>("library_calendars")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<LibraryHoliday>("library_holidays")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "date": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    // New arrivals
+    db.collection::<Book>("books")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "created_at": -1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<FeaturedBook>("featured_books")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "book_id": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<Category>("categories")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "slug": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<BookIssue>("book_issues")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "student_id": 1, "issue_date": -1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    // Issue listing: overdue and due-date views, and per-book filters
+    db.collection::<BookIssue>("book_issues")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "status": 1, "due_date": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<BookIssue>("book_issues")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "issue_date": -1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    // Who else borrowed a title, for recommendations
+    db.collection::<BookIssue>("book_issues")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "book_id": 1, "student_id": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<EResourceAccess>("e_resource_access")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "resource_id": 1, "user_id": 1 })
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<AcquisitionFund>("acquisition_funds")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "financial_year": 1, "code": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<Vendor>("vendors")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "name": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<BookCopy>("book_copies")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "campus_id": 1, "accession_number": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    db.collection::<StockAuditScan>("stock_audit_scans")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "audit_id": 1, "accession_number": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    // One check-in per expected issue
+    db.collection::<PeriodicalIssue>("periodical_issues")
+        .create_index(
+            IndexModel::builder()
+                .keys(doc! { "periodical_id": 1, "expected_date": 1 })
+                .options(IndexOptions::builder().unique(true).build())
+                .build(),
+            None,
+        )
+        .await?;
+
+    Ok(())
 }
 
 #[actix_web::main]
@@ -749,6 +8979,31 @@ async fn main() -> std::io::Result<()> {
     let database_name = env::var("DATABASE_NAME").unwrap_or_else(|_| "campusconnect".to_string());
     let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "your-secret-key".to_string());
     let port = env::var("PORT").unwrap_or_else(|_| "8084".to_string());
+    let loan_period_days = env::var("LOAN_PERIOD_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(14);
+    let max_renewals = env::var("MAX_RENEWALS").ok().and_then(|v| v.parse().ok()).unwrap_or(2);
+    let hold_pickup_days = env::var("HOLD_PICKUP_DAYS").ok().and_then(|v| v.parse().ok()).unwrap_or(3);
+    // Minutes between hold expiry and promotion runs; 0 disables the job
+    let hold_scan_minutes = env::var("HOLD_SCAN_INTERVAL_MINUTES")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(60);
+    let institution_name = env::var("INSTITUTION_NAME").unwrap_or_else(|_| "CampusConnect".to_string());
+    let loan_limits = parse_loan_limits(
+        &env::var("LOAN_LIMITS").unwrap_or_else(|_| format!("student={},teacher=10", DEFAULT_STUDENT_LOAN_LIMIT)),
+    );
+    let librarians_in_charge = env::var("LIBRARIANS_IN_CHARGE")
+        .unwrap_or_default()
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
+    let isbn_lookup = IsbnLookup {
+        google_api_key: env::var("GOOGLE_BOOKS_API_KEY").ok().filter(|k| !k.is_empty()),
+        http: reqwest::Client::builder()
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .expect("Failed to build HTTP client"),
+    };
 
     println!("📚 Starting Library Service...");
     println!("📡 Connecting to MongoDB: {}", mongodb_uri);
@@ -759,12 +9014,79 @@ async fn main() -> std::io::Result<()> {
     
     let db = client.database(&database_name);
 
+    let access_ttl = env::var("ACCESS_TOKEN_TTL_MINS")
+        .ok()
+        .and_then(|v| v.parse::<i64>().ok())
+        .unwrap_or(24 * 60);
+    let jwt_keys: SharedKeySet = std::sync::Arc::new(std::sync::RwLock::new(
+        load_jwt_keys(&db, &jwt_secret, chrono::Duration::minutes(access_ttl))
+            .await
+            .expect("Failed to load JWT keys"),
+    ));
+
+    // Pick up keys the auth service adds or retires
+    {
+        let db = db.clone();
+        let jwt_keys = jwt_keys.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(JWT_KEY_REFRESH_SECS));
+            loop {
+                interval.tick().await;
+                reload_jwt_keys(&db, &jwt_secret, &jwt_keys).await;
+            }
+        });
+    }
+
+    create_indexes(&db)
+        .await
+        .expect("Failed to create indexes");
+
+    // Duplicate loans are real books on a shelf somewhere, so they are left
+    // for the desk to return; the index is built on the first clean start
+    let duplicate_loans = find_duplicate_loans(&db)
+        .await
+        .expect("Failed to check book issues for duplicate loans");
+    if duplicate_loans.is_empty() {
+        create_active_loan_index(&db)
+            .await
+            .expect("Failed to create active loan index");
+    } else {
+        eprintln!("⚠️  These borrowers have the same book on loan more than once. Return the extra loans (see SETUP.md); until then concurrent issues of the same book are not blocked:");
+        for (campus_id, student_id, book_id, ids) in &duplicate_loans {
+            eprintln!("   campus {}, borrower {}, book {}: issues {}", campus_id, student_id, book_id, ids.join(", "));
+        }
+    }
+
+    if hold_scan_minutes > 0 {
+        let db = db.clone();
+        actix_web::rt::spawn(async move {
+            let mut interval = actix_web::rt::time::interval(std::time::Duration::from_secs(hold_scan_minutes * 60));
+            loop {
+                interval.tick().await;
+                match process_holds(&db, None, hold_pickup_days).await {
+                    Ok(summary) => log::info!(
+                        "Hold scan: {} holds expired, {} students promoted",
+                        summary.expired, summary.promoted
+                    ),
+                    Err(e) => log::warn!("Hold scan failed: {}", e),
+                }
+            }
+        });
+    }
+
     println!("✅ Connected to MongoDB");
     println!("🚀 Server starting on http://127.0.0.1:{}", port);
 
     let app_state = web::Data::new(AppState {
         db,
-        jwt_secret,
+        jwt_keys,
+        loan_period_days,
+        max_renewals,
+        isbn_lookup,
+        hold_pickup_days,
+        loan_limits,
+        institution_name,
+        librarians_in_charge,
     });
 
     HttpServer::new(move || {
@@ -778,12 +9100,32 @@ async fn main() -> std::io::Result<()> {
             // Book routes
             .route("/api/books", web::post().to(add_book))
             .route("/api/books", web::get().to(get_books))
+            .route("/api/books/lookup/{isbn}", web::get().to(lookup_isbn))
+            .route("/api/books/import", web::post().to(import_books))
+            .route("/api/books/new-arrivals", web::get().to(get_new_arrivals))
+            .route("/api/books/featured", web::get().to(get_featured_books))
+            .route("/api/books/featured", web::post().to(add_featured_book))
+            .route("/api/books/featured/{id}", web::put().to(update_featured_book))
+            .route("/api/books/featured/{id}", web::delete().to(remove_featured_book))
+            .route("/api/books/{id}", web::put().to(update_book))
+            .route("/api/books/{id}", web::delete().to(withdraw_book))
+            .route("/api/books/{id}/copies", web::post().to(register_copies))
+            .route("/api/books/{id}/copies", web::get().to(get_book_copies))
             // Issue/Return routes
             .route("/api/issue", web::post().to(issue_book))
             .route("/api/return", web::post().to(return_book))
             .route("/api/issues", web::get().to(get_issues))
+            .route("/api/issues/{id}/renew", web::post().to(renew_issue))
+            .route("/api/issues/{id}/receipt", web::get().to(get_issue_receipt))
             // Student Dashboard routes
             .route("/api/student/books/{student_id}", web::get().to(get_student_books))
+            .route("/api/students/{id}/history", web::get().to(get_student_history))
+            .route("/api/my/history", web::get().to(get_my_history))
+            .route("/api/my/recommendations", web::get().to(get_my_recommendations))
+
+            .route("/api/fines/waivers", web::post().to(create_fine_waiver))
+            .route("/api/fines/waivers", web::get().to(get_fine_waivers))
+            .route("/api/fines/waivers/{id}/{action}", web::put().to(decide_fine_waiver))
             // Librarian Dashboard routes
             .route("/api/librarian/summary", web::get().to(librarian_summary))
             .route("/api/librarian/borrowed", web::get().to(librarian_borrowed_books))
@@ -791,6 +9133,65 @@ async fn main() -> std::io::Result<()> {
             .route("/api/librarian/waitlist", web::get().to(get_waitlist))
             .route("/api/waitlist", web::post().to(add_to_waitlist))
             .route("/api/librarian/waitlist/{entry_id}/{status}", web::put().to(update_waitlist_status))
+            .route("/api/librarian/holds/process", web::post().to(run_hold_processing))
+            // Report routes
+            .route("/api/reports/circulation", web::get().to(circulation_report))
+
+            .route("/api/library/calendar", web::get().to(get_library_calendar))
+            .route("/api/library/calendar", web::put().to(set_weekly_off))
+            .route("/api/library/calendar/holidays", web::post().to(add_library_holiday))
+            .route("/api/library/calendar/holidays/{id}", web::put().to(update_library_holiday))
+            .route("/api/library/calendar/holidays/{id}", web::delete().to(delete_library_holiday))
+
+            .route("/api/audits", web::post().to(open_stock_audit))
+            .route("/api/audits", web::get().to(get_stock_audits))
+            .route("/api/audits/{id}/scans", web::post().to(scan_audit_copies))
+            .route("/api/audits/{id}/report", web::get().to(stock_audit_report))
+            .route("/api/audits/{id}/close", web::post().to(close_stock_audit))
+            // Category routes
+            .route("/api/categories", web::post().to(create_category))
+            .route("/api/categories", web::get().to(get_categories))
+            .route("/api/categories/unmanaged", web::get().to(get_unmanaged_categories))
+            .route("/api/categories/merge", web::post().to(merge_categories))
+            .route("/api/categories/{id}", web::put().to(update_category))
+            .route("/api/categories/{id}", web::delete().to(delete_category))
+            // Inter-library loan routes
+            .route("/api/ill", web::post().to(create_ill_request))
+            .route("/api/ill", web::get().to(get_ill_requests))
+            .route("/api/ill/{id}/status", web::put().to(update_ill_status))
+            // E-resource routes
+            .route("/api/e-resources", web::post().to(add_e_resource))
+            .route("/api/e-resources", web::get().to(get_e_resources))
+            .route("/api/e-resources/upload", web::post().to(upload_e_resource))
+            .route("/api/e-resources/licenses/expiring", web::get().to(expiring_e_resource_licenses))
+            .route("/api/e-resources/{id}", web::get().to(view_e_resource))
+            .route("/api/e-resources/{id}", web::put().to(update_e_resource))
+            .route("/api/e-resources/{id}", web::delete().to(delete_e_resource))
+            .route("/api/e-resources/{id}/download", web::get().to(download_e_resource))
+            .route("/api/e-resources/{id}/usage", web::get().to(e_resource_usage))
+
+            .route("/api/acquisitions/funds", web::post().to(create_fund))
+            .route("/api/acquisitions/funds", web::get().to(get_funds))
+            .route("/api/acquisitions/funds/{id}", web::put().to(update_fund))
+            .route("/api/acquisitions/vendors", web::post().to(create_vendor))
+            .route("/api/acquisitions/vendors", web::get().to(get_vendors))
+            .route("/api/acquisitions/requests", web::post().to(create_purchase_request))
+            .route("/api/acquisitions/requests", web::get().to(get_purchase_requests))
+            .route("/api/acquisitions/requests/{id}/{action}", web::put().to(review_purchase_request))
+            .route("/api/acquisitions/orders", web::post().to(create_purchase_order))
+            .route("/api/acquisitions/orders", web::get().to(get_purchase_orders))
+            .route("/api/acquisitions/orders/{id}/receive", web::post().to(receive_purchase_order))
+            .route("/api/acquisitions/orders/{id}/cancel", web::post().to(cancel_purchase_order))
+
+            .route("/api/periodicals", web::post().to(create_periodical))
+            .route("/api/periodicals", web::get().to(get_periodicals))
+            .route("/api/periodicals/renewals-due", web::get().to(periodical_renewals_due))
+            .route("/api/periodicals/claims", web::get().to(get_periodical_claims))
+            .route("/api/periodicals/{id}", web::get().to(get_periodical))
+            .route("/api/periodicals/{id}", web::put().to(update_periodical))
+            .route("/api/periodicals/{id}/issues", web::post().to(check_in_periodical_issue))
+            .route("/api/periodicals/{id}/claims", web::post().to(claim_periodical_issue))
+            .route("/api/periodicals/{id}/renew", web::post().to(renew_periodical))
     })
     .bind(format!("127.0.0.1:{}", port))?
     .run()